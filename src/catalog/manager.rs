@@ -7,45 +7,77 @@ use parking_lot::RwLock;
 use serde_json::Value;
 
 use super::schema::{SchemaError, TableSchema};
-use crate::storage::{GitRepository, Row, RowKey, StorageError, TableName};
+use crate::storage::{GitRepository, GitSignature, Row, RowKey, RowPath, StorageError, TableName};
 
 /// Directory where schemas are stored.
-const SCHEMA_DIR: &str = "_schemas";
+pub(crate) const SCHEMA_DIR: &str = "_schemas";
+
+/// Directory holding one subdirectory of index entries per table.
+pub(crate) const INDEX_DIR: &str = "_indexes";
+
+/// Directory holding the last key handed out by each table with
+/// [`KeyStrategy::Sequential`](crate::sql::KeyStrategy::Sequential) keys.
+pub(crate) const SEQUENCE_DIR: &str = "_sequences";
 
 /// The catalog manages table schemas, storing them in the repository.
 pub struct Catalog {
     repo: Arc<RwLock<GitRepository>>,
+    author: Option<GitSignature>,
+    note: Option<String>,
 }
 
 impl Catalog {
     /// Create a new catalog backed by the given repository.
     pub fn new(repo: Arc<RwLock<GitRepository>>) -> Self {
-        Self { repo }
+        Self {
+            repo,
+            author: None,
+            note: None,
+        }
+    }
+
+    /// Commit schema changes as `author`, or with the repository's own
+    /// signature for `None`.
+    pub fn set_author(&mut self, author: Option<GitSignature>) {
+        self.author = author;
+    }
+
+    /// Add `note` to the message of schema change commits, or stop for `None`.
+    pub fn set_note(&mut self, note: Option<String>) {
+        self.note = note;
+    }
+
+    /// The repository to commit through, with the current author and note.
+    fn writer(&self, repo: &GitRepository) -> GitRepository {
+        let repo = match &self.author {
+            Some(author) => repo.as_author(author.clone()),
+            None => repo.clone(),
+        };
+        match &self.note {
+            Some(note) => repo.annotated(note.clone()),
+            None => repo,
+        }
     }
 
-    /// Create a new table schema.
+    /// Create a table: its schema and its empty data directory go in a
+    /// single commit, so history never has one without the other.
     pub fn create_table(&self, schema: TableSchema) -> Result<(), SchemaError> {
         // Validate schema
         schema.validate()?;
 
-        let repo = self.repo.write();
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
         let head = repo.head().map_err(|e| SchemaError::Storage(e.to_string()))?;
 
         let table_name = TableName::new(SCHEMA_DIR)
             .map_err(|e| SchemaError::Storage(e.to_string()))?;
 
-        // Ensure _schemas table exists
-        let head = if !repo.table_exists(&table_name, head).map_err(|e| SchemaError::Storage(e.to_string()))? {
-            repo.create_table(&table_name, head, None)
-                .map_err(|e| SchemaError::Storage(e.to_string()))?
-        } else {
-            head
-        };
-
         // Check if table schema already exists
         let row_key = RowKey::new(&schema.name)
             .map_err(|e| SchemaError::Storage(e.to_string()))?;
-        if repo.read_row(&table_name, &row_key, head).map_err(|e| SchemaError::Storage(e.to_string()))?.is_some() {
+        if repo.table_exists(&table_name, head).map_err(|e| SchemaError::Storage(e.to_string()))?
+            && repo.read_row(&table_name, &row_key, head).map_err(|e| SchemaError::Storage(e.to_string()))?.is_some()
+        {
             return Err(SchemaError::TableExists(schema.name.clone()));
         }
 
@@ -56,11 +88,12 @@ impl Catalog {
         data.insert("schema".to_string(), schema_json);
         let row = Row::new(row_key, data);
 
-        let new_head = repo.upsert_row(&table_name, row, head, None)
+        let table = TableName::new(&schema.name)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let new_head = repo.create_table_with_schema(&table, &table_name, row, head, None)
             .map_err(|e| SchemaError::Storage(e.to_string()))?;
 
-        // Update main branch
-        repo.update_branch(&crate::storage::BranchName::main(), new_head)
+        repo.advance_head(new_head)
             .map_err(|e| SchemaError::Storage(e.to_string()))?;
 
         Ok(())
@@ -118,7 +151,8 @@ impl Catalog {
     pub fn update_table(&self, schema: TableSchema) -> Result<(), SchemaError> {
         schema.validate()?;
 
-        let repo = self.repo.write();
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
         let head = repo.head().map_err(|e| SchemaError::Storage(e.to_string()))?;
 
         // Check table exists and verify version
@@ -154,15 +188,17 @@ impl Catalog {
         let new_head = repo.upsert_row(&table_name, row, head, None)
             .map_err(|e| SchemaError::Storage(e.to_string()))?;
 
-        repo.update_branch(&crate::storage::BranchName::main(), new_head)
+        repo.advance_head(new_head)
             .map_err(|e| SchemaError::Storage(e.to_string()))?;
 
         Ok(())
     }
 
-    /// Drop a table schema.
+    /// Drop a table: its rows, its schema, its index entries and its key
+    /// sequence go in a single commit, so a failure leaves all of them in place.
     pub fn drop_table(&self, name: &str) -> Result<(), SchemaError> {
-        let repo = self.repo.write();
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
         let head = repo.head().map_err(|e| SchemaError::Storage(e.to_string()))?;
 
         let table_name = TableName::new(SCHEMA_DIR)
@@ -184,10 +220,181 @@ impl Catalog {
             return Err(SchemaError::TableNotFound(name.to_string()));
         }
 
-        let new_head = repo.delete_row(&table_name, &row_key, head, None)
+        let table = TableName::new(name)
             .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let indexes = TableName::new(INDEX_DIR)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let mut rows = vec![RowPath::new(table_name, row_key.clone())];
+        let sequences = TableName::new(SEQUENCE_DIR)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        if repo.table_exists(&sequences, head).map_err(|e| SchemaError::Storage(e.to_string()))?
+            && repo.read_row(&sequences, &row_key, head)
+                .map_err(|e| SchemaError::Storage(e.to_string()))?
+                .is_some()
+        {
+            rows.push(RowPath::new(sequences, row_key));
+        }
+        let new_head = repo.drop_table_with(
+            &table,
+            &rows,
+            &[(indexes, name.to_string())],
+            head,
+            None,
+        )
+        .map_err(|e| SchemaError::Storage(e.to_string()))?;
+
+        repo.advance_head(new_head)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Rename a table: its rows, its schema, its index entries, its key
+    /// sequence and the foreign keys that reference it all move in a single
+    /// commit. Git still has the history of the old paths.
+    pub fn rename_table(&self, old: &str, new: &str) -> Result<(), SchemaError> {
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
+        let head = repo.head().map_err(|e| SchemaError::Storage(e.to_string()))?;
 
-        repo.update_branch(&crate::storage::BranchName::main(), new_head)
+        let table_name = TableName::new(SCHEMA_DIR)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let old_key = RowKey::new(old)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let new_key = RowKey::new(new)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+
+        let exists = |key: &RowKey| -> Result<bool, SchemaError> {
+            Ok(repo.table_exists(&table_name, head).map_err(|e| SchemaError::Storage(e.to_string()))?
+                && repo.read_row(&table_name, key, head)
+                    .map_err(|e| SchemaError::Storage(e.to_string()))?
+                    .is_some())
+        };
+        if !exists(&old_key)? {
+            return Err(SchemaError::TableNotFound(old.to_string()));
+        }
+        if exists(&new_key)? {
+            return Err(SchemaError::TableExists(new.to_string()));
+        }
+
+        // The table's own schema, and those with foreign keys into it
+        let mut rows = Vec::new();
+        for row in repo.scan_table(&table_name, head).map_err(|e| SchemaError::Storage(e.to_string()))? {
+            let mut schema: TableSchema = row.get("schema")
+                .and_then(|v| serde_json::from_value(v.clone()).ok())
+                .ok_or_else(|| SchemaError::Storage("corrupted schema".into()))?;
+            let renamed = row.key == old_key;
+            let mut references = false;
+            for fk in schema.foreign_keys.iter_mut().filter(|fk| fk.references_table == old) {
+                fk.references_table = new.to_string();
+                references = true;
+            }
+            if !renamed && !references {
+                continue;
+            }
+            if renamed {
+                schema.name = new.to_string();
+            }
+            schema.bump_version();
+
+            let schema_json = serde_json::to_value(&schema)
+                .map_err(|e| SchemaError::Storage(e.to_string()))?;
+            let path = RowPath::new(table_name.clone(), row.key.clone());
+            let mut updated = row.with_update(BTreeMap::from([("schema".to_string(), schema_json)]));
+            if renamed {
+                updated.key = new_key.clone();
+            }
+            rows.push((path, updated));
+        }
+
+        let sequences = TableName::new(SEQUENCE_DIR)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        if repo.table_exists(&sequences, head).map_err(|e| SchemaError::Storage(e.to_string()))? {
+            if let Some(row) = repo.read_row(&sequences, &old_key, head)
+                .map_err(|e| SchemaError::Storage(e.to_string()))?
+            {
+                let path = RowPath::new(sequences, old_key);
+                rows.push((path, Row { key: new_key, ..row }));
+            }
+        }
+
+        let old_table = TableName::new(old)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let new_table = TableName::new(new)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let indexes = TableName::new(INDEX_DIR)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let new_head = repo.rename_table_with(&old_table, &new_table, &rows, &[indexes], head, None)
+            .map_err(|e| match e {
+                StorageError::TableAlreadyExists(_) => SchemaError::TableExists(new.to_string()),
+                other => SchemaError::Storage(other.to_string()),
+            })?;
+
+        repo.advance_head(new_head)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Rename a column of a table: its schema, the foreign keys that
+    /// reference it and every row holding a value for it are rewritten in a
+    /// single commit.
+    pub fn rename_column(&self, table: &str, old: &str, new: &str) -> Result<(), SchemaError> {
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
+        let head = repo.head().map_err(|e| SchemaError::Storage(e.to_string()))?;
+
+        let table_name = TableName::new(SCHEMA_DIR)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let row_key = RowKey::new(table)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        let schema_rows = repo.scan_table(&table_name, head)
+            .map_err(|e| match e {
+                StorageError::TableNotFound { .. } => SchemaError::TableNotFound(table.to_string()),
+                other => SchemaError::Storage(other.to_string()),
+            })?;
+
+        // The table's own schema, and those with foreign keys into the column
+        let mut rows = Vec::new();
+        let mut found = false;
+        for row in schema_rows {
+            let mut schema: TableSchema = row.get("schema")
+                .and_then(|v| serde_json::from_value(v.clone()).ok())
+                .ok_or_else(|| SchemaError::Storage("corrupted schema".into()))?;
+            if row.key == row_key {
+                schema.rename_column(old, new)?;
+                found = true;
+            } else if schema.rename_referenced_column(table, old, new) {
+                schema.bump_version();
+            } else {
+                continue;
+            }
+
+            let schema_json = serde_json::to_value(&schema)
+                .map_err(|e| SchemaError::Storage(e.to_string()))?;
+            let updated = row.with_update(BTreeMap::from([("schema".to_string(), schema_json)]));
+            rows.push((table_name.clone(), updated));
+        }
+        if !found {
+            return Err(SchemaError::TableNotFound(table.to_string()));
+        }
+
+        // Rows hold their values by column name
+        let data_table = TableName::new(table)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+        for row in repo.scan_table(&data_table, head).map_err(|e| SchemaError::Storage(e.to_string()))? {
+            let mut data = row.data.clone();
+            let Some(value) = data.remove(old) else {
+                continue;
+            };
+            data.insert(new.to_string(), value);
+            rows.push((data_table.clone(), row.with_update(data)));
+        }
+
+        let new_head = repo.rename_column_with(&data_table, old, new, &rows, head, None)
+            .map_err(|e| SchemaError::Storage(e.to_string()))?;
+
+        repo.advance_head(new_head)
             .map_err(|e| SchemaError::Storage(e.to_string()))?;
 
         Ok(())
@@ -229,7 +436,7 @@ impl Catalog {
 mod tests {
     use super::*;
     use crate::catalog::schema::SchemaBuilder;
-    use crate::catalog::types::DataType;
+    use crate::catalog::types::{DataType, ForeignKey};
     use tempfile::TempDir;
 
     fn setup_catalog() -> (Catalog, TempDir) {
@@ -255,7 +462,7 @@ mod tests {
         let retrieved = catalog.get_table("users").unwrap();
         assert_eq!(retrieved.name, "users");
         assert_eq!(retrieved.columns.len(), 2);
-        assert_eq!(retrieved.primary_key, Some("id".to_string()));
+        assert_eq!(retrieved.primary_key, vec!["id".to_string()]);
     }
 
     #[test]
@@ -304,6 +511,62 @@ mod tests {
         assert!(!catalog.table_exists("users"));
     }
 
+    #[test]
+    fn test_create_and_drop_table_are_one_commit() {
+        let dir = TempDir::new().unwrap();
+        let repo = GitRepository::open_or_init(dir.path()).unwrap();
+        let catalog = Catalog::new(Arc::new(RwLock::new(repo.clone())));
+        let before = repo.head().unwrap();
+        catalog.create_table(SchemaBuilder::new("users").add_column("id", DataType::Text).build().unwrap()).unwrap();
+        let users = TableName::new("users").unwrap();
+        let head = repo.head().unwrap();
+
+        // The table and its schema arrive together too
+        assert_eq!(repo.get_commit(head).unwrap().parent_ids, vec![before]);
+        assert!(repo.table_exists(&users, head).unwrap());
+
+        // A drop that can't commit leaves the schema in place
+        let read_only = Catalog::new(Arc::new(RwLock::new(GitRepository::open_read_only(dir.path()).unwrap())));
+        assert!(read_only.drop_table("users").is_err());
+        assert!(catalog.table_exists("users"));
+
+        catalog.drop_table("users").unwrap();
+        let dropped = repo.head().unwrap();
+        assert_eq!(repo.get_commit(dropped).unwrap().parent_ids, vec![head]);
+        assert!(!catalog.table_exists("users"));
+        assert!(!repo.table_exists(&users, dropped).unwrap());
+    }
+
+    #[test]
+    fn test_rename_table() {
+        let dir = TempDir::new().unwrap();
+        let repo = GitRepository::open_or_init(dir.path()).unwrap();
+        let catalog = Catalog::new(Arc::new(RwLock::new(repo.clone())));
+        catalog.create_table(SchemaBuilder::new("users").add_column("id", DataType::Text).build().unwrap()).unwrap();
+        let orders = SchemaBuilder::new("orders")
+            .add_column("user_id", DataType::Text)
+            .foreign_key(ForeignKey::new("orders_user_fkey", vec!["user_id".into()], "users", vec!["id".into()]))
+            .build()
+            .unwrap();
+        catalog.create_table(orders).unwrap();
+        let before = repo.head().unwrap();
+
+        assert!(matches!(catalog.rename_table("users", "orders"), Err(SchemaError::TableExists(_))));
+        assert!(matches!(catalog.rename_table("nope", "other"), Err(SchemaError::TableNotFound(_))));
+
+        catalog.rename_table("users", "customers").unwrap();
+        let head = repo.head().unwrap();
+        assert_eq!(repo.get_commit(head).unwrap().parent_ids, vec![before]);
+        assert!(!catalog.table_exists("users"));
+        let customers = catalog.get_table("customers").unwrap();
+        assert_eq!(customers.name, "customers");
+        assert_eq!(customers.version, 2);
+        assert!(repo.table_exists(&TableName::new("customers").unwrap(), head).unwrap());
+
+        // Foreign keys follow the table to its new name
+        assert_eq!(catalog.get_table("orders").unwrap().foreign_keys[0].references_table, "customers");
+    }
+
     #[test]
     fn test_drop_nonexistent_table() {
         let (catalog, _dir) = setup_catalog();
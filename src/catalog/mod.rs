@@ -8,5 +8,6 @@ mod schema;
 mod types;
 
 pub use manager::Catalog;
-pub use schema::{SchemaBuilder, SchemaError, SchemaVersion, TableSchema};
-pub use types::{ColumnDef, Constraint, DataType};
+pub(crate) use manager::{SCHEMA_DIR, SEQUENCE_DIR};
+pub use schema::{SchemaBuilder, SchemaError, SchemaVersion, TableSchema, SCHEMALESS_KEY};
+pub use types::{CheckConstraint, ColumnDef, Constraint, DataType, ForeignKey, UniqueConstraint};
@@ -4,11 +4,16 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use super::types::{ColumnDef, Constraint, DataType};
+use super::types::{CheckConstraint, ColumnDef, Constraint, DataType, ForeignKey, UniqueConstraint};
+use crate::sql::eval::evaluate;
+use crate::sql::KeyStrategy;
 
 /// Schema version for tracking migrations.
 pub type SchemaVersion = u32;
 
+/// Primary key column of a schemaless table.
+pub const SCHEMALESS_KEY: &str = "id";
+
 /// Table schema definition.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TableSchema {
@@ -18,12 +23,32 @@ pub struct TableSchema {
     pub version: SchemaVersion,
     /// Column definitions.
     pub columns: Vec<ColumnDef>,
-    /// Primary key column name (optional, defaults to auto-generated row key).
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub primary_key: Option<String>,
+    /// Primary key columns, in key order (empty means auto-generated row keys).
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "deserialize_primary_key"
+    )]
+    pub primary_key: Vec<String>,
+    /// Table-level check constraints.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<CheckConstraint>,
+    /// Table-level unique constraints.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unique: Vec<UniqueConstraint>,
+    /// Foreign keys referencing other tables (or this one).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub foreign_keys: Vec<ForeignKey>,
     /// Table description/comment.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Rows may have any columns, of any type; only the primary key,
+    /// [`SCHEMALESS_KEY`], is declared.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub schemaless: bool,
+    /// How rows are keyed when the key isn't taken from the primary key.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub key_strategy: KeyStrategy,
     /// Creation timestamp.
     pub created_at: DateTime<Utc>,
     /// Last modification timestamp.
@@ -38,16 +63,53 @@ impl TableSchema {
             name: name.into(),
             version: 1,
             columns,
-            primary_key: None,
+            primary_key: Vec::new(),
+            checks: Vec::new(),
+            unique: Vec::new(),
+            foreign_keys: Vec::new(),
             description: None,
+            schemaless: false,
+            key_strategy: KeyStrategy::default(),
             created_at: now,
             updated_at: now,
         }
     }
 
+    /// Create the schema of a table that takes rows with any columns.
+    ///
+    /// Rows are keyed by their [`SCHEMALESS_KEY`] column.
+    pub fn schemaless(name: impl Into<String>) -> Self {
+        let key = ColumnDef::new(SCHEMALESS_KEY, DataType::Text).with_constraint(Constraint::PrimaryKey);
+        Self {
+            schemaless: true,
+            ..Self::new(name, vec![key]).with_primary_key(SCHEMALESS_KEY)
+        }
+    }
+
     /// Set the primary key column.
     pub fn with_primary_key(mut self, column_name: impl Into<String>) -> Self {
-        self.primary_key = Some(column_name.into());
+        self.primary_key = vec![column_name.into()];
+        self
+    }
+
+    /// Set a composite primary key over several columns.
+    pub fn with_primary_key_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.primary_key = columns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Check if a column is part of the primary key.
+    pub fn is_primary_key(&self, column: &str) -> bool {
+        self.primary_key.iter().any(|pk| pk == column)
+    }
+
+    /// Set how rows are keyed when the key isn't taken from the primary key.
+    pub fn with_key_strategy(mut self, strategy: KeyStrategy) -> Self {
+        self.key_strategy = strategy;
         self
     }
 
@@ -57,6 +119,29 @@ impl TableSchema {
         self
     }
 
+    /// Add a table-level check constraint.
+    pub fn with_check(mut self, check: CheckConstraint) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    /// Add a table-level unique constraint.
+    pub fn with_unique(mut self, unique: UniqueConstraint) -> Self {
+        self.unique.push(unique);
+        self
+    }
+
+    /// Add a foreign key.
+    pub fn with_foreign_key(mut self, foreign_key: ForeignKey) -> Self {
+        self.foreign_keys.push(foreign_key);
+        self
+    }
+
+    /// Iterate over every check constraint, column-level first.
+    pub fn all_checks(&self) -> impl Iterator<Item = &CheckConstraint> {
+        self.columns.iter().flat_map(|c| c.checks()).chain(&self.checks)
+    }
+
     /// Get a column definition by name.
     pub fn get_column(&self, name: &str) -> Option<&ColumnDef> {
         self.columns.iter().find(|c| c.name == name)
@@ -77,27 +162,93 @@ impl TableSchema {
             }
         }
 
-        // Check primary key references valid column
-        if let Some(pk) = &self.primary_key {
-            if !self.columns.iter().any(|c| &c.name == pk) {
+        // Check primary key references valid, distinct columns
+        let mut seen = std::collections::HashSet::new();
+        for pk in &self.primary_key {
+            if self.get_column(pk).is_none() || !seen.insert(pk) {
                 return Err(SchemaError::InvalidPrimaryKey(pk.clone()));
             }
         }
 
+        // Unique and foreign keys need existing columns, and a foreign key one
+        // referenced column per referencing column
+        let key_columns = self
+            .unique
+            .iter()
+            .flat_map(|u| &u.columns)
+            .chain(self.foreign_keys.iter().flat_map(|fk| &fk.columns));
+        for column in key_columns {
+            if self.get_column(column).is_none() {
+                return Err(SchemaError::ColumnNotFound(column.clone()));
+            }
+        }
+        for fk in &self.foreign_keys {
+            if fk.columns.is_empty() || fk.columns.len() != fk.references_columns.len() {
+                return Err(SchemaError::InvalidForeignKey(fk.name.clone()));
+            }
+        }
+
+        // Check constraints may only reference columns of this table
+        for check in self.all_checks() {
+            for column in check.expr.referenced_columns() {
+                if self.get_column(column).is_none() {
+                    return Err(SchemaError::ColumnNotFound(column.to_string()));
+                }
+            }
+        }
+
         Ok(())
     }
 
     /// Validate a row against this schema.
+    ///
+    /// Any object is a valid row of a schemaless table.
     pub fn validate_row(&self, row: &Value) -> Result<(), SchemaError> {
         let obj = row
             .as_object()
             .ok_or_else(|| SchemaError::InvalidRow("row must be a JSON object".into()))?;
+        if self.schemaless {
+            return Ok(());
+        }
 
         // Check all columns
         for col in &self.columns {
             let value = obj.get(&col.name);
             col.validate(value)
-                .map_err(|e| SchemaError::InvalidRow(e))?;
+                .map_err(SchemaError::InvalidRow)?;
+        }
+
+        self.validate_checks(obj)
+    }
+
+    /// Evaluate every check constraint against a row.
+    ///
+    /// As in SQL, a check whose inputs include NULL is not violated.
+    pub fn validate_checks(&self, obj: &serde_json::Map<String, Value>) -> Result<(), SchemaError> {
+        let mut row = obj.clone();
+        for col in &self.columns {
+            row.entry(col.name.clone()).or_insert(Value::Null);
+        }
+
+        for check in self.all_checks() {
+            let has_null = check
+                .expr
+                .referenced_columns()
+                .iter()
+                .any(|c| row.get(*c).is_none_or(Value::is_null));
+            if has_null {
+                continue;
+            }
+
+            let result = evaluate(&check.expr, &row).map_err(|e| {
+                SchemaError::InvalidRow(format!(
+                    "check constraint '{}' could not be evaluated: {}",
+                    check.name, e
+                ))
+            })?;
+            if result == Value::Bool(false) {
+                return Err(SchemaError::CheckViolation(check.name.clone()));
+            }
         }
 
         Ok(())
@@ -110,10 +261,18 @@ impl TableSchema {
             .cloned()
             .ok_or_else(|| SchemaError::InvalidRow("row must be a JSON object".into()))?;
 
+        // Defaults are evaluated per row so that e.g. NOW() is fresh each time
+        let empty_row = serde_json::Map::new();
         for col in &self.columns {
             if !obj.contains_key(&col.name) {
-                if let Some(default) = col.default_value() {
-                    obj.insert(col.name.clone(), default.clone());
+                if let Some(default) = col.default_expr() {
+                    let value = evaluate(default, &empty_row).map_err(|e| {
+                        SchemaError::InvalidRow(format!(
+                            "default for column '{}' could not be evaluated: {}",
+                            col.name, e
+                        ))
+                    })?;
+                    obj.insert(col.name.clone(), value);
                 }
             }
         }
@@ -146,7 +305,7 @@ impl TableSchema {
             .ok_or_else(|| SchemaError::ColumnNotFound(name.to_string()))?;
 
         // Don't allow removing primary key
-        if self.primary_key.as_deref() == Some(name) {
+        if self.is_primary_key(name) {
             return Err(SchemaError::CannotRemovePrimaryKey(name.to_string()));
         }
 
@@ -154,6 +313,97 @@ impl TableSchema {
         self.bump_version();
         Ok(col)
     }
+
+    /// Rename a column (schema migration), along with the keys, constraints
+    /// and defaults that name it.
+    ///
+    /// Row keys are made from key values rather than column names, so a
+    /// primary key column can be renamed too, except the key of a schemaless
+    /// table.
+    pub fn rename_column(&mut self, old: &str, new: &str) -> Result<(), SchemaError> {
+        if self.schemaless && old == SCHEMALESS_KEY {
+            return Err(SchemaError::InvalidPrimaryKey(format!(
+                "the key of schemaless table '{}' can't be renamed",
+                self.name
+            )));
+        }
+        if self.get_column(new).is_some() {
+            return Err(SchemaError::DuplicateColumn(new.to_string()));
+        }
+        let column = self
+            .columns
+            .iter_mut()
+            .find(|c| c.name == old)
+            .ok_or_else(|| SchemaError::ColumnNotFound(old.to_string()))?;
+        column.name = new.to_string();
+
+        let rename = |name: &mut String| {
+            if name == old {
+                *name = new.to_string();
+            }
+        };
+        self.primary_key.iter_mut().for_each(rename);
+        for unique in &mut self.unique {
+            unique.columns.iter_mut().for_each(rename);
+        }
+        for fk in &mut self.foreign_keys {
+            fk.columns.iter_mut().for_each(rename);
+        }
+        for constraint in self.columns.iter_mut().flat_map(|c| &mut c.constraints) {
+            match constraint {
+                Constraint::Default(expr) | Constraint::Check(CheckConstraint { expr, .. }) => {
+                    expr.rename_column(old, new)
+                }
+                _ => {}
+            }
+        }
+        for check in &mut self.checks {
+            check.expr.rename_column(old, new);
+        }
+        let name = self.name.clone();
+        self.rename_referenced_column(&name, old, new);
+
+        self.bump_version();
+        Ok(())
+    }
+
+    /// Follow the rename of `table`'s column `old` to `new` in the foreign
+    /// keys that reference it. Returns whether any did.
+    pub fn rename_referenced_column(&mut self, table: &str, old: &str, new: &str) -> bool {
+        let mut renamed = false;
+        for fk in self.foreign_keys.iter_mut().filter(|fk| fk.references_table == table) {
+            for column in fk.references_columns.iter_mut().filter(|c| *c == old) {
+                *column = new.to_string();
+                renamed = true;
+            }
+        }
+        renamed
+    }
+}
+
+/// Whether a field holds its default, which needn't be written out.
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// Read a primary key as a list of columns, or as the single column name
+/// written by older versions.
+fn deserialize_primary_key<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PrimaryKey {
+        Single(String),
+        Columns(Vec<String>),
+    }
+
+    Ok(match Option::<PrimaryKey>::deserialize(deserializer)? {
+        None => Vec::new(),
+        Some(PrimaryKey::Single(column)) => vec![column],
+        Some(PrimaryKey::Columns(columns)) => columns,
+    })
 }
 
 /// Schema-related errors.
@@ -168,12 +418,18 @@ pub enum SchemaError {
     #[error("column not found: {0}")]
     ColumnNotFound(String),
 
+    #[error("invalid foreign key: {0}")]
+    InvalidForeignKey(String),
+
     #[error("cannot remove primary key column: {0}")]
     CannotRemovePrimaryKey(String),
 
     #[error("invalid row: {0}")]
     InvalidRow(String),
 
+    #[error("row violates check constraint '{0}'")]
+    CheckViolation(String),
+
     #[error("table already exists: {0}")]
     TableExists(String),
 
@@ -187,12 +443,35 @@ pub enum SchemaError {
     Storage(String),
 }
 
+impl SchemaError {
+    /// A short name for the kind of error, stable across releases.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SchemaError::DuplicateColumn(_) => "duplicate_column",
+            SchemaError::InvalidPrimaryKey(_) => "invalid_primary_key",
+            SchemaError::ColumnNotFound(_) => "column_not_found",
+            SchemaError::InvalidForeignKey(_) => "invalid_foreign_key",
+            SchemaError::CannotRemovePrimaryKey(_) => "cannot_remove_primary_key",
+            SchemaError::InvalidRow(_) => "invalid_row",
+            SchemaError::CheckViolation(_) => "check_violation",
+            SchemaError::TableExists(_) => "table_exists",
+            SchemaError::TableNotFound(_) => "table_not_found",
+            SchemaError::VersionMismatch { .. } => "schema_version_mismatch",
+            SchemaError::Storage(_) => "storage",
+        }
+    }
+}
+
 /// Builder for creating table schemas.
 pub struct SchemaBuilder {
     name: String,
     columns: Vec<ColumnDef>,
-    primary_key: Option<String>,
+    primary_key: Vec<String>,
+    checks: Vec<CheckConstraint>,
+    unique: Vec<UniqueConstraint>,
+    foreign_keys: Vec<ForeignKey>,
     description: Option<String>,
+    key_strategy: KeyStrategy,
 }
 
 impl SchemaBuilder {
@@ -201,8 +480,12 @@ impl SchemaBuilder {
         Self {
             name: name.into(),
             columns: Vec::new(),
-            primary_key: None,
+            primary_key: Vec::new(),
+            checks: Vec::new(),
+            unique: Vec::new(),
+            foreign_keys: Vec::new(),
             description: None,
+            key_strategy: KeyStrategy::default(),
         }
     }
 
@@ -228,7 +511,35 @@ impl SchemaBuilder {
 
     /// Set the primary key.
     pub fn primary_key(mut self, column_name: impl Into<String>) -> Self {
-        self.primary_key = Some(column_name.into());
+        self.primary_key = vec![column_name.into()];
+        self
+    }
+
+    /// Set a composite primary key over several columns.
+    pub fn primary_key_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.primary_key = columns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Add a table-level check constraint.
+    pub fn check(mut self, check: CheckConstraint) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    /// Add a table-level unique constraint.
+    pub fn unique(mut self, unique: UniqueConstraint) -> Self {
+        self.unique.push(unique);
+        self
+    }
+
+    /// Add a foreign key.
+    pub fn foreign_key(mut self, foreign_key: ForeignKey) -> Self {
+        self.foreign_keys.push(foreign_key);
         self
     }
 
@@ -238,15 +549,23 @@ impl SchemaBuilder {
         self
     }
 
+    /// Set how rows are keyed when the key isn't taken from the primary key.
+    pub fn key_strategy(mut self, strategy: KeyStrategy) -> Self {
+        self.key_strategy = strategy;
+        self
+    }
+
     /// Build the schema.
     pub fn build(self) -> Result<TableSchema, SchemaError> {
-        let mut schema = TableSchema::new(self.name, self.columns);
-        if let Some(pk) = self.primary_key {
-            schema = schema.with_primary_key(pk);
-        }
+        let mut schema = TableSchema::new(self.name, self.columns)
+            .with_primary_key_columns(self.primary_key)
+            .with_key_strategy(self.key_strategy);
         if let Some(desc) = self.description {
             schema = schema.with_description(desc);
         }
+        schema.checks = self.checks;
+        schema.unique = self.unique;
+        schema.foreign_keys = self.foreign_keys;
         schema.validate()?;
         Ok(schema)
     }
@@ -257,6 +576,8 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    use crate::sql::{BinaryOperator, Expr, LiteralValue};
+
     fn sample_schema() -> TableSchema {
         SchemaBuilder::new("users")
             .add_required_column("id", DataType::Uuid)
@@ -273,7 +594,7 @@ mod tests {
     fn test_schema_validation() {
         let schema = sample_schema();
         assert!(schema.validate().is_ok());
-        assert_eq!(schema.primary_key, Some("id".to_string()));
+        assert_eq!(schema.primary_key, vec!["id".to_string()]);
     }
 
     #[test]
@@ -324,13 +645,40 @@ mod tests {
         assert!(schema.validate_row(&wrong_type).is_err());
     }
 
+    #[test]
+    fn test_check_constraint_validation() {
+        let check = CheckConstraint::new(
+            "items_qty_check",
+            Expr::BinaryOp {
+                left: Box::new(Expr::Column("qty".into())),
+                op: BinaryOperator::Gt,
+                right: Box::new(Expr::Literal(LiteralValue::Integer(0))),
+            },
+        );
+        let schema = SchemaBuilder::new("items")
+            .column(ColumnDef::new("qty", DataType::Integer).with_constraint(Constraint::Check(check)))
+            .build()
+            .unwrap();
+
+        assert!(schema.validate_row(&json!({"qty": 3})).is_ok());
+        assert!(schema.validate_row(&json!({})).is_ok());
+
+        let err = schema.validate_row(&json!({"qty": 0})).unwrap_err();
+        assert!(matches!(err, SchemaError::CheckViolation(ref name) if name == "items_qty_check"));
+
+        // Checks survive a round trip through the catalog's JSON form
+        let json = serde_json::to_string(&schema).unwrap();
+        let restored: TableSchema = serde_json::from_str(&json).unwrap();
+        assert!(restored.validate_row(&json!({"qty": -1})).is_err());
+    }
+
     #[test]
     fn test_apply_defaults() {
         let schema = SchemaBuilder::new("items")
             .add_required_column("id", DataType::Text)
             .column(
                 ColumnDef::new("status", DataType::Text)
-                    .with_constraint(Constraint::Default(json!("pending"))),
+                    .with_constraint(Constraint::Default(Expr::Literal(LiteralValue::String("pending".into())))),
             )
             .build()
             .unwrap();
@@ -363,6 +711,38 @@ mod tests {
         assert!(matches!(result, Err(SchemaError::CannotRemovePrimaryKey(_))));
     }
 
+    #[test]
+    fn test_rename_column() {
+        let adult = Expr::BinaryOp {
+            left: Box::new(Expr::Column("age".into())),
+            op: BinaryOperator::GtEq,
+            right: Box::new(Expr::Literal(LiteralValue::Integer(18))),
+        };
+        let mut schema = SchemaBuilder::new("users")
+            .add_required_column("id", DataType::Integer)
+            .add_column("age", DataType::Integer)
+            .add_column("manager", DataType::Integer)
+            .primary_key("id")
+            .check(CheckConstraint::new("adult", adult))
+            .foreign_key(ForeignKey::new("manager_fkey", vec!["manager".into()], "users", vec!["id".into()]))
+            .build()
+            .unwrap();
+        let version = schema.version;
+
+        schema.rename_column("id", "user_id").unwrap();
+        schema.rename_column("age", "years").unwrap();
+        assert_eq!(schema.version, version + 2);
+        assert_eq!(schema.primary_key, vec!["user_id"]);
+        assert_eq!(schema.foreign_keys[0].references_columns, vec!["user_id"]);
+        assert_eq!(schema.checks[0].expr.referenced_columns(), vec!["years"]);
+        assert!(schema.validate().is_ok());
+
+        assert!(matches!(schema.rename_column("age", "x"), Err(SchemaError::ColumnNotFound(_))));
+        assert!(matches!(schema.rename_column("years", "manager"), Err(SchemaError::DuplicateColumn(_))));
+        let mut docs = TableSchema::schemaless("docs");
+        assert!(matches!(docs.rename_column("id", "key"), Err(SchemaError::InvalidPrimaryKey(_))));
+    }
+
     #[test]
     fn test_schema_serialization() {
         let schema = sample_schema();
@@ -373,4 +753,30 @@ mod tests {
         assert_eq!(schema.columns.len(), deserialized.columns.len());
         assert_eq!(schema.primary_key, deserialized.primary_key);
     }
+
+    #[test]
+    fn test_composite_primary_key() {
+        let schema = SchemaBuilder::new("members")
+            .add_required_column("tenant_id", DataType::Text)
+            .add_required_column("id", DataType::Integer)
+            .primary_key_columns(["tenant_id", "id"])
+            .build()
+            .unwrap();
+        assert!(schema.is_primary_key("tenant_id") && schema.is_primary_key("id"));
+
+        let json = serde_json::to_value(&schema).unwrap();
+        assert_eq!(json["primary_key"], json!(["tenant_id", "id"]));
+
+        // Schemas written with a single primary key column still load
+        let mut legacy = json;
+        legacy["primary_key"] = json!("id");
+        let restored: TableSchema = serde_json::from_value(legacy).unwrap();
+        assert_eq!(restored.primary_key, vec!["id".to_string()]);
+
+        let result = SchemaBuilder::new("bad")
+            .add_column("id", DataType::Integer)
+            .primary_key_columns(["id", "id"])
+            .build();
+        assert!(matches!(result, Err(SchemaError::InvalidPrimaryKey(_))));
+    }
 }
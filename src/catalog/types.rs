@@ -5,6 +5,10 @@ use std::fmt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::sql::decimal::Decimal;
+use crate::sql::eval::{parse_date, parse_timestamp};
+use crate::sql::{quote_ident, quote_idents, Expr, LiteralValue, ReferentialAction};
+
 /// SQL-like data types supported by GitDB.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -15,12 +19,16 @@ pub enum DataType {
     Integer,
     /// Floating point numbers (DOUBLE in SQL).
     Float,
+    /// Exact decimals (stored as strings, so no digits are lost).
+    Decimal { precision: u8, scale: u8 },
     /// Boolean values.
     Boolean,
     /// JSON objects or arrays.
     Json,
     /// Timestamps (stored as ISO 8601 strings).
     Timestamp,
+    /// Calendar dates (stored as `YYYY-MM-DD` strings).
+    Date,
     /// UUIDs (stored as strings).
     Uuid,
 }
@@ -32,13 +40,16 @@ impl DataType {
             (DataType::Text, Value::String(_)) => true,
             (DataType::Integer, Value::Number(n)) => n.is_i64() || n.is_u64(),
             (DataType::Float, Value::Number(_)) => true,
+            (DataType::Decimal { precision, scale }, Value::String(s)) => {
+                Decimal::parse(s).is_some_and(|d| {
+                    d.scale() <= u32::from(*scale)
+                        && d.integer_digits() <= u32::from(precision - scale)
+                })
+            }
             (DataType::Boolean, Value::Bool(_)) => true,
             (DataType::Json, Value::Object(_) | Value::Array(_)) => true,
-            (DataType::Timestamp, Value::String(s)) => {
-                // Basic ISO 8601 check
-                chrono::DateTime::parse_from_rfc3339(s).is_ok()
-                    || chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").is_ok()
-            }
+            (DataType::Timestamp, Value::String(s)) => parse_timestamp(s).is_some(),
+            (DataType::Date, Value::String(s)) => parse_date(s).is_some(),
             (DataType::Uuid, Value::String(s)) => {
                 // Basic UUID format check (8-4-4-4-12)
                 s.len() == 36 && s.chars().filter(|c| *c == '-').count() == 4
@@ -48,15 +59,17 @@ impl DataType {
     }
 
     /// Get the SQL name for this type.
-    pub fn sql_name(&self) -> &'static str {
+    pub fn sql_name(&self) -> String {
         match self {
-            DataType::Text => "TEXT",
-            DataType::Integer => "INTEGER",
-            DataType::Float => "REAL",
-            DataType::Boolean => "BOOLEAN",
-            DataType::Json => "JSON",
-            DataType::Timestamp => "TIMESTAMP",
-            DataType::Uuid => "UUID",
+            DataType::Text => "TEXT".to_string(),
+            DataType::Integer => "INTEGER".to_string(),
+            DataType::Float => "REAL".to_string(),
+            DataType::Decimal { precision, scale } => format!("DECIMAL({}, {})", precision, scale),
+            DataType::Boolean => "BOOLEAN".to_string(),
+            DataType::Json => "JSON".to_string(),
+            DataType::Timestamp => "TIMESTAMP".to_string(),
+            DataType::Date => "DATE".to_string(),
+            DataType::Uuid => "UUID".to_string(),
         }
     }
 }
@@ -68,8 +81,11 @@ impl fmt::Display for DataType {
 }
 
 /// Column constraints.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
+///
+/// Expressions are stored as SQL text (see [`StoredConstraint`]), so the
+/// catalog format doesn't depend on the layout of [`Expr`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredConstraint", into = "StoredConstraint")]
 pub enum Constraint {
     /// Column cannot be null.
     NotNull,
@@ -77,10 +93,10 @@ pub enum Constraint {
     Unique,
     /// Column is the primary key (implies NotNull + Unique).
     PrimaryKey,
-    /// Default value for the column.
-    Default(Value),
-    /// Check constraint (expression stored as string for now).
-    Check(String),
+    /// Default expression, evaluated each time a row omits the column.
+    Default(Expr),
+    /// Check constraint evaluated against every row written.
+    Check(CheckConstraint),
 }
 
 impl Constraint {
@@ -100,8 +116,8 @@ impl Constraint {
             Constraint::NotNull => "NOT NULL".to_string(),
             Constraint::Unique => "UNIQUE".to_string(),
             Constraint::PrimaryKey => "PRIMARY KEY".to_string(),
-            Constraint::Default(v) => format!("DEFAULT {}", v),
-            Constraint::Check(expr) => format!("CHECK ({})", expr),
+            Constraint::Default(expr) => format!("DEFAULT {}", expr),
+            Constraint::Check(check) => check.to_string(),
         }
     }
 }
@@ -112,6 +128,196 @@ impl fmt::Display for Constraint {
     }
 }
 
+/// On-disk form of a [`Constraint`].
+///
+/// Older catalogs stored a default as a plain JSON value under `default`
+/// and a check as bare SQL text, so both are still read.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum StoredConstraint {
+    NotNull,
+    Unique,
+    PrimaryKey,
+    DefaultSql(#[serde(with = "expr_sql")] Expr),
+    Default(Value),
+    Check(StoredCheck),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredCheck {
+    Named(CheckConstraint),
+    Legacy(#[serde(with = "expr_sql")] Expr),
+}
+
+/// Name given to checks read from catalogs that didn't name them.
+const LEGACY_CHECK_NAME: &str = "check";
+
+impl From<StoredConstraint> for Constraint {
+    fn from(stored: StoredConstraint) -> Self {
+        match stored {
+            StoredConstraint::NotNull => Constraint::NotNull,
+            StoredConstraint::Unique => Constraint::Unique,
+            StoredConstraint::PrimaryKey => Constraint::PrimaryKey,
+            StoredConstraint::DefaultSql(expr) => Constraint::Default(expr),
+            StoredConstraint::Default(value) => Constraint::Default(Expr::Literal(LiteralValue::from_json(&value))),
+            StoredConstraint::Check(StoredCheck::Named(check)) => Constraint::Check(check),
+            StoredConstraint::Check(StoredCheck::Legacy(expr)) => {
+                Constraint::Check(CheckConstraint::new(LEGACY_CHECK_NAME, expr))
+            }
+        }
+    }
+}
+
+impl From<Constraint> for StoredConstraint {
+    fn from(constraint: Constraint) -> Self {
+        match constraint {
+            Constraint::NotNull => StoredConstraint::NotNull,
+            Constraint::Unique => StoredConstraint::Unique,
+            Constraint::PrimaryKey => StoredConstraint::PrimaryKey,
+            Constraint::Default(expr) => StoredConstraint::DefaultSql(expr),
+            Constraint::Check(check) => StoredConstraint::Check(StoredCheck::Named(check)),
+        }
+    }
+}
+
+/// Serialize an expression as SQL text, and parse it back when reading.
+mod expr_sql {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::sql::{Expr, Parser};
+
+    pub fn serialize<S: Serializer>(expr: &Expr, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(expr)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Expr, D::Error> {
+        let sql = String::deserialize(deserializer)?;
+        Parser::parse_expr(&sql).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A named CHECK constraint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckConstraint {
+    /// Constraint name, reported when a row violates it.
+    pub name: String,
+    /// Boolean expression every row must satisfy, stored as SQL text.
+    #[serde(rename = "sql", with = "expr_sql")]
+    pub expr: Expr,
+}
+
+impl CheckConstraint {
+    /// Create a new check constraint.
+    pub fn new(name: impl Into<String>, expr: Expr) -> Self {
+        Self {
+            name: name.into(),
+            expr,
+        }
+    }
+}
+
+impl fmt::Display for CheckConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CONSTRAINT {} CHECK ({})", quote_ident(&self.name), self.expr)
+    }
+}
+
+/// A named UNIQUE constraint over one or more columns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UniqueConstraint {
+    /// Constraint name.
+    pub name: String,
+    /// Columns whose combined values must be unique.
+    pub columns: Vec<String>,
+}
+
+impl UniqueConstraint {
+    /// Create a new unique constraint.
+    pub fn new(name: impl Into<String>, columns: Vec<String>) -> Self {
+        Self {
+            name: name.into(),
+            columns,
+        }
+    }
+}
+
+impl fmt::Display for UniqueConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CONSTRAINT {} UNIQUE ({})", quote_ident(&self.name), quote_idents(&self.columns))
+    }
+}
+
+/// A named FOREIGN KEY constraint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    /// Constraint name, reported when a row violates it.
+    pub name: String,
+    /// Referencing columns in this table.
+    pub columns: Vec<String>,
+    /// Referenced (parent) table.
+    pub references_table: String,
+    /// Referenced columns in the parent table, matched up with `columns`.
+    pub references_columns: Vec<String>,
+    /// What happens to referencing rows when the parent row is deleted.
+    #[serde(default)]
+    pub on_delete: ReferentialAction,
+    /// What happens to referencing rows when the parent key is updated.
+    #[serde(default)]
+    pub on_update: ReferentialAction,
+}
+
+impl ForeignKey {
+    /// Create a foreign key with the default `NO ACTION` behaviour.
+    pub fn new(
+        name: impl Into<String>,
+        columns: Vec<String>,
+        references_table: impl Into<String>,
+        references_columns: Vec<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            columns,
+            references_table: references_table.into(),
+            references_columns,
+            on_delete: ReferentialAction::NoAction,
+            on_update: ReferentialAction::NoAction,
+        }
+    }
+
+    /// Set the action taken when the parent row is deleted.
+    pub fn on_delete(mut self, action: ReferentialAction) -> Self {
+        self.on_delete = action;
+        self
+    }
+
+    /// Set the action taken when the parent key is updated.
+    pub fn on_update(mut self, action: ReferentialAction) -> Self {
+        self.on_update = action;
+        self
+    }
+}
+
+impl fmt::Display for ForeignKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({})",
+            quote_ident(&self.name),
+            quote_idents(&self.columns),
+            quote_ident(&self.references_table),
+            quote_idents(&self.references_columns)
+        )?;
+        if self.on_delete != ReferentialAction::NoAction {
+            write!(f, " ON DELETE {}", self.on_delete)?;
+        }
+        if self.on_update != ReferentialAction::NoAction {
+            write!(f, " ON UPDATE {}", self.on_update)?;
+        }
+        Ok(())
+    }
+}
+
 /// Full column definition including name, type, and constraints.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ColumnDef {
@@ -160,20 +366,69 @@ impl ColumnDef {
         self.constraints.iter().any(|c| c.is_unique())
     }
 
-    /// Get the default value, if any.
-    pub fn default_value(&self) -> Option<&Value> {
+    /// Get the default expression, if any.
+    pub fn default_expr(&self) -> Option<&Expr> {
         self.constraints.iter().find_map(|c| {
-            if let Constraint::Default(v) = c {
-                Some(v)
+            if let Constraint::Default(expr) = c {
+                Some(expr)
             } else {
                 None
             }
         })
     }
 
+    /// Get the check constraints declared on this column.
+    pub fn checks(&self) -> impl Iterator<Item = &CheckConstraint> {
+        self.constraints.iter().filter_map(|c| {
+            if let Constraint::Check(check) = c {
+                Some(check)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Convert a SQL value into this column's storage form.
+    ///
+    /// SQL has no JSON literal, so string literals written to a JSON column
+    /// are parsed as JSON documents. Timestamps are normalized to RFC 3339
+    /// UTC, so `'2024-01-01'` is stored as `2024-01-01T00:00:00+00:00`, and
+    /// dates to `YYYY-MM-DD`, the UTC date of a timestamp given for one.
+    /// Decimals are rounded to the column's scale and stored as strings.
+    pub fn coerce(&self, value: Value) -> Value {
+        match (&self.data_type, value) {
+            (DataType::Json, Value::String(s)) => match serde_json::from_str::<Value>(&s) {
+                Ok(doc @ (Value::Object(_) | Value::Array(_))) => doc,
+                _ => Value::String(s),
+            },
+            // Store timestamps in one canonical RFC 3339 UTC form
+            (DataType::Timestamp, Value::String(s)) => match parse_timestamp(&s) {
+                Some(ts) => Value::String(ts.to_rfc3339()),
+                None => Value::String(s),
+            },
+            (DataType::Date, Value::String(s)) => match parse_date(&s).or_else(|| Some(parse_timestamp(&s)?.date_naive())) {
+                Some(date) => Value::String(date.format("%Y-%m-%d").to_string()),
+                None => Value::String(s),
+            },
+            (DataType::Decimal { scale, .. }, value @ (Value::Number(_) | Value::String(_))) => {
+                match Decimal::from_value(&value).and_then(|d| d.rescale(u32::from(*scale))) {
+                    Some(d) => d.to_value(),
+                    None => value,
+                }
+            }
+            (_, value) => value,
+        }
+    }
+
     /// Validate a value against this column definition.
     pub fn validate(&self, value: Option<&Value>) -> Result<(), String> {
         match value {
+            Some(Value::Null) => {
+                if !self.is_nullable() {
+                    return Err(format!("column '{}' cannot be null", self.name));
+                }
+                Ok(())
+            }
             Some(v) => {
                 if !self.data_type.matches(v) {
                     return Err(format!(
@@ -186,7 +441,7 @@ impl ColumnDef {
                 Ok(())
             }
             None => {
-                if !self.is_nullable() && self.default_value().is_none() {
+                if !self.is_nullable() && self.default_expr().is_none() {
                     return Err(format!("column '{}' cannot be null", self.name));
                 }
                 Ok(())
@@ -197,7 +452,7 @@ impl ColumnDef {
 
 impl fmt::Display for ColumnDef {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {}", self.name, self.data_type)?;
+        write!(f, "{} {}", quote_ident(&self.name), self.data_type)?;
         for constraint in &self.constraints {
             write!(f, " {}", constraint)?;
         }
@@ -210,7 +465,10 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    use crate::sql::Parser;
+
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_data_type_matches() {
         assert!(DataType::Text.matches(&json!("hello")));
         assert!(!DataType::Text.matches(&json!(123)));
@@ -229,6 +487,48 @@ mod tests {
         assert!(DataType::Json.matches(&json!([1, 2, 3])));
     }
 
+    #[test]
+    fn test_timestamp_coercion() {
+        assert!(DataType::Timestamp.matches(&json!("2024-01-01")));
+        assert!(DataType::Timestamp.matches(&json!("2024-01-01 12:30:00")));
+        assert!(!DataType::Timestamp.matches(&json!("01/02/2024")));
+
+        let col = ColumnDef::new("created_at", DataType::Timestamp);
+        assert_eq!(
+            col.coerce(json!("2024-01-01T12:00:00+02:00")),
+            json!("2024-01-01T10:00:00+00:00")
+        );
+        assert_eq!(col.coerce(json!("not a date")), json!("not a date"));
+    }
+
+    #[test]
+    fn test_date_coercion() {
+        assert!(DataType::Date.matches(&json!("2024-01-31")));
+        assert!(!DataType::Date.matches(&json!("2024-01-31T00:00:00+00:00")));
+
+        let col = ColumnDef::new("due", DataType::Date);
+        assert_eq!(col.coerce(json!("2024-01-31")), json!("2024-01-31"));
+        assert_eq!(col.coerce(json!("2024-01-31 22:00:00-05:00")), json!("2024-02-01"));
+        assert_eq!(col.coerce(json!("someday")), json!("someday"));
+    }
+
+    #[test]
+    fn test_decimal_coercion() {
+        let price = DataType::Decimal { precision: 6, scale: 2 };
+        assert!(price.matches(&json!("1234.50")));
+        assert!(!price.matches(&json!("12345.00")));
+        assert!(!price.matches(&json!("1.234")));
+        assert!(!price.matches(&json!(1.5)));
+        assert_eq!(price.to_string(), "DECIMAL(6, 2)");
+
+        let col = ColumnDef::new("price", price);
+        assert_eq!(col.coerce(json!(1.5)), json!("1.50"));
+        assert_eq!(col.coerce(json!(7)), json!("7.00"));
+        assert_eq!(col.coerce(json!("2.345")), json!("2.35"));
+        assert_eq!(col.coerce(json!("cheap")), json!("cheap"));
+        assert!(col.validate(Some(&col.coerce(json!(99999)))).is_err());
+    }
+
     #[test]
     fn test_column_validation() {
         let col = ColumnDef::new("name", DataType::Text)
@@ -237,18 +537,46 @@ mod tests {
         assert!(col.validate(Some(&json!("Alice"))).is_ok());
         assert!(col.validate(Some(&json!(123))).is_err());
         assert!(col.validate(None).is_err());
+        assert!(col.validate(Some(&Value::Null)).is_err());
 
         let nullable_col = ColumnDef::new("nickname", DataType::Text);
         assert!(nullable_col.validate(None).is_ok());
+        assert!(nullable_col.validate(Some(&Value::Null)).is_ok());
     }
 
     #[test]
     fn test_column_with_default() {
         let col = ColumnDef::new("status", DataType::Text)
             .with_constraint(Constraint::NotNull)
-            .with_constraint(Constraint::Default(json!("active")));
+            .with_constraint(Constraint::Default(Expr::Literal(LiteralValue::String("active".into()))));
 
         assert!(col.validate(None).is_ok()); // Has default
-        assert_eq!(col.default_value(), Some(&json!("active")));
+        assert_eq!(col.to_string(), "status TEXT NOT NULL DEFAULT 'active'");
+    }
+
+    #[test]
+    fn test_legacy_constraints() {
+        // As written before defaults and checks were expressions
+        let legacy = json!({
+            "name": "name",
+            "data_type": "text",
+            "constraints": ["not_null", {"default": "anon"}, {"check": "length(name) > 0"}]
+        });
+        let col: ColumnDef = serde_json::from_value(legacy).unwrap();
+        assert_eq!(col.default_expr(), Some(&Expr::Literal(LiteralValue::String("anon".into()))));
+        let check = col.checks().next().unwrap();
+        assert_eq!(check.expr.to_string(), "length(name) > 0");
+
+        // Rewritten as SQL text, which reads back the same
+        let stored = serde_json::to_value(&col).unwrap();
+        assert_eq!(stored["constraints"][1], json!({"default_sql": "'anon'"}));
+        assert_eq!(stored["constraints"][2]["check"]["sql"], json!("length(name) > 0"));
+        assert_eq!(serde_json::from_value::<ColumnDef>(stored).unwrap(), col);
+
+        // Names that need quoting keep their case
+        let check = CheckConstraint::new("positive", Parser::parse_expr(r#""Age" > 0"#).unwrap());
+        let stored = serde_json::to_value(&check).unwrap();
+        assert_eq!(stored["sql"], json!(r#""Age" > 0"#));
+        assert_eq!(serde_json::from_value::<CheckConstraint>(stored).unwrap(), check);
     }
 }
@@ -1,17 +1,26 @@
 //! Database API - high-level interface for GitDB.
 
+use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use parking_lot::RwLock;
+use serde_json::Value;
 use thiserror::Error;
 
-use crate::catalog::Catalog;
-use crate::executor::{ExecuteError, QueryExecutor, QueryResult};
+use super::import;
+use super::migrate::{self, AppliedMigration, Migrations};
+use crate::catalog::{Catalog, SchemaVersion, TableSchema};
+use crate::sql::eval::values_equal;
+use crate::executor::{BatchConfig, CancelToken, ExecuteError, Metrics, Page, QueryExecutor, QueryResult, SpillConfig, VacuumReport};
 use crate::planner::{PlanError, QueryPlanner};
-use crate::sql::{ParseError, Parser, Statement};
-use crate::storage::{GitRepository, StorageError};
-use crate::transaction::{Transaction, TransactionError, TransactionManager, TxActive};
+use crate::sql::{quote_ident, quote_idents, LiteralValue, ParseError, Parser, Statement};
+use super::watch::Watch;
+use crate::storage::{
+    Change, CommitId, GcReport, GitRepository, Row as StorageRow, RowKey, StorageError, TableName,
+};
+use crate::transaction::{IsolationLevel, Transaction, TransactionError, TransactionManager, TxActive};
 
 /// Result type for database operations.
 pub type DatabaseResult<T> = Result<T, DatabaseError>;
@@ -51,6 +60,80 @@ pub enum DatabaseError {
 
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("statement {index} failed: {source}\n  in: {statement}")]
+    Script {
+        /// 1-based position of the failing statement in the script.
+        index: usize,
+        /// SQL text of the failing statement.
+        statement: String,
+        source: Box<DatabaseError>,
+    },
+
+    #[error("migration {version} failed: {source}")]
+    Migration {
+        /// Version of the migration that failed.
+        version: SchemaVersion,
+        source: Box<DatabaseError>,
+    },
+
+    #[error("line {line}: {source}")]
+    Import {
+        /// 1-based line of the input the failing record is on.
+        line: usize,
+        source: Box<DatabaseError>,
+    },
+
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("operation cancelled before it finished")]
+    Cancelled,
+
+    #[error("connection pool exhausted")]
+    PoolExhausted,
+}
+
+impl DatabaseError {
+    /// Check if retrying the operation may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DatabaseError::Execute(e) => e.is_retryable(),
+            DatabaseError::Transaction(e) => e.is_retryable(),
+            DatabaseError::Script { source, .. }
+            | DatabaseError::Migration { source, .. }
+            | DatabaseError::Import { source, .. } => source.is_retryable(),
+            DatabaseError::PoolExhausted => true,
+            _ => false,
+        }
+    }
+
+    /// A short name for the kind of error, stable across releases, for
+    /// tools to branch on instead of the message.
+    ///
+    /// Wrapped errors report their own kind, so a missing table is
+    /// `table_not_found` whichever layer noticed.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DatabaseError::Storage(e) => e.kind(),
+            DatabaseError::Parse(e) => e.kind(),
+            DatabaseError::Execute(e) => e.kind(),
+            DatabaseError::Plan(e) => e.kind(),
+            DatabaseError::Transaction(e) => e.kind(),
+            DatabaseError::Schema(e) => e.kind(),
+            DatabaseError::Script { source, .. }
+            | DatabaseError::Migration { source, .. }
+            | DatabaseError::Import { source, .. } => source.kind(),
+            DatabaseError::NotOpen => "not_open",
+            DatabaseError::AlreadyExists(_) => "database_exists",
+            DatabaseError::NotFound(_) => "database_not_found",
+            DatabaseError::InvalidConfig(_) => "invalid_config",
+            DatabaseError::Io(_) => "io",
+            DatabaseError::Json(_) => "invalid_json",
+            DatabaseError::Cancelled => "cancelled",
+            DatabaseError::PoolExhausted => "pool_exhausted",
+        }
+    }
 }
 
 /// Database configuration options.
@@ -66,6 +149,28 @@ pub struct DatabaseConfig {
     pub verbose: bool,
     /// Auto-commit mode (commit after each statement).
     pub auto_commit: bool,
+    /// Number of deserialized rows kept in memory (0 disables the cache).
+    pub cache_capacity: usize,
+    /// Commits to main between automatic repacks (0 for never).
+    pub auto_pack_every: usize,
+    /// Open the repository read-only; every write fails.
+    pub read_only: bool,
+    /// Isolation level for transactions that don't name one.
+    pub default_isolation: IsolationLevel,
+    /// Delete the branches of transactions left open by a process that
+    /// exited without finishing them, when the database is opened.
+    pub cleanup_on_open: bool,
+    /// Create tables on first INSERT, without a schema.
+    pub schemaless: bool,
+    /// Most rows a SELECT without LIMIT may return (`None` for no cap).
+    pub max_result_rows: Option<usize>,
+    /// When and where sorts and aggregates spill rows to disk.
+    pub spill: SpillConfig,
+    /// Buffer writes outside transactions and commit them in batches
+    /// (`None` to commit each statement).
+    pub commit_batch: Option<BatchConfig>,
+    /// Revision to read the database at instead of main's head.
+    pub at_commit: Option<String>,
 }
 
 impl Default for DatabaseConfig {
@@ -76,6 +181,16 @@ impl Default for DatabaseConfig {
             enable_planner: true,
             verbose: false,
             auto_commit: true,
+            cache_capacity: 10_000,
+            auto_pack_every: 0,
+            read_only: false,
+            default_isolation: IsolationLevel::default(),
+            cleanup_on_open: false,
+            schemaless: false,
+            max_result_rows: None,
+            spill: SpillConfig::default(),
+            commit_batch: None,
+            at_commit: None,
         }
     }
 }
@@ -106,6 +221,99 @@ impl DatabaseConfig {
         self.auto_commit = value;
         self
     }
+
+    /// Set read_only flag.
+    ///
+    /// A read-only database must already exist; `create_if_missing` is ignored.
+    pub fn read_only(mut self, value: bool) -> Self {
+        self.read_only = value;
+        self
+    }
+
+    /// Repack the object store after every `commits` commits to main, see
+    /// [`GitRepository::with_auto_pack`]. Zero, the default, leaves packing
+    /// to git's own gc.
+    pub fn auto_pack_every(mut self, commits: usize) -> Self {
+        self.auto_pack_every = commits;
+        self
+    }
+
+    /// Set the row cache capacity.
+    pub fn cache_capacity(mut self, value: usize) -> Self {
+        self.cache_capacity = value;
+        self
+    }
+
+    /// Set the isolation level for `BEGIN` without `ISOLATION LEVEL`, and
+    /// for [`Database::begin`].
+    pub fn default_isolation(mut self, level: IsolationLevel) -> Self {
+        self.default_isolation = level;
+        self
+    }
+
+    /// Set cleanup_on_open flag.
+    ///
+    /// A freshly opened database has no transactions of its own, so every
+    /// `tx/*` branch is deleted. Leave this off while another process may
+    /// have transactions open on the same directory. Ignored when read-only.
+    pub fn cleanup_on_open(mut self, value: bool) -> Self {
+        self.cleanup_on_open = value;
+        self
+    }
+
+    /// Set schemaless flag.
+    ///
+    /// INSERT into a table that doesn't exist then creates it, with just an
+    /// `id` primary key, and takes rows with any columns; see
+    /// [`TableSchema::schemaless`]. Tables made with CREATE TABLE still
+    /// check their schema.
+    pub fn schemaless(mut self, value: bool) -> Self {
+        self.schemaless = value;
+        self
+    }
+
+    /// Set the most rows a SELECT without LIMIT may return.
+    ///
+    /// A query that would return more fails with `result_too_large` as soon
+    /// as it passes the cap, rather than holding every row in memory. Give
+    /// the query a LIMIT to opt out.
+    pub fn max_result_rows(mut self, value: Option<usize>) -> Self {
+        self.max_result_rows = value;
+        self
+    }
+
+    /// Set when and where sorts and aggregates spill rows to disk.
+    ///
+    /// A sort holds up to `max_rows` rows, or `max_bytes` of them, in
+    /// memory; past that it writes sorted runs to temporary files and
+    /// merges them, so ORDER BY on any size of table runs in bounded memory.
+    pub fn spill(mut self, value: SpillConfig) -> Self {
+        self.spill = value;
+        self
+    }
+
+    /// Commit INSERT, UPDATE and DELETE outside a transaction in batches,
+    /// see [`QueryExecutor::set_commit_batch`].
+    ///
+    /// Fewer, larger commits make bulk loads faster and history shorter.
+    /// The cost is durability: writes are only on disk once their batch is
+    /// flushed, so a crash loses up to one batch, and until then only this
+    /// handle sees them.
+    pub fn commit_batch(mut self, value: Option<BatchConfig>) -> Self {
+        self.commit_batch = value;
+        self
+    }
+
+    /// Read the database as it was at `commit`, and forbid writes.
+    ///
+    /// `commit` is any git revision: a full or short commit id, a tag such
+    /// as `migration/3`, or `main~10`. Every query, schema included, sees
+    /// that commit; writes fail with `read_only`. Implies `read_only`.
+    pub fn at_commit(mut self, commit: impl Into<String>) -> Self {
+        self.at_commit = Some(commit.into());
+        self.read_only = true;
+        self
+    }
 }
 
 /// The main database handle.
@@ -126,18 +334,38 @@ impl Database {
 
     /// Open or create a database with custom configuration.
     pub fn open_with_config(config: DatabaseConfig) -> DatabaseResult<Self> {
-        let repo = if config.create_if_missing {
+        let repo = if config.read_only {
+            if !config.path.exists() {
+                return Err(DatabaseError::NotFound(config.path.clone()));
+            }
+            GitRepository::open_read_only(&config.path)?
+        } else if config.create_if_missing {
             GitRepository::open_or_init(&config.path)?
         } else if config.path.exists() {
             GitRepository::open(&config.path)?
         } else {
             return Err(DatabaseError::NotFound(config.path.clone()));
         };
+        let repo = repo.with_cache(config.cache_capacity).with_auto_pack(config.auto_pack_every);
+        let repo = match &config.at_commit {
+            Some(commit) => repo.pinned_at(repo.resolve_commit(commit)?),
+            None => repo,
+        };
 
+        // SQL transactions and `begin` share a manager, so the manager
+        // knows about every transaction this handle has open
         let shared_repo = Arc::new(RwLock::new(repo.clone()));
-        let executor = QueryExecutor::new(repo.clone());
-        let catalog = Catalog::new(shared_repo.clone());
         let tx_manager = TransactionManager::new(repo);
+        if config.cleanup_on_open && !config.read_only {
+            tx_manager.cleanup_abandoned()?;
+        }
+        let mut executor = QueryExecutor::with_shared(shared_repo.clone(), tx_manager.clone());
+        executor.set_isolation(config.default_isolation);
+        executor.set_schemaless(config.schemaless);
+        executor.set_max_result_rows(config.max_result_rows);
+        executor.set_spill(config.spill.clone());
+        executor.set_commit_batch(config.commit_batch);
+        let catalog = Catalog::new(shared_repo.clone());
 
         let planner = if config.enable_planner {
             Some(QueryPlanner::new(shared_repo.clone()))
@@ -155,6 +383,14 @@ impl Database {
         })
     }
 
+    /// Open an existing database read-only, as it was at `commit`.
+    ///
+    /// Shorthand for [`DatabaseConfig::at_commit`]; unlike a per-query
+    /// time travel, the whole session is pinned to that commit.
+    pub fn open_at(path: impl AsRef<Path>, commit: &str) -> DatabaseResult<Self> {
+        Self::open_with_config(DatabaseConfig::new(path.as_ref()).at_commit(commit))
+    }
+
     /// Create a new in-memory database (for testing).
     pub fn in_memory() -> DatabaseResult<Self> {
         let dir = tempfile::TempDir::new()?;
@@ -177,6 +413,127 @@ impl Database {
         Ok(result)
     }
 
+    /// Execute a SQL query string, giving up with
+    /// [`ExecuteError::Cancelled`] if it runs longer than `timeout`.
+    ///
+    /// Scans, joins and the rows of a write check the deadline as they go;
+    /// a write that runs out of time commits nothing.
+    pub fn execute_with_deadline(&mut self, sql: &str, timeout: Duration) -> DatabaseResult<QueryResult> {
+        if self.config.verbose {
+            eprintln!("[SQL] {}", sql);
+        }
+
+        let result = self.executor.execute_with_deadline(sql, timeout)?;
+
+        if self.config.verbose {
+            eprintln!("[Result] {:?}", result);
+        }
+
+        Ok(result)
+    }
+
+    /// A token that cancels the statement this database is running, say
+    /// from another thread or a Ctrl-C handler.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.executor.cancel_token()
+    }
+
+    /// Execute a SQL statement with positional parameters.
+    ///
+    /// `?` placeholders are numbered left to right; `$n` refers to the n-th
+    /// parameter directly. Placeholders are accepted for LIMIT and OFFSET.
+    pub fn execute_with_params(&mut self, sql: &str, params: &[Value]) -> DatabaseResult<QueryResult> {
+        if self.config.verbose {
+            eprintln!("[SQL] {} {:?}", sql, params);
+        }
+
+        let result = self.executor.execute_with_params(sql, params)?;
+
+        if self.config.verbose {
+            eprintln!("[Result] {:?}", result);
+        }
+
+        Ok(result)
+    }
+
+    /// Read a page of `table` in primary key order, after the key `after`.
+    ///
+    /// See [`QueryExecutor::page_after`]; start with `None` and pass each
+    /// page's `next` back in until it is `None`.
+    pub fn page_after(&mut self, table: &str, after: Option<&[Value]>, limit: usize) -> DatabaseResult<Page> {
+        Ok(self.executor.page_after(table, after, limit)?)
+    }
+
+    /// Read the document stored under `key` in `table`, without SQL.
+    ///
+    /// Returns `None` if there is no such row, or no such table.
+    pub fn get_doc(&self, table: &str, key: &str) -> DatabaseResult<Option<Value>> {
+        Ok(self.executor.get_doc(table, key)?)
+    }
+
+    /// Store a JSON object under `key` in `table`, replacing any document
+    /// already there. Each put is one commit, so documents are versioned.
+    ///
+    /// Tables created with CREATE TABLE check the document against their
+    /// schema; see [`QueryExecutor::put_doc`]. Other tables are created on
+    /// the first put, schemaless, and take any object; the table is created
+    /// in the same transaction as the document, so a rejected first put
+    /// leaves no table behind.
+    pub fn put_doc(&mut self, table: &str, key: &str, doc: Value) -> DatabaseResult<()> {
+        if self.executor.in_transaction() || self.catalog.table_exists(table) {
+            return Ok(self.executor.put_doc(table, key, doc)?);
+        }
+        self.transaction(|db| Ok(db.executor.put_doc(table, key, doc)?))
+    }
+
+    /// Delete the document stored under `key` in `table`, returning whether
+    /// there was one.
+    pub fn delete_doc(&mut self, table: &str, key: &str) -> DatabaseResult<bool> {
+        Ok(self.executor.delete_doc(table, key)?)
+    }
+
+    /// Load newline-delimited JSON objects into `table`, returning how many
+    /// rows were inserted.
+    ///
+    /// Each object becomes a row as with INSERT, keyed by its primary key
+    /// fields or a generated key. A table that doesn't exist is created
+    /// first: schemaless if [`DatabaseConfig::schemaless`] is set, otherwise
+    /// with a schema inferred from the first 100 records. The rows are
+    /// written in one transaction, along with the new table. The whole input
+    /// is parsed before anything is written, and any failure is reported as
+    /// [`DatabaseError::Import`] with its line number and leaves the database
+    /// as it was.
+    pub fn import_ndjson(&mut self, table: &str, reader: impl BufRead) -> DatabaseResult<usize> {
+        self.check_no_transaction("import")?;
+        let records = import::read_records(reader)?;
+
+        let schema = if self.catalog.table_exists(table) {
+            None
+        } else if self.config.schemaless {
+            Some(TableSchema::schemaless(table))
+        } else {
+            Some(import::infer_schema(table, &records)?)
+        };
+
+        let count = records.len();
+        self.transaction(|db| {
+            if let Some(schema) = schema {
+                db.executor.catalog().create_table(schema)?;
+            }
+            for (line, record) in records {
+                db.executor
+                    .insert_doc(table, Value::Object(record))
+                    .map_err(|e| import::at_line(line, e.into()))?;
+            }
+            Ok(count)
+        })
+    }
+
+    /// Add `note` to the commits of the next statement that changes data.
+    pub fn annotate(&mut self, note: impl Into<String>) {
+        self.executor.annotate(note);
+    }
+
     /// Execute multiple SQL statements separated by semicolons.
     pub fn execute_batch(&mut self, sql: &str) -> DatabaseResult<Vec<QueryResult>> {
         let mut results = Vec::new();
@@ -192,6 +549,41 @@ impl Database {
         Ok(results)
     }
 
+    /// Execute a script of semicolon-separated statements in order.
+    ///
+    /// Comments and empty statements are ignored. Execution stops at the
+    /// first failing statement, which is reported as [`DatabaseError::Script`].
+    pub fn execute_script(&mut self, sql: &str) -> DatabaseResult<Vec<QueryResult>> {
+        let script = Parser::parse_script(sql)?;
+        let mut results = Vec::with_capacity(script.len());
+
+        for (i, (text, stmt)) in script.into_iter().enumerate() {
+            if self.config.verbose {
+                eprintln!("[SQL] {}", text);
+            }
+            match self.executor.execute_statement(stmt) {
+                Ok(result) => results.push(result),
+                Err(e) => {
+                    return Err(DatabaseError::Script {
+                        index: i + 1,
+                        statement: text,
+                        source: Box::new(e.into()),
+                    })
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Execute a script inside a single transaction.
+    ///
+    /// The transaction is committed if every statement succeeds and rolled
+    /// back at the first failure.
+    pub fn execute_script_in_transaction(&mut self, sql: &str) -> DatabaseResult<Vec<QueryResult>> {
+        self.transaction(|db| db.execute_script(sql))
+    }
+
     /// Parse a SQL statement without executing.
     pub fn parse(&self, sql: &str) -> DatabaseResult<Statement> {
         Ok(Parser::parse(sql)?)
@@ -210,23 +602,88 @@ impl Database {
 
     /// Get database statistics.
     pub fn stats(&self) -> DatabaseStats {
+        self.collect_stats(false)
+    }
+
+    /// Get database statistics, including the commit count.
+    ///
+    /// Counting commits walks the whole history, so this is slower than
+    /// [`stats`](Self::stats) on long-lived databases.
+    pub fn stats_with_commits(&self) -> DatabaseStats {
+        self.collect_stats(true)
+    }
+
+    fn collect_stats(&self, with_commits: bool) -> DatabaseStats {
         let repo = self.repo.read();
         let tables = self.catalog.list_tables().unwrap_or_default();
-        
-        let total_rows = if let Ok(head) = repo.head() {
-            repo.stats(head).map(|s| s.total_rows).unwrap_or(0)
-        } else {
-            0
-        };
-        
+
+        let repo_stats = repo.head().ok().and_then(|head| {
+            let stats = if with_commits {
+                repo.stats_with_commits(head)
+            } else {
+                repo.stats(head)
+            };
+            stats.ok()
+        });
+
         DatabaseStats {
             tables: tables.len(),
-            total_rows,
-            total_size_bytes: 0, // Not tracked currently.
+            total_rows: repo_stats.as_ref().map_or(0, |s| s.total_rows),
+            total_size_bytes: repo_stats.as_ref().map_or(0, |s| s.disk_size_bytes as usize),
             active_transactions: self.tx_manager.active_count(),
+            loose_objects: repo_stats.as_ref().map_or(0, |s| s.loose_objects),
+            packed_objects: repo_stats.as_ref().map_or(0, |s| s.packed_objects),
+            pack_size_bytes: repo_stats.as_ref().map_or(0, |s| s.pack_size_bytes as usize),
+            total_commits: repo_stats.and_then(|s| s.total_commits),
         }
     }
 
+    /// A snapshot of this handle's counters: statements by kind, commits,
+    /// transaction conflicts, read cache hits and time spent executing.
+    ///
+    /// Counts start at zero when the database is opened, see
+    /// [`reset_metrics`](Self::reset_metrics).
+    pub fn metrics(&self) -> Metrics {
+        self.executor.metrics()
+    }
+
+    /// Set every counter [`metrics`](Self::metrics) reports back to zero.
+    pub fn reset_metrics(&self) {
+        self.executor.reset_metrics();
+    }
+
+    /// Pack the object store and delete the loose objects it replaces.
+    ///
+    /// Worth running after heavy write churn. Must not run while another
+    /// process has a transaction mid-commit on the same repository.
+    pub fn gc(&self) -> DatabaseResult<GcReport> {
+        Ok(self.repo.read().gc()?)
+    }
+
+    /// Run `VACUUM`, or `VACUUM FULL` with `full`, see [`QueryExecutor::vacuum`].
+    ///
+    /// `full` is the confirmation that history may be discarded: main is
+    /// left with a single commit and every older one is deleted.
+    pub fn vacuum(&mut self, full: bool) -> DatabaseResult<VacuumReport> {
+        Ok(self.executor.vacuum(full)?)
+    }
+
+    /// Commit the writes buffered under [`DatabaseConfig::commit_batch`]
+    /// now, see [`QueryExecutor::flush`].
+    pub fn flush(&mut self) -> DatabaseResult<Option<CommitId>> {
+        Ok(self.executor.flush()?)
+    }
+
+    /// Whether a `BEGIN` is waiting on its `COMMIT` or `ROLLBACK`.
+    pub fn in_transaction(&self) -> bool {
+        self.executor.in_transaction()
+    }
+
+    /// The transaction started by `BEGIN`, if one is open.
+    pub fn current_transaction(&self) -> Option<&Transaction<TxActive>> {
+        self.executor.current_transaction()
+    }
+
     /// List all tables.
     pub fn tables(&self) -> DatabaseResult<Vec<String>> {
         Ok(self.catalog.list_tables()?)
@@ -246,9 +703,45 @@ impl Database {
         }
     }
 
+    /// Rename tables and columns with upper-case letters in their names to
+    /// the lower-case form, returning the renames made as `(old, new)`
+    /// pairs, with columns written `table.column`.
+    ///
+    /// Databases created before unquoted identifiers were folded to lower
+    /// case can hold names like `Users` that only quoted SQL reaches now.
+    /// This makes them reachable unquoted again. A name whose lower-case form
+    /// is already taken is left as it is. Each rename is its own commit.
+    pub fn fold_identifiers(&mut self) -> DatabaseResult<Vec<(String, String)>> {
+        self.check_no_transaction("fold identifiers")?;
+        self.executor.flush()?;
+
+        let mut renamed = Vec::new();
+        let tables = self.catalog.list_tables()?;
+        for name in &tables {
+            let folded = name.to_lowercase();
+            let table = if folded != *name && !tables.contains(&folded) {
+                self.catalog.rename_table(name, &folded)?;
+                renamed.push((name.clone(), folded.clone()));
+                folded
+            } else {
+                name.clone()
+            };
+
+            let columns: Vec<String> = self.catalog.get_table(&table)?.columns.into_iter().map(|c| c.name).collect();
+            for column in &columns {
+                let folded = column.to_lowercase();
+                if folded != *column && !columns.contains(&folded) {
+                    self.catalog.rename_column(&table, column, &folded)?;
+                    renamed.push((format!("{}.{}", table, column), format!("{}.{}", table, folded)));
+                }
+            }
+        }
+        Ok(renamed)
+    }
+
     /// Begin a new transaction.
     pub fn begin(&mut self) -> DatabaseResult<Transaction<TxActive>> {
-        Ok(self.tx_manager.begin()?)
+        Ok(self.tx_manager.begin_with_isolation(self.config.default_isolation)?)
     }
 
     /// Execute within a transaction.
@@ -269,6 +762,102 @@ impl Database {
         }
     }
 
+    /// Apply every registered migration that hasn't been applied yet, in
+    /// version order, returning the versions applied.
+    ///
+    /// Each migration runs in its own transaction: if one of its statements
+    /// fails, the transaction is rolled back and the error is returned as
+    /// [`DatabaseError::Migration`]. Migrations applied before it stay
+    /// applied, and running `migrate` again resumes from the failed one.
+    pub fn migrate(&mut self, migrations: &Migrations) -> DatabaseResult<Vec<SchemaVersion>> {
+        self.check_no_transaction("migrate")?;
+        let applied: Vec<SchemaVersion> = self.applied_migrations()?
+            .into_iter()
+            .map(|m| m.version)
+            .collect();
+
+        let mut versions = Vec::new();
+        for migration in migrations.ordered()? {
+            if applied.contains(&migration.version) {
+                continue;
+            }
+            let finished = self.run_migration_step(migration.version, |db| {
+                for script in &migration.up {
+                    db.execute_script(script)?;
+                }
+                Ok(migrate::record(&db.executor.session_repo().read(), migration)?)
+            })?;
+            self.repo.read().set_tag(&migrate::migration_tag(migration.version), finished)?;
+            versions.push(migration.version);
+        }
+        Ok(versions)
+    }
+
+    /// Revert applied migrations newer than `target`, newest first, using
+    /// their `down` scripts. Returns the versions reverted.
+    ///
+    /// Each revert runs in its own transaction, like in [`migrate`](Self::migrate).
+    pub fn migrate_down(
+        &mut self,
+        migrations: &Migrations,
+        target: SchemaVersion,
+    ) -> DatabaseResult<Vec<SchemaVersion>> {
+        self.check_no_transaction("migrate")?;
+        let mut versions = Vec::new();
+        for applied in self.applied_migrations()?.into_iter().rev() {
+            if applied.version <= target {
+                break;
+            }
+            let migration = migrations.get(applied.version).ok_or_else(|| {
+                DatabaseError::InvalidConfig(format!(
+                    "migration {} is applied but not registered",
+                    applied.version
+                ))
+            })?;
+            self.run_migration_step(migration.version, |db| {
+                for script in &migration.down {
+                    db.execute_script(script)?;
+                }
+                Ok(migrate::unrecord(&db.executor.session_repo().read(), migration.version)?)
+            })?;
+            self.repo.read().delete_tag(&migrate::migration_tag(migration.version))?;
+            versions.push(migration.version);
+        }
+        Ok(versions)
+    }
+
+    /// List the applied migrations in version order.
+    pub fn applied_migrations(&self) -> DatabaseResult<Vec<AppliedMigration>> {
+        Ok(migrate::applied(&self.repo.read())?)
+    }
+
+    /// Run one migration step in its own transaction, so a failure leaves
+    /// nothing of it behind and main is never moved back.
+    ///
+    /// Writes buffered under [`DatabaseConfig::commit_batch`] are flushed
+    /// first; the step itself is never batched.
+    fn run_migration_step<F, T>(&mut self, version: SchemaVersion, step: F) -> DatabaseResult<T>
+    where
+        F: FnOnce(&mut Self) -> DatabaseResult<T>,
+    {
+        self.executor.flush()?;
+        self.transaction(step).map_err(|e| DatabaseError::Migration {
+            version,
+            source: Box::new(e),
+        })
+    }
+
+    fn check_no_transaction(&self, operation: &str) -> DatabaseResult<()> {
+        if self.executor.in_transaction() {
+            return Err(TransactionError::InvalidOperation(format!(
+                "cannot {} inside a transaction",
+                operation
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
     /// Get the database path.
     pub fn path(&self) -> &Path {
         &self.config.path
@@ -286,17 +875,117 @@ impl Database {
         let commits_result = repo.history(head, limit);
         
         match commits_result {
-            Ok(commits) => {
-                Ok(commits.into_iter().map(|c| CommitInfo {
-                    id: c.id.to_string(),
-                    message: c.message,
-                    timestamp: c.timestamp.timestamp(),
-                }).collect())
-            }
+            Ok(commits) => Ok(commits.into_iter().map(CommitInfo::from).collect()),
             Err(e) => Err(e.into()),
         }
     }
 
+    /// The commit main is at.
+    pub fn head(&self) -> DatabaseResult<CommitId> {
+        Ok(self.repo.read().head()?)
+    }
+
+    /// Follow the commits made to main after `since`, oldest first.
+    ///
+    /// Start from [`head`](Self::head) to see only changes from now on. The
+    /// watch has its own handle on the repository, so it can be moved to
+    /// another thread while this one keeps writing.
+    pub fn watch(&self, since: CommitId) -> Watch {
+        Watch::new(self.repo.clone(), since)
+    }
+
+    /// The rows a commit changed, relative to its first parent.
+    ///
+    /// [`Change::row`] gives the table and key of each.
+    pub fn changes(&self, commit: &CommitInfo) -> DatabaseResult<Vec<Change>> {
+        let Some(parent) = &commit.parent else {
+            return Ok(Vec::new());
+        };
+        let parse = |id: &str| {
+            CommitId::from_hex(id).map_err(|e| DatabaseError::Storage(StorageError::Git(e)))
+        };
+        Ok(self.repo.read().diff(parse(parent)?, parse(&commit.id)?)?)
+    }
+
+    /// The last commit on main that changed a row: who changed it, when,
+    /// and with what message.
+    ///
+    /// `key` is the row's key, the primary key value for a single-column
+    /// key. A row never modified since it was inserted blames the commit
+    /// that inserted it. Fails with [`StorageError::RowNotFound`] if there
+    /// is no such row.
+    pub fn blame(&self, table: &str, key: &str) -> DatabaseResult<CommitInfo> {
+        let table = TableName::new(table).map_err(ExecuteError::from)?;
+        let key = RowKey::new(key).map_err(ExecuteError::from)?;
+        let repo = self.repo.read();
+        match repo.blame(&table, &key, repo.head()?)? {
+            Some(commit) => Ok(commit.into()),
+            None => Err(StorageError::RowNotFound { table, key }.into()),
+        }
+    }
+
+    /// Write the whole database as a SQL script.
+    ///
+    /// Each table's `CREATE TABLE` is followed by one `INSERT` per row, all
+    /// wrapped in a single transaction. Executing the script against an empty
+    /// database reproduces the current data. Referenced tables and rows are
+    /// written before the ones referencing them, so foreign keys hold at every
+    /// step of the restore.
+    pub fn dump<W: Write>(&self, writer: &mut W) -> DatabaseResult<()> {
+        let mut tables = self.catalog.list_tables()?;
+        tables.sort();
+        let schemas = tables.iter()
+            .map(|table| self.catalog.get_table(table))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let repo = self.repo.read();
+        let head = repo.head()?;
+
+        writeln!(writer, "-- GitDB dump at commit {}", head)?;
+        writeln!(writer, "BEGIN;")?;
+
+        for schema in dependency_order(schemas) {
+            // Single-column keys are written on their column
+            let composite_key = (schema.primary_key.len() > 1)
+                .then(|| format!("PRIMARY KEY ({})", quote_idents(&schema.primary_key)));
+
+            writeln!(writer)?;
+            writeln!(writer, "CREATE TABLE {} (", quote_ident(&schema.name))?;
+            let definitions: Vec<String> = schema.columns.iter()
+                .map(|c| c.to_string())
+                .chain(composite_key)
+                .chain(schema.checks.iter().map(|c| c.to_string()))
+                .chain(schema.unique.iter().map(|u| u.to_string()))
+                .chain(schema.foreign_keys.iter().map(|fk| fk.to_string()))
+                .collect();
+            writeln!(writer, "  {}", definitions.join(",\n  "))?;
+            writeln!(writer, ");")?;
+
+            let table_name = TableName::new(&schema.name).map_err(ExecuteError::from)?;
+            let rows = repo.scan_table(&table_name, head)?;
+            for row in parents_first(rows, &schema) {
+                if row.data.is_empty() {
+                    continue;
+                }
+                let columns: Vec<String> = row.data.keys().cloned().collect();
+                let values: Vec<String> = row.data.values()
+                    .map(|v| LiteralValue::from_json(v).to_string())
+                    .collect();
+                writeln!(
+                    writer,
+                    "INSERT INTO {} ({}) VALUES ({});",
+                    quote_ident(&schema.name),
+                    quote_idents(&columns),
+                    values.join(", ")
+                )?;
+            }
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "COMMIT;")?;
+        Ok(())
+    }
+
     /// Create a backup/snapshot at current state.
     pub fn snapshot(&self, _message: &str) -> DatabaseResult<String> {
         let repo = self.repo.read();
@@ -305,6 +994,77 @@ impl Database {
     }
 }
 
+/// Order schemas so every table comes after the tables its foreign keys
+/// reference. Tables in a reference cycle keep their original order.
+fn dependency_order(mut pending: Vec<TableSchema>) -> Vec<TableSchema> {
+    let mut ordered: Vec<TableSchema> = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|schema| {
+            schema.foreign_keys.iter().all(|fk| {
+                fk.references_table == schema.name
+                    || !pending.iter().any(|p| p.name == fk.references_table)
+            })
+        });
+        ordered.push(pending.remove(ready.unwrap_or(0)));
+    }
+    ordered
+}
+
+/// Order a table's rows so rows referenced through a self-referencing
+/// foreign key come before the rows referencing them.
+fn parents_first(rows: Vec<StorageRow>, schema: &TableSchema) -> Vec<StorageRow> {
+    let self_keys: Vec<_> = schema.foreign_keys.iter()
+        .filter(|fk| fk.references_table == schema.name)
+        .collect();
+    if self_keys.is_empty() {
+        return rows;
+    }
+
+    // A row waits while another pending row is the one it references
+    let references = |child: &StorageRow, parent: &StorageRow| {
+        self_keys.iter().any(|fk| {
+            fk.columns.iter().zip(&fk.references_columns).all(|(column, parent_column)| {
+                match (child.data.get(column), parent.data.get(parent_column)) {
+                    (Some(a), Some(b)) => !a.is_null() && values_equal(a, b),
+                    _ => false,
+                }
+            })
+        })
+    };
+
+    let mut pending = rows;
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let (ready, waiting): (Vec<_>, Vec<_>) = pending.iter().cloned().partition(|row| {
+            !pending.iter().any(|parent| parent.key != row.key && references(row, parent))
+        });
+        if ready.is_empty() {
+            // A cycle of rows; no order satisfies it
+            ordered.extend(waiting);
+            break;
+        }
+        ordered.extend(ready);
+        pending = waiting;
+    }
+    ordered
+}
+
+impl Drop for Database {
+    /// Flush buffered writes, roll back the open SQL transaction, if any,
+    /// and delete the branches of transactions nothing is running any more.
+    ///
+    /// Transactions from [`begin`](Database::begin) that are still held
+    /// keep their branches; those of another handle on the same directory
+    /// don't, as this one can't tell them from abandoned ones.
+    fn drop(&mut self) {
+        let _ = self.executor.flush();
+        self.executor.rollback_open();
+        if !self.config.read_only {
+            let _ = self.tx_manager.cleanup_abandoned();
+        }
+    }
+}
+
 /// Database statistics.
 #[derive(Debug, Clone)]
 pub struct DatabaseStats {
@@ -312,10 +1072,18 @@ pub struct DatabaseStats {
     pub tables: usize,
     /// Total number of rows across all tables.
     pub total_rows: usize,
-    /// Total size in bytes.
+    /// Total size of the git object store in bytes.
     pub total_size_bytes: usize,
     /// Number of active transactions.
     pub active_transactions: usize,
+    /// Objects stored as individual files; a large number means gc is due.
+    pub loose_objects: usize,
+    /// Objects stored in pack files.
+    pub packed_objects: usize,
+    /// Size of all pack files in bytes.
+    pub pack_size_bytes: usize,
+    /// Number of commits, if requested via `stats_with_commits`.
+    pub total_commits: Option<usize>,
 }
 
 /// Information about a commit in history.
@@ -325,8 +1093,24 @@ pub struct CommitInfo {
     pub id: String,
     /// Commit message.
     pub message: String,
+    /// Author, as `Name <email>`.
+    pub author: String,
     /// Unix timestamp.
     pub timestamp: i64,
+    /// ID of the first parent, the commit this one's changes are made to.
+    pub parent: Option<String>,
+}
+
+impl From<crate::storage::CommitInfo> for CommitInfo {
+    fn from(c: crate::storage::CommitInfo) -> Self {
+        Self {
+            id: c.id.to_string(),
+            message: c.message,
+            author: format!("{} <{}>", c.author_name, c.author_email),
+            timestamp: c.timestamp.timestamp(),
+            parent: c.parent_ids.first().map(|p| p.to_string()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -366,6 +1150,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_fold_identifiers() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        // Names as an older version stored them, unfolded
+        db.execute(r#"CREATE TABLE "Users" ("Id" INTEGER PRIMARY KEY, "Name" TEXT CHECK ("Name" <> ''))"#).unwrap();
+        db.execute(r#"INSERT INTO "Users" ("Id", "Name") VALUES (1, 'Alice')"#).unwrap();
+        db.execute(r#"CREATE TABLE "Items" ("Id" INTEGER PRIMARY KEY, id TEXT)"#).unwrap();
+        db.execute("CREATE TABLE items (id INTEGER PRIMARY KEY)").unwrap();
+        assert!(db.execute("SELECT * FROM Users").is_err());
+
+        let renamed = db.fold_identifiers().unwrap();
+        assert!(renamed.contains(&("Users".to_string(), "users".to_string())));
+        assert!(renamed.contains(&("users.Name".to_string(), "users.name".to_string())));
+        let QueryResult::Select(rs) = db.execute("SELECT name FROM Users WHERE ID = 1").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.rows[0].get("name"), Some(&Value::from("Alice")));
+        assert!(db.execute("UPDATE users SET name = '' WHERE id = 1").is_err());
+
+        // Taken lower-case names are left alone
+        assert!(db.table_exists("Items") && db.table_exists("items"));
+        let columns: Vec<String> = db.table_schema("Items").unwrap().unwrap().columns.into_iter().map(|c| c.name).collect();
+        assert_eq!(columns, vec!["Id", "id"]);
+        assert!(db.fold_identifiers().unwrap().is_empty());
+    }
+
     #[test]
     fn test_execute_batch() {
         let dir = tempfile::TempDir::new().unwrap();
@@ -393,6 +1205,177 @@ mod tests {
         assert_eq!(stats.tables, 1);
     }
 
+    #[test]
+    fn test_auto_pack_every() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = DatabaseConfig::new(dir.path()).auto_pack_every(2);
+        let mut db = Database::open_with_config(config).unwrap();
+
+        // The initial commit and CREATE TABLE fill the first window
+        db.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").unwrap();
+        assert_eq!(db.stats().loose_objects, 0);
+        db.execute("INSERT INTO users (id) VALUES ('1')").unwrap();
+        assert!(db.stats().loose_objects > 0);
+        db.execute("INSERT INTO users (id) VALUES ('2')").unwrap();
+        assert_eq!(db.stats().loose_objects, 0);
+    }
+
+    #[test]
+    fn test_commit_batch_durability() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = DatabaseConfig::new(dir.path()).commit_batch(Some(BatchConfig::default()));
+        let ids = |db: &mut Database| match db.execute("SELECT id FROM users").unwrap() {
+            QueryResult::Select(rs) => rs.rows.iter().map(|r| r["id"].as_str().unwrap().to_string()).collect::<Vec<_>>(),
+            other => panic!("Expected Select result, got {:?}", other),
+        };
+
+        let mut db = Database::open_with_config(config.clone()).unwrap();
+        db.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").unwrap();
+        db.execute("INSERT INTO users (id) VALUES ('1')").unwrap();
+        db.flush().unwrap();
+        db.execute("INSERT INTO users (id) VALUES ('2')").unwrap();
+        assert_eq!(ids(&mut db), vec!["1", "2"]);
+        // A crash before the next flush loses only the write since the last
+        std::mem::forget(db);
+
+        let mut db = Database::open_with_config(config.clone()).unwrap();
+        assert_eq!(ids(&mut db), vec!["1"]);
+        // Closing the database flushes
+        db.execute("INSERT INTO users (id) VALUES ('3')").unwrap();
+        drop(db);
+        let mut db = Database::open_with_config(config).unwrap();
+        assert_eq!(ids(&mut db), vec!["1", "3"]);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let dir = tempfile::TempDir::new().unwrap();
+        {
+            let mut db = Database::open(dir.path()).unwrap();
+            db.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").unwrap();
+            db.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
+        }
+
+        let config = DatabaseConfig::new(dir.path()).read_only(true);
+        let mut db = Database::open_with_config(config).unwrap();
+        let head = db.history(Some(1)).unwrap()[0].id.clone();
+
+        let result = db.execute("SELECT * FROM users").unwrap();
+        assert!(matches!(result, QueryResult::Select(rs) if rs.len() == 1));
+
+        for sql in [
+            "INSERT INTO users (id, name) VALUES ('2', 'Bob')",
+            "UPDATE users SET name = 'Alicia'",
+            "DELETE FROM users",
+            "CREATE TABLE other (id TEXT)",
+            "BEGIN",
+        ] {
+            let err = db.execute(sql).unwrap_err();
+            assert!(err.to_string().contains("read-only"), "{}: {}", sql, err);
+        }
+        assert!(db.gc().is_err());
+        assert_eq!(db.history(Some(1)).unwrap()[0].id, head);
+
+        let missing = dir.path().join("missing");
+        let config = DatabaseConfig::new(&missing).read_only(true);
+        assert!(matches!(Database::open_with_config(config), Err(DatabaseError::NotFound(_))));
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_open_at_commit() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let before;
+        {
+            let mut db = Database::open(dir.path()).unwrap();
+            db.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").unwrap();
+            db.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
+            before = db.head().unwrap().to_string();
+            db.execute("UPDATE users SET name = 'Alicia' WHERE id = '1'").unwrap();
+            db.execute("INSERT INTO users (id, name) VALUES ('2', 'Bob')").unwrap();
+            db.execute("CREATE TABLE orders (id TEXT PRIMARY KEY)").unwrap();
+        }
+
+        let mut db = Database::open_at(dir.path(), &before[..10]).unwrap();
+        assert_eq!(db.head().unwrap().to_string(), before);
+        match db.execute("SELECT name FROM users").unwrap() {
+            QueryResult::Select(rs) => {
+                assert_eq!(rs.len(), 1);
+                assert_eq!(rs.rows[0]["name"], Value::from("Alice"));
+            }
+            other => panic!("expected rows, got {:?}", other),
+        }
+        // The catalog is read at the pinned commit too
+        assert!(!db.table_exists("orders"));
+        assert_eq!(db.tables().unwrap(), vec!["users"]);
+
+        let err = db.execute("INSERT INTO users (id, name) VALUES ('3', 'Cy')").unwrap_err();
+        assert_eq!(err.kind(), "read_only");
+
+        // Any revision works
+        let db = Database::open_at(dir.path(), "main~1").unwrap();
+        assert!(db.table_exists("users") && !db.table_exists("orders"));
+        let err = Database::open_at(dir.path(), "nope").err().unwrap();
+        assert_eq!(err.kind(), "commit_not_found");
+    }
+
+    #[test]
+    fn test_default_isolation() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = DatabaseConfig::new(dir.path()).default_isolation(IsolationLevel::RepeatableRead);
+        let mut db = Database::open_with_config(config).unwrap();
+
+        let tx = db.begin().unwrap();
+        assert_eq!(tx.isolation(), IsolationLevel::RepeatableRead);
+        db.tx_manager.rollback_transaction(tx).unwrap();
+
+        db.execute("BEGIN").unwrap();
+        assert_eq!(db.executor.isolation(), IsolationLevel::RepeatableRead);
+        db.execute("ROLLBACK").unwrap();
+        let err = db.execute("BEGIN ISOLATION LEVEL SERIALIZABLE").unwrap_err();
+        assert_eq!(err.kind(), "unsupported_isolation");
+    }
+
+    #[test]
+    fn test_transaction_branch_cleanup() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let tx_branches = || {
+            let repo = GitRepository::open(dir.path()).unwrap();
+            repo.with_repo(crate::storage::RefManager::list_transaction_branches).unwrap().len()
+        };
+
+        // Dropped mid-transaction: rolled back, branch and all
+        {
+            let mut db = Database::open(dir.path()).unwrap();
+            db.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").unwrap();
+            db.execute("BEGIN").unwrap();
+            db.execute("INSERT INTO users (id) VALUES ('1')").unwrap();
+            assert_eq!(tx_branches(), 1);
+        }
+        assert_eq!(tx_branches(), 0);
+        let mut db = Database::open(dir.path()).unwrap();
+        assert!(matches!(db.execute("SELECT * FROM users").unwrap(), QueryResult::Select(rs) if rs.is_empty()));
+
+        // A transaction held past the drop keeps its branch
+        let tx = db.begin().unwrap();
+        drop(db);
+        assert_eq!(tx_branches(), 1);
+
+        // Left behind as if by a crash: opening only cleans it up on
+        // request, dropping always does
+        std::mem::forget(tx);
+        let db = Database::open(dir.path()).unwrap();
+        assert_eq!(tx_branches(), 1);
+        drop(db);
+        assert_eq!(tx_branches(), 0);
+
+        let repo = GitRepository::open(dir.path()).unwrap();
+        repo.create_transaction_branch("crashed", repo.head().unwrap()).unwrap();
+        let config = DatabaseConfig::new(dir.path()).cleanup_on_open(true);
+        let _db = Database::open_with_config(config).unwrap();
+        assert_eq!(tx_branches(), 0);
+    }
+
     #[test]
     fn test_explain() {
         let dir = tempfile::TempDir::new().unwrap();
@@ -403,4 +1386,376 @@ mod tests {
         let plan = db.explain("SELECT * FROM users WHERE id = '1'").unwrap();
         assert!(plan.contains("Plan"));
     }
+
+    #[test]
+    fn test_dump_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        db.execute_batch(r#"
+            CREATE TABLE people (id TEXT PRIMARY KEY, name TEXT NOT NULL, age INTEGER CHECK (age >= 0), score REAL, active BOOLEAN, meta JSON);
+            INSERT INTO people (id, name, age, score, active, meta) VALUES ('1', 'O''Brien', 42, 2.5, TRUE, '{"tags":["a","b"]}');
+            INSERT INTO people (id, name, age, score, active) VALUES ('2', 'Bob', NULL, 3.0, FALSE)
+        "#).unwrap();
+
+        let mut out = Vec::new();
+        db.dump(&mut out).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.contains("CREATE TABLE people"));
+        assert!(script.contains("'O''Brien'"));
+
+        let dir2 = tempfile::TempDir::new().unwrap();
+        let mut restored = Database::open(dir2.path()).unwrap();
+        restored.execute_batch(&script).unwrap();
+
+        let select = "SELECT * FROM people ORDER BY name";
+        let original = db.execute(select).unwrap();
+        let copy = restored.execute(select).unwrap();
+        match (original, copy) {
+            (QueryResult::Select(a), QueryResult::Select(b)) => {
+                assert_eq!(a.rows, b.rows);
+                assert_eq!(b.rows[0].get("age"), Some(&serde_json::Value::Null));
+                assert_eq!(b.rows[0].get("score"), Some(&serde_json::json!(3.0)));
+            }
+            _ => panic!("Expected Select results"),
+        }
+    }
+
+    #[test]
+    fn test_dump_quotes_names_and_keeps_text() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        db.execute_batch(r#"
+            CREATE TABLE "OrderItems" ("Item Id" TEXT PRIMARY KEY, "Qty" INTEGER CHECK ("Qty" > 0), note TEXT, UNIQUE ("Qty"));
+            INSERT INTO "OrderItems" ("Item Id", "Qty", note) VALUES ('a', 1, '[1, 2]'), ('b', 2, '{"x":1}')
+        "#).unwrap();
+
+        let mut out = Vec::new();
+        db.dump(&mut out).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.contains(r#"CREATE TABLE "OrderItems" ("#));
+
+        let dir2 = tempfile::TempDir::new().unwrap();
+        let mut restored = Database::open(dir2.path()).unwrap();
+        restored.execute_script(&script).unwrap();
+
+        let select = r#"SELECT * FROM "OrderItems""#;
+        match (db.execute(select).unwrap(), restored.execute(select).unwrap()) {
+            (QueryResult::Select(a), QueryResult::Select(b)) => {
+                assert_eq!(a.rows, b.rows);
+                assert_eq!(b.rows[0].get("note"), Some(&serde_json::json!("[1, 2]")));
+                assert_eq!(b.rows[1].get("note"), Some(&serde_json::json!(r#"{"x":1}"#)));
+            }
+            _ => panic!("Expected Select results"),
+        }
+    }
+
+    #[test]
+    fn test_dump_restores_keys_and_references() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        // Alphabetically the child tables come first
+        db.execute_batch(r#"
+            CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE accounts (tenant TEXT, id INTEGER, owner INTEGER REFERENCES users ON DELETE CASCADE, PRIMARY KEY (tenant, id), UNIQUE (owner));
+            CREATE TABLE staff (id INTEGER PRIMARY KEY, boss INTEGER REFERENCES staff (id));
+            INSERT INTO users (id, name) VALUES (1, 'Ann');
+            INSERT INTO accounts (tenant, id, owner) VALUES ('acme', 1, 1);
+            INSERT INTO staff (id, boss) VALUES (3, NULL);
+            INSERT INTO staff (id, boss) VALUES (2, 3);
+            INSERT INTO staff (id, boss) VALUES (1, 2)
+        "#).unwrap();
+
+        let mut out = Vec::new();
+        db.dump(&mut out).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.contains("PRIMARY KEY (tenant, id)"));
+
+        let dir2 = tempfile::TempDir::new().unwrap();
+        let mut restored = Database::open(dir2.path()).unwrap();
+        restored.execute_script(&script).unwrap();
+
+        let accounts = restored.table_schema("accounts").unwrap().unwrap();
+        assert_eq!(accounts.primary_key, vec!["tenant".to_string(), "id".to_string()]);
+        assert_eq!(accounts.unique.len(), 1);
+        assert_eq!(accounts.foreign_keys, db.table_schema("accounts").unwrap().unwrap().foreign_keys);
+        match restored.execute("SELECT * FROM staff").unwrap() {
+            QueryResult::Select(rs) => assert_eq!(rs.len(), 3),
+            other => panic!("Expected Select result, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_blame() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").unwrap();
+        db.execute("INSERT INTO users (id, name) VALUES ('a', 'Alice')").unwrap();
+        db.execute("INSERT INTO users (id, name) VALUES ('b', 'Bob')").unwrap();
+        db.execute("SET AUTHOR = 'Carol <carol@example.com>'").unwrap();
+        db.execute("UPDATE users SET name = 'Alicia' WHERE id = 'a'").unwrap();
+        db.execute("INSERT INTO users (id, name) VALUES ('c', 'Cy')").unwrap();
+
+        let a = db.blame("users", "a").unwrap();
+        assert_eq!(a.author, "Carol <carol@example.com>");
+        assert!(a.message.starts_with("[UPDATE] users/a"));
+
+        let b = db.blame("users", "b").unwrap();
+        assert!(b.message.starts_with("[INSERT] users/b"));
+        assert_ne!(b.author, a.author);
+
+        assert!(matches!(
+            db.blame("users", "nobody"),
+            Err(DatabaseError::Storage(StorageError::RowNotFound { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_execute_script() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        let results = db.execute_script(r#"
+            -- schema
+            CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT);
+            /* seed data; two rows */
+            INSERT INTO users (id, name) VALUES ('1', 'semi;colon');
+            INSERT INTO users (id, name) VALUES ('2', 'Bob');;
+            SELECT * FROM users;
+        "#).unwrap();
+        assert_eq!(results.len(), 4);
+
+        let err = db.execute_script(
+            "INSERT INTO users (id, name) VALUES ('3', 'Carol'); SELECT * FROM missing; DELETE FROM users",
+        ).unwrap_err();
+        match err {
+            DatabaseError::Script { index, statement, .. } => {
+                assert_eq!(index, 2);
+                assert_eq!(statement, "SELECT * FROM missing");
+            }
+            other => panic!("Expected Script error, got {:?}", other),
+        }
+
+        // Statements after the failure never ran
+        if let QueryResult::Select(rs) = db.execute("SELECT * FROM users").unwrap() {
+            assert_eq!(rs.len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_execute_script_special_commands() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        let results = db.execute_script(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY);\n\
+             SHOW TRANSACTIONS;\n\
+             INSERT INTO t (id) VALUES (1);\n\
+             VACUUM;\n\
+             SELECT * FROM t;",
+        ).unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(matches!(&results[1], QueryResult::Select(rs) if rs.is_empty()));
+        assert!(matches!(&results[3], QueryResult::Success { message } if message.starts_with("VACUUM")));
+        assert!(matches!(&results[4], QueryResult::Select(rs) if rs.len() == 1));
+    }
+
+    #[test]
+    fn test_document_api() {
+        use serde_json::json;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        // Schemaless: the table appears on the first put
+        assert_eq!(db.get_doc("notes", "a").unwrap(), None);
+        db.put_doc("notes", "a", json!({"title": "hello", "tags": ["x"]})).unwrap();
+        db.put_doc("notes", "a", json!({"title": "hello again"})).unwrap();
+        assert_eq!(db.get_doc("notes", "a").unwrap(), Some(json!({"id": "a", "title": "hello again"})));
+        assert!(db.put_doc("notes", "b", json!([1, 2])).is_err());
+        // A rejected first put doesn't create the table
+        let head = db.head().unwrap();
+        assert!(db.put_doc("drafts", "a", json!({"id": "b"})).is_err());
+        assert!(!db.table_exists("drafts"));
+        assert_eq!(db.head().unwrap(), head);
+
+        assert!(db.delete_doc("notes", "a").unwrap());
+        assert!(!db.delete_doc("notes", "a").unwrap());
+        assert_eq!(db.get_doc("notes", "a").unwrap(), None);
+
+        // Typed tables keep their schema
+        db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)").unwrap();
+        db.put_doc("users", "1", json!({"id": 1, "name": "Ann"})).unwrap();
+        assert!(db.put_doc("users", "2", json!({"id": 2})).is_err());
+        assert!(db.put_doc("users", "3", json!({"id": 4, "name": "Bo"})).is_err());
+        match db.execute("SELECT name FROM users WHERE id = 1").unwrap() {
+            QueryResult::Select(rs) => assert_eq!(rs.rows[0]["name"], json!("Ann")),
+            other => panic!("expected rows, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        db.execute("INSERT INTO users (id, name) VALUES (1, 'Ann')").unwrap();
+        db.execute("SELECT * FROM users").unwrap();
+        assert!(db.execute("SELECT * FROM missing").is_err());
+
+        // Inside a transaction only the COMMIT counts as a commit
+        db.execute("BEGIN").unwrap();
+        db.execute("INSERT INTO users (id, name) VALUES (2, 'Bo')").unwrap();
+        db.execute("UPDATE users SET name = 'Cy' WHERE id = 2").unwrap();
+        db.execute("COMMIT").unwrap();
+
+        let metrics = db.metrics();
+        assert_eq!(metrics.queries.get("select"), Some(&2));
+        assert_eq!(metrics.queries.get("insert"), Some(&2));
+        assert_eq!(metrics.queries.get("delete"), None);
+        assert_eq!(metrics.total_queries(), 8);
+        assert_eq!(metrics.commits, 3);
+        assert_eq!(metrics.conflicts, 0);
+        assert!(metrics.cache.is_some());
+        assert!(metrics.average_latency().is_some());
+
+        db.reset_metrics();
+        let metrics = db.metrics();
+        assert_eq!(metrics.total_queries(), 0);
+        assert_eq!(metrics.commits, 0);
+        assert_eq!(metrics.cache, Some(crate::storage::CacheStats::default()));
+    }
+
+    #[test]
+    fn test_schemaless_tables() {
+        use serde_json::json;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open_with_config(DatabaseConfig::new(dir.path()).schemaless(true)).unwrap();
+        let rows = |db: &mut Database, sql: &str| match db.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs,
+            other => panic!("expected rows, got {:?}", other),
+        };
+
+        db.execute("INSERT INTO events (id, kind, payload) VALUES (1, 'click', '{\"x\": 3}')").unwrap();
+        db.execute("INSERT INTO events (id, kind, who) VALUES ('e2', 'view', 'ann')").unwrap();
+        db.execute("INSERT INTO events (kind) VALUES ('scroll')").unwrap();
+        assert!(db.table_schema("events").unwrap().unwrap().schemaless);
+
+        let rs = rows(&mut db, "SELECT * FROM events");
+        assert_eq!(rs.columns, vec!["id", "kind", "payload", "who"]);
+        assert_eq!(rs.len(), 3);
+        assert_eq!(rows(&mut db, "SELECT kind FROM events WHERE who = 'ann'").rows[0]["kind"], json!("view"));
+
+        db.execute("UPDATE events SET seen = TRUE WHERE who IS NULL").unwrap();
+        assert_eq!(rows(&mut db, "SELECT id FROM events WHERE seen").len(), 2);
+        db.execute("DELETE FROM events WHERE kind = 'scroll'").unwrap();
+        assert_eq!(rows(&mut db, "SELECT * FROM events").len(), 2);
+
+        // Typed tables alongside still enforce their schema
+        db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)").unwrap();
+        assert!(db.execute("INSERT INTO users (id) VALUES (1)").is_err());
+        assert!(db.execute("UPDATE users SET name = 'x' WHERE nope = 1").is_err());
+
+        // Off by default
+        let other = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(other.path()).unwrap();
+        assert_eq!(db.execute("INSERT INTO events (id) VALUES (1)").unwrap_err().kind(), "table_not_found");
+    }
+
+    #[test]
+    fn test_import_ndjson() {
+        use serde_json::json;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        let rows = |db: &mut Database, sql: &str| match db.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs,
+            other => panic!("expected rows, got {:?}", other),
+        };
+
+        // A new table gets an inferred schema keyed by id
+        let input = "{\"id\": 1, \"name\": \"Ann\", \"tags\": [\"a\"]}\n\n{\"id\": 2, \"name\": \"Bo\"}\n";
+        assert_eq!(db.import_ndjson("people", input.as_bytes()).unwrap(), 2);
+        let schema = db.table_schema("people").unwrap().unwrap();
+        assert_eq!(schema.primary_key, vec!["id"]);
+        let rs = rows(&mut db, "SELECT name, tags FROM people WHERE id = 1");
+        assert_eq!(rs.rows[0]["name"], json!("Ann"));
+        assert_eq!(rs.rows[0]["tags"], json!(["a"]));
+
+        // Into an existing table, checked against its schema
+        db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)").unwrap();
+        db.import_ndjson("users", "{\"id\": 1, \"name\": \"Cy\"}\n".as_bytes()).unwrap();
+        let err = db
+            .import_ndjson("users", "{\"id\": 2, \"name\": \"Di\"}\n{\"id\": 1, \"name\": \"Ed\"}\n".as_bytes())
+            .unwrap_err();
+        assert!(matches!(err, DatabaseError::Import { line: 2, .. }));
+        assert_eq!(err.kind(), "duplicate_key");
+        let err = db.import_ndjson("users", "{\"id\": 3, \"nick\": \"x\"}\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), "column_not_found");
+        // All or nothing: Di was rolled back
+        assert_eq!(rows(&mut db, "SELECT * FROM users").len(), 1);
+
+        // A failed import doesn't leave the table it created behind, nor
+        // any commit creating it
+        let head = db.head().unwrap();
+        let err = db.import_ndjson("pets", "{\"id\": 1}\n{\"id\": 1}\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), "duplicate_key");
+        assert!(!db.table_exists("pets"));
+        assert_eq!(db.head().unwrap(), head);
+        let err = db.import_ndjson("pets", "{\"id\": 1}\nnot json\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, DatabaseError::Import { line: 2, .. }));
+        assert!(!db.table_exists("pets"));
+
+        // With schemaless tables on, new tables take any shape
+        let other = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open_with_config(DatabaseConfig::new(other.path()).schemaless(true)).unwrap();
+        db.import_ndjson("events", "{\"kind\": \"a\"}\n{\"id\": \"e2\", \"kind\": 2}\n".as_bytes()).unwrap();
+        assert!(db.table_schema("events").unwrap().unwrap().schemaless);
+        assert_eq!(db.get_doc("events", "e2").unwrap(), Some(json!({"id": "e2", "kind": 2})));
+        assert_eq!(rows(&mut db, "SELECT * FROM events").len(), 2);
+    }
+
+    #[test]
+    fn test_watch_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+
+        let mut watch = db.watch(db.head().unwrap());
+        assert!(watch.poll().unwrap().is_empty());
+
+        db.execute("INSERT INTO users (id, name) VALUES (1, 'Ann')").unwrap();
+        db.execute("BEGIN").unwrap();
+        db.execute("UPDATE users SET name = 'Bo' WHERE id = 1").unwrap();
+        db.execute("INSERT INTO users (id, name) VALUES (2, 'Cy')").unwrap();
+        db.execute("COMMIT").unwrap();
+
+        let commits = watch.poll().unwrap();
+        // A fast-forwarded transaction shows up statement by statement
+        assert_eq!(commits.len(), 3);
+        assert_eq!(watch.position().to_string(), commits[2].id);
+        let rows = |db: &Database, commit: &CommitInfo| {
+            let mut rows: Vec<String> = db
+                .changes(commit)
+                .unwrap()
+                .iter()
+                .filter_map(|c| c.row())
+                .map(|r| format!("{}/{}", r.table, r.key))
+                .collect();
+            rows.sort();
+            rows
+        };
+        assert_eq!(rows(&db, &commits[0]), vec!["users/1"]);
+        assert_eq!(rows(&db, &commits[1]), vec!["users/1"]);
+        assert_eq!(rows(&db, &commits[2]), vec!["users/2"]);
+
+        // Blocking: picks up a commit made after it started waiting
+        let mut watch = db.watch(db.head().unwrap()).with_interval(std::time::Duration::from_millis(5));
+        db.execute("DELETE FROM users WHERE id = 2").unwrap();
+        let commit = watch.next().unwrap().unwrap();
+        assert_eq!(rows(&db, &commit), vec!["users/2"]);
+    }
 }
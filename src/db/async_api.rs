@@ -0,0 +1,158 @@
+//! Async facade over [`Database`] for use from tokio.
+//!
+//! Git operations block on disk and burn CPU, so calling [`Database::execute`]
+//! straight from an async task stalls the runtime's worker thread. Each method
+//! here instead runs the call on tokio's blocking thread pool and resolves when
+//! it finishes.
+//!
+//! Only built with the `async` cargo feature.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde_json::Value;
+
+use super::api::{Database, DatabaseConfig, DatabaseError, DatabaseResult, DatabaseStats};
+use crate::executor::QueryResult;
+use crate::storage::GcReport;
+
+/// A [`Database`] that can be awaited from async code.
+///
+/// Clones share the same database and the same session, so a `BEGIN` on one
+/// clone is visible to the others; statements run one at a time. Use a
+/// [`ConnectionPool`](super::ConnectionPool) for independent sessions.
+#[derive(Clone)]
+pub struct AsyncDatabase {
+    db: Arc<Mutex<Database>>,
+}
+
+impl AsyncDatabase {
+    /// Open or create a database at the given path.
+    pub async fn open(path: impl Into<PathBuf>) -> DatabaseResult<Self> {
+        Self::open_with_config(DatabaseConfig::new(path)).await
+    }
+
+    /// Open a database with custom configuration.
+    pub async fn open_with_config(config: DatabaseConfig) -> DatabaseResult<Self> {
+        let db = run_blocking(move || Database::open_with_config(config)).await?;
+        Ok(Self::from_database(db))
+    }
+
+    /// Wrap an already open database.
+    pub fn from_database(db: Database) -> Self {
+        Self {
+            db: Arc::new(Mutex::new(db)),
+        }
+    }
+
+    /// Execute a SQL statement.
+    pub async fn execute(&self, sql: impl Into<String>) -> DatabaseResult<QueryResult> {
+        let sql = sql.into();
+        self.with_database(move |db| db.execute(&sql)).await
+    }
+
+    /// Execute a SQL statement with positional parameters.
+    pub async fn execute_with_params(
+        &self,
+        sql: impl Into<String>,
+        params: Vec<Value>,
+    ) -> DatabaseResult<QueryResult> {
+        let sql = sql.into();
+        self.with_database(move |db| db.execute_with_params(&sql, &params)).await
+    }
+
+    /// Execute multiple SQL statements separated by semicolons.
+    pub async fn execute_batch(&self, sql: impl Into<String>) -> DatabaseResult<Vec<QueryResult>> {
+        let sql = sql.into();
+        self.with_database(move |db| db.execute_batch(&sql)).await
+    }
+
+    /// Execute a SQL script, stopping at the first failing statement.
+    pub async fn execute_script(&self, sql: impl Into<String>) -> DatabaseResult<Vec<QueryResult>> {
+        let sql = sql.into();
+        self.with_database(move |db| db.execute_script(&sql)).await
+    }
+
+    /// List all tables.
+    pub async fn tables(&self) -> DatabaseResult<Vec<String>> {
+        self.with_database(|db| db.tables()).await
+    }
+
+    /// Get database statistics.
+    pub async fn stats(&self) -> DatabaseResult<DatabaseStats> {
+        self.with_database(|db| Ok(db.stats())).await
+    }
+
+    /// Repack the object store and remove redundant loose objects.
+    pub async fn gc(&self) -> DatabaseResult<GcReport> {
+        self.with_database(|db| db.gc()).await
+    }
+
+    /// Run a closure against the underlying database on the blocking pool.
+    pub async fn with_database<F, T>(&self, f: F) -> DatabaseResult<T>
+    where
+        F: FnOnce(&mut Database) -> DatabaseResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.db.clone();
+        run_blocking(move || f(&mut db.lock())).await
+    }
+}
+
+impl std::fmt::Debug for AsyncDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncDatabase").finish_non_exhaustive()
+    }
+}
+
+/// Run a blocking call on tokio's blocking pool, re-raising its panics.
+async fn run_blocking<F, T>(f: F) -> DatabaseResult<T>
+where
+    F: FnOnce() -> DatabaseResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(DatabaseError::Cancelled),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_async_execute() {
+        let dir = TempDir::new().unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        runtime.block_on(async {
+            let db = AsyncDatabase::open(dir.path()).await.unwrap();
+            db.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").await.unwrap();
+
+            // Clones share the database, so concurrent tasks see each other's rows
+            let inserts: Vec<_> = (0..4)
+                .map(|i| {
+                    let db = db.clone();
+                    tokio::spawn(async move {
+                        db.execute(format!("INSERT INTO users (id, name) VALUES ('{}', 'user{}')", i, i))
+                            .await
+                    })
+                })
+                .collect();
+            for insert in inserts {
+                insert.await.unwrap().unwrap();
+            }
+
+            match db.execute("SELECT * FROM users").await.unwrap() {
+                QueryResult::Select(rs) => assert_eq!(rs.len(), 4),
+                other => panic!("expected rows, got {:?}", other),
+            }
+            assert!(db.execute("SELECT * FROM missing").await.is_err());
+            assert_eq!(db.tables().await.unwrap(), vec!["users".to_string()]);
+        });
+    }
+}
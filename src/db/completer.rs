@@ -0,0 +1,213 @@
+//! Tab completion for the REPL.
+//!
+//! Completion runs on every Tab press, so it never touches the repository.
+//! Instead the REPL keeps a [`CompletionCache`] snapshot of table and column
+//! names up to date, and the completer only reads that snapshot.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::RwLock;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use super::api::Database;
+
+/// Longest the completer will wait for a cache refresh to finish.
+const CACHE_WAIT: Duration = Duration::from_millis(5);
+
+/// SQL keywords offered for completion.
+const KEYWORDS: &[&str] = &[
+    "ANALYZE", "AND", "AS", "ASC", "AUTHOR", "BEGIN", "BETWEEN", "BOOLEAN", "BY", "CHECK", "COMMIT",
+    "CONSTRAINT", "CREATE", "DEFAULT", "DELETE", "DESC", "DESCRIBE", "DROP", "EXISTS", "EXPLAIN",
+    "FLOAT", "FROM", "FULL", "IF", "IN", "INSERT", "INTEGER", "INTO", "IS", "JSON", "KEY", "LIKE",
+    "LIMIT", "NOT", "NULL", "OFFSET", "OR", "ORDER", "PRIMARY", "ROLLBACK", "SELECT", "SET", "SHOW",
+    "TABLE", "TABLES", "TEXT", "TIMESTAMP", "TRANSACTION", "TRANSACTIONS", "UNIQUE", "UPDATE", "UUID", "VACUUM",
+    "VALUES", "WHERE",
+];
+
+/// REPL dot-commands offered for completion.
+const COMMANDS: &[&str] = &[
+    ".blame", ".clear", ".describe", ".dt", ".dump", ".exit", ".expanded", ".explain", ".fold-names", ".gc",
+    ".help", ".history", ".quit", ".schema", ".stats", ".tables", ".timing", ".tx", ".x",
+];
+
+/// Keywords after which a table name is expected.
+const TABLE_KEYWORDS: &[&str] = &["FROM", "INTO", "UPDATE", "TABLE", "DESCRIBE", "DESC", "EXISTS"];
+
+/// Snapshot of catalog names used for completion.
+#[derive(Debug, Default)]
+pub(crate) struct CompletionCache {
+    tables: Vec<String>,
+    columns: HashMap<String, Vec<String>>,
+}
+
+impl CompletionCache {
+    /// Rebuild the snapshot from the database catalog.
+    pub(crate) fn load(db: &Database) -> Self {
+        let tables = db.tables().unwrap_or_default();
+        let columns = tables
+            .iter()
+            .filter_map(|t| {
+                let schema = db.table_schema(t).ok().flatten()?;
+                let names = schema.columns.into_iter().map(|c| c.name).collect();
+                Some((t.clone(), names))
+            })
+            .collect();
+        Self { tables, columns }
+    }
+}
+
+/// Rustyline helper providing keyword, table, and column completion.
+pub(crate) struct ReplHelper {
+    cache: Arc<RwLock<CompletionCache>>,
+}
+
+impl ReplHelper {
+    /// Create a helper reading from the given cache.
+    pub(crate) fn new(cache: Arc<RwLock<CompletionCache>>) -> Self {
+        Self { cache }
+    }
+
+    /// Compute the start of the word under the cursor and its candidates.
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let start = before
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &before[start..];
+
+        // Dot-commands are only completed as the first word.
+        if word.starts_with('.') && before[..start].trim().is_empty() {
+            let matches = COMMANDS
+                .iter()
+                .filter(|c| c.starts_with(word))
+                .map(|c| c.to_string())
+                .collect();
+            return (start, matches);
+        }
+
+        let tokens: Vec<String> = line
+            .split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')' || c == ';')
+            .filter(|t| !t.is_empty())
+            .map(|t| t.to_string())
+            .collect();
+        let previous = before[..start]
+            .split_whitespace()
+            .last()
+            .map(|t| t.to_uppercase());
+
+        // Never block the editor on a cache refresh in progress.
+        let Some(cache) = self.cache.try_read_for(CACHE_WAIT) else {
+            return (start, Vec::new());
+        };
+
+        let mut matches: Vec<String> = Vec::new();
+        let wants_table = previous
+            .as_deref()
+            .is_some_and(|p| TABLE_KEYWORDS.contains(&p));
+
+        if wants_table {
+            matches.extend(cache.tables.iter().filter(|t| starts_with_ci(t, word)).cloned());
+        } else {
+            // Columns of any table the statement names after FROM/INTO/UPDATE.
+            for pair in tokens.windows(2) {
+                let keyword = pair[0].to_uppercase();
+                if !matches!(keyword.as_str(), "FROM" | "INTO" | "UPDATE") {
+                    continue;
+                }
+                if let Some(columns) = cache.columns.get(&pair[1]) {
+                    for column in columns.iter().filter(|c| starts_with_ci(c, word)) {
+                        if !matches.contains(column) {
+                            matches.push(column.clone());
+                        }
+                    }
+                }
+            }
+
+            if !word.is_empty() {
+                let lowercase = word.chars().all(|c| !c.is_uppercase());
+                matches.extend(
+                    KEYWORDS
+                        .iter()
+                        .filter(|k| starts_with_ci(k, word))
+                        .map(|k| if lowercase { k.to_lowercase() } else { k.to_string() }),
+                );
+            }
+        }
+
+        (start, matches)
+    }
+}
+
+fn starts_with_ci(candidate: &str, prefix: &str) -> bool {
+    candidate.len() >= prefix.len()
+        && candidate.is_char_boundary(prefix.len())
+        && candidate[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, matches) = self.candidates(line, pos);
+        let pairs = matches
+            .into_iter()
+            .map(|m| Pair {
+                display: m.clone(),
+                replacement: m,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn helper() -> ReplHelper {
+        let cache = CompletionCache {
+            tables: vec!["users".into(), "orders".into()],
+            columns: HashMap::from([("users".into(), vec!["id".into(), "name".into()])]),
+        };
+        ReplHelper::new(Arc::new(RwLock::new(cache)))
+    }
+
+    #[test]
+    fn test_complete_tables_and_columns() {
+        let h = helper();
+
+        let line = "SELECT * FROM us";
+        assert_eq!(h.candidates(line, line.len()), (14, vec!["users".to_string()]));
+
+        let line = "SELECT na FROM users";
+        assert_eq!(h.candidates(line, 9), (7, vec!["name".to_string()]));
+
+        let line = "sel";
+        assert_eq!(h.candidates(line, line.len()), (0, vec!["select".to_string()]));
+
+        let line = ".ta";
+        assert_eq!(h.candidates(line, line.len()), (0, vec![".tables".to_string()]));
+    }
+}
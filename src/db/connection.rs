@@ -1,19 +1,30 @@
 //! Connection pooling for database access.
+//!
+//! A [`ConnectionPool`] opens the repository once and pre-opens a fixed number
+//! of [`Connection`]s on it. Threads borrow a connection with
+//! [`ConnectionPool::acquire`] and get it back as a [`PooledConnection`] guard,
+//! which returns it to the pool when dropped. Every connection has its own
+//! open transaction (if any), so sessions on different threads don't see each
+//! other's `BEGIN`. [`Connection::begin`] opens one as a [`TransactionHandle`],
+//! which keeps the connection borrowed until it is committed or rolled back.
 
-use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use parking_lot::{Mutex, RwLock};
+use parking_lot::{Condvar, Mutex, RwLock};
+use serde_json::Value;
 
 use super::api::{DatabaseConfig, DatabaseError, DatabaseResult};
-use crate::executor::QueryExecutor;
-use crate::storage::GitRepository;
+use crate::executor::{Page, QueryExecutor, QueryResult};
+use crate::sql::{Parser, Statement};
+use crate::storage::{CommitId, GitRepository, GitSignature};
+use crate::transaction::{TransactionError, TransactionManager};
 
-/// A database connection from the pool.
+/// A database connection.
 pub struct Connection {
     id: usize,
     executor: QueryExecutor,
-    pool: Option<Arc<ConnectionPoolInner>>,
 }
 
 impl Connection {
@@ -22,137 +33,476 @@ impl Connection {
         Self {
             id: 0,
             executor: QueryExecutor::new(repo),
-            pool: None,
         }
     }
 
+    /// Connection ID, unique within its pool.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
     /// Execute a SQL query.
-    pub fn execute(&mut self, sql: &str) -> DatabaseResult<crate::executor::QueryResult> {
+    pub fn execute(&mut self, sql: &str) -> DatabaseResult<QueryResult> {
         Ok(self.executor.execute(sql)?)
     }
+
+    /// Execute a SQL query with positional parameters.
+    pub fn execute_with_params(&mut self, sql: &str, params: &[Value]) -> DatabaseResult<QueryResult> {
+        Ok(self.executor.execute_with_params(sql, params)?)
+    }
+
+    /// Read a page of `table` in primary key order, after the key `after`.
+    ///
+    /// See [`QueryExecutor::page_after`].
+    pub fn page_after(&mut self, table: &str, after: Option<&[Value]>, limit: usize) -> DatabaseResult<Page> {
+        Ok(self.executor.page_after(table, after, limit)?)
+    }
+
+    /// Commit this connection's changes as `name <email>` instead of GitDB.
+    ///
+    /// Same as `SET AUTHOR = 'name <email>'`.
+    pub fn set_author(&mut self, name: impl Into<String>, email: impl Into<String>) {
+        self.executor.set_author(Some(GitSignature::new(name, email)));
+    }
+
+    /// Go back to committing with the repository's signature.
+    pub fn clear_author(&mut self) {
+        self.executor.set_author(None);
+    }
+
+    /// Add `note` to the commits of the next statement that changes data.
+    ///
+    /// The note goes in the commit message body, above the `Tx-Id:` and
+    /// `Rows:` trailers, so `git log --grep` finds it.
+    pub fn annotate(&mut self, note: impl Into<String>) {
+        self.executor.annotate(note);
+    }
+
+    /// Commit the writes this connection has buffered, see
+    /// [`QueryExecutor::flush`].
+    pub fn flush(&mut self) -> DatabaseResult<Option<CommitId>> {
+        Ok(self.executor.flush()?)
+    }
+
+    /// Check if this connection has an open transaction.
+    pub fn in_transaction(&self) -> bool {
+        self.executor.in_transaction()
+    }
+
+    /// Begin a transaction at the connection's default isolation level.
+    ///
+    /// The handle borrows the connection, so it can't be used, or begin
+    /// another transaction, until the handle is committed, rolled back or
+    /// dropped. Fails if a transaction is already open on the connection
+    /// through SQL `BEGIN`.
+    pub fn begin(&mut self) -> DatabaseResult<TransactionHandle<'_>> {
+        if self.in_transaction() {
+            return Err(TransactionError::InvalidOperation(
+                "connection already has an open transaction".into(),
+            )
+            .into());
+        }
+        self.executor.execute_statement(Statement::Begin { isolation: None })?;
+        Ok(TransactionHandle { conn: self, finished: false })
+    }
+
+    /// Flush buffered writes, roll back any transaction left open and
+    /// forget the author and any pending note, so the next user starts
+    /// clean.
+    fn reset(&mut self) {
+        let _ = self.executor.flush();
+        if self.in_transaction() {
+            let _ = self.executor.execute("ROLLBACK");
+        }
+        self.clear_author();
+        self.executor.clear_note();
+    }
+}
+
+/// A transaction open on a [`Connection`], from [`Connection::begin`].
+///
+/// Statements run through it see the transaction's own writes; other
+/// connections see none of them until [`commit`](Self::commit). Dropping the
+/// handle without committing rolls the transaction back.
+pub struct TransactionHandle<'c> {
+    conn: &'c mut Connection,
+    finished: bool,
 }
 
-impl Drop for Connection {
+impl TransactionHandle<'_> {
+    /// Execute a SQL statement in the transaction.
+    ///
+    /// `BEGIN`, `COMMIT` and `ROLLBACK` are refused; use the handle's methods.
+    pub fn execute(&mut self, sql: &str) -> DatabaseResult<QueryResult> {
+        Self::check_statement(sql)?;
+        self.conn.execute(sql)
+    }
+
+    /// Execute a SQL statement with positional parameters in the transaction.
+    pub fn execute_with_params(&mut self, sql: &str, params: &[Value]) -> DatabaseResult<QueryResult> {
+        Self::check_statement(sql)?;
+        self.conn.execute_with_params(sql, params)
+    }
+
+    /// Commit the transaction.
+    ///
+    /// On a conflict the error is returned and the transaction is rolled
+    /// back, as with SQL `COMMIT`.
+    pub fn commit(mut self) -> DatabaseResult<()> {
+        self.finished = true;
+        self.conn.executor.execute_statement(Statement::Commit)?;
+        Ok(())
+    }
+
+    /// Roll the transaction back, discarding its changes.
+    pub fn rollback(mut self) -> DatabaseResult<()> {
+        self.finished = true;
+        self.conn.executor.execute_statement(Statement::Rollback)?;
+        Ok(())
+    }
+
+    /// The transaction's lifetime belongs to the handle, not to SQL.
+    fn check_statement(sql: &str) -> DatabaseResult<()> {
+        match Parser::parse(sql)? {
+            Statement::Begin { .. } | Statement::Commit | Statement::Rollback => {
+                Err(TransactionError::InvalidOperation(
+                    "use the transaction handle's commit() or rollback() instead of SQL".into(),
+                )
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for TransactionHandle<'_> {
     fn drop(&mut self) {
-        // Return connection to pool if pooled.
-        if let Some(ref pool) = self.pool {
-            let mut available = pool.available.lock();
-            available.push_back(self.id);
+        if !self.finished {
+            self.conn.executor.rollback_open();
         }
     }
 }
 
 struct ConnectionPoolInner {
-    config: DatabaseConfig,
-    repo: Arc<RwLock<GitRepository>>,
-    available: Mutex<VecDeque<usize>>,
-    max_connections: usize,
-    created: Mutex<usize>,
+    repo: GitRepository,
+    idle: Mutex<Vec<Connection>>,
+    returned: Condvar,
+    size: usize,
 }
 
-/// Connection pool for database access.
+/// Fixed-size pool of connections sharing one repository.
 pub struct ConnectionPool {
     inner: Arc<ConnectionPoolInner>,
 }
 
 impl ConnectionPool {
-    /// Create a new connection pool.
-    pub fn new(config: DatabaseConfig, max_connections: usize) -> DatabaseResult<Self> {
-        let repo = if config.create_if_missing {
+    /// Open the database and pre-open `size` connections on it.
+    pub fn new(config: DatabaseConfig, size: usize) -> DatabaseResult<Self> {
+        if size == 0 {
+            return Err(DatabaseError::InvalidConfig(
+                "connection pool size must be at least 1".into(),
+            ));
+        }
+
+        let repo = if config.read_only {
+            GitRepository::open_read_only(&config.path)?
+        } else if config.create_if_missing {
             GitRepository::open_or_init(&config.path)?
         } else {
             GitRepository::open(&config.path)?
         };
+        let repo = repo.with_cache(config.cache_capacity).with_auto_pack(config.auto_pack_every);
+        let repo = match &config.at_commit {
+            Some(commit) => repo.pinned_at(repo.resolve_commit(commit)?),
+            None => repo,
+        };
 
-        let inner = Arc::new(ConnectionPoolInner {
-            config,
-            repo: Arc::new(RwLock::new(repo)),
-            available: Mutex::new(VecDeque::new()),
-            max_connections,
-            created: Mutex::new(0),
-        });
+        // All connections write through one lock and share one lock table
+        let shared = Arc::new(RwLock::new(repo.clone()));
+        let tx_manager = TransactionManager::new(repo.clone());
+        let idle = (1..=size)
+            .map(|id| {
+                let mut executor = QueryExecutor::with_shared(shared.clone(), tx_manager.clone());
+                executor.set_isolation(config.default_isolation);
+                executor.set_schemaless(config.schemaless);
+                executor.set_max_result_rows(config.max_result_rows);
+                executor.set_spill(config.spill.clone());
+                executor.set_commit_batch(config.commit_batch);
+                Connection { id, executor }
+            })
+            .collect();
 
-        Ok(Self { inner })
+        Ok(Self {
+            inner: Arc::new(ConnectionPoolInner {
+                repo,
+                idle: Mutex::new(idle),
+                returned: Condvar::new(),
+                size,
+            }),
+        })
     }
 
-    /// Get a connection from the pool.
-    pub fn get(&self) -> DatabaseResult<Connection> {
-        // Try to get an available connection.
-        {
-            let mut available = self.inner.available.lock();
-            if let Some(id) = available.pop_front() {
-                let repo = self.inner.repo.read().clone();
-                return Ok(Connection {
-                    id,
-                    executor: QueryExecutor::new(repo),
-                    pool: Some(self.inner.clone()),
-                });
+    /// Take a connection, waiting until one is free.
+    pub fn acquire(&self) -> PooledConnection {
+        let mut idle = self.inner.idle.lock();
+        loop {
+            if let Some(conn) = idle.pop() {
+                return self.guard(conn);
             }
+            self.inner.returned.wait(&mut idle);
         }
+    }
 
-        // Create a new connection if under limit.
-        {
-            let mut created = self.inner.created.lock();
-            if *created < self.inner.max_connections {
-                *created += 1;
-                let id = *created;
-                let repo = self.inner.repo.read().clone();
-                return Ok(Connection {
-                    id,
-                    executor: QueryExecutor::new(repo),
-                    pool: Some(self.inner.clone()),
-                });
+    /// Take a connection, waiting at most `timeout` for one to be free.
+    pub fn acquire_timeout(&self, timeout: Duration) -> DatabaseResult<PooledConnection> {
+        let deadline = Instant::now() + timeout;
+        let mut idle = self.inner.idle.lock();
+        loop {
+            if let Some(conn) = idle.pop() {
+                return Ok(self.guard(conn));
+            }
+            if self.inner.returned.wait_until(&mut idle, deadline).timed_out() {
+                return Err(DatabaseError::PoolExhausted);
             }
         }
+    }
 
-        // Pool exhausted - in production we'd wait, but for now error.
-        Err(DatabaseError::InvalidConfig("connection pool exhausted".into()))
+    /// Take a connection if one is free, without waiting.
+    pub fn get(&self) -> DatabaseResult<PooledConnection> {
+        let conn = self.inner.idle.lock().pop();
+        conn.map(|conn| self.guard(conn)).ok_or(DatabaseError::PoolExhausted)
     }
 
-    /// Get the number of available connections.
+    fn guard(&self, conn: Connection) -> PooledConnection {
+        PooledConnection {
+            conn: Some(conn),
+            pool: self.inner.clone(),
+        }
+    }
+
+    /// Get the number of idle connections.
     pub fn available(&self) -> usize {
-        self.inner.available.lock().len()
+        self.inner.idle.lock().len()
     }
 
-    /// Get the total number of connections created.
-    pub fn created(&self) -> usize {
-        *self.inner.created.lock()
+    /// Get the total number of connections in the pool.
+    pub fn size(&self) -> usize {
+        self.inner.size
+    }
+
+    /// Get the repository the connections share.
+    pub fn repository(&self) -> &GitRepository {
+        &self.inner.repo
+    }
+}
+
+impl std::fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("size", &self.size())
+            .field("available", &self.available())
+            .finish()
+    }
+}
+
+/// A connection borrowed from a [`ConnectionPool`].
+///
+/// Dereferences to [`Connection`]. On drop, any open transaction is rolled
+/// back, the author and note are reset and the connection goes back to the pool.
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    pool: Arc<ConnectionPoolInner>,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection already returned")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("connection already returned")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(mut conn) = self.conn.take() {
+            conn.reset();
+            self.pool.idle.lock().push(conn);
+            self.pool.returned.notify_one();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::thread;
 
     #[test]
     fn test_connection_pool() {
         let dir = tempfile::TempDir::new().unwrap();
         let config = DatabaseConfig::new(dir.path());
         let pool = ConnectionPool::new(config, 5).unwrap();
-        
-        let mut conn = pool.get().unwrap();
+        assert_eq!(pool.available(), 5);
+
+        let mut conn = pool.acquire();
         conn.execute("CREATE TABLE test (id TEXT)").unwrap();
-        
-        assert_eq!(pool.created(), 1);
+        assert_eq!(pool.available(), 4);
     }
 
     #[test]
     fn test_pool_reuse() {
         let dir = tempfile::TempDir::new().unwrap();
         let config = DatabaseConfig::new(dir.path());
-        let pool = ConnectionPool::new(config, 5).unwrap();
-        
+        let pool = ConnectionPool::new(config, 2).unwrap();
+
+        let first_id;
         {
-            let _conn1 = pool.get().unwrap();
-            let _conn2 = pool.get().unwrap();
-            assert_eq!(pool.created(), 2);
+            let mut conn1 = pool.acquire();
+            let _conn2 = pool.acquire();
+            assert!(matches!(pool.get(), Err(DatabaseError::PoolExhausted)));
+            let err = pool.acquire_timeout(Duration::from_millis(10)).err().unwrap();
+            assert!(matches!(err, DatabaseError::PoolExhausted));
+            assert!(err.is_retryable());
+
+            // An open transaction doesn't leak to the next user
+            conn1.execute("BEGIN").unwrap();
+            first_id = conn1.id();
         }
-        
+
         // Connections returned to pool.
         assert_eq!(pool.available(), 2);
-        
-        // Reuse existing connection.
-        let _conn3 = pool.get().unwrap();
-        assert_eq!(pool.created(), 2); // No new connection created.
+        let conns = [pool.acquire(), pool.acquire()];
+        assert!(conns.iter().any(|c| c.id() == first_id));
+        assert!(conns.iter().all(|c| !c.in_transaction()));
+    }
+
+    #[test]
+    fn test_transaction_handle() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let pool = ConnectionPool::new(DatabaseConfig::new(dir.path()), 2).unwrap();
+        let count = |conn: &mut Connection| match conn.execute("SELECT * FROM users").unwrap() {
+            QueryResult::Select(rs) => rs.len(),
+            other => panic!("expected rows, got {:?}", other),
+        };
+        let mut conn = pool.acquire();
+        let mut other = pool.acquire();
+        conn.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").unwrap();
+
+        let mut tx = conn.begin().unwrap();
+        tx.execute("INSERT INTO users (id) VALUES ('a')").unwrap();
+        assert!(tx.execute("COMMIT").is_err());
+        assert_eq!(count(&mut other), 0);
+        tx.commit().unwrap();
+        assert!(!conn.in_transaction());
+        assert_eq!(count(&mut other), 1);
+
+        let mut tx = conn.begin().unwrap();
+        tx.execute("INSERT INTO users (id) VALUES ('b')").unwrap();
+        tx.rollback().unwrap();
+
+        // Dropping the handle rolls back too
+        {
+            let mut tx = conn.begin().unwrap();
+            tx.execute("INSERT INTO users (id) VALUES ('c')").unwrap();
+        }
+        assert!(!conn.in_transaction());
+        assert_eq!(count(&mut conn), 1);
+
+        // A transaction opened with SQL blocks begin()
+        conn.execute("BEGIN").unwrap();
+        assert_eq!(conn.begin().err().map(|e| e.kind()), Some("invalid_operation"));
+        conn.execute("ROLLBACK").unwrap();
+    }
+
+    #[test]
+    fn test_session_author() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let pool = ConnectionPool::new(DatabaseConfig::new(dir.path()), 1).unwrap();
+        let repo = pool.repository().clone();
+        let last_author = || {
+            let commit = repo.get_commit(repo.head().unwrap()).unwrap();
+            format!("{} <{}>", commit.author_name, commit.author_email)
+        };
+
+        {
+            let mut conn = pool.acquire();
+            conn.set_author("Alice", "alice@example.com");
+            conn.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").unwrap();
+            assert_eq!(last_author(), "Alice <alice@example.com>");
+
+            conn.execute("SET AUTHOR = 'Bob <bob@example.com>'").unwrap();
+            conn.execute("INSERT INTO users (id) VALUES ('a')").unwrap();
+            assert_eq!(last_author(), "Bob <bob@example.com>");
+        }
+
+        // The next user of the connection commits as GitDB again
+        let mut conn = pool.acquire();
+        conn.execute("INSERT INTO users (id) VALUES ('b')").unwrap();
+        assert_eq!(last_author(), "GitDB <gitdb@localhost>");
+    }
+
+    #[test]
+    fn test_annotate() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut conn = Connection::new(GitRepository::init(dir.path()).unwrap());
+        let repo = GitRepository::open(dir.path()).unwrap();
+        let last_trailers = || repo.get_commit(repo.head().unwrap()).unwrap().trailers();
+
+        conn.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").unwrap();
+        conn.annotate("ticket #123");
+
+        // Reads and failed writes leave the note for the next write
+        conn.execute("SELECT * FROM users").unwrap();
+        assert!(conn.execute("INSERT INTO missing (id) VALUES ('a')").is_err());
+        conn.execute("INSERT INTO users (id) VALUES ('a')").unwrap();
+        let trailers = last_trailers();
+        assert_eq!(trailers.note.as_deref(), Some("ticket #123"));
+        assert_eq!(trailers.rows, vec!["users/a".to_string()]);
+
+        conn.execute("INSERT INTO users (id) VALUES ('b')").unwrap();
+        assert_eq!(last_trailers().note, None);
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        const THREADS: usize = 8;
+        const INSERTS: usize = 20;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let pool = Arc::new(ConnectionPool::new(DatabaseConfig::new(dir.path()), 4).unwrap());
+        pool.acquire()
+            .execute("CREATE TABLE events (id TEXT PRIMARY KEY, thread INTEGER)")
+            .unwrap();
+
+        let workers: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let pool = pool.clone();
+                thread::spawn(move || {
+                    for i in 0..INSERTS {
+                        let mut conn = pool.acquire();
+                        conn.execute(&format!(
+                            "INSERT INTO events (id, thread) VALUES ('{}-{}', {})",
+                            t, i, t
+                        ))
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let mut conn = pool.acquire();
+        match conn.execute("SELECT * FROM events").unwrap() {
+            QueryResult::Select(rs) => assert_eq!(rs.len(), THREADS * INSERTS),
+            other => panic!("expected rows, got {:?}", other),
+        }
     }
 }
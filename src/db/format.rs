@@ -0,0 +1,291 @@
+//! Output formats for query results.
+//!
+//! Used by the command-line interface to render [`QueryResult`]s either for
+//! people (tab-separated or vertical text) or for other programs (JSON, NDJSON),
+//! and errors to match.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use super::api::DatabaseError;
+use crate::executor::{QueryResult, ResultSet};
+
+/// How query results are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Tab-separated columns with a header line and a row count.
+    #[default]
+    Text,
+    /// A single JSON array of row objects.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    /// One `column | value` block per row, for wide rows.
+    Vertical,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" | "tsv" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "vertical" | "expanded" => Ok(OutputFormat::Vertical),
+            other => Err(format!("unknown output format: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Vertical => "vertical",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Write a query result in the given format.
+pub fn write_result<W: Write>(
+    out: &mut W,
+    result: &QueryResult,
+    format: OutputFormat,
+) -> io::Result<()> {
+    match (result, format) {
+        (QueryResult::Select(rs), OutputFormat::Text) => write_text(out, rs),
+        (QueryResult::Select(rs), OutputFormat::Vertical) => write_vertical(out, rs, usize::MAX),
+        (QueryResult::Select(rs), OutputFormat::Json) => {
+            write!(out, "[")?;
+            for (i, row) in rs.rows.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ",")?;
+                }
+                write_json_row(out, rs, row)?;
+            }
+            writeln!(out, "]")
+        }
+        (QueryResult::Select(rs), OutputFormat::Ndjson) => {
+            for row in &rs.rows {
+                write_json_row(out, rs, row)?;
+                writeln!(out)?;
+            }
+            Ok(())
+        }
+        (other, OutputFormat::Text | OutputFormat::Vertical) => {
+            writeln!(out, "{}", status_message(other))
+        }
+        (other, OutputFormat::Json | OutputFormat::Ndjson) => {
+            let status = match other {
+                QueryResult::Modified { rows_affected } => {
+                    serde_json::json!({ "rows_affected": rows_affected })
+                }
+                other => serde_json::json!({ "message": status_message(other) }),
+            };
+            writeln!(out, "{}", status)
+        }
+    }
+}
+
+/// Write an error in the given format.
+///
+/// The JSON formats write `{"error": {"kind": ..., "message": ...}}` on one
+/// line, with [`DatabaseError::kind`] for programs to branch on.
+pub fn write_error<W: Write>(out: &mut W, error: &DatabaseError, format: OutputFormat) -> io::Result<()> {
+    match format {
+        OutputFormat::Text | OutputFormat::Vertical => writeln!(out, "Error: {}", error),
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let error = serde_json::json!({
+                "error": { "kind": error.kind(), "message": error.to_string() },
+            });
+            writeln!(out, "{}", error)
+        }
+    }
+}
+
+/// Human-readable message for a non-SELECT result.
+fn status_message(result: &QueryResult) -> String {
+    match result {
+        QueryResult::Success { message } | QueryResult::Transaction { message } => message.clone(),
+        QueryResult::Modified { rows_affected } => format!("{} row(s) modified", rows_affected),
+        QueryResult::Select(rs) => format!("({} rows)", rs.len()),
+    }
+}
+
+/// Column names to print, in result order.
+fn output_columns(rs: &ResultSet) -> Vec<&str> {
+    if rs.columns.is_empty() {
+        rs.rows
+            .first()
+            .map(|r| r.keys().map(|k| k.as_str()).collect())
+            .unwrap_or_default()
+    } else {
+        rs.columns.iter().map(|c| c.as_str()).collect()
+    }
+}
+
+fn write_text<W: Write>(out: &mut W, rs: &ResultSet) -> io::Result<()> {
+    if rs.is_empty() && rs.columns.is_empty() {
+        return writeln!(out, "(0 rows)");
+    }
+
+    let columns = output_columns(rs);
+    writeln!(out, "{}", columns.join("\t"))?;
+    for row in &rs.rows {
+        let values: Vec<String> = columns
+            .iter()
+            .map(|col| row.get(*col).map(format_value).unwrap_or_default())
+            .collect();
+        writeln!(out, "{}", values.join("\t"))?;
+    }
+    writeln!(out, "({} rows)", rs.len())
+}
+
+/// Write rows as vertical `column | value` records, psql `\x` style.
+///
+/// At most `max_rows` records are printed; the total row count always is.
+pub fn write_vertical<W: Write>(out: &mut W, rs: &ResultSet, max_rows: usize) -> io::Result<()> {
+    if rs.is_empty() {
+        return writeln!(out, "(0 rows)");
+    }
+
+    let columns = output_columns(rs);
+    let width = columns.iter().map(|c| c.chars().count()).max().unwrap_or(0);
+    let shown = rs.len().min(max_rows);
+
+    for (i, row) in rs.rows.iter().take(shown).enumerate() {
+        let title = format!("-[ RECORD {} ]", i + 1);
+        writeln!(out, "{:-<w$}", title, w = (width + 3).max(title.len() + 4))?;
+        for col in &columns {
+            let value = row.get(*col).map(format_value).unwrap_or_default();
+            writeln!(out, "{:<width$} | {}", col, value, width = width)?;
+        }
+    }
+
+    if rs.len() > shown {
+        writeln!(out, "... ({} more rows)", rs.len() - shown)?;
+    }
+    writeln!(out, "({} rows)", rs.len())
+}
+
+/// Write one row as a JSON object with keys in column order.
+fn write_json_row<W: Write>(
+    out: &mut W,
+    rs: &ResultSet,
+    row: &std::collections::BTreeMap<String, Value>,
+) -> io::Result<()> {
+    write!(out, "{{")?;
+    for (i, col) in output_columns(rs).into_iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        let value = row.get(col).unwrap_or(&Value::Null);
+        write!(out, "{}:{}", Value::String(col.to_string()), value)?;
+    }
+    write!(out, "}}")
+}
+
+/// Format a single value for text output.
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn sample() -> QueryResult {
+        let mut row = BTreeMap::new();
+        row.insert("name".to_string(), json!("Alice"));
+        row.insert("age".to_string(), json!(30));
+        row.insert("email".to_string(), Value::Null);
+        QueryResult::Select(ResultSet {
+            columns: vec!["name".into(), "age".into(), "email".into()],
+            rows: vec![row.clone(), row],
+        })
+    }
+
+    fn render(result: &QueryResult, format: OutputFormat) -> String {
+        let mut out = Vec::new();
+        write_result(&mut out, result, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_text_format() {
+        let empty = QueryResult::Select(ResultSet { columns: vec!["id".into(), "name".into()], rows: Vec::new() });
+        assert_eq!(render(&empty, OutputFormat::Text), "id\tname\n(0 rows)\n");
+        let unknown = QueryResult::Select(ResultSet { columns: Vec::new(), rows: Vec::new() });
+        assert_eq!(render(&unknown, OutputFormat::Text), "(0 rows)\n");
+    }
+
+    #[test]
+    fn test_json_formats() {
+        let row = r#"{"name":"Alice","age":30,"email":null}"#;
+        assert_eq!(render(&sample(), OutputFormat::Json), format!("[{},{}]\n", row, row));
+        assert_eq!(render(&sample(), OutputFormat::Ndjson), format!("{}\n{}\n", row, row));
+        assert_eq!(
+            render(&QueryResult::modified(2), OutputFormat::Json),
+            "{\"rows_affected\":2}\n"
+        );
+    }
+
+    #[test]
+    fn test_error_formats() {
+        let error = DatabaseError::Execute(crate::executor::ExecuteError::TableNotFound("users".into()));
+        let render = |format| {
+            let mut out = Vec::new();
+            write_error(&mut out, &error, format).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(render(OutputFormat::Text), "Error: execution error: table not found: users\n");
+        let json: Value = serde_json::from_str(&render(OutputFormat::Json)).unwrap();
+        assert_eq!(json["error"]["kind"], "table_not_found");
+        assert_eq!(json["error"]["message"], "execution error: table not found: users");
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("JSON".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!("jsonl".parse::<OutputFormat>().unwrap(), OutputFormat::Ndjson);
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_vertical_format() {
+        let expected = "\
+-[ RECORD 1 ]----
+name  | Alice
+age   | 30
+email | NULL
+-[ RECORD 2 ]----
+name  | Alice
+age   | 30
+email | NULL
+(2 rows)
+";
+        assert_eq!(render(&sample(), OutputFormat::Vertical), expected);
+
+        let QueryResult::Select(rs) = sample() else { unreachable!() };
+        let mut out = Vec::new();
+        write_vertical(&mut out, &rs, 1).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("... (1 more rows)\n(2 rows)"));
+        assert!(!text.contains("RECORD 2"));
+    }
+}
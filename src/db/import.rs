@@ -0,0 +1,163 @@
+//! Loading newline-delimited JSON into a table.
+//!
+//! [`Database::import_ndjson`](super::Database::import_ndjson) reads the whole
+//! input before writing anything, so a malformed line aborts the import with
+//! nothing changed. A table that doesn't exist yet gets a schema inferred from
+//! the first records, see [`infer_schema`].
+
+use std::io::BufRead;
+
+use serde_json::{Map, Value};
+
+use super::api::{DatabaseError, DatabaseResult};
+use crate::catalog::{ColumnDef, Constraint, DataType, SchemaError, TableSchema};
+
+/// How many records a new table's schema is inferred from.
+pub(crate) const INFER_SAMPLE: usize = 100;
+
+/// Column that becomes the primary key of an inferred schema, when every
+/// sampled record has it.
+const KEY_COLUMN: &str = "id";
+
+/// A record read from the input, with the line it came from.
+pub(crate) type Record = (usize, Map<String, Value>);
+
+/// Read one JSON object per line, skipping blank lines.
+pub(crate) fn read_records(reader: impl BufRead) -> DatabaseResult<Vec<Record>> {
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.map_err(|e| at_line(line_no, e.into()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Value>(&line) {
+            Ok(Value::Object(record)) => records.push((line_no, record)),
+            Ok(_) => {
+                let e = SchemaError::InvalidRow("expected a JSON object".into());
+                return Err(at_line(line_no, e.into()));
+            }
+            Err(e) => return Err(at_line(line_no, DatabaseError::Json(e))),
+        }
+    }
+    Ok(records)
+}
+
+/// Infer a schema for `table` from the first [`INFER_SAMPLE`] records.
+///
+/// Columns appear in the order they are first seen, each record's fields
+/// taken alphabetically. Each gets the type its
+/// non-null values share, with integers and floats together making a REAL
+/// column; a column with only nulls is TEXT. Any other mix of types is an
+/// error for the first record that disagrees. `id` is the primary key if
+/// every sampled record has one, otherwise keys are generated. Every other
+/// column is nullable.
+pub(crate) fn infer_schema(table: &str, records: &[Record]) -> DatabaseResult<TableSchema> {
+    let sample = &records[..records.len().min(INFER_SAMPLE)];
+    let mut columns: Vec<(String, Option<DataType>)> = Vec::new();
+    for (line, record) in sample {
+        for (field, value) in record {
+            let Some(found) = value_type(value) else {
+                if !columns.iter().any(|(name, _)| name == field) {
+                    columns.push((field.clone(), None));
+                }
+                continue;
+            };
+            match columns.iter_mut().find(|(name, _)| name == field) {
+                None => columns.push((field.clone(), Some(found))),
+                Some((_, ty @ None)) => *ty = Some(found),
+                Some((_, Some(ty))) => match (&*ty, &found) {
+                    (a, b) if a == b => {}
+                    (DataType::Integer, DataType::Float) => *ty = DataType::Float,
+                    (DataType::Float, DataType::Integer) => {}
+                    _ => {
+                        let e = SchemaError::InvalidRow(format!(
+                            "column '{}' was {} in earlier records but is {} here",
+                            field, ty, found
+                        ));
+                        return Err(at_line(*line, e.into()));
+                    }
+                },
+            }
+        }
+    }
+
+    let keyed = !sample.is_empty()
+        && sample.iter().all(|(_, record)| record.get(KEY_COLUMN).is_some_and(|v| !v.is_null()));
+    let columns = columns.into_iter()
+        .map(|(name, ty)| {
+            let col = ColumnDef::new(&name, ty.unwrap_or(DataType::Text));
+            if keyed && name == KEY_COLUMN {
+                col.with_constraint(Constraint::PrimaryKey)
+            } else {
+                col
+            }
+        })
+        .collect();
+    let schema = TableSchema::new(table, columns);
+    Ok(if keyed { schema.with_primary_key(KEY_COLUMN) } else { schema })
+}
+
+/// The column type a JSON value fits, or `None` for null.
+fn value_type(value: &Value) -> Option<DataType> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some(DataType::Boolean),
+        Value::Number(n) if n.is_i64() || n.is_u64() => Some(DataType::Integer),
+        Value::Number(_) => Some(DataType::Float),
+        Value::String(_) => Some(DataType::Text),
+        Value::Array(_) | Value::Object(_) => Some(DataType::Json),
+    }
+}
+
+/// Attach the input line number to an import error.
+pub(crate) fn at_line(line: usize, source: DatabaseError) -> DatabaseError {
+    DatabaseError::Import { line, source: Box::new(source) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_records() {
+        let input = "{\"a\": 1}\n\n  \n{\"a\": 2}\n";
+        let records = read_records(input.as_bytes()).unwrap();
+        assert_eq!(records.iter().map(|(line, _)| *line).collect::<Vec<_>>(), vec![1, 4]);
+
+        let err = read_records("{\"a\": 1}\n{\"a\": \n".as_bytes()).unwrap_err();
+        assert!(matches!(err, DatabaseError::Import { line: 2, .. }));
+        assert_eq!(err.kind(), "invalid_json");
+        let err = read_records("[1, 2]\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, DatabaseError::Import { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        let input = concat!(
+            "{\"id\": 1, \"name\": \"a\", \"score\": 1, \"tags\": [\"x\"], \"note\": null}\n",
+            "{\"id\": 2, \"name\": \"b\", \"score\": 2.5, \"active\": true}\n",
+        );
+        let schema = infer_schema("t", &read_records(input.as_bytes()).unwrap()).unwrap();
+        assert_eq!(schema.primary_key, vec!["id"]);
+        let types: Vec<(&str, String)> = schema.columns.iter()
+            .map(|c| (c.name.as_str(), c.data_type.sql_name()))
+            .collect();
+        assert_eq!(types, vec![
+            ("id", "INTEGER".to_string()),
+            ("name", "TEXT".to_string()),
+            ("note", "TEXT".to_string()),
+            ("score", "REAL".to_string()),
+            ("tags", "JSON".to_string()),
+            ("active", "BOOLEAN".to_string()),
+        ]);
+
+        // Without an id on every record, keys are generated
+        let records = read_records("{\"id\": 1}\n{\"name\": \"b\"}\n".as_bytes()).unwrap();
+        assert!(infer_schema("t", &records).unwrap().primary_key.is_empty());
+
+        let records = read_records("{\"a\": 1}\n{\"a\": \"x\"}\n".as_bytes()).unwrap();
+        let err = infer_schema("t", &records).unwrap_err();
+        assert!(matches!(err, DatabaseError::Import { line: 2, .. }));
+    }
+}
@@ -0,0 +1,266 @@
+//! Numbered schema migrations.
+//!
+//! Register migrations on a [`Migrations`] set and apply them with
+//! [`Database::migrate`](super::Database::migrate). Applied versions are
+//! recorded in the `_migrations` system table, so running the same set again
+//! only applies the new ones. The commit that finishes each migration is
+//! tagged `migration/<version>`, which makes it easy to find in the history.
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use super::api::{DatabaseError, DatabaseResult};
+use crate::catalog::SchemaVersion;
+use crate::storage::{CommitId, GitRepository, Row, RowKey, StorageResult, TableName};
+
+/// System table holding one row per applied migration.
+const MIGRATIONS_TABLE: &str = "_migrations";
+
+/// A numbered migration: SQL to apply it and, optionally, to revert it.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    /// Version number; migrations apply in ascending order.
+    pub version: SchemaVersion,
+    /// Short description, recorded when applied.
+    pub name: String,
+    /// Scripts run by [`Database::migrate`](super::Database::migrate).
+    pub up: Vec<String>,
+    /// Scripts run by [`Database::migrate_down`](super::Database::migrate_down).
+    pub down: Vec<String>,
+}
+
+impl Migration {
+    /// Create an empty migration.
+    pub fn new(version: SchemaVersion, name: impl Into<String>) -> Self {
+        Self {
+            version,
+            name: name.into(),
+            up: Vec::new(),
+            down: Vec::new(),
+        }
+    }
+
+    /// Add a script to run when applying; it may hold several statements.
+    pub fn up(mut self, sql: impl Into<String>) -> Self {
+        self.up.push(sql.into());
+        self
+    }
+
+    /// Add a script to run when reverting.
+    pub fn down(mut self, sql: impl Into<String>) -> Self {
+        self.down.push(sql.into());
+        self
+    }
+}
+
+/// A set of migrations to bring a database up to date.
+#[derive(Debug, Clone, Default)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a migration.
+    pub fn migration(mut self, migration: Migration) -> Self {
+        self.migrations.push(migration);
+        self
+    }
+
+    /// Get a migration by version.
+    pub fn get(&self, version: SchemaVersion) -> Option<&Migration> {
+        self.migrations.iter().find(|m| m.version == version)
+    }
+
+    /// Get the highest registered version.
+    pub fn latest_version(&self) -> Option<SchemaVersion> {
+        self.migrations.iter().map(|m| m.version).max()
+    }
+
+    /// Migrations in version order, rejecting duplicate versions.
+    pub(crate) fn ordered(&self) -> DatabaseResult<Vec<&Migration>> {
+        let mut seen = HashSet::new();
+        if let Some(dup) = self.migrations.iter().find(|m| !seen.insert(m.version)) {
+            return Err(DatabaseError::InvalidConfig(format!(
+                "duplicate migration version {}",
+                dup.version
+            )));
+        }
+        let mut ordered: Vec<_> = self.migrations.iter().collect();
+        ordered.sort_by_key(|m| m.version);
+        Ok(ordered)
+    }
+}
+
+/// A migration recorded as applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedMigration {
+    /// Migration version.
+    pub version: SchemaVersion,
+    /// Migration name at the time it was applied.
+    pub name: String,
+    /// When it was applied.
+    pub applied_at: DateTime<Utc>,
+}
+
+/// Tag name for the commit that finished a migration.
+pub(crate) fn migration_tag(version: SchemaVersion) -> String {
+    format!("migration/{}", version)
+}
+
+fn migrations_table() -> TableName {
+    TableName::new(MIGRATIONS_TABLE).expect("valid system table name")
+}
+
+/// Zero-padded so the rows list in version order.
+fn migration_key(version: SchemaVersion) -> RowKey {
+    RowKey::new(format!("{:010}", version)).expect("digits are a valid row key")
+}
+
+/// Read the applied migrations in version order.
+pub(crate) fn applied(repo: &GitRepository) -> StorageResult<Vec<AppliedMigration>> {
+    let head = repo.head()?;
+    let table = migrations_table();
+    if !repo.table_exists(&table, head)? {
+        return Ok(Vec::new());
+    }
+
+    let mut applied: Vec<AppliedMigration> = repo.scan_table(&table, head)?
+        .into_iter()
+        .filter_map(|row| {
+            Some(AppliedMigration {
+                version: row.data.get("version")?.as_u64()? as SchemaVersion,
+                name: row.data.get("name")?.as_str()?.to_string(),
+                applied_at: row.data.get("applied_at")?.as_str()?.parse().ok()?,
+            })
+        })
+        .collect();
+    applied.sort_by_key(|m| m.version);
+    Ok(applied)
+}
+
+/// Record a migration as applied on `repo`'s branch, returning the new head.
+///
+/// The caller tags that commit once it has reached main.
+pub(crate) fn record(repo: &GitRepository, migration: &Migration) -> StorageResult<CommitId> {
+    let mut head = repo.head()?;
+    let table = migrations_table();
+    if !repo.table_exists(&table, head)? {
+        head = repo.create_table(&table, head, None)?;
+    }
+
+    let mut data = BTreeMap::new();
+    data.insert("version".to_string(), Value::from(migration.version));
+    data.insert("name".to_string(), Value::from(migration.name.clone()));
+    data.insert("applied_at".to_string(), Value::from(Utc::now().to_rfc3339()));
+    let head = repo.upsert_row(&table, Row::new(migration_key(migration.version), data), head, None)?;
+
+    repo.advance_head(head)?;
+    Ok(head)
+}
+
+/// Remove a migration's record from `repo`'s branch after reverting it.
+pub(crate) fn unrecord(repo: &GitRepository, version: SchemaVersion) -> StorageResult<CommitId> {
+    let head = repo.delete_row(&migrations_table(), &migration_key(version), repo.head()?, None)?;
+    repo.advance_head(head)?;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Database, DatabaseConfig};
+    use crate::executor::BatchConfig;
+
+    fn migrations() -> Migrations {
+        Migrations::new()
+            .migration(
+                Migration::new(1, "create users")
+                    .up("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+                    .down("DROP TABLE users"),
+            )
+            .migration(
+                Migration::new(2, "seed users")
+                    .up("INSERT INTO users (id, name) VALUES (1, 'Ann'); INSERT INTO users (id, name) VALUES (2, 'Bo')")
+                    .down("DELETE FROM users"),
+            )
+    }
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        assert_eq!(db.migrate(&migrations()).unwrap(), vec![1, 2]);
+        assert_eq!(db.migrate(&migrations()).unwrap(), Vec::<SchemaVersion>::new());
+
+        let applied = db.applied_migrations().unwrap();
+        assert_eq!(applied.iter().map(|m| m.version).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(applied[1].name, "seed users");
+        let repo = GitRepository::open(dir.path()).unwrap();
+        assert_eq!(repo.resolve_tag(&migration_tag(2)).unwrap(), Some(repo.head().unwrap()));
+        // The system table is not a user table
+        assert_eq!(db.tables().unwrap(), vec!["users".to_string()]);
+
+        assert_eq!(db.migrate_down(&migrations(), 0).unwrap(), vec![2, 1]);
+        assert!(!db.table_exists("users"));
+        assert!(db.applied_migrations().unwrap().is_empty());
+        assert_eq!(repo.resolve_tag(&migration_tag(1)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.migrate(&migrations()).unwrap();
+        let head = db.snapshot("before").unwrap();
+
+        let broken = migrations().migration(
+            Migration::new(3, "add orders")
+                .up("CREATE TABLE orders (id INTEGER PRIMARY KEY)")
+                .up("INSERT INTO missing (id) VALUES (1)"),
+        );
+        let err = db.migrate(&broken).unwrap_err();
+        assert!(matches!(err, DatabaseError::Migration { version: 3, .. }));
+        assert_eq!(db.snapshot("after").unwrap(), head);
+        assert!(!db.table_exists("orders"));
+        assert_eq!(db.applied_migrations().unwrap().len(), 2);
+
+        let duplicate = migrations().migration(Migration::new(2, "again"));
+        assert!(matches!(db.migrate(&duplicate), Err(DatabaseError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_failed_migration_with_commit_batch() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = DatabaseConfig::new(dir.path()).commit_batch(Some(BatchConfig::default()));
+        let mut db = Database::open_with_config(config.clone()).unwrap();
+        db.migrate(&migrations()).unwrap();
+        db.execute("INSERT INTO users (id, name) VALUES (3, 'Cy')").unwrap();
+
+        let broken = migrations().migration(
+            Migration::new(3, "add t")
+                .up("CREATE TABLE t (id INTEGER PRIMARY KEY)")
+                .up("INSERT INTO t (id) VALUES (1)")
+                .up("INSERT INTO missing (id) VALUES (1)"),
+        );
+        assert!(matches!(db.migrate(&broken), Err(DatabaseError::Migration { version: 3, .. })));
+
+        // Nothing of the failed migration is left to flush, and the write
+        // buffered before it was kept
+        db.flush().unwrap();
+        drop(db);
+        let db = Database::open_with_config(config).unwrap();
+        assert_eq!(db.tables().unwrap(), vec!["users".to_string()]);
+        assert_eq!(db.applied_migrations().unwrap().len(), 2);
+        let repo = GitRepository::open(dir.path()).unwrap();
+        let users = TableName::new("users").unwrap();
+        assert_eq!(repo.scan_table(&users, repo.head().unwrap()).unwrap().len(), 3);
+    }
+}
@@ -4,9 +4,21 @@
 //! and an interactive command-line interface.
 
 mod api;
+#[cfg(feature = "async")]
+mod async_api;
+mod completer;
+mod format;
+mod import;
+mod migrate;
 mod repl;
 mod connection;
+mod watch;
 
-pub use api::{Database, DatabaseConfig, DatabaseError, DatabaseResult};
-pub use connection::{Connection, ConnectionPool};
+pub use api::{CommitInfo, Database, DatabaseConfig, DatabaseError, DatabaseResult, DatabaseStats};
+#[cfg(feature = "async")]
+pub use async_api::AsyncDatabase;
+pub use connection::{Connection, ConnectionPool, PooledConnection, TransactionHandle};
+pub use format::{write_error, write_result, OutputFormat};
+pub use migrate::{AppliedMigration, Migration, Migrations};
 pub use repl::{Repl, ReplConfig};
+pub use watch::Watch;
@@ -1,30 +1,52 @@
 //! Interactive REPL (Read-Eval-Print Loop) for GitDB.
 
-use std::io::{self, BufRead, Write};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use super::api::{Database, DatabaseResult};
-use crate::executor::QueryResult;
+use parking_lot::{Mutex, RwLock};
+use rustyline::error::ReadlineError;
+use rustyline::history::FileHistory;
+use rustyline::Editor;
+
+use super::api::{Database, DatabaseError, DatabaseResult};
+use super::completer::{CompletionCache, ReplHelper};
+use super::format::{format_value, write_vertical};
+use crate::executor::{CancelToken, QueryResult};
+
+/// What Ctrl-C cancels while a statement runs, see [`cancel_on_interrupt`].
+static INTERRUPT: Mutex<Option<CancelToken>> = Mutex::new(None);
 
 /// REPL configuration.
 #[derive(Debug, Clone)]
 pub struct ReplConfig {
     /// Prompt string.
     pub prompt: String,
+    /// Prompt string while a transaction is open.
+    pub transaction_prompt: String,
     /// Show timing information.
     pub timing: bool,
     /// Enable colors in output.
     pub colors: bool,
     /// Max rows to display.
     pub max_rows: usize,
+    /// Print each row as a vertical block of `column | value` lines.
+    pub expanded: bool,
+    /// File the command history is loaded from and saved to.
+    pub history_file: Option<PathBuf>,
 }
 
 impl Default for ReplConfig {
     fn default() -> Self {
         Self {
             prompt: "gitdb> ".into(),
+            transaction_prompt: "gitdb*> ".into(),
             timing: true,
             colors: true,
             max_rows: 100,
+            expanded: false,
+            history_file: std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".gitdb_history")),
         }
     }
 }
@@ -33,50 +55,58 @@ impl Default for ReplConfig {
 pub struct Repl {
     db: Database,
     config: ReplConfig,
-    history: Vec<String>,
+    editor: Editor<ReplHelper, FileHistory>,
+    completions: Arc<RwLock<CompletionCache>>,
 }
 
 impl Repl {
     /// Create a new REPL with the given database.
-    pub fn new(db: Database) -> Self {
-        Self {
-            db,
-            config: ReplConfig::default(),
-            history: Vec::new(),
-        }
+    pub fn new(db: Database) -> DatabaseResult<Self> {
+        Self::with_config(db, ReplConfig::default())
     }
 
     /// Create a REPL with custom configuration.
-    pub fn with_config(db: Database, config: ReplConfig) -> Self {
-        Self {
+    pub fn with_config(db: Database, config: ReplConfig) -> DatabaseResult<Self> {
+        let completions = Arc::new(RwLock::new(CompletionCache::load(&db)));
+        let mut editor = Editor::new().map_err(editor_error)?;
+        editor.set_helper(Some(ReplHelper::new(completions.clone())));
+        if let Some(path) = &config.history_file {
+            // A missing history file just means this is the first session.
+            let _ = editor.load_history(path);
+        }
+        Ok(Self {
             db,
             config,
-            history: Vec::new(),
-        }
+            editor,
+            completions,
+        })
     }
 
     /// Run the REPL interactively.
     pub fn run(&mut self) -> DatabaseResult<()> {
         self.print_banner();
-        
-        let stdin = io::stdin();
-        let mut stdout = io::stdout();
+        cancel_on_interrupt(self.db.cancel_token());
+
         let mut input = String::new();
         let mut multiline = false;
 
         loop {
-            // Print prompt.
-            let prompt = if multiline { "     -> " } else { &self.config.prompt };
-            print!("{}", prompt);
-            stdout.flush()?;
-
             // Read line.
-            let mut line = String::new();
-            if stdin.lock().read_line(&mut line)? == 0 {
-                // EOF.
-                println!("\nGoodbye!");
-                break;
-            }
+            let prompt = if multiline { "     -> ".to_string() } else { self.prompt().to_string() };
+            let line = match self.editor.readline(&prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    // Ctrl-C discards the current statement instead of exiting.
+                    input.clear();
+                    multiline = false;
+                    continue;
+                }
+                Err(ReadlineError::Eof) => {
+                    println!("\nGoodbye!");
+                    break;
+                }
+                Err(e) => return Err(editor_error(e)),
+            };
 
             let line = line.trim_end();
 
@@ -107,7 +137,7 @@ impl Repl {
             }
 
             // Add to history.
-            self.history.push(cmd.clone());
+            self.editor.add_history_entry(cmd.as_str()).map_err(editor_error)?;
 
             // Handle special commands.
             if self.is_command(&cmd) {
@@ -123,6 +153,9 @@ impl Repl {
             let start = std::time::Instant::now();
             match self.db.execute(&cmd) {
                 Ok(result) => {
+                    if is_ddl(&cmd) {
+                        self.refresh_completions();
+                    }
                     self.print_result(&result);
                     if self.config.timing {
                         println!("Time: {:.3}ms", start.elapsed().as_secs_f64() * 1000.0);
@@ -132,9 +165,33 @@ impl Repl {
             }
         }
 
+        self.save_history();
         Ok(())
     }
 
+    /// The prompt for a new statement, which shows whether a transaction
+    /// is open.
+    fn prompt(&self) -> &str {
+        if self.db.in_transaction() {
+            &self.config.transaction_prompt
+        } else {
+            &self.config.prompt
+        }
+    }
+
+    fn refresh_completions(&self) {
+        let cache = CompletionCache::load(&self.db);
+        *self.completions.write() = cache;
+    }
+
+    fn save_history(&mut self) {
+        if let Some(path) = &self.config.history_file {
+            if let Err(e) = self.editor.save_history(path) {
+                eprintln!("Warning: could not save history to {}: {}", path.display(), e);
+            }
+        }
+    }
+
     fn print_banner(&self) {
         println!("╔═══════════════════════════════════════════════════╗");
         println!("║                     GitDB v0.1.0                  ║");
@@ -159,8 +216,14 @@ impl Repl {
                 self.print_help();
             }
             Some("quit") | Some("exit") | Some("q") => {
+                if self.db.in_transaction() && !self.confirm_rollback()? {
+                    return Ok(false);
+                }
                 return Ok(true);
             }
+            Some("tx") => {
+                println!("{}", self.transaction_status());
+            }
             Some("tables") | Some("dt") => {
                 self.list_tables()?;
             }
@@ -188,10 +251,47 @@ impl Repl {
                     }
                 }
             }
+            Some("dump") => {
+                match parts.get(1) {
+                    Some(path) => {
+                        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+                        self.db.dump(&mut file)?;
+                        println!("Dumped database to {}", path);
+                    }
+                    None => self.db.dump(&mut std::io::stdout().lock())?,
+                }
+            }
+            Some("blame") => match (parts.get(1), parts.get(2)) {
+                (Some(table), Some(key)) => {
+                    let commit = self.db.blame(table, key)?;
+                    let when = chrono::DateTime::from_timestamp(commit.timestamp, 0)
+                        .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                        .unwrap_or_default();
+                    let subject = commit.message.lines().next().unwrap_or_default();
+                    println!("{} {} {}", &commit.id[..8.min(commit.id.len())], commit.author, when);
+                    println!("    {}", subject);
+                }
+                _ => eprintln!("Usage: .blame <table> <key>"),
+            },
+            Some("gc") => {
+                let report = self.db.gc()?;
+                println!("gc: {}", report);
+            }
+            Some("fold-names") => {
+                let renamed = self.db.fold_identifiers()?;
+                for (old, new) in &renamed {
+                    println!("Renamed {} to {}", old, new);
+                }
+                println!("{} names folded to lower case", renamed.len());
+            }
             Some("clear") => {
                 // Clear screen (ANSI escape).
                 print!("\x1B[2J\x1B[H");
             }
+            Some("expanded") | Some("x") => {
+                self.config.expanded = !self.config.expanded;
+                println!("Expanded display: {}", if self.config.expanded { "on" } else { "off" });
+            }
             Some("timing") => {
                 self.config.timing = !self.config.timing;
                 println!("Timing: {}", if self.config.timing { "on" } else { "off" });
@@ -209,26 +309,73 @@ impl Repl {
     fn print_help(&self) {
         println!("Commands:");
         println!("  .help, .h, .?           Show this help message");
-        println!("  .quit, .exit, .q        Exit the REPL");
+        println!("  .quit, .exit, .q        Exit the REPL (rolling back an open transaction)");
+        println!("  .tx                     Show the open transaction, if any");
         println!("  .tables, .dt            List all tables");
         println!("  .schema <table>         Show table schema");
         println!("  .stats                  Show database statistics");
         println!("  .history                Show command history");
         println!("  .explain <sql>          Show query execution plan");
+        println!("  .dump [file]            Write the database as SQL to stdout or a file");
+        println!("  .blame <table> <key>    Show the last commit that changed a row");
+        println!("  .gc                     Pack the object store to reclaim disk space");
+        println!("  .fold-names             Lower-case mixed-case table and column names");
         println!("  .timing                 Toggle timing display");
+        println!("  .expanded, .x           Toggle vertical (one field per line) display");
         println!("  .clear                  Clear the screen");
         println!();
+        println!("Editing:");
+        println!("  Up/Down                 Browse history (saved to ~/.gitdb_history)");
+        println!("  Ctrl-R                  Reverse search history");
+        println!("  Ctrl-C                  Discard the current statement");
+        println!("  Ctrl-D                  Exit");
+        println!();
         println!("SQL Statements:");
         println!("  CREATE TABLE name (columns...)");
         println!("  DROP TABLE name");
-        println!("  INSERT INTO table (cols) VALUES (vals)");
+        println!("  ALTER TABLE name RENAME TO new_name");
+        println!("  ALTER TABLE name RENAME COLUMN old TO new");
+        println!("  INSERT [OR IGNORE | OR REPLACE] INTO table (cols) VALUES (vals)");
         println!("  SELECT cols FROM table [WHERE ...] [ORDER BY ...] [LIMIT ...]");
         println!("  UPDATE table SET col=val [WHERE ...]");
         println!("  DELETE FROM table [WHERE ...]");
         println!("  BEGIN / COMMIT / ROLLBACK");
+        println!("  SHOW TABLES [LIKE 'pattern']");
+        println!("  SHOW COLUMNS FROM table [LIKE 'pattern']");
         println!();
     }
 
+    /// Ask before exiting throws away an open transaction, and roll it
+    /// back if that's fine.
+    fn confirm_rollback(&mut self) -> DatabaseResult<bool> {
+        println!("A transaction is open; exiting rolls it back.");
+        let answer = match self.editor.readline("Roll back and exit? [y/N] ") {
+            Ok(answer) => answer,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(false),
+            Err(e) => return Err(editor_error(e)),
+        };
+        if !answer.trim().eq_ignore_ascii_case("y") && !answer.trim().eq_ignore_ascii_case("yes") {
+            return Ok(false);
+        }
+        self.db.execute("ROLLBACK")?;
+        Ok(true)
+    }
+
+    /// What `.tx` prints: the open transaction's id, branch, isolation
+    /// level and start time.
+    fn transaction_status(&self) -> String {
+        match self.db.current_transaction() {
+            Some(tx) => format!(
+                "Transaction: {}\nBranch: {}\nIsolation: {}\nStarted: {}",
+                tx.id(),
+                tx.branch(),
+                tx.isolation(),
+                tx.started_at().format("%Y-%m-%d %H:%M:%S UTC"),
+            ),
+            None => "No transaction in progress.".to_string(),
+        }
+    }
+
     fn list_tables(&self) -> DatabaseResult<()> {
         let tables = self.db.tables()?;
         if tables.is_empty() {
@@ -246,8 +393,8 @@ impl Repl {
         match self.db.table_schema(name)? {
             Some(schema) => {
                 println!("Table: {}", schema.name);
-                if let Some(pk) = &schema.primary_key {
-                    println!("Primary Key: {}", pk);
+                if !schema.primary_key.is_empty() {
+                    println!("Primary Key: {}", schema.primary_key.join(", "));
                 }
                 println!("\nColumns:");
                 println!("{:<20} {:<15} {:<10}", "Name", "Type", "Nullable");
@@ -265,17 +412,22 @@ impl Repl {
     }
 
     fn print_stats(&self) {
-        let stats = self.db.stats();
+        let stats = self.db.stats_with_commits();
         println!("Database Statistics:");
         println!("  Tables: {}", stats.tables);
         println!("  Total Rows: {}", stats.total_rows);
         println!("  Size: {} bytes", stats.total_size_bytes);
         println!("  Active Transactions: {}", stats.active_transactions);
+        if let Some(commits) = stats.total_commits {
+            println!("  Commits: {}", commits);
+        }
+        println!("  Loose Objects: {}", stats.loose_objects);
+        println!("  Packed Objects: {} ({} bytes in packs)", stats.packed_objects, stats.pack_size_bytes);
     }
 
     fn print_history(&self) {
         println!("Command History:");
-        for (i, cmd) in self.history.iter().enumerate() {
+        for (i, cmd) in self.editor.history().iter().enumerate() {
             println!("  {}: {}", i + 1, cmd);
         }
     }
@@ -298,66 +450,142 @@ impl Repl {
     }
 
     fn print_result_set(&self, rs: &crate::executor::ResultSet) {
-        if rs.is_empty() {
+        // Headers are still worth showing when the columns are known
+        if rs.is_empty() && (rs.columns.is_empty() || self.config.expanded) {
             println!("(0 rows)");
             return;
         }
 
-        // Get column names from first row.
-        let columns: Vec<&String> = rs.rows[0].keys().collect();
-        
-        // Calculate column widths.
-        let mut widths: Vec<usize> = columns.iter().map(|c| c.len()).collect();
-        for row in &rs.rows {
-            for (i, col) in columns.iter().enumerate() {
-                if let Some(val) = row.get(*col) {
-                    let len = format_value(val).len();
-                    widths[i] = widths[i].max(len);
-                }
-            }
+        if self.config.expanded {
+            let _ = write_vertical(&mut std::io::stdout().lock(), rs, self.config.max_rows);
+            return;
         }
 
-        // Print header.
-        let header: Vec<String> = columns.iter()
-            .enumerate()
-            .map(|(i, c)| format!("{:width$}", c, width = widths[i]))
-            .collect();
-        println!("{}", header.join(" | "));
-        
-        // Print separator.
-        let sep: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
-        println!("{}", sep.join("-+-"));
-
-        // Print rows.
-        let limit = rs.len().min(self.config.max_rows);
-        for row in rs.rows.iter().take(limit) {
-            let values: Vec<String> = columns.iter()
-                .enumerate()
-                .map(|(i, col)| {
-                    let val = row.get(*col).map(format_value).unwrap_or_default();
-                    format!("{:width$}", val, width = widths[i])
+        print!("{}", render_table(rs, self.config.max_rows, self.config.colors));
+    }
+}
+
+/// Widest a column may be before its values are truncated.
+const MAX_COLUMN_WIDTH: usize = 40;
+
+/// Render up to `max_rows` rows as a bordered table followed by the row count.
+fn render_table(rs: &crate::executor::ResultSet, max_rows: usize, colors: bool) -> String {
+    let columns: Vec<&str> = if rs.columns.is_empty() {
+        rs.rows[0].keys().map(|k| k.as_str()).collect()
+    } else {
+        rs.columns.iter().map(|c| c.as_str()).collect()
+    };
+    let limit = rs.len().min(max_rows);
+
+    // Cell text, truncated to the column cap, for the displayed rows only.
+    let cells: Vec<Vec<Option<String>>> = rs.rows[..limit]
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|col| match row.get(*col) {
+                    None | Some(serde_json::Value::Null) => None,
+                    Some(val) => Some(truncate(&format_value(val), MAX_COLUMN_WIDTH)),
                 })
-                .collect();
-            println!("{}", values.join(" | "));
+                .collect()
+        })
+        .collect();
+
+    let headers: Vec<String> = columns.iter().map(|c| truncate(c, MAX_COLUMN_WIDTH)).collect();
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &cells {
+        for (i, cell) in row.iter().enumerate() {
+            let len = cell.as_ref().map_or(4, |c| c.chars().count());
+            widths[i] = widths[i].max(len);
         }
+    }
+
+    let border = |left: &str, mid: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+        format!("{}{}{}\n", left, segments.join(mid), right)
+    };
 
-        // Print row count.
-        if rs.len() > limit {
-            println!("... ({} more rows)", rs.len() - limit);
+    let mut out = border("┌", "┬", "┐");
+    out.push('│');
+    for (header, width) in headers.iter().zip(&widths) {
+        out.push_str(&format!(" {} │", pad(header, *width)));
+    }
+    out.push('\n');
+    out.push_str(&border("├", "┼", "┤"));
+    for row in &cells {
+        out.push('│');
+        for (cell, width) in row.iter().zip(&widths) {
+            let text = match cell {
+                Some(text) => pad(text, *width),
+                None if colors => format!("\x1b[2mNULL\x1b[0m{}", " ".repeat(width - 4)),
+                None => pad("NULL", *width),
+            };
+            out.push_str(&format!(" {} │", text));
         }
-        println!("({} rows)", rs.len());
+        out.push('\n');
     }
+    out.push_str(&border("└", "┴", "┘"));
+
+    if rs.len() > limit {
+        out.push_str(&format!("... ({} more rows)\n", rs.len() - limit));
+    }
+    out.push_str(&format!("({} rows)\n", rs.len()));
+    out
 }
 
-fn format_value(value: &serde_json::Value) -> String {
-    match value {
-        serde_json::Value::Null => "NULL".to_string(),
-        serde_json::Value::Bool(b) => b.to_string(),
-        serde_json::Value::Number(n) => n.to_string(),
-        serde_json::Value::String(s) => s.clone(),
-        serde_json::Value::Array(a) => format!("{:?}", a),
-        serde_json::Value::Object(o) => format!("{:?}", o),
+/// Shorten `text` to at most `max` characters, ending in an ellipsis if cut.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
     }
+    let mut cut: String = text.chars().take(max - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// Left-align `text` in a field of `width` characters.
+fn pad(text: &str, width: usize) -> String {
+    format!("{:<width$}", text, width = width)
+}
+
+/// Make Ctrl-C cancel the running statement instead of ending the process.
+///
+/// At the prompt the editor reads Ctrl-C as a key, so this only fires while
+/// a statement runs.
+#[cfg(unix)]
+fn cancel_on_interrupt(token: CancelToken) {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        // Never waits on the lock: the interrupted thread may hold it
+        if let Some(token) = INTERRUPT.try_lock() {
+            if let Some(token) = token.as_ref() {
+                token.cancel();
+            }
+        }
+    }
+
+    *INTERRUPT.lock() = Some(token);
+    let handler: extern "C" fn(libc::c_int) = on_interrupt;
+    // SAFETY: the handler only makes atomic operations
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn cancel_on_interrupt(_token: CancelToken) {}
+
+/// A line editor failure, reported as the terminal I/O error it is.
+fn editor_error(e: ReadlineError) -> DatabaseError {
+    match e {
+        ReadlineError::Io(e) => DatabaseError::Io(e),
+        other => DatabaseError::Io(io::Error::other(other)),
+    }
+}
+
+/// Whether a statement may change the set of tables or columns.
+fn is_ddl(sql: &str) -> bool {
+    let first = sql.split_whitespace().next().unwrap_or("").to_uppercase();
+    matches!(first.as_str(), "CREATE" | "DROP" | "ALTER")
 }
 
 #[cfg(test)]
@@ -371,4 +599,59 @@ mod tests {
         assert_eq!(format_value(&serde_json::json!(42)), "42");
         assert_eq!(format_value(&serde_json::json!("hello")), "hello");
     }
+
+    #[test]
+    fn test_render_table() {
+        let mut alice = std::collections::BTreeMap::new();
+        alice.insert("name".to_string(), serde_json::json!("Alice"));
+        alice.insert("email".to_string(), serde_json::Value::Null);
+        let mut bob = std::collections::BTreeMap::new();
+        bob.insert("name".to_string(), serde_json::json!("b".repeat(50)));
+        bob.insert("email".to_string(), serde_json::json!("bob@example.com"));
+        let rs = crate::executor::ResultSet {
+            columns: vec!["name".into(), "email".into()],
+            rows: vec![alice, bob],
+        };
+
+        let long = format!("{}…", "b".repeat(MAX_COLUMN_WIDTH - 1));
+        let expected = format!(
+            "\
+┌─{bar}─┬─────────────────┐
+│ {name} │ email           │
+├─{bar}─┼─────────────────┤
+│ {alice} │ NULL            │
+│ {long} │ bob@example.com │
+└─{bar}─┴─────────────────┘
+(2 rows)
+",
+            bar = "─".repeat(MAX_COLUMN_WIDTH),
+            name = pad("name", MAX_COLUMN_WIDTH),
+            alice = pad("Alice", MAX_COLUMN_WIDTH),
+        );
+        assert_eq!(render_table(&rs, 100, false), expected);
+
+        let limited = render_table(&rs, 1, true);
+        assert!(limited.contains("\x1b[2mNULL\x1b[0m"));
+        assert!(limited.ends_with("... (1 more rows)\n(2 rows)\n"));
+    }
+
+    #[test]
+    fn test_transaction_prompt() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let config = ReplConfig { history_file: None, ..ReplConfig::default() };
+        let mut repl = Repl::with_config(db, config).unwrap();
+
+        assert_eq!(repl.prompt(), "gitdb> ");
+        assert_eq!(repl.transaction_status(), "No transaction in progress.");
+
+        repl.db.execute("BEGIN").unwrap();
+        assert_eq!(repl.prompt(), "gitdb*> ");
+        let tx_id = repl.db.current_transaction().unwrap().id().to_string();
+        let status = repl.transaction_status();
+        assert!(status.starts_with(&format!("Transaction: {}\nBranch: tx/{}", tx_id, tx_id)), "{}", status);
+
+        repl.db.execute("ROLLBACK").unwrap();
+        assert_eq!(repl.prompt(), "gitdb> ");
+    }
 }
@@ -0,0 +1,84 @@
+//! Change feed: follow the commits made to main.
+//!
+//! Every change to the data is a commit on main, so a [`Watch`] only has to
+//! poll main's head and report the commits since the last one it saw. Each
+//! reported commit can be diffed against its parent with
+//! [`Database::changes`](super::Database::changes) to find the rows it touched.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use parking_lot::RwLock;
+
+use super::api::{CommitInfo, DatabaseResult};
+use crate::storage::{CommitId, GitRepository};
+
+/// How long a blocking [`Watch`] waits between looks at main.
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Commits made to main after a starting point, oldest first.
+///
+/// [`poll`](Self::poll) returns whatever is new without waiting. As an
+/// iterator, a watch blocks until there is a new commit, checking every
+/// [`interval`](Self::with_interval), and never ends on its own.
+pub struct Watch {
+    repo: Arc<RwLock<GitRepository>>,
+    last: CommitId,
+    pending: VecDeque<CommitInfo>,
+    interval: Duration,
+}
+
+impl Watch {
+    pub(crate) fn new(repo: Arc<RwLock<GitRepository>>, since: CommitId) -> Self {
+        Self {
+            repo,
+            last: since,
+            pending: VecDeque::new(),
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    /// Set how often a blocking watch checks main for new commits.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The last commit reported, or the starting point if none has been.
+    ///
+    /// A new watch from here picks up where this one left off.
+    pub fn position(&self) -> CommitId {
+        self.last
+    }
+
+    /// The commits made to main since the last call, without waiting.
+    pub fn poll(&mut self) -> DatabaseResult<Vec<CommitInfo>> {
+        let mut commits: Vec<CommitInfo> = self.pending.drain(..).collect();
+        let repo = self.repo.read();
+        let head = repo.head()?;
+        if head != self.last {
+            commits.extend(repo.commits_since(self.last, head)?.into_iter().map(CommitInfo::from));
+            self.last = head;
+        }
+        Ok(commits)
+    }
+}
+
+impl Iterator for Watch {
+    type Item = DatabaseResult<CommitInfo>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(commit) = self.pending.pop_front() {
+                return Some(Ok(commit));
+            }
+            match self.poll() {
+                Ok(commits) if commits.is_empty() => thread::sleep(self.interval),
+                Ok(commits) => self.pending.extend(commits),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
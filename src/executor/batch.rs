@@ -0,0 +1,72 @@
+//! Coalescing many small writes into one commit.
+//!
+//! With a [`BatchConfig`], INSERT, UPDATE and DELETE outside a transaction
+//! don't each move main. The first one starts a [`Batch`]: a
+//! [`detached`](crate::storage::GitRepository::detached) handle whose head is
+//! kept in memory, so later statements of the session, reads included, see
+//! the buffered writes. The batch is flushed to main as a single commit once
+//! it holds `max_statements` writes or is `window` old, or on
+//! [`QueryExecutor::flush`](super::QueryExecutor::flush).
+//!
+//! Until then only the ref `refs/batch/<name>` refers to the buffered
+//! writes. It keeps gc from pruning them and history rewrites from
+//! discarding their base, but nothing reads through it: a crash loses the
+//! unflushed window, and other sessions don't see it.
+
+use std::time::{Duration, Instant};
+
+use crate::storage::CommitId;
+
+/// When a session's buffered writes are flushed to main.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchConfig {
+    /// Most write statements buffered before they are flushed.
+    pub max_statements: usize,
+    /// Longest a write is buffered before the next statement flushes it.
+    pub window: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_statements: 1_000,
+            window: Duration::from_millis(100),
+        }
+    }
+}
+
+impl BatchConfig {
+    /// Set the most write statements buffered.
+    pub fn with_max_statements(mut self, max_statements: usize) -> Self {
+        self.max_statements = max_statements;
+        self
+    }
+
+    /// Set the longest a write is buffered.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+}
+
+/// Writes buffered since the last flush.
+pub(crate) struct Batch {
+    /// Main's head when the batch started, the parent of its commit.
+    pub(crate) base: CommitId,
+    /// Name of the ref under `refs/batch/` pinning the batch's head.
+    pub(crate) name: String,
+    /// Write statements buffered.
+    pub(crate) statements: usize,
+    started: Instant,
+}
+
+impl Batch {
+    pub(crate) fn new(base: CommitId, name: String) -> Self {
+        Self { base, name, statements: 0, started: Instant::now() }
+    }
+
+    /// Whether the batch should be flushed before it takes another write.
+    pub(crate) fn is_due(&self, config: &BatchConfig) -> bool {
+        self.statements >= config.max_statements.max(1) || self.started.elapsed() >= config.window
+    }
+}
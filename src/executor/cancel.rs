@@ -0,0 +1,84 @@
+//! Stopping a statement part way through.
+//!
+//! Scans, joins and the row loops of INSERT, UPDATE and DELETE check a
+//! [`CancelToken`] as they go and give up with
+//! [`ExecuteError::Cancelled`] once it is cancelled or its deadline passes.
+//! A write that stops early never moves main, so nothing it did is kept.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::error::{ExecuteError, ExecuteResult};
+
+/// A flag that cancels the statement running on a
+/// [`QueryExecutor`](super::QueryExecutor), shared by every clone.
+///
+/// Cancelling is a single atomic store, so it is safe from another thread
+/// or a signal handler. Each statement clears the flag as it starts.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    /// Create a token that isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop the running statement at its next check.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the statement has been cancelled or is past its deadline.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// The same flag, also cancelled once `deadline` passes.
+    pub(crate) fn until(&self, deadline: Option<Instant>) -> Self {
+        Self {
+            cancelled: self.cancelled.clone(),
+            deadline,
+        }
+    }
+
+    /// Clear the flag for the next statement.
+    pub(crate) fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    /// Fail with [`ExecuteError::Cancelled`] if the statement should stop.
+    pub(crate) fn check(&self) -> ExecuteResult<()> {
+        if self.is_cancelled() {
+            return Err(ExecuteError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cancel_token() {
+        let token = CancelToken::new();
+        let statement = token.until(None);
+        assert!(statement.check().is_ok());
+
+        // Clones share the flag
+        token.cancel();
+        assert!(matches!(statement.check(), Err(ExecuteError::Cancelled)));
+        token.reset();
+        assert!(!statement.is_cancelled());
+
+        let late = token.until(Some(Instant::now() - Duration::from_millis(1)));
+        assert!(late.is_cancelled());
+        assert!(!token.is_cancelled());
+    }
+}
@@ -3,7 +3,8 @@
 use thiserror::Error;
 
 use crate::catalog::SchemaError;
-use crate::sql::ParseError;
+use crate::planner::PlanError;
+use crate::sql::{EvalError, ParseError};
 use crate::storage::{InvalidNameError, StorageError};
 use crate::transaction::TransactionError;
 
@@ -19,6 +20,9 @@ pub enum ExecuteError {
     #[error("storage error: {0}")]
     Storage(#[from] StorageError),
 
+    #[error("plan error: {0}")]
+    Plan(#[from] PlanError),
+
     #[error("schema error: {0}")]
     Schema(#[from] SchemaError),
 
@@ -46,22 +50,93 @@ pub enum ExecuteError {
     #[error("missing required column: {0}")]
     MissingColumn(String),
 
+    #[error("VALUES row {row} has {actual} values for {expected} columns")]
+    ValuesArity { row: usize, expected: usize, actual: usize },
+
+    #[error("row violates foreign key '{0}'")]
+    ForeignKeyViolation(String),
+
+    #[error("invalid parameter: {0}")]
+    InvalidParameter(String),
+
     #[error("invalid expression: {0}")]
     InvalidExpression(String),
 
+    #[error("scalar subquery returned {0} rows, expected at most one")]
+    SubqueryRows(usize),
+
     #[error("division by zero")]
     DivisionByZero,
 
+    #[error("numeric overflow")]
+    NumericOverflow,
+
+    #[error("query returned more than {0} rows; add a LIMIT to read more")]
+    ResultTooLarge(usize),
+
+    #[error("query cancelled")]
+    Cancelled,
+
+    #[error("spilling rows to disk failed: {0}")]
+    Spill(#[source] std::io::Error),
+
     #[error("no active transaction")]
     NoTransaction,
 
+    #[error("unsupported isolation level: {0}")]
+    UnsupportedIsolation(String),
+
     #[error("internal error: {0}")]
     Internal(String),
 }
 
+impl From<EvalError> for ExecuteError {
+    fn from(e: EvalError) -> Self {
+        match e {
+            EvalError::ColumnNotFound(column) => ExecuteError::ColumnNotFound(column),
+            EvalError::InvalidExpression(msg) => ExecuteError::InvalidExpression(msg),
+            EvalError::DivisionByZero => ExecuteError::DivisionByZero,
+            EvalError::NumericOverflow => ExecuteError::NumericOverflow,
+        }
+    }
+}
+
 impl ExecuteError {
     /// Check if error is retryable.
     pub fn is_retryable(&self) -> bool {
         matches!(self, ExecuteError::Transaction(t) if t.is_retryable())
     }
+
+    /// A short name for the kind of error, stable across releases.
+    ///
+    /// Wrapped errors report their own kind.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ExecuteError::Parse(e) => e.kind(),
+            ExecuteError::Storage(e) => e.kind(),
+            ExecuteError::Plan(e) => e.kind(),
+            ExecuteError::Schema(e) => e.kind(),
+            ExecuteError::Transaction(e) => e.kind(),
+            ExecuteError::InvalidName(_) => "invalid_name",
+            ExecuteError::TableNotFound(_) => "table_not_found",
+            ExecuteError::ColumnNotFound(_) => "column_not_found",
+            ExecuteError::TypeMismatch { .. } => "type_mismatch",
+            ExecuteError::NullValue(_) => "null_value",
+            ExecuteError::DuplicateKey(_) => "duplicate_key",
+            ExecuteError::MissingColumn(_) => "missing_column",
+            ExecuteError::ValuesArity { .. } => "values_arity",
+            ExecuteError::ForeignKeyViolation(_) => "foreign_key_violation",
+            ExecuteError::InvalidParameter(_) => "invalid_parameter",
+            ExecuteError::InvalidExpression(_) => "invalid_expression",
+            ExecuteError::SubqueryRows(_) => "subquery_rows",
+            ExecuteError::DivisionByZero => "division_by_zero",
+            ExecuteError::NumericOverflow => "numeric_overflow",
+            ExecuteError::ResultTooLarge(_) => "result_too_large",
+            ExecuteError::Cancelled => "cancelled",
+            ExecuteError::Spill(_) => "spill_failed",
+            ExecuteError::NoTransaction => "no_transaction",
+            ExecuteError::UnsupportedIsolation(_) => "unsupported_isolation",
+            ExecuteError::Internal(_) => "internal",
+        }
+    }
 }
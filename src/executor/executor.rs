@@ -1,43 +1,247 @@
 //! Main query executor.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use parking_lot::RwLock;
 use serde_json::Value;
+use ulid::Ulid;
 
+use super::batch::{Batch, BatchConfig};
+use super::cancel::CancelToken;
 use super::error::{ExecuteError, ExecuteResult};
-use super::eval::evaluate;
-use super::operators::{FilterOperator, LimitOperator, Operator, ProjectOperator, Row, ScanOperator, SortOperator};
-use super::result::{QueryResult, ResultSet};
-use crate::catalog::{Catalog, ColumnDef, Constraint, DataType, SchemaBuilder};
+use super::metrics::{Metrics, QueryMetrics};
+use crate::sql::decimal::Decimal;
+use crate::sql::eval::{evaluate, like_match, parse_timestamp, values_equal, Predicate};
+use super::operators::{
+    AggregateOperator, AppendOperator, DistinctOperator, FilterOperator, JoinCondition, JoinKind, JoinOperator,
+    LimitOperator, Operator, ProjectOperator, QualifyOperator, Row, ScanOperator, SortOperator,
+    VERSION_COLUMN, scanned_row,
+};
+use super::result::{Page, QueryResult, ResultSet, VacuumReport};
+use super::spill::SpillConfig;
+use crate::catalog::{
+    Catalog, CheckConstraint, ColumnDef, Constraint, DataType, ForeignKey, SchemaBuilder, SchemaError,
+    TableSchema, UniqueConstraint, SCHEMALESS_KEY, SEQUENCE_DIR,
+};
+use crate::planner::{
+    AggregatePhysical, JoinPhysicalType, PhysicalAggregate, PhysicalOperator, PhysicalPlanNode, PlanError,
+    QueryPlanner, SortDirection,
+};
 use crate::sql::{
-    Assignment, CreateTable, Delete, DropTable, Insert, Parser, Select,
-    SelectColumn, SqlDataType, Statement, Update,
+    AlterTable, AlterTableOperation, Assignment, BinaryOperator, CreateTable, Delete, DropTable, Expr, FromSource, Insert, InsertConflict, KeyStrategy, LiteralValue,
+    OrderBy, Parser, ReferentialAction, RowCount, Select, SelectColumn, SqlDataType, Statement, TableConstraint,
+    TransactionIsolation, Update,
+};
+use crate::storage::{
+    CommitId, CommitMessage, GitRepository, GitSignature, InvalidNameError, Row as StorageRow, RowKey, StorageError, TableName,
 };
-use crate::storage::{GitRepository, Row as StorageRow, RowKey, TableName};
-use crate::transaction::{Transaction, TransactionManager, TxActive};
+use crate::trace;
+use crate::transaction::{IsolationLevel, Transaction, TransactionManager, TxActive};
+
+/// Field of a table's [`SEQUENCE_DIR`] entry holding the last key handed out.
+const SEQUENCE_COLUMN: &str = "last";
 
 /// The query executor.
 pub struct QueryExecutor {
+    /// Where statements read and write: `main`, or the current
+    /// transaction's branch between BEGIN and COMMIT/ROLLBACK, or the
+    /// pending batch's detached head.
     repo: Arc<RwLock<GitRepository>>,
+    main: Arc<RwLock<GitRepository>>,
     catalog: Catalog,
     tx_manager: TransactionManager,
     current_tx: Option<Transaction<TxActive>>,
+    author: Option<GitSignature>,
+    note: Option<String>,
+    /// Level for a BEGIN that doesn't name one.
+    isolation: IsolationLevel,
+    /// Create unknown tables, schemaless, when they're inserted into.
+    schemaless: bool,
+    /// Most rows a SELECT without LIMIT may return.
+    max_result_rows: Option<usize>,
+    /// When sorts and aggregates spill rows to disk.
+    spill: SpillConfig,
+    metrics: QueryMetrics,
+    /// Cancels the running statement, see [`cancel_token`](Self::cancel_token).
+    cancel: CancelToken,
+    /// When the running statement gives up, if it has a deadline.
+    deadline: Option<Instant>,
+    /// When buffered writes are flushed, if writes are batched at all.
+    batching: Option<BatchConfig>,
+    /// Writes buffered since the last flush.
+    batch: Option<Batch>,
 }
 
 impl QueryExecutor {
     /// Create a new executor.
     pub fn new(repo: GitRepository) -> Self {
-        let shared_repo = Arc::new(RwLock::new(repo.clone()));
-        let catalog = Catalog::new(shared_repo.clone());
-        let tx_manager = TransactionManager::new(repo);
+        let tx_manager = TransactionManager::new(repo.clone());
+        Self::with_shared(Arc::new(RwLock::new(repo)), tx_manager)
+    }
+
+    /// Create an executor sharing its repository and transactions with others.
+    ///
+    /// Executors built from the same `repo` serialize their writes to main
+    /// on its lock; each still has its own current transaction.
+    pub(crate) fn with_shared(repo: Arc<RwLock<GitRepository>>, tx_manager: TransactionManager) -> Self {
+        let catalog = Catalog::new(repo.clone());
         Self {
-            repo: shared_repo,
+            main: repo.clone(),
+            repo,
             catalog,
             tx_manager,
             current_tx: None,
+            author: None,
+            note: None,
+            isolation: IsolationLevel::default(),
+            schemaless: false,
+            max_result_rows: None,
+            spill: SpillConfig::default(),
+            metrics: QueryMetrics::default(),
+            cancel: CancelToken::new(),
+            deadline: None,
+            batching: None,
+            batch: None,
+        }
+    }
+
+    /// Commit this session's changes as `author`, or with the repository's
+    /// own signature for `None`.
+    pub fn set_author(&mut self, author: Option<GitSignature>) {
+        self.catalog.set_author(author.clone());
+        self.author = author;
+    }
+
+    /// Run transactions at `isolation` unless BEGIN names another level.
+    pub fn set_isolation(&mut self, isolation: IsolationLevel) {
+        self.isolation = isolation;
+    }
+
+    /// The level BEGIN starts transactions at by default.
+    pub fn isolation(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    /// Let INSERT into an unknown table create it as a schemaless table,
+    /// see [`TableSchema::schemaless`].
+    pub fn set_schemaless(&mut self, schemaless: bool) {
+        self.schemaless = schemaless;
+    }
+
+    /// Fail a SELECT without LIMIT with [`ExecuteError::ResultTooLarge`] as
+    /// soon as it has more than `max` rows, instead of collecting them all.
+    ///
+    /// An explicit LIMIT, however large, opts a query out. `None` removes
+    /// the cap.
+    pub fn set_max_result_rows(&mut self, max: Option<usize>) {
+        self.max_result_rows = max;
+    }
+
+    /// Have sorts and aggregates that outgrow `config` spill rows to disk,
+    /// rather than hold every row in memory.
+    pub fn set_spill(&mut self, config: SpillConfig) {
+        self.spill = config;
+    }
+
+    /// Buffer INSERT, UPDATE and DELETE outside a transaction and commit
+    /// them to main together, as `config` says, or commit each one as it
+    /// runs for `None`, the default.
+    ///
+    /// Later statements of this session, reads included, see the buffered
+    /// writes; other sessions see them only once they are flushed, by
+    /// [`flush`](Self::flush) or by the first statement after the batch
+    /// fills up or its window passes. A statement that isn't a read or a
+    /// batched write, such as DDL or BEGIN, flushes first. Buffered writes
+    /// are lost if the process exits before they are flushed.
+    pub fn set_commit_batch(&mut self, config: Option<BatchConfig>) {
+        self.batching = config;
+    }
+
+    /// Commit the writes buffered by [`set_commit_batch`](Self::set_commit_batch)
+    /// to main as one commit, returning it, or `None` if nothing was buffered.
+    ///
+    /// If main has moved on since the batch started, the commit is merged
+    /// onto it. On a conflict the batch is discarded and the error returned.
+    pub fn flush(&mut self) -> ExecuteResult<Option<CommitId>> {
+        let Some(batch) = self.batch.take() else {
+            return Ok(None);
+        };
+        let head = self.repo.read().head();
+        self.use_repo(self.main.clone());
+        let lock = self.main.write();
+        let published = head.and_then(|head| {
+            self.author_of(&lock).publish_squashed(batch.base, head, &CommitMessage::batch(batch.statements))
+        });
+        // Landed or discarded, the batch needs no pinning any more
+        lock.release_batch(&batch.name)?;
+        let commit = published?;
+        if commit == batch.base {
+            return Ok(None);
+        }
+        self.metrics.record_commit();
+        Ok(Some(commit))
+    }
+
+    /// Flush the pending batch if it is due, or before a statement that
+    /// neither reads nor joins it; then start one for a batched write.
+    fn prepare_batch(&mut self, batched: bool, reads: bool) -> ExecuteResult<()> {
+        if let Some(batch) = &self.batch {
+            let due = self.batching.as_ref().is_none_or(|config| batch.is_due(config));
+            if due || !(batched || reads) {
+                self.flush()?;
+            }
+        }
+        if batched && self.batch.is_none() {
+            let main = self.main.read();
+            let base = main.head()?;
+            let name = Ulid::new().to_string().to_lowercase();
+            let detached = main.detached_batch(base, &name)?;
+            drop(main);
+            self.use_repo(Arc::new(RwLock::new(detached)));
+            self.batch = Some(Batch::new(base, name));
         }
+        Ok(())
+    }
+
+    /// A snapshot of this executor's counters, with the conflicts of its
+    /// transaction manager and the hits of its repository's read cache.
+    pub fn metrics(&self) -> Metrics {
+        let mut metrics = self.metrics.snapshot();
+        metrics.conflicts = self.tx_manager.conflicts();
+        metrics.cache = self.main.read().cache_stats();
+        metrics
+    }
+
+    /// Set every counter [`metrics`](Self::metrics) reports back to zero.
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+        self.tx_manager.reset_conflicts();
+        self.main.read().reset_cache_stats();
+    }
+
+    /// The author this session's changes are committed as.
+    pub fn author(&self) -> Option<&GitSignature> {
+        self.author.as_ref()
+    }
+
+    /// Add `note` to the commits of the next statement that changes data
+    /// or schema, see [`CommitMessage::with_note`](crate::storage::CommitMessage::with_note).
+    ///
+    /// The note is kept until such a statement succeeds.
+    pub fn annotate(&mut self, note: impl Into<String>) {
+        self.set_note(Some(note.into()));
+    }
+
+    /// Drop a note given to [`annotate`](Self::annotate) that hasn't been used.
+    pub fn clear_note(&mut self) {
+        self.set_note(None);
+    }
+
+    fn set_note(&mut self, note: Option<String>) {
+        self.catalog.set_note(note.clone());
+        self.note = note;
     }
 
     /// Execute a SQL string.
@@ -46,21 +250,388 @@ impl QueryExecutor {
         self.execute_statement(stmt)
     }
 
+    /// Execute a SQL string, failing with [`ExecuteError::Cancelled`] if it
+    /// runs longer than `timeout`.
+    ///
+    /// A write that times out commits nothing.
+    pub fn execute_with_deadline(&mut self, sql: &str, timeout: Duration) -> ExecuteResult<QueryResult> {
+        self.deadline = Some(Instant::now() + timeout);
+        let result = self.execute(sql);
+        self.deadline = None;
+        result
+    }
+
+    /// A token that cancels the statement this executor is running, from
+    /// another thread or a signal handler.
+    ///
+    /// It stays tied to the executor: cancelling with no statement running
+    /// has no effect, as each statement starts uncancelled.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// The token the running statement checks: cancelled by
+    /// [`cancel_token`](Self::cancel_token) or at its deadline.
+    fn statement_cancel(&self) -> CancelToken {
+        self.cancel.until(self.deadline)
+    }
+
+    /// Execute a SQL string, binding `params` to its `?`/`$n` placeholders.
+    ///
+    /// Placeholders are accepted for LIMIT and OFFSET, which must be bound
+    /// to non-negative integers.
+    pub fn execute_with_params(&mut self, sql: &str, params: &[Value]) -> ExecuteResult<QueryResult> {
+        let mut stmt = Parser::parse(sql)?;
+        bind_params(&mut stmt, params)?;
+        self.execute_statement(stmt)
+    }
+
+    /// Read up to `limit` rows of `table` in primary key order, starting
+    /// after the row whose primary key is `after` (from the start for `None`).
+    ///
+    /// This is keyset pagination, the same as
+    /// `SELECT * FROM table WHERE id > :after ORDER BY id LIMIT :limit`
+    /// but for composite keys too. Unlike OFFSET, rows inserted or deleted
+    /// between pages don't shift later pages. Pass [`Page::next`] back as
+    /// `after` to get the following page.
+    pub fn page_after(&mut self, table: &str, after: Option<&[Value]>, limit: usize) -> ExecuteResult<Page> {
+        let primary_key = self.catalog.get_table(table)?.primary_key;
+        if primary_key.is_empty() {
+            return Err(ExecuteError::Schema(SchemaError::InvalidPrimaryKey(format!(
+                "table '{}' has no primary key to paginate by",
+                table
+            ))));
+        }
+        let where_clause = match after {
+            Some(after) if after.len() != primary_key.len() => {
+                return Err(ExecuteError::InvalidParameter(format!(
+                    "page cursor has {} values, primary key of '{}' has {} columns",
+                    after.len(),
+                    table,
+                    primary_key.len()
+                )))
+            }
+            Some(after) => Some(keyset_after(&primary_key, after)),
+            None => None,
+        };
+
+        let select = Select {
+            columns: vec![SelectColumn::Wildcard],
+            from: FromSource::Table(table.to_string()),
+            where_clause,
+            group_by: Vec::new(),
+            having: None,
+            order_by: primary_key.iter()
+                .map(|column| OrderBy { column: column.clone(), ascending: true })
+                .collect(),
+            // One row more than asked for tells whether there is a next page
+            limit: Some(RowCount::Count(limit.saturating_add(1))),
+            offset: None,
+        };
+        let QueryResult::Select(mut rs) = self.execute_select(select)? else {
+            return Err(ExecuteError::Internal("page query did not return rows".into()));
+        };
+
+        let more = rs.rows.len() > limit;
+        rs.rows.truncate(limit);
+        let next = match rs.rows.last() {
+            Some(last) if more => Some(
+                primary_key.iter()
+                    .map(|column| last.get(column).cloned().unwrap_or(Value::Null))
+                    .collect(),
+            ),
+            _ => None,
+        };
+        Ok(Page { rows: rs.rows, next })
+    }
+
+    /// Read the document stored under `key` in `table`.
+    ///
+    /// Returns `None` if there is no such row, or no such table.
+    pub fn get_doc(&self, table: &str, key: &str) -> ExecuteResult<Option<Value>> {
+        let table_name = TableName::new(table)?;
+        let key = RowKey::from_parts(&[key])?;
+        let repo = self.repo.read();
+        match repo.read_row(&table_name, &key, repo.head()?) {
+            Ok(row) => Ok(row.map(|row| Value::Object(row.data.into_iter().collect()))),
+            Err(StorageError::TableNotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Insert `doc`, a JSON object, as a new row of `table`, as INSERT does.
+    ///
+    /// Its key comes from the primary key fields, or is generated when the
+    /// table has none. A field that isn't a column of a typed table is an
+    /// error rather than being stored alongside the columns.
+    pub fn insert_doc(&mut self, table: &str, doc: Value) -> ExecuteResult<()> {
+        // Commits at once, so anything batched before it has to land first
+        self.flush()?;
+        let Value::Object(doc) = doc else {
+            return Err(ExecuteError::InvalidParameter("document must be a JSON object".into()));
+        };
+        let schema = match self.table_schema(table)? {
+            Some(schema) => schema,
+            None if self.schemaless => {
+                let schema = TableSchema::schemaless(table);
+                self.catalog.create_table(schema.clone())?;
+                schema
+            }
+            None => return Err(SchemaError::TableNotFound(table.to_string()).into()),
+        };
+        let mut data = BTreeMap::new();
+        for (field, value) in doc {
+            match schema.get_column(&field) {
+                Some(col) => {
+                    let value = col.coerce(value);
+                    data.insert(field, value);
+                }
+                None if schema.schemaless => {
+                    data.insert(field, value);
+                }
+                None => return Err(SchemaError::ColumnNotFound(format!("{}.{}", table, field)).into()),
+            }
+        }
+        let parents = self.parent_schemas(&schema)?;
+        let table_name = TableName::new(table)?;
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
+        let head = insert_data(&repo, &table_name, &schema, &parents, data, None, repo.head()?)?;
+        repo.advance_head(head)?;
+        drop(lock);
+        self.set_note(None);
+        Ok(())
+    }
+
+    /// Store `doc`, a JSON object, under `key` in `table`, replacing any
+    /// document already there.
+    ///
+    /// A table created with CREATE TABLE checks the document against its
+    /// schema. An unknown table is created as a schemaless one, see
+    /// [`TableSchema::schemaless`], which takes any object. Either way `key`
+    /// must be the document's primary key; a document for a schemaless
+    /// table without an `id` is given `key` as its `id`.
+    pub fn put_doc(&mut self, table: &str, key: &str, doc: Value) -> ExecuteResult<()> {
+        self.flush()?;
+        let Value::Object(doc) = doc else {
+            return Err(ExecuteError::InvalidParameter("document must be a JSON object".into()));
+        };
+        // A new table is only created once the document has been checked
+        let (schema, created) = match self.table_schema(table)? {
+            Some(schema) => (schema, false),
+            None => (TableSchema::schemaless(table), true),
+        };
+        let table_name = TableName::new(table)?;
+        let key = RowKey::from_parts(&[key])?;
+
+        let mut data: BTreeMap<String, Value> = doc.into_iter().collect();
+        if schema.schemaless {
+            data.entry(SCHEMALESS_KEY.to_string()).or_insert_with(|| Value::from(key.as_str()));
+        }
+        for (column, value) in data.iter_mut() {
+            if let Some(col) = schema.get_column(column) {
+                *value = col.coerce(std::mem::take(value));
+            }
+        }
+        let with_defaults = schema.apply_defaults(&Value::Object(data.into_iter().collect()))?;
+        schema.validate_row(&with_defaults)?;
+        let data: BTreeMap<String, Value> = with_defaults.as_object().into_iter().flatten()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if !schema.primary_key.is_empty() && primary_row_key(&schema.primary_key, &data)? != key {
+            return Err(ExecuteError::InvalidParameter(format!(
+                "key '{}' does not match the document's primary key",
+                key.as_str()
+            )));
+        }
+
+        if created {
+            self.catalog.create_table(schema.clone())?;
+        }
+        let parents = self.parent_schemas(&schema)?;
+        let schemas = self.referencing_schemas(table)?;
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
+        let mut head = repo.head()?;
+        for (fk, parent) in &parents {
+            check_parent_exists(&repo, head, fk, parent, &data)?;
+        }
+
+        let (row, old) = match repo.read_row(&table_name, &key, head)? {
+            Some(existing) => {
+                let old = existing.data.clone();
+                (existing.with_update(data.clone()), Some(old))
+            }
+            None => (StorageRow::new(key, data.clone()), None),
+        };
+        head = repo.upsert_row(&table_name, row, head, None)?;
+        if let Some(old) = old {
+            head = update_referenced(&repo, &schemas, table, &old, &data, head)?;
+        }
+        repo.advance_head(head)?;
+        drop(lock);
+        self.set_note(None);
+        Ok(())
+    }
+
+    /// Delete the document stored under `key` in `table`.
+    ///
+    /// Returns whether there was one. Foreign keys referencing a typed
+    /// table's row get their ON DELETE actions, as with DELETE.
+    pub fn delete_doc(&mut self, table: &str, key: &str) -> ExecuteResult<bool> {
+        self.flush()?;
+        let schemas = match self.table_schema(table)? {
+            Some(_) => self.referencing_schemas(table)?,
+            None => Vec::new(),
+        };
+        let table_name = TableName::new(table)?;
+        let key = RowKey::from_parts(&[key])?;
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
+        let head = repo.head()?;
+
+        let row = match repo.read_row(&table_name, &key, head) {
+            Ok(Some(row)) => row,
+            Ok(None) | Err(StorageError::TableNotFound(_)) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let head = if schemas.is_empty() {
+            repo.delete_row(&table_name, &key, head, None)?
+        } else {
+            match delete_referenced(&repo, &schemas, table, &row, head)? {
+                Some(head) => head,
+                None => return Ok(false),
+            }
+        };
+        repo.advance_head(head)?;
+        drop(lock);
+        self.set_note(None);
+        Ok(true)
+    }
+
+    /// The schema of `table`, or `None` if it was never given one.
+    fn table_schema(&self, table: &str) -> ExecuteResult<Option<TableSchema>> {
+        match self.catalog.get_table(table) {
+            Ok(schema) => Ok(Some(schema)),
+            Err(SchemaError::TableNotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Pack the object store; with `full`, first replace main's history
+    /// with a single commit holding its current tree.
+    ///
+    /// FULL discards history for good, see [`GitRepository::vacuum`]: old
+    /// commits are pruned once past [`PRUNE_GRACE`](crate::storage::PRUNE_GRACE).
+    /// It is refused while a tag points into that history, or while any
+    /// transaction is open, this session's included.
+    pub fn vacuum(&mut self, full: bool) -> ExecuteResult<VacuumReport> {
+        self.flush()?;
+        let lock = self.repo.write();
+        let root = if full {
+            Some(self.writer(&lock).vacuum()?)
+        } else {
+            None
+        };
+        let gc = if full { lock.gc_prune()? } else { lock.gc()? };
+        Ok(VacuumReport { root, gc })
+    }
+
     /// Execute a parsed statement.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "execute",
+            skip_all,
+            fields(kind = stmt.kind(), rows = tracing::field::Empty, commit = tracing::field::Empty),
+            err(Display),
+        )
+    )]
     pub fn execute_statement(&mut self, stmt: Statement) -> ExecuteResult<QueryResult> {
-        match stmt {
+        let started = Instant::now();
+        self.cancel.reset();
+        let kind = stmt.kind();
+        let commits = matches!(stmt, Statement::Commit);
+        let writes = matches!(
+            stmt,
+            Statement::CreateTable(_)
+                | Statement::DropTable(_)
+                | Statement::AlterTable(_)
+                | Statement::Insert(_)
+                | Statement::Update(_)
+                | Statement::Delete(_)
+        );
+        let batched = self.batching.is_some()
+            && self.current_tx.is_none()
+            && self.note.is_none()
+            && matches!(stmt, Statement::Insert(_) | Statement::Update(_) | Statement::Delete(_));
+        let reads = matches!(
+            stmt,
+            Statement::Select(_)
+                | Statement::ShowTables { .. }
+                | Statement::ShowTransactions
+                | Statement::Describe(_)
+                | Statement::ShowColumns { .. }
+                | Statement::Explain { .. }
+        );
+        if let Err(e) = self.prepare_batch(batched, reads) {
+            self.metrics.record(kind, started.elapsed(), false);
+            return Err(e);
+        }
+        let result = match stmt {
             Statement::CreateTable(ct) => self.execute_create_table(ct),
             Statement::DropTable(dt) => self.execute_drop_table(dt),
+            Statement::AlterTable(at) => self.execute_alter_table(at),
             Statement::Select(s) => self.execute_select(s),
             Statement::Insert(i) => self.execute_insert(i),
             Statement::Update(u) => self.execute_update(u),
             Statement::Delete(d) => self.execute_delete(d),
-            Statement::Begin => self.execute_begin(),
+            Statement::Begin { isolation } => self.execute_begin(isolation),
             Statement::Commit => self.execute_commit(),
             Statement::Rollback => self.execute_rollback(),
-            Statement::ShowTables => self.execute_show_tables(),
-            Statement::Describe(table) => self.execute_describe(&table),
+            Statement::ShowTables { like } => self.execute_show_tables(like.as_deref()),
+            Statement::ShowTransactions => self.execute_show_transactions(),
+            Statement::Describe(table) => self.execute_describe(&table, None),
+            Statement::ShowColumns { table, like } => self.execute_describe(&table, like.as_deref()),
+            Statement::Explain { statement, analyze } => self.execute_explain(*statement, analyze),
+            Statement::Vacuum { full } => {
+                self.vacuum(full).map(|report| QueryResult::success(format!("VACUUM: {}", report)))
+            }
+            Statement::SetAuthor(author) => {
+                let author = author.map(|a| GitSignature::new(a.name, a.email));
+                self.set_author(author);
+                Ok(QueryResult::success("SET AUTHOR"))
+            }
+        };
+
+        if writes && result.is_ok() {
+            self.set_note(None);
+            trace::record("commit", || self.repo.read().head().map(|id| id.to_string()).unwrap_or_default());
+        }
+        let result = match result {
+            Ok(result) if batched => self.count_batched().map(|()| result),
+            result => result,
+        };
+        let committed = result.is_ok() && (commits || writes && !batched && self.current_tx.is_none());
+        self.metrics.record(kind, started.elapsed(), committed);
+        match &result {
+            Ok(QueryResult::Modified { rows_affected }) => trace::record("rows", || rows_affected),
+            Ok(QueryResult::Select(rs)) => trace::record("rows", || rs.len()),
+            _ => {}
         }
+        result
+    }
+
+    /// Count a write added to the pending batch, flushing it once full.
+    fn count_batched(&mut self) -> ExecuteResult<()> {
+        let Some(batch) = &mut self.batch else {
+            return Ok(());
+        };
+        batch.statements += 1;
+        if self.batching.as_ref().is_none_or(|config| batch.statements >= config.max_statements) {
+            self.flush()?;
+        }
+        Ok(())
     }
 
     fn execute_create_table(&mut self, ct: CreateTable) -> ExecuteResult<QueryResult> {
@@ -75,38 +646,126 @@ impl QueryExecutor {
             )));
         }
 
+        let multiple_keys = || {
+            ExecuteError::Schema(SchemaError::InvalidPrimaryKey(format!(
+                "multiple primary keys for table '{}'",
+                ct.name
+            )))
+        };
+        let table_key = ct.constraints.iter().find_map(|c| match c {
+            TableConstraint::PrimaryKey(columns) => Some(columns.clone()),
+            _ => None,
+        });
+
         // Convert SQL column defs to catalog column defs
-        let mut builder = SchemaBuilder::new(&ct.name);
+        let mut builder = SchemaBuilder::new(&ct.name).key_strategy(ct.key_strategy.unwrap_or_default());
+        let mut primary_key = Vec::new();
+        let mut foreign_keys = Vec::new();
+        let column_names: Vec<String> = ct.columns.iter().map(|c| c.name.clone()).collect();
         for col in ct.columns {
             let data_type = convert_sql_type(&col.data_type);
             let mut col_def = ColumnDef::new(&col.name, data_type);
+            let mut is_primary_key = false;
 
             for constraint in col.constraints {
                 let c = match constraint {
                     crate::sql::ColumnConstraint::NotNull => Constraint::NotNull,
                     crate::sql::ColumnConstraint::Unique => Constraint::Unique,
-                    crate::sql::ColumnConstraint::PrimaryKey => Constraint::PrimaryKey,
-                    crate::sql::ColumnConstraint::Default(expr) => {
-                        // Evaluate default expression
-                        let empty_row = serde_json::Map::new();
-                        let value = evaluate(&expr, &empty_row)?;
-                        Constraint::Default(value)
+                    crate::sql::ColumnConstraint::PrimaryKey => {
+                        is_primary_key = true;
+                        Constraint::PrimaryKey
+                    }
+                    // Stored unevaluated; apply_defaults evaluates it per insert
+                    crate::sql::ColumnConstraint::Default(expr) => Constraint::Default(expr),
+                    crate::sql::ColumnConstraint::Check { name, expr } => {
+                        // Postgres-style generated name: <table>_<column>_check
+                        let name = name.unwrap_or_else(|| format!("{}_{}_check", ct.name, col.name));
+                        Constraint::Check(CheckConstraint::new(name, expr))
+                    }
+                    crate::sql::ColumnConstraint::References { name, table, columns, on_delete, on_update } => {
+                        let name = name.unwrap_or_else(|| format!("{}_{}_fkey", ct.name, col.name));
+                        foreign_keys.push(
+                            ForeignKey::new(name, vec![col.name.clone()], table, columns)
+                                .on_delete(on_delete)
+                                .on_update(on_update),
+                        );
+                        continue;
                     }
                 };
                 col_def = col_def.with_constraint(c);
             }
+            // Key columns of a table-level PRIMARY KEY are NOT NULL too
+            if table_key.as_ref().is_some_and(|key| key.contains(&col.name)) && col_def.is_nullable() {
+                col_def = col_def.with_constraint(Constraint::NotNull);
+            }
             builder = builder.column(col_def);
+            if is_primary_key {
+                if !primary_key.is_empty() {
+                    return Err(multiple_keys());
+                }
+                primary_key.push(col.name);
+            }
+        }
+
+        for (i, constraint) in ct.constraints.into_iter().enumerate() {
+            match constraint {
+                TableConstraint::Check { name, expr } => {
+                    let name = name.unwrap_or_else(|| format!("{}_check{}", ct.name, i + 1));
+                    builder = builder.check(CheckConstraint::new(name, expr));
+                }
+                TableConstraint::PrimaryKey(columns) => {
+                    if !primary_key.is_empty() {
+                        return Err(multiple_keys());
+                    }
+                    primary_key = columns;
+                }
+                TableConstraint::Unique { name, columns } => {
+                    let name = name.unwrap_or_else(|| format!("{}_{}_key", ct.name, columns.join("_")));
+                    builder = builder.unique(UniqueConstraint::new(name, columns));
+                }
+                TableConstraint::ForeignKey {
+                    name,
+                    columns,
+                    references_table,
+                    references_columns,
+                    on_delete,
+                    on_update,
+                } => {
+                    let name = name.unwrap_or_else(|| format!("{}_{}_fkey", ct.name, columns.join("_")));
+                    foreign_keys.push(
+                        ForeignKey::new(name, columns, references_table, references_columns)
+                            .on_delete(on_delete)
+                            .on_update(on_update),
+                    );
+                }
+            }
+        }
+
+        // A foreign key without columns references the parent's primary key
+        for mut fk in foreign_keys {
+            let (parent_key, parent_columns) = if fk.references_table == ct.name {
+                (primary_key.clone(), column_names.clone())
+            } else {
+                let parent = self.catalog.get_table(&fk.references_table)?;
+                let columns = parent.columns.iter().map(|c| c.name.clone()).collect();
+                (parent.primary_key, columns)
+            };
+            if fk.references_columns.is_empty() {
+                fk.references_columns = parent_key;
+            }
+            if let Some(missing) = fk.references_columns.iter().find(|c| !parent_columns.contains(c)) {
+                return Err(ExecuteError::Schema(SchemaError::ColumnNotFound(format!(
+                    "{}.{}",
+                    fk.references_table, missing
+                ))));
+            }
+            builder = builder.foreign_key(fk);
         }
+        builder = builder.primary_key_columns(primary_key);
 
         let schema = builder.build().map_err(ExecuteError::Schema)?;
-        self.catalog.create_table(schema)?;
 
-        // Also create the actual table in storage
-        let repo = self.repo.write();
-        let head = repo.head()?;
-        let table_name = TableName::new(&ct.name)?;
-        let new_head = repo.create_table(&table_name, head, None)?;
-        repo.update_branch(&crate::storage::BranchName::main(), new_head)?;
+        self.catalog.create_table(schema)?;
 
         Ok(QueryResult::success(format!("Created table '{}'", ct.name)))
     }
@@ -122,225 +781,604 @@ impl QueryExecutor {
             return Err(ExecuteError::TableNotFound(dt.name));
         }
 
+        // Rows, schema and indexes go in one commit
         self.catalog.drop_table(&dt.name)?;
 
-        // Also drop from storage
-        let repo = self.repo.write();
-        let head = repo.head()?;
-        let table_name = TableName::new(&dt.name)?;
-        let new_head = repo.drop_table(&table_name, head, None)?;
-        repo.update_branch(&crate::storage::BranchName::main(), new_head)?;
-
         Ok(QueryResult::success(format!("Dropped table '{}'", dt.name)))
     }
 
-    fn execute_select(&self, select: Select) -> ExecuteResult<QueryResult> {
-        // Get table rows
-        let rows = self.scan_table(&select.from)?;
-
-        // Build operator tree
-        let mut op: Box<dyn Operator> = Box::new(ScanOperator::new(rows));
-
-        // Apply WHERE
-        if let Some(where_clause) = select.where_clause {
-            op = Box::new(FilterOperator::new(op, where_clause));
+    fn execute_alter_table(&mut self, at: AlterTable) -> ExecuteResult<QueryResult> {
+        if !self.catalog.table_exists(&at.name) {
+            if at.if_exists {
+                return Ok(QueryResult::success(format!(
+                    "Table '{}' does not exist",
+                    at.name
+                )));
+            }
+            return Err(ExecuteError::TableNotFound(at.name));
         }
 
-        // Apply ORDER BY
-        if !select.order_by.is_empty() {
-            op = Box::new(SortOperator::new(op, select.order_by));
+        match at.operation {
+            AlterTableOperation::RenameTable(new_name) => {
+                TableName::new(&new_name)?;
+                // Rows, schema and indexes move in one commit
+                self.catalog.rename_table(&at.name, &new_name)?;
+                Ok(QueryResult::success(format!("Renamed table '{}' to '{}'", at.name, new_name)))
+            }
+            AlterTableOperation::RenameColumn { old, new } => {
+                // The schema and every row holding the column in one commit
+                self.catalog.rename_column(&at.name, &old, &new)?;
+                Ok(QueryResult::success(format!(
+                    "Renamed column '{}' to '{}' in table '{}'",
+                    old, new, at.name
+                )))
+            }
         }
+    }
 
-        // Apply LIMIT/OFFSET
-        if select.limit.is_some() || select.offset.is_some() {
-            let limit = select.limit.unwrap_or(usize::MAX);
-            let offset = select.offset.unwrap_or(0);
-            op = Box::new(LimitOperator::new(op, limit, offset));
-        }
+    fn execute_select(&self, mut select: Select) -> ExecuteResult<QueryResult> {
+        self.prepare_select(&mut select)?;
 
-        // Apply projection
-        if !select.columns.iter().any(|c| matches!(c, SelectColumn::Wildcard)) {
-            op = Box::new(ProjectOperator::new(op, select.columns.clone()));
-        }
+        let stmt = Statement::Select(select.clone());
+        let plan = QueryPlanner::new(self.repo.clone()).plan(&stmt).map_err(|e| match e {
+            PlanError::TableNotFound(table) => ExecuteError::TableNotFound(table),
+            other => ExecuteError::Plan(other),
+        })?;
+        let cancel = self.statement_cancel();
+        let mut op = build_operator(&plan.physical.root, &self.repo, reads_version(&select), &cancel, &self.spill)?;
 
-        // Collect results
+        // Collect results, giving up early on a runaway unlimited query
+        let max_rows = if select.limit.is_none() { self.max_result_rows } else { None };
         let mut result_rows = Vec::new();
         while let Some(row) = op.next_row()? {
+            if max_rows.is_some_and(|max| result_rows.len() >= max) {
+                return Err(ExecuteError::ResultTooLarge(result_rows.len()));
+            }
             result_rows.push(row);
         }
 
-        // Determine columns
-        let columns = if select.columns.iter().any(|c| matches!(c, SelectColumn::Wildcard)) {
-            result_rows.first()
-                .map(|r| r.keys().cloned().collect())
-                .unwrap_or_default()
+        let columns = self.result_columns(&select, &plan.physical.root, &result_rows);
+        Ok(QueryResult::Select(ResultSet { columns, rows: result_rows }))
+    }
+
+    /// Resolve a SELECT's subqueries and qualified names ahead of planning,
+    /// derived tables first.
+    fn prepare_select(&self, select: &mut Select) -> ExecuteResult<()> {
+        self.prepare_from(&mut select.from)?;
+        for condition in select.where_clause.iter_mut().chain(&mut select.having) {
+            self.resolve_subqueries(condition)?;
+        }
+        for column in &mut select.columns {
+            if let SelectColumn::Expr { expr, .. } = column {
+                self.resolve_subqueries(expr)?;
+            }
+        }
+        if let FromSource::Join { .. } = &select.from {
+            self.qualify_join_columns(select)?;
         } else {
-            select.columns.iter()
-                .filter_map(|c| match c {
-                    SelectColumn::Column(name) => Some(name.clone()),
-                    SelectColumn::Expr { alias, .. } => alias.clone(),
-                    SelectColumn::Wildcard => None,
-                })
-                .collect()
+            self.unqualify_columns(select)?;
+        }
+
+        // Break ORDER BY ties on the primary key so rows that sort equal come
+        // out in the same order every time and pages don't overlap. Grouped
+        // rows have no key but are one per group, so the groups break ties.
+        if let (false, Some(table)) = (select.order_by.is_empty(), select.from.table()) {
+            let tiebreak = if select.is_grouped() {
+                select.group_by.clone()
+            } else {
+                self.primary_key_of(table)
+            };
+            for column in tiebreak {
+                if !select.order_by.iter().any(|ob| ob.column == column) {
+                    select.order_by.push(OrderBy { column, ascending: true });
+                }
+            }
+        }
+        for count in select.limit.iter().chain(&select.offset) {
+            bound_row_count(*count)?;
+        }
+        Ok(())
+    }
+
+    /// Prepare the derived tables a FROM reads, and the subqueries in its
+    /// join conditions.
+    fn prepare_from(&self, from: &mut FromSource) -> ExecuteResult<()> {
+        match from {
+            FromSource::Table(_) => Ok(()),
+            FromSource::Derived(subquery, _) => self.prepare_select(subquery),
+            FromSource::Join { left, right, on, .. } => {
+                self.prepare_from(left)?;
+                self.prepare_from(right)?;
+                match on {
+                    Some(on) => self.resolve_subqueries(on),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Rows of a single-table query aren't prefixed, so `from.*` is every
+    /// column and `from.column` is just `column`.
+    fn unqualify_columns(&self, select: &mut Select) -> ExecuteResult<()> {
+        let from = select.from.name().to_string();
+        let prefix = format!("{}.", from);
+        let bare = |name: &mut String| -> ExecuteResult<()> {
+            if let Some((table, column)) = name.split_once('.') {
+                if table != from {
+                    return Err(ExecuteError::TableNotFound(table.to_string()));
+                }
+                *name = column.to_string();
+            }
+            Ok(())
+        };
+        for column in &mut select.columns {
+            match column {
+                SelectColumn::QualifiedWildcard(table) => {
+                    if *table != from {
+                        return Err(ExecuteError::TableNotFound(table.clone()));
+                    }
+                    *column = SelectColumn::Wildcard;
+                }
+                SelectColumn::Column(name) => bare(name)?,
+                SelectColumn::Expr { expr, .. } => unqualify(expr, &prefix),
+                SelectColumn::Wildcard => {}
+            }
+        }
+        for condition in select.where_clause.iter_mut().chain(&mut select.having) {
+            unqualify(condition, &prefix);
+        }
+        for name in select.group_by.iter_mut().chain(select.order_by.iter_mut().map(|ob| &mut ob.column)) {
+            bare(name)?;
+        }
+        Ok(())
+    }
+
+    /// Name every column of a join `source.column`, as its rows do. A bare
+    /// name is taken from the one table that has the column; a derived or
+    /// schemaless table's columns aren't known, so those need qualifying.
+    fn qualify_join_columns(&self, select: &mut Select) -> ExecuteResult<()> {
+        let mut sources = Vec::new();
+        for source in select.from.sources() {
+            let columns = match source {
+                FromSource::Table(table) => match self.table_schema(table)? {
+                    Some(schema) if !schema.schemaless => {
+                        schema.column_names().into_iter().map(String::from).collect()
+                    }
+                    Some(_) => Vec::new(),
+                    None => return Err(ExecuteError::TableNotFound(table.clone())),
+                },
+                _ => Vec::new(),
+            };
+            sources.push((source.name().to_string(), columns));
+        }
+        let qualify = |name: &str| -> ExecuteResult<String> {
+            if let Some((table, _)) = name.split_once('.') {
+                if !sources.iter().any(|(source, _)| source == table) {
+                    return Err(ExecuteError::TableNotFound(table.to_string()));
+                }
+                return Ok(name.to_string());
+            }
+            let mut owners = sources.iter().filter(|(_, columns)| columns.iter().any(|c| c == name));
+            match (owners.next(), owners.next()) {
+                (Some((table, _)), None) => Ok(format!("{}.{}", table, name)),
+                (Some(_), Some(_)) => Err(PlanError::InvalidJoin(format!("column '{}' is ambiguous", name)).into()),
+                // An alias, or a column of a table whose columns aren't known
+                (None, _) => Ok(name.to_string()),
+            }
+        };
+        let qualify_expr = |expr: &mut Expr| -> ExecuteResult<()> {
+            let names: Vec<String> = expr.referenced_columns().into_iter().map(String::from).collect();
+            for name in names {
+                let qualified = qualify(&name)?;
+                if qualified != name {
+                    expr.rename_column(&name, &qualified);
+                }
+            }
+            Ok(())
         };
 
-        Ok(QueryResult::Select(ResultSet { columns, rows: result_rows }))
+        for column in &mut select.columns {
+            match column {
+                SelectColumn::QualifiedWildcard(table) => {
+                    if !sources.iter().any(|(source, _)| source == table) {
+                        return Err(ExecuteError::TableNotFound(table.clone()));
+                    }
+                }
+                SelectColumn::Column(name) => *name = qualify(name)?,
+                SelectColumn::Expr { expr, .. } => qualify_expr(expr)?,
+                SelectColumn::Wildcard => {}
+            }
+        }
+        let mut conditions = join_conditions(&mut select.from);
+        conditions.extend(select.where_clause.iter_mut().chain(&mut select.having));
+        for condition in conditions {
+            qualify_expr(condition)?;
+        }
+        for name in select.group_by.iter_mut().chain(select.order_by.iter_mut().map(|ob| &mut ob.column)) {
+            *name = qualify(name)?;
+        }
+        Ok(())
+    }
+
+    /// The column headers of a SELECT's result, from the projection at the
+    /// top of its plan.
+    ///
+    /// `*` stands for the table's columns in schema order, so even no rows
+    /// have headers, or for whatever a derived table selects.
+    fn result_columns(&self, select: &Select, root: &PhysicalPlanNode, rows: &[Row]) -> Vec<String> {
+        let projected = match &root.operator {
+            PhysicalOperator::Project { columns, .. } => columns.clone(),
+            _ => vec!["*".to_string()],
+        };
+        let mut columns = Vec::new();
+        for column in projected {
+            // `table.*` is one joined table's columns
+            if let Some(table) = column.strip_suffix(".*") {
+                let source = select.from.sources().into_iter().find(|s| s.name() == table);
+                columns.extend(source.map(|s| self.joined_columns(s, rows)).unwrap_or_default());
+                continue;
+            }
+            if column != "*" {
+                columns.push(column);
+                continue;
+            }
+            let table = match &select.from {
+                FromSource::Table(table) => table,
+                FromSource::Derived(subquery, _) => {
+                    match subquery_scan(root) {
+                        Some(inner) => columns.extend(self.result_columns(subquery, inner, rows)),
+                        None => columns.extend(rows.first().into_iter().flat_map(|r| r.keys().cloned())),
+                    }
+                    continue;
+                }
+                FromSource::Join { .. } => {
+                    for source in select.from.sources() {
+                        columns.extend(self.joined_columns(source, rows));
+                    }
+                    continue;
+                }
+            };
+            match self.catalog.get_table(table) {
+                Ok(schema) if !schema.schemaless => {
+                    columns.extend(schema.column_names().into_iter().map(String::from))
+                }
+                // Every column any row has, the key first
+                Ok(_) => {
+                    let keys: BTreeSet<&String> = rows.iter().flat_map(|r| r.keys()).collect();
+                    columns.push(SCHEMALESS_KEY.to_string());
+                    columns.extend(keys.into_iter().filter(|k| *k != SCHEMALESS_KEY).cloned());
+                }
+                Err(_) => columns.extend(rows.first().into_iter().flat_map(|r| r.keys().cloned())),
+            }
+        }
+        columns
+    }
+
+    /// The qualified columns one source of a join gives its rows: a table's
+    /// in schema order, or, without a schema to go by, those the rows have.
+    fn joined_columns(&self, source: &FromSource, rows: &[Row]) -> Vec<String> {
+        let prefix = format!("{}.", source.name());
+        if let Some(Ok(schema)) = source.table().map(|table| self.catalog.get_table(table)) {
+            if !schema.schemaless {
+                return schema.column_names().into_iter().map(|column| format!("{}{}", prefix, column)).collect();
+            }
+        }
+        let keys: BTreeSet<&String> = rows.iter().flat_map(|r| r.keys()).filter(|k| k.starts_with(&prefix)).collect();
+        keys.into_iter().cloned().collect()
     }
 
     fn execute_insert(&mut self, insert: Insert) -> ExecuteResult<QueryResult> {
-        let schema = self.catalog.get_table(&insert.table)?;
-        let repo = self.repo.write();
+        let schema = match self.table_schema(&insert.table)? {
+            Some(schema) => schema,
+            None if self.schemaless => {
+                let schema = TableSchema::schemaless(&insert.table);
+                self.catalog.create_table(schema.clone())?;
+                schema
+            }
+            None => return Err(SchemaError::TableNotFound(insert.table).into()),
+        };
+        let parents = self.parent_schemas(&schema)?;
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
         let mut head = repo.head()?;
         let table_name = TableName::new(&insert.table)?;
 
-        let column_names = insert.columns.as_ref()
-            .map(|c| c.clone())
+        let column_names = insert.columns.clone()
             .unwrap_or_else(|| schema.column_names().into_iter().map(|s| s.to_string()).collect());
+        // Every row must fill the column list, or the table's columns without one
+        if let Some((row, values)) = insert.values.iter().enumerate().find(|(_, v)| v.len() != column_names.len()) {
+            return Err(ExecuteError::ValuesArity {
+                row: row + 1,
+                expected: column_names.len(),
+                actual: values.len(),
+            });
+        }
 
+        let cancel = self.statement_cancel();
         let mut inserted = 0;
         for row_values in &insert.values {
+            cancel.check()?;
             // Build row data
             let mut data = BTreeMap::new();
             let empty_row = serde_json::Map::new();
 
-            for (i, expr) in row_values.iter().enumerate() {
-                if i < column_names.len() {
-                    let value = evaluate(expr, &empty_row)?;
-                    data.insert(column_names[i].clone(), value);
-                }
-            }
-
-            // Apply defaults
-            let row_value = Value::Object(
-                data.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
-            );
-            let with_defaults = schema.apply_defaults(&row_value)?;
-            let data: BTreeMap<String, Value> = with_defaults
-                .as_object()
-                .map(|o| o.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
-                .unwrap_or_default();
-
-            // Validate
-            schema.validate_row(&Value::Object(
-                data.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
-            ))?;
-
-            // Generate row key
-            let key = if let Some(pk) = &schema.primary_key {
-                let pk_value = data.get(pk)
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| ExecuteError::MissingColumn(pk.clone()))?;
-                RowKey::new(pk_value)?
-            } else {
-                RowKey::generate()
-            };
+            for (column, expr) in column_names.iter().zip(row_values) {
+                // Left out, the column gets its default when the row is written
+                if *expr == Expr::Default {
+                    continue;
+                }
+                let mut value = evaluate(expr, &empty_row)?;
+                if let Some(col) = schema.get_column(column) {
+                    value = col.coerce(value);
+                }
+                data.insert(column.clone(), value);
+            }
 
-            let storage_row = StorageRow::new(key, data);
-            head = repo.insert_row(&table_name, storage_row, head, None)?;
-            inserted += 1;
+            // An ignored row leaves the head where it was
+            let next = insert_data(&repo, &table_name, &schema, &parents, data, insert.on_conflict, head)?;
+            if next != head {
+                head = next;
+                inserted += 1;
+            }
         }
 
-        repo.update_branch(&crate::storage::BranchName::main(), head)?;
+        repo.advance_head(head)?;
         Ok(QueryResult::modified(inserted))
     }
 
-    fn execute_update(&mut self, update: Update) -> ExecuteResult<QueryResult> {
-        let _schema = self.catalog.get_table(&update.table)?;
-        let repo = self.repo.write();
+    fn execute_update(&mut self, mut update: Update) -> ExecuteResult<QueryResult> {
+        if let Some(where_clause) = &mut update.where_clause {
+            self.resolve_subqueries(where_clause)?;
+        }
+        for assignment in &mut update.assignments {
+            self.resolve_subqueries(&mut assignment.value)?;
+        }
+
+        let schema = self.catalog.get_table(&update.table)?;
+        if let Some(where_clause) = &update.where_clause {
+            check_columns(where_clause, &schema)?;
+        }
+        for assignment in &update.assignments {
+            check_columns(&assignment.value, &schema)?;
+        }
+        let columns = read_columns(
+            &schema,
+            update.where_clause.iter().chain(update.assignments.iter().map(|a| &a.value)),
+        );
+        let parents = self.parent_schemas(&schema)?;
+        // Every schema, if some table references this one, for ON UPDATE actions
+        let schemas = self.referencing_schemas(&update.table)?;
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
         let mut head = repo.head()?;
         let table_name = TableName::new(&update.table)?;
 
-        // Get all rows
-        let rows = repo.scan_table(&table_name, head)?;
+        // Only the targeted rows when the WHERE clause pins the primary key
+        let rows = candidate_rows(
+            &repo,
+            &table_name,
+            head,
+            &schema,
+            update.where_clause.as_ref(),
+        )?;
+        let predicate = update.where_clause.clone().map(Predicate::new);
+        let cancel = self.statement_cancel();
         let mut updated = 0;
 
         for storage_row in rows {
+            cancel.check()?;
             // Check WHERE clause
-            let row_map: serde_json::Map<String, Value> = storage_row.data.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect();
+            let mut row_map = row_values(&storage_row, &columns);
 
-            let matches = if let Some(ref where_clause) = update.where_clause {
-                super::eval::matches_where(where_clause, &row_map)?
-            } else {
-                true
+            let matches = match &predicate {
+                Some(predicate) => predicate.matches(&mut row_map)?,
+                None => true,
             };
 
             if matches {
                 // Apply updates
                 let mut new_data = storage_row.data.clone();
                 for Assignment { column, value } in &update.assignments {
-                    let new_value = evaluate(value, &row_map)?;
+                    let mut new_value = evaluate(value, &row_map)?;
+                    if let Some(col) = schema.get_column(column) {
+                        new_value = col.coerce(new_value);
+                    }
                     new_data.insert(column.clone(), new_value);
                 }
 
-                let updated_row = storage_row.with_update(new_data);
-                head = repo.update_row(&table_name, updated_row, head, None)?;
+                // The merged row must still satisfy the whole schema
+                schema.validate_row(&Value::Object(
+                    new_data.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+                ))?;
+                for (fk, parent) in &parents {
+                    check_parent_exists(&repo, head, fk, parent, &new_data)?;
+                }
+
+                // Changing the primary key moves the row to a new key
+                let new_key = if schema.primary_key.is_empty() {
+                    storage_row.key.clone()
+                } else {
+                    primary_row_key(&schema.primary_key, &new_data)?
+                };
+
+                let old_data = storage_row.data.clone();
+                if new_key == storage_row.key {
+                    let read_version = storage_row.version;
+                    let updated_row = storage_row.with_update(new_data.clone());
+                    head = repo.update_row_if_version(&table_name, updated_row, read_version, head, None)?;
+                } else {
+                    head = repo.delete_row(&table_name, &storage_row.key, head, None)?;
+                    let mut moved_row = storage_row.with_update(new_data.clone());
+                    moved_row.key = new_key;
+                    head = insert_unique(&repo, &table_name, moved_row, head)?;
+                }
+                head = update_referenced(&repo, &schemas, &update.table, &old_data, &new_data, head)?;
                 updated += 1;
             }
         }
 
-        repo.update_branch(&crate::storage::BranchName::main(), head)?;
+        repo.advance_head(head)?;
         Ok(QueryResult::modified(updated))
     }
 
-    fn execute_delete(&mut self, delete: Delete) -> ExecuteResult<QueryResult> {
-        let repo = self.repo.write();
+    fn execute_delete(&mut self, mut delete: Delete) -> ExecuteResult<QueryResult> {
+        if let Some(where_clause) = &mut delete.where_clause {
+            self.resolve_subqueries(where_clause)?;
+        }
+
+        let schema = self.catalog.get_table(&delete.table)?;
+        if let Some(where_clause) = &delete.where_clause {
+            check_columns(where_clause, &schema)?;
+        }
+        let columns = read_columns(&schema, delete.where_clause.iter());
+        // Every schema, if some table references this one, for ON DELETE actions
+        let schemas = self.referencing_schemas(&delete.table)?;
+        let lock = self.repo.write();
+        let repo = self.writer(&lock);
         let mut head = repo.head()?;
         let table_name = TableName::new(&delete.table)?;
 
-        // Get all rows
-        let rows = repo.scan_table(&table_name, head)?;
+        // Only the targeted rows when the WHERE clause pins the primary key
+        let rows = candidate_rows(
+            &repo,
+            &table_name,
+            head,
+            &schema,
+            delete.where_clause.as_ref(),
+        )?;
+        let predicate = delete.where_clause.clone().map(Predicate::new);
+        let cancel = self.statement_cancel();
         let mut deleted = 0;
 
         for storage_row in rows {
+            cancel.check()?;
             // Check WHERE clause
-            let row_map: serde_json::Map<String, Value> = storage_row.data.iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect();
+            let mut row_map = row_values(&storage_row, &columns);
 
-            let matches = if let Some(ref where_clause) = delete.where_clause {
-                super::eval::matches_where(where_clause, &row_map)?
-            } else {
-                true
+            let matches = match &predicate {
+                Some(predicate) => predicate.matches(&mut row_map)?,
+                None => true,
             };
 
-            if matches {
+            if !matches {
+                continue;
+            }
+            if schemas.is_empty() {
                 head = repo.delete_row(&table_name, &storage_row.key, head, None)?;
                 deleted += 1;
+            } else if let Some(new_head) =
+                delete_referenced(&repo, &schemas, &delete.table, &storage_row, head)?
+            {
+                head = new_head;
+                deleted += 1;
             }
         }
 
-        repo.update_branch(&crate::storage::BranchName::main(), head)?;
+        repo.advance_head(head)?;
         Ok(QueryResult::modified(deleted))
     }
 
-    fn execute_begin(&mut self) -> ExecuteResult<QueryResult> {
+    /// The plan as a `plan` column, one row per line, like the REPL's `.explain`.
+    ///
+    /// With ANALYZE the query is run too, and the rows it returned and the
+    /// time it took are added at the end.
+    fn execute_explain(&mut self, statement: Statement, analyze: bool) -> ExecuteResult<QueryResult> {
+        let mut plan = QueryPlanner::new(self.repo.clone()).explain(&statement)?;
+
+        if analyze {
+            let start = Instant::now();
+            let rows = match self.execute_statement(statement)? {
+                QueryResult::Select(rs) => rs.len(),
+                _ => 0,
+            };
+            plan.push_str(&format!(
+                "\n=== Analyze ===\nActual Rows: {}\nExecution Time: {:.3}ms",
+                rows,
+                start.elapsed().as_secs_f64() * 1000.0
+            ));
+        }
+
+        let rows = plan.lines()
+            .map(|line| BTreeMap::from([("plan".to_string(), Value::String(line.to_string()))]))
+            .collect();
+        Ok(QueryResult::Select(ResultSet { columns: vec!["plan".to_string()], rows }))
+    }
+
+    fn execute_begin(&mut self, isolation: Option<TransactionIsolation>) -> ExecuteResult<QueryResult> {
         if self.current_tx.is_some() {
             return Err(ExecuteError::Internal("transaction already active".into()));
         }
-        let tx = self.tx_manager.begin()?;
+        // Levels GitDB can't give are refused rather than quietly weakened
+        let isolation = match isolation {
+            None => self.isolation,
+            Some(TransactionIsolation::ReadCommitted) => IsolationLevel::ReadCommitted,
+            Some(TransactionIsolation::RepeatableRead | TransactionIsolation::Snapshot) => {
+                IsolationLevel::RepeatableRead
+            }
+            Some(other @ (TransactionIsolation::ReadUncommitted | TransactionIsolation::Serializable)) => {
+                return Err(ExecuteError::UnsupportedIsolation(other.to_string()));
+            }
+        };
+        let mut tx = self.tx_manager.begin_with_isolation(isolation)?;
+        tx.repo = self.author_of(&tx.repo);
+        let branch = self.main.read().on_branch(tx.branch().clone());
+        self.use_repo(Arc::new(RwLock::new(branch)));
         self.current_tx = Some(tx);
         Ok(QueryResult::transaction("BEGIN"))
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "commit",
+            skip_all,
+            fields(tx = tracing::field::Empty, commit = tracing::field::Empty),
+            err(Display),
+        )
+    )]
     fn execute_commit(&mut self) -> ExecuteResult<QueryResult> {
-        let tx = self.current_tx.take()
+        let mut tx = self.current_tx.take()
             .ok_or(ExecuteError::NoTransaction)?;
-        tx.commit()?;
+        trace::record("tx", || tx.id().to_string());
+        self.use_repo(self.main.clone());
+        tx.sync_with_branch()?;
+        // Through the manager, so it stops listing the transaction as open
+        let commit = self.tx_manager.commit_transaction(tx)?;
+        trace::record("commit", || commit);
         Ok(QueryResult::transaction("COMMIT"))
     }
 
+    /// Roll back the transaction BEGIN started, if there is one, leaving
+    /// nothing of it behind. Failures are ignored, as there's no caller to
+    /// tell.
+    pub(crate) fn rollback_open(&mut self) {
+        if let Some(tx) = self.current_tx.take() {
+            self.use_repo(self.main.clone());
+            let _ = self.tx_manager.rollback_transaction(tx);
+        }
+    }
+
     fn execute_rollback(&mut self) -> ExecuteResult<QueryResult> {
         let tx = self.current_tx.take()
             .ok_or(ExecuteError::NoTransaction)?;
-        tx.rollback()?;
+        self.use_repo(self.main.clone());
+        self.tx_manager.rollback_transaction(tx)?;
         Ok(QueryResult::transaction("ROLLBACK"))
     }
 
-    fn execute_show_tables(&self) -> ExecuteResult<QueryResult> {
+    /// Run later statements, DDL included, against `repo`.
+    fn use_repo(&mut self, repo: Arc<RwLock<GitRepository>>) {
+        let mut catalog = Catalog::new(repo.clone());
+        catalog.set_author(self.author.clone());
+        catalog.set_note(self.note.clone());
+        self.repo = repo;
+        self.catalog = catalog;
+    }
+
+    fn execute_show_tables(&self, like: Option<&str>) -> ExecuteResult<QueryResult> {
         let tables = self.catalog.list_tables()?;
         let rows: Vec<Row> = tables.into_iter()
+            .filter(|name| like.is_none_or(|pattern| show_like_match(name, pattern)))
             .map(|name| {
                 let mut row = Row::new();
                 row.insert("table_name".into(), Value::String(name));
@@ -354,17 +1392,53 @@ impl QueryExecutor {
         }))
     }
 
-    fn execute_describe(&self, table: &str) -> ExecuteResult<QueryResult> {
+    /// Every open transaction, oldest first.
+    ///
+    /// Only reads the transaction manager, so it never waits on a
+    /// transaction, however long it's been open.
+    fn execute_show_transactions(&self) -> ExecuteResult<QueryResult> {
+        let mut transactions: Vec<_> = self.tx_manager.active_transactions()
+            .iter()
+            .filter_map(|tx_id| self.tx_manager.get_transaction_info(tx_id))
+            .collect();
+        transactions.sort_by(|a, b| (a.started_at, &a.tx_id).cmp(&(b.started_at, &b.tx_id)));
+
+        let now = chrono::Utc::now();
+        let main = self.main.read();
+        let rows = transactions.into_iter()
+            .map(|info| {
+                // Writes move the branch, not the metadata recorded at BEGIN
+                let current = main.resolve_branch(&info.branch).unwrap_or(info.current_commit);
+                let age = (now - info.started_at).num_milliseconds().max(0) as f64 / 1000.0;
+                let mut row = Row::new();
+                row.insert("tx_id".into(), Value::String(info.tx_id));
+                row.insert("isolation".into(), Value::String(info.isolation.to_string()));
+                row.insert("started_at".into(), Value::String(info.started_at.to_rfc3339()));
+                row.insert("age_seconds".into(), Value::from(age));
+                row.insert("base_commit".into(), Value::String(info.base_commit.to_string()));
+                row.insert("current_commit".into(), Value::String(current.to_string()));
+                row
+            })
+            .collect();
+
+        Ok(QueryResult::Select(ResultSet {
+            columns: ["tx_id", "isolation", "started_at", "age_seconds", "base_commit", "current_commit"]
+                .map(String::from)
+                .to_vec(),
+            rows,
+        }))
+    }
+
+    fn execute_describe(&self, table: &str, like: Option<&str>) -> ExecuteResult<QueryResult> {
         let schema = self.catalog.get_table(table)?;
         let rows: Vec<Row> = schema.columns.iter()
+            .filter(|col| like.is_none_or(|pattern| show_like_match(&col.name, pattern)))
             .map(|col| {
                 let mut row = Row::new();
                 row.insert("column".into(), Value::String(col.name.clone()));
-                row.insert("type".into(), Value::String(col.data_type.sql_name().to_string()));
+                row.insert("type".into(), Value::String(col.data_type.sql_name()));
                 row.insert("nullable".into(), Value::Bool(col.is_nullable()));
-                row.insert("primary_key".into(), Value::Bool(
-                    schema.primary_key.as_deref() == Some(&col.name)
-                ));
+                row.insert("primary_key".into(), Value::Bool(schema.is_primary_key(&col.name)));
                 row
             })
             .collect();
@@ -375,17 +1449,145 @@ impl QueryExecutor {
         }))
     }
 
-    fn scan_table(&self, table: &str) -> ExecuteResult<Vec<Row>> {
-        let repo = self.repo.read();
-        let head = repo.head()?;
-        let table_name = TableName::new(table)?;
+    /// Replace the subqueries in an expression with the values they produce.
+    ///
+    /// Subqueries can't refer to the outer row, so each one runs once per
+    /// statement. This has to happen before the statement locks the repository.
+    fn resolve_subqueries(&self, expr: &mut Expr) -> ExecuteResult<()> {
+        match expr {
+            Expr::Subquery(select) => {
+                let values = self.subquery_values(select)?;
+                if values.len() > 1 {
+                    return Err(ExecuteError::SubqueryRows(values.len()));
+                }
+                let value = values.first().unwrap_or(&Value::Null);
+                *expr = Expr::Literal(LiteralValue::from_json(value));
+            }
+            Expr::InSubquery { expr: inner, subquery, negated } => {
+                self.resolve_subqueries(inner)?;
+                let list = self.subquery_values(subquery)?
+                    .iter()
+                    .map(|v| Expr::Literal(LiteralValue::from_json(v)))
+                    .collect();
+                let inner = std::mem::replace(inner.as_mut(), Expr::Literal(LiteralValue::Null));
+                *expr = Expr::InList { expr: Box::new(inner), list, negated: *negated };
+            }
+            Expr::Exists { subquery, negated } => {
+                let found = self.subquery_exists(subquery)?;
+                *expr = Expr::Literal(LiteralValue::Boolean(found != *negated));
+            }
+            Expr::BinaryOp { left, right, .. } => {
+                self.resolve_subqueries(left)?;
+                self.resolve_subqueries(right)?;
+            }
+            Expr::UnaryOp { expr, .. }
+            | Expr::IsNull { expr, .. }
+            | Expr::Like { expr, .. }
+            | Expr::JsonAccess { expr, .. }
+            | Expr::Nested(expr) => self.resolve_subqueries(expr)?,
+            Expr::InList { expr, list, .. } => {
+                self.resolve_subqueries(expr)?;
+                for item in list {
+                    self.resolve_subqueries(item)?;
+                }
+            }
+            Expr::Between { expr, low, high, .. } => {
+                self.resolve_subqueries(expr)?;
+                self.resolve_subqueries(low)?;
+                self.resolve_subqueries(high)?;
+            }
+            Expr::Function { args, .. } => {
+                for arg in args {
+                    self.resolve_subqueries(arg)?;
+                }
+            }
+            Expr::Column(_) | Expr::Literal(_) | Expr::Default => {}
+        }
+        Ok(())
+    }
 
-        let storage_rows = repo.scan_table(&table_name, head)?;
-        let rows: Vec<Row> = storage_rows.into_iter()
-            .map(|sr| sr.data)
-            .collect();
+    /// Whether a subquery returns any rows.
+    ///
+    /// Only the first row is asked for, so the scan stops at the first match
+    /// instead of reading every row that would. Order makes no difference to
+    /// whether there is a row, so the subquery isn't sorted either.
+    fn subquery_exists(&self, select: &Select) -> ExecuteResult<bool> {
+        let mut select = select.clone();
+        select.order_by.clear();
+        if select.limit != Some(RowCount::Count(0)) {
+            select.limit = Some(RowCount::Count(1));
+        }
+        match self.execute_select(select)? {
+            QueryResult::Select(rs) => Ok(!rs.rows.is_empty()),
+            _ => Err(ExecuteError::Internal("subquery did not return rows".into())),
+        }
+    }
+
+    /// Run a subquery and return its single column's values.
+    fn subquery_values(&self, select: &Select) -> ExecuteResult<Vec<Value>> {
+        let QueryResult::Select(rs) = self.execute_select(select.clone())? else {
+            return Err(ExecuteError::Internal("subquery did not return rows".into()));
+        };
+        let [column] = rs.columns.as_slice() else {
+            return Err(ExecuteError::InvalidExpression(format!(
+                "subquery must return exactly one column, got {}",
+                rs.columns.len()
+            )));
+        };
+        Ok(rs.rows.iter()
+            .map(|row| row.get(column).cloned().unwrap_or(Value::Null))
+            .collect())
+    }
+
+    /// The foreign keys of a table, each with the schema of the table it references.
+    fn parent_schemas(&self, schema: &TableSchema) -> ExecuteResult<Vec<(ForeignKey, TableSchema)>> {
+        schema.foreign_keys.iter()
+            .map(|fk| {
+                let parent = if fk.references_table == schema.name {
+                    schema.clone()
+                } else {
+                    self.catalog.get_table(&fk.references_table)?
+                };
+                Ok((fk.clone(), parent))
+            })
+            .collect()
+    }
+
+    /// All table schemas if any foreign key references `table`, otherwise none.
+    ///
+    /// Cascades can reach tables that don't reference `table` directly, so
+    /// callers get every schema rather than only the referencing ones.
+    fn referencing_schemas(&self, table: &str) -> ExecuteResult<Vec<TableSchema>> {
+        let schemas = self.catalog.list_tables()?
+            .iter()
+            .map(|name| self.catalog.get_table(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let referenced = schemas.iter()
+            .flat_map(|s| &s.foreign_keys)
+            .any(|fk| fk.references_table == table);
+        Ok(if referenced { schemas } else { Vec::new() })
+    }
+
+    /// Primary key columns of a table, empty if it has no schema or no key.
+    fn primary_key_of(&self, table: &str) -> Vec<String> {
+        self.catalog.get_table(table).map(|s| s.primary_key).unwrap_or_default()
+    }
+
+    /// The repository to commit through, with the session's author and note.
+    fn writer(&self, repo: &GitRepository) -> GitRepository {
+        let repo = self.author_of(repo);
+        match &self.note {
+            Some(note) => repo.annotated(note.clone()),
+            None => repo,
+        }
+    }
 
-        Ok(rows)
+    /// The repository to commit through, attributed to the session's author.
+    fn author_of(&self, repo: &GitRepository) -> GitRepository {
+        match &self.author {
+            Some(author) => repo.as_author(author.clone()),
+            None => repo.clone(),
+        }
     }
 
     /// Get the catalog.
@@ -397,137 +1599,2863 @@ impl QueryExecutor {
     pub fn in_transaction(&self) -> bool {
         self.current_tx.is_some()
     }
+
+    /// The repository statements run against: the transaction's branch
+    /// inside BEGIN, main otherwise.
+    pub(crate) fn session_repo(&self) -> &Arc<RwLock<GitRepository>> {
+        &self.repo
+    }
+
+    /// The transaction BEGIN started, until COMMIT or ROLLBACK.
+    pub fn current_transaction(&self) -> Option<&Transaction<TxActive>> {
+        self.current_tx.as_ref()
+    }
 }
 
-fn convert_sql_type(sql_type: &SqlDataType) -> DataType {
-    match sql_type {
-        SqlDataType::Text => DataType::Text,
-        SqlDataType::Integer => DataType::Integer,
-        SqlDataType::Float => DataType::Float,
-        SqlDataType::Boolean => DataType::Boolean,
-        SqlDataType::Json => DataType::Json,
-        SqlDataType::Timestamp => DataType::Timestamp,
-        SqlDataType::Uuid => DataType::Uuid,
+impl Drop for QueryExecutor {
+    /// A session that ends inside a transaction rolls it back, so its
+    /// branch doesn't outlive it, and one with buffered writes flushes them.
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            trace::warn("discarded unflushed write batch", &e);
+        }
+        self.rollback_open();
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+/// Whether `name` matches the LIKE pattern of SHOW TABLES or SHOW COLUMNS,
+/// in which `\` escapes `%` and `_`. A pattern ending in a lone `\`
+/// matches nothing.
+fn show_like_match(name: &str, pattern: &str) -> bool {
+    like_match(name, pattern, false, Some('\\')).unwrap_or(false)
+}
 
-    fn setup() -> (QueryExecutor, TempDir) {
-        let dir = TempDir::new().unwrap();
-        let repo = GitRepository::open_or_init(dir.path()).unwrap();
-        let executor = QueryExecutor::new(repo);
-        (executor, dir)
-    }
+/// `(k1, k2, ...) > (v1, v2, ...)`, compared column by column.
+fn keyset_after(primary_key: &[String], after: &[Value]) -> Expr {
+    let column = |name: &String| Box::new(Expr::Column(name.clone()));
+    let literal = |value: &Value| Box::new(Expr::Literal(LiteralValue::from_json(value)));
+    let and = |left, right| Expr::BinaryOp { left: Box::new(left), op: BinaryOperator::And, right: Box::new(right) };
 
-    #[test]
-    fn test_create_table() {
-        let (mut exec, _dir) = setup();
+    // k1 > v1 OR (k1 = v1 AND k2 > v2) OR ...
+    (0..primary_key.len())
+        .map(|i| {
+            let greater = Expr::BinaryOp {
+                left: column(&primary_key[i]),
+                op: BinaryOperator::Gt,
+                right: literal(&after[i]),
+            };
+            (0..i).rev().fold(greater, |rest, j| {
+                let equal = Expr::BinaryOp {
+                    left: column(&primary_key[j]),
+                    op: BinaryOperator::Eq,
+                    right: literal(&after[j]),
+                };
+                and(equal, rest)
+            })
+        })
+        .reduce(|left, right| Expr::BinaryOp {
+            left: Box::new(left),
+            op: BinaryOperator::Or,
+            right: Box::new(right),
+        })
+        .expect("primary key has at least one column")
+}
 
-        let result = exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT NOT NULL)").unwrap();
-        assert!(matches!(result, QueryResult::Success { .. }));
+/// Fail on a column that isn't in the table, before touching any row.
+///
+/// Rows written before a column was added have no value for it; with the
+/// columns checked here, [`row_values`] can treat those as NULL.
+///
+/// A schemaless table has every column, NULL in the rows that lack it.
+fn check_columns(expr: &Expr, schema: &TableSchema) -> ExecuteResult<()> {
+    if schema.schemaless {
+        return Ok(());
+    }
+    let unknown = |c: &&str| *c != VERSION_COLUMN && schema.get_column(c).is_none();
+    match expr.referenced_columns().into_iter().find(unknown) {
+        Some(column) => Err(ExecuteError::ColumnNotFound(column.to_string())),
+        None => Ok(()),
+    }
+}
 
-        // Verify in catalog
-        assert!(exec.catalog().table_exists("users"));
+/// The columns a statement's rows are read with: the schema's, plus for a
+/// schemaless table every column `exprs` refer to.
+fn read_columns<'a>(schema: &TableSchema, exprs: impl Iterator<Item = &'a Expr>) -> Vec<String> {
+    let mut columns: Vec<String> = schema.column_names().into_iter().map(String::from).collect();
+    if schema.schemaless {
+        for expr in exprs {
+            for column in expr.referenced_columns() {
+                if column != VERSION_COLUMN && !columns.iter().any(|c| c == column) {
+                    columns.push(column.to_string());
+                }
+            }
+        }
     }
+    columns
+}
 
-    #[test]
-    fn test_insert_and_select() {
-        let (mut exec, _dir) = setup();
+/// A row's values by column, with NULL for `columns` the row lacks, plus
+/// its [`VERSION_COLUMN`].
+fn row_values(row: &StorageRow, columns: &[String]) -> serde_json::Map<String, Value> {
+    let mut values: serde_json::Map<String, Value> = row.data.iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    for column in columns {
+        values.entry(column.clone()).or_insert(Value::Null);
+    }
+    values.insert(VERSION_COLUMN.to_string(), Value::from(row.version));
+    values
+}
 
-        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT NOT NULL)").unwrap();
-        exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
-        exec.execute("INSERT INTO users (id, name) VALUES ('2', 'Bob')").unwrap();
+/// Whether a query mentions [`VERSION_COLUMN`] anywhere, so scans add it.
+/// Strip `prefix`, a single-table query's `table.`, from the columns `expr`
+/// names.
+fn unqualify(expr: &mut Expr, prefix: &str) {
+    let names: Vec<String> = expr.referenced_columns().into_iter().map(String::from).collect();
+    for name in names {
+        if let Some(column) = name.strip_prefix(prefix) {
+            expr.rename_column(&name, column);
+        }
+    }
+}
 
-        let result = exec.execute("SELECT * FROM users").unwrap();
-        if let QueryResult::Select(rs) = result {
-            assert_eq!(rs.len(), 2);
-        } else {
-            panic!("Expected Select result");
+/// The ON conditions of a FROM's joins.
+fn join_conditions(from: &mut FromSource) -> Vec<&mut Expr> {
+    match from {
+        FromSource::Join { left, right, on, .. } => {
+            let mut conditions = join_conditions(left);
+            conditions.extend(join_conditions(right));
+            conditions.extend(on.as_mut());
+            conditions
         }
+        FromSource::Table(_) | FromSource::Derived(..) => Vec::new(),
     }
+}
 
-    #[test]
-    fn test_select_where() {
-        let (mut exec, _dir) = setup();
+/// The derived tables a FROM reads, joined or not.
+fn derived_tables(from: &mut FromSource) -> Vec<&mut Select> {
+    match from {
+        FromSource::Table(_) => Vec::new(),
+        FromSource::Derived(subquery, _) => vec![subquery.as_mut()],
+        FromSource::Join { left, right, .. } => {
+            let mut tables = derived_tables(left);
+            tables.extend(derived_tables(right));
+            tables
+        }
+    }
+}
 
-        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT, age INTEGER)").unwrap();
-        exec.execute("INSERT INTO users (id, name, age) VALUES ('1', 'Alice', 30)").unwrap();
-        exec.execute("INSERT INTO users (id, name, age) VALUES ('2', 'Bob', 25)").unwrap();
-        exec.execute("INSERT INTO users (id, name, age) VALUES ('3', 'Charlie', 35)").unwrap();
+fn reads_version(select: &Select) -> bool {
+    let in_expr = |expr: &Expr| expr.referenced_columns().contains(&VERSION_COLUMN);
+    select.columns.iter().any(|column| match column {
+        SelectColumn::Column(name) => name == VERSION_COLUMN,
+        SelectColumn::Expr { expr, .. } => in_expr(expr),
+        SelectColumn::Wildcard | SelectColumn::QualifiedWildcard(_) => false,
+    }) || select.where_clause.iter().chain(&select.having).any(in_expr)
+        || select.group_by.iter().chain(select.order_by.iter().map(|ob| &ob.column)).any(|c| c == VERSION_COLUMN)
+        || select.from.sources().into_iter().any(|source| matches!(source, FromSource::Derived(subquery, _) if reads_version(subquery)))
+}
 
-        let result = exec.execute("SELECT * FROM users WHERE age > 28").unwrap();
-        if let QueryResult::Select(rs) = result {
-            assert_eq!(rs.len(), 2); // Alice and Charlie
-        } else {
-            panic!("Expected Select result");
+/// Rows that can match `where_clause`, read by key when it pins the primary key.
+///
+/// This is a superset of the matching rows; callers still apply the filter.
+/// Falls back to a full table scan when the predicate isn't key-eligible.
+fn candidate_rows(
+    repo: &GitRepository,
+    table: &TableName,
+    head: CommitId,
+    schema: &TableSchema,
+    where_clause: Option<&Expr>,
+) -> ExecuteResult<Vec<StorageRow>> {
+    match where_clause.and_then(|expr| primary_key_lookup(expr, schema)) {
+        Some(keys) => read_keys(repo, table, head, keys),
+        None => Ok(repo.scan_table(table, head)?),
+    }
+}
+
+/// Read the rows with the given keys that exist, in scan order.
+fn read_keys(
+    repo: &GitRepository,
+    table: &TableName,
+    head: CommitId,
+    mut keys: Vec<RowKey>,
+) -> ExecuteResult<Vec<StorageRow>> {
+    // Match scan order and never visit a row twice
+    keys.sort();
+    keys.dedup();
+
+    let mut rows = Vec::with_capacity(keys.len());
+    for key in keys {
+        if let Some(row) = repo.read_row(table, &key, head)? {
+            rows.push(row);
         }
     }
+    Ok(rows)
+}
 
-    #[test]
-    fn test_update() {
-        let (mut exec, _dir) = setup();
+/// Row keys a predicate restricts the primary key to, or `None` if it doesn't.
+///
+/// Every key column has to be pinned by `col = literal` or `col IN (literals...)`,
+/// possibly on one side of a conjunction; a composite key looks up each
+/// combination of the pinned values. Values that can't be row keys simply
+/// match no row.
+fn primary_key_lookup(expr: &Expr, schema: &TableSchema) -> Option<Vec<RowKey>> {
+    if schema.primary_key.is_empty() {
+        return None;
+    }
 
-        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").unwrap();
-        exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
+    let mut combinations = vec![Vec::new()];
+    for column in &schema.primary_key {
+        let data_type = &schema.get_column(column)?.data_type;
+        let values = key_column_values(expr, column, data_type)?;
+        combinations = combinations
+            .iter()
+            .flat_map(|prefix: &Vec<String>| {
+                values.iter().map(move |value| {
+                    let mut parts = prefix.clone();
+                    parts.push(value.clone());
+                    parts
+                })
+            })
+            .collect();
+    }
 
-        let result = exec.execute("UPDATE users SET name = 'Alicia' WHERE id = '1'").unwrap();
-        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+    Some(combinations.iter().filter_map(|parts| row_key(parts).ok()).collect())
+}
 
-        let result = exec.execute("SELECT * FROM users WHERE id = '1'").unwrap();
-        if let QueryResult::Select(rs) = result {
-            assert_eq!(rs.rows[0].get("name").unwrap(), &Value::String("Alicia".into()));
+/// Values a predicate restricts one key column to, or `None` if it doesn't.
+fn key_column_values(expr: &Expr, column: &str, data_type: &DataType) -> Option<Vec<String>> {
+    match expr {
+        Expr::BinaryOp { left, op: BinaryOperator::Eq, right } => {
+            match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(c), Expr::Literal(lit)) | (Expr::Literal(lit), Expr::Column(c))
+                    if c == column =>
+                {
+                    literal_key_part(lit, data_type).map(|part| vec![part])
+                }
+                _ => None,
+            }
         }
+        Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+            key_column_values(left, column, data_type).or_else(|| key_column_values(right, column, data_type))
+        }
+        Expr::InList { expr, list, negated: false }
+            if matches!(expr.as_ref(), Expr::Column(c) if c == column) =>
+        {
+            list.iter()
+                .map(|item| match item {
+                    Expr::Literal(lit) => literal_key_part(lit, data_type),
+                    _ => None,
+                })
+                .collect()
+        }
+        Expr::Nested(inner) => key_column_values(inner, column, data_type),
+        _ => None,
     }
+}
 
-    #[test]
-    fn test_delete() {
-        let (mut exec, _dir) = setup();
+/// Key part a literal maps to on a key column of type `data_type`.
+///
+/// Only a literal equal to no stored key but its own is looked up by key: an
+/// integer on an INTEGER column, or a string on a TEXT or UUID column that
+/// isn't numeric or a timestamp, since those compare by value. Anything else
+/// makes the lookup ineligible, so a scan compares it like the filter does.
+fn literal_key_part(lit: &LiteralValue, data_type: &DataType) -> Option<String> {
+    match (lit, data_type) {
+        (LiteralValue::Integer(i), DataType::Integer) => Some(i.to_string()),
+        (LiteralValue::String(s), DataType::Text | DataType::Uuid)
+            if Decimal::parse(s).is_none() && parse_timestamp(s).is_none() =>
+        {
+            Some(s.clone())
+        }
+        _ => None,
+    }
+}
 
-        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").unwrap();
-        exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
-        exec.execute("INSERT INTO users (id, name) VALUES ('2', 'Bob')").unwrap();
+/// Row key for the values of a primary key, one part per key column.
+///
+/// Keys are encoded with [`RowKey::from_parts`], so any text is a valid key
+/// and an alphanumeric single-column key is stored under its value as-is.
+fn row_key(parts: &[String]) -> Result<RowKey, InvalidNameError> {
+    RowKey::from_parts(parts)
+}
 
-        let result = exec.execute("DELETE FROM users WHERE id = '1'").unwrap();
-        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+/// Row key for a row's primary key: strings as-is, integers in decimal.
+///
+/// A missing or NULL key is an error rather than a generated key, and so is
+/// any other type, since it would have no stable key to look the row up by.
+fn primary_row_key(pk: &[String], data: &BTreeMap<String, Value>) -> ExecuteResult<RowKey> {
+    let mut parts = Vec::with_capacity(pk.len());
+    for column in pk {
+        parts.push(match data.get(column) {
+            None | Some(Value::Null) => return Err(ExecuteError::MissingColumn(column.clone())),
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Number(n)) if n.is_i64() || n.is_u64() => n.to_string(),
+            Some(other) => {
+                return Err(ExecuteError::TypeMismatch {
+                    expected: "TEXT, INTEGER or UUID primary key".into(),
+                    actual: other.to_string(),
+                })
+            }
+        });
+    }
+    Ok(row_key(&parts)?)
+}
 
-        let result = exec.execute("SELECT * FROM users").unwrap();
-        if let QueryResult::Select(rs) = result {
-            assert_eq!(rs.len(), 1);
+/// Check that the row a foreign key points at exists in its parent table.
+///
+/// As in SQL, a reference with a NULL in any of its columns points nowhere
+/// and is not checked. References to the parent's primary key are looked up
+/// by row key; any other columns need a scan of the parent.
+fn check_parent_exists(
+    repo: &GitRepository,
+    head: CommitId,
+    fk: &ForeignKey,
+    parent: &TableSchema,
+    data: &BTreeMap<String, Value>,
+) -> ExecuteResult<()> {
+    let mut values = BTreeMap::new();
+    for (column, parent_column) in fk.columns.iter().zip(&fk.references_columns) {
+        match data.get(column) {
+            None | Some(Value::Null) => return Ok(()),
+            Some(value) => values.insert(parent_column.clone(), value.clone()),
+        };
+    }
+
+    let parent_table = TableName::new(&parent.name)?;
+    let found = if fk.references_columns == parent.primary_key {
+        match primary_row_key(&parent.primary_key, &values) {
+            Ok(key) => repo.read_row(&parent_table, &key, head)?.is_some(),
+            Err(_) => false,
         }
+    } else {
+        repo.scan_table(&parent_table, head)?
+            .iter()
+            .any(|row| has_values(row, &values))
+    };
+
+    if found {
+        Ok(())
+    } else {
+        Err(ExecuteError::ForeignKeyViolation(fk.name.clone()))
     }
+}
 
-    #[test]
-    fn test_show_tables() {
-        let (mut exec, _dir) = setup();
+/// Delete a row after applying the ON DELETE action of each foreign key that
+/// references it, recursing into cascaded deletes.
+///
+/// Returns `None` if an earlier cascade in the same statement already
+/// deleted the row.
+fn delete_referenced(
+    repo: &GitRepository,
+    schemas: &[TableSchema],
+    table: &str,
+    row: &StorageRow,
+    head: CommitId,
+) -> ExecuteResult<Option<CommitId>> {
+    let table_name = TableName::new(table)?;
+    if repo.read_row(&table_name, &row.key, head)?.is_none() {
+        return Ok(None);
+    }
+    // Delete first, so a self-referencing row can't find itself as a child
+    let mut head = repo.delete_row(&table_name, &row.key, head, None)?;
 
-        exec.execute("CREATE TABLE users (id TEXT)").unwrap();
-        exec.execute("CREATE TABLE orders (id TEXT)").unwrap();
+    for child in schemas {
+        for fk in child.foreign_keys.iter().filter(|fk| fk.references_table == table) {
+            let mut values = BTreeMap::new();
+            for (column, parent_column) in fk.columns.iter().zip(&fk.references_columns) {
+                if let Some(value) = row.data.get(parent_column).filter(|v| !v.is_null()) {
+                    values.insert(column.clone(), value.clone());
+                }
+            }
+            if values.len() != fk.columns.len() {
+                continue;
+            }
 
-        let result = exec.execute("SHOW TABLES").unwrap();
-        if let QueryResult::Select(rs) = result {
-            assert_eq!(rs.len(), 2);
+            let child_table = TableName::new(&child.name)?;
+            let referencing: Vec<StorageRow> = repo.scan_table(&child_table, head)?
+                .into_iter()
+                .filter(|r| has_values(r, &values))
+                .collect();
+            if referencing.is_empty() {
+                continue;
+            }
+
+            match fk.on_delete {
+                ReferentialAction::NoAction | ReferentialAction::Restrict => {
+                    return Err(ExecuteError::ForeignKeyViolation(fk.name.clone()));
+                }
+                ReferentialAction::Cascade => {
+                    for r in &referencing {
+                        if let Some(new_head) = delete_referenced(repo, schemas, &child.name, r, head)? {
+                            head = new_head;
+                        }
+                    }
+                }
+                ReferentialAction::SetNull | ReferentialAction::SetDefault => {
+                    for r in referencing {
+                        let data = cleared_reference(child, fk, fk.on_delete, &r.data)?;
+                        head = repo.update_row(&child_table, r.with_update(data), head, None)?;
+                    }
+                }
+            }
         }
     }
 
-    #[test]
-    fn test_order_by_limit() {
-        let (mut exec, _dir) = setup();
+    Ok(Some(head))
+}
 
-        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT, age INTEGER)").unwrap();
-        exec.execute("INSERT INTO users (id, name, age) VALUES ('1', 'Alice', 30)").unwrap();
-        exec.execute("INSERT INTO users (id, name, age) VALUES ('2', 'Bob', 25)").unwrap();
-        exec.execute("INSERT INTO users (id, name, age) VALUES ('3', 'Charlie', 35)").unwrap();
+/// Apply the ON UPDATE action of each foreign key that references a row of
+/// `table` whose referenced columns change from `old` to `new`, recursing
+/// into cascaded updates.
+fn update_referenced(
+    repo: &GitRepository,
+    schemas: &[TableSchema],
+    table: &str,
+    old: &BTreeMap<String, Value>,
+    new: &BTreeMap<String, Value>,
+    mut head: CommitId,
+) -> ExecuteResult<CommitId> {
+    let value = |row: &BTreeMap<String, Value>, column: &String| row.get(column).cloned().unwrap_or(Value::Null);
 
-        let result = exec.execute("SELECT * FROM users ORDER BY age DESC LIMIT 2").unwrap();
-        if let QueryResult::Select(rs) = result {
-            assert_eq!(rs.len(), 2);
-            // Should be Charlie (35) then Alice (30)
-            assert_eq!(rs.rows[0].get("name").unwrap(), &Value::String("Charlie".into()));
-            assert_eq!(rs.rows[1].get("name").unwrap(), &Value::String("Alice".into()));
+    for child in schemas {
+        for fk in child.foreign_keys.iter().filter(|fk| fk.references_table == table) {
+            let changed = fk.references_columns.iter()
+                .any(|column| !values_equal(&value(old, column), &value(new, column)));
+            if !changed {
+                continue;
+            }
+            let mut values = BTreeMap::new();
+            for (column, parent_column) in fk.columns.iter().zip(&fk.references_columns) {
+                if let Some(v) = old.get(parent_column).filter(|v| !v.is_null()) {
+                    values.insert(column.clone(), v.clone());
+                }
+            }
+            if values.len() != fk.columns.len() {
+                continue;
+            }
+
+            let child_table = TableName::new(&child.name)?;
+            let referencing: Vec<StorageRow> = repo.scan_table(&child_table, head)?
+                .into_iter()
+                .filter(|r| has_values(r, &values))
+                .collect();
+            if referencing.is_empty() {
+                continue;
+            }
+
+            for r in referencing {
+                let data = match fk.on_update {
+                    ReferentialAction::NoAction | ReferentialAction::Restrict => {
+                        return Err(ExecuteError::ForeignKeyViolation(fk.name.clone()));
+                    }
+                    ReferentialAction::Cascade => {
+                        let mut data = r.data.clone();
+                        for (column, parent_column) in fk.columns.iter().zip(&fk.references_columns) {
+                            data.insert(column.clone(), value(new, parent_column));
+                        }
+                        child.validate_row(&Value::Object(data.clone().into_iter().collect()))?;
+                        data
+                    }
+                    ReferentialAction::SetNull | ReferentialAction::SetDefault => {
+                        cleared_reference(child, fk, fk.on_update, &r.data)?
+                    }
+                };
+
+                // The child's own key may be part of the reference
+                let new_key = if child.primary_key.is_empty() {
+                    r.key.clone()
+                } else {
+                    primary_row_key(&child.primary_key, &data)?
+                };
+                let old_data = r.data.clone();
+                if new_key == r.key {
+                    head = repo.update_row(&child_table, r.with_update(data.clone()), head, None)?;
+                } else {
+                    head = repo.delete_row(&child_table, &r.key, head, None)?;
+                    let mut moved = r.with_update(data.clone());
+                    moved.key = new_key;
+                    head = insert_unique(repo, &child_table, moved, head)?;
+                }
+                head = update_referenced(repo, schemas, &child.name, &old_data, &data, head)?;
+            }
         }
     }
+
+    Ok(head)
+}
+
+/// A referencing row with the columns of `fk` set to NULL, or to their
+/// defaults for SET DEFAULT, checked against the child's schema.
+fn cleared_reference(
+    child: &TableSchema,
+    fk: &ForeignKey,
+    action: ReferentialAction,
+    data: &BTreeMap<String, Value>,
+) -> ExecuteResult<BTreeMap<String, Value>> {
+    let mut data = data.clone();
+    for column in &fk.columns {
+        data.remove(column);
+    }
+    let mut obj: serde_json::Map<String, Value> = data.into_iter().collect();
+    if action == ReferentialAction::SetDefault {
+        obj = match child.apply_defaults(&Value::Object(obj))? {
+            Value::Object(obj) => obj,
+            _ => unreachable!("apply_defaults returns an object"),
+        };
+    }
+    for column in &fk.columns {
+        obj.entry(column.clone()).or_insert(Value::Null);
+    }
+    child.validate_row(&Value::Object(obj.clone()))?;
+    Ok(obj.into_iter().collect())
+}
+
+/// Check if a row holds the given column values.
+fn has_values(row: &StorageRow, values: &BTreeMap<String, Value>) -> bool {
+    values.iter().all(|(column, value)| {
+        row.data.get(column).is_some_and(|v| values_equal(v, value))
+    })
+}
+/// Write a new row built from `data`, after filling in defaults and checking
+/// it against the schema and its foreign keys.
+///
+/// A row whose key is taken fails with a duplicate key error, unless
+/// `on_conflict` says to skip it, returning `head` unchanged, or to replace
+/// the existing row.
+fn insert_data(
+    repo: &GitRepository,
+    table: &TableName,
+    schema: &TableSchema,
+    parents: &[(ForeignKey, TableSchema)],
+    data: BTreeMap<String, Value>,
+    on_conflict: Option<InsertConflict>,
+    head: CommitId,
+) -> ExecuteResult<CommitId> {
+    // Apply defaults
+    let row_value = Value::Object(
+        data.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    );
+    let with_defaults = schema.apply_defaults(&row_value)?;
+    let mut data: BTreeMap<String, Value> = with_defaults
+        .as_object()
+        .map(|o| o.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+        .unwrap_or_default();
+
+    // Validate
+    schema.validate_row(&Value::Object(
+        data.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    ))?;
+
+    for (fk, parent) in parents {
+        check_parent_exists(repo, head, fk, parent, &data)?;
+    }
+
+    let generated = schema.primary_key.is_empty()
+        || (schema.schemaless && data.get(SCHEMALESS_KEY).is_none_or(Value::is_null));
+    if !generated {
+        let key = primary_row_key(&schema.primary_key, &data)?;
+        let existing = match on_conflict {
+            Some(_) => repo.read_row(table, &key, head)?,
+            None => None,
+        };
+        return match (existing, on_conflict) {
+            (Some(_), Some(InsertConflict::Ignore)) => Ok(head),
+            (Some(existing), Some(InsertConflict::Replace)) => {
+                Ok(repo.update_row(table, existing.with_update(data), head, None)?)
+            }
+            _ => insert_unique(repo, table, StorageRow::new(key, data), head),
+        };
+    }
+
+    // Generate row key
+    let (key, sequence) = match schema.key_strategy {
+        KeyStrategy::Ulid => (RowKey::generate(), None),
+        KeyStrategy::UuidV7 => (RowKey::generate_uuid_v7(), None),
+        KeyStrategy::UuidV4 => (RowKey::generate_uuid_v4(), None),
+        KeyStrategy::Sequential => {
+            let n = last_in_sequence(repo, table, head)? + 1;
+            (RowKey::sequential(n), Some(n))
+        }
+    };
+    if schema.schemaless {
+        let id = sequence.map_or_else(|| Value::from(key.as_str()), Value::from);
+        data.insert(SCHEMALESS_KEY.to_string(), id);
+    }
+    let row = StorageRow::new(key, data);
+
+    // The sequence moves on in the same commit as the row that used it
+    let Some(n) = sequence else {
+        return insert_unique(repo, table, row, head);
+    };
+    let counter = StorageRow::new(
+        RowKey::new(table.as_str())?,
+        BTreeMap::from([(SEQUENCE_COLUMN.to_string(), Value::from(n))]),
+    );
+    let sequences = TableName::new(SEQUENCE_DIR)?;
+    repo.insert_row_with(table, row, &sequences, counter, head, None).map_err(duplicate_key)
+}
+
+/// The last key handed out to a table with sequential keys, 0 if none has been.
+fn last_in_sequence(repo: &GitRepository, table: &TableName, head: CommitId) -> ExecuteResult<u64> {
+    let sequences = TableName::new(SEQUENCE_DIR)?;
+    if !repo.table_exists(&sequences, head)? {
+        return Ok(0);
+    }
+    let counter = repo.read_row(&sequences, &RowKey::new(table.as_str())?, head)?;
+    Ok(counter.and_then(|row| row.data.get(SEQUENCE_COLUMN).and_then(Value::as_u64)).unwrap_or(0))
+}
+
+/// Insert a row, reporting an existing key as a duplicate key error.
+fn insert_unique(
+    repo: &GitRepository,
+    table: &TableName,
+    row: StorageRow,
+    head: CommitId,
+) -> ExecuteResult<CommitId> {
+    repo.insert_row(table, row, head, None).map_err(duplicate_key)
+}
+
+/// Report an insert over an existing row as a duplicate key error.
+fn duplicate_key(e: StorageError) -> ExecuteError {
+    match e {
+        StorageError::RowAlreadyExists { key, .. } => ExecuteError::DuplicateKey(key.into_string()),
+        e => e.into(),
+    }
+}
+
+/// Bind positional parameters into a statement's LIMIT and OFFSET.
+fn bind_params(stmt: &mut Statement, params: &[Value]) -> ExecuteResult<()> {
+    if let Statement::Explain { statement, .. } = stmt {
+        return bind_params(statement, params);
+    }
+
+    let mut counts = Vec::new();
+    let mut pending = match stmt {
+        Statement::Select(select) => vec![select],
+        _ => Vec::new(),
+    };
+    // Derived tables can take parameters in their LIMIT and OFFSET too
+    while let Some(Select { limit, offset, from, .. }) = pending.pop() {
+        counts.extend(limit.iter_mut());
+        counts.extend(offset.iter_mut());
+        pending.extend(derived_tables(from));
+    }
+
+    let expected = counts.iter()
+        .filter_map(|c| match c {
+            RowCount::Parameter(n) => Some(*n),
+            RowCount::Count(_) => None,
+        })
+        .max()
+        .unwrap_or(0);
+    if params.len() != expected {
+        return Err(ExecuteError::InvalidParameter(format!(
+            "expected {} parameters, got {}",
+            expected,
+            params.len()
+        )));
+    }
+
+    for count in counts {
+        if let RowCount::Parameter(n) = *count {
+            let value = &params[n - 1];
+            let bound = value.as_u64()
+                .and_then(|v| usize::try_from(v).ok())
+                .ok_or_else(|| ExecuteError::InvalidParameter(format!(
+                    "${} must be a non-negative integer, got {}",
+                    n, value
+                )))?;
+            *count = RowCount::Count(bound);
+        }
+    }
+    Ok(())
+}
+
+/// Turn a physical plan node into the operators that run it.
+///
+/// Operators without an implementation of their own run as their in-memory
+/// equivalent: a merge join hashes, a streaming aggregate groups by hash and
+/// an index scan reads the table. Every sort, external or not, and every
+/// aggregate spills to disk once its rows outgrow `spill`.
+///
+/// With `versions`, scanned rows carry their [`VERSION_COLUMN`]. Scans and
+/// joins stop once `cancel` is cancelled.
+pub(crate) fn build_operator(
+    node: &PhysicalPlanNode,
+    repo: &Arc<RwLock<GitRepository>>,
+    versions: bool,
+    cancel: &CancelToken,
+    spill: &SpillConfig,
+) -> ExecuteResult<Box<dyn Operator>> {
+    let child = |i: usize| -> ExecuteResult<Box<dyn Operator>> {
+        let input = node.children.get(i)
+            .ok_or_else(|| ExecuteError::Internal(format!("plan node is missing input {}", i)))?;
+        build_operator(input, repo, versions, cancel, spill)
+    };
+    let scan = |scan: ScanOperator| scan.with_cancel(cancel.clone());
+    // Joined rows name their columns `table.column`
+    let join_input = |i: usize| -> ExecuteResult<Box<dyn Operator>> {
+        let op = child(i)?;
+        Ok(match node.children.get(i).and_then(|c| scanned_table(c)) {
+            Some(table) => Box::new(QualifyOperator::new(op, table)),
+            None => op,
+        })
+    };
+
+    Ok(match &node.operator {
+        // The optimizer folds a constant-false WHERE into a scan that can't
+        // match anything, so there's nothing to read
+        PhysicalOperator::SeqScan { predicate: Some(Expr::Literal(LiteralValue::Boolean(false))), .. } => {
+            Box::new(ScanOperator::new(Vec::new()))
+        }
+        // A pushed-down WHERE can pick rows by primary key, and is checked
+        // by the scan as it reads
+        PhysicalOperator::SeqScan { table, predicate, .. } => {
+            let scan = scan(scan_table(repo, table, predicate.as_ref(), versions)?);
+            match predicate {
+                Some(predicate) => Box::new(scan.with_predicate(predicate.clone())),
+                None => Box::new(scan),
+            }
+        }
+        PhysicalOperator::IndexScan { table, .. } => Box::new(scan(scan_table(repo, table, None, versions)?)),
+        PhysicalOperator::IndexLookup { table, index, keys } => {
+            Box::new(scan(lookup_table(repo, table, index, keys, versions)?))
+        }
+        PhysicalOperator::Filter { predicate } => {
+            // A filter straight over a scan can use the primary key to read
+            // only the rows it names
+            let source = match node.children.first().map(|c| &c.operator) {
+                Some(PhysicalOperator::SeqScan { table, predicate: None, .. }) => {
+                    Box::new(scan(scan_table(repo, table, Some(predicate), versions)?))
+                }
+                _ => child(0)?,
+            };
+            Box::new(FilterOperator::new(source, predicate.clone()))
+        }
+        PhysicalOperator::Project { columns, expressions } => {
+            let columns = columns.iter()
+                .map(|name| {
+                    if let Some((expr, _)) = expressions.iter().find(|(_, n)| n == name) {
+                        SelectColumn::Expr { expr: expr.clone(), alias: Some(name.clone()) }
+                    } else if name == "*" {
+                        SelectColumn::Wildcard
+                    } else if let Some(table) = name.strip_suffix(".*") {
+                        SelectColumn::QualifiedWildcard(table.to_string())
+                    } else {
+                        SelectColumn::Column(name.clone())
+                    }
+                })
+                .collect();
+            Box::new(ProjectOperator::new(child(0)?, columns))
+        }
+        PhysicalOperator::NestedLoopJoin { join_type, condition } => {
+            let condition = match condition {
+                Some(expr) => JoinCondition::On(expr.clone()),
+                None => JoinCondition::Always,
+            };
+            join(node, repo, join_type, join_input(0)?, join_input(1)?, condition, cancel)?
+        }
+        PhysicalOperator::HashJoin { join_type, left_keys, right_keys }
+        | PhysicalOperator::MergeJoin { join_type, left_keys, right_keys } => {
+            let condition = JoinCondition::Keys { left: left_keys.clone(), right: right_keys.clone() };
+            join(node, repo, join_type, join_input(0)?, join_input(1)?, condition, cancel)?
+        }
+        PhysicalOperator::Sort { order } | PhysicalOperator::ExternalSort { order, .. } => {
+            let order_by = order.iter()
+                .map(|spec| OrderBy {
+                    column: spec.column.clone(),
+                    ascending: spec.direction == SortDirection::Ascending,
+                })
+                .collect();
+            // Whether rows spill depends on how many there turn out to be,
+            // not the estimate, but an external sort's budget still holds
+            let spill = match &node.operator {
+                PhysicalOperator::ExternalSort { memory_limit, .. } => {
+                    spill.clone().with_max_bytes(spill.max_bytes.min(*memory_limit))
+                }
+                _ => spill.clone(),
+            };
+            Box::new(SortOperator::new(child(0)?, order_by).with_spill(spill))
+        }
+        PhysicalOperator::Limit { limit, offset } => {
+            Box::new(LimitOperator::new(child(0)?, *limit, offset.unwrap_or(0)))
+        }
+        PhysicalOperator::HashAggregate { group_by, aggregates }
+        | PhysicalOperator::StreamAggregate { group_by, aggregates } => {
+            // A lone COUNT(*) only needs the number of rows, not the rows
+            let counts_rows = |a: &PhysicalAggregate| {
+                a.function == AggregatePhysical::Count && a.input_column.is_none() && !a.distinct
+            };
+            let count = match node.children.first() {
+                Some(input) if group_by.is_empty() && aggregates.iter().all(counts_rows) => {
+                    count_rows(input, repo, versions, cancel, spill)?
+                }
+                _ => None,
+            };
+            match count {
+                Some(count) => {
+                    let row = aggregates.iter()
+                        .map(|a| (a.output_column.clone(), Value::from(count)))
+                        .collect();
+                    Box::new(ScanOperator::new(vec![row]))
+                }
+                None => Box::new(
+                    AggregateOperator::new(child(0)?, group_by.clone(), aggregates.clone())
+                        .with_spill(spill.clone()),
+                ),
+            }
+        }
+        PhysicalOperator::HashDistinct => Box::new(DistinctOperator::new(child(0)?)),
+        PhysicalOperator::Append => {
+            let sources = (0..node.children.len()).map(child).collect::<ExecuteResult<_>>()?;
+            Box::new(AppendOperator::new(sources))
+        }
+        // A derived table's rows come out of its own query's operators as is
+        PhysicalOperator::SubqueryScan { .. } => child(0)?,
+    })
+}
+
+/// How many rows a plan subtree reading one table would produce, counted
+/// without keeping them, or `None` if it does more than read and filter.
+///
+/// A WHERE that picks rows by primary key reads just those rows; anything
+/// else is counted by storage as it scans.
+fn count_rows(
+    node: &PhysicalPlanNode,
+    repo: &Arc<RwLock<GitRepository>>,
+    versions: bool,
+    cancel: &CancelToken,
+    spill: &SpillConfig,
+) -> ExecuteResult<Option<usize>> {
+    let scan = match (&node.operator, node.children.first().map(|c| &c.operator)) {
+        // A folded constant-false WHERE, with no table behind it
+        (PhysicalOperator::SeqScan { predicate: Some(Expr::Literal(LiteralValue::Boolean(false))), .. }, _) => {
+            return Ok(Some(0));
+        }
+        (PhysicalOperator::SeqScan { table, predicate, .. }, _) => Some((table, predicate.as_ref())),
+        (PhysicalOperator::Filter { predicate }, Some(PhysicalOperator::SeqScan { table, predicate: None, .. })) => {
+            Some((table, Some(predicate)))
+        }
+        (PhysicalOperator::Filter { .. }, Some(PhysicalOperator::IndexLookup { .. })) => None,
+        _ => return Ok(None),
+    };
+
+    if let Some((table, predicate)) = scan {
+        let (schema, _) = schema_and_columns(repo, table, None);
+        let by_key = predicate
+            .zip(schema.as_ref())
+            .is_some_and(|(p, schema)| primary_key_lookup(p, schema).is_some());
+        // Storage doesn't know about `_version`, so that needs the scan too
+        if !by_key && !versions {
+            cancel.check()?;
+            let repo = repo.read();
+            let (table, head) = (TableName::new(table)?, repo.head()?);
+            return Ok(Some(match predicate {
+                Some(predicate) => repo.count_where(&table, head, predicate)?,
+                None => repo.count_rows(&table, head)?,
+            }));
+        }
+    }
+
+    let mut rows = build_operator(node, repo, versions, cancel, spill)?;
+    let mut count = 0;
+    while rows.next_row()?.is_some() {
+        count += 1;
+    }
+    Ok(Some(count))
+}
+
+fn join(
+    node: &PhysicalPlanNode,
+    repo: &Arc<RwLock<GitRepository>>,
+    join_type: &JoinPhysicalType,
+    left: Box<dyn Operator>,
+    right: Box<dyn Operator>,
+    condition: JoinCondition,
+    cancel: &CancelToken,
+) -> ExecuteResult<Box<dyn Operator>> {
+    let kind = match join_type {
+        JoinPhysicalType::Inner | JoinPhysicalType::Cross => JoinKind::Inner,
+        JoinPhysicalType::LeftOuter => JoinKind::Left,
+        JoinPhysicalType::RightOuter => JoinKind::Right,
+        JoinPhysicalType::FullOuter => JoinKind::Full,
+    };
+    let columns = |i: usize| table_columns(repo, node.children.get(i).and_then(|c| scanned_table(c)));
+    Ok(Box::new(
+        JoinOperator::new(left, right, condition)
+            .kind(kind)
+            .with_columns(columns(0), columns(1))
+            .with_cancel(cancel.clone()),
+    ))
+}
+
+/// The qualified columns of a join input's table, from its schema.
+fn table_columns(repo: &Arc<RwLock<GitRepository>>, table: Option<&str>) -> Vec<String> {
+    let Some((table, schema)) = table.and_then(|t| Some((t, Catalog::new(repo.clone()).get_table(t).ok()?))) else {
+        return Vec::new();
+    };
+    schema.column_names()
+        .into_iter()
+        .map(|column| format!("{}.{}", table, column))
+        .collect()
+}
+
+/// The table a plan subtree reads, if it reads exactly one.
+fn scanned_table(node: &PhysicalPlanNode) -> Option<&str> {
+    match &node.operator {
+        PhysicalOperator::SeqScan { table, .. }
+        | PhysicalOperator::IndexScan { table, .. }
+        | PhysicalOperator::IndexLookup { table, .. } => Some(table),
+        PhysicalOperator::SubqueryScan { alias } => Some(alias),
+        _ if node.children.len() == 1 => scanned_table(&node.children[0]),
+        _ => None,
+    }
+}
+
+/// The plan of the derived table a query reads from, under its single-input
+/// operators.
+fn subquery_scan(node: &PhysicalPlanNode) -> Option<&PhysicalPlanNode> {
+    match &node.operator {
+        PhysicalOperator::SubqueryScan { .. } => node.children.first().map(|c| c.as_ref()),
+        _ if node.children.len() == 1 => subquery_scan(&node.children[0]),
+        _ => None,
+    }
+}
+
+fn scan_table(
+    repo: &Arc<RwLock<GitRepository>>,
+    table: &str,
+    where_clause: Option<&Expr>,
+    versions: bool,
+) -> ExecuteResult<ScanOperator> {
+    let (schema, columns) = schema_and_columns(repo, table, where_clause);
+    let repo = repo.read();
+    let head = repo.head()?;
+    let table_name = TableName::new(table)?;
+
+    // A point lookup reads just its rows; anything else is read lazily so
+    // LIMIT can stop early
+    if let Some(keys) = where_clause.zip(schema.as_ref()).and_then(|(expr, schema)| primary_key_lookup(expr, schema)) {
+        let rows = read_keys(&repo, &table_name, head, keys)?;
+        let rows = rows.into_iter().map(|sr| scanned_row(sr, versions)).collect();
+        return Ok(ScanOperator::new(rows).with_columns(columns));
+    }
+    Ok(ScanOperator::lazy(repo.scan_iter(&table_name, head)?)
+        .with_versions(versions)
+        .with_columns(columns))
+}
+
+/// A table's schema and the columns its rows are read with, or nothing if
+/// it has no schema.
+///
+/// A schemaless table's rows also get the columns `reads` refers to, see
+/// [`read_columns`].
+fn schema_and_columns(
+    repo: &Arc<RwLock<GitRepository>>,
+    table: &str,
+    reads: Option<&Expr>,
+) -> (Option<TableSchema>, Vec<String>) {
+    match Catalog::new(repo.clone()).get_table(table) {
+        Ok(schema) => {
+            let columns = read_columns(&schema, reads.into_iter());
+            (Some(schema), columns)
+        }
+        Err(_) => (None, Vec::new()),
+    }
+}
+
+/// Rows of a table stored under the given primary key values.
+///
+/// A value that can't be a key at all falls back to reading the whole
+/// table, leaving it to the filter above to pick the rows.
+fn lookup_table(
+    repo: &Arc<RwLock<GitRepository>>,
+    table: &str,
+    column: &str,
+    keys: &[Expr],
+    versions: bool,
+) -> ExecuteResult<ScanOperator> {
+    let (schema, columns) = schema_and_columns(repo, table, None);
+    let data_type = schema.as_ref().and_then(|schema| schema.get_column(column)).map(|c| &c.data_type);
+    let parts: Option<Vec<String>> = keys.iter()
+        .map(|key| match (key, data_type) {
+            (Expr::Literal(lit), Some(data_type)) => literal_key_part(lit, data_type),
+            _ => None,
+        })
+        .collect();
+    let Some(parts) = parts else {
+        return scan_table(repo, table, None, versions);
+    };
+
+    let repo = repo.read();
+    let head = repo.head()?;
+    let keys = parts.iter().filter_map(|part| row_key(std::slice::from_ref(part)).ok()).collect();
+    let rows = read_keys(&repo, &TableName::new(table)?, head, keys)?;
+    let rows = rows.into_iter().map(|sr| scanned_row(sr, versions)).collect();
+    Ok(ScanOperator::new(rows).with_columns(columns))
+}
+
+/// The error for a SELECT feature the executor can't run.
+fn unsupported(what: &str) -> ExecuteError {
+    PlanError::Unsupported(format!("SELECT does not support {}", what)).into()
+}
+
+/// A LIMIT or OFFSET count; parameters must already be bound.
+fn bound_row_count(count: RowCount) -> ExecuteResult<usize> {
+    count.count()
+        .ok_or_else(|| ExecuteError::InvalidParameter(format!("no value bound for {}", count)))
+}
+
+fn convert_sql_type(sql_type: &SqlDataType) -> DataType {
+    match sql_type {
+        SqlDataType::Text => DataType::Text,
+        SqlDataType::Integer => DataType::Integer,
+        SqlDataType::Float => DataType::Float,
+        SqlDataType::Decimal { precision, scale } => DataType::Decimal {
+            precision: *precision,
+            scale: *scale,
+        },
+        SqlDataType::Boolean => DataType::Boolean,
+        SqlDataType::Json => DataType::Json,
+        SqlDataType::Timestamp => DataType::Timestamp,
+        SqlDataType::Date => DataType::Date,
+        SqlDataType::Uuid => DataType::Uuid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::RefManager;
+    use tempfile::TempDir;
+
+    fn setup() -> (QueryExecutor, TempDir) {
+        let dir = TempDir::new().unwrap();
+        let repo = GitRepository::open_or_init(dir.path()).unwrap();
+        let executor = QueryExecutor::new(repo);
+        (executor, dir)
+    }
+
+    #[test]
+    fn test_create_table() {
+        let (mut exec, _dir) = setup();
+
+        let result = exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT NOT NULL)").unwrap();
+        assert!(matches!(result, QueryResult::Success { .. }));
+
+        // Verify in catalog
+        assert!(exec.catalog().table_exists("users"));
+    }
+
+    #[test]
+    fn test_insert_and_select() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT NOT NULL)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES ('2', 'Bob')").unwrap();
+
+        let result = exec.execute("SELECT * FROM users").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.len(), 2);
+        } else {
+            panic!("Expected Select result");
+        }
+    }
+
+    #[test]
+    fn test_insert_or_ignore_and_replace() {
+        let (mut exec, _dir) = setup();
+        let names = |exec: &mut QueryExecutor| match exec.execute("SELECT id, name FROM users").unwrap() {
+            QueryResult::Select(rs) => rs.rows.iter()
+                .map(|r| format!("{}={}", r["id"].as_str().unwrap(), r["name"].as_str().unwrap()))
+                .collect::<Vec<_>>(),
+            other => panic!("Expected Select result, got {:?}", other),
+        };
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT NOT NULL)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
+
+        // Running a seed twice only inserts what's new, the repeat included
+        let sql = "INSERT OR IGNORE INTO users (id, name) VALUES ('1', 'Ann'), ('2', 'Bob'), ('2', 'Bea')";
+        assert!(matches!(exec.execute(sql).unwrap(), QueryResult::Modified { rows_affected: 1 }));
+        assert!(matches!(exec.execute(sql).unwrap(), QueryResult::Modified { rows_affected: 0 }));
+        assert_eq!(names(&mut exec), vec!["1=Alice", "2=Bob"]);
+
+        let sql = "INSERT OR REPLACE INTO users (id, name) VALUES ('1', 'Ann'), ('3', 'Cy')";
+        assert!(matches!(exec.execute(sql).unwrap(), QueryResult::Modified { rows_affected: 2 }));
+        assert_eq!(names(&mut exec), vec!["1=Ann", "2=Bob", "3=Cy"]);
+
+        // Without a modifier a taken key still fails the statement
+        let result = exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Al')");
+        assert!(matches!(result, Err(ExecuteError::DuplicateKey(_))));
+    }
+
+    #[test]
+    fn test_insert_values_arity() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").unwrap();
+
+        let err = exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice'), ('2'), ('3', 'Cy')").unwrap_err();
+        assert!(matches!(err, ExecuteError::ValuesArity { row: 2, expected: 2, actual: 1 }));
+        assert_eq!(err.kind(), "values_arity");
+
+        // Without a column list the table's columns set the count
+        let err = exec.execute("INSERT INTO users VALUES ('1', 'Alice', 30)").unwrap_err();
+        assert!(matches!(err, ExecuteError::ValuesArity { row: 1, expected: 2, actual: 3 }));
+
+        // Nothing from a rejected statement was written
+        let QueryResult::Select(rs) = exec.execute("SELECT * FROM users").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert!(rs.is_empty());
+    }
+
+    #[test]
+    fn test_qualified_columns() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
+
+        let result = exec.execute("SELECT users.name FROM users").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.columns, vec!["name".to_string()]);
+            assert_eq!(rs.rows[0].get("name"), Some(&Value::String("Alice".into())));
+        } else {
+            panic!("Expected Select result");
+        }
+
+        let result = exec.execute("SELECT users.* FROM users").unwrap();
+        assert!(matches!(result, QueryResult::Select(rs) if rs.columns.len() == 2));
+
+        let result = exec.execute("SELECT orders.* FROM users");
+        assert!(matches!(result, Err(ExecuteError::TableNotFound(t)) if t == "orders"));
+    }
+
+    #[test]
+    fn test_select_where() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT, age INTEGER)").unwrap();
+        exec.execute("INSERT INTO users (id, name, age) VALUES ('1', 'Alice', 30)").unwrap();
+        exec.execute("INSERT INTO users (id, name, age) VALUES ('2', 'Bob', 25)").unwrap();
+        exec.execute("INSERT INTO users (id, name, age) VALUES ('3', 'Charlie', 35)").unwrap();
+
+        let result = exec.execute("SELECT * FROM users WHERE age > 28").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.len(), 2); // Alice and Charlie
+        } else {
+            panic!("Expected Select result");
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
+
+        let result = exec.execute("UPDATE users SET name = 'Alicia' WHERE id = '1'").unwrap();
+        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+
+        let result = exec.execute("SELECT * FROM users WHERE id = '1'").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.rows[0].get("name").unwrap(), &Value::String("Alicia".into()));
+        }
+    }
+
+    #[test]
+    fn test_delete() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES ('1', 'Alice')").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES ('2', 'Bob')").unwrap();
+
+        let result = exec.execute("DELETE FROM users WHERE id = '1'").unwrap();
+        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+
+        let result = exec.execute("SELECT * FROM users").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_show_tables() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT)").unwrap();
+        exec.execute("CREATE TABLE orders (id TEXT)").unwrap();
+
+        let result = exec.execute("SHOW TABLES").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_show_like() {
+        let (mut exec, _dir) = setup();
+        let names = |exec: &mut QueryExecutor, sql: &str, column: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.rows.iter().map(|r| r[column].as_str().unwrap().to_string()).collect::<Vec<_>>(),
+            other => panic!("Expected Select result, got {:?}", other),
+        };
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT, nickname TEXT)").unwrap();
+        exec.execute("CREATE TABLE user_roles (id TEXT PRIMARY KEY)").unwrap();
+        exec.execute("CREATE TABLE orders (id TEXT PRIMARY KEY)").unwrap();
+
+        assert_eq!(names(&mut exec, "SHOW TABLES LIKE 'user%'", "table_name"), vec!["user_roles", "users"]);
+        assert_eq!(names(&mut exec, "SHOW TABLES LIKE 'user\\_%'", "table_name"), vec!["user_roles"]);
+        assert!(names(&mut exec, "SHOW TABLES LIKE 'user\\'", "table_name").is_empty());
+        assert_eq!(names(&mut exec, "SHOW COLUMNS FROM users LIKE '%name'", "column"), vec!["name", "nickname"]);
+        assert_eq!(names(&mut exec, "SHOW COLUMNS FROM users", "column").len(), 3);
+    }
+
+    #[test]
+    fn test_order_by_limit() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT, age INTEGER)").unwrap();
+        exec.execute("INSERT INTO users (id, name, age) VALUES ('1', 'Alice', 30)").unwrap();
+        exec.execute("INSERT INTO users (id, name, age) VALUES ('2', 'Bob', 25)").unwrap();
+        exec.execute("INSERT INTO users (id, name, age) VALUES ('3', 'Charlie', 35)").unwrap();
+
+        let result = exec.execute("SELECT * FROM users ORDER BY age DESC LIMIT 2").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.len(), 2);
+            // Should be Charlie (35) then Alice (30)
+            assert_eq!(rs.rows[0].get("name").unwrap(), &Value::String("Charlie".into()));
+            assert_eq!(rs.rows[1].get("name").unwrap(), &Value::String("Alice".into()));
+        }
+    }
+
+    #[test]
+    fn test_order_by_spills() {
+        let (mut exec, _dir) = setup();
+        let spill_dir = TempDir::new().unwrap();
+        exec.set_spill(SpillConfig::default().with_max_rows(4).with_dir(spill_dir.path()));
+
+        exec.execute("CREATE TABLE nums (id INTEGER PRIMARY KEY, n INTEGER)").unwrap();
+        let values: Vec<String> = (0..50).map(|i| format!("({}, {})", i, (i * 7) % 10)).collect();
+        exec.execute(&format!("INSERT INTO nums (id, n) VALUES {}", values.join(", "))).unwrap();
+
+        let QueryResult::Select(rs) = exec.execute("SELECT id, n FROM nums ORDER BY n DESC LIMIT 20 OFFSET 3").unwrap() else {
+            panic!("Expected Select result");
+        };
+        let got: Vec<(i64, i64)> = rs.rows.iter().map(|r| (r["n"].as_i64().unwrap(), r["id"].as_i64().unwrap())).collect();
+        let mut expected: Vec<(i64, i64)> = (0..50).map(|i| ((i * 7) % 10, i)).collect();
+        expected.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        assert_eq!(got, expected[3..23]);
+        assert_eq!(std::fs::read_dir(spill_dir.path()).unwrap().count(), 0);
+
+        // Grouping spills too, and every group still comes out whole
+        let QueryResult::Select(rs) = exec.execute("SELECT n, COUNT(id) AS c FROM nums GROUP BY n ORDER BY n").unwrap() else {
+            panic!("Expected Select result");
+        };
+        let got: Vec<(i64, i64)> = rs.rows.iter().map(|r| (r["n"].as_i64().unwrap(), r["c"].as_i64().unwrap())).collect();
+        assert_eq!(got, (0..10).map(|n| (n, 5)).collect::<Vec<_>>());
+        assert_eq!(std::fs::read_dir(spill_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_explain() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, age INTEGER)").unwrap();
+        exec.execute("INSERT INTO users (id, age) VALUES ('a', 30), ('b', 25), ('c', 40)").unwrap();
+
+        let plan = |exec: &mut QueryExecutor, sql: &str, params: &[Value]| {
+            match exec.execute_with_params(sql, params).unwrap() {
+                QueryResult::Select(rs) => {
+                    assert_eq!(rs.columns, vec!["plan".to_string()]);
+                    rs.rows.iter().map(|r| r["plan"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+                }
+                other => panic!("expected rows, got {:?}", other),
+            }
+        };
+
+        let lines = plan(&mut exec, "EXPLAIN SELECT * FROM users WHERE age > 26", &[]);
+        assert!(lines.contains(&"=== Logical Plan ===".to_string()));
+        assert!(lines.contains(&"=== Physical Plan ===".to_string()));
+        assert!(!lines.iter().any(|l| l.starts_with("Actual Rows")));
+
+        let lines = plan(&mut exec, "EXPLAIN ANALYZE SELECT * FROM users WHERE age > 26 LIMIT ?", &[Value::from(5)]);
+        assert!(lines.contains(&"Actual Rows: 2".to_string()));
+        assert!(lines.iter().any(|l| l.starts_with("Execution Time: ")));
+
+        assert!(exec.execute("EXPLAIN UPDATE users SET age = 1").is_err());
+    }
+
+    #[test]
+    fn test_select_runs_planned_operators() {
+        let dir = TempDir::new().unwrap();
+        let repo = GitRepository::open_or_init(dir.path()).unwrap().with_cache(100);
+        let mut exec = QueryExecutor::new(repo.clone());
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT, age INTEGER)").unwrap();
+        exec.execute("INSERT INTO users (id, name, age) VALUES ('a', 'Ann', 30), ('b', 'Bob', 25)").unwrap();
+
+        // Constant folding turns the WHERE into an empty plan, so planning
+        // and naming the `*` columns read the schema and nothing else
+        let mut lookups = |sql: &str| {
+            let before = repo.cache_stats().unwrap();
+            match exec.execute(sql).unwrap() {
+                QueryResult::Select(rs) => assert!(rs.is_empty()),
+                other => panic!("expected rows, got {:?}", other),
+            }
+            let after = repo.cache_stats().unwrap();
+            after.hits + after.misses - before.hits - before.misses
+        };
+        assert_eq!(lookups("SELECT * FROM users WHERE FALSE"), 2);
+        assert!(lookups("SELECT * FROM users WHERE age > 100") > 4);
+        // An OR on the key becomes an IN list, looked up key by key: the
+        // schema for planning, the lookup's columns and the headers, then
+        // each key
+        assert!(lookups("SELECT * FROM users WHERE id = 'x' OR id = 'y'") <= 5);
+
+        // Sorting happens before projection, so ORDER BY can use any column
+        match exec.execute("SELECT name AS who FROM users ORDER BY age").unwrap() {
+            QueryResult::Select(rs) => {
+                assert_eq!(rs.columns, vec!["who".to_string()]);
+                let names: Vec<_> = rs.rows.iter().map(|r| r["who"].clone()).collect();
+                assert_eq!(names, vec![Value::from("Bob"), Value::from("Ann")]);
+            }
+            other => panic!("expected rows, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_build_operator() {
+        use crate::planner::SortSpec;
+
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, age INTEGER)").unwrap();
+        exec.execute("CREATE TABLE admins (id TEXT PRIMARY KEY, age INTEGER)").unwrap();
+        exec.execute("CREATE TABLE bans (id TEXT PRIMARY KEY, reason TEXT)").unwrap();
+        exec.execute("INSERT INTO users (id, age) VALUES ('a', 30), ('b', 25)").unwrap();
+        exec.execute("INSERT INTO admins (id, age) VALUES ('a', 30), ('c', 40)").unwrap();
+
+        let scan = |table: &str| {
+            Arc::new(PhysicalPlanNode::new(PhysicalOperator::SeqScan {
+                table: table.into(),
+                columns: None,
+                predicate: None,
+            }))
+        };
+        let run = |node: PhysicalPlanNode| {
+            let mut op = build_operator(&node, &exec.repo, false, &CancelToken::new(), &SpillConfig::default()).unwrap();
+            let mut rows = Vec::new();
+            while let Some(row) = op.next_row().unwrap() {
+                rows.push(row);
+            }
+            rows
+        };
+
+        // UNION: append, drop duplicates, then an external sort, small enough for memory
+        let union = PhysicalPlanNode::new(PhysicalOperator::Append)
+            .with_children(vec![scan("users"), scan("admins")]);
+        let distinct = PhysicalPlanNode::new(PhysicalOperator::HashDistinct).with_child(Arc::new(union));
+        let sorted = PhysicalPlanNode::new(PhysicalOperator::ExternalSort {
+            order: vec![SortSpec { column: "age".into(), direction: SortDirection::Descending, nulls_first: false }],
+            memory_limit: 1024,
+        })
+        .with_child(Arc::new(distinct));
+        let ids: Vec<_> = run(sorted).iter().map(|r| r["id"].clone()).collect();
+        assert_eq!(ids, vec![Value::from("c"), Value::from("a"), Value::from("b")]);
+
+        // Join inputs are qualified with their table
+        let join = PhysicalPlanNode::new(PhysicalOperator::HashJoin {
+            join_type: JoinPhysicalType::Inner,
+            left_keys: vec!["users.id".into()],
+            right_keys: vec!["admins.id".into()],
+        })
+        .with_children(vec![scan("users"), scan("admins")]);
+        let rows = run(join);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["admins.age"], Value::from(30));
+
+        // An outer join pads with NULL for every column in the other table's
+        // schema, even one with no rows at all
+        let left_join = PhysicalPlanNode::new(PhysicalOperator::HashJoin {
+            join_type: JoinPhysicalType::LeftOuter,
+            left_keys: vec!["users.id".into()],
+            right_keys: vec!["bans.id".into()],
+        })
+        .with_children(vec![scan("users"), scan("bans")]);
+        let rows = run(left_join);
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|r| r["bans.id"].is_null() && r["bans.reason"].is_null()));
+
+        // Aggregates run through SQL too, in SELECT order
+        match exec.execute("SELECT MAX(age) AS oldest, COUNT(*) FROM users").unwrap() {
+            QueryResult::Select(rs) => {
+                assert_eq!(rs.columns, vec!["oldest".to_string(), "COUNT(*)".to_string()]);
+                assert_eq!(rs.rows[0]["oldest"], Value::from(30));
+                assert_eq!(rs.rows[0]["COUNT(*)"], Value::from(2));
+            }
+            other => panic!("expected rows, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_group_by_order_by_aggregate() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE tasks (id INTEGER PRIMARY KEY, status TEXT, hours INTEGER)").unwrap();
+        exec.execute(
+            "INSERT INTO tasks (id, status, hours) VALUES \
+             (1, 'done', 3), (2, 'open', 1), (3, 'done', 2), (4, 'blocked', 5), (5, 'done', 1), (6, 'open', 4)",
+        ).unwrap();
+
+        // `c` only exists once rows are grouped
+        let QueryResult::Select(rs) = exec.execute(
+            "SELECT status, COUNT(*) AS c, SUM(hours) AS total FROM tasks GROUP BY status ORDER BY c DESC",
+        ).unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.columns, vec!["status", "c", "total"]);
+        let rows: Vec<(Value, Value, Value)> = rs.rows.iter()
+            .map(|r| (r["status"].clone(), r["c"].clone(), r["total"].clone()))
+            .collect();
+        assert_eq!(rows, vec![
+            (Value::from("done"), Value::from(3), Value::from(6)),
+            (Value::from("open"), Value::from(2), Value::from(5)),
+            (Value::from("blocked"), Value::from(1), Value::from(5)),
+        ]);
+
+        // Ties on the aggregate fall back to the group
+        let QueryResult::Select(rs) = exec.execute(
+            "SELECT status, SUM(hours) AS total FROM tasks GROUP BY status ORDER BY total DESC LIMIT 2 OFFSET 1",
+        ).unwrap() else {
+            panic!("Expected Select result");
+        };
+        let statuses: Vec<&Value> = rs.rows.iter().map(|r| &r["status"]).collect();
+        assert_eq!(statuses, vec!["blocked", "open"]);
+
+        let err = exec.execute("SELECT status, COUNT(*) FROM tasks GROUP BY status ORDER BY hours").unwrap_err();
+        assert_eq!(err.kind(), "invalid_grouping");
+        let err = exec.execute("SELECT hours, COUNT(*) FROM tasks GROUP BY status").unwrap_err();
+        assert_eq!(err.kind(), "invalid_grouping");
+    }
+
+    #[test]
+    fn test_where_group_by_having_order() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE emp (id INTEGER PRIMARY KEY, dept TEXT, salary INTEGER, active BOOLEAN)").unwrap();
+        exec.execute(
+            "INSERT INTO emp (id, dept, salary, active) VALUES \
+             (1, 'eng', 800, true), (2, 'eng', 700, true), (3, 'eng', 5000, false), \
+             (4, 'ops', 600, true), (5, 'ops', 300, false), \
+             (6, 'sales', 900, true), (7, 'sales', 400, true), \
+             (8, 'hr', 1200, true)",
+        ).unwrap();
+
+        // WHERE drops the inactive rows before grouping, so ops is left with
+        // 600 and fails HAVING even though all of its rows add up to 900
+        let sql = "SELECT dept, SUM(salary) FROM emp WHERE active GROUP BY dept \
+                   HAVING SUM(salary) > 1000 ORDER BY dept LIMIT 5";
+        let QueryResult::Select(rs) = exec.execute(sql).unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.columns, vec!["dept", "SUM(salary)"]);
+        let rows: Vec<(&Value, &Value)> = rs.rows.iter().map(|r| (&r["dept"], &r["SUM(salary)"])).collect();
+        assert_eq!(rows, vec![
+            (&Value::from("eng"), &Value::from(1500)),
+            (&Value::from("hr"), &Value::from(1200)),
+            (&Value::from("sales"), &Value::from(1300)),
+        ]);
+
+        // HAVING can use aggregates SELECT doesn't, and aliases it does;
+        // the extra aggregate isn't part of the result
+        let QueryResult::Select(rs) = exec.execute(
+            "SELECT dept, COUNT(*) AS n FROM emp GROUP BY dept HAVING MAX(salary) < 1000 AND n > 1",
+        ).unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.columns, vec!["dept", "n"]);
+        assert_eq!(rs.rows.len(), 2);
+        assert!(rs.rows.iter().all(|r| r.len() == 2));
+
+        // Without GROUP BY, HAVING filters the one group
+        let QueryResult::Select(rs) = exec.execute("SELECT COUNT(*) FROM emp HAVING COUNT(*) > 100").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert!(rs.rows.is_empty());
+
+        let err = exec.execute("SELECT dept FROM emp GROUP BY dept HAVING salary > 1").unwrap_err();
+        assert_eq!(err.kind(), "invalid_grouping");
+    }
+
+    #[test]
+    fn test_existence_check_reads_prefix() {
+        // The read cache counts every row loaded, standing in for an
+        // instrumented scan
+        let dir = TempDir::new().unwrap();
+        let repo = GitRepository::open_or_init(dir.path()).unwrap().with_cache(4096);
+        let mut exec = QueryExecutor::new(repo);
+        exec.execute("CREATE TABLE events (id INTEGER PRIMARY KEY, kind TEXT)").unwrap();
+        exec.execute("CREATE TABLE flags (id INTEGER PRIMARY KEY)").unwrap();
+        let values: Vec<String> = (0..500).map(|i| format!("({}, '{}')", i, if i % 2 == 0 { "a" } else { "b" })).collect();
+        exec.execute(&format!("INSERT INTO events (id, kind) VALUES {}", values.join(", "))).unwrap();
+        exec.execute("INSERT INTO flags (id) VALUES (1)").unwrap();
+        let rows_read = |exec: &mut QueryExecutor, sql: &str| {
+            exec.repo.read().reset_cache_stats();
+            let result = exec.execute(sql).unwrap();
+            let stats = exec.repo.read().cache_stats().unwrap();
+            (result, stats.hits + stats.misses)
+        };
+
+        let (result, read) = rows_read(&mut exec, "SELECT 1 FROM events WHERE kind = 'b' LIMIT 1");
+        let QueryResult::Select(rs) = result else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.rows.len(), 1);
+        assert!(read < 20, "read {} rows for one match", read);
+
+        // EXISTS stops at the first match too, even when the subquery asks for more
+        let (result, read) = rows_read(&mut exec, "SELECT id FROM flags WHERE EXISTS (SELECT * FROM events WHERE kind = 'b')");
+        let QueryResult::Select(rs) = result else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.rows.len(), 1);
+        assert!(read < 20, "read {} rows for one match", read);
+
+        let QueryResult::Select(rs) = exec.execute("SELECT id FROM flags WHERE NOT EXISTS (SELECT id FROM events WHERE kind = 'c')").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.rows.len(), 1);
+        let QueryResult::Select(rs) = exec.execute("SELECT id FROM flags WHERE EXISTS (SELECT id FROM events WHERE id > 1000)").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert!(rs.rows.is_empty());
+    }
+
+    #[test]
+    fn test_transactional_ddl() {
+        let (mut exec, _dir) = setup();
+        // What other sessions see: the schema and the table on main
+        let on_main = |exec: &QueryExecutor, name: &str| {
+            let repo = exec.main.read();
+            let stored = repo.table_exists(&TableName::new(name).unwrap(), repo.head().unwrap()).unwrap();
+            let schema = Catalog::new(exec.main.clone()).table_exists(name);
+            assert_eq!(stored, schema, "table and schema of {} disagree", name);
+            stored
+        };
+
+        // Rolled back: no table and no schema
+        exec.execute("BEGIN").unwrap();
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").unwrap();
+        exec.execute("INSERT INTO users (id) VALUES ('a')").unwrap();
+        assert!(exec.catalog().table_exists("users"));
+        assert!(!on_main(&exec, "users"));
+        exec.execute("ROLLBACK").unwrap();
+        assert!(!exec.catalog().table_exists("users"));
+        assert!(!on_main(&exec, "users"));
+
+        // Committed: both appear on main, with the rows written after them
+        exec.execute("BEGIN").unwrap();
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").unwrap();
+        assert!(exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").is_err());
+        exec.execute("INSERT INTO users (id) VALUES ('a')").unwrap();
+        exec.execute("CREATE TABLE scratch (id TEXT)").unwrap();
+        exec.execute("DROP TABLE scratch").unwrap();
+        exec.execute("COMMIT").unwrap();
+        assert!(on_main(&exec, "users"));
+        assert!(!on_main(&exec, "scratch"));
+        match exec.execute("SELECT id FROM users").unwrap() {
+            QueryResult::Select(rs) => assert_eq!(rs.rows.len(), 1),
+            other => panic!("expected rows, got {:?}", other),
+        }
+
+        // A dropped table stays on main until COMMIT
+        exec.execute("BEGIN").unwrap();
+        exec.execute("DROP TABLE users").unwrap();
+        assert!(!exec.catalog().table_exists("users"));
+        assert!(on_main(&exec, "users"));
+        exec.execute("ROLLBACK").unwrap();
+        assert!(exec.catalog().table_exists("users"));
+
+        exec.execute("BEGIN").unwrap();
+        exec.execute("DROP TABLE users").unwrap();
+        exec.execute("COMMIT").unwrap();
+        assert!(!on_main(&exec, "users"));
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY)").unwrap();
+        exec.execute("INSERT INTO users (id) VALUES ('a'), ('b')").unwrap();
+        let main = exec.main.clone();
+        let commits = || {
+            let repo = main.read();
+            repo.count_commits(repo.head().unwrap()).unwrap()
+        };
+        let before = commits();
+        assert!(before > 1);
+
+        // Plain VACUUM only packs
+        exec.execute("VACUUM").unwrap();
+        assert_eq!(commits(), before);
+
+        exec.execute("BEGIN").unwrap();
+        assert!(exec.execute("VACUUM FULL").is_err());
+        exec.execute("ROLLBACK").unwrap();
+
+        let report = exec.vacuum(true).unwrap();
+        assert!(report.root.is_some());
+        assert_eq!(commits(), 1);
+        match exec.execute("SELECT id FROM users").unwrap() {
+            QueryResult::Select(rs) => assert_eq!(rs.rows.len(), 2),
+            other => panic!("expected rows, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_limit_offset_edges() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, age INTEGER)").unwrap();
+        exec.execute("INSERT INTO users (id, age) VALUES ('a', 30), ('b', 25), ('c', 30), ('d', 25)").unwrap();
+
+        let ids = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.rows.iter().map(|r| r["id"].clone()).collect::<Vec<_>>(),
+            other => panic!("expected rows, got {:?}", other),
+        };
+
+        assert!(ids(&mut exec, "SELECT * FROM users LIMIT 0").is_empty());
+        assert!(ids(&mut exec, "SELECT * FROM users LIMIT 10 OFFSET 1000000").is_empty());
+        assert!(ids(&mut exec, "SELECT * FROM users ORDER BY age OFFSET 4").is_empty());
+        assert_eq!(ids(&mut exec, "SELECT * FROM users OFFSET 3"), vec![Value::from("d")]);
+
+        // Ties on age are broken by primary key, so pages never overlap
+        let first = ids(&mut exec, "SELECT * FROM users ORDER BY age LIMIT 2");
+        let second = ids(&mut exec, "SELECT * FROM users ORDER BY age LIMIT 2 OFFSET 2");
+        assert_eq!(first, vec![Value::from("b"), Value::from("d")]);
+        assert_eq!(second, vec![Value::from("a"), Value::from("c")]);
+    }
+
+    #[test]
+    fn test_page_after() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        for id in [9, 10, 2, 1, 30] {
+            exec.execute(&format!("INSERT INTO items (id, name) VALUES ({}, 'item{}')", id, id)).unwrap();
+        }
+
+        // Integer keys page in numeric order, not by their text
+        let page = exec.page_after("items", None, 2).unwrap();
+        let ids: Vec<_> = page.rows.iter().map(|r| r["id"].clone()).collect();
+        assert_eq!(ids, vec![Value::from(1), Value::from(2)]);
+        assert_eq!(page.next, Some(vec![Value::from(2)]));
+
+        // A row inserted before the cursor doesn't shift the next page
+        exec.execute("INSERT INTO items (id, name) VALUES (0, 'item0')").unwrap();
+        let page = exec.page_after("items", page.next.as_deref(), 2).unwrap();
+        let ids: Vec<_> = page.rows.iter().map(|r| r["id"].clone()).collect();
+        assert_eq!(ids, vec![Value::from(9), Value::from(10)]);
+
+        let page = exec.page_after("items", page.next.as_deref(), 2).unwrap();
+        assert_eq!(page.rows.len(), 1);
+        assert_eq!(page.next, None);
+
+        assert!(exec.page_after("items", Some(&[Value::from(1), Value::from(2)]), 2).is_err());
+    }
+
+    #[test]
+    fn test_max_result_rows() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE items (id INTEGER PRIMARY KEY)").unwrap();
+        exec.execute("INSERT INTO items (id) VALUES (1), (2), (3)").unwrap();
+        exec.set_max_result_rows(Some(2));
+
+        let err = exec.execute("SELECT * FROM items").unwrap_err();
+        assert!(matches!(err, ExecuteError::ResultTooLarge(2)));
+        assert_eq!(err.kind(), "result_too_large");
+
+        // Under the cap, or with an explicit LIMIT, queries run as usual
+        let count = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.len(),
+            other => panic!("expected rows, got {:?}", other),
+        };
+        assert_eq!(count(&mut exec, "SELECT * FROM items WHERE id > 1"), 2);
+        assert_eq!(count(&mut exec, "SELECT COUNT(*) FROM items"), 1);
+        assert_eq!(count(&mut exec, "SELECT * FROM items LIMIT 100"), 3);
+
+        exec.set_max_result_rows(None);
+        assert_eq!(count(&mut exec, "SELECT * FROM items"), 3);
+    }
+
+    #[test]
+    fn test_page_after_composite_key() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE grid (x INTEGER, y INTEGER, PRIMARY KEY (x, y))").unwrap();
+        exec.execute("INSERT INTO grid (x, y) VALUES (1, 2), (2, 1), (1, 1), (2, 2)").unwrap();
+
+        let mut seen = Vec::new();
+        let mut after = None;
+        loop {
+            let page = exec.page_after("grid", after.as_deref(), 3).unwrap();
+            seen.extend(page.rows.iter().map(|r| (r["x"].clone(), r["y"].clone())));
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+        let expected: Vec<_> = [(1, 1), (1, 2), (2, 1), (2, 2)]
+            .into_iter()
+            .map(|(x, y)| (Value::from(x), Value::from(y)))
+            .collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn test_limit_offset_params() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        for (id, name) in [(1, "Alice"), (2, "Bob"), (3, "Charlie")] {
+            exec.execute(&format!("INSERT INTO users (id, name) VALUES ({}, '{}')", id, name)).unwrap();
+        }
+
+        let sql = "SELECT name FROM users ORDER BY id LIMIT ? OFFSET ?";
+        let result = exec.execute_with_params(sql, &[Value::from(1), Value::from(1)]).unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.len(), 1);
+            assert_eq!(rs.rows[0].get("name").unwrap(), &Value::String("Bob".into()));
+        } else {
+            panic!("Expected Select");
+        }
+
+        for params in [vec![Value::from(1)], vec![Value::from(-1), Value::from(0)], vec![Value::from("2"), Value::from(0)]] {
+            let err = exec.execute_with_params(sql, &params).unwrap_err();
+            assert!(matches!(err, ExecuteError::InvalidParameter(_)), "{:?}", params);
+        }
+        assert!(matches!(exec.execute(sql), Err(ExecuteError::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn test_json_access() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE people (id INTEGER PRIMARY KEY, data JSON)").unwrap();
+        exec.execute(r#"INSERT INTO people (id, data) VALUES (1, '{"city": "NYC", "tags": ["a", "b"], "age": 30}')"#).unwrap();
+        exec.execute(r#"INSERT INTO people (id, data) VALUES (2, '{"city": "LA"}')"#).unwrap();
+
+        let result = exec.execute("SELECT id FROM people WHERE data->>'city' = 'NYC'").unwrap();
+        let QueryResult::Select(rs) = result else { panic!("Expected Select") };
+        assert_eq!(rs.len(), 1);
+        assert_eq!(rs.rows[0].get("id").unwrap(), &Value::from(1));
+
+        let result = exec.execute(
+            "SELECT data->'tags'->>-1 AS last_tag, data->'age' AS age, data->>'age' AS age_text FROM people ORDER BY id",
+        ).unwrap();
+        let QueryResult::Select(rs) = result else { panic!("Expected Select") };
+        assert_eq!(rs.rows[0].get("last_tag").unwrap(), &Value::from("b"));
+        assert_eq!(rs.rows[0].get("age").unwrap(), &Value::from(30));
+        assert_eq!(rs.rows[0].get("age_text").unwrap(), &Value::from("30"));
+        // Missing paths are NULL
+        assert_eq!(rs.rows[1].get("last_tag").unwrap(), &Value::Null);
+        assert_eq!(rs.rows[1].get("age").unwrap(), &Value::Null);
+    }
+
+    #[test]
+    fn test_like_escape() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE codes (code TEXT PRIMARY KEY)").unwrap();
+        for code in ["100%", "1000", "10_1", "ABC"] {
+            exec.execute(&format!("INSERT INTO codes (code) VALUES ('{}')", code)).unwrap();
+        }
+        let codes = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.rows.iter().map(|r| r["code"].as_str().unwrap().to_string()).collect::<Vec<_>>(),
+            other => panic!("Expected Select, got {:?}", other),
+        };
+
+        assert_eq!(codes(&mut exec, r"SELECT code FROM codes WHERE code LIKE '100\%' ESCAPE '\'"), vec!["100%"]);
+        assert_eq!(codes(&mut exec, "SELECT code FROM codes WHERE code LIKE '10#_%' ESCAPE '#'"), vec!["10_1"]);
+        assert_eq!(codes(&mut exec, "SELECT code FROM codes WHERE code LIKE 'abc'"), Vec::<String>::new());
+        assert_eq!(codes(&mut exec, "SELECT code FROM codes WHERE code ILIKE 'abc'"), vec!["ABC"]);
+        assert!(matches!(
+            exec.execute("SELECT code FROM codes WHERE code LIKE '100#' ESCAPE '#'"),
+            Err(ExecuteError::InvalidExpression(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_constraints() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute(
+            "CREATE TABLE people (id TEXT PRIMARY KEY, age INTEGER CHECK (age >= 0), \
+             lo INTEGER, hi INTEGER, CONSTRAINT lo_le_hi CHECK (lo <= hi))",
+        ).unwrap();
+
+        exec.execute("INSERT INTO people (id, age, lo, hi) VALUES ('1', 30, 1, 2)").unwrap();
+        // NULL inputs do not violate a check
+        exec.execute("INSERT INTO people (id) VALUES ('2')").unwrap();
+
+        let err = exec.execute("INSERT INTO people (id, age) VALUES ('3', -1)").unwrap_err();
+        assert!(err.to_string().contains("people_age_check"));
+
+        let err = exec.execute("INSERT INTO people (id, lo, hi) VALUES ('4', 5, 1)").unwrap_err();
+        assert!(err.to_string().contains("lo_le_hi"));
+
+        let err = exec.execute("UPDATE people SET age = -5 WHERE id = '1'").unwrap_err();
+        assert!(err.to_string().contains("people_age_check"));
+
+        let result = exec.execute("SELECT * FROM people").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_update_validates_row() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT NOT NULL, age INTEGER)").unwrap();
+        exec.execute("INSERT INTO users (id, name, age) VALUES ('1', 'Alice', 30), ('2', 'Bob', 25)").unwrap();
+        let head = exec.repo.read().head().unwrap();
+
+        let err = exec.execute("UPDATE users SET name = NULL WHERE id = '1'").unwrap_err();
+        assert!(err.to_string().contains("cannot be null"));
+        assert!(exec.execute("UPDATE users SET age = 'old'").is_err());
+        assert_eq!(exec.repo.read().head().unwrap(), head);
+
+        let result = exec.execute("SELECT * FROM users ORDER BY id").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.rows[0].get("name"), Some(&Value::String("Alice".into())));
+            assert_eq!(rs.rows[1].get("age"), Some(&Value::from(25)));
+        } else {
+            panic!("Expected Select result");
+        }
+    }
+
+    #[test]
+    fn test_where_missing_column_is_null() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id TEXT PRIMARY KEY, name TEXT, email TEXT)").unwrap();
+        exec.execute("INSERT INTO users (id, name, email) VALUES ('1', 'Alice', 'a@x.com')").unwrap();
+
+        // A row written without the optional column, as before it was added
+        {
+            let repo = exec.repo.write();
+            let mut data = BTreeMap::new();
+            data.insert("id".to_string(), Value::from("2"));
+            data.insert("name".to_string(), Value::from("Bob"));
+            let row = StorageRow::new(RowKey::new("2").unwrap(), data);
+            let table = TableName::new("users").unwrap();
+            let head = repo.insert_row(&table, row, repo.head().unwrap(), None).unwrap();
+            repo.update_branch(&crate::storage::BranchName::main(), head).unwrap();
+        }
+
+        let result = exec.execute("UPDATE users SET name = 'Carol' WHERE LOWER(email) = 'a@x.com'").unwrap();
+        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+        let result = exec.execute("UPDATE users SET email = name WHERE email IS NULL").unwrap();
+        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+        let result = exec.execute("DELETE FROM users WHERE email = 'Bob'").unwrap();
+        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+
+        // Columns the table doesn't have are still an error
+        let err = exec.execute("DELETE FROM users WHERE nickname = 'x'").unwrap_err();
+        assert!(matches!(err, ExecuteError::ColumnNotFound(c) if c == "nickname"));
+        let err = exec.execute("UPDATE users SET name = nickname").unwrap_err();
+        assert!(matches!(err, ExecuteError::ColumnNotFound(c) if c == "nickname"));
+    }
+
+    #[test]
+    fn test_default_expressions_evaluated_per_insert() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute(
+            "CREATE TABLE events (id TEXT PRIMARY KEY, status TEXT DEFAULT 'new', \
+             created TIMESTAMP DEFAULT NOW())",
+        ).unwrap();
+
+        exec.execute("INSERT INTO events (id) VALUES ('1')").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        exec.execute("INSERT INTO events (id) VALUES ('2')").unwrap();
+
+        let result = exec.execute("SELECT * FROM events ORDER BY id").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.rows[0].get("status"), Some(&Value::String("new".into())));
+            assert_eq!(rs.rows[1].get("status"), Some(&Value::String("new".into())));
+            assert_ne!(rs.rows[0].get("created"), rs.rows[1].get("created"));
+        } else {
+            panic!("Expected Select result");
+        }
+    }
+
+    #[test]
+    fn test_insert_default_keyword() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute(
+            "CREATE TABLE events (id TEXT PRIMARY KEY, status TEXT DEFAULT 'new', \
+             created TIMESTAMP DEFAULT NOW(), note TEXT)",
+        ).unwrap();
+
+        // Positionally, DEFAULT picks the default and NULL overrides it
+        exec.execute("INSERT INTO events VALUES ('1', DEFAULT, DEFAULT, DEFAULT)").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        exec.execute("INSERT INTO events VALUES ('2', NULL, DEFAULT, 'x')").unwrap();
+
+        let QueryResult::Select(rs) = exec.execute("SELECT * FROM events ORDER BY id").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.rows[0].get("status"), Some(&Value::String("new".into())));
+        assert_eq!(rs.rows[1].get("status"), Some(&Value::Null));
+        assert!(rs.rows[0]["created"].is_string());
+        assert_ne!(rs.rows[0].get("created"), rs.rows[1].get("created"));
+        assert_eq!(rs.rows[0].get("note"), Some(&Value::Null));
+    }
+
+    #[test]
+    fn test_generated_id_defaults() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute(
+            "CREATE TABLE orders (ref TEXT DEFAULT gen_ulid(), token UUID DEFAULT uuid(), item TEXT)",
+        ).unwrap();
+        exec.execute("INSERT INTO orders (item) VALUES ('a'), ('b')").unwrap();
+
+        let result = exec.execute("SELECT ref, token FROM orders").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.len(), 2);
+            assert_ne!(rs.rows[0].get("ref"), rs.rows[1].get("ref"));
+            assert_ne!(rs.rows[0].get("token"), rs.rows[1].get("token"));
+        } else {
+            panic!("Expected Select result");
+        }
+
+        let result = exec.execute("SELECT item, gen_ulid() AS fresh FROM orders").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_ne!(rs.rows[0].get("fresh"), rs.rows[1].get("fresh"));
+        } else {
+            panic!("Expected Select result");
+        }
+    }
+
+    #[test]
+    fn test_primary_key_lookup() {
+        let where_of = |sql: &str| match Parser::parse(sql).unwrap() {
+            Statement::Delete(d) => d.where_clause.unwrap(),
+            _ => unreachable!(),
+        };
+        let text = TableSchema::new("t", vec![ColumnDef::new("id", DataType::Text)]).with_primary_key("id");
+        let integer = TableSchema::new("t", vec![ColumnDef::new("id", DataType::Integer)]).with_primary_key("id");
+        let keys_in = |schema: &TableSchema, sql: &str| {
+            primary_key_lookup(&where_of(sql), schema)
+                .map(|keys| keys.into_iter().map(RowKey::into_string).collect::<Vec<_>>())
+        };
+        let keys = |sql: &str| keys_in(&text, sql);
+
+        assert_eq!(keys("DELETE FROM t WHERE id = 'a'"), Some(vec!["a".to_string()]));
+        assert_eq!(keys_in(&integer, "DELETE FROM t WHERE 7 = id AND x > 1"), Some(vec!["7".to_string()]));
+        assert_eq!(keys("DELETE FROM t WHERE id IN ('a', 'b c')"), Some(vec!["a".to_string(), "b%20c".to_string()]));
+        assert_eq!(keys("DELETE FROM t WHERE id = 'a' OR id = 'b'"), None);
+        assert_eq!(keys("DELETE FROM t WHERE name = 'a'"), None);
+        assert_eq!(keys("DELETE FROM t WHERE id > 'a'"), None);
+
+        // Only literals of the key's own type, that no other key equals
+        assert_eq!(keys("DELETE FROM t WHERE id = 1"), None);
+        assert_eq!(keys("DELETE FROM t WHERE id = '1'"), None);
+        assert_eq!(keys("DELETE FROM t WHERE id = '2024-01-01'"), None);
+        assert_eq!(keys_in(&integer, "DELETE FROM t WHERE id = '01'"), None);
+        assert_eq!(keys_in(&integer, "DELETE FROM t WHERE id = 1.0"), None);
+    }
+
+    #[test]
+    fn test_primary_key_lookup_matches_scan() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE codes (code TEXT PRIMARY KEY)").unwrap();
+        exec.execute("INSERT INTO codes (code) VALUES ('1'), ('01'), ('a'), ('b')").unwrap();
+        exec.execute("CREATE TABLE items (id INTEGER PRIMARY KEY)").unwrap();
+        exec.execute("INSERT INTO items (id) VALUES (1), (2)").unwrap();
+
+        let rows = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.rows,
+            other => panic!("expected rows, got {:?}", other),
+        };
+        // `NOT (x <> v)` never takes the key path, so it is the full scan
+        for (table, column, value) in [
+            ("codes", "code", "1"),
+            ("codes", "code", "1.0"),
+            ("codes", "code", "'1'"),
+            ("codes", "code", "'a'"),
+            ("items", "id", "'01'"),
+            ("items", "id", "1"),
+        ] {
+            let by_key = rows(&mut exec, &format!("SELECT * FROM {} WHERE {} = {}", table, column, value));
+            let scanned = rows(&mut exec, &format!("SELECT * FROM {} WHERE NOT ({} <> {})", table, column, value));
+            assert_eq!(by_key, scanned, "{} = {}", column, value);
+            assert!(!by_key.is_empty(), "{} = {}", column, value);
+        }
+
+        exec.execute("DELETE FROM codes WHERE code = 1").unwrap();
+        let left = rows(&mut exec, "SELECT code FROM codes");
+        assert_eq!(left.iter().map(|r| r["code"].clone()).collect::<Vec<_>>(), vec![Value::from("a"), Value::from("b")]);
+    }
+
+    #[test]
+    fn test_in_list_reads_only_its_keys() {
+        let dir = TempDir::new().unwrap();
+        let repo = GitRepository::open_or_init(dir.path()).unwrap().with_cache(1000);
+        let mut exec = QueryExecutor::new(repo.clone());
+
+        exec.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, n INTEGER)").unwrap();
+        let values: Vec<String> = (0..200).map(|i| format!("({}, {})", i, i % 7)).collect();
+        exec.execute(&format!("INSERT INTO items (id, n) VALUES {}", values.join(", "))).unwrap();
+
+        let plan = QueryPlanner::new(exec.repo.clone())
+            .explain(&Parser::parse("SELECT * FROM items WHERE id IN (3, 150, 999)").unwrap())
+            .unwrap();
+        assert!(plan.contains("IndexLookup: items using id (3 keys)"));
+
+        let before = repo.cache_stats().unwrap();
+        let result = exec.execute("SELECT n FROM items WHERE id IN (3, 150, 999) AND n > 0").unwrap();
+        let after = repo.cache_stats().unwrap();
+        match result {
+            QueryResult::Select(rs) => {
+                let ns: Vec<_> = rs.rows.iter().map(|r| r["n"].clone()).collect();
+                assert_eq!(ns, vec![Value::from(3), Value::from(3)]);
+            }
+            other => panic!("expected rows, got {:?}", other),
+        }
+        // The schema and the three keys, not the 200 rows
+        assert!(after.hits + after.misses - before.hits - before.misses < 10);
+
+        // Off the key it's still a scan
+        let plan = QueryPlanner::new(exec.repo.clone())
+            .explain(&Parser::parse("SELECT * FROM items WHERE n IN (1, 2)").unwrap())
+            .unwrap();
+        assert!(plan.contains("SeqScan: items"));
+    }
+
+    #[test]
+    fn test_update_delete_by_primary_key() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES (1, 'Alice'), (2, 'Bob'), (3, 'Carol')").unwrap();
+
+        let result = exec.execute("INSERT INTO users (id, name) VALUES (1, 'Again')");
+        assert!(matches!(result, Err(ExecuteError::DuplicateKey(_))));
+
+        let result = exec.execute("UPDATE users SET name = 'Alicia' WHERE id = 1").unwrap();
+        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+
+        // The rest of the predicate still applies to the looked-up row
+        let result = exec.execute("DELETE FROM users WHERE id IN (2, 3, 4) AND name = 'Bob'").unwrap();
+        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+
+        // Changing the key moves the row
+        exec.execute("UPDATE users SET id = 10 WHERE id = 3").unwrap();
+        let result = exec.execute("SELECT name FROM users WHERE id = 10").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.len(), 1);
+            assert_eq!(rs.rows[0].get("name"), Some(&Value::String("Carol".into())));
+        } else {
+            panic!("Expected Select result");
+        }
+
+        let result = exec.execute("SELECT name FROM users ORDER BY id").unwrap();
+        if let QueryResult::Select(rs) = result {
+            let names: Vec<_> = rs.rows.iter().map(|r| r["name"].clone()).collect();
+            assert_eq!(names, vec![Value::from("Alicia"), Value::from("Carol")]);
+        } else {
+            panic!("Expected Select result");
+        }
+    }
+
+    #[test]
+    fn test_optimistic_locking() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES (1, 'Alice')").unwrap();
+
+        let version = |exec: &mut QueryExecutor| match exec.execute("SELECT _version FROM users WHERE id = 1").unwrap() {
+            QueryResult::Select(rs) => rs.rows[0]["_version"].clone(),
+            other => panic!("expected rows, got {:?}", other),
+        };
+        assert_eq!(version(&mut exec), Value::from(1));
+
+        // Only the writer that read the current version gets to update
+        let update = "UPDATE users SET name = 'Alicia' WHERE id = 1 AND _version = 1";
+        assert!(matches!(exec.execute(update).unwrap(), QueryResult::Modified { rows_affected: 1 }));
+        assert!(matches!(exec.execute(update).unwrap(), QueryResult::Modified { rows_affected: 0 }));
+        assert_eq!(version(&mut exec), Value::from(2));
+
+        // Not part of the row otherwise
+        match exec.execute("SELECT * FROM users").unwrap() {
+            QueryResult::Select(rs) => assert!(!rs.rows[0].contains_key("_version")),
+            other => panic!("expected rows, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_omitted_column_reads_as_null() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, email TEXT)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES (1, 'Alice')").unwrap();
+
+        for sql in [
+            "SELECT email FROM users",
+            "SELECT id, email FROM users WHERE email IS NULL",
+            "SELECT email FROM users WHERE id = 1",
+            "SELECT email FROM users WHERE id IN (1, 2)",
+        ] {
+            match exec.execute(sql).unwrap() {
+                QueryResult::Select(rs) => {
+                    assert_eq!(rs.len(), 1, "{}", sql);
+                    assert_eq!(rs.rows[0]["email"], Value::Null, "{}", sql);
+                }
+                other => panic!("expected rows, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_wildcard_columns_from_schema() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER)").unwrap();
+        let columns = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.columns,
+            other => panic!("expected rows, got {:?}", other),
+        };
+        // Known even with no rows to look at
+        assert_eq!(columns(&mut exec, "SELECT * FROM users"), ["id", "name", "age"]);
+
+        exec.execute("INSERT INTO users (id, name, age) VALUES (1, 'Ann', 30)").unwrap();
+        assert_eq!(columns(&mut exec, "SELECT * FROM users"), ["id", "name", "age"]);
+        assert_eq!(
+            columns(&mut exec, "SELECT *, age + 1 AS next FROM users"),
+            ["id", "name", "age", "next"],
+        );
+        assert_eq!(columns(&mut exec, "SELECT age * 2 AS double, users.* FROM users"), ["double", "id", "name", "age"]);
+        match exec.execute("SELECT *, age + 1 AS next FROM users").unwrap() {
+            QueryResult::Select(rs) => assert_eq!(rs.rows[0]["next"], Value::from(31)),
+            other => panic!("expected rows, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_filtered_count() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, active BOOLEAN)").unwrap();
+        exec.execute(
+            "INSERT INTO users (id, name, active) VALUES (1, 'Ann', TRUE), (2, 'Bob', FALSE), (3, 'Cy', TRUE)",
+        ).unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES (4, 'Di')").unwrap();
+
+        let count = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.rows[0]["COUNT(*)"].clone(),
+            other => panic!("expected rows, got {:?}", other),
+        };
+        assert_eq!(count(&mut exec, "SELECT COUNT(*) FROM users"), Value::from(4));
+        assert_eq!(count(&mut exec, "SELECT COUNT(*) FROM users WHERE active"), Value::from(2));
+        assert_eq!(count(&mut exec, "SELECT COUNT(*) FROM users WHERE active IS NULL"), Value::from(1));
+        assert_eq!(count(&mut exec, "SELECT COUNT(*) FROM users WHERE id = 2"), Value::from(1));
+        assert_eq!(count(&mut exec, "SELECT COUNT(*) FROM users WHERE id IN (1, 3, 9) AND active"), Value::from(2));
+        assert_eq!(count(&mut exec, "SELECT COUNT(*) FROM users WHERE _version = 1"), Value::from(4));
+        assert_eq!(count(&mut exec, "SELECT COUNT(*) FROM users WHERE FALSE"), Value::from(0));
+    }
+
+    #[test]
+    fn test_decimal_columns() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, price DECIMAL(30, 2), weight REAL)").unwrap();
+        exec.execute(
+            "INSERT INTO items (id, price, weight) VALUES (1, 0.1, 0.1), (2, 0.2, 0.2), \
+             (3, 1234567890123456789012.34, 1.5), (4, 9.5, 2)",
+        ).unwrap();
+
+        let query = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.rows,
+            other => panic!("expected rows, got {:?}", other),
+        };
+
+        // Stored and returned without rounding through a float
+        let rows = query(&mut exec, "SELECT price FROM items WHERE id = 3");
+        assert_eq!(rows[0]["price"], Value::from("1234567890123456789012.34"));
+
+        let rows = query(&mut exec, "SELECT price + 0.01 AS total, price * 2 AS doubled FROM items WHERE id = 1");
+        assert_eq!(rows[0]["total"], Value::from("0.11"));
+        assert_eq!(rows[0]["doubled"], Value::from("0.20"));
+
+        let rows = query(&mut exec, "SELECT SUM(price), AVG(price) FROM items WHERE id < 3");
+        assert_eq!(rows[0]["SUM(price)"], Value::from("0.30"));
+        assert_eq!(rows[0]["AVG(price)"], Value::from("0.15"));
+
+        // Compared and sorted by value, not as text
+        let rows = query(&mut exec, "SELECT id FROM items WHERE price > 1 ORDER BY price DESC");
+        assert_eq!(rows.iter().map(|r| r["id"].clone()).collect::<Vec<_>>(), vec![Value::from(3), Value::from(4)]);
+        assert_eq!(query(&mut exec, "SELECT id FROM items WHERE price = 9.50").len(), 1);
+        assert_eq!(query(&mut exec, "SELECT id FROM items WHERE price = 0.30 - 0.2").len(), 1);
+
+        // Float columns keep float arithmetic
+        let rows = query(&mut exec, "SELECT SUM(weight) FROM items WHERE id < 3");
+        assert_eq!(rows[0]["SUM(weight)"], Value::from(0.1 + 0.2));
+    }
+
+    #[test]
+    fn test_join() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute("CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER, total INTEGER)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES (1, 'alice'), (2, 'bob'), (3, 'carol')").unwrap();
+        exec.execute("INSERT INTO orders (id, user_id, total) VALUES (10, 1, 5), (11, 1, 7), (12, 2, 9), (13, 9, 4)").unwrap();
+        let select = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs,
+            other => panic!("expected rows, got {:?}", other),
+        };
+        let pairs = |rs: &ResultSet| -> Vec<(Value, Value)> {
+            rs.rows.iter().map(|r| (r[&rs.columns[0]].clone(), r[&rs.columns[1]].clone())).collect()
+        };
+
+        // A left row with two matches comes out twice, one with none once
+        // with NULL for the right columns
+        let rs = select(&mut exec, "SELECT users.name, orders.id FROM users LEFT JOIN orders \
+            ON users.id = orders.user_id ORDER BY users.id, orders.id");
+        assert_eq!(rs.columns, vec!["users.name", "orders.id"]);
+        assert_eq!(pairs(&rs), vec![
+            (Value::from("alice"), Value::from(10)),
+            (Value::from("alice"), Value::from(11)),
+            (Value::from("bob"), Value::from(12)),
+            (Value::from("carol"), Value::Null),
+        ]);
+
+        // Bare names are taken from the table that has them
+        let rs = select(&mut exec, "SELECT name, total FROM users RIGHT JOIN orders ON users.id = user_id ORDER BY total");
+        assert_eq!(pairs(&rs), vec![
+            (Value::Null, Value::from(4)),
+            (Value::from("alice"), Value::from(5)),
+            (Value::from("alice"), Value::from(7)),
+            (Value::from("bob"), Value::from(9)),
+        ]);
+
+        let rs = select(&mut exec, "SELECT * FROM users FULL JOIN orders ON users.id = orders.user_id");
+        assert_eq!(rs.columns, vec!["users.id", "users.name", "orders.id", "orders.user_id", "orders.total"]);
+        assert_eq!(rs.rows.len(), 5);
+
+        let rs = select(&mut exec, "SELECT users.*, orders.total FROM users JOIN orders \
+            ON users.id = orders.user_id AND orders.total > 6");
+        assert_eq!(rs.columns, vec!["users.id", "users.name", "orders.total"]);
+        assert_eq!(rs.rows.len(), 2);
+
+        let rs = select(&mut exec, "SELECT COUNT(*) AS n FROM users CROSS JOIN orders WHERE users.id < orders.user_id");
+        assert_eq!(rs.rows[0]["n"], Value::from(4));
+
+        let result = exec.execute("SELECT id FROM users JOIN orders ON users.id = orders.user_id");
+        assert!(matches!(result, Err(ExecuteError::Plan(PlanError::InvalidJoin(_)))), "{:?}", result);
+        let result = exec.execute("SELECT items.* FROM users JOIN orders ON users.id = orders.user_id");
+        assert!(matches!(result, Err(ExecuteError::TableNotFound(t)) if t == "items"));
+    }
+
+    #[test]
+    fn test_date_columns_and_headers() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE events (id INTEGER PRIMARY KEY, d DATE, ts TIMESTAMP, x REAL)").unwrap();
+        exec.execute("INSERT INTO events (id, d, ts, x) VALUES \
+            (1, '2024-01-31', '2024-01-31 10:30:00', 1.5), (2, '2024-02-01T23:00:00-02:00', NULL, 2.5)").unwrap();
+
+        let rs = match exec.execute("SELECT d, DATE_ADD(d, INTERVAL 1 MONTH), EXTRACT(YEAR FROM ts), \
+            CEIL(x), FLOOR(x) FROM events ORDER BY id").unwrap() {
+            QueryResult::Select(rs) => rs,
+            other => panic!("expected rows, got {:?}", other),
+        };
+        assert_eq!(rs.columns, vec![
+            "d", "DATE_ADD(d, INTERVAL 1 MONTH)", "EXTRACT(YEAR FROM ts)", "CEIL(x)", "FLOOR(x)",
+        ]);
+        // Dates are kept as dates, the UTC date of a timestamp given for one
+        assert_eq!(rs.rows[0]["d"], Value::from("2024-01-31"));
+        assert_eq!(rs.rows[0]["DATE_ADD(d, INTERVAL 1 MONTH)"], Value::from("2024-02-29"));
+        assert_eq!(rs.rows[0]["EXTRACT(YEAR FROM ts)"], Value::from(2024));
+        assert_eq!(rs.rows[1]["d"], Value::from("2024-02-02"));
+
+        let count = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.len(),
+            other => panic!("expected rows, got {:?}", other),
+        };
+        assert_eq!(count(&mut exec, "SELECT id FROM events WHERE d > '2024-02-01'"), 1);
+        assert!(exec.execute("INSERT INTO events (id, d) VALUES (3, 'soon')").is_err());
+    }
+
+    #[test]
+    fn test_negative_and_boolean_literals() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE accounts (id INTEGER PRIMARY KEY, balance REAL)").unwrap();
+        exec.execute("INSERT INTO accounts (id, balance) VALUES (1, -250), (2, -5.5), (3, 40)").unwrap();
+
+        let rows = match exec.execute("SELECT id, -5 AS n, TRUE AS t FROM accounts WHERE balance > -100 AND FALSE = FALSE").unwrap() {
+            QueryResult::Select(rs) => rs.rows,
+            other => panic!("expected rows, got {:?}", other),
+        };
+        assert_eq!(rows.iter().map(|r| r["id"].clone()).collect::<Vec<_>>(), vec![Value::from(2), Value::from(3)]);
+        assert_eq!(rows[0]["n"], Value::from(-5));
+        assert_eq!(rows[0]["t"], Value::from(true));
+    }
+
+    #[test]
+    fn test_begin_isolation_level() {
+        let (mut exec, _dir) = setup();
+        let isolation = |exec: &QueryExecutor| exec.current_tx.as_ref().map(|tx| tx.isolation());
+
+        exec.execute("BEGIN TRANSACTION ISOLATION LEVEL REPEATABLE READ").unwrap();
+        assert_eq!(isolation(&exec), Some(IsolationLevel::RepeatableRead));
+        exec.execute("ROLLBACK").unwrap();
+
+        // Refused, not run at a weaker level
+        let result = exec.execute("BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE");
+        assert!(matches!(result, Err(ExecuteError::UnsupportedIsolation(_))));
+        assert_eq!(isolation(&exec), None);
+
+        exec.set_isolation(IsolationLevel::RepeatableRead);
+        exec.execute("BEGIN").unwrap();
+        assert_eq!(isolation(&exec), Some(IsolationLevel::RepeatableRead));
+        exec.execute("ROLLBACK").unwrap();
+        exec.execute("START TRANSACTION ISOLATION LEVEL READ COMMITTED").unwrap();
+        assert_eq!(isolation(&exec), Some(IsolationLevel::ReadCommitted));
+    }
+
+    #[test]
+    fn test_show_transactions() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        let mut other = QueryExecutor::with_shared(exec.main.clone(), exec.tx_manager.clone());
+
+        let show = |exec: &mut QueryExecutor| match exec.execute("SHOW TRANSACTIONS").unwrap() {
+            QueryResult::Select(rs) => rs,
+            other => panic!("expected rows, got {:?}", other),
+        };
+        assert!(show(&mut exec).is_empty());
+
+        other.execute("BEGIN TRANSACTION ISOLATION LEVEL REPEATABLE READ").unwrap();
+        other.execute("INSERT INTO users (id, name) VALUES (1, 'Ann')").unwrap();
+        let rs = show(&mut exec);
+        assert_eq!(rs.columns[0], "tx_id");
+        assert_eq!(rs.len(), 1);
+        let row = &rs.rows[0];
+        assert_eq!(row["tx_id"], Value::String(other.current_tx.as_ref().unwrap().id().to_string()));
+        assert_eq!(row["isolation"], "REPEATABLE READ");
+        assert!(row["age_seconds"].as_f64().unwrap() >= 0.0);
+        // The insert moved the transaction on from where it started
+        assert_ne!(row["current_commit"], row["base_commit"]);
+
+        other.execute("COMMIT").unwrap();
+        assert!(show(&mut exec).is_empty());
+    }
+
+    #[test]
+    fn test_commit_batch() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        let mut other = QueryExecutor::with_shared(exec.main.clone(), exec.tx_manager.clone());
+        let count = |exec: &mut QueryExecutor| match exec.execute("SELECT COUNT(*) AS n FROM users").unwrap() {
+            QueryResult::Select(rs) => rs.rows[0]["n"].as_u64().unwrap(),
+            other => panic!("Expected Select result, got {:?}", other),
+        };
+        let start = exec.main.read().head().unwrap();
+        exec.set_commit_batch(Some(BatchConfig::default().with_max_statements(3).with_window(Duration::from_secs(60))));
+
+        exec.execute("INSERT INTO users (id, name) VALUES (1, 'Ann')").unwrap();
+        exec.execute("UPDATE users SET name = 'Anne' WHERE id = 1").unwrap();
+        // Buffered: this session reads its own writes, main hasn't moved
+        assert_eq!(count(&mut exec), 1);
+        assert_eq!(count(&mut other), 0);
+        assert_eq!(exec.main.read().head().unwrap(), start);
+
+        // The third write fills the batch, which lands as one commit
+        exec.execute("INSERT INTO users (id, name) VALUES (2, 'Bob')").unwrap();
+        let head = exec.main.read().head().unwrap();
+        let commit = exec.main.read().get_commit(head).unwrap();
+        assert_eq!(commit.summary(), "[BATCH] 3 statements");
+        assert_eq!(commit.first_parent(), Some(start));
+        assert_eq!(count(&mut other), 2);
+        assert_eq!(exec.metrics().commits, 2);
+
+        // A flush merges onto commits other sessions made meanwhile
+        exec.execute("DELETE FROM users WHERE id = 2").unwrap();
+        other.execute("INSERT INTO users (id, name) VALUES (3, 'Cy')").unwrap();
+        assert!(exec.flush().unwrap().is_some());
+        assert_eq!(exec.flush().unwrap(), None);
+        assert_eq!(count(&mut other), 2);
+
+        // DDL isn't batched, and lands what was buffered first
+        exec.execute("INSERT INTO users (id, name) VALUES (4, 'Di')").unwrap();
+        exec.execute("CREATE TABLE tags (id INTEGER PRIMARY KEY)").unwrap();
+        assert_eq!(count(&mut other), 3);
+    }
+
+    #[test]
+    fn test_commit_batch_is_pinned() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        let mut other = QueryExecutor::with_shared(exec.main.clone(), exec.tx_manager.clone());
+        let batches = |exec: &QueryExecutor| exec.main.read().with_repo(RefManager::list_batch_refs).unwrap().len();
+        exec.set_commit_batch(Some(BatchConfig::default().with_window(Duration::from_secs(60))));
+        exec.execute("INSERT INTO users (id, name) VALUES (1, 'Ann')").unwrap();
+        assert_eq!(batches(&exec), 1);
+
+        // Another session can neither squash main under the batch nor prune it
+        let result = other.vacuum(true);
+        assert!(matches!(result, Err(ExecuteError::Storage(StorageError::PendingBatches(_)))));
+        exec.main.read().gc_prune_older_than(Duration::ZERO).unwrap();
+
+        assert!(exec.flush().unwrap().is_some());
+        assert_eq!(batches(&exec), 0);
+        match other.execute("SELECT name FROM users").unwrap() {
+            QueryResult::Select(rs) => assert_eq!(rs.rows.len(), 1),
+            other => panic!("Expected Select result, got {:?}", other),
+        }
+        assert!(other.vacuum(true).is_ok());
+    }
+
+    #[test]
+    fn test_primary_key_row_keys() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute("INSERT INTO items (id, name) VALUES (5, 'five')").unwrap();
+        let repo = exec.repo.read();
+        let keys = repo.list_rows(&TableName::new("items").unwrap(), repo.head().unwrap()).unwrap();
+        assert_eq!(keys, vec![RowKey::new("5").unwrap()]);
+        drop(repo);
+
+        assert!(exec.execute("INSERT INTO items (name) VALUES ('none')").is_err());
+
+        exec.execute("CREATE TABLE readings (id REAL PRIMARY KEY)").unwrap();
+        let result = exec.execute("INSERT INTO readings (id) VALUES (1.5)");
+        assert!(matches!(result, Err(ExecuteError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_composite_primary_key() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute(
+            "CREATE TABLE members (tenant_id TEXT, id INTEGER, name TEXT, PRIMARY KEY (tenant_id, id))",
+        ).unwrap();
+        exec.execute(
+            "INSERT INTO members (tenant_id, id, name) VALUES ('acme', 1, 'Ann'), ('acme', 2, 'Bo'), ('a:b', 1, 'Cy')",
+        ).unwrap();
+
+        let result = exec.execute("INSERT INTO members (tenant_id, id, name) VALUES ('acme', 1, 'Again')");
+        assert!(matches!(result, Err(ExecuteError::DuplicateKey(_))));
+        assert!(exec.execute("INSERT INTO members (tenant_id, name) VALUES ('acme', 'NoId')").is_err());
+
+        // Only a predicate pinning every key column is a point lookup
+        let pk = TableSchema::new(
+            "t",
+            vec![ColumnDef::new("tenant_id", DataType::Text), ColumnDef::new("id", DataType::Integer)],
+        )
+        .with_primary_key_columns(["tenant_id", "id"]);
+        let where_of = |sql: &str| match Parser::parse(sql).unwrap() {
+            Statement::Delete(d) => d.where_clause.unwrap(),
+            _ => unreachable!(),
+        };
+        let keys = primary_key_lookup(&where_of("DELETE FROM t WHERE tenant_id = 'a:b' AND id IN (1, 2)"), &pk);
+        assert_eq!(
+            keys.unwrap().into_iter().map(RowKey::into_string).collect::<Vec<_>>(),
+            vec!["a%3Ab:1".to_string(), "a%3Ab:2".to_string()]
+        );
+        assert!(primary_key_lookup(&where_of("DELETE FROM t WHERE id = 1"), &pk).is_none());
+
+        exec.execute("UPDATE members SET name = 'Cyd' WHERE tenant_id = 'a:b' AND id = 1").unwrap();
+        let result = exec.execute("SELECT name FROM members WHERE id = 1 ORDER BY name").unwrap();
+        if let QueryResult::Select(rs) = result {
+            let names: Vec<_> = rs.rows.iter().map(|r| r["name"].clone()).collect();
+            assert_eq!(names, vec![Value::from("Ann"), Value::from("Cyd")]);
+        } else {
+            panic!("Expected Select result");
+        }
+
+        let result = exec.execute("CREATE TABLE bad (a TEXT PRIMARY KEY, b TEXT, PRIMARY KEY (a, b))");
+        assert!(matches!(result, Err(ExecuteError::Schema(SchemaError::InvalidPrimaryKey(_)))));
+    }
+
+    #[test]
+    fn test_text_primary_key_any_characters() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE contacts (email TEXT PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute(
+            "INSERT INTO contacts (email, name) VALUES ('a@b.com', 'Ann'), ('a b/c', 'Bo'), ('1.0', 'Cy'), ('plain', 'Di')",
+        ).unwrap();
+
+        let name_of = |exec: &mut QueryExecutor, key: &str| {
+            match exec.execute(&format!("SELECT name FROM contacts WHERE email = '{}'", key)).unwrap() {
+                QueryResult::Select(rs) => rs.rows.iter().map(|r| r["name"].clone()).collect::<Vec<_>>(),
+                other => panic!("expected rows, got {:?}", other),
+            }
+        };
+        assert_eq!(name_of(&mut exec, "a@b.com"), vec![Value::from("Ann")]);
+        assert_eq!(name_of(&mut exec, "a b/c"), vec![Value::from("Bo")]);
+        assert_eq!(name_of(&mut exec, "1.0"), vec![Value::from("Cy")]);
+
+        // Alphanumeric keys keep their own name as the row's filename
+        assert_eq!(row_key(&["plain".to_string()]).unwrap().as_str(), "plain");
+        assert_eq!(row_key(&["a@b.com".to_string()]).unwrap().as_str(), "a%40b%2Ecom");
+    }
+
+    #[test]
+    fn test_create_table_keeps_unique_and_foreign_keys() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT)").unwrap();
+        exec.execute(
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, \
+             user_id INTEGER REFERENCES users ON DELETE CASCADE, \
+             parent_id INTEGER, sku TEXT, UNIQUE (user_id, sku), \
+             FOREIGN KEY (parent_id) REFERENCES orders (id))",
+        ).unwrap();
+
+        let schema = exec.catalog().get_table("orders").unwrap();
+        assert_eq!(
+            schema.unique,
+            vec![UniqueConstraint::new("orders_user_id_sku_key", vec!["user_id".into(), "sku".into()])]
+        );
+        assert_eq!(
+            schema.foreign_keys.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "CONSTRAINT orders_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE",
+                "CONSTRAINT orders_parent_id_fkey FOREIGN KEY (parent_id) REFERENCES orders (id)",
+            ]
+        );
+
+        let result = exec.execute("CREATE TABLE items (id INTEGER, owner INTEGER REFERENCES missing)");
+        assert!(result.is_err());
+        let result = exec.execute("CREATE TABLE items (id INTEGER, owner INTEGER REFERENCES users (nope))");
+        assert!(matches!(result, Err(ExecuteError::Schema(SchemaError::ColumnNotFound(_)))));
+        assert!(!exec.catalog().table_exists("items"));
+    }
+
+    #[test]
+    fn test_foreign_key_enforcement() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT)").unwrap();
+        exec.execute(
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER REFERENCES users ON DELETE CASCADE)",
+        ).unwrap();
+        exec.execute(
+            "CREATE TABLE notes (id INTEGER PRIMARY KEY, order_id INTEGER, \
+             CONSTRAINT notes_order FOREIGN KEY (order_id) REFERENCES orders (id))",
+        ).unwrap();
+        exec.execute("INSERT INTO users (id, email) VALUES (1, 'a@x'), (2, 'b@x')").unwrap();
+        exec.execute("INSERT INTO orders (id, user_id) VALUES (10, 1), (11, 1), (20, 2), (30, NULL)").unwrap();
+        exec.execute("INSERT INTO notes (id, order_id) VALUES (1, 20)").unwrap();
+
+        let result = exec.execute("INSERT INTO orders (id, user_id) VALUES (40, 99)");
+        assert!(matches!(result, Err(ExecuteError::ForeignKeyViolation(ref name)) if name == "orders_user_id_fkey"));
+        let result = exec.execute("UPDATE orders SET user_id = 99 WHERE id = 10");
+        assert!(matches!(result, Err(ExecuteError::ForeignKeyViolation(_))));
+
+        // Deleting user 2 would cascade to order 20, which a note still references
+        let result = exec.execute("DELETE FROM users WHERE id = 2");
+        assert!(matches!(result, Err(ExecuteError::ForeignKeyViolation(ref name)) if name == "notes_order"));
+
+        let result = exec.execute("DELETE FROM users WHERE id = 1").unwrap();
+        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+        let result = exec.execute("SELECT id FROM orders ORDER BY id").unwrap();
+        if let QueryResult::Select(rs) = result {
+            let ids: Vec<_> = rs.rows.iter().map(|r| r["id"].clone()).collect();
+            assert_eq!(ids, vec![Value::from(20), Value::from(30)]);
+        } else {
+            panic!("Expected Select result");
+        }
+    }
+
+    #[test]
+    fn test_foreign_key_on_update() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE p (id INTEGER PRIMARY KEY, code TEXT UNIQUE)").unwrap();
+        exec.execute("CREATE TABLE c (id INTEGER PRIMARY KEY, pid INTEGER REFERENCES p (id))").unwrap();
+        exec.execute(
+            "CREATE TABLE d (id INTEGER PRIMARY KEY, code TEXT, \
+             FOREIGN KEY (code) REFERENCES p (code) ON UPDATE CASCADE)",
+        ).unwrap();
+        exec.execute("INSERT INTO p (id, code) VALUES (1, 'a'), (2, 'b')").unwrap();
+        exec.execute("INSERT INTO c (id, pid) VALUES (10, 1)").unwrap();
+        exec.execute("INSERT INTO d (id, code) VALUES (20, 'b')").unwrap();
+
+        // A referenced key can't change under a child
+        let result = exec.execute("UPDATE p SET id = 5 WHERE id = 1");
+        assert!(matches!(result, Err(ExecuteError::ForeignKeyViolation(_))), "{:?}", result);
+        // Unreferenced or unchanged ones can
+        exec.execute("UPDATE p SET id = 6 WHERE id = 2").unwrap();
+        exec.execute("UPDATE p SET id = 1, code = 'a' WHERE id = 1").unwrap();
+
+        // ON UPDATE CASCADE follows the parent
+        exec.execute("UPDATE p SET code = 'z' WHERE id = 6").unwrap();
+        match exec.execute("SELECT code FROM d WHERE id = 20").unwrap() {
+            QueryResult::Select(rs) => assert_eq!(rs.rows[0]["code"], Value::from("z")),
+            other => panic!("Expected Select result, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_foreign_key_set_null() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute(
+            "CREATE TABLE staff (id INTEGER PRIMARY KEY, manager_id INTEGER, \
+             FOREIGN KEY (manager_id) REFERENCES staff (id) ON DELETE SET NULL)",
+        ).unwrap();
+        exec.execute("INSERT INTO staff (id, manager_id) VALUES (1, NULL), (2, 1), (3, 2)").unwrap();
+        exec.execute("DELETE FROM staff WHERE id = 1").unwrap();
+
+        let result = exec.execute("SELECT * FROM staff ORDER BY id").unwrap();
+        if let QueryResult::Select(rs) = result {
+            assert_eq!(rs.rows[0].get("manager_id"), Some(&Value::Null));
+            assert_eq!(rs.rows[1].get("manager_id"), Some(&Value::from(2)));
+        } else {
+            panic!("Expected Select result");
+        }
+    }
+
+    #[test]
+    fn test_subqueries() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute("CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES (1, 'Alice'), (2, 'Bob'), (3, 'Carol')").unwrap();
+        exec.execute("INSERT INTO orders (id, user_id) VALUES (10, 1), (11, 3), (12, 3)").unwrap();
+
+        let names = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs.rows.iter().map(|r| r["name"].clone()).collect::<Vec<_>>(),
+            other => panic!("Expected Select result, got {:?}", other),
+        };
+
+        let sql = "SELECT name FROM users WHERE id IN (SELECT user_id FROM orders) ORDER BY id";
+        assert_eq!(names(&mut exec, sql), vec![Value::from("Alice"), Value::from("Carol")]);
+
+        let sql = "SELECT name FROM users WHERE id = (SELECT user_id FROM orders WHERE id = 10)";
+        assert_eq!(names(&mut exec, sql), vec![Value::from("Alice")]);
+
+        // A scalar subquery with no rows is NULL, with several it's an error
+        let sql = "SELECT name FROM users WHERE id = (SELECT user_id FROM orders WHERE id = 99)";
+        assert!(names(&mut exec, sql).is_empty());
+        let result = exec.execute("SELECT name FROM users WHERE id = (SELECT user_id FROM orders)");
+        assert!(matches!(result, Err(ExecuteError::SubqueryRows(3))));
+
+        let result = exec
+            .execute("DELETE FROM users WHERE id NOT IN (SELECT user_id FROM orders)")
+            .unwrap();
+        assert!(matches!(result, QueryResult::Modified { rows_affected: 1 }));
+    }
+
+    #[test]
+    fn test_derived_tables() {
+        let (mut exec, _dir) = setup();
+
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        exec.execute("CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER)").unwrap();
+        exec.execute("INSERT INTO users (id, name) VALUES (1, 'Alice'), (2, 'Bob'), (3, 'Carol')").unwrap();
+        exec.execute("INSERT INTO orders (id, user_id) VALUES (10, 1), (11, 3), (12, 3)").unwrap();
+
+        let select = |exec: &mut QueryExecutor, sql: &str| match exec.execute(sql).unwrap() {
+            QueryResult::Select(rs) => rs,
+            other => panic!("Expected Select result, got {:?}", other),
+        };
+
+        // The alias qualifies the outer query's columns
+        let rs = select(&mut exec, "SELECT t.name, t.total FROM \
+            (SELECT name, id * 10 AS total FROM users WHERE id > 1) t ORDER BY total DESC");
+        assert_eq!(rs.columns, vec!["name", "total"]);
+        assert_eq!(rs.rows.iter().map(|r| r["total"].clone()).collect::<Vec<_>>(), vec![Value::from(30), Value::from(20)]);
+
+        let rs = select(&mut exec, "SELECT COUNT(*) AS n FROM (SELECT * FROM orders WHERE user_id = 3) o");
+        assert_eq!(rs.rows[0]["n"], Value::from(2));
+
+        // `*` over a derived table is whatever it selects, nested or not
+        let rs = select(&mut exec, "SELECT * FROM (SELECT * FROM users) u WHERE id = 2");
+        assert_eq!(rs.columns, vec!["id", "name"]);
+        let rs = select(&mut exec, "SELECT * FROM (SELECT name FROM (SELECT * FROM users WHERE id < 3) a) b");
+        assert_eq!(rs.columns, vec!["name"]);
+        assert_eq!(rs.rows.len(), 2);
+
+        let result = exec.execute("SELECT x.name FROM (SELECT name FROM users) t");
+        assert!(matches!(result, Err(ExecuteError::TableNotFound(t)) if t == "x"));
+    }
+
+    #[test]
+    fn test_key_strategy() {
+        let (mut exec, _dir) = setup();
+        let keys = |exec: &QueryExecutor, table: &str| {
+            let repo = exec.repo.read();
+            let keys = repo.list_rows(&TableName::new(table).unwrap(), repo.head().unwrap()).unwrap();
+            keys.into_iter().map(RowKey::into_string).collect::<Vec<_>>()
+        };
+
+        let create = "CREATE TABLE events (name TEXT) WITH (key_strategy = 'sequential')";
+        exec.execute(create).unwrap();
+        exec.execute("INSERT INTO events (name) VALUES ('a'), ('b')").unwrap();
+        exec.execute("INSERT INTO events (name) VALUES ('c')").unwrap();
+        let padded = |ns: &[u64]| ns.iter().map(|n| format!("{:020}", n)).collect::<Vec<_>>();
+        assert_eq!(keys(&exec, "events"), padded(&[1, 2, 3]));
+        assert_eq!(exec.catalog.get_table("events").unwrap().key_strategy, KeyStrategy::Sequential);
+
+        // Dropping the table starts its sequence over
+        exec.execute("DROP TABLE events").unwrap();
+        exec.execute(create).unwrap();
+        exec.execute("INSERT INTO events (name) VALUES ('d')").unwrap();
+        assert_eq!(keys(&exec, "events"), padded(&[1]));
+
+        // Keys sort by number past a change in digit count
+        let values = ["('x')"; 10].join(", ");
+        exec.execute(&format!("INSERT INTO events (name) VALUES {}", values)).unwrap();
+        assert_eq!(keys(&exec, "events"), padded(&(1..=11).collect::<Vec<_>>()));
+
+        exec.execute("CREATE TABLE visits (page TEXT) WITH (key_strategy = uuid_v7)").unwrap();
+        exec.execute("INSERT INTO visits (page) VALUES ('/')").unwrap();
+        let visits = keys(&exec, "visits");
+        assert_eq!(uuid::Uuid::parse_str(&visits[0]).unwrap().get_version_num(), 7);
+    }
+
+    #[test]
+    fn test_rename_table() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT)").unwrap();
+        exec.execute("CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER REFERENCES users)").unwrap();
+        exec.execute("CREATE TABLE events (name TEXT) WITH (key_strategy = 'sequential')").unwrap();
+        exec.execute("INSERT INTO users (id, email) VALUES (1, 'a@x')").unwrap();
+        exec.execute("INSERT INTO events (name) VALUES ('a'), ('b')").unwrap();
+
+        exec.execute("ALTER TABLE users RENAME TO customers").unwrap();
+        assert!(matches!(exec.execute("SELECT * FROM users"), Err(ExecuteError::TableNotFound(_))));
+        let QueryResult::Select(rs) = exec.execute("SELECT * FROM customers").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.len(), 1);
+
+        // Foreign keys still check against the renamed table
+        exec.execute("INSERT INTO orders (id, user_id) VALUES (10, 1)").unwrap();
+        let result = exec.execute("INSERT INTO orders (id, user_id) VALUES (11, 2)");
+        assert!(matches!(result, Err(ExecuteError::ForeignKeyViolation(_))));
+
+        // The key sequence carries on under the new name
+        exec.execute("ALTER TABLE events RENAME TO log").unwrap();
+        exec.execute("INSERT INTO log (name) VALUES ('c')").unwrap();
+        let repo = exec.repo.read().clone();
+        let keys = repo.list_rows(&TableName::new("log").unwrap(), repo.head().unwrap()).unwrap();
+        assert_eq!(keys.last().unwrap(), &RowKey::sequential(3));
+
+        let result = exec.execute("ALTER TABLE customers RENAME TO orders");
+        assert!(matches!(result, Err(ExecuteError::Schema(SchemaError::TableExists(_)))));
+        let result = exec.execute("ALTER TABLE customers RENAME TO _meta");
+        assert!(matches!(result, Err(ExecuteError::InvalidName(_))));
+        assert!(exec.execute("ALTER TABLE IF EXISTS users RENAME TO people").is_ok());
+        assert!(matches!(exec.execute("ALTER TABLE users RENAME TO people"), Err(ExecuteError::TableNotFound(_))));
+    }
+
+    #[test]
+    fn test_rename_column() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, age INTEGER CHECK (age >= 0))").unwrap();
+        exec.execute("CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER REFERENCES users (id))").unwrap();
+        exec.execute("INSERT INTO users (id, name, age) VALUES (1, 'Alice', 30), (2, 'Bob', NULL)").unwrap();
+        let before = exec.repo.read().head().unwrap();
+
+        exec.execute("ALTER TABLE users RENAME COLUMN name TO full_name").unwrap();
+        exec.execute("ALTER TABLE users RENAME COLUMN age TO years").unwrap();
+        let QueryResult::Select(rs) = exec.execute("SELECT id FROM users WHERE full_name = 'Alice' AND years = 30").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.len(), 1);
+        assert!(exec.execute("SELECT * FROM users WHERE name = 'Alice'").is_err());
+        // Each rename is a single commit
+        let head = exec.repo.read().head().unwrap();
+        assert_eq!(exec.repo.read().count_commits(head).unwrap(), exec.repo.read().count_commits(before).unwrap() + 2);
+
+        // The check constraint follows the column
+        let result = exec.execute("INSERT INTO users (id, full_name, years) VALUES (3, 'Carol', -1)");
+        assert!(matches!(result, Err(ExecuteError::Schema(SchemaError::CheckViolation(_)))));
+
+        // Keys are values, so the primary key renames like any column
+        exec.execute("ALTER TABLE users RENAME COLUMN id TO user_id").unwrap();
+        exec.execute("UPDATE users SET years = 31 WHERE user_id = 1").unwrap();
+        exec.execute("INSERT INTO orders (id, user_id) VALUES (10, 1)").unwrap();
+        let result = exec.execute("INSERT INTO orders (id, user_id) VALUES (11, 9)");
+        assert!(matches!(result, Err(ExecuteError::ForeignKeyViolation(_))));
+        assert_eq!(exec.catalog.get_table("orders").unwrap().foreign_keys[0].references_columns, vec!["user_id"]);
+
+        let result = exec.execute("ALTER TABLE users RENAME COLUMN full_name TO years");
+        assert!(matches!(result, Err(ExecuteError::Schema(SchemaError::DuplicateColumn(_)))));
+    }
+
+    #[test]
+    fn test_execute_with_deadline() {
+        let (mut exec, _dir) = setup();
+        exec.execute("CREATE TABLE a (id INTEGER PRIMARY KEY)").unwrap();
+        exec.execute("INSERT INTO a (id) VALUES (1), (2), (3)").unwrap();
+        let head = exec.repo.read().head().unwrap();
+
+        let result = exec.execute_with_deadline("SELECT COUNT(*) FROM a WHERE id > 1", Duration::ZERO);
+        assert!(matches!(result, Err(ExecuteError::Cancelled)));
+        assert_eq!(result.unwrap_err().kind(), "cancelled");
+
+        // A write that runs out of time leaves main where it was
+        let result = exec.execute_with_deadline("UPDATE a SET id = id + 10", Duration::ZERO);
+        assert!(matches!(result, Err(ExecuteError::Cancelled)));
+        let result = exec.execute_with_deadline("INSERT INTO a (id) VALUES (4)", Duration::ZERO);
+        assert!(matches!(result, Err(ExecuteError::Cancelled)));
+        assert_eq!(exec.repo.read().head().unwrap(), head);
+
+        // The deadline only covers its own statement, and a cancel from
+        // before a statement started doesn't carry over
+        exec.cancel_token().cancel();
+        let QueryResult::Select(rs) = exec.execute("SELECT * FROM a").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.len(), 3);
+        assert!(exec.execute_with_deadline("DELETE FROM a WHERE id = 1", Duration::from_secs(60)).is_ok());
+    }
+
+    #[test]
+    fn test_identifier_case() {
+        let (mut exec, _dir) = setup();
+
+        // Unquoted names fold to lower case, so any spelling finds the table
+        exec.execute("CREATE TABLE Users (Id INTEGER PRIMARY KEY, Name TEXT)").unwrap();
+        assert!(exec.catalog.get_table("users").is_ok());
+        exec.execute("INSERT INTO USERS (ID, NAME) VALUES (1, 'alice')").unwrap();
+        let QueryResult::Select(rs) = exec.execute("SELECT name FROM users WHERE id = 1").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.rows[0].get("name"), Some(&Value::from("alice")));
+        let result = exec.execute("SELECT * FROM \"Users\"");
+        assert!(matches!(result, Err(ExecuteError::TableNotFound(_))));
+
+        // Quoted names are kept exactly and only match when quoted the same
+        exec.execute("CREATE TABLE \"MyTable\" (\"Key\" INTEGER PRIMARY KEY, \"Label\" TEXT)").unwrap();
+        exec.execute("INSERT INTO \"MyTable\" (\"Key\", \"Label\") VALUES (1, 'x')").unwrap();
+        let QueryResult::Select(rs) = exec.execute("SELECT \"Label\" FROM \"MyTable\" WHERE \"Key\" = 1").unwrap() else {
+            panic!("Expected Select result");
+        };
+        assert_eq!(rs.rows[0].get("Label"), Some(&Value::from("x")));
+        let result = exec.execute("SELECT * FROM MyTable");
+        assert!(matches!(result, Err(ExecuteError::TableNotFound(_))));
+        let result = exec.execute("INSERT INTO \"MyTable\" (key, label) VALUES (2, 'y')");
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,146 @@
+//! Counters for monitoring a database: queries, commits, conflicts, latency.
+//!
+//! [`QueryExecutor`](super::QueryExecutor) bumps relaxed atomics as it runs
+//! statements, so keeping count costs next to nothing; [`Metrics`] is a
+//! point-in-time copy of them to read or export.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::storage::CacheStats;
+
+/// Every kind [`Statement::kind`](crate::sql::Statement::kind) reports.
+const STATEMENT_KINDS: [&str; 17] = [
+    "create_table",
+    "drop_table",
+    "alter_table",
+    "select",
+    "insert",
+    "update",
+    "delete",
+    "begin",
+    "commit",
+    "rollback",
+    "show_tables",
+    "show_transactions",
+    "describe",
+    "show_columns",
+    "set_author",
+    "explain",
+    "vacuum",
+];
+
+/// A snapshot of a database's counters since it was opened or last reset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    /// Statements executed, failed ones included, by kind (`select`,
+    /// `insert`, ...). Kinds never run are left out.
+    pub queries: BTreeMap<&'static str, u64>,
+    /// Changes made durable on main: each write statement outside a
+    /// transaction, each successful COMMIT and each flushed batch.
+    pub commits: u64,
+    /// Transaction commits that failed, or stopped for resolution, on a
+    /// conflict with another transaction.
+    pub conflicts: u64,
+    /// Read cache hits and misses, if the cache is enabled.
+    pub cache: Option<CacheStats>,
+    /// Time spent executing statements, in total.
+    pub query_time: Duration,
+}
+
+impl Metrics {
+    /// Statements executed, of every kind.
+    pub fn total_queries(&self) -> u64 {
+        self.queries.values().sum()
+    }
+
+    /// Mean time a statement took, or `None` before any has run.
+    pub fn average_latency(&self) -> Option<Duration> {
+        let total = u32::try_from(self.total_queries()).ok().filter(|n| *n > 0)?;
+        Some(self.query_time / total)
+    }
+}
+
+/// The live counters behind [`Metrics`].
+#[derive(Debug, Default)]
+pub(crate) struct QueryMetrics {
+    queries: [AtomicU64; STATEMENT_KINDS.len()],
+    commits: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl QueryMetrics {
+    /// Count a statement of `kind` that took `elapsed`.
+    pub(crate) fn record(&self, kind: &str, elapsed: Duration, committed: bool) {
+        if let Some(slot) = STATEMENT_KINDS.iter().position(|k| *k == kind) {
+            self.queries[slot].fetch_add(1, Ordering::Relaxed);
+        }
+        if committed {
+            self.commits.fetch_add(1, Ordering::Relaxed);
+        }
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// Count a commit made outside any statement, such as a batch flush.
+    pub(crate) fn record_commit(&self) {
+        self.commits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Copy the counters out. Conflicts and cache counts are kept elsewhere
+    /// and left for the caller to fill in.
+    pub(crate) fn snapshot(&self) -> Metrics {
+        let queries = STATEMENT_KINDS.iter()
+            .zip(&self.queries)
+            .map(|(kind, count)| (*kind, count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        Metrics {
+            queries,
+            commits: self.commits.load(Ordering::Relaxed),
+            conflicts: 0,
+            cache: None,
+            query_time: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Set every counter back to zero.
+    pub(crate) fn reset(&self) {
+        for count in self.queries.iter().chain([&self.commits, &self.nanos]) {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::Parser;
+
+    #[test]
+    fn test_metrics_counts() {
+        let metrics = QueryMetrics::default();
+        metrics.record("select", Duration::from_millis(3), false);
+        metrics.record("select", Duration::from_millis(1), false);
+        metrics.record("insert", Duration::from_millis(2), true);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.queries, BTreeMap::from([("insert", 1), ("select", 2)]));
+        assert_eq!(snapshot.total_queries(), 3);
+        assert_eq!(snapshot.commits, 1);
+        assert_eq!(snapshot.average_latency(), Some(Duration::from_millis(2)));
+
+        metrics.reset();
+        assert_eq!(metrics.snapshot(), Metrics::default());
+        assert_eq!(Metrics::default().average_latency(), None);
+    }
+
+    #[test]
+    fn test_every_statement_kind_counted() {
+        for sql in ["SELECT * FROM t", "ALTER TABLE t RENAME TO u", "VACUUM", "SET AUTHOR = DEFAULT", "SHOW TRANSACTIONS", "SHOW COLUMNS FROM t"] {
+            let kind = Parser::parse(sql).unwrap().kind();
+            assert!(STATEMENT_KINDS.contains(&kind), "{} is not counted", kind);
+        }
+    }
+}
@@ -3,12 +3,20 @@
 //! Uses the Volcano/Iterator model where each operator produces
 //! rows one at a time.
 
+mod batch;
+mod cancel;
 mod error;
-mod eval;
+#[allow(clippy::module_inception)]
 mod executor;
+mod metrics;
 mod operators;
 mod result;
+mod spill;
 
+pub use batch::BatchConfig;
+pub use cancel::CancelToken;
 pub use error::{ExecuteError, ExecuteResult};
 pub use executor::QueryExecutor;
-pub use result::{QueryResult, ResultSet, RowIter};
+pub use metrics::Metrics;
+pub use result::{Page, QueryResult, ResultSet, RowIter, VacuumReport};
+pub use spill::SpillConfig;
@@ -3,16 +3,35 @@
 //! Each operator implements the iterator model where rows are pulled
 //! one at a time through the tree.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use serde_json::Value;
 
-use super::error::ExecuteResult;
-use super::eval::{evaluate, matches_where};
+use super::cancel::CancelToken;
+use super::error::{ExecuteError, ExecuteResult};
+use super::spill::{row_size, Run, RunReader, SpillConfig};
+use crate::sql::decimal::Decimal;
+use crate::sql::eval::{evaluate, matches_where, Predicate};
+use crate::planner::{AggregatePhysical, PhysicalAggregate};
 use crate::sql::{Expr, OrderBy, SelectColumn};
+use crate::storage::{Row as StorageRow, TableScan};
 
 /// A row in the query execution pipeline.
 pub type Row = BTreeMap<String, Value>;
 
+/// Pseudo-column holding a stored row's version, for optimistic locking.
+///
+/// Scans only add it when a query asks for it, so `SELECT *` doesn't show it.
+pub const VERSION_COLUMN: &str = "_version";
+
+/// The pipeline row for a stored row, with its version if `versions` is set.
+pub fn scanned_row(row: StorageRow, versions: bool) -> Row {
+    let mut data = row.data;
+    if versions {
+        data.insert(VERSION_COLUMN.to_string(), Value::from(row.version));
+    }
+    data
+}
+
 /// Trait for all query operators.
 pub trait Operator: Send {
     /// Get the next row, or None if exhausted.
@@ -20,46 +39,163 @@ pub trait Operator: Send {
 
     /// Reset the operator to start over.
     fn reset(&mut self) -> ExecuteResult<()>;
+
+    /// Discard up to `n` rows, returning how many there were.
+    ///
+    /// Operators that can skip without producing the rows override this.
+    fn skip(&mut self, n: usize) -> ExecuteResult<usize> {
+        let mut skipped = 0;
+        while skipped < n && self.next_row()?.is_some() {
+            skipped += 1;
+        }
+        Ok(skipped)
+    }
 }
 
 /// Scan operator - reads all rows from a table.
 pub struct ScanOperator {
-    rows: Vec<Row>,
-    position: usize,
+    source: ScanSource,
+    predicate: Option<Predicate>,
+    versions: bool,
+    columns: Vec<String>,
+    cancel: CancelToken,
+}
+
+enum ScanSource {
+    Rows { rows: Vec<Row>, position: usize },
+    Table(TableScan),
 }
 
 impl ScanOperator {
     pub fn new(rows: Vec<Row>) -> Self {
-        Self { rows, position: 0 }
+        Self {
+            source: ScanSource::Rows { rows, position: 0 },
+            predicate: None,
+            versions: false,
+            columns: Vec::new(),
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Pull rows from storage as they are needed.
+    pub fn lazy(scan: TableScan) -> Self {
+        Self {
+            source: ScanSource::Table(scan),
+            predicate: None,
+            versions: false,
+            columns: Vec::new(),
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Add each row's [`VERSION_COLUMN`] as it's read from storage.
+    pub fn with_versions(mut self, versions: bool) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Give every row these columns, as NULL where a row doesn't have one.
+    ///
+    /// Rows only store the columns they were written with, so this is how
+    /// a column that was left out, or added later, reads as NULL.
+    pub fn with_columns(mut self, columns: Vec<String>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Only return the rows matching `predicate`, checked as they're read.
+    pub fn with_predicate(mut self, predicate: Expr) -> Self {
+        self.predicate = Some(Predicate::new(predicate));
+        self
+    }
+
+    /// Stop with [`ExecuteError::Cancelled`] once `cancel` is, checked
+    /// before each row is read.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    fn read_row(&mut self) -> ExecuteResult<Option<Row>> {
+        self.cancel.check()?;
+        let row = match &mut self.source {
+            ScanSource::Rows { rows, position } => {
+                let row = rows.get(*position).cloned();
+                if row.is_some() {
+                    *position += 1;
+                }
+                row
+            }
+            ScanSource::Table(scan) => {
+                scan.next().transpose()?.map(|row| scanned_row(row, self.versions))
+            }
+        };
+        Ok(row.map(|mut row| {
+            for column in &self.columns {
+                row.entry(column.clone()).or_insert(Value::Null);
+            }
+            row
+        }))
     }
 }
 
 impl Operator for ScanOperator {
     fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
-        if self.position < self.rows.len() {
-            let row = self.rows[self.position].clone();
-            self.position += 1;
-            Ok(Some(row))
-        } else {
-            Ok(None)
+        while let Some(row) = self.read_row()? {
+            let matches = match &self.predicate {
+                Some(predicate) => {
+                    let mut row_map = row.iter()
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect();
+                    predicate.matches(&mut row_map)?
+                }
+                None => true,
+            };
+            if matches {
+                return Ok(Some(row));
+            }
         }
+        Ok(None)
     }
 
     fn reset(&mut self) -> ExecuteResult<()> {
-        self.position = 0;
+        match &mut self.source {
+            ScanSource::Rows { position, .. } => *position = 0,
+            ScanSource::Table(scan) => scan.rewind(),
+        }
         Ok(())
     }
+
+    fn skip(&mut self, n: usize) -> ExecuteResult<usize> {
+        // Which rows count depends on the predicate, so they have to be read
+        if self.predicate.is_some() {
+            let mut skipped = 0;
+            while skipped < n && self.next_row()?.is_some() {
+                skipped += 1;
+            }
+            return Ok(skipped);
+        }
+        match &mut self.source {
+            ScanSource::Rows { rows, position } => {
+                let skipped = n.min(rows.len() - *position);
+                *position += skipped;
+                Ok(skipped)
+            }
+            // Skipped rows are never read from storage
+            ScanSource::Table(scan) => Ok(scan.skip_rows(n)),
+        }
+    }
 }
 
 /// Filter operator - applies WHERE clause.
 pub struct FilterOperator {
     source: Box<dyn Operator>,
-    predicate: Expr,
+    predicate: Predicate,
 }
 
 impl FilterOperator {
     pub fn new(source: Box<dyn Operator>, predicate: Expr) -> Self {
-        Self { source, predicate }
+        Self { source, predicate: Predicate::new(predicate) }
     }
 }
 
@@ -68,10 +204,10 @@ impl Operator for FilterOperator {
         loop {
             match self.source.next_row()? {
                 Some(row) => {
-                    let row_map = row.iter()
+                    let mut row_map = row.iter()
                         .map(|(k, v)| (k.clone(), v.clone()))
                         .collect();
-                    if matches_where(&self.predicate, &row_map)? {
+                    if self.predicate.matches(&mut row_map)? {
                         return Ok(Some(row));
                     }
                     // Row doesn't match, continue to next
@@ -115,6 +251,15 @@ impl Operator for ProjectOperator {
                                 projected.insert(k.clone(), v.clone());
                             }
                         }
+                        SelectColumn::QualifiedWildcard(table) => {
+                            // Joined rows name their columns `table.column`
+                            let prefix = format!("{}.", table);
+                            for (k, v) in &row {
+                                if k.starts_with(&prefix) {
+                                    projected.insert(k.clone(), v.clone());
+                                }
+                            }
+                        }
                         SelectColumn::Column(name) => {
                             if let Some(v) = row.get(name) {
                                 projected.insert(name.clone(), v.clone());
@@ -138,12 +283,26 @@ impl Operator for ProjectOperator {
     }
 }
 
-/// Sort operator - orders rows.
+/// Sort operator - orders rows by columns.
+///
+/// Rows are sorted in memory unless a [`SpillConfig`] is given and they
+/// outgrow it. Then each full buffer is sorted and written out as a run,
+/// and the runs are merged as rows are pulled, so memory holds at most one
+/// buffer and one row per merged run. Runs are deleted once the operator
+/// is dropped, whether or not the query finished.
 pub struct SortOperator {
     source: Box<dyn Operator>,
     order_by: Vec<OrderBy>,
-    sorted_rows: Option<Vec<Row>>,
-    position: usize,
+    spill: Option<SpillConfig>,
+    sorted: Option<Sorted>,
+}
+
+/// Most runs merged at once, which bounds the files open while merging.
+const MERGE_FAN_IN: usize = 16;
+
+enum Sorted {
+    Memory(std::vec::IntoIter<Row>),
+    Merge(RunMerge),
 }
 
 impl SortOperator {
@@ -151,60 +310,164 @@ impl SortOperator {
         Self {
             source,
             order_by,
-            sorted_rows: None,
-            position: 0,
+            spill: None,
+            sorted: None,
         }
     }
 
-    fn materialize(&mut self) -> ExecuteResult<()> {
-        if self.sorted_rows.is_some() {
-            return Ok(());
-        }
-
-        let mut rows = Vec::new();
-        while let Some(row) = self.source.next_row()? {
-            rows.push(row);
-        }
+    /// Spill sorted runs to disk when rows outgrow `config`.
+    pub fn with_spill(mut self, config: SpillConfig) -> Self {
+        self.spill = Some(config);
+        self
+    }
 
-        // Sort by order_by columns
-        let order_by = self.order_by.clone();
-        rows.sort_by(|a, b| {
-            for ob in &order_by {
-                let va = a.get(&ob.column);
-                let vb = b.get(&ob.column);
-                let cmp = compare_json_values(va, vb);
-                if cmp != std::cmp::Ordering::Equal {
-                    return if ob.ascending { cmp } else { cmp.reverse() };
+    fn materialize(&mut self) -> ExecuteResult<&mut Sorted> {
+        if self.sorted.is_none() {
+            let mut rows = Vec::new();
+            let mut bytes = 0;
+            let mut runs = Vec::new();
+            while let Some(row) = self.source.next_row()? {
+                let Some(spill) = &self.spill else {
+                    rows.push(row);
+                    continue;
+                };
+                bytes += row_size(&row);
+                rows.push(row);
+                if spill.exceeded(rows.len(), bytes) {
+                    sort_rows(&mut rows, &self.order_by);
+                    runs.push(spill.spill(rows.drain(..))?);
+                    bytes = 0;
                 }
             }
-            std::cmp::Ordering::Equal
-        });
+            sort_rows(&mut rows, &self.order_by);
 
-        self.sorted_rows = Some(rows);
-        Ok(())
+            let sorted = match &self.spill {
+                Some(spill) if !runs.is_empty() => {
+                    if !rows.is_empty() {
+                        runs.push(spill.spill(rows)?);
+                    }
+                    Sorted::Merge(RunMerge::new(runs, &self.order_by, spill)?)
+                }
+                _ => Sorted::Memory(rows.into_iter()),
+            };
+            self.sorted = Some(sorted);
+        }
+        Ok(self.sorted.as_mut().expect("sorted above"))
     }
 }
 
 impl Operator for SortOperator {
     fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
-        self.materialize()?;
-
-        if let Some(ref rows) = self.sorted_rows {
-            if self.position < rows.len() {
-                let row = rows[self.position].clone();
-                self.position += 1;
-                return Ok(Some(row));
-            }
+        match self.materialize()? {
+            Sorted::Memory(rows) => Ok(rows.next()),
+            Sorted::Merge(merge) => merge.next_row(),
         }
-        Ok(None)
     }
 
     fn reset(&mut self) -> ExecuteResult<()> {
         self.source.reset()?;
-        self.sorted_rows = None;
-        self.position = 0;
+        self.sorted = None;
         Ok(())
     }
+
+    fn skip(&mut self, n: usize) -> ExecuteResult<usize> {
+        match self.materialize()? {
+            Sorted::Memory(rows) => Ok(rows.by_ref().take(n).count()),
+            Sorted::Merge(merge) => {
+                let mut skipped = 0;
+                while skipped < n && merge.next_row()?.is_some() {
+                    skipped += 1;
+                }
+                Ok(skipped)
+            }
+        }
+    }
+}
+
+/// Sort rows by `order_by`, keeping rows that tie in the order they came.
+fn sort_rows(rows: &mut [Row], order_by: &[OrderBy]) {
+    rows.sort_by(|a, b| compare_rows(a, b, order_by));
+}
+
+fn compare_rows(a: &Row, b: &Row, order_by: &[OrderBy]) -> std::cmp::Ordering {
+    for ob in order_by {
+        let cmp = compare_json_values(a.get(&ob.column), b.get(&ob.column));
+        if cmp != std::cmp::Ordering::Equal {
+            return if ob.ascending { cmp } else { cmp.reverse() };
+        }
+    }
+    std::cmp::Ordering::Equal
+}
+
+/// Merges sorted runs into one sorted stream.
+///
+/// Each run's next row is held, and the least is returned; on a tie the
+/// earlier run's row, so the merge is as stable as the in-memory sort.
+struct RunMerge {
+    order_by: Vec<OrderBy>,
+    readers: Vec<RunReader>,
+    heads: Vec<Option<Row>>,
+    // Dropped last, deleting the files once nothing reads them
+    _runs: Vec<Run>,
+}
+
+impl RunMerge {
+    /// Merge `runs`, in the order they were written. More than
+    /// [`MERGE_FAN_IN`] are merged in passes, each merging neighbouring
+    /// runs into one new run, until few enough are left.
+    fn new(mut runs: Vec<Run>, order_by: &[OrderBy], spill: &SpillConfig) -> ExecuteResult<Self> {
+        while runs.len() > MERGE_FAN_IN {
+            let mut merged = Vec::with_capacity(runs.len().div_ceil(MERGE_FAN_IN));
+            let mut pending = runs.into_iter();
+            loop {
+                let group: Vec<Run> = pending.by_ref().take(MERGE_FAN_IN).collect();
+                match group.len() {
+                    0 => break,
+                    1 => merged.extend(group),
+                    _ => {
+                        let mut merge = Self::open(group, order_by)?;
+                        let mut writer = spill.create_run()?;
+                        while let Some(row) = merge.next_row()? {
+                            writer.push(&row)?;
+                        }
+                        merged.push(writer.finish()?);
+                    }
+                }
+            }
+            runs = merged;
+        }
+        Self::open(runs, order_by)
+    }
+
+    fn open(runs: Vec<Run>, order_by: &[OrderBy]) -> ExecuteResult<Self> {
+        let mut readers = runs.iter().map(Run::reader).collect::<ExecuteResult<Vec<_>>>()?;
+        let heads = readers.iter_mut().map(RunReader::next_row).collect::<ExecuteResult<Vec<_>>>()?;
+        Ok(Self {
+            order_by: order_by.to_vec(),
+            readers,
+            heads,
+            _runs: runs,
+        })
+    }
+
+    fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
+        let mut least: Option<usize> = None;
+        for (i, head) in self.heads.iter().enumerate() {
+            let Some(row) = head else { continue };
+            let is_less = match least.and_then(|l| self.heads[l].as_ref()) {
+                Some(current) => compare_rows(row, current, &self.order_by).is_lt(),
+                None => true,
+            };
+            if is_less {
+                least = Some(i);
+            }
+        }
+        let Some(i) = least else {
+            return Ok(None);
+        };
+        let next = self.readers[i].next_row()?;
+        Ok(std::mem::replace(&mut self.heads[i], next))
+    }
 }
 
 /// Limit operator - restricts number of rows.
@@ -230,22 +493,27 @@ impl LimitOperator {
 
 impl Operator for LimitOperator {
     fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
-        // Skip offset rows
-        while self.skipped < self.offset {
-            if self.source.next_row()?.is_none() {
+        // LIMIT 0, or the limit reached: nothing more to read
+        if self.current >= self.limit {
+            return Ok(None);
+        }
+
+        // Skip offset rows; an offset past the end leaves nothing
+        if self.skipped < self.offset {
+            self.skipped += self.source.skip(self.offset - self.skipped)?;
+            if self.skipped < self.offset {
                 return Ok(None);
             }
-            self.skipped += 1;
         }
 
         // Return up to limit rows
-        if self.current < self.limit {
-            if let Some(row) = self.source.next_row()? {
+        match self.source.next_row()? {
+            Some(row) => {
                 self.current += 1;
-                return Ok(Some(row));
+                Ok(Some(row))
             }
+            None => Ok(None),
         }
-        Ok(None)
     }
 
     fn reset(&mut self) -> ExecuteResult<()> {
@@ -256,26 +524,974 @@ impl Operator for LimitOperator {
     }
 }
 
+/// Qualify operator - prefixes column names with their table, the way
+/// joined rows name them.
+pub struct QualifyOperator {
+    source: Box<dyn Operator>,
+    table: String,
+}
+
+impl QualifyOperator {
+    pub fn new(source: Box<dyn Operator>, table: impl Into<String>) -> Self {
+        Self { source, table: table.into() }
+    }
+}
+
+impl Operator for QualifyOperator {
+    fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
+        Ok(self.source.next_row()?.map(|row| {
+            row.into_iter()
+                .map(|(k, v)| (format!("{}.{}", self.table, k), v))
+                .collect()
+        }))
+    }
+
+    fn reset(&mut self) -> ExecuteResult<()> {
+        self.source.reset()
+    }
+
+    fn skip(&mut self, n: usize) -> ExecuteResult<usize> {
+        self.source.skip(n)
+    }
+}
+
+/// How a join pairs left rows with right rows.
+#[derive(Debug, Clone)]
+pub enum JoinCondition {
+    /// Every pair matches (CROSS JOIN).
+    Always,
+    /// Pairs whose combined row satisfies the expression.
+    On(Expr),
+    /// Pairs whose key columns are equal; NULL keys never match.
+    Keys { left: Vec<String>, right: Vec<String> },
+}
+
+/// Which unmatched rows a join keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    /// Only pairs that match.
+    Inner,
+    /// Also every left row without a match, with NULL right columns.
+    Left,
+    /// Also every right row without a match, with NULL left columns.
+    Right,
+    /// Unmatched rows from both sides.
+    Full,
+}
+
+/// Join operator - reads the right side once and matches every left row
+/// against it, through a hash table when joining on keys.
+///
+/// Outer joins pad the missing side with NULL for every column that side
+/// has: the columns given to [`JoinOperator::with_columns`] plus any seen
+/// in its rows, so unmatched rows have the same columns as matched ones.
+pub struct JoinOperator {
+    left: Box<dyn Operator>,
+    right: Box<dyn Operator>,
+    condition: JoinCondition,
+    kind: JoinKind,
+    left_columns: BTreeSet<String>,
+    right_columns: BTreeSet<String>,
+    right_rows: Option<Vec<Row>>,
+    right_matched: Vec<bool>,
+    index: HashMap<String, Vec<usize>>,
+    pending: VecDeque<Row>,
+    left_done: bool,
+    cancel: CancelToken,
+}
+
+impl JoinOperator {
+    pub fn new(left: Box<dyn Operator>, right: Box<dyn Operator>, condition: JoinCondition) -> Self {
+        Self {
+            left,
+            right,
+            condition,
+            kind: JoinKind::Inner,
+            left_columns: BTreeSet::new(),
+            right_columns: BTreeSet::new(),
+            right_rows: None,
+            right_matched: Vec::new(),
+            index: HashMap::new(),
+            pending: VecDeque::new(),
+            left_done: false,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Keep unmatched rows as `kind` says.
+    pub fn kind(mut self, kind: JoinKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Columns each side has even when it has no rows, for NULL padding.
+    pub fn with_columns(mut self, left: Vec<String>, right: Vec<String>) -> Self {
+        self.left_columns.extend(left);
+        self.right_columns.extend(right);
+        self
+    }
+
+    /// Stop with [`ExecuteError::Cancelled`] once `cancel` is, checked for
+    /// every pair of rows tried.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    fn materialize(&mut self) -> ExecuteResult<()> {
+        if self.right_rows.is_some() {
+            return Ok(());
+        }
+        let mut rows = Vec::new();
+        while let Some(row) = self.right.next_row()? {
+            if let JoinCondition::Keys { right, .. } = &self.condition {
+                if let Some(key) = join_key(&row, right) {
+                    self.index.entry(key).or_default().push(rows.len());
+                }
+            }
+            self.right_columns.extend(row.keys().cloned());
+            rows.push(row);
+        }
+        self.right_matched = vec![false; rows.len()];
+        self.right_rows = Some(rows);
+        Ok(())
+    }
+
+    /// The right rows that pair with `left`, with their positions.
+    fn matches(&self, left: &Row) -> ExecuteResult<Vec<(usize, Row)>> {
+        let right_rows = self.right_rows.as_deref().unwrap_or_default();
+        let candidates: Vec<usize> = match &self.condition {
+            JoinCondition::Keys { left: keys, .. } => join_key(left, keys)
+                .and_then(|key| self.index.get(&key))
+                .cloned()
+                .unwrap_or_default(),
+            _ => (0..right_rows.len()).collect(),
+        };
+
+        let mut joined = Vec::new();
+        for i in candidates {
+            self.cancel.check()?;
+            let mut row = left.clone();
+            row.extend(right_rows[i].iter().map(|(k, v)| (k.clone(), v.clone())));
+            if let JoinCondition::On(expr) = &self.condition {
+                let row_map = row.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                if !matches_where(expr, &row_map)? {
+                    continue;
+                }
+            }
+            joined.push((i, row));
+        }
+        Ok(joined)
+    }
+
+    /// `row` with NULL for each of `columns` it doesn't have.
+    fn pad(mut row: Row, columns: &BTreeSet<String>) -> Row {
+        for column in columns {
+            row.entry(column.clone()).or_insert(Value::Null);
+        }
+        row
+    }
+}
+
+impl Operator for JoinOperator {
+    fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
+        self.materialize()?;
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Ok(Some(row));
+            }
+            if self.left_done {
+                return Ok(None);
+            }
+            match self.left.next_row()? {
+                Some(left) => {
+                    self.left_columns.extend(left.keys().cloned());
+                    let matches = self.matches(&left)?;
+                    if matches.is_empty() && matches!(self.kind, JoinKind::Left | JoinKind::Full) {
+                        self.pending.push_back(Self::pad(left, &self.right_columns));
+                    }
+                    for (i, row) in matches {
+                        self.right_matched[i] = true;
+                        self.pending.push_back(row);
+                    }
+                }
+                None => {
+                    // Every left row has been seen: the right rows nothing
+                    // matched come last
+                    self.left_done = true;
+                    if matches!(self.kind, JoinKind::Right | JoinKind::Full) {
+                        let right_rows = self.right_rows.as_deref().unwrap_or_default();
+                        for (row, _) in right_rows.iter().zip(&self.right_matched).filter(|(_, m)| !**m) {
+                            self.pending.push_back(Self::pad(row.clone(), &self.left_columns));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) -> ExecuteResult<()> {
+        self.left.reset()?;
+        self.right_matched.iter_mut().for_each(|m| *m = false);
+        self.pending.clear();
+        self.left_done = false;
+        Ok(())
+    }
+}
+
+/// The JSON text of a row's key columns, or None if any of them is NULL.
+fn join_key(row: &Row, columns: &[String]) -> Option<String> {
+    let mut values = Vec::with_capacity(columns.len());
+    for column in columns {
+        match row.get(column) {
+            None | Some(Value::Null) => return None,
+            Some(value) => values.push(value),
+        }
+    }
+    serde_json::to_string(&values).ok()
+}
+
+/// Aggregate operator - groups its input and computes COUNT, SUM, AVG, MIN
+/// and MAX for each group.
+///
+/// Without GROUP BY columns every row is one group, so an empty input still
+/// produces a row (`COUNT` 0, the others NULL).
+///
+/// Groups are built in memory unless a [`SpillConfig`] is given and the
+/// rows outgrow it. Then every row is written to one of several partitions
+/// on disk by a hash of its group, so each group lands whole in a single
+/// partition, and the partitions are aggregated one at a time as rows are
+/// pulled. A partition still too big is split again, a few times at most.
+/// Spilled groups come out partition by partition rather than in key order.
+pub struct AggregateOperator {
+    source: Box<dyn Operator>,
+    group_by: Vec<String>,
+    aggregates: Vec<PhysicalAggregate>,
+    spill: Option<SpillConfig>,
+    results: Option<Vec<Row>>,
+    position: usize,
+    /// Spilled partitions not yet aggregated, last first, with how many
+    /// times their rows have been split.
+    partitions: Vec<(Run, usize)>,
+    spilled: bool,
+}
+
+/// Groups keyed by the JSON text of their GROUP BY values.
+type Groups = BTreeMap<String, (Vec<Value>, Vec<Row>)>;
+
+/// How many partitions a spilling aggregate splits its rows into.
+const SPILL_PARTITIONS: usize = 16;
+
+/// How many times rows are split before a partition is grouped in memory
+/// however big it is, as one huge group never gets smaller.
+const MAX_SPILL_DEPTH: usize = 3;
+
+enum Grouped {
+    Memory(Groups),
+    Spilled(Vec<Run>),
+}
+
+impl AggregateOperator {
+    pub fn new(source: Box<dyn Operator>, group_by: Vec<String>, aggregates: Vec<PhysicalAggregate>) -> Self {
+        Self {
+            source,
+            group_by,
+            aggregates,
+            spill: None,
+            results: None,
+            position: 0,
+            partitions: Vec::new(),
+            spilled: false,
+        }
+    }
+
+    /// Spill partitions of rows to disk when groups outgrow `config`.
+    pub fn with_spill(mut self, config: SpillConfig) -> Self {
+        self.spill = Some(config);
+        self
+    }
+
+    fn materialize(&mut self) -> ExecuteResult<()> {
+        if self.results.is_some() {
+            return Ok(());
+        }
+
+        let source = &mut self.source;
+        match group_rows(&self.group_by, self.spill.as_ref(), 0, || source.next_row())? {
+            Grouped::Memory(mut groups) => {
+                if groups.is_empty() && self.group_by.is_empty() {
+                    groups.insert(String::new(), (Vec::new(), Vec::new()));
+                }
+                self.results = Some(self.aggregate(groups)?);
+            }
+            Grouped::Spilled(runs) => {
+                self.spilled = true;
+                self.partitions = runs.into_iter().rev().map(|run| (run, 1)).collect();
+                self.results = Some(Vec::new());
+            }
+        }
+        self.position = 0;
+        Ok(())
+    }
+
+    /// Aggregate the next spilled partition, or split it further. Returns
+    /// false once every partition is done.
+    fn next_partition(&mut self) -> ExecuteResult<bool> {
+        let Some((run, depth)) = self.partitions.pop() else {
+            return Ok(false);
+        };
+        let mut reader = run.reader()?;
+        match group_rows(&self.group_by, self.spill.as_ref(), depth, || reader.next_row())? {
+            Grouped::Memory(groups) => {
+                self.results = Some(self.aggregate(groups)?);
+                self.position = 0;
+            }
+            Grouped::Spilled(runs) => {
+                self.partitions.extend(runs.into_iter().rev().map(|run| (run, depth + 1)));
+            }
+        }
+        Ok(true)
+    }
+
+    fn aggregate(&self, groups: Groups) -> ExecuteResult<Vec<Row>> {
+        let mut results = Vec::with_capacity(groups.len());
+        for (values, rows) in groups.into_values() {
+            let mut out: Row = self.group_by.iter().cloned().zip(values).collect();
+            for aggregate in &self.aggregates {
+                out.insert(aggregate.output_column.clone(), compute_aggregate(aggregate, &rows)?);
+            }
+            results.push(out);
+        }
+        Ok(results)
+    }
+}
+
+impl Operator for AggregateOperator {
+    fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
+        self.materialize()?;
+        loop {
+            let row = self.results.as_ref().and_then(|rows| rows.get(self.position)).cloned();
+            if row.is_some() {
+                self.position += 1;
+                return Ok(row);
+            }
+            if !self.next_partition()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn reset(&mut self) -> ExecuteResult<()> {
+        // Spilled partitions are consumed as they're read, so start over
+        if self.spilled {
+            self.source.reset()?;
+            self.results = None;
+            self.partitions.clear();
+            self.spilled = false;
+        }
+        self.position = 0;
+        Ok(())
+    }
+}
+
+/// Group the rows `next` yields by `group_by`. Once they outgrow `spill`,
+/// every row goes to a partition on disk instead, chosen by hashing its
+/// group with `depth` so a partition split again spreads differently.
+fn group_rows(
+    group_by: &[String],
+    spill: Option<&SpillConfig>,
+    depth: usize,
+    mut next: impl FnMut() -> ExecuteResult<Option<Row>>,
+) -> ExecuteResult<Grouped> {
+    // Without GROUP BY there's one group, which splitting can't shrink
+    let spill = spill.filter(|_| !group_by.is_empty() && depth < MAX_SPILL_DEPTH);
+    let group_of = |row: &Row| -> (String, Vec<Value>) {
+        let values: Vec<Value> = group_by.iter()
+            .map(|c| row.get(c).cloned().unwrap_or(Value::Null))
+            .collect();
+        (serde_json::to_string(&values).unwrap_or_default(), values)
+    };
+
+    let mut groups = Groups::new();
+    let (mut rows, mut bytes) = (0, 0);
+    while let Some(row) = next()? {
+        let (key, values) = group_of(&row);
+        if let Some(spill) = spill {
+            rows += 1;
+            bytes += row_size(&row);
+            if spill.exceeded(rows, bytes) {
+                let mut partitions = (0..SPILL_PARTITIONS)
+                    .map(|_| spill.create_run())
+                    .collect::<ExecuteResult<Vec<_>>>()?;
+                for (key, (_, rows)) in std::mem::take(&mut groups) {
+                    let partition = &mut partitions[partition_of(&key, depth)];
+                    for row in rows {
+                        partition.push(&row)?;
+                    }
+                }
+                partitions[partition_of(&key, depth)].push(&row)?;
+                while let Some(row) = next()? {
+                    partitions[partition_of(&group_of(&row).0, depth)].push(&row)?;
+                }
+
+                let mut runs = Vec::with_capacity(SPILL_PARTITIONS);
+                for partition in partitions {
+                    let run = partition.finish()?;
+                    if run.len() > 0 {
+                        runs.push(run);
+                    }
+                }
+                return Ok(Grouped::Spilled(runs));
+            }
+        }
+        groups.entry(key).or_insert_with(|| (values, Vec::new())).1.push(row);
+    }
+    Ok(Grouped::Memory(groups))
+}
+
+fn partition_of(key: &str, depth: usize) -> usize {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    (depth, key).hash(&mut hasher);
+    (hasher.finish() % SPILL_PARTITIONS as u64) as usize
+}
+
+fn compute_aggregate(aggregate: &PhysicalAggregate, rows: &[Row]) -> ExecuteResult<Value> {
+    // COUNT(*) counts rows; everything else ignores NULLs
+    let Some(column) = &aggregate.input_column else {
+        return Ok(Value::from(rows.len()));
+    };
+    let mut values: Vec<&Value> = rows.iter()
+        .filter_map(|row| row.get(column))
+        .filter(|v| !v.is_null())
+        .collect();
+    if aggregate.distinct {
+        let mut seen = HashSet::new();
+        values.retain(|v| seen.insert(v.to_string()));
+    }
+
+    let numbers = || -> ExecuteResult<Vec<&serde_json::Number>> {
+        values.iter()
+            .map(|v| match v {
+                Value::Number(n) => Ok(n),
+                other => Err(ExecuteError::TypeMismatch {
+                    expected: "number".into(),
+                    actual: other.to_string(),
+                }),
+            })
+            .collect()
+    };
+
+    // Decimal columns store numeric strings; sum those exactly
+    if values.iter().any(|v| v.is_string())
+        && matches!(aggregate.function, AggregatePhysical::Sum | AggregatePhysical::Avg)
+    {
+        let decimals = values.iter()
+            .map(|v| Decimal::from_value(v).ok_or_else(|| ExecuteError::TypeMismatch {
+                expected: "number".into(),
+                actual: v.to_string(),
+            }))
+            .collect::<ExecuteResult<Vec<_>>>()?;
+        return decimal_aggregate(&aggregate.function, &decimals);
+    }
+
+    Ok(match aggregate.function {
+        AggregatePhysical::Count => Value::from(values.len()),
+        AggregatePhysical::Sum if values.is_empty() => Value::Null,
+        AggregatePhysical::Sum => {
+            let numbers = numbers()?;
+            let integers: Option<i64> = numbers.iter()
+                .try_fold(0i64, |sum, n| n.as_i64().and_then(|n| sum.checked_add(n)));
+            match integers {
+                Some(sum) => Value::from(sum),
+                None => Value::from(numbers.iter().filter_map(|n| n.as_f64()).sum::<f64>()),
+            }
+        }
+        AggregatePhysical::Avg if values.is_empty() => Value::Null,
+        AggregatePhysical::Avg => {
+            let numbers = numbers()?;
+            let sum: f64 = numbers.iter().filter_map(|n| n.as_f64()).sum();
+            Value::from(sum / numbers.len() as f64)
+        }
+        AggregatePhysical::Min => values.into_iter()
+            .min_by(|a, b| compare_json_values(Some(a), Some(b)))
+            .cloned()
+            .unwrap_or(Value::Null),
+        AggregatePhysical::Max => values.into_iter()
+            .max_by(|a, b| compare_json_values(Some(a), Some(b)))
+            .cloned()
+            .unwrap_or(Value::Null),
+    })
+}
+
+/// SUM or AVG of decimal values, without going through floats.
+fn decimal_aggregate(function: &AggregatePhysical, values: &[Decimal]) -> ExecuteResult<Value> {
+    let sum = values.iter()
+        .try_fold(Decimal::from(0), |sum, d| sum.checked_add(*d))
+        .ok_or(ExecuteError::NumericOverflow)?;
+    let result = match function {
+        AggregatePhysical::Avg => sum.checked_div(Decimal::from(values.len() as i64)).ok_or(ExecuteError::NumericOverflow)?,
+        _ => sum,
+    };
+    Ok(result.to_value())
+}
+
+/// Distinct operator - drops rows equal to one already returned.
+pub struct DistinctOperator {
+    source: Box<dyn Operator>,
+    seen: HashSet<String>,
+}
+
+impl DistinctOperator {
+    pub fn new(source: Box<dyn Operator>) -> Self {
+        Self { source, seen: HashSet::new() }
+    }
+}
+
+impl Operator for DistinctOperator {
+    fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
+        while let Some(row) = self.source.next_row()? {
+            let key = serde_json::to_string(&row).unwrap_or_default();
+            if self.seen.insert(key) {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    fn reset(&mut self) -> ExecuteResult<()> {
+        self.seen.clear();
+        self.source.reset()
+    }
+}
+
+/// Append operator - returns the rows of each input in turn.
+pub struct AppendOperator {
+    sources: Vec<Box<dyn Operator>>,
+    current: usize,
+}
+
+impl AppendOperator {
+    pub fn new(sources: Vec<Box<dyn Operator>>) -> Self {
+        Self { sources, current: 0 }
+    }
+}
+
+impl Operator for AppendOperator {
+    fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
+        while let Some(source) = self.sources.get_mut(self.current) {
+            if let Some(row) = source.next_row()? {
+                return Ok(Some(row));
+            }
+            self.current += 1;
+        }
+        Ok(None)
+    }
+
+    fn reset(&mut self) -> ExecuteResult<()> {
+        for source in &mut self.sources {
+            source.reset()?;
+        }
+        self.current = 0;
+        Ok(())
+    }
+}
+
 /// Compare two JSON values for ordering.
+///
+/// This is a total order, so sorting is deterministic even when a column
+/// holds values of different types: missing and NULL first, then booleans,
+/// numbers, strings, arrays and objects.
 fn compare_json_values(a: Option<&Value>, b: Option<&Value>) -> std::cmp::Ordering {
+    let a = a.unwrap_or(&Value::Null);
+    let b = b.unwrap_or(&Value::Null);
     match (a, b) {
-        (None, None) => std::cmp::Ordering::Equal,
-        (None, Some(_)) => std::cmp::Ordering::Less,
-        (Some(_), None) => std::cmp::Ordering::Greater,
-        (Some(a), Some(b)) => {
-            match (a, b) {
-                (Value::Null, Value::Null) => std::cmp::Ordering::Equal,
-                (Value::Null, _) => std::cmp::Ordering::Less,
-                (_, Value::Null) => std::cmp::Ordering::Greater,
-                (Value::Number(a), Value::Number(b)) => {
-                    let a = a.as_f64().unwrap_or(0.0);
-                    let b = b.as_f64().unwrap_or(0.0);
-                    a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
-                }
-                (Value::String(a), Value::String(b)) => a.cmp(b),
-                (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
-                _ => std::cmp::Ordering::Equal,
+        (Value::Number(a), Value::Number(b)) => {
+            let a = a.as_f64().unwrap_or(0.0);
+            let b = b.as_f64().unwrap_or(0.0);
+            a.total_cmp(&b)
+        }
+        // Decimal columns hold numeric strings, which sort by value
+        (Value::String(a), Value::String(b)) => match (Decimal::parse(a), Decimal::parse(b)) {
+            (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.cmp(b)),
+            _ => a.cmp(b),
+        },
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        // Same-typed arrays and objects compare by their JSON text
+        (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_)) => {
+            a.to_string().cmp(&b.to_string())
+        }
+        _ => type_rank(a).cmp(&type_rank(b)),
+    }
+}
+
+/// Position of a value's type in the sort order.
+fn type_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::sql::{BinaryOperator, LiteralValue};
+
+    #[test]
+    fn test_project_qualified_wildcard() {
+        // A joined row, with columns prefixed by their table
+        let mut row = Row::new();
+        row.insert("users.id".into(), Value::from(1));
+        row.insert("users.name".into(), Value::from("Alice"));
+        row.insert("orders.id".into(), Value::from(10));
+        row.insert("orders.total".into(), Value::from(25));
+
+        let columns = vec![
+            SelectColumn::QualifiedWildcard("users".into()),
+            SelectColumn::Column("orders.total".into()),
+        ];
+        let mut op = ProjectOperator::new(Box::new(ScanOperator::new(vec![row])), columns);
+
+        let projected = op.next_row().unwrap().unwrap();
+        let keys: Vec<_> = projected.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["orders.total", "users.id", "users.name"]);
+    }
+
+    #[test]
+    fn test_sort_mixed_types() {
+        let rows = [json!(null), json!("b"), json!(2), json!(true), json!("a"), json!(10)]
+            .into_iter()
+            .map(|v| Row::from([("v".to_string(), v)]))
+            .collect();
+        let order_by = vec![OrderBy { column: "v".into(), ascending: true }];
+        let mut op = SortOperator::new(Box::new(ScanOperator::new(rows)), order_by);
+
+        let mut values = Vec::new();
+        while let Some(row) = op.next_row().unwrap() {
+            values.push(row["v"].clone());
+        }
+        assert_eq!(values, vec![json!(null), json!(true), json!(2), json!(10), json!("a"), json!("b")]);
+    }
+
+    #[test]
+    fn test_sort_spills_to_disk() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let spill = SpillConfig::default().with_max_rows(10).with_dir(dir.path());
+        let spill_files = || std::fs::read_dir(dir.path()).unwrap().count();
+        // Many ties on `k`, with `seq` telling whether their order held
+        let rows = || -> Vec<Row> {
+            (0..1000)
+                .map(|i| Row::from([("k".to_string(), json!((i * 37) % 13)), ("seq".to_string(), json!(i))]))
+                .collect()
+        };
+        let order_by = vec![OrderBy { column: "k".into(), ascending: false }];
+        let drain = |op: &mut SortOperator| {
+            let mut out = Vec::new();
+            while let Some(row) = op.next_row().unwrap() {
+                out.push(row);
             }
+            out
+        };
+
+        let mut in_memory = SortOperator::new(Box::new(ScanOperator::new(rows())), order_by.clone());
+        let expected = drain(&mut in_memory);
+
+        // A hundred runs take two merge passes; the result is the same
+        let mut op = SortOperator::new(Box::new(ScanOperator::new(rows())), order_by.clone())
+            .with_spill(spill.clone());
+        assert_eq!(op.skip(5).unwrap(), 5);
+        assert!(spill_files() > 0);
+        assert_eq!(drain(&mut op), expected[5..]);
+        drop(op);
+        assert_eq!(spill_files(), 0);
+
+        // Runs written before the input failed are cleaned up too
+        let failing = FilterOperator::new(
+            Box::new(ScanOperator::new(rows())),
+            Expr::BinaryOp {
+                left: Box::new(Expr::BinaryOp {
+                    left: Box::new(Expr::Literal(LiteralValue::Integer(1))),
+                    op: BinaryOperator::Divide,
+                    right: Box::new(Expr::BinaryOp {
+                        left: Box::new(Expr::Column("seq".into())),
+                        op: BinaryOperator::Minus,
+                        right: Box::new(Expr::Literal(LiteralValue::Integer(500))),
+                    }),
+                }),
+                op: BinaryOperator::NotEq,
+                right: Box::new(Expr::Literal(LiteralValue::Integer(1000))),
+            },
+        );
+        let mut op = SortOperator::new(Box::new(failing), order_by).with_spill(spill);
+        assert!(matches!(op.next_row(), Err(ExecuteError::DivisionByZero)));
+        assert_eq!(spill_files(), 0);
+    }
+
+    #[test]
+    fn test_join_on_keys() {
+        let users = vec![
+            Row::from([("users.id".to_string(), json!(1)), ("users.name".to_string(), json!("Alice"))]),
+            Row::from([("users.id".to_string(), json!(2)), ("users.name".to_string(), json!("Bob"))]),
+        ];
+        let orders = vec![
+            Row::from([("orders.user_id".to_string(), json!(1)), ("orders.total".to_string(), json!(5))]),
+            Row::from([("orders.user_id".to_string(), json!(1)), ("orders.total".to_string(), json!(7))]),
+            Row::from([("orders.user_id".to_string(), json!(null)), ("orders.total".to_string(), json!(9))]),
+        ];
+        let condition = JoinCondition::Keys {
+            left: vec!["users.id".into()],
+            right: vec!["orders.user_id".into()],
+        };
+        let mut op = JoinOperator::new(
+            Box::new(ScanOperator::new(users)),
+            Box::new(ScanOperator::new(orders)),
+            condition,
+        );
+
+        // Bob has no orders and the NULL key matches nobody
+        let mut totals = Vec::new();
+        while let Some(row) = op.next_row().unwrap() {
+            assert_eq!(row["users.name"], json!("Alice"));
+            totals.push(row["orders.total"].clone());
         }
+        assert_eq!(totals, vec![json!(5), json!(7)]);
+    }
+
+    #[test]
+    fn test_outer_joins() {
+        let users = || vec![
+            Row::from([("users.id".to_string(), json!(1)), ("users.name".to_string(), json!("Alice"))]),
+            Row::from([("users.id".to_string(), json!(2)), ("users.name".to_string(), json!("Bob"))]),
+        ];
+        let orders = || vec![
+            Row::from([("orders.user_id".to_string(), json!(1)), ("orders.total".to_string(), json!(5))]),
+            Row::from([("orders.user_id".to_string(), json!(1)), ("orders.total".to_string(), json!(7))]),
+            Row::from([("orders.user_id".to_string(), json!(3)), ("orders.total".to_string(), json!(9))]),
+        ];
+        let join = |kind| {
+            let condition = JoinCondition::On(Expr::BinaryOp {
+                left: Box::new(Expr::Column("users.id".into())),
+                op: crate::sql::BinaryOperator::Eq,
+                right: Box::new(Expr::Column("orders.user_id".into())),
+            });
+            let mut op = JoinOperator::new(
+                Box::new(ScanOperator::new(users())),
+                Box::new(ScanOperator::new(orders())),
+                condition,
+            )
+            .kind(kind);
+            let mut rows = Vec::new();
+            while let Some(row) = op.next_row().unwrap() {
+                assert_eq!(row.len(), 4, "every row has all four columns: {:?}", row);
+                rows.push((row["users.name"].clone(), row["orders.total"].clone()));
+            }
+            rows
+        };
+
+        // Alice matches twice; Bob has no orders
+        assert_eq!(join(JoinKind::Left), vec![
+            (json!("Alice"), json!(5)),
+            (json!("Alice"), json!(7)),
+            (json!("Bob"), json!(null)),
+        ]);
+        // The order for user 3 has no user
+        assert_eq!(join(JoinKind::Right), vec![
+            (json!("Alice"), json!(5)),
+            (json!("Alice"), json!(7)),
+            (json!(null), json!(9)),
+        ]);
+        assert_eq!(join(JoinKind::Full).len(), 4);
+        assert_eq!(join(JoinKind::Inner).len(), 2);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let aggregate = |function, input: Option<&str>, output: &str| PhysicalAggregate {
+            function,
+            input_column: input.map(String::from),
+            output_column: output.into(),
+            distinct: false,
+        };
+        let aggregates = vec![
+            aggregate(AggregatePhysical::Count, None, "rows"),
+            aggregate(AggregatePhysical::Count, Some("age"), "ages"),
+            aggregate(AggregatePhysical::Sum, Some("age"), "sum"),
+            aggregate(AggregatePhysical::Avg, Some("age"), "avg"),
+            aggregate(AggregatePhysical::Max, Some("age"), "max"),
+        ];
+        let rows = [json!(30), json!(null), json!(20)]
+            .into_iter()
+            .map(|v| Row::from([("age".to_string(), v)]))
+            .collect();
+
+        let mut op = AggregateOperator::new(Box::new(ScanOperator::new(rows)), vec![], aggregates.clone());
+        let row = op.next_row().unwrap().unwrap();
+        assert_eq!(row["rows"], json!(3));
+        assert_eq!(row["ages"], json!(2));
+        assert_eq!(row["sum"], json!(50));
+        assert_eq!(row["avg"], json!(25.0));
+        assert_eq!(row["max"], json!(30));
+        assert!(op.next_row().unwrap().is_none());
+
+        // No input is still one row
+        let mut op = AggregateOperator::new(Box::new(ScanOperator::new(vec![])), vec![], aggregates);
+        let row = op.next_row().unwrap().unwrap();
+        assert_eq!(row["rows"], json!(0));
+        assert_eq!(row["sum"], json!(null));
+    }
+
+    #[test]
+    fn test_aggregate_spills_to_disk() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let spill = SpillConfig::default().with_max_rows(50).with_dir(dir.path());
+        let spill_files = || std::fs::read_dir(dir.path()).unwrap().count();
+        let rows = |n: i64| -> Vec<Row> {
+            (0..n)
+                .map(|i| Row::from([("k".to_string(), json!(i % 300)), ("v".to_string(), json!(i))]))
+                .collect()
+        };
+        let aggregates = vec![
+            PhysicalAggregate {
+                function: AggregatePhysical::Count,
+                input_column: None,
+                output_column: "rows".into(),
+                distinct: false,
+            },
+            PhysicalAggregate {
+                function: AggregatePhysical::Sum,
+                input_column: Some("v".into()),
+                output_column: "total".into(),
+                distinct: false,
+            },
+        ];
+        let aggregate = |source: Box<dyn Operator>| {
+            AggregateOperator::new(source, vec!["k".into()], aggregates.clone()).with_spill(spill.clone())
+        };
+        let drain = |op: &mut AggregateOperator| {
+            let mut out = Vec::new();
+            while let Some(row) = op.next_row().unwrap() {
+                out.push(row);
+            }
+            out.sort_by_key(|row| row["k"].as_i64());
+            out
+        };
+
+        // Partitions split twice over still give every group whole
+        let mut in_memory = AggregateOperator::new(Box::new(ScanOperator::new(rows(2000))), vec!["k".into()], aggregates.clone());
+        let expected = drain(&mut in_memory);
+        assert_eq!(expected.len(), 300);
+        let mut op = aggregate(Box::new(ScanOperator::new(rows(2000))));
+        assert!(op.next_row().unwrap().is_some());
+        assert!(spill_files() > 0);
+        op.reset().unwrap();
+        assert_eq!(drain(&mut op), expected);
+        drop(op);
+        assert_eq!(spill_files(), 0);
+
+        // Under the limits nothing touches disk
+        let mut op = aggregate(Box::new(ScanOperator::new(rows(40))));
+        assert!(op.next_row().unwrap().is_some());
+        assert_eq!(spill_files(), 0);
+
+        // Partitions written before the input failed are cleaned up too
+        struct Failing(std::vec::IntoIter<Row>);
+        impl Operator for Failing {
+            fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
+                self.0.next().map(Some).ok_or(ExecuteError::DivisionByZero)
+            }
+            fn reset(&mut self) -> ExecuteResult<()> {
+                Ok(())
+            }
+        }
+        let mut op = aggregate(Box::new(Failing(rows(1000).into_iter())));
+        assert!(matches!(op.next_row(), Err(ExecuteError::DivisionByZero)));
+        assert_eq!(spill_files(), 0);
+    }
+
+    #[test]
+    fn test_lazy_scan_stops_at_limit() {
+        use crate::storage::{GitRepository, Row as StorageRow, RowKey, TableName};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = GitRepository::init(dir.path()).unwrap().with_cache(100);
+        let table = TableName::new("items").unwrap();
+        let mut head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        for i in 0..10 {
+            let mut data = Row::new();
+            data.insert("value".into(), Value::from(i));
+            let row = StorageRow::new(RowKey::new(format!("item{}", i)).unwrap(), data);
+            head = repo.insert_row(&table, row, head, None).unwrap();
+        }
+
+        let scan = ScanOperator::lazy(repo.scan_iter(&table, head).unwrap());
+        let mut op = LimitOperator::new(Box::new(scan), 2, 1);
+        let mut values = Vec::new();
+        while let Some(row) = op.next_row().unwrap() {
+            values.push(row["value"].clone());
+        }
+        assert_eq!(values, vec![Value::from(1), Value::from(2)]);
+
+        // The listing plus the two rows returned; the offset row and the
+        // other seven were never read
+        let stats = repo.cache_stats().unwrap();
+        assert_eq!(stats.hits + stats.misses, 3);
+
+        // LIMIT 0 reads nothing, and an offset past the end is just empty
+        let scan = ScanOperator::lazy(repo.scan_iter(&table, head).unwrap());
+        assert!(LimitOperator::new(Box::new(scan), 0, 0).next_row().unwrap().is_none());
+        let scan = ScanOperator::lazy(repo.scan_iter(&table, head).unwrap());
+        assert!(LimitOperator::new(Box::new(scan), 5, usize::MAX).next_row().unwrap().is_none());
+        assert_eq!(repo.cache_stats().unwrap().misses + repo.cache_stats().unwrap().hits, 5);
+    }
+
+    #[test]
+    fn test_scan_with_predicate() {
+        use crate::sql::{BinaryOperator, LiteralValue};
+
+        let rows = (0..10).map(|i| Row::from([("value".to_string(), Value::from(i))])).collect();
+        let predicate = Expr::BinaryOp {
+            left: Box::new(Expr::Column("value".into())),
+            op: BinaryOperator::Gt,
+            right: Box::new(Expr::Literal(LiteralValue::Integer(4))),
+        };
+        let scan = ScanOperator::new(rows).with_predicate(predicate);
+
+        // The offset skips matching rows only
+        let mut op = LimitOperator::new(Box::new(scan), 2, 1);
+        let mut values = Vec::new();
+        while let Some(row) = op.next_row().unwrap() {
+            values.push(row["value"].clone());
+        }
+        assert_eq!(values, vec![Value::from(6), Value::from(7)]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let rows = || (0..3).map(|i| Row::from([("value".to_string(), Value::from(i))])).collect::<Vec<_>>();
+        let cancel = CancelToken::new();
+        let mut scan = ScanOperator::new(rows()).with_cancel(cancel.clone());
+        assert!(scan.next_row().unwrap().is_some());
+        cancel.cancel();
+        assert!(matches!(scan.next_row(), Err(ExecuteError::Cancelled)));
+
+        // A join stops between pairs, even with its inputs still going
+        let cancel = CancelToken::new();
+        let mut join = JoinOperator::new(
+            Box::new(ScanOperator::new(rows())),
+            Box::new(ScanOperator::new(rows())),
+            JoinCondition::Always,
+        )
+        .with_cancel(cancel.clone());
+        assert!(join.next_row().unwrap().is_some());
+        cancel.cancel();
+        while let Ok(Some(_)) = join.next_row() {}
+        assert!(matches!(join.next_row(), Err(ExecuteError::Cancelled)));
     }
 }
@@ -2,6 +2,9 @@
 
 use serde_json::Value;
 use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::storage::{CommitId, GcReport};
 
 /// Result of a query execution.
 #[derive(Debug)]
@@ -42,6 +45,35 @@ pub struct ResultSet {
     pub rows: Vec<BTreeMap<String, Value>>,
 }
 
+/// One page of rows from [`QueryExecutor::page_after`](super::QueryExecutor::page_after).
+#[derive(Debug, Clone)]
+pub struct Page {
+    /// The rows, in primary key order.
+    pub rows: Vec<BTreeMap<String, Value>>,
+    /// Primary key of the last row, to pass as `after` for the next page;
+    /// `None` when there are no more rows.
+    pub next: Option<Vec<Value>>,
+}
+
+/// Outcome of [`QueryExecutor::vacuum`](super::QueryExecutor::vacuum).
+#[derive(Debug, Clone)]
+pub struct VacuumReport {
+    /// The root commit main's history was squashed into; `None` unless the
+    /// vacuum was FULL.
+    pub root: Option<CommitId>,
+    /// What the gc that followed removed.
+    pub gc: GcReport,
+}
+
+impl fmt::Display for VacuumReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(root) = self.root {
+            write!(f, "squashed history into {}, ", root.short())?;
+        }
+        write!(f, "{}", self.gc)
+    }
+}
+
 impl ResultSet {
     /// Create a new empty result set.
     pub fn new(columns: Vec<String>) -> Self {
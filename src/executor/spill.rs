@@ -0,0 +1,184 @@
+//! Spilling rows to temporary files when they outgrow memory.
+//!
+//! Operators that would otherwise hold every row, like sorts and aggregates,
+//! buffer rows up to a [`SpillConfig`]'s limits and then write them out as
+//! [`Run`]s: sorted chunks for a sort, hash partitions for an aggregate.
+//! A run is a temporary file of rows, one JSON object per line, deleted as
+//! soon as the run is dropped, so a failed or abandoned query leaves nothing
+//! behind.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use serde_json::Value;
+use tempfile::NamedTempFile;
+
+use super::error::{ExecuteError, ExecuteResult};
+use super::operators::Row;
+
+/// When and where operators spill rows to disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpillConfig {
+    /// Most rows an operator holds in memory before spilling.
+    pub max_rows: usize,
+    /// Most bytes of rows, roughly, an operator holds before spilling.
+    pub max_bytes: usize,
+    /// Directory for spill files, or the system's temporary directory.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        Self {
+            max_rows: 100_000,
+            max_bytes: 64 * 1024 * 1024,
+            dir: None,
+        }
+    }
+}
+
+impl SpillConfig {
+    /// Set the most rows held in memory.
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    /// Set the most bytes of rows held in memory.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Write spill files to `dir`.
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Whether `rows` rows taking `bytes` are more than fit in memory.
+    pub(crate) fn exceeded(&self, rows: usize, bytes: usize) -> bool {
+        rows >= self.max_rows.max(1) || bytes >= self.max_bytes
+    }
+
+    /// Start writing a new run.
+    pub(crate) fn create_run(&self) -> ExecuteResult<RunWriter> {
+        let file = match &self.dir {
+            Some(dir) => NamedTempFile::with_prefix_in("gitdb-spill-", dir),
+            None => NamedTempFile::with_prefix("gitdb-spill-"),
+        }
+        .map_err(ExecuteError::Spill)?;
+        Ok(RunWriter { writer: BufWriter::new(file), rows: 0 })
+    }
+
+    /// Write `rows` out as a run.
+    pub(crate) fn spill(&self, rows: impl IntoIterator<Item = Row>) -> ExecuteResult<Run> {
+        let mut writer = self.create_run()?;
+        for row in rows {
+            writer.push(&row)?;
+        }
+        writer.finish()
+    }
+}
+
+/// About how many bytes a row takes in memory.
+pub(crate) fn row_size(row: &Row) -> usize {
+    row.iter().map(|(column, value)| column.len() + value_size(value)).sum()
+}
+
+fn value_size(value: &Value) -> usize {
+    const SLOT: usize = std::mem::size_of::<Value>();
+    match value {
+        Value::String(s) => SLOT + s.len(),
+        Value::Array(items) => SLOT + items.iter().map(value_size).sum::<usize>(),
+        Value::Object(fields) => SLOT + fields.iter().map(|(k, v)| k.len() + value_size(v)).sum::<usize>(),
+        _ => SLOT,
+    }
+}
+
+/// A run being written.
+pub(crate) struct RunWriter {
+    writer: BufWriter<NamedTempFile>,
+    rows: usize,
+}
+
+impl RunWriter {
+    pub(crate) fn push(&mut self, row: &Row) -> ExecuteResult<()> {
+        serde_json::to_writer(&mut self.writer, row)
+            .map_err(|e| ExecuteError::Spill(e.into()))?;
+        self.writer.write_all(b"\n").map_err(ExecuteError::Spill)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> ExecuteResult<Run> {
+        let file = self.writer.into_inner().map_err(|e| ExecuteError::Spill(e.into_error()))?;
+        Ok(Run { file, rows: self.rows })
+    }
+}
+
+/// Rows spilled to a temporary file, which is deleted when this is dropped.
+pub(crate) struct Run {
+    file: NamedTempFile,
+    rows: usize,
+}
+
+impl Run {
+    /// How many rows the run holds.
+    pub(crate) fn len(&self) -> usize {
+        self.rows
+    }
+
+    /// Read the rows back in the order they were written.
+    pub(crate) fn reader(&self) -> ExecuteResult<RunReader> {
+        let file = self.file.reopen().map_err(ExecuteError::Spill)?;
+        Ok(RunReader { lines: BufReader::new(file), line: String::new() })
+    }
+}
+
+/// Reads a run's rows back, one at a time.
+pub(crate) struct RunReader {
+    lines: BufReader<File>,
+    line: String,
+}
+
+impl RunReader {
+    pub(crate) fn next_row(&mut self) -> ExecuteResult<Option<Row>> {
+        self.line.clear();
+        if self.lines.read_line(&mut self.line).map_err(ExecuteError::Spill)? == 0 {
+            return Ok(None);
+        }
+        serde_json::from_str(&self.line)
+            .map(Some)
+            .map_err(|e| ExecuteError::Spill(e.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = SpillConfig::default().with_dir(dir.path());
+        let rows: Vec<Row> = (0..3)
+            .map(|i| Row::from([("id".to_string(), Value::from(i)), ("tags".to_string(), serde_json::json!(["a\nb"]))]))
+            .collect();
+
+        let run = config.spill(rows.clone()).unwrap();
+        assert_eq!(run.len(), 3);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        let mut reader = run.reader().unwrap();
+        let mut read = Vec::new();
+        while let Some(row) = reader.next_row().unwrap() {
+            read.push(row);
+        }
+        assert_eq!(read, rows);
+
+        drop(reader);
+        drop(run);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -23,3 +23,5 @@ pub mod planner;
 pub mod sql;
 pub mod storage;
 pub mod transaction;
+
+mod trace;
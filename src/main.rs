@@ -2,10 +2,13 @@
 //!
 //! This is the main entry point for the GitDB command-line interface.
 
+use std::io::IsTerminal;
 use std::path::PathBuf;
 use std::process::ExitCode;
 
-use gitdb::db::{Database, DatabaseConfig, Repl};
+use gitdb::db::{write_error, write_result, Database, DatabaseConfig, DatabaseError, DatabaseResult, OutputFormat, Repl};
+use gitdb::executor::QueryResult;
+use gitdb::storage::GitRepository;
 
 fn main() -> ExitCode {
     let args: Vec<String> = std::env::args().collect();
@@ -14,6 +17,14 @@ fn main() -> ExitCode {
     let mut path = PathBuf::from(".gitdb");
     let mut verbose = false;
     let mut execute: Option<String> = None;
+    let mut script: Option<PathBuf> = None;
+    let mut stdin = false;
+    let mut quiet = false;
+    let mut single_transaction = false;
+    let mut format = OutputFormat::default();
+    let mut gc_on_exit = false;
+    let mut read_only = false;
+    let mut at_commit: Option<String> = None;
     
     let mut i = 1;
     while i < args.len() {
@@ -33,6 +44,51 @@ fn main() -> ExitCode {
                     execute = Some(args[i].clone());
                 }
             }
+            "-f" | "--file" => {
+                i += 1;
+                if i < args.len() {
+                    script = Some(PathBuf::from(&args[i]));
+                }
+            }
+            "--format" => {
+                i += 1;
+                match args.get(i).map(|f| f.parse()) {
+                    Some(Ok(f)) => format = f,
+                    Some(Err(e)) => {
+                        eprintln!("{}", e);
+                        return ExitCode::FAILURE;
+                    }
+                    None => {
+                        eprintln!("--format requires a value (text, json, ndjson, vertical)");
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            "-" | "--stdin" => {
+                stdin = true;
+            }
+            "-q" | "--quiet" => {
+                quiet = true;
+            }
+            "-1" | "--single-transaction" => {
+                single_transaction = true;
+            }
+            "--gc-on-exit" => {
+                gc_on_exit = true;
+            }
+            "--read-only" => {
+                read_only = true;
+            }
+            "--at" => {
+                i += 1;
+                match args.get(i) {
+                    Some(rev) => at_commit = Some(rev.clone()),
+                    None => {
+                        eprintln!("--at requires a commit (id, tag or revision like main~3)");
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
             "-h" | "--help" => {
                 print_help();
                 return ExitCode::SUCCESS;
@@ -55,34 +111,56 @@ fn main() -> ExitCode {
     }
 
     // Open database.
-    let config = DatabaseConfig::new(&path)
+    let mut config = DatabaseConfig::new(&path)
         .create_if_missing(true)
+        .read_only(read_only)
         .verbose(verbose);
+    if let Some(rev) = at_commit {
+        config = config.at_commit(rev);
+    }
 
     let db = match Database::open_with_config(config) {
         Ok(db) => db,
         Err(e) => {
-            eprintln!("Error opening database: {}", e);
+            match format {
+                OutputFormat::Json | OutputFormat::Ndjson => print_error(&e, format),
+                OutputFormat::Text | OutputFormat::Vertical => eprintln!("Error opening database: {}", e),
+            }
             return ExitCode::FAILURE;
         }
     };
 
-    // Execute single command or run REPL.
-    if let Some(sql) = execute {
-        match execute_command(db, &sql) {
-            Ok(_) => ExitCode::SUCCESS,
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                ExitCode::FAILURE
-            }
-        }
+    // Execute a script, a single command, or run the REPL. Piped input is
+    // a script too, so `gitdb mydb < seed.sql` works.
+    let output = Output { format, quiet };
+    let outcome = if let Some(file) = script {
+        std::fs::read_to_string(file)
+            .map_err(DatabaseError::from)
+            .and_then(|sql| run_script(db, &sql, single_transaction, output))
+    } else if let Some(sql) = execute {
+        execute_command(db, &sql, output)
+    } else if stdin || !std::io::stdin().is_terminal() {
+        std::io::read_to_string(std::io::stdin())
+            .map_err(DatabaseError::from)
+            .and_then(|sql| run_script(db, &sql, single_transaction, output))
     } else {
-        match run_repl(db) {
-            Ok(_) => ExitCode::SUCCESS,
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                ExitCode::FAILURE
-            }
+        run_repl(db)
+    };
+
+    if gc_on_exit {
+        // The database handle is gone by now, so gc through the repository
+        match GitRepository::open(&path).and_then(|repo| repo.gc()) {
+            Ok(report) if verbose => eprintln!("gc: {}", report),
+            Ok(_) => {}
+            Err(e) => eprintln!("Warning: gc failed: {}", e),
+        }
+    }
+
+    match outcome {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            print_error(&e, format);
+            ExitCode::FAILURE
         }
     }
 }
@@ -95,6 +173,17 @@ fn print_help() {
     println!("Options:");
     println!("  -d, --database PATH    Path to database directory (default: .gitdb)");
     println!("  -e, --execute SQL      Execute SQL and exit");
+    println!("  -f, --file PATH        Execute a SQL script file and exit");
+    println!("  -, --stdin             Execute a SQL script read from stdin and exit");
+    println!("                         (the default when stdin isn't a terminal)");
+    println!("  -1, --single-transaction");
+    println!("                         Run the script in one transaction");
+    println!("  -q, --quiet            Only print errors");
+    println!("  --format FORMAT        Output format for scripts and -e: text, json, ndjson, vertical");
+    println!("                         (json and ndjson report errors as JSON on stderr)");
+    println!("  --read-only            Open an existing database without allowing writes");
+    println!("  --at REV               Open read-only as of a commit, tag or revision (e.g. main~3)");
+    println!("  --gc-on-exit           Pack the object store before exiting");
     println!("  -v, --verbose          Enable verbose output");
     println!("  -h, --help             Show this help message");
     println!("  --version              Show version");
@@ -103,64 +192,55 @@ fn print_help() {
     println!("  gitdb                           Start REPL with default database");
     println!("  gitdb mydb                      Start REPL with 'mydb' database");
     println!("  gitdb -e 'SELECT * FROM users'  Execute query and exit");
+    println!("  gitdb -f seed.sql               Run a script and exit");
+    println!("  gitdb mydb -q < seed.sql        Run a script from stdin, printing only errors");
+    println!("  gitdb -e 'SELECT * FROM users' --format json");
 }
 
-fn execute_command(mut db: Database, sql: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// How results are printed for `-e`, `-f` and piped scripts.
+#[derive(Clone, Copy)]
+struct Output {
+    format: OutputFormat,
+    /// Print nothing but errors.
+    quiet: bool,
+}
+
+fn execute_command(mut db: Database, sql: &str, output: Output) -> DatabaseResult<()> {
     let result = db.execute(sql)?;
-    print_result(&result);
+    print_result(&result, output)?;
     Ok(())
 }
 
-fn run_repl(db: Database) -> Result<(), Box<dyn std::error::Error>> {
-    let mut repl = Repl::new(db);
+/// Run statements in order, stopping at the first that fails.
+fn run_script(mut db: Database, sql: &str, single_transaction: bool, output: Output) -> DatabaseResult<()> {
+    let results = if single_transaction {
+        db.execute_script_in_transaction(sql)?
+    } else {
+        db.execute_script(sql)?
+    };
+    for result in &results {
+        print_result(result, output)?;
+    }
+    Ok(())
+}
+
+fn run_repl(db: Database) -> DatabaseResult<()> {
+    let mut repl = Repl::new(db)?;
     repl.run()?;
     Ok(())
 }
 
-fn print_result(result: &gitdb::executor::QueryResult) {
-    use gitdb::executor::QueryResult;
-    
-    match result {
-        QueryResult::Success { message } => {
-            println!("{}", message);
-        }
-        QueryResult::Modified { rows_affected } => {
-            println!("{} row(s) modified", rows_affected);
-        }
-        QueryResult::Select(rs) => {
-            if rs.is_empty() {
-                println!("(0 rows)");
-                return;
-            }
-            
-            // Simple output for CLI.
-            let columns: Vec<&String> = rs.rows[0].keys().collect();
-            println!("{}", columns.iter().map(|s| s.as_str()).collect::<Vec<_>>().join("\t"));
-            
-            for row in &rs.rows {
-                let values: Vec<String> = columns.iter()
-                    .map(|col| {
-                        row.get(*col)
-                            .map(|v| format_value(v))
-                            .unwrap_or_default()
-                    })
-                    .collect();
-                println!("{}", values.join("\t"));
-            }
-            println!("({} rows)", rs.len());
-        }
-        QueryResult::Transaction { message } => {
-            println!("{}", message);
-        }
+fn print_result(result: &QueryResult, output: Output) -> std::io::Result<()> {
+    if output.quiet {
+        return Ok(());
     }
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    write_result(&mut out, result, output.format)
 }
 
-fn format_value(value: &serde_json::Value) -> String {
-    match value {
-        serde_json::Value::Null => "NULL".to_string(),
-        serde_json::Value::Bool(b) => b.to_string(),
-        serde_json::Value::Number(n) => n.to_string(),
-        serde_json::Value::String(s) => s.clone(),
-        _ => value.to_string(),
-    }
+/// Report a failure on stderr, as JSON if results are JSON.
+fn print_error(error: &DatabaseError, format: OutputFormat) {
+    let stderr = std::io::stderr();
+    let _ = write_error(&mut stderr.lock(), error, format);
 }
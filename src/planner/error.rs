@@ -33,6 +33,9 @@ pub enum PlanError {
     #[error("invalid join condition: {0}")]
     InvalidJoin(String),
 
+    #[error("invalid grouping: {0}")]
+    InvalidGrouping(String),
+
     #[error("type mismatch: {0}")]
     TypeMismatch(String),
 
@@ -45,3 +48,25 @@ pub enum PlanError {
     #[error("internal error: {0}")]
     Internal(String),
 }
+
+impl PlanError {
+    /// A short name for the kind of error, stable across releases.
+    ///
+    /// Wrapped errors report their own kind.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PlanError::Parse(e) => e.kind(),
+            PlanError::Storage(e) => e.kind(),
+            PlanError::Schema(e) => e.kind(),
+            PlanError::TableNotFound(_) => "table_not_found",
+            PlanError::ColumnNotFound(_) => "column_not_found",
+            PlanError::AmbiguousColumn(_) => "ambiguous_column",
+            PlanError::InvalidJoin(_) => "invalid_join",
+            PlanError::InvalidGrouping(_) => "invalid_grouping",
+            PlanError::TypeMismatch(_) => "type_mismatch",
+            PlanError::Unsupported(_) => "unsupported",
+            PlanError::OptimizationFailed(_) => "optimization_failed",
+            PlanError::Internal(_) => "internal",
+        }
+    }
+}
@@ -117,6 +117,16 @@ pub enum LogicalPlan {
         columns: Option<Vec<String>>,
     },
 
+    /// Fetch the rows of a table with the given keys through an index,
+    /// instead of scanning it.
+    IndexLookup {
+        table: String,
+        /// The indexed column the keys are values of.
+        index: String,
+        /// Literal key values.
+        keys: Vec<Expr>,
+    },
+
     /// Filter rows based on a predicate.
     Filter {
         input: Box<LogicalPlan>,
@@ -168,6 +178,12 @@ pub enum LogicalPlan {
         right: Box<LogicalPlan>,
     },
 
+    /// The rows of a nested query, named by an alias.
+    Subquery {
+        input: Box<LogicalPlan>,
+        alias: String,
+    },
+
     /// Return no rows.
     Empty {
         columns: Vec<String>,
@@ -205,6 +221,7 @@ impl LogicalPlan {
             LogicalPlan::Scan { columns, .. } => {
                 columns.clone().unwrap_or_default()
             }
+            LogicalPlan::IndexLookup { .. } => Vec::new(),
             LogicalPlan::Filter { input, .. } => input.output_columns(),
             LogicalPlan::Project { columns, .. } => {
                 columns.iter().filter_map(|c| match c {
@@ -227,6 +244,7 @@ impl LogicalPlan {
             }
             LogicalPlan::Distinct { input } => input.output_columns(),
             LogicalPlan::Union { left, .. } => left.output_columns(),
+            LogicalPlan::Subquery { input, .. } => input.output_columns(),
             LogicalPlan::Empty { columns } => columns.clone(),
         }
     }
@@ -240,7 +258,7 @@ impl LogicalPlan {
 
     fn collect_tables(&self, tables: &mut HashSet<String>) {
         match self {
-            LogicalPlan::Scan { table, .. } => {
+            LogicalPlan::Scan { table, .. } | LogicalPlan::IndexLookup { table, .. } => {
                 tables.insert(table.clone());
             }
             LogicalPlan::Filter { input, .. } => input.collect_tables(tables),
@@ -257,6 +275,7 @@ impl LogicalPlan {
                 left.collect_tables(tables);
                 right.collect_tables(tables);
             }
+            LogicalPlan::Subquery { input, .. } => input.collect_tables(tables),
             LogicalPlan::Empty { .. } => {}
         }
     }
@@ -267,6 +286,8 @@ impl LogicalPlan {
         match self {
             // Assume 1000 rows per table as baseline
             LogicalPlan::Scan { .. } => 1000,
+            // At most one row per key
+            LogicalPlan::IndexLookup { keys, .. } => keys.len(),
             // Filter typically reduces by 1/3
             LogicalPlan::Filter { input, .. } => input.estimated_cardinality() / 3,
             // Projection doesn't change row count
@@ -291,6 +312,7 @@ impl LogicalPlan {
             LogicalPlan::Union { left, right } => {
                 left.estimated_cardinality() + right.estimated_cardinality()
             }
+            LogicalPlan::Subquery { input, .. } => input.estimated_cardinality(),
             LogicalPlan::Empty { .. } => 0,
         }
     }
@@ -316,6 +338,10 @@ impl LogicalPlan {
                 }
                 writeln!(f)
             }
+            LogicalPlan::IndexLookup { table, index, keys } => {
+                let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+                writeln!(f, "{}IndexLookup: {}.{} [{}]", pad, table, index, keys.join(", "))
+            }
             LogicalPlan::Filter { input, predicate } => {
                 writeln!(f, "{}Filter: {:?}", pad, predicate)?;
                 input.format_indent(f, indent + 1)
@@ -342,7 +368,11 @@ impl LogicalPlan {
                 input.format_indent(f, indent + 1)
             }
             LogicalPlan::Limit { input, limit, offset } => {
-                write!(f, "{}Limit: {}", pad, limit)?;
+                if *limit == usize::MAX {
+                    write!(f, "{}Limit: ALL", pad)?;
+                } else {
+                    write!(f, "{}Limit: {}", pad, limit)?;
+                }
                 if let Some(o) = offset {
                     write!(f, " OFFSET {}", o)?;
                 }
@@ -366,6 +396,10 @@ impl LogicalPlan {
                 left.format_indent(f, indent + 1)?;
                 right.format_indent(f, indent + 1)
             }
+            LogicalPlan::Subquery { input, alias } => {
+                writeln!(f, "{}Subquery: {}", pad, alias)?;
+                input.format_indent(f, indent + 1)
+            }
             LogicalPlan::Empty { columns } => {
                 writeln!(f, "{}Empty: [{}]", pad, columns.join(", "))
             }
@@ -7,10 +7,13 @@ mod error;
 mod logical;
 mod optimizer;
 mod physical;
+#[allow(clippy::module_inception)]
 mod planner;
 
 pub use error::{PlanError, PlanResult};
-pub use logical::{LogicalPlan, JoinType};
+pub use logical::{LogicalPlan, JoinType, SortDirection, SortSpec};
 pub use optimizer::{Optimizer, OptimizationRule};
-pub use physical::{PhysicalPlan, PhysicalOperator};
+pub use physical::{
+    AggregatePhysical, JoinPhysicalType, PhysicalAggregate, PhysicalOperator, PhysicalPlan, PhysicalPlanNode,
+};
 pub use planner::QueryPlanner;
@@ -2,6 +2,7 @@
 //!
 //! The optimizer transforms logical plans to improve execution efficiency.
 
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use super::error::PlanResult;
@@ -177,6 +178,168 @@ impl OptimizationRule for ConstantFolding {
     }
 }
 
+/// Looks up the indexed column of a table, if it has one.
+type IndexOf = Box<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// OR-to-IN rewriting - turns `col = a OR col = b` on an indexed column into
+/// `col IN (a, b)`, which the executor answers with one lookup per value
+/// instead of a full scan.
+///
+/// Only fires on a filter directly over a scan, when `index_of` names an
+/// indexed column for the table and every disjunct compares that column
+/// with a literal. Indexes are only looked up once such an OR turns up.
+pub struct OrToInList {
+    index_of: IndexOf,
+}
+
+impl OrToInList {
+    /// Create the rule, looking up a table's indexed column with `index_of`.
+    pub fn new(index_of: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Self {
+        Self { index_of: Box::new(index_of) }
+    }
+
+    /// Literals of an OR of equalities (or IN lists) on `column`.
+    fn collect(expr: &Expr, column: &str, out: &mut Vec<Expr>) -> bool {
+        match expr {
+            Expr::BinaryOp { left, op: crate::sql::BinaryOperator::Or, right } => {
+                Self::collect(left, column, out) && Self::collect(right, column, out)
+            }
+            Expr::BinaryOp { left, op: crate::sql::BinaryOperator::Eq, right } => {
+                match (left.as_ref(), right.as_ref()) {
+                    (Expr::Column(c), lit @ Expr::Literal(_))
+                    | (lit @ Expr::Literal(_), Expr::Column(c)) if c == column => {
+                        out.push(lit.clone());
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Expr::InList { expr, list, negated: false }
+                if matches!(expr.as_ref(), Expr::Column(c) if c == column)
+                    && list.iter().all(|item| matches!(item, Expr::Literal(_))) =>
+            {
+                out.extend(list.iter().cloned());
+                true
+            }
+            Expr::Nested(inner) => Self::collect(inner, column, out),
+            _ => false,
+        }
+    }
+}
+
+impl OptimizationRule for OrToInList {
+    fn name(&self) -> &str {
+        "OrToInList"
+    }
+
+    fn apply(&self, plan: &LogicalPlan) -> Option<LogicalPlan> {
+        let LogicalPlan::Filter { input, predicate } = plan else {
+            return None;
+        };
+        let LogicalPlan::Scan { table, .. } = input.as_ref() else {
+            return None;
+        };
+
+        // A lone equality is already a point lookup.
+        let is_or = |e: &Expr| matches!(e, Expr::BinaryOp { op: crate::sql::BinaryOperator::Or, .. });
+        if !is_or(predicate) && !matches!(predicate, Expr::Nested(inner) if is_or(inner)) {
+            return None;
+        }
+
+        let column = (self.index_of)(table)?;
+        let mut list = Vec::new();
+        if !Self::collect(predicate, &column, &mut list) {
+            return None;
+        }
+        list.dedup();
+
+        Some(LogicalPlan::Filter {
+            input: input.clone(),
+            predicate: Expr::InList {
+                expr: Box::new(Expr::Column(column)),
+                list,
+                negated: false,
+            },
+        })
+    }
+}
+
+/// IN-list lookups - reads the rows an `indexed IN (literals...)` filter
+/// names straight from the index instead of scanning the table.
+///
+/// The filter stays on top of the lookup, so the rest of a conjunction (and
+/// the IN itself, for values that only look like a key) still applies.
+pub struct InListToLookup {
+    index_of: IndexOf,
+}
+
+impl InListToLookup {
+    /// Create the rule, looking up a table's indexed column with `index_of`.
+    pub fn new(index_of: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Self {
+        Self { index_of: Box::new(index_of) }
+    }
+
+    /// Literal values of an IN list on `column`, possibly one side of an AND.
+    fn keys<'a>(expr: &'a Expr, column: &str) -> Option<&'a [Expr]> {
+        match expr {
+            Expr::InList { expr, list, negated: false }
+                if matches!(expr.as_ref(), Expr::Column(c) if c == column)
+                    && list.iter().all(|item| matches!(item, Expr::Literal(_))) =>
+            {
+                Some(list)
+            }
+            Expr::BinaryOp { left, op: crate::sql::BinaryOperator::And, right } => {
+                Self::keys(left, column).or_else(|| Self::keys(right, column))
+            }
+            Expr::Nested(inner) => Self::keys(inner, column),
+            _ => None,
+        }
+    }
+
+    /// Whether a predicate has an IN list on any column, so the index is
+    /// only looked up when it could be used.
+    fn has_in_list(expr: &Expr) -> bool {
+        match expr {
+            Expr::InList { negated: false, .. } => true,
+            Expr::BinaryOp { left, op: crate::sql::BinaryOperator::And, right } => {
+                Self::has_in_list(left) || Self::has_in_list(right)
+            }
+            Expr::Nested(inner) => Self::has_in_list(inner),
+            _ => false,
+        }
+    }
+}
+
+impl OptimizationRule for InListToLookup {
+    fn name(&self) -> &str {
+        "InListToLookup"
+    }
+
+    fn apply(&self, plan: &LogicalPlan) -> Option<LogicalPlan> {
+        let LogicalPlan::Filter { input, predicate } = plan else {
+            return None;
+        };
+        let LogicalPlan::Scan { table, .. } = input.as_ref() else {
+            return None;
+        };
+        if !Self::has_in_list(predicate) {
+            return None;
+        }
+
+        let index = (self.index_of)(table)?;
+        let keys = Self::keys(predicate, &index)?.to_vec();
+
+        Some(LogicalPlan::Filter {
+            input: Box::new(LogicalPlan::IndexLookup {
+                table: table.clone(),
+                index,
+                keys,
+            }),
+            predicate: predicate.clone(),
+        })
+    }
+}
+
 /// The query optimizer.
 pub struct Optimizer {
     rules: Vec<Box<dyn OptimizationRule>>,
@@ -203,6 +366,17 @@ impl Optimizer {
         }
     }
 
+    /// Create an optimizer with default rules plus the index-driven ones,
+    /// which look up a table's indexed column with `index_of`.
+    pub fn with_indexes(index_of: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Self {
+        let index_of = Arc::new(index_of);
+        let mut optimizer = Self::new();
+        let shared = index_of.clone();
+        optimizer.add_rule(Box::new(OrToInList::new(move |table| shared(table))));
+        optimizer.add_rule(Box::new(InListToLookup::new(move |table| index_of(table))));
+        optimizer
+    }
+
     /// Add a custom optimization rule.
     pub fn add_rule(&mut self, rule: Box<dyn OptimizationRule>) {
         self.rules.push(rule);
@@ -292,6 +466,12 @@ impl Optimizer {
                     right: Box::new(self.apply_rules_recursive(&right, changed)),
                 }
             }
+            LogicalPlan::Subquery { input, alias } => {
+                LogicalPlan::Subquery {
+                    input: Box::new(self.apply_rules_recursive(&input, changed)),
+                    alias,
+                }
+            }
             // Leaf nodes stay the same.
             other => other,
         }
@@ -318,6 +498,31 @@ impl Optimizer {
                 Ok(node)
             }
 
+            LogicalPlan::IndexLookup { table, index, keys } => {
+                let node = PhysicalPlanNode::new(PhysicalOperator::IndexLookup {
+                    table: table.clone(),
+                    index: index.clone(),
+                    keys: keys.clone(),
+                })
+                .with_cost(keys.len() as f64 * cost::SEQ_SCAN_PER_ROW)
+                .with_rows(keys.len());
+
+                Ok(node)
+            }
+
+            // A filter straight over a scan is evaluated by the scan itself.
+            LogicalPlan::Filter { input, predicate } if matches!(input.as_ref(), LogicalPlan::Scan { .. }) => {
+                let mut node = self.logical_to_physical(input)?;
+                let input_rows = node.estimated_rows;
+                if let PhysicalOperator::SeqScan { predicate: slot, .. } = &mut node.operator {
+                    *slot = Some(predicate.clone());
+                }
+                node.estimated_cost += input_rows as f64 * cost::FILTER_PER_ROW;
+                node.estimated_rows = input_rows / 3;
+
+                Ok(node)
+            }
+
             LogicalPlan::Filter { input, predicate } => {
                 let child = self.logical_to_physical(input)?;
                 let input_rows = child.estimated_rows;
@@ -337,18 +542,26 @@ impl Optimizer {
                 let child = self.logical_to_physical(input)?;
                 let rows = child.estimated_rows;
                 
-                // Extract column names from ProjectColumn.
-                let col_names: Vec<String> = columns.iter().filter_map(|c| {
+                // Output column names in order, wildcards written as `*`
+                // and `table.*`; computed columns also keep their expression.
+                let mut col_names = Vec::with_capacity(columns.len());
+                let mut expressions = Vec::new();
+                for c in columns {
                     match c {
-                        ProjectColumn::Column(col) => Some(col.column.clone()),
-                        ProjectColumn::Expr { alias, .. } => alias.clone(),
-                        _ => None,
+                        ProjectColumn::Star => col_names.push("*".to_string()),
+                        ProjectColumn::TableStar(table) => col_names.push(format!("{}.*", table)),
+                        ProjectColumn::Column(col) => col_names.push(col.column.clone()),
+                        ProjectColumn::Expr { expr, alias } => {
+                            let name = alias.clone().unwrap_or_else(|| expr.to_string());
+                            col_names.push(name.clone());
+                            expressions.push((expr.clone(), name));
+                        }
                     }
-                }).collect();
+                }
                 
                 let node = PhysicalPlanNode::new(PhysicalOperator::Project {
                     columns: col_names,
-                    expressions: Vec::new(),
+                    expressions,
                 })
                 .with_cost(rows as f64 * cost::PROJECT_PER_ROW)
                 .with_rows(rows)
@@ -364,16 +577,19 @@ impl Optimizer {
                 let left_rows = left_child.estimated_rows;
                 let right_rows = right_child.estimated_rows;
                 
-                // Choose join algorithm based on size.
-                let (operator, join_cost) = if right_rows > 100 {
+                // Choose join algorithm based on size, hashing only when
+                // the ON condition pairs up columns.
+                let keys = on.as_ref()
+                    .and_then(|on| join_keys(on, &left.referenced_tables(), &right.referenced_tables()));
+                let (operator, join_cost) = if let (true, Some((left_keys, right_keys))) = (right_rows > 100, keys) {
                     // Use hash join for larger right side.
                     let build_cost = right_rows as f64 * cost::HASH_BUILD_PER_ROW;
                     let probe_cost = left_rows as f64 * cost::HASH_JOIN_PER_ROW;
                     
                     (PhysicalOperator::HashJoin {
                         join_type: self.convert_join_type(join_type),
-                        left_keys: vec![], // Would be extracted from ON condition.
-                        right_keys: vec![],
+                        left_keys,
+                        right_keys,
                     }, build_cost + probe_cost)
                 } else {
                     // Use nested loop for small right side.
@@ -496,6 +712,20 @@ impl Optimizer {
                 Ok(node)
             }
 
+            LogicalPlan::Subquery { input, alias } => {
+                let child = self.logical_to_physical(input)?;
+                let rows = child.estimated_rows;
+
+                let node = PhysicalPlanNode::new(PhysicalOperator::SubqueryScan {
+                    alias: alias.clone(),
+                })
+                .with_cost(0.0)
+                .with_rows(rows)
+                .with_child(Arc::new(child));
+
+                Ok(node)
+            }
+
             LogicalPlan::Empty { .. } => {
                 let node = PhysicalPlanNode::new(PhysicalOperator::SeqScan {
                     table: String::new(),
@@ -521,6 +751,34 @@ impl Optimizer {
     }
 }
 
+/// The columns an ON condition of `left.a = right.b`, possibly several
+/// joined by AND, pairs up: the left input's first. Columns are qualified by
+/// the table they come from, so each must name one of `left` or `right`.
+fn join_keys(on: &Expr, left: &HashSet<String>, right: &HashSet<String>) -> Option<(Vec<String>, Vec<String>)> {
+    let side = |column: &str| column.split_once('.').map(|(table, _)| (left.contains(table), right.contains(table)));
+    match on {
+        Expr::Nested(inner) => join_keys(inner, left, right),
+        Expr::BinaryOp { left: a, op: crate::sql::BinaryOperator::And, right: b } => {
+            let (mut left_keys, mut right_keys) = join_keys(a, left, right)?;
+            let (more_left, more_right) = join_keys(b, left, right)?;
+            left_keys.extend(more_left);
+            right_keys.extend(more_right);
+            Some((left_keys, right_keys))
+        }
+        Expr::BinaryOp { left: a, op: crate::sql::BinaryOperator::Eq, right: b } => {
+            let (Expr::Column(a), Expr::Column(b)) = (a.as_ref(), b.as_ref()) else {
+                return None;
+            };
+            match (side(a)?, side(b)?) {
+                ((true, false), (false, true)) => Some((vec![a.clone()], vec![b.clone()])),
+                ((false, true), (true, false)) => Some((vec![b.clone()], vec![a.clone()])),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -582,6 +840,72 @@ mod tests {
         assert!(matches!(result, LogicalPlan::Scan { .. }));
     }
 
+    #[test]
+    fn test_or_to_in_list() {
+        let eq = |value: i64| Expr::BinaryOp {
+            left: Box::new(Expr::Column("id".into())),
+            op: crate::sql::BinaryOperator::Eq,
+            right: Box::new(Expr::Literal(crate::sql::LiteralValue::Integer(value))),
+        };
+        let or = |left: Expr, right: Expr| Expr::BinaryOp {
+            left: Box::new(left),
+            op: crate::sql::BinaryOperator::Or,
+            right: Box::new(right),
+        };
+        let filter = |predicate: Expr| LogicalPlan::Filter {
+            input: Box::new(LogicalPlan::Scan {
+                table: "users".to_string(),
+                alias: None,
+                columns: None,
+            }),
+            predicate,
+        };
+
+        let rule = OrToInList::new(|table| (table == "users").then(|| "id".to_string()));
+        let result = rule.apply(&filter(or(or(eq(1), eq(2)), eq(3)))).unwrap();
+        let LogicalPlan::Filter { predicate, .. } = result else { panic!("expected a filter") };
+        assert_eq!(predicate.to_string(), "id IN (1, 2, 3)");
+
+        // A disjunct on another column keeps the OR.
+        let other = Expr::BinaryOp {
+            left: Box::new(Expr::Column("age".into())),
+            op: crate::sql::BinaryOperator::Eq,
+            right: Box::new(Expr::Literal(crate::sql::LiteralValue::Integer(30))),
+        };
+        assert!(rule.apply(&filter(or(eq(1), other))).is_none());
+
+        // Without an index on the table there's nothing to rewrite.
+        let unindexed = OrToInList::new(|_| None);
+        assert!(unindexed.apply(&filter(or(eq(1), eq(2)))).is_none());
+    }
+
+    #[test]
+    fn test_in_list_to_lookup() {
+        let filter = |column: &str, item: Expr| LogicalPlan::Filter {
+            input: Box::new(LogicalPlan::Scan {
+                table: "users".to_string(),
+                alias: None,
+                columns: None,
+            }),
+            predicate: Expr::InList {
+                expr: Box::new(Expr::Column(column.into())),
+                list: vec![Expr::Literal(crate::sql::LiteralValue::Integer(1)), item],
+                negated: false,
+            },
+        };
+        let two = Expr::Literal(crate::sql::LiteralValue::Integer(2));
+
+        let rule = InListToLookup::new(|table| (table == "users").then(|| "id".to_string()));
+        let result = rule.apply(&filter("id", two.clone())).unwrap();
+        let LogicalPlan::Filter { input, .. } = &result else { panic!("expected a filter") };
+        assert!(matches!(input.as_ref(), LogicalPlan::IndexLookup { keys, .. } if keys.len() == 2));
+        assert!(Optimizer::new().to_physical(&result).unwrap().estimated_rows() < 1000);
+
+        // Not the indexed column, or not all literals: keep scanning.
+        assert!(rule.apply(&filter("age", two)).is_none());
+        assert!(rule.apply(&filter("id", Expr::Column("age".into()))).is_none());
+    }
+
     #[test]
     fn test_to_physical_scan() {
         let scan = LogicalPlan::Scan {
@@ -614,5 +938,41 @@ mod tests {
         
         // Filter should reduce rows.
         assert!(physical.estimated_rows() < 1000);
+
+        // The scan evaluates the filter itself.
+        assert!(physical.root.children.is_empty());
+        assert!(matches!(
+            &physical.root.operator,
+            PhysicalOperator::SeqScan { predicate: Some(Expr::Column(c)), .. } if c == "active"
+        ));
+    }
+
+    #[test]
+    fn test_to_physical_join() {
+        let scan = |table: &str| Box::new(LogicalPlan::Scan { table: table.to_string(), alias: None, columns: None });
+        let eq = |a: &str, b: &str| Expr::BinaryOp {
+            left: Box::new(Expr::Column(a.into())),
+            op: crate::sql::BinaryOperator::Eq,
+            right: Box::new(Expr::Column(b.into())),
+        };
+        let join = |on: Expr| LogicalPlan::Join {
+            left: scan("users"),
+            right: scan("orders"),
+            join_type: crate::planner::JoinType::Left,
+            on: Some(on),
+        };
+        let optimizer = Optimizer::new();
+
+        // Keys are paired up by table, whichever side of = they are on
+        let physical = optimizer.to_physical(&join(eq("orders.user_id", "users.id"))).unwrap();
+        assert!(matches!(
+            &physical.root.operator,
+            PhysicalOperator::HashJoin { join_type: JoinPhysicalType::LeftOuter, left_keys, right_keys }
+                if left_keys == &["users.id"] && right_keys == &["orders.user_id"]
+        ));
+
+        // Anything else is tried row by row
+        let physical = optimizer.to_physical(&join(eq("users.id", "users.name"))).unwrap();
+        assert!(matches!(&physical.root.operator, PhysicalOperator::NestedLoopJoin { condition: Some(_), .. }));
     }
 }
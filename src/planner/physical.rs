@@ -29,6 +29,13 @@ pub enum PhysicalOperator {
         columns: Option<Vec<String>>,
     },
 
+    /// Point lookups of the given keys through an index.
+    IndexLookup {
+        table: String,
+        index: String,
+        keys: Vec<Expr>,
+    },
+
     /// Filter operator.
     Filter {
         predicate: Expr,
@@ -94,6 +101,11 @@ pub enum PhysicalOperator {
 
     /// Append multiple inputs (for UNION).
     Append,
+
+    /// Pass on the rows of a nested query, named by an alias.
+    SubqueryScan {
+        alias: String,
+    },
 }
 
 /// Key range for index scans.
@@ -236,6 +248,9 @@ impl PhysicalPlan {
             PhysicalOperator::IndexScan { table, index, .. } => {
                 write!(f, "{}IndexScan: {} using {}", pad, table, index)?;
             }
+            PhysicalOperator::IndexLookup { table, index, keys } => {
+                write!(f, "{}IndexLookup: {} using {} ({} keys)", pad, table, index, keys.len())?;
+            }
             PhysicalOperator::Filter { predicate } => {
                 write!(f, "{}Filter: {:?}", pad, predicate)?;
             }
@@ -260,7 +275,11 @@ impl PhysicalPlan {
                 write!(f, "{}ExternalSort: {:?} (limit: {})", pad, cols, memory_limit)?;
             }
             PhysicalOperator::Limit { limit, offset } => {
-                write!(f, "{}Limit: {}", pad, limit)?;
+                if *limit == usize::MAX {
+                    write!(f, "{}Limit: ALL", pad)?;
+                } else {
+                    write!(f, "{}Limit: {}", pad, limit)?;
+                }
                 if let Some(o) = offset {
                     write!(f, " OFFSET {}", o)?;
                 }
@@ -278,6 +297,9 @@ impl PhysicalPlan {
             PhysicalOperator::Append => {
                 write!(f, "{}Append", pad)?;
             }
+            PhysicalOperator::SubqueryScan { alias } => {
+                write!(f, "{}SubqueryScan: {}", pad, alias)?;
+            }
         }
         
         writeln!(f, " (rows: {}, cost: {:.2})", node.estimated_rows, node.estimated_cost)?;
@@ -8,13 +8,13 @@ use parking_lot::RwLock;
 
 use super::error::{PlanError, PlanResult};
 use super::logical::{
-    AggregateExpr, AggregateFunction, ColumnRef, LogicalPlan,
+    AggregateExpr, AggregateFunction, ColumnRef, JoinType, LogicalPlan,
     ProjectColumn, SortDirection, SortSpec,
 };
 use super::optimizer::Optimizer;
 use super::physical::PhysicalPlan;
 use crate::catalog::Catalog;
-use crate::sql::{Expr, OrderBy, Select, SelectColumn, Statement};
+use crate::sql::{Expr, FromSource, JoinType as SqlJoinType, OrderBy, RowCount, Select, SelectColumn, Statement};
 use crate::storage::GitRepository;
 
 /// The query planner.
@@ -25,10 +25,14 @@ pub struct QueryPlanner {
 
 impl QueryPlanner {
     /// Create a new query planner.
+    ///
+    /// A table's single-column primary key is the optimizer's index on it,
+    /// so lookups by key can avoid a full scan.
     pub fn new(repo: Arc<RwLock<GitRepository>>) -> Self {
+        let schemas = Catalog::new(repo.clone());
         Self {
             catalog: Catalog::new(repo),
-            optimizer: Optimizer::new(),
+            optimizer: Optimizer::with_indexes(move |table| indexed_column(&schemas, table)),
         }
     }
 
@@ -41,6 +45,7 @@ impl QueryPlanner {
     }
 
     /// Plan a SQL statement.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "plan", level = "debug", skip_all, err))]
     pub fn plan(&self, stmt: &Statement) -> PlanResult<QueryPlan> {
         match stmt {
             Statement::Select(select) => {
@@ -72,56 +77,109 @@ impl QueryPlanner {
             };
         }
 
-        // Check for aggregates.
-        if self.has_aggregates(&select.columns) {
-            let (aggregates, non_agg_columns) = self.extract_aggregates(&select.columns)?;
+        // Group rows, by GROUP BY or into one for an aggregate.
+        if select.is_grouped() {
+            self.check_grouped_columns(select)?;
+            let (mut aggregates, columns) = self.extract_aggregates(&select.columns)?;
+            let having = select.having.clone().map(|mut having| {
+                self.resolve_having(&mut having, &mut aggregates);
+                having
+            });
             
             plan = LogicalPlan::Aggregate {
                 input: Box::new(plan),
-                group_by: Vec::new(), // Would need GROUP BY in AST.
+                group_by: select.group_by.clone(),
                 aggregates,
             };
 
-            // Project the result if needed.
-            if !non_agg_columns.is_empty() {
-                plan = LogicalPlan::Project {
-                    input: Box::new(plan),
-                    columns: non_agg_columns,
-                };
-            }
-        } else {
-            // Regular projection.
-            let columns = self.convert_select_columns(&select.columns)?;
-            if !columns.is_empty() && !self.is_star_only(&columns) {
-                plan = LogicalPlan::Project {
+            // HAVING filters the groups, before they are sorted.
+            if let Some(predicate) = having {
+                let output = plan.output_columns();
+                if let Some(column) = predicate.referenced_columns().into_iter().find(|c| !output.iter().any(|o| o == c)) {
+                    return Err(PlanError::InvalidGrouping(format!(
+                        "HAVING column '{}' must appear in GROUP BY or be used in an aggregate",
+                        column
+                    )));
+                }
+                plan = LogicalPlan::Filter {
                     input: Box::new(plan),
-                    columns,
+                    predicate,
                 };
             }
+
+            // Sort the groups, so ORDER BY sees aggregates by their aliases,
+            // then put the columns back in SELECT order.
+            let order_by = self.resolve_grouped_order(select, &plan.output_columns())?;
+            plan = self.plan_order_and_limit(plan, &order_by, select)?;
+            return Ok(LogicalPlan::Project {
+                input: Box::new(plan),
+                columns,
+            });
         }
 
+        // Sort and limit before projecting, so ORDER BY can use columns
+        // that aren't selected.
+        plan = self.plan_order_and_limit(plan, &select.order_by, select)?;
+        let columns = self.convert_select_columns(&select.columns)?;
+        if !columns.is_empty() && !self.is_star_only(&columns) {
+            plan = LogicalPlan::Project {
+                input: Box::new(plan),
+                columns,
+            };
+        }
+
+        Ok(plan)
+    }
+
+    fn plan_order_and_limit(&self, mut plan: LogicalPlan, order_by: &[OrderBy], select: &Select) -> PlanResult<LogicalPlan> {
         // Add ORDER BY.
-        if !select.order_by.is_empty() {
-            let order = self.convert_order_by(&select.order_by)?;
+        if !order_by.is_empty() {
+            let order = self.convert_order_by(order_by)?;
             plan = LogicalPlan::Sort {
                 input: Box::new(plan),
                 order,
             };
         }
 
-        // Add LIMIT/OFFSET.
-        if let Some(limit) = select.limit {
+        // Add LIMIT/OFFSET. OFFSET on its own keeps every remaining row.
+        if select.limit.is_some() || select.offset.is_some() {
             plan = LogicalPlan::Limit {
                 input: Box::new(plan),
-                limit,
-                offset: select.offset,
+                limit: select.limit.map(row_count).transpose()?.unwrap_or(usize::MAX),
+                offset: select.offset.map(row_count).transpose()?,
             };
         }
 
         Ok(plan)
     }
 
-    fn plan_from(&self, table: &str) -> PlanResult<LogicalPlan> {
+    fn plan_from(&self, from: &FromSource) -> PlanResult<LogicalPlan> {
+        let table = match from {
+            FromSource::Table(table) => table,
+            // A derived table is planned as a query of its own, nested under
+            // the outer one.
+            FromSource::Derived(select, alias) => {
+                return Ok(LogicalPlan::Subquery {
+                    input: Box::new(self.plan_select(select)?),
+                    alias: alias.clone(),
+                });
+            }
+            FromSource::Join { left, right, join_type, on } => {
+                return Ok(LogicalPlan::Join {
+                    left: Box::new(self.plan_from(left)?),
+                    right: Box::new(self.plan_from(right)?),
+                    join_type: match join_type {
+                        SqlJoinType::Inner => JoinType::Inner,
+                        SqlJoinType::Left => JoinType::Left,
+                        SqlJoinType::Right => JoinType::Right,
+                        SqlJoinType::Full => JoinType::Full,
+                        SqlJoinType::Cross => JoinType::Cross,
+                    },
+                    on: on.clone(),
+                });
+            }
+        };
+
         // Verify table exists.
         if !self.catalog.table_exists(table) {
             return Err(PlanError::TableNotFound(table.to_string()));
@@ -142,12 +200,15 @@ impl QueryPlanner {
                 SelectColumn::Wildcard => {
                     result.push(ProjectColumn::Star);
                 }
+                SelectColumn::QualifiedWildcard(table) => {
+                    result.push(ProjectColumn::TableStar(table.clone()));
+                }
                 SelectColumn::Column(name) => {
                     result.push(ProjectColumn::Column(ColumnRef::new(name.clone())));
                 }
                 SelectColumn::Expr { expr, alias } => {
                     let converted = self.convert_expr(expr);
-                    if let Expr::Column(name) = &converted {
+                    if let (Expr::Column(name), None) = (&converted, alias) {
                         result.push(ProjectColumn::Column(ColumnRef::new(name.clone())));
                     } else {
                         result.push(ProjectColumn::Expr {
@@ -186,95 +247,150 @@ impl QueryPlanner {
         expr.clone()
     }
 
-    fn has_aggregates(&self, columns: &[SelectColumn]) -> bool {
-        columns.iter().any(|col| {
-            if let SelectColumn::Expr { expr, .. } = col {
-                self.expr_has_aggregate(expr)
-            } else {
-                false
+    /// Check that a grouped query selects nothing but grouped columns and
+    /// aggregates, as there is one row per group to take values from.
+    fn check_grouped_columns(&self, select: &Select) -> PlanResult<()> {
+        let grouped = |column: &str| select.group_by.iter().any(|g| g == column);
+        for col in &select.columns {
+            let ungrouped = match col {
+                SelectColumn::Wildcard => Some("*"),
+                SelectColumn::QualifiedWildcard(table) => Some(table.as_str()),
+                SelectColumn::Column(name) => Some(name.as_str()).filter(|name| !grouped(name)),
+                SelectColumn::Expr { expr, .. } if expr.has_aggregate() => None,
+                SelectColumn::Expr { expr, .. } => expr.referenced_columns().into_iter().find(|c| !grouped(c)),
+            };
+            if let Some(column) = ungrouped {
+                return Err(PlanError::InvalidGrouping(format!(
+                    "column '{}' must appear in GROUP BY or be used in an aggregate",
+                    column
+                )));
             }
-        })
+        }
+        Ok(())
     }
 
-    fn expr_has_aggregate(&self, expr: &crate::sql::Expr) -> bool {
-        match expr {
-            crate::sql::Expr::Function { name, .. } => {
-                let upper = name.to_uppercase();
-                matches!(upper.as_str(), "COUNT" | "SUM" | "AVG" | "MIN" | "MAX")
+    /// Resolve a grouped query's ORDER BY against the grouped rows, whose
+    /// columns are `output`: the GROUP BY columns and the aggregates.
+    ///
+    /// A SELECT alias for a grouped column sorts by that column.
+    fn resolve_grouped_order(&self, select: &Select, output: &[String]) -> PlanResult<Vec<OrderBy>> {
+        select.order_by.iter().map(|item| {
+            if output.contains(&item.column) {
+                return Ok(item.clone());
             }
-            crate::sql::Expr::BinaryOp { left, right, .. } => {
-                self.expr_has_aggregate(left) || self.expr_has_aggregate(right)
-            }
-            crate::sql::Expr::UnaryOp { expr, .. } => self.expr_has_aggregate(expr),
-            crate::sql::Expr::IsNull { expr, .. } => self.expr_has_aggregate(expr),
-            crate::sql::Expr::Between { expr, low, high, .. } => {
-                self.expr_has_aggregate(expr) || 
-                self.expr_has_aggregate(low) || 
-                self.expr_has_aggregate(high)
-            }
-            crate::sql::Expr::InList { expr, list, .. } => {
-                self.expr_has_aggregate(expr) || 
-                list.iter().any(|e| self.expr_has_aggregate(e))
+            let aliased = select.columns.iter().find_map(|col| match col {
+                SelectColumn::Expr { expr: Expr::Column(column), alias: Some(alias) } if *alias == item.column => {
+                    Some(column)
+                }
+                _ => None,
+            });
+            match aliased {
+                Some(column) if output.contains(column) => Ok(OrderBy { column: column.clone(), ..item.clone() }),
+                _ => Err(PlanError::InvalidGrouping(format!(
+                    "ORDER BY column '{}' must appear in GROUP BY or name an aggregate",
+                    item.column
+                ))),
             }
-            _ => false,
-        }
+        }).collect()
     }
 
+    /// Split out the aggregates, returning them with the projection that
+    /// puts every SELECT column, aggregates included, back in order.
     fn extract_aggregates(&self, columns: &[SelectColumn]) -> PlanResult<(Vec<AggregateExpr>, Vec<ProjectColumn>)> {
         let mut aggregates = Vec::new();
-        let mut non_agg = Vec::new();
+        let mut projection = Vec::new();
         
-        for (i, col) in columns.iter().enumerate() {
+        for col in columns {
             if let SelectColumn::Expr { expr, alias } = col {
-                if let crate::sql::Expr::Function { name, args } = expr {
-                    let upper = name.to_uppercase();
-                    let function = match upper.as_str() {
-                        "COUNT" => AggregateFunction::Count,
-                        "SUM" => AggregateFunction::Sum,
-                        "AVG" => AggregateFunction::Avg,
-                        "MIN" => AggregateFunction::Min,
-                        "MAX" => AggregateFunction::Max,
-                        _ => {
-                            // Not an aggregate function.
-                            non_agg.push(ProjectColumn::Expr {
-                                expr: self.convert_expr(expr),
-                                alias: alias.clone(),
-                            });
-                            continue;
-                        }
-                    };
-
-                    let column = if args.is_empty() {
-                        None
-                    } else if let crate::sql::Expr::Column(col) = &args[0] {
-                        Some(col.clone())
-                    } else {
-                        None
-                    };
-
-                    let alias_name = alias.clone().unwrap_or_else(|| {
-                        format!("{}_{}", name.to_lowercase(), i)
-                    });
-
-                    aggregates.push(AggregateExpr {
-                        function,
-                        column,
-                        alias: alias_name,
-                    });
-                } else if let crate::sql::Expr::Column(name) = expr {
-                    non_agg.push(ProjectColumn::Column(ColumnRef::new(name.clone())));
+                if let Some(aggregate) = self.aggregate_of(expr, alias.as_ref()) {
+                    projection.push(ProjectColumn::Column(ColumnRef::new(aggregate.alias.clone())));
+                    aggregates.push(aggregate);
+                } else if let (crate::sql::Expr::Column(name), None) = (expr, alias) {
+                    projection.push(ProjectColumn::Column(ColumnRef::new(name.clone())));
                 } else {
-                    non_agg.push(ProjectColumn::Expr {
+                    projection.push(ProjectColumn::Expr {
                         expr: self.convert_expr(expr),
                         alias: alias.clone(),
                     });
                 }
             } else if let SelectColumn::Column(name) = col {
-                non_agg.push(ProjectColumn::Column(ColumnRef::new(name.clone())));
+                projection.push(ProjectColumn::Column(ColumnRef::new(name.clone())));
             }
         }
         
-        Ok((aggregates, non_agg))
+        Ok((aggregates, projection))
+    }
+
+    /// The aggregate an expression computes, if it is an aggregate call.
+    fn aggregate_of(&self, expr: &Expr, alias: Option<&String>) -> Option<AggregateExpr> {
+        let Expr::Function { name, args } = expr else {
+            return None;
+        };
+        let function = match name.to_uppercase().as_str() {
+            "COUNT" => AggregateFunction::Count,
+            "SUM" => AggregateFunction::Sum,
+            "AVG" => AggregateFunction::Avg,
+            "MIN" => AggregateFunction::Min,
+            "MAX" => AggregateFunction::Max,
+            _ => return None,
+        };
+
+        let column = match args.first() {
+            Some(Expr::Column(col)) => Some(col.clone()),
+            _ => None,
+        };
+
+        // Unnamed aggregates are named after how they were written.
+        let alias = alias.cloned().unwrap_or_else(|| {
+            if args.is_empty() {
+                format!("{}(*)", name)
+            } else {
+                expr.to_string()
+            }
+        });
+
+        Some(AggregateExpr { function, column, alias })
+    }
+
+    /// Rewrite a HAVING condition to run on the grouped rows: each aggregate
+    /// in it reads the column the grouping computes it into, adding one to
+    /// `aggregates` if SELECT doesn't compute it already.
+    fn resolve_having(&self, expr: &mut Expr, aggregates: &mut Vec<AggregateExpr>) {
+        if let Some(aggregate) = self.aggregate_of(expr, None) {
+            let existing = aggregates.iter()
+                .find(|a| a.function == aggregate.function && a.column == aggregate.column);
+            let alias = match existing {
+                Some(existing) => existing.alias.clone(),
+                None => {
+                    let alias = aggregate.alias.clone();
+                    aggregates.push(aggregate);
+                    alias
+                }
+            };
+            *expr = Expr::Column(alias);
+            return;
+        }
+        match expr {
+            Expr::BinaryOp { left, right, .. } => {
+                self.resolve_having(left, aggregates);
+                self.resolve_having(right, aggregates);
+            }
+            Expr::UnaryOp { expr, .. } | Expr::IsNull { expr, .. } | Expr::Nested(expr) => {
+                self.resolve_having(expr, aggregates);
+            }
+            Expr::Between { expr, low, high, .. } => {
+                for e in [expr, low, high] {
+                    self.resolve_having(e, aggregates);
+                }
+            }
+            Expr::InList { expr, list, .. } => {
+                self.resolve_having(expr, aggregates);
+                for e in list {
+                    self.resolve_having(e, aggregates);
+                }
+            }
+            _ => {}
+        }
     }
 
     /// Explain a query plan.
@@ -306,6 +422,20 @@ impl QueryPlan {
     }
 }
 
+/// Indexed column of a table: its primary key, when that is a single column.
+fn indexed_column(catalog: &Catalog, table: &str) -> Option<String> {
+    match catalog.get_table(table).ok()?.primary_key.as_slice() {
+        [column] => Some(column.clone()),
+        _ => None,
+    }
+}
+
+/// A LIMIT or OFFSET count; parameters must be bound before planning.
+fn row_count(count: RowCount) -> PlanResult<usize> {
+    count.count()
+        .ok_or_else(|| PlanError::Unsupported(format!("unbound parameter {}", count)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -374,6 +504,27 @@ mod tests {
         assert_eq!(plan.estimated_rows(), 10);
     }
 
+    #[test]
+    fn test_plan_grouped_columns() {
+        let (planner, _dir) = setup();
+        let plan = |sql: &str| planner.plan(&Parser::parse(sql).unwrap());
+
+        assert!(plan("SELECT age, COUNT(*) AS c FROM users GROUP BY age ORDER BY c DESC").is_ok());
+        // A SELECT alias for a grouped column sorts by the column
+        assert!(plan("SELECT age AS years, COUNT(*) FROM users GROUP BY age ORDER BY years").is_ok());
+
+        // Columns that aren't grouped have no single value per group
+        for sql in [
+            "SELECT name, COUNT(*) FROM users GROUP BY age",
+            "SELECT * FROM users GROUP BY age",
+            "SELECT name || '!' AS n FROM users GROUP BY age",
+            "SELECT age, COUNT(*) FROM users GROUP BY age ORDER BY name",
+        ] {
+            let err = plan(sql).err().unwrap_or_else(|| panic!("{} planned", sql));
+            assert_eq!(err.kind(), "invalid_grouping", "{}", sql);
+        }
+    }
+
     #[test]
     fn test_explain() {
         let (planner, _dir) = setup();
@@ -385,6 +536,22 @@ mod tests {
         assert!(explanation.contains("Physical Plan"));
     }
 
+    #[test]
+    fn test_or_on_primary_key_becomes_in_list() {
+        let (planner, _dir) = setup();
+
+        let stmt = Parser::parse("SELECT * FROM users WHERE id = 'a' OR id = 'b'").unwrap();
+        let predicate = |stmt: &Statement| match planner.plan(stmt).unwrap().logical {
+            LogicalPlan::Filter { predicate, .. } => predicate.to_string(),
+            other => panic!("expected a filter, got {}", other),
+        };
+        assert_eq!(predicate(&stmt), "id IN ('a', 'b')");
+
+        // Not on the key: the OR stays.
+        let stmt = Parser::parse("SELECT * FROM users WHERE id = 'a' OR age = 3").unwrap();
+        assert_eq!(predicate(&stmt), "id = 'a' OR age = 3");
+    }
+
     #[test]
     fn test_table_not_found() {
         let (planner, _dir) = setup();
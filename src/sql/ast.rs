@@ -3,6 +3,8 @@
 //! These types are simplified representations of SQL statements
 //! that the query executor understands.
 
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -13,6 +15,8 @@ pub enum Statement {
     CreateTable(CreateTable),
     /// DROP TABLE statement.
     DropTable(DropTable),
+    /// ALTER TABLE statement.
+    AlterTable(AlterTable),
     /// SELECT statement.
     Select(Select),
     /// INSERT statement.
@@ -21,16 +25,57 @@ pub enum Statement {
     Update(Update),
     /// DELETE statement.
     Delete(Delete),
-    /// BEGIN TRANSACTION.
-    Begin,
+    /// `BEGIN [TRANSACTION] [ISOLATION LEVEL ...]`; `None` for the
+    /// session's default level.
+    Begin { isolation: Option<TransactionIsolation> },
     /// COMMIT.
     Commit,
     /// ROLLBACK.
     Rollback,
-    /// SHOW TABLES.
-    ShowTables,
+    /// `SHOW TABLES [LIKE 'pattern']`.
+    ShowTables { like: Option<String> },
+    /// SHOW TRANSACTIONS: the transactions open on this database.
+    ShowTransactions,
     /// DESCRIBE table.
     Describe(String),
+    /// `SHOW COLUMNS FROM table [LIKE 'pattern']`: DESCRIBE, for the
+    /// columns whose names match.
+    ShowColumns { table: String, like: Option<String> },
+    /// `SET AUTHOR = 'Name <email>'`, or `None` for `SET AUTHOR = DEFAULT`.
+    SetAuthor(Option<Author>),
+    /// `EXPLAIN [ANALYZE] SELECT ...`; ANALYZE also runs the query.
+    Explain {
+        statement: Box<Statement>,
+        analyze: bool,
+    },
+    /// `VACUUM [FULL]`: gc, after squashing main's history for FULL.
+    Vacuum { full: bool },
+}
+
+impl Statement {
+    /// A short snake_case name for the kind of statement, such as
+    /// `select` or `create_table`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Statement::CreateTable(_) => "create_table",
+            Statement::DropTable(_) => "drop_table",
+            Statement::AlterTable(_) => "alter_table",
+            Statement::Select(_) => "select",
+            Statement::Insert(_) => "insert",
+            Statement::Update(_) => "update",
+            Statement::Delete(_) => "delete",
+            Statement::Begin { .. } => "begin",
+            Statement::Commit => "commit",
+            Statement::Rollback => "rollback",
+            Statement::ShowTables { .. } => "show_tables",
+            Statement::ShowTransactions => "show_transactions",
+            Statement::Describe(_) => "describe",
+            Statement::ShowColumns { .. } => "show_columns",
+            Statement::SetAuthor(_) => "set_author",
+            Statement::Explain { .. } => "explain",
+            Statement::Vacuum { .. } => "vacuum",
+        }
+    }
 }
 
 /// CREATE TABLE statement.
@@ -38,7 +83,10 @@ pub enum Statement {
 pub struct CreateTable {
     pub name: String,
     pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
     pub if_not_exists: bool,
+    /// `WITH (key_strategy = '...')`, if given.
+    pub key_strategy: Option<KeyStrategy>,
 }
 
 /// Column definition in CREATE TABLE.
@@ -55,9 +103,11 @@ pub enum SqlDataType {
     Text,
     Integer,
     Float,
+    Decimal { precision: u8, scale: u8 },
     Boolean,
     Json,
     Timestamp,
+    Date,
     Uuid,
 }
 
@@ -68,6 +118,109 @@ pub enum ColumnConstraint {
     Unique,
     PrimaryKey,
     Default(Expr),
+    Check {
+        name: Option<String>,
+        expr: Expr,
+    },
+    /// `REFERENCES table [(column)]`; no columns means the parent's primary key.
+    References {
+        name: Option<String>,
+        table: String,
+        columns: Vec<String>,
+        on_delete: ReferentialAction,
+        on_update: ReferentialAction,
+    },
+}
+
+/// Table-level constraints in CREATE TABLE.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+    Check {
+        name: Option<String>,
+        expr: Expr,
+    },
+    /// `PRIMARY KEY (col, ...)`, possibly over several columns.
+    PrimaryKey(Vec<String>),
+    /// `UNIQUE (col, ...)`.
+    Unique {
+        name: Option<String>,
+        columns: Vec<String>,
+    },
+    /// `FOREIGN KEY (col, ...) REFERENCES table [(col, ...)]`.
+    ForeignKey {
+        name: Option<String>,
+        columns: Vec<String>,
+        references_table: String,
+        references_columns: Vec<String>,
+        on_delete: ReferentialAction,
+        on_update: ReferentialAction,
+    },
+}
+
+/// What happens to referencing rows when the referenced row changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferentialAction {
+    #[default]
+    NoAction,
+    Restrict,
+    Cascade,
+    SetNull,
+    SetDefault,
+}
+
+impl fmt::Display for ReferentialAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReferentialAction::NoAction => "NO ACTION",
+            ReferentialAction::Restrict => "RESTRICT",
+            ReferentialAction::Cascade => "CASCADE",
+            ReferentialAction::SetNull => "SET NULL",
+            ReferentialAction::SetDefault => "SET DEFAULT",
+        })
+    }
+}
+
+/// How a table's rows are keyed when the key doesn't come from a primary
+/// key: in tables without one, and for schemaless rows inserted without an
+/// `id`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStrategy {
+    /// Lowercase ULIDs, which sort by creation time.
+    #[default]
+    Ulid,
+    /// UUIDv7s, which sort by creation time.
+    UuidV7,
+    /// Random UUIDv4s.
+    UuidV4,
+    /// 1, 2, 3, ... counted per table.
+    Sequential,
+}
+
+impl fmt::Display for KeyStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyStrategy::Ulid => "ulid",
+            KeyStrategy::UuidV7 => "uuid_v7",
+            KeyStrategy::UuidV4 => "uuid_v4",
+            KeyStrategy::Sequential => "sequential",
+        })
+    }
+}
+
+impl std::str::FromStr for KeyStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ulid" => Ok(KeyStrategy::Ulid),
+            "uuid_v7" | "uuidv7" => Ok(KeyStrategy::UuidV7),
+            "uuid_v4" | "uuidv4" => Ok(KeyStrategy::UuidV4),
+            "sequential" => Ok(KeyStrategy::Sequential),
+            other => Err(format!("unknown key strategy: {}", other)),
+        }
+    }
 }
 
 /// DROP TABLE statement.
@@ -77,22 +230,203 @@ pub struct DropTable {
     pub if_exists: bool,
 }
 
-/// SELECT statement.
+/// ALTER TABLE statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlterTable {
+    pub name: String,
+    pub if_exists: bool,
+    pub operation: AlterTableOperation,
+}
+
+/// The change an ALTER TABLE makes.
 #[derive(Debug, Clone, PartialEq)]
+pub enum AlterTableOperation {
+    /// `RENAME TO new_name`.
+    RenameTable(String),
+    /// `RENAME COLUMN old TO new`.
+    RenameColumn { old: String, new: String },
+}
+
+/// Isolation level named by `BEGIN ... ISOLATION LEVEL`.
+///
+/// Every level SQL can name, whether or not GitDB runs transactions at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionIsolation {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+    Snapshot,
+}
+
+impl fmt::Display for TransactionIsolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TransactionIsolation::ReadUncommitted => "READ UNCOMMITTED",
+            TransactionIsolation::ReadCommitted => "READ COMMITTED",
+            TransactionIsolation::RepeatableRead => "REPEATABLE READ",
+            TransactionIsolation::Serializable => "SERIALIZABLE",
+            TransactionIsolation::Snapshot => "SNAPSHOT",
+        })
+    }
+}
+
+/// Author that a session's commits are attributed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Author {
+    pub name: String,
+    pub email: String,
+}
+
+/// SELECT statement.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Select {
     pub columns: Vec<SelectColumn>,
-    pub from: String,
+    pub from: FromSource,
     pub where_clause: Option<Expr>,
+    /// GROUP BY columns.
+    pub group_by: Vec<String>,
+    /// HAVING condition on the groups.
+    pub having: Option<Expr>,
     pub order_by: Vec<OrderBy>,
-    pub limit: Option<usize>,
-    pub offset: Option<usize>,
+    pub limit: Option<RowCount>,
+    pub offset: Option<RowCount>,
+}
+
+impl Select {
+    /// Whether the query folds rows into groups: by GROUP BY, or into a
+    /// single group by HAVING or an aggregate in SELECT.
+    pub fn is_grouped(&self) -> bool {
+        !self.group_by.is_empty()
+            || self.having.is_some()
+            || self.columns.iter().any(|column| match column {
+                SelectColumn::Expr { expr, .. } => expr.has_aggregate(),
+                _ => false,
+            })
+    }
+}
+
+/// What a SELECT reads its rows from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FromSource {
+    /// FROM table_name
+    Table(String),
+    /// FROM (SELECT ...) alias, a derived table.
+    Derived(Box<Select>, String),
+    /// FROM left [LEFT | RIGHT | FULL | CROSS] JOIN right [ON condition].
+    Join {
+        left: Box<FromSource>,
+        right: Box<FromSource>,
+        join_type: JoinType,
+        on: Option<Expr>,
+    },
+}
+
+/// Which rows a JOIN keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JoinType {
+    Inner,
+    Left,
+    Right,
+    Full,
+    Cross,
+}
+
+impl FromSource {
+    /// The table read, if this is neither a derived table nor a join.
+    pub fn table(&self) -> Option<&str> {
+        match self {
+            FromSource::Table(name) => Some(name),
+            FromSource::Derived(..) | FromSource::Join { .. } => None,
+        }
+    }
+
+    /// The name the outer query refers to the rows by: the table name, or a
+    /// derived table's alias. A join goes by its first source.
+    pub fn name(&self) -> &str {
+        match self {
+            FromSource::Table(name) | FromSource::Derived(_, name) => name,
+            FromSource::Join { left, .. } => left.name(),
+        }
+    }
+
+    /// The tables and derived tables read, left to right. Joined rows name
+    /// their columns `name.column` after these.
+    pub fn sources(&self) -> Vec<&FromSource> {
+        match self {
+            FromSource::Join { left, right, .. } => {
+                let mut sources = left.sources();
+                sources.extend(right.sources());
+                sources
+            }
+            source => vec![source],
+        }
+    }
+}
+
+impl fmt::Display for FromSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromSource::Table(name) => write!(f, "{}", name),
+            FromSource::Derived(select, alias) => write!(f, "({}) AS {}", select, alias),
+            FromSource::Join { left, right, join_type, on } => {
+                write!(f, "{} {} {}", left, join_type, right)?;
+                match on {
+                    Some(on) => write!(f, " ON {}", on),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for JoinType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinType::Inner => write!(f, "JOIN"),
+            JoinType::Left => write!(f, "LEFT JOIN"),
+            JoinType::Right => write!(f, "RIGHT JOIN"),
+            JoinType::Full => write!(f, "FULL JOIN"),
+            JoinType::Cross => write!(f, "CROSS JOIN"),
+        }
+    }
+}
+
+/// Row count for LIMIT or OFFSET.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RowCount {
+    /// A constant count, folded at parse time.
+    Count(usize),
+    /// A positional parameter (`?` or `$n`, 1-based), bound at execution.
+    Parameter(usize),
+}
+
+impl RowCount {
+    /// The count, if it is not waiting on a parameter.
+    pub fn count(&self) -> Option<usize> {
+        match self {
+            RowCount::Count(n) => Some(*n),
+            RowCount::Parameter(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RowCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowCount::Count(n) => write!(f, "{}", n),
+            RowCount::Parameter(n) => write!(f, "${}", n),
+        }
+    }
 }
 
 /// A column in SELECT clause.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SelectColumn {
     /// SELECT *
     Wildcard,
+    /// SELECT table.*
+    QualifiedWildcard(String),
     /// SELECT column_name
     Column(String),
     /// SELECT expr AS alias
@@ -100,7 +434,7 @@ pub enum SelectColumn {
 }
 
 /// ORDER BY clause item.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OrderBy {
     pub column: String,
     pub ascending: bool,
@@ -112,6 +446,18 @@ pub struct Insert {
     pub table: String,
     pub columns: Option<Vec<String>>,
     pub values: Vec<Vec<Expr>>,
+    /// What to do with a row whose key is taken (`INSERT OR ...`); `None`
+    /// fails the statement.
+    pub on_conflict: Option<InsertConflict>,
+}
+
+/// How an `INSERT OR ...` treats a row whose key already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertConflict {
+    /// `OR IGNORE`: skip the row and keep the existing one.
+    Ignore,
+    /// `OR REPLACE`: overwrite the existing row with the new one.
+    Replace,
 }
 
 /// UPDATE statement.
@@ -137,7 +483,7 @@ pub struct Delete {
 }
 
 /// SQL expression.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Expr {
     /// Column reference.
     Column(String),
@@ -172,11 +518,20 @@ pub enum Expr {
         high: Box<Expr>,
         negated: bool,
     },
-    /// LIKE pattern.
+    /// LIKE pattern, or ILIKE when case-insensitive.
     Like {
         expr: Box<Expr>,
         pattern: String,
         negated: bool,
+        case_insensitive: bool,
+        /// Character that makes the next pattern character literal.
+        escape_char: Option<char>,
+    },
+    /// JSON field access: `expr->path`, or `expr->>path` for text.
+    JsonAccess {
+        expr: Box<Expr>,
+        path: JsonPath,
+        as_text: bool,
     },
     /// Function call.
     Function {
@@ -185,10 +540,303 @@ pub enum Expr {
     },
     /// Nested expression in parentheses.
     Nested(Box<Expr>),
+    /// Scalar subquery, e.g. `(SELECT max(age) FROM users)`.
+    Subquery(Box<Select>),
+    /// IN (SELECT ...).
+    InSubquery {
+        expr: Box<Expr>,
+        subquery: Box<Select>,
+        negated: bool,
+    },
+    /// EXISTS (SELECT ...).
+    Exists {
+        subquery: Box<Select>,
+        negated: bool,
+    },
+    /// `DEFAULT` in INSERT VALUES: the column's default, as if left out.
+    Default,
+}
+
+/// One step into a JSON value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum JsonPath {
+    /// Object key.
+    Key(String),
+    /// Array index; negative counts from the end.
+    Index(i64),
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonPath::Key(key) => write!(f, "{}", LiteralValue::String(key.clone())),
+            JsonPath::Index(i) => write!(f, "{}", i),
+        }
+    }
+}
+
+impl Expr {
+    /// Collect the names of all columns referenced by this expression.
+    pub fn referenced_columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns
+    }
+
+    fn collect_columns<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Column(name) => {
+                if !out.contains(&name.as_str()) {
+                    out.push(name);
+                }
+            }
+            Expr::Literal(_) => {}
+            Expr::BinaryOp { left, right, .. } => {
+                left.collect_columns(out);
+                right.collect_columns(out);
+            }
+            Expr::UnaryOp { expr, .. }
+            | Expr::IsNull { expr, .. }
+            | Expr::Like { expr, .. }
+            | Expr::JsonAccess { expr, .. }
+            | Expr::Nested(expr)
+            | Expr::InSubquery { expr, .. } => expr.collect_columns(out),
+            // Columns inside a subquery belong to its own table
+            Expr::Subquery(_) | Expr::Exists { .. } | Expr::Default => {}
+            Expr::InList { expr, list, .. } => {
+                expr.collect_columns(out);
+                for item in list {
+                    item.collect_columns(out);
+                }
+            }
+            Expr::Between { expr, low, high, .. } => {
+                expr.collect_columns(out);
+                low.collect_columns(out);
+                high.collect_columns(out);
+            }
+            Expr::Function { args, .. } => {
+                for arg in args {
+                    arg.collect_columns(out);
+                }
+            }
+        }
+    }
+
+    /// Whether this expression calls an aggregate function.
+    pub fn has_aggregate(&self) -> bool {
+        match self {
+            Expr::Function { name, .. } => {
+                let upper = name.to_uppercase();
+                matches!(upper.as_str(), "COUNT" | "SUM" | "AVG" | "MIN" | "MAX")
+            }
+            Expr::BinaryOp { left, right, .. } => left.has_aggregate() || right.has_aggregate(),
+            Expr::UnaryOp { expr, .. } | Expr::IsNull { expr, .. } | Expr::Nested(expr) => expr.has_aggregate(),
+            Expr::Between { expr, low, high, .. } => {
+                expr.has_aggregate() || low.has_aggregate() || high.has_aggregate()
+            }
+            Expr::InList { expr, list, .. } => expr.has_aggregate() || list.iter().any(Expr::has_aggregate),
+            _ => false,
+        }
+    }
+
+    /// Rename every reference to column `old` to `new`, leaving subqueries,
+    /// whose columns belong to their own table, alone.
+    pub fn rename_column(&mut self, old: &str, new: &str) {
+        match self {
+            Expr::Column(name) => {
+                if name == old {
+                    *name = new.to_string();
+                }
+            }
+            Expr::Literal(_) | Expr::Subquery(_) | Expr::Exists { .. } | Expr::Default => {}
+            Expr::BinaryOp { left, right, .. } => {
+                left.rename_column(old, new);
+                right.rename_column(old, new);
+            }
+            Expr::UnaryOp { expr, .. }
+            | Expr::IsNull { expr, .. }
+            | Expr::Like { expr, .. }
+            | Expr::JsonAccess { expr, .. }
+            | Expr::Nested(expr)
+            | Expr::InSubquery { expr, .. } => expr.rename_column(old, new),
+            Expr::InList { expr, list, .. } => {
+                expr.rename_column(old, new);
+                for item in list {
+                    item.rename_column(old, new);
+                }
+            }
+            Expr::Between { expr, low, high, .. } => {
+                expr.rename_column(old, new);
+                low.rename_column(old, new);
+                high.rename_column(old, new);
+            }
+            Expr::Function { args, .. } => {
+                for arg in args {
+                    arg.rename_column(old, new);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // table.column, from a join
+            Expr::Column(name) => match name.split_once('.') {
+                Some((table, column)) => {
+                    write_ident(f, table)?;
+                    write!(f, ".")?;
+                    write_ident(f, column)
+                }
+                None => write_ident(f, name),
+            },
+            Expr::Literal(lit) => write!(f, "{}", lit),
+            Expr::BinaryOp { left, op, right } => write!(f, "{} {} {}", left, op, right),
+            Expr::UnaryOp { op: UnaryOperator::Not, expr } => write!(f, "NOT {}", expr),
+            // `- -1`, as `--` would start a comment
+            Expr::UnaryOp { op, expr } => {
+                let operand = expr.to_string();
+                let space = if *op == UnaryOperator::Minus && operand.starts_with('-') { " " } else { "" };
+                write!(f, "{}{}{}", op, space, operand)
+            }
+            Expr::IsNull { expr, negated } => {
+                write!(f, "{} IS {}NULL", expr, if *negated { "NOT " } else { "" })
+            }
+            Expr::InList { expr, list, negated } => {
+                write!(f, "{} {}IN (", expr, if *negated { "NOT " } else { "" })?;
+                for (i, item) in list.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Between { expr, low, high, negated } => write!(
+                f,
+                "{} {}BETWEEN {} AND {}",
+                expr,
+                if *negated { "NOT " } else { "" },
+                low,
+                high
+            ),
+            Expr::Like { expr, pattern, negated, case_insensitive, escape_char } => {
+                write!(
+                    f,
+                    "{} {}{} {}",
+                    expr,
+                    if *negated { "NOT " } else { "" },
+                    if *case_insensitive { "ILIKE" } else { "LIKE" },
+                    LiteralValue::String(pattern.clone())
+                )?;
+                if let Some(escape) = escape_char {
+                    write!(f, " ESCAPE {}", LiteralValue::String(escape.to_string()))?;
+                }
+                Ok(())
+            }
+            Expr::JsonAccess { expr, path, as_text } => {
+                write!(f, "{}{}{}", expr, if *as_text { "->>" } else { "->" }, path)
+            }
+            // EXTRACT and INTERVAL are written in their own syntax, as parsed
+            Expr::Function { name, args } => match (name.as_str(), args.as_slice()) {
+                ("extract", [Expr::Literal(LiteralValue::String(field)), expr]) => {
+                    write!(f, "EXTRACT({} FROM {})", field.to_uppercase(), expr)
+                }
+                ("interval", [value, Expr::Literal(LiteralValue::String(unit))]) => {
+                    write!(f, "INTERVAL {} {}", value, unit.to_uppercase())
+                }
+                ("interval", [value]) => write!(f, "INTERVAL {}", value),
+                _ => {
+                    write!(f, "{}(", name)?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", arg)?;
+                    }
+                    write!(f, ")")
+                }
+            },
+            Expr::Nested(inner) => write!(f, "({})", inner),
+            Expr::Subquery(select) => write!(f, "({})", select),
+            Expr::InSubquery { expr, subquery, negated } => write!(
+                f,
+                "{} {}IN ({})",
+                expr,
+                if *negated { "NOT " } else { "" },
+                subquery
+            ),
+            Expr::Exists { subquery, negated } => {
+                write!(f, "{}EXISTS ({})", if *negated { "NOT " } else { "" }, subquery)
+            }
+            Expr::Default => write!(f, "DEFAULT"),
+        }
+    }
+}
+
+/// A table or column name as SQL that parses back to the same name: quoted
+/// unless it is a plain lower-case identifier, which unquoted names fold to.
+pub fn quote_ident(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// Names as a comma-separated SQL list, each quoted as [`quote_ident`] does.
+pub fn quote_idents(names: &[String]) -> String {
+    names.iter().map(|name| quote_ident(name)).collect::<Vec<_>>().join(", ")
+}
+
+fn write_ident(f: &mut fmt::Formatter<'_>, name: &str) -> fmt::Result {
+    write!(f, "{}", quote_ident(name))
+}
+
+impl fmt::Display for Select {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SELECT ")?;
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match column {
+                SelectColumn::Wildcard => write!(f, "*")?,
+                SelectColumn::QualifiedWildcard(table) => write!(f, "{}.*", table)?,
+                SelectColumn::Column(name) => write!(f, "{}", name)?,
+                SelectColumn::Expr { expr, alias: Some(alias) } => write!(f, "{} AS {}", expr, alias)?,
+                SelectColumn::Expr { expr, alias: None } => write!(f, "{}", expr)?,
+            }
+        }
+        write!(f, " FROM {}", self.from)?;
+        if let Some(where_clause) = &self.where_clause {
+            write!(f, " WHERE {}", where_clause)?;
+        }
+        if !self.group_by.is_empty() {
+            write!(f, " GROUP BY {}", self.group_by.join(", "))?;
+        }
+        if let Some(having) = &self.having {
+            write!(f, " HAVING {}", having)?;
+        }
+        for (i, order) in self.order_by.iter().enumerate() {
+            let sep = if i == 0 { " ORDER BY" } else { "," };
+            write!(f, "{} {} {}", sep, order.column, if order.ascending { "ASC" } else { "DESC" })?;
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " OFFSET {}", offset)?;
+        }
+        Ok(())
+    }
 }
 
 /// Literal value.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum LiteralValue {
     Null,
     Boolean(bool),
@@ -199,6 +847,20 @@ pub enum LiteralValue {
 }
 
 impl LiteralValue {
+    /// Build a literal from a stored JSON value.
+    pub fn from_json(value: &Value) -> Self {
+        match value {
+            Value::Null => LiteralValue::Null,
+            Value::Bool(b) => LiteralValue::Boolean(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => LiteralValue::Integer(i),
+                None => LiteralValue::Float(n.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) => LiteralValue::String(s.clone()),
+            Value::Array(_) | Value::Object(_) => LiteralValue::Json(value.clone()),
+        }
+    }
+
     /// Convert to JSON value for storage.
     pub fn to_json(&self) -> Value {
         match self {
@@ -216,8 +878,21 @@ impl LiteralValue {
     }
 }
 
+impl fmt::Display for LiteralValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiteralValue::Null => write!(f, "NULL"),
+            LiteralValue::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            LiteralValue::Integer(n) => write!(f, "{}", n),
+            LiteralValue::Float(x) => write!(f, "{:?}", x),
+            LiteralValue::String(s) => write!(f, "'{}'", s.replace('\'', "''")),
+            LiteralValue::Json(v) => write!(f, "'{}'", v.to_string().replace('\'', "''")),
+        }
+    }
+}
+
 /// Binary operators.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BinaryOperator {
     // Comparison
     Eq,
@@ -259,10 +934,43 @@ impl BinaryOperator {
     }
 }
 
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            BinaryOperator::Eq => "=",
+            BinaryOperator::NotEq => "<>",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::LtEq => "<=",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::GtEq => ">=",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Or => "OR",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Concat => "||",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 /// Unary operators.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum UnaryOperator {
     Not,
     Minus,
     Plus,
 }
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            UnaryOperator::Not => "NOT ",
+            UnaryOperator::Minus => "-",
+            UnaryOperator::Plus => "+",
+        };
+        write!(f, "{}", s)
+    }
+}
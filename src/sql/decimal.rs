@@ -0,0 +1,267 @@
+//! Exact decimal numbers for DECIMAL/NUMERIC columns.
+//!
+//! JSON numbers are read as `f64`, so decimal values are stored as strings
+//! (`"12.50"`) and parsed into a scaled integer when they take part in
+//! arithmetic, comparisons or aggregates.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde_json::Value;
+
+/// Most digits a decimal can hold; the limit of an `i128` mantissa.
+pub const MAX_PRECISION: u32 = 38;
+
+/// Extra fractional digits kept when dividing.
+const DIVISION_SCALE: u32 = 6;
+
+/// A decimal number: `mantissa * 10^-scale`.
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Parse a decimal from text such as `-12.50` or `1.5e3`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (number, exponent) = match s.find(['e', 'E']) {
+            Some(pos) => (&s[..pos], s[pos + 1..].parse::<i32>().ok()?),
+            None => (s, 0),
+        };
+        let (negative, digits) = match number.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() || !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut mantissa: i128 = 0;
+        for b in whole.bytes().chain(fraction.bytes()) {
+            mantissa = mantissa.checked_mul(10)?.checked_add(i128::from(b - b'0'))?;
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+        let scale = fraction.len() as i32 - exponent;
+        if scale >= 0 {
+            Some(Self { mantissa, scale: scale as u32 })
+        } else {
+            Some(Self { mantissa: mantissa.checked_mul(pow10(scale.unsigned_abs())?)?, scale: 0 })
+        }
+    }
+
+    /// Read a decimal from a JSON number or numeric string.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => Some(Self::from(i)),
+                None => Self::parse(&n.to_string()),
+            },
+            Value::String(s) => Self::parse(s),
+            _ => None,
+        }
+    }
+
+    /// The decimal as a JSON string, the form decimal columns store.
+    pub fn to_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+
+    /// Digits to the right of the decimal point.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Digits to the left of the decimal point, ignoring leading zeros.
+    pub fn integer_digits(&self) -> u32 {
+        let whole = self.mantissa.unsigned_abs() / 10u128.pow(self.scale.min(MAX_PRECISION));
+        if whole == 0 { 0 } else { whole.ilog10() + 1 }
+    }
+
+    /// Change the scale, rounding half away from zero when it shrinks.
+    pub fn rescale(self, scale: u32) -> Option<Self> {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Some(self),
+            Ordering::Greater => Some(Self {
+                mantissa: self.mantissa.checked_mul(pow10(scale - self.scale)?)?,
+                scale,
+            }),
+            Ordering::Less => {
+                let divisor = pow10(self.scale - scale).unwrap_or(i128::MAX);
+                let quotient = self.mantissa / divisor;
+                let remainder = self.mantissa % divisor;
+                let round = if remainder.unsigned_abs() * 2 >= divisor.unsigned_abs() {
+                    self.mantissa.signum()
+                } else {
+                    0
+                };
+                Some(Self { mantissa: quotient + round, scale })
+            }
+        }
+    }
+
+    /// Drop trailing fractional zeros, keeping at least `min_scale` digits.
+    fn trim(mut self, min_scale: u32) -> Self {
+        while self.scale > min_scale && self.mantissa % 10 == 0 {
+            self.mantissa /= 10;
+            self.scale -= 1;
+        }
+        self
+    }
+
+    /// Bring two decimals to the same scale.
+    fn align(self, other: Self) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        Some((self.rescale(scale)?.mantissa, other.rescale(scale)?.mantissa, scale))
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Self { mantissa: a.checked_add(b)?, scale })
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Self { mantissa: a.checked_sub(b)?, scale })
+    }
+
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        let scale = self.scale + other.scale;
+        if scale > MAX_PRECISION {
+            return None;
+        }
+        Some(Self { mantissa: self.mantissa.checked_mul(other.mantissa)?, scale })
+    }
+
+    /// Divide, keeping a few more fractional digits than either operand.
+    ///
+    /// Returns `None` on division by zero or overflow.
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        if other.mantissa == 0 {
+            return None;
+        }
+        let min_scale = self.scale.max(other.scale);
+        let scale = (min_scale + DIVISION_SCALE).min(MAX_PRECISION);
+        // Work one digit past the result scale, then round it away
+        let shift = (scale + 1 + other.scale).checked_sub(self.scale)?;
+        let numerator = self.mantissa.checked_mul(pow10(shift)?)?;
+        let quotient = Self { mantissa: numerator / other.mantissa, scale: scale + 1 };
+        Some(quotient.rescale(scale)?.trim(min_scale))
+    }
+
+    /// Remainder of division, with the sign of the dividend.
+    pub fn checked_rem(self, other: Self) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Self { mantissa: a.checked_rem(b)?, scale })
+    }
+
+    pub fn neg(self) -> Self {
+        Self { mantissa: -self.mantissa, scale: self.scale }
+    }
+
+    /// Lossy conversion, for comparisons that overflow.
+    fn to_f64(self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(self.scale as i32)
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Self { mantissa: i128::from(value), scale: 0 }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.align(*other) {
+            Some((a, b, _)) => a.cmp(&b),
+            None => self.to_f64().total_cmp(&other.to_f64()),
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+fn pow10(exp: u32) -> Option<i128> {
+    10i128.checked_pow(exp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(dec("12.50").to_string(), "12.50");
+        assert_eq!(dec("-0.05").to_string(), "-0.05");
+        assert_eq!(dec("1.5e3").to_string(), "1500");
+        assert_eq!(dec("2.5E-2").to_string(), "0.025");
+        assert_eq!(dec("12345678901234567890.123456789").to_string(), "12345678901234567890.123456789");
+        assert!(Decimal::parse("abc").is_none());
+        assert!(Decimal::parse("1.2.3").is_none());
+        assert!(Decimal::parse(".5").is_none());
+        assert!(Decimal::parse("").is_none());
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        assert_eq!(dec("0.1").checked_add(dec("0.2")).unwrap().to_string(), "0.3");
+        assert_eq!(dec("10.00").checked_sub(dec("0.01")).unwrap().to_string(), "9.99");
+        assert_eq!(dec("1.10").checked_mul(dec("3")).unwrap().to_string(), "3.30");
+        assert_eq!(dec("10.00").checked_div(dec("4")).unwrap().to_string(), "2.50");
+        assert_eq!(dec("1").checked_div(dec("3")).unwrap().to_string(), "0.333333");
+        assert_eq!(dec("2").checked_div(dec("3")).unwrap().to_string(), "0.666667");
+        assert_eq!(dec("7.5").checked_rem(dec("2")).unwrap().to_string(), "1.5");
+        assert!(dec("1").checked_div(dec("0.00")).is_none());
+
+        let big = dec("99999999999999999999.99");
+        assert_eq!(big.checked_add(dec("0.01")).unwrap().to_string(), "100000000000000000000.00");
+    }
+
+    #[test]
+    fn test_rescale_and_compare() {
+        assert_eq!(dec("2.345").rescale(2).unwrap().to_string(), "2.35");
+        assert_eq!(dec("-2.345").rescale(2).unwrap().to_string(), "-2.35");
+        assert_eq!(dec("2.344").rescale(2).unwrap().to_string(), "2.34");
+        assert_eq!(dec("5").rescale(2).unwrap().to_string(), "5.00");
+        assert_eq!(dec("123.45").integer_digits(), 3);
+        assert_eq!(dec("0.45").integer_digits(), 0);
+
+        assert_eq!(dec("1.50"), dec("1.5"));
+        assert!(dec("10.5") > dec("9.25"));
+        assert!(dec("-1") < dec("0.001"));
+    }
+}
@@ -0,0 +1,87 @@
+//! The SQL dialect GitDB parses.
+//!
+//! This is sqlparser's generic dialect with one change: the JSON operators
+//! `->` and `->>` bind tighter than comparisons and arithmetic, so
+//! `data->>'city' = 'NYC'` compares the accessed field rather than parsing as
+//! `data->>('city' = 'NYC')`.
+
+use std::any::TypeId;
+
+use sqlparser::dialect::{Dialect, GenericDialect};
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::Token;
+
+/// Above `*`/`/` and `AT TIME ZONE`, below `::` casts.
+const JSON_ACCESS_PREC: u8 = 45;
+
+/// Generic dialect with tight-binding JSON access operators.
+#[derive(Debug, Default)]
+pub(crate) struct GitDbDialect(GenericDialect);
+
+macro_rules! delegate {
+    ($($method:ident),* $(,)?) => {
+        $(
+            fn $method(&self) -> bool {
+                self.0.$method()
+            }
+        )*
+    };
+}
+
+impl Dialect for GitDbDialect {
+    // Report as the generic dialect so sqlparser's dialect checks treat us the same
+    fn dialect(&self) -> TypeId {
+        self.0.dialect()
+    }
+
+    fn is_delimited_identifier_start(&self, ch: char) -> bool {
+        self.0.is_delimited_identifier_start(ch)
+    }
+
+    fn is_identifier_start(&self, ch: char) -> bool {
+        self.0.is_identifier_start(ch)
+    }
+
+    fn is_identifier_part(&self, ch: char) -> bool {
+        self.0.is_identifier_part(ch)
+    }
+
+    fn get_next_precedence(&self, parser: &Parser) -> Option<Result<u8, ParserError>> {
+        match parser.peek_token().token {
+            Token::Arrow | Token::LongArrow => Some(Ok(JSON_ACCESS_PREC)),
+            _ => self.0.get_next_precedence(parser),
+        }
+    }
+
+    delegate!(
+        supports_unicode_string_literal,
+        supports_group_by_expr,
+        supports_group_by_with_modifier,
+        supports_connect_by,
+        supports_match_recognize,
+        supports_start_transaction_modifier,
+        supports_window_function_null_treatment_arg,
+        supports_dictionary_syntax,
+        supports_window_clause_named_window_reference,
+        supports_parenthesized_set_variables,
+        supports_select_wildcard_except,
+        support_map_literal_syntax,
+        allow_extract_custom,
+        allow_extract_single_quotes,
+        supports_create_index_with_clause,
+        supports_explain_with_utility_options,
+        supports_limit_comma,
+        supports_asc_desc_in_column_definition,
+        supports_try_convert,
+        supports_comment_on,
+        supports_load_extension,
+        supports_named_fn_args_with_assignment_operator,
+        supports_struct_literal,
+        supports_empty_projections,
+        supports_nested_comments,
+        supports_user_host_grantee,
+        supports_string_escape_constant,
+        supports_array_typedef_with_brackets,
+        supports_match_against,
+    );
+}
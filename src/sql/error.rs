@@ -1,4 +1,4 @@
-//! SQL parsing errors.
+//! SQL parsing and evaluation errors.
 
 use thiserror::Error;
 
@@ -20,9 +20,15 @@ pub enum ParseError {
     #[error("unsupported data type: {0}")]
     UnsupportedDataType(String),
 
+    #[error("unsupported constraint: {0}")]
+    UnsupportedConstraint(String),
+
     #[error("invalid identifier: {0}")]
     InvalidIdentifier(String),
 
+    #[error("invalid row count: {0}")]
+    InvalidRowCount(String),
+
     #[error("missing required clause: {0}")]
     MissingClause(String),
 
@@ -33,6 +39,55 @@ pub enum ParseError {
     MultipleStatements,
 }
 
+/// Result type for expression evaluation.
+pub type EvalResult<T> = Result<T, EvalError>;
+
+/// Expression evaluation errors.
+#[derive(Debug, Clone, Error)]
+pub enum EvalError {
+    #[error("column not found: {0}")]
+    ColumnNotFound(String),
+
+    #[error("invalid expression: {0}")]
+    InvalidExpression(String),
+
+    #[error("division by zero")]
+    DivisionByZero,
+
+    #[error("numeric overflow")]
+    NumericOverflow,
+}
+
+impl ParseError {
+    /// A short name for the kind of error, stable across releases.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::Syntax(_) => "syntax",
+            ParseError::UnsupportedStatement(_) => "unsupported_statement",
+            ParseError::UnsupportedExpression(_) => "unsupported_expression",
+            ParseError::UnsupportedDataType(_) => "unsupported_data_type",
+            ParseError::UnsupportedConstraint(_) => "unsupported_constraint",
+            ParseError::InvalidIdentifier(_) => "invalid_identifier",
+            ParseError::InvalidRowCount(_) => "invalid_row_count",
+            ParseError::MissingClause(_) => "missing_clause",
+            ParseError::EmptyQuery => "empty_query",
+            ParseError::MultipleStatements => "multiple_statements",
+        }
+    }
+}
+
+impl EvalError {
+    /// A short name for the kind of error, stable across releases.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EvalError::ColumnNotFound(_) => "column_not_found",
+            EvalError::InvalidExpression(_) => "invalid_expression",
+            EvalError::DivisionByZero => "division_by_zero",
+            EvalError::NumericOverflow => "numeric_overflow",
+        }
+    }
+}
+
 impl From<sqlparser::parser::ParserError> for ParseError {
     fn from(e: sqlparser::parser::ParserError) -> Self {
         ParseError::Syntax(e.to_string())
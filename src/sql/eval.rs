@@ -0,0 +1,1334 @@
+//! Expression evaluation.
+
+use serde_json::Value;
+
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, Timelike, Utc};
+
+use super::ast::{BinaryOperator, Expr, JsonPath, UnaryOperator};
+use super::decimal::Decimal;
+use super::error::{EvalError, EvalResult};
+
+#[cfg(test)]
+use crate::sql::LiteralValue;
+
+/// Evaluate an expression against a row.
+pub fn evaluate(expr: &Expr, row: &serde_json::Map<String, Value>) -> EvalResult<Value> {
+    match expr {
+        // Only joined rows name their columns `table.column`
+        Expr::Column(name) => {
+            row.get(name)
+                .or_else(|| name.split_once('.').and_then(|(_, column)| row.get(column)))
+                .cloned()
+                .ok_or_else(|| EvalError::ColumnNotFound(name.clone()))
+        }
+
+        Expr::Literal(lit) => Ok(lit.to_json()),
+
+        Expr::BinaryOp { left, op, right } => {
+            let l = evaluate(left, row)?;
+            let r = evaluate(right, row)?;
+            eval_binary_op(&l, *op, &r)
+        }
+
+        Expr::UnaryOp { op, expr } => {
+            let v = evaluate(expr, row)?;
+            eval_unary_op(*op, &v)
+        }
+
+        Expr::IsNull { expr, negated } => {
+            let v = evaluate(expr, row)?;
+            let is_null = v.is_null();
+            Ok(Value::Bool(if *negated { !is_null } else { is_null }))
+        }
+
+        // As a chain of ORed `=`: no match against a list with a NULL in it
+        // is unknown, and so is anything IN for NULL itself
+        Expr::InList { expr, list, negated } => {
+            let v = evaluate(expr, row)?;
+            let mut in_list = Value::Bool(false);
+            for item in list {
+                let Ok(iv) = evaluate(item, row) else { continue };
+                in_list = or3(&in_list, &eval_binary_op(&v, BinaryOperator::Eq, &iv)?);
+                if in_list == Value::Bool(true) {
+                    break;
+                }
+            }
+            Ok(if *negated { not3(&in_list) } else { in_list })
+        }
+
+        Expr::Between { expr, low, high, negated } => {
+            let v = evaluate(expr, row)?;
+            let l = evaluate(low, row)?;
+            let h = evaluate(high, row)?;
+            let in_range = and3(
+                &eval_binary_op(&v, BinaryOperator::GtEq, &l)?,
+                &eval_binary_op(&v, BinaryOperator::LtEq, &h)?,
+            );
+            Ok(if *negated { not3(&in_range) } else { in_range })
+        }
+
+        Expr::Like { expr, pattern, negated, case_insensitive, escape_char } => {
+            let v = evaluate(expr, row)?;
+            if v.is_null() {
+                return Ok(Value::Null);
+            }
+            let s = v.as_str().unwrap_or("");
+            let matches = like_match(s, pattern, *case_insensitive, *escape_char)?;
+            Ok(Value::Bool(if *negated { !matches } else { matches }))
+        }
+
+        Expr::JsonAccess { expr, path, as_text } => {
+            let v = evaluate(expr, row)?;
+            Ok(match json_get(&v, path) {
+                Some(Value::Null) | None => Value::Null,
+                Some(found) if *as_text => Value::String(value_to_string(found)),
+                Some(found) => found.clone(),
+            })
+        }
+
+        Expr::Function { name, args } => {
+            let evaluated: Vec<Value> = args.iter()
+                .map(|a| evaluate(a, row))
+                .collect::<EvalResult<_>>()?;
+            eval_function(name, &evaluated)
+        }
+
+        Expr::Nested(inner) => evaluate(inner, row),
+
+        // The executor runs subqueries before evaluating the expression
+        Expr::Subquery(_) | Expr::InSubquery { .. } | Expr::Exists { .. } => Err(EvalError::InvalidExpression(
+            format!("subquery was not executed: {}", expr),
+        )),
+
+        // INSERT fills in the default itself; anywhere else it means nothing
+        Expr::Default => Err(EvalError::InvalidExpression(
+            "DEFAULT is only allowed as a value in INSERT".into(),
+        )),
+    }
+}
+
+/// Evaluate a WHERE clause, returning true if row matches.
+pub fn matches_where(expr: &Expr, row: &serde_json::Map<String, Value>) -> EvalResult<bool> {
+    let result = evaluate(expr, row)?;
+    Ok(value_to_bool(&result))
+}
+
+/// A WHERE clause prepared for evaluating against many rows.
+///
+/// Subexpressions that appear more than once, like `a + b` in
+/// `a + b > 10 AND a + b < 20`, are worked out once per row and shared.
+/// Anything calling a volatile function such as `now()` is left alone, so
+/// each call still gets its own value.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    expr: Expr,
+    /// `expr` with the shared subexpressions read from their columns.
+    rewritten: Expr,
+    /// Shared subexpressions under column names no table can have,
+    /// innermost first so later ones can use earlier ones.
+    shared: Vec<(String, Expr)>,
+}
+
+impl Predicate {
+    pub fn new(expr: Expr) -> Self {
+        let mut counts: Vec<(Expr, usize)> = Vec::new();
+        count_subexprs(&expr, &mut counts);
+        let mut repeated: Vec<Expr> = counts.into_iter()
+            .filter(|(_, n)| *n > 1)
+            .map(|(e, _)| e)
+            .collect();
+        repeated.sort_by_key(expr_size);
+
+        // Each one is replaced in the bigger ones too, so those still match
+        let mut rewritten = expr.clone();
+        let mut shared = Vec::with_capacity(repeated.len());
+        for i in 0..repeated.len() {
+            let name = format!("\0shared{}", i);
+            let target = repeated[i].clone();
+            replace_subexpr(&mut rewritten, &target, &name);
+            for later in &mut repeated[i + 1..] {
+                replace_subexpr(later, &target, &name);
+            }
+            shared.push((name, target));
+        }
+
+        Self { expr, rewritten, shared }
+    }
+
+    /// Whether a row matches. The row is left as it was found.
+    pub fn matches(&self, row: &mut serde_json::Map<String, Value>) -> EvalResult<bool> {
+        if self.shared.is_empty() {
+            return matches_where(&self.expr, row);
+        }
+
+        let mut computed = true;
+        for (name, expr) in &self.shared {
+            match evaluate(expr, row) {
+                Ok(value) => {
+                    row.insert(name.clone(), value);
+                }
+                Err(_) => {
+                    computed = false;
+                    break;
+                }
+            }
+        }
+        // Errors are reported (or swallowed, inside IN lists) the same way
+        // as without sharing
+        let result = if computed {
+            matches_where(&self.rewritten, row)
+        } else {
+            matches_where(&self.expr, row)
+        };
+        for (name, _) in &self.shared {
+            row.remove(name);
+        }
+        result
+    }
+}
+
+/// Functions that can return something different on every call.
+const VOLATILE_FUNCTIONS: &[&str] = &[
+    "now", "current_timestamp", "gen_ulid", "ulid", "uuid", "gen_random_uuid",
+];
+
+/// Count the subexpressions worth sharing, by structure.
+fn count_subexprs(expr: &Expr, counts: &mut Vec<(Expr, usize)>) {
+    let worth_sharing = !matches!(expr, Expr::Column(_) | Expr::Literal(_) | Expr::Nested(_))
+        && is_stable(expr);
+    if worth_sharing {
+        match counts.iter_mut().find(|(e, _)| e == expr) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((expr.clone(), 1)),
+        }
+    }
+    for child in children(expr) {
+        count_subexprs(child, counts);
+    }
+}
+
+/// Whether an expression gives the same value every time for the same row.
+fn is_stable(expr: &Expr) -> bool {
+    match expr {
+        Expr::Function { name, .. } if VOLATILE_FUNCTIONS.contains(&name.to_lowercase().as_str()) => false,
+        Expr::Subquery(_) | Expr::InSubquery { .. } | Expr::Exists { .. } => false,
+        _ => children(expr).into_iter().all(is_stable),
+    }
+}
+
+fn expr_size(expr: &Expr) -> usize {
+    1 + children(expr).into_iter().map(expr_size).sum::<usize>()
+}
+
+/// Replace every occurrence of `target` in `expr` with a column.
+fn replace_subexpr(expr: &mut Expr, target: &Expr, column: &str) {
+    if expr == target {
+        *expr = Expr::Column(column.to_string());
+        return;
+    }
+    match expr {
+        Expr::BinaryOp { left, right, .. } => {
+            replace_subexpr(left, target, column);
+            replace_subexpr(right, target, column);
+        }
+        Expr::UnaryOp { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Like { expr, .. }
+        | Expr::JsonAccess { expr, .. }
+        | Expr::Nested(expr) => replace_subexpr(expr, target, column),
+        Expr::InList { expr, list, .. } => {
+            replace_subexpr(expr, target, column);
+            for item in list {
+                replace_subexpr(item, target, column);
+            }
+        }
+        Expr::Between { expr, low, high, .. } => {
+            replace_subexpr(expr, target, column);
+            replace_subexpr(low, target, column);
+            replace_subexpr(high, target, column);
+        }
+        Expr::Function { args, .. } => {
+            for arg in args {
+                replace_subexpr(arg, target, column);
+            }
+        }
+        Expr::Column(_)
+        | Expr::Literal(_)
+        | Expr::Subquery(_)
+        | Expr::InSubquery { .. }
+        | Expr::Exists { .. }
+        | Expr::Default => {}
+    }
+}
+
+/// The expressions an expression is evaluated from.
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::BinaryOp { left, right, .. } => vec![left, right],
+        Expr::UnaryOp { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Like { expr, .. }
+        | Expr::JsonAccess { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::InSubquery { expr, .. } => vec![expr],
+        Expr::InList { expr, list, .. } => std::iter::once(expr.as_ref()).chain(list).collect(),
+        Expr::Between { expr, low, high, .. } => vec![expr, low, high],
+        Expr::Function { args, .. } => args.iter().collect(),
+        Expr::Column(_) | Expr::Literal(_) | Expr::Subquery(_) | Expr::Exists { .. } | Expr::Default => Vec::new(),
+    }
+}
+
+fn eval_binary_op(left: &Value, op: BinaryOperator, right: &Value) -> EvalResult<Value> {
+    // Arithmetic and concatenation with NULL are NULL, even dividing by it
+    let arithmetic = matches!(
+        op,
+        BinaryOperator::Plus
+            | BinaryOperator::Minus
+            | BinaryOperator::Multiply
+            | BinaryOperator::Divide
+            | BinaryOperator::Modulo
+            | BinaryOperator::Concat
+    );
+    if arithmetic && (left.is_null() || right.is_null()) {
+        return Ok(Value::Null);
+    }
+
+    if let Some((l, r)) = decimal_operands(left, right) {
+        if let Some(result) = eval_decimal_op(l, op, r) {
+            return result;
+        }
+    }
+
+    // Comparing with NULL, or across types that don't convert to each
+    // other, is unknown
+    let unknown = left.is_null() || right.is_null() || !comparable(left, right);
+    let compare = |test: fn(i32) -> bool| match compare_values(left, right) {
+        Some(c) if !unknown => Value::Bool(test(c)),
+        _ => Value::Null,
+    };
+    match op {
+        // Comparison operators
+        BinaryOperator::Eq | BinaryOperator::NotEq if unknown => Ok(Value::Null),
+        BinaryOperator::Eq => Ok(Value::Bool(values_equal(left, right))),
+        BinaryOperator::NotEq => Ok(Value::Bool(!values_equal(left, right))),
+        BinaryOperator::Lt => Ok(compare(|c| c < 0)),
+        BinaryOperator::LtEq => Ok(compare(|c| c <= 0)),
+        BinaryOperator::Gt => Ok(compare(|c| c > 0)),
+        BinaryOperator::GtEq => Ok(compare(|c| c >= 0)),
+
+        // Logical operators
+        BinaryOperator::And => Ok(and3(left, right)),
+        BinaryOperator::Or => Ok(or3(left, right)),
+
+        // Arithmetic operators
+        BinaryOperator::Plus => eval_arithmetic(left, right, |a, b| a + b),
+        BinaryOperator::Minus => eval_arithmetic(left, right, |a, b| a - b),
+        BinaryOperator::Multiply => eval_arithmetic(left, right, |a, b| a * b),
+        BinaryOperator::Divide => {
+            let r = value_to_f64(right);
+            if r == 0.0 {
+                Err(EvalError::DivisionByZero)
+            } else {
+                eval_arithmetic(left, right, |a, b| a / b)
+            }
+        }
+        BinaryOperator::Modulo => {
+            if value_to_f64(right) == 0.0 {
+                Err(EvalError::DivisionByZero)
+            } else {
+                eval_arithmetic(left, right, |a, b| a % b)
+            }
+        }
+
+        // String concatenation
+        BinaryOperator::Concat => {
+            let l = value_to_string(left);
+            let r = value_to_string(right);
+            Ok(Value::String(format!("{}{}", l, r)))
+        }
+    }
+}
+
+fn eval_unary_op(op: UnaryOperator, value: &Value) -> EvalResult<Value> {
+    match op {
+        UnaryOperator::Not => Ok(not3(value)),
+        UnaryOperator::Minus if value.is_null() => Ok(Value::Null),
+        UnaryOperator::Minus => {
+            if let Some(d) = decimal_operand(value) {
+                return Ok(d.neg().to_value());
+            }
+            let n = value_to_f64(value);
+            if n.fract() == 0.0 {
+                Ok(Value::Number((-n as i64).into()))
+            } else {
+                Ok(serde_json::Number::from_f64(-n)
+                    .map(Value::Number)
+                    .unwrap_or(Value::Null))
+            }
+        }
+        UnaryOperator::Plus => Ok(value.clone()),
+    }
+}
+
+/// Read a decimal column value: a numeric string.
+fn decimal_operand(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::String(s) => Decimal::parse(s),
+        _ => None,
+    }
+}
+
+/// Both operands as decimals, when either is one.
+///
+/// Decimal columns store their values as strings, so a numeric string on
+/// either side switches to exact arithmetic and comparison.
+fn decimal_operands(left: &Value, right: &Value) -> Option<(Decimal, Decimal)> {
+    if !left.is_string() && !right.is_string() {
+        return None;
+    }
+    Decimal::from_value(left).zip(Decimal::from_value(right))
+}
+
+/// Apply an arithmetic operator exactly; `None` for other operators.
+fn eval_decimal_op(left: Decimal, op: BinaryOperator, right: Decimal) -> Option<EvalResult<Value>> {
+    let result = match op {
+        BinaryOperator::Plus => left.checked_add(right),
+        BinaryOperator::Minus => left.checked_sub(right),
+        BinaryOperator::Multiply => left.checked_mul(right),
+        BinaryOperator::Divide | BinaryOperator::Modulo if right == Decimal::from(0) => {
+            return Some(Err(EvalError::DivisionByZero));
+        }
+        BinaryOperator::Divide => left.checked_div(right),
+        BinaryOperator::Modulo => left.checked_rem(right),
+        _ => return None,
+    };
+    Some(result.map(Decimal::to_value).ok_or(EvalError::NumericOverflow))
+}
+
+fn eval_arithmetic<F>(left: &Value, right: &Value, f: F) -> EvalResult<Value>
+where
+    F: Fn(f64, f64) -> f64,
+{
+    let l = value_to_f64(left);
+    let r = value_to_f64(right);
+    Ok(number_value(f(l, r), left.is_i64() && right.is_i64()))
+}
+
+/// Build a numeric result: an integer if the inputs were and it is whole.
+///
+/// Results that aren't finite (overflow, NaN) become NULL.
+fn number_value(result: f64, integer_inputs: bool) -> Value {
+    if integer_inputs && result.fract() == 0.0 && result.abs() < i64::MAX as f64 {
+        Value::Number((result as i64).into())
+    } else {
+        serde_json::Number::from_f64(result)
+            .map(Value::Number)
+            .unwrap_or(Value::Null)
+    }
+}
+
+/// Evaluate a math function on numeric arguments.
+fn eval_math_function(name: &str, args: &[Value]) -> EvalResult<Value> {
+    let lower_name = name.to_lowercase();
+    let (min, max) = match lower_name.as_str() {
+        "abs" | "sqrt" => (1, 1),
+        "round" | "ceil" | "ceiling" | "floor" => (1, 2),
+        _ => (2, 2),
+    };
+    expect_args(name, args, min, max)?;
+    if has_null(args) {
+        return Ok(Value::Null);
+    }
+
+    let x = value_to_f64(&args[0]);
+    let integer = args.iter().all(Value::is_i64);
+    let result = match lower_name.as_str() {
+        "abs" => x.abs(),
+        "sqrt" if x < 0.0 => {
+            return Err(EvalError::InvalidExpression(format!(
+                "{}: negative argument",
+                name
+            )))
+        }
+        "sqrt" => x.sqrt(),
+        "round" | "ceil" | "ceiling" | "floor" => {
+            // Digits after the decimal point; negative rounds left of it
+            let scale = 10f64.powi(args.get(1).map(value_to_i64).unwrap_or(0) as i32);
+            let scaled = x * scale;
+            let rounded = match lower_name.as_str() {
+                "round" => scaled.round(),
+                "floor" => scaled.floor(),
+                _ => scaled.ceil(),
+            };
+            rounded / scale
+        }
+        "mod" => {
+            let y = value_to_f64(&args[1]);
+            if y == 0.0 {
+                return Err(EvalError::DivisionByZero);
+            }
+            x % y
+        }
+        _ => x.powf(value_to_f64(&args[1])),
+    };
+    Ok(number_value(result, integer))
+}
+
+fn eval_function(name: &str, args: &[Value]) -> EvalResult<Value> {
+    let lower_name = name.to_lowercase();
+    match lower_name.as_str() {
+        "count" => Ok(Value::Number(1.into())), // Counting is done at aggregate level
+        "lower" => {
+            let s = args.first().and_then(|v| v.as_str()).unwrap_or("");
+            Ok(Value::String(s.to_lowercase()))
+        }
+        "upper" => {
+            let s = args.first().and_then(|v| v.as_str()).unwrap_or("");
+            Ok(Value::String(s.to_uppercase()))
+        }
+        "length" | "len" => {
+            let s = args.first().and_then(|v| v.as_str()).unwrap_or("");
+            Ok(Value::Number(s.len().into()))
+        }
+        "substr" | "substring" => {
+            expect_args(name, args, 2, 3)?;
+            if has_null(args) {
+                return Ok(Value::Null);
+            }
+            let len = args.get(2).map(value_to_i64);
+            if len.is_some_and(|len| len < 0) {
+                return Err(EvalError::InvalidExpression(format!(
+                    "{}: negative length",
+                    name
+                )));
+            }
+            Ok(Value::String(substr(&value_to_string(&args[0]), value_to_i64(&args[1]), len)))
+        }
+        "trim" | "ltrim" | "rtrim" => {
+            expect_args(name, args, 1, 2)?;
+            if has_null(args) {
+                return Ok(Value::Null);
+            }
+            let s = value_to_string(&args[0]);
+            let chars: Vec<char> = match args.get(1) {
+                Some(set) => value_to_string(set).chars().collect(),
+                None => vec![' ', '\t', '\n', '\r'],
+            };
+            let trimmed = match lower_name.as_str() {
+                "ltrim" => s.trim_start_matches(chars.as_slice()),
+                "rtrim" => s.trim_end_matches(chars.as_slice()),
+                _ => s.trim_matches(chars.as_slice()),
+            };
+            Ok(Value::String(trimmed.to_string()))
+        }
+        "replace" => {
+            expect_args(name, args, 3, 3)?;
+            if has_null(args) {
+                return Ok(Value::Null);
+            }
+            let s = value_to_string(&args[0]);
+            let from = value_to_string(&args[1]);
+            if from.is_empty() {
+                return Ok(Value::String(s));
+            }
+            Ok(Value::String(s.replace(&from, &value_to_string(&args[2]))))
+        }
+        "concat" => {
+            expect_args(name, args, 1, usize::MAX)?;
+            if has_null(args) {
+                return Ok(Value::Null);
+            }
+            Ok(Value::String(args.iter().map(value_to_string).collect()))
+        }
+        "lpad" | "rpad" => {
+            expect_args(name, args, 2, 3)?;
+            if has_null(args) {
+                return Ok(Value::Null);
+            }
+            let fill = args.get(2).map(value_to_string).unwrap_or_else(|| " ".to_string());
+            let width = value_to_i64(&args[1]).max(0) as usize;
+            let left = lower_name == "lpad";
+            Ok(Value::String(pad(&value_to_string(&args[0]), width, &fill, left)))
+        }
+        "abs" | "round" | "ceil" | "ceiling" | "floor" | "mod" | "power" | "pow" | "sqrt" => {
+            eval_math_function(name, args)
+        }
+        "date" => {
+            expect_args(name, args, 1, 1)?;
+            if has_null(args) {
+                return Ok(Value::Null);
+            }
+            let ts = timestamp_arg(name, &args[0])?;
+            Ok(Value::String(ts.format("%Y-%m-%d").to_string()))
+        }
+        "date_add" | "date_sub" => {
+            expect_args(name, args, 2, 3)?;
+            if has_null(args) {
+                return Ok(Value::Null);
+            }
+            let ts = timestamp_arg(name, &args[0])?;
+            // Either an interval string (`'3 days'`, INTERVAL 3 DAY) or amount and unit
+            let interval = match args.get(2) {
+                Some(unit) => format!("{} {}", value_to_string(&args[1]), value_to_string(unit)),
+                None => value_to_string(&args[1]),
+            };
+            let (amount, unit) = parse_interval(&interval).ok_or_else(|| {
+                EvalError::InvalidExpression(format!("{}: invalid interval '{}'", name, interval))
+            })?;
+            let amount = if lower_name == "date_sub" { -amount } else { amount };
+            let shifted = add_interval(ts, amount, unit).ok_or_else(|| {
+                EvalError::InvalidExpression(format!("{}: result out of range", name))
+            })?;
+            // A date moved by whole days stays a date
+            let whole_days = matches!(unit, IntervalUnit::Day | IntervalUnit::Week | IntervalUnit::Month | IntervalUnit::Year);
+            if whole_days && args[0].as_str().and_then(parse_date).is_some() {
+                return Ok(Value::String(shifted.format("%Y-%m-%d").to_string()));
+            }
+            Ok(Value::String(shifted.to_rfc3339()))
+        }
+        "interval" => {
+            // INTERVAL 3 DAY; kept as text for date_add/date_sub to parse
+            expect_args(name, args, 1, 2)?;
+            let parts: Vec<String> = args.iter().map(value_to_string).collect();
+            Ok(Value::String(parts.join(" ")))
+        }
+        "extract" | "date_part" => {
+            expect_args(name, args, 2, 2)?;
+            if args[1].is_null() {
+                return Ok(Value::Null);
+            }
+            let field = value_to_string(&args[0]).to_lowercase();
+            let ts = timestamp_arg(name, &args[1])?;
+            let value = match field.as_str() {
+                "year" => ts.year() as i64,
+                "quarter" => (ts.month0() / 3 + 1) as i64,
+                "month" => ts.month() as i64,
+                "week" => ts.iso_week().week() as i64,
+                "day" => ts.day() as i64,
+                "dow" => ts.weekday().num_days_from_sunday() as i64,
+                "doy" => ts.ordinal() as i64,
+                "hour" => ts.hour() as i64,
+                "minute" => ts.minute() as i64,
+                "second" => ts.second() as i64,
+                "epoch" => ts.timestamp(),
+                other => {
+                    return Err(EvalError::InvalidExpression(format!(
+                        "{}: unknown field '{}'",
+                        name, other
+                    )))
+                }
+            };
+            Ok(Value::Number(value.into()))
+        }
+        "coalesce" => {
+            for arg in args {
+                if !arg.is_null() {
+                    return Ok(arg.clone());
+                }
+            }
+            Ok(Value::Null)
+        }
+        "now" | "current_timestamp" => {
+            let now = chrono::Utc::now().to_rfc3339();
+            Ok(Value::String(now))
+        }
+        "gen_ulid" | "ulid" => {
+            // Lowercase to match RowKey::generate()
+            let id = ulid::Ulid::new().to_string().to_lowercase();
+            Ok(Value::String(id))
+        }
+        "uuid" | "gen_random_uuid" => {
+            let id = uuid::Uuid::new_v4().to_string();
+            Ok(Value::String(id))
+        }
+        _ => Err(EvalError::InvalidExpression(format!(
+            "unknown function: {}",
+            name
+        ))),
+    }
+}
+
+/// Check that a function got between `min` and `max` arguments.
+fn expect_args(name: &str, args: &[Value], min: usize, max: usize) -> EvalResult<()> {
+    if (min..=max).contains(&args.len()) {
+        return Ok(());
+    }
+    let expected = if min == max {
+        min.to_string()
+    } else if max == usize::MAX {
+        format!("at least {}", min)
+    } else {
+        format!("{} to {}", min, max)
+    };
+    Err(EvalError::InvalidExpression(format!(
+        "{} takes {} arguments, got {}",
+        name,
+        expected,
+        args.len()
+    )))
+}
+
+/// Whether any argument is NULL, which makes most functions return NULL.
+fn has_null(args: &[Value]) -> bool {
+    args.iter().any(Value::is_null)
+}
+
+/// SQL SUBSTR: `len` characters from 1-based position `start`.
+///
+/// Positions before the start of the string still count towards `len`, so
+/// `substr('abc', 0, 2)` is `'a'`.
+fn substr(s: &str, start: i64, len: Option<i64>) -> String {
+    let end = len.map(|len| start.saturating_add(len));
+    let skip = start.max(1) - 1;
+    let take = match end {
+        Some(end) if end <= skip + 1 => 0,
+        Some(end) => end - 1 - skip,
+        None => i64::MAX,
+    };
+    s.chars().skip(skip as usize).take(take as usize).collect()
+}
+
+/// Pad `s` to `width` characters with repeats of `fill`, truncating if longer.
+fn pad(s: &str, width: usize, fill: &str, left: bool) -> String {
+    let len = s.chars().count();
+    if len >= width || fill.is_empty() {
+        return s.chars().take(width).collect();
+    }
+    let padding: String = fill.chars().cycle().take(width - len).collect();
+    if left {
+        padding + s
+    } else {
+        format!("{}{}", s, padding)
+    }
+}
+
+/// Parse an ISO 8601 timestamp or date.
+///
+/// Accepts RFC 3339 (`2024-01-01T12:00:00+02:00`), the same without an offset
+/// and with either `T` or a space as separator, and plain dates
+/// (`2024-01-01`, read as midnight). Times without an offset are UTC.
+pub(crate) fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    // Cheap rejection, since this runs on every string comparison
+    let bytes = s.as_bytes();
+    if bytes.len() < 10 || !bytes[..4].iter().all(u8::is_ascii_digit) || bytes[4] != b'-' {
+        return None;
+    }
+
+    if let Ok(ts) = DateTime::parse_from_rfc3339(s) {
+        return Some(ts.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| parse_date(s)?.and_hms_opt(0, 0, 0))
+        .map(|naive| naive.and_utc())
+}
+
+/// Parse a plain date, `2024-01-01`.
+pub(crate) fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+}
+
+/// Parse a function's timestamp argument, failing on anything else.
+fn timestamp_arg(name: &str, value: &Value) -> EvalResult<DateTime<Utc>> {
+    value.as_str().and_then(parse_timestamp).ok_or_else(|| {
+        EvalError::InvalidExpression(format!("{}: not a timestamp: {}", name, value))
+    })
+}
+
+/// Units an interval can be given in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IntervalUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// Parse an interval like `3 day` or `'-2 months'`.
+fn parse_interval(s: &str) -> Option<(i64, IntervalUnit)> {
+    let mut parts = s.split_whitespace();
+    let amount = parts.next()?.parse().ok()?;
+    let unit = match parts.next()?.to_lowercase().trim_end_matches('s') {
+        "second" => IntervalUnit::Second,
+        "minute" => IntervalUnit::Minute,
+        "hour" => IntervalUnit::Hour,
+        "day" => IntervalUnit::Day,
+        "week" => IntervalUnit::Week,
+        "month" => IntervalUnit::Month,
+        "year" => IntervalUnit::Year,
+        _ => return None,
+    };
+    parts.next().is_none().then_some((amount, unit))
+}
+
+/// Shift a timestamp by an interval; months and years keep the day of month
+/// where possible, clamping to the end of shorter months.
+fn add_interval(ts: DateTime<Utc>, amount: i64, unit: IntervalUnit) -> Option<DateTime<Utc>> {
+    let months = |n: i64| {
+        let n = Months::new(u32::try_from(n.unsigned_abs()).ok()?);
+        if amount < 0 { ts.checked_sub_months(n) } else { ts.checked_add_months(n) }
+    };
+    match unit {
+        IntervalUnit::Second => ts.checked_add_signed(Duration::try_seconds(amount)?),
+        IntervalUnit::Minute => ts.checked_add_signed(Duration::try_minutes(amount)?),
+        IntervalUnit::Hour => ts.checked_add_signed(Duration::try_hours(amount)?),
+        IntervalUnit::Day => ts.checked_add_signed(Duration::try_days(amount)?),
+        IntervalUnit::Week => ts.checked_add_signed(Duration::try_weeks(amount)?),
+        IntervalUnit::Month => months(amount),
+        IntervalUnit::Year => months(amount.checked_mul(12)?),
+    }
+}
+
+/// Check if two JSON values are the same value.
+///
+/// NULL is the same as NULL here, as rows are told apart; SQL's `=`, where
+/// comparing with NULL is unknown, is evaluated by [`evaluate`]. A boolean and
+/// a number compare as 0 or 1.
+pub fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Null, Value::Null) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Bool(x), Value::Number(n)) | (Value::Number(n), Value::Bool(x)) => {
+            n.as_f64() == Some(f64::from(u8::from(*x)))
+        }
+        (Value::Number(_), Value::Number(_)) => compare_values(a, b) == Some(0),
+        (Value::String(a), Value::String(b)) => {
+            a == b
+                || matches!((parse_timestamp(a), parse_timestamp(b)), (Some(x), Some(y)) if x == y)
+                || matches!((Decimal::parse(a), Decimal::parse(b)), (Some(x), Some(y)) if x == y)
+        }
+        // Decimal columns hold numeric strings
+        (Value::String(_), Value::Number(_)) | (Value::Number(_), Value::String(_)) => {
+            compare_values(a, b) == Some(0)
+        }
+        (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether SQL can compare two values: ones of the same type, numbers with
+/// booleans or numeric strings, and NULL with anything.
+fn comparable(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(_), Value::String(s)) | (Value::String(s), Value::Number(_)) => {
+            numeric_string(s).is_some()
+        }
+        (Value::Number(_), Value::Bool(_)) | (Value::Bool(_), Value::Number(_)) => true,
+        (Value::Null, _) | (_, Value::Null) => true,
+        _ => std::mem::discriminant(a) == std::mem::discriminant(b),
+    }
+}
+
+/// A string holding a finite number, as that number.
+fn numeric_string(s: &str) -> Option<f64> {
+    s.trim().parse::<f64>().ok().filter(|f| f.is_finite())
+}
+
+/// Compare two JSON values, returning ordering.
+fn compare_values(a: &Value, b: &Value) -> Option<i32> {
+    match (a, b) {
+        // Integers compare exactly, anything else as f64
+        (Value::Number(x), Value::Number(y)) => match (x.as_i64(), y.as_i64()) {
+            (Some(x), Some(y)) => Some(x.cmp(&y) as i32),
+            _ => Some(x.as_f64()?.partial_cmp(&y.as_f64()?).map(|o| o as i32).unwrap_or(0)),
+        },
+        // Timestamps compare by time, so offsets and date-only forms order correctly
+        (Value::String(x), Value::String(y)) => match (parse_timestamp(x), parse_timestamp(y)) {
+            (Some(x), Some(y)) => Some(x.cmp(&y) as i32),
+            _ => match decimal_operands(a, b) {
+                Some((x, y)) => Some(x.cmp(&y) as i32),
+                None => Some(x.cmp(y) as i32),
+            },
+        },
+        // Numeric strings compare as numbers: exactly if they are decimals,
+        // otherwise as f64
+        (Value::String(s), Value::Number(n)) | (Value::Number(n), Value::String(s)) => {
+            if let Some((x, y)) = decimal_operands(a, b) {
+                return Some(x.cmp(&y) as i32);
+            }
+            let (x, y) = (numeric_string(s)?, n.as_f64()?);
+            let ordering = x.partial_cmp(&y)? as i32;
+            Some(if a.is_string() { ordering } else { -ordering })
+        }
+        (Value::Bool(a), Value::Bool(b)) => Some((*a as i32) - (*b as i32)),
+        (Value::Bool(x), Value::Number(_)) => compare_values(&Value::from(u8::from(*x)), b),
+        (Value::Number(_), Value::Bool(y)) => compare_values(a, &Value::from(u8::from(*y))),
+        _ => None,
+    }
+}
+
+/// SQL's three-valued truth of a value: NULL is unknown.
+fn truth(v: &Value) -> Option<bool> {
+    (!v.is_null()).then(|| value_to_bool(v))
+}
+
+/// AND, where false wins over unknown.
+fn and3(left: &Value, right: &Value) -> Value {
+    match (truth(left), truth(right)) {
+        (Some(false), _) | (_, Some(false)) => Value::Bool(false),
+        (Some(true), Some(true)) => Value::Bool(true),
+        _ => Value::Null,
+    }
+}
+
+/// OR, where true wins over unknown.
+fn or3(left: &Value, right: &Value) -> Value {
+    match (truth(left), truth(right)) {
+        (Some(true), _) | (_, Some(true)) => Value::Bool(true),
+        (Some(false), Some(false)) => Value::Bool(false),
+        _ => Value::Null,
+    }
+}
+
+/// NOT, where unknown stays unknown.
+fn not3(value: &Value) -> Value {
+    truth(value).map_or(Value::Null, |t| Value::Bool(!t))
+}
+
+/// Convert JSON value to boolean.
+fn value_to_bool(v: &Value) -> bool {
+    match v {
+        Value::Bool(b) => *b,
+        Value::Null => false,
+        Value::Number(n) => n.as_f64().map(|f| f != 0.0).unwrap_or(false),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+/// Convert JSON value to f64.
+fn value_to_f64(v: &Value) -> f64 {
+    match v {
+        Value::Number(n) => n.as_f64().unwrap_or(0.0),
+        Value::String(s) => s.parse().unwrap_or(0.0),
+        Value::Bool(true) => 1.0,
+        _ => 0.0,
+    }
+}
+
+/// Convert JSON value to an integer, truncating fractions.
+fn value_to_i64(v: &Value) -> i64 {
+    v.as_i64().unwrap_or_else(|| value_to_f64(v) as i64)
+}
+
+/// Convert JSON value to string.
+fn value_to_string(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        _ => v.to_string(),
+    }
+}
+
+/// Step into a JSON object or array; `None` if the path is missing.
+fn json_get<'a>(value: &'a Value, path: &JsonPath) -> Option<&'a Value> {
+    match (value, path) {
+        (Value::Object(map), JsonPath::Key(key)) => map.get(key),
+        (Value::Array(items), JsonPath::Index(i)) => {
+            let index = if *i < 0 { items.len().checked_sub(i.unsigned_abs() as usize)? } else { *i as usize };
+            items.get(index)
+        }
+        _ => None,
+    }
+}
+
+/// LIKE pattern matching; `%` matches any sequence and `_` one character.
+///
+/// With an escape character, the character after it matches literally. A
+/// pattern ending in an unpaired escape character is an error.
+pub(crate) fn like_match(s: &str, pattern: &str, case_insensitive: bool, escape_char: Option<char>) -> EvalResult<bool> {
+    if let Some(escape) = escape_char {
+        let trailing = pattern.chars().rev().take_while(|&c| c == escape).count();
+        if trailing % 2 == 1 {
+            return Err(EvalError::InvalidExpression(format!(
+                "LIKE pattern '{}' ends with escape character '{}'",
+                pattern, escape
+            )));
+        }
+    }
+    Ok(simple_like_match(s, pattern, case_insensitive, escape_char))
+}
+
+/// Simple LIKE matching without regex.
+fn simple_like_match(s: &str, pattern: &str, case_insensitive: bool, escape_char: Option<char>) -> bool {
+    let s_chars: Vec<char> = s.chars().collect();
+    let p_chars: Vec<char> = pattern.chars().collect();
+    match_like(&s_chars, &p_chars, 0, 0, case_insensitive, escape_char)
+}
+
+fn match_like(s: &[char], p: &[char], si: usize, pi: usize, case_insensitive: bool, escape_char: Option<char>) -> bool {
+    if pi >= p.len() {
+        return si >= s.len();
+    }
+    let rest = |si, pi| match_like(s, p, si, pi, case_insensitive, escape_char);
+
+    match p[pi] {
+        // The escaped character matches itself, wildcard or not; a trailing
+        // escape is rejected by like_match and otherwise matches itself
+        e if Some(e) == escape_char && pi + 1 < p.len() => {
+            si < s.len() && chars_equal(s[si], p[pi + 1], case_insensitive) && rest(si + 1, pi + 2)
+        }
+        '%' => {
+            // Match any sequence (including empty)
+            for i in si..=s.len() {
+                if rest(i, pi + 1) {
+                    return true;
+                }
+            }
+            false
+        }
+        '_' => {
+            // Match single character
+            si < s.len() && rest(si + 1, pi + 1)
+        }
+        c => {
+            // Match exact character
+            si < s.len() && chars_equal(s[si], c, case_insensitive) && rest(si + 1, pi + 1)
+        }
+    }
+}
+
+fn chars_equal(a: char, b: char, case_insensitive: bool) -> bool {
+    a == b || (case_insensitive && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn make_row() -> serde_json::Map<String, Value> {
+        let obj = json!({
+            "id": 1,
+            "name": "Alice",
+            "age": 30,
+            "active": true
+        });
+        obj.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_column_eval() {
+        let row = make_row();
+        let expr = Expr::Column("name".into());
+        assert_eq!(evaluate(&expr, &row).unwrap(), json!("Alice"));
+    }
+
+    #[test]
+    fn test_literal_eval() {
+        let row = make_row();
+        let expr = Expr::Literal(LiteralValue::Integer(42));
+        assert_eq!(evaluate(&expr, &row).unwrap(), json!(42));
+    }
+
+    #[test]
+    fn test_comparison() {
+        let row = make_row();
+        let expr = Expr::BinaryOp {
+            left: Box::new(Expr::Column("age".into())),
+            op: BinaryOperator::Gt,
+            right: Box::new(Expr::Literal(LiteralValue::Integer(25))),
+        };
+        assert_eq!(evaluate(&expr, &row).unwrap(), json!(true));
+    }
+
+    #[test]
+    fn test_and_or() {
+        let row = make_row();
+        let expr = Expr::BinaryOp {
+            left: Box::new(Expr::Column("active".into())),
+            op: BinaryOperator::And,
+            right: Box::new(Expr::BinaryOp {
+                left: Box::new(Expr::Column("age".into())),
+                op: BinaryOperator::Lt,
+                right: Box::new(Expr::Literal(LiteralValue::Integer(50))),
+            }),
+        };
+        assert_eq!(evaluate(&expr, &row).unwrap(), json!(true));
+    }
+
+    #[test]
+    fn test_null_comparisons() {
+        use BinaryOperator::*;
+        let comparisons = [Eq, NotEq, Lt, LtEq, Gt, GtEq];
+        for op in comparisons {
+            for (l, r) in [(json!(null), json!(1)), (json!("a"), json!(null)), (json!(null), json!(null))] {
+                assert_eq!(eval_binary_op(&l, op, &r).unwrap(), Value::Null, "{} {:?} {}", l, op, r);
+            }
+        }
+
+        // Three-valued AND, OR and NOT
+        let (t, f, n) = (json!(true), json!(false), json!(null));
+        let table = [
+            (&t, &n, Value::Null, json!(true)),
+            (&f, &n, json!(false), Value::Null),
+            (&n, &n, Value::Null, Value::Null),
+            (&n, &f, json!(false), Value::Null),
+        ];
+        for (l, r, and, or) in table {
+            assert_eq!(eval_binary_op(l, And, r).unwrap(), and, "{} AND {}", l, r);
+            assert_eq!(eval_binary_op(l, Or, r).unwrap(), or, "{} OR {}", l, r);
+        }
+        assert_eq!(eval_unary_op(UnaryOperator::Not, &n).unwrap(), Value::Null);
+
+        // Types that don't convert to each other compare as unknown
+        assert_eq!(eval_binary_op(&json!(1), Eq, &json!("abc")).unwrap(), Value::Null);
+        assert_eq!(eval_binary_op(&json!(1), Lt, &json!("abc")).unwrap(), Value::Null);
+        assert_eq!(eval_binary_op(&json!(1), Eq, &json!("1")).unwrap(), json!(true));
+        assert_eq!(eval_binary_op(&json!(true), Eq, &json!(1)).unwrap(), json!(true));
+        assert_eq!(eval_binary_op(&json!(false), Lt, &json!(1)).unwrap(), json!(true));
+
+        let row = serde_json::Map::from_iter([("a".to_string(), json!(null)), ("b".to_string(), json!(2))]);
+        let eval = |sql: &str| {
+            let sql = format!("SELECT * FROM t WHERE {}", sql);
+            let Ok(crate::sql::Statement::Select(s)) = crate::sql::Parser::parse(&sql) else {
+                panic!("bad test query: {}", sql);
+            };
+            let expr = s.where_clause.unwrap();
+            (evaluate(&expr, &row).unwrap(), matches_where(&expr, &row).unwrap())
+        };
+        assert_eq!(eval("a = a"), (Value::Null, false));
+        assert_eq!(eval("NOT (a = 1)"), (Value::Null, false));
+        assert_eq!(eval("b IN (1, a)"), (Value::Null, false));
+        assert_eq!(eval("b IN (2, a)"), (json!(true), true));
+        assert_eq!(eval("b NOT IN (1, a)"), (Value::Null, false));
+        assert_eq!(eval("a BETWEEN 1 AND 3"), (Value::Null, false));
+        assert_eq!(eval("b NOT BETWEEN a AND 1"), (json!(true), true));
+        assert_eq!(eval("a LIKE '%'"), (Value::Null, false));
+        assert_eq!(eval("a IS NULL"), (json!(true), true));
+    }
+
+    #[test]
+    fn test_like_pattern() {
+        let like = |s, p| simple_like_match(s, p, false, None);
+        assert!(like("Alice", "A%"));
+        assert!(like("Alice", "%ice"));
+        assert!(like("Alice", "%lic%"));
+        assert!(like("Alice", "A____"));
+        assert!(!like("Alice", "B%"));
+    }
+
+    #[test]
+    fn test_like_case_and_escape() {
+        // LIKE is case-sensitive, ILIKE is not
+        assert!(!simple_like_match("Alice", "alice", false, None));
+        assert!(!simple_like_match("Alice", "a%", false, None));
+        assert!(simple_like_match("Alice", "alice", true, None));
+        assert!(simple_like_match("ÄRGER", "är%", true, None));
+
+        assert!(simple_like_match("100%", "100\\%", false, Some('\\')));
+        assert!(!simple_like_match("1000", "100\\%", false, Some('\\')));
+        assert!(simple_like_match("a_b", "a!_b", false, Some('!')));
+        assert!(!simple_like_match("axb", "a!_b", false, Some('!')));
+        assert!(simple_like_match("a!b", "a!!b", false, Some('!')));
+        assert!(simple_like_match("ab", "!a!b", false, Some('!')));
+        // Without ESCAPE the backslash is an ordinary character
+        assert!(simple_like_match("100\\", "100\\", false, None));
+
+        assert!(like_match("100!", "100!!", false, Some('!')).unwrap());
+        assert!(matches!(
+            like_match("100!", "100!", false, Some('!')),
+            Err(EvalError::InvalidExpression(_))
+        ));
+        assert!(like_match("100!", "100!", false, None).unwrap());
+    }
+
+    #[test]
+    fn test_string_functions() {
+        let call = |name: &str, args: &[Value]| eval_function(name, args).unwrap();
+
+        assert_eq!(call("SUBSTR", &[json!("Alice"), json!(1), json!(3)]), json!("Ali"));
+        assert_eq!(call("substr", &[json!("Alice"), json!(3)]), json!("ice"));
+        assert_eq!(call("substr", &[json!("Alice"), json!(0), json!(2)]), json!("A"));
+        assert_eq!(call("substr", &[json!("Alice"), json!(9)]), json!(""));
+        assert_eq!(call("trim", &[json!("  hi \n")]), json!("hi"));
+        assert_eq!(call("ltrim", &[json!("xxhixx"), json!("x")]), json!("hixx"));
+        assert_eq!(call("rtrim", &[json!("xxhixx"), json!("x")]), json!("xxhi"));
+        assert_eq!(call("replace", &[json!("a-b-c"), json!("-"), json!("+")]), json!("a+b+c"));
+        assert_eq!(call("concat", &[json!("id:"), json!(7), json!(true)]), json!("id:7true"));
+        assert_eq!(call("lpad", &[json!("7"), json!(3), json!("0")]), json!("007"));
+        assert_eq!(call("rpad", &[json!("ab"), json!(5), json!("xy")]), json!("abxyx"));
+        assert_eq!(call("lpad", &[json!("Alice"), json!(2)]), json!("Al"));
+
+        // NULL in, NULL out
+        assert_eq!(call("substr", &[Value::Null, json!(1)]), Value::Null);
+        assert_eq!(call("concat", &[json!("a"), Value::Null]), Value::Null);
+
+        assert!(matches!(
+            eval_function("replace", &[json!("a"), json!("b")]),
+            Err(EvalError::InvalidExpression(_))
+        ));
+        assert!(eval_function("concat", &[]).is_err());
+        assert!(eval_function("substr", &[json!("a"), json!(1), json!(-1)]).is_err());
+    }
+
+    #[test]
+    fn test_math_functions() {
+        let call = |name: &str, args: &[Value]| eval_function(name, args).unwrap();
+
+        // Whole results of integer inputs stay integers, floats stay floats
+        assert_eq!(call("abs", &[json!(-3)]), json!(3));
+        assert_eq!(call("abs", &[json!(-2.5)]), json!(2.5));
+        assert_eq!(call("round", &[json!(2.5)]), json!(3.0));
+        assert_eq!(call("round", &[json!(7)]), json!(7));
+        assert_eq!(call("round", &[json!(19.987), json!(2)]), json!(19.99));
+        assert_eq!(call("round", &[json!(1250), json!(-2)]), json!(1300));
+        assert_eq!(call("ceil", &[json!(1.2)]), json!(2.0));
+        assert_eq!(call("floor", &[json!(-1.2)]), json!(-2.0));
+        assert_eq!(call("mod", &[json!(10), json!(3)]), json!(1));
+        assert_eq!(call("mod", &[json!(5.5), json!(2)]), json!(1.5));
+        assert_eq!(call("power", &[json!(2), json!(10)]), json!(1024));
+        assert_eq!(call("power", &[json!(2), json!(-1)]), json!(0.5));
+        assert_eq!(call("sqrt", &[json!(16)]), json!(4));
+        assert_eq!(call("sqrt", &[json!(2.25)]), json!(1.5));
+        assert_eq!(call("abs", &[Value::Null]), Value::Null);
+
+        assert!(matches!(
+            eval_function("mod", &[json!(1), json!(0)]),
+            Err(EvalError::DivisionByZero)
+        ));
+        assert!(eval_function("sqrt", &[json!(-1)]).is_err());
+        assert!(eval_function("power", &[json!(2)]).is_err());
+    }
+
+    #[test]
+    fn test_date_functions() {
+        let call = |name: &str, args: &[Value]| eval_function(name, args).unwrap();
+        let ts = json!("2024-01-31T10:30:00+00:00");
+
+        assert_eq!(call("date", std::slice::from_ref(&ts)), json!("2024-01-31"));
+        assert_eq!(call("extract", &[json!("year"), ts.clone()]), json!(2024));
+        assert_eq!(call("extract", &[json!("hour"), json!("2024-01-31 10:30:00")]), json!(10));
+        assert_eq!(call("date_add", &[ts.clone(), json!("1 month")]), json!("2024-02-29T10:30:00+00:00"));
+        assert_eq!(call("date_add", &[ts.clone(), json!(2), json!("hours")]), json!("2024-01-31T12:30:00+00:00"));
+        assert_eq!(call("date_sub", &[json!("2024-03-01"), json!("1 day")]), json!("2024-02-29"));
+        assert_eq!(call("date_add", &[json!("2024-03-01"), json!("1 hour")]), json!("2024-03-01T01:00:00+00:00"));
+        assert_eq!(call("date", &[Value::Null]), Value::Null);
+
+        assert!(eval_function("date", &[json!("yesterday")]).is_err());
+        assert!(eval_function("date_add", &[ts.clone(), json!("3 fortnights")]).is_err());
+        assert!(eval_function("extract", &[json!("century"), ts]).is_err());
+    }
+
+    #[test]
+    fn test_timestamp_comparison() {
+        // 01:00 at +02:00 is still the previous day in UTC
+        let early = json!("2024-01-01T01:00:00+02:00");
+        assert_eq!(compare_values(&early, &json!("2024-01-01")), Some(-1));
+        assert!(values_equal(&json!("2024-01-01"), &json!("2024-01-01T00:00:00Z")));
+
+        // Strings that aren't timestamps still compare as text
+        assert_eq!(compare_values(&json!("b"), &json!("a")), Some(1));
+        assert!(!values_equal(&json!("2024"), &json!("2024-01-01")));
+    }
+
+    #[test]
+    fn test_numeric_coercion() {
+        use BinaryOperator::*;
+        assert_eq!(eval_binary_op(&json!(30), Eq, &json!(30.0)).unwrap(), json!(true));
+        assert_eq!(eval_binary_op(&json!(30), Lt, &json!(30.5)).unwrap(), json!(true));
+        assert_eq!(eval_binary_op(&json!("90210"), Eq, &json!(90210)).unwrap(), json!(true));
+        assert_eq!(eval_binary_op(&json!(" 1e3 "), Gt, &json!(999)).unwrap(), json!(true));
+        assert_eq!(eval_binary_op(&json!(5), Lt, &json!("40")).unwrap(), json!(true));
+
+        // Large integers compare exactly rather than through f64
+        assert_eq!(compare_values(&json!(i64::MAX), &json!(i64::MAX - 1)), Some(1));
+
+        // Booleans and strings have no common type
+        assert_eq!(eval_binary_op(&json!(true), Eq, &json!("true")).unwrap(), Value::Null);
+        assert_eq!(eval_binary_op(&json!("x"), NotEq, &json!(1)).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let row = make_row();
+        let expr = Expr::BinaryOp {
+            left: Box::new(Expr::Column("age".into())),
+            op: BinaryOperator::Plus,
+            right: Box::new(Expr::Literal(LiteralValue::Integer(10))),
+        };
+        assert_eq!(evaluate(&expr, &row).unwrap(), json!(40));
+    }
+
+    #[test]
+    fn test_null_arithmetic() {
+        use BinaryOperator::*;
+        let n = json!(null);
+        for op in [Plus, Minus, Multiply, Divide, Modulo, Concat] {
+            assert_eq!(eval_binary_op(&n, op, &json!(2)).unwrap(), Value::Null, "NULL {:?} 2", op);
+            assert_eq!(eval_binary_op(&json!(2), op, &n).unwrap(), Value::Null, "2 {:?} NULL", op);
+            assert_eq!(eval_binary_op(&json!("1.50"), op, &n).unwrap(), Value::Null, "'1.50' {:?} NULL", op);
+        }
+        assert_eq!(eval_binary_op(&json!("a"), Concat, &n).unwrap(), Value::Null);
+        assert_eq!(eval_unary_op(UnaryOperator::Minus, &n).unwrap(), Value::Null);
+        assert!(matches!(eval_binary_op(&json!(1), Divide, &json!(0)), Err(EvalError::DivisionByZero)));
+
+        let row = serde_json::Map::from_iter([("age".to_string(), json!(null))]);
+        let expr = Expr::BinaryOp {
+            left: Box::new(Expr::Column("age".into())),
+            op: Plus,
+            right: Box::new(Expr::Literal(LiteralValue::Integer(1))),
+        };
+        assert_eq!(evaluate(&expr, &row).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_in_list() {
+        let row = make_row();
+        let expr = Expr::InList {
+            expr: Box::new(Expr::Column("name".into())),
+            list: vec![
+                Expr::Literal(LiteralValue::String("Alice".into())),
+                Expr::Literal(LiteralValue::String("Bob".into())),
+            ],
+            negated: false,
+        };
+        assert_eq!(evaluate(&expr, &row).unwrap(), json!(true));
+    }
+
+    #[test]
+    fn test_predicate_shares_subexpressions() {
+        let where_of = |sql: &str| match crate::sql::Parser::parse(sql).unwrap() {
+            crate::sql::Statement::Delete(d) => Predicate::new(d.where_clause.unwrap()),
+            _ => unreachable!(),
+        };
+
+        let predicate = where_of("DELETE FROM t WHERE (age + id) > 10 AND (age + id) < 40");
+        assert_eq!(predicate.shared.len(), 1);
+        let mut row = make_row();
+        assert!(predicate.matches(&mut row).unwrap());
+        assert_eq!(row, make_row());
+        row.insert("age".into(), json!(50));
+        assert!(!predicate.matches(&mut row).unwrap());
+
+        // Nested repeats share both levels
+        let predicate = where_of("DELETE FROM t WHERE (age + id) * 2 > 10 AND (age + id) * 2 < 40 OR age + id = 5");
+        assert_eq!(predicate.shared.len(), 2);
+        assert!(!predicate.matches(&mut make_row()).unwrap());
+
+        // Each now() gets its own value
+        let predicate = where_of("DELETE FROM t WHERE now() > '2000-01-01' AND now() > '2000-01-01'");
+        assert!(predicate.shared.is_empty());
+        assert!(predicate.matches(&mut make_row()).unwrap());
+    }
+
+    #[test]
+    fn test_id_generators() {
+        let row = make_row();
+        let ulid = Expr::Function { name: "gen_ulid".into(), args: vec![] };
+        let a = evaluate(&ulid, &row).unwrap();
+        let b = evaluate(&ulid, &row).unwrap();
+        assert_eq!(a.as_str().unwrap().len(), 26);
+        assert_ne!(a, b);
+
+        let uuid = Expr::Function { name: "UUID".into(), args: vec![] };
+        let id = evaluate(&uuid, &row).unwrap();
+        assert!(crate::catalog::DataType::Uuid.matches(&id));
+        assert_ne!(id, evaluate(&uuid, &row).unwrap());
+    }
+}
@@ -4,9 +4,12 @@
 //! representation for execution.
 
 mod ast;
+pub(crate) mod decimal;
+mod dialect;
 mod error;
+pub(crate) mod eval;
 mod parser;
 
 pub use ast::*;
-pub use error::{ParseError, ParseResult};
+pub use error::{EvalError, EvalResult, ParseError, ParseResult};
 pub use parser::Parser;
@@ -3,10 +3,12 @@
 //! Converts SQL strings to our internal AST using sqlparser.
 
 use sqlparser::ast as sp;
-use sqlparser::dialect::GenericDialect;
 use sqlparser::parser::Parser as SqlParser;
+use sqlparser::tokenizer::Token;
 
 use super::ast::*;
+use super::dialect::GitDbDialect;
+use super::decimal::{Decimal, MAX_PRECISION};
 use super::error::{ParseError, ParseResult};
 
 /// SQL parser for GitDB.
@@ -14,16 +16,23 @@ pub struct Parser;
 
 impl Parser {
     /// Parse a SQL string into a statement.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "parse", level = "debug", skip_all, err))]
     pub fn parse(sql: &str) -> ParseResult<Statement> {
         let sql = sql.trim();
         if sql.is_empty() {
             return Err(ParseError::EmptyQuery);
         }
 
-        // Handle special commands not supported by sqlparser
-        let upper = sql.to_uppercase();
+        // Handle special commands not supported by sqlparser. Comments are
+        // stripped first so they can't hide the command keyword.
+        let stripped = strip_comments(sql);
+        let command = stripped.trim().trim_end_matches(';').trim_end();
+        if command.is_empty() {
+            return Err(ParseError::EmptyQuery);
+        }
+        let upper = command.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase();
         if upper == "BEGIN" || upper == "BEGIN TRANSACTION" || upper == "START TRANSACTION" {
-            return Ok(Statement::Begin);
+            return Ok(Statement::Begin { isolation: None });
         }
         if upper == "COMMIT" {
             return Ok(Statement::Commit);
@@ -32,16 +41,25 @@ impl Parser {
             return Ok(Statement::Rollback);
         }
         if upper == "SHOW TABLES" {
-            return Ok(Statement::ShowTables);
+            return Ok(Statement::ShowTables { like: None });
+        }
+        if upper == "SHOW TRANSACTIONS" {
+            return Ok(Statement::ShowTransactions);
+        }
+        if upper == "VACUUM" || upper == "VACUUM FULL" {
+            return Ok(Statement::Vacuum { full: upper == "VACUUM FULL" });
         }
         if upper.starts_with("DESCRIBE ") || upper.starts_with("DESC ") {
-            let table = sql.split_whitespace().nth(1)
+            let table = command.split_whitespace().nth(1)
                 .ok_or_else(|| ParseError::MissingClause("table name".into()))?;
-            return Ok(Statement::Describe(table.to_string()));
+            let table = match table.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+                Some(quoted) => quoted.to_string(),
+                None => table.to_lowercase(),
+            };
+            return Ok(Statement::Describe(table));
         }
 
-        let dialect = GenericDialect {};
-        let statements = SqlParser::parse_sql(&dialect, sql)?;
+        let statements = Self::parse_sql(sql)?;
 
         if statements.is_empty() {
             return Err(ParseError::EmptyQuery);
@@ -55,9 +73,37 @@ impl Parser {
 
     /// Parse multiple SQL statements.
     pub fn parse_multi(sql: &str) -> ParseResult<Vec<Statement>> {
-        let dialect = GenericDialect {};
-        let statements = SqlParser::parse_sql(&dialect, sql)?;
-        statements.iter().map(Self::convert_statement).collect()
+        Ok(Self::parse_script(sql)?.into_iter().map(|(_, stmt)| stmt).collect())
+    }
+
+    /// Parse a script of statements, pairing each with its SQL text.
+    ///
+    /// Each statement is parsed like [`parse`](Self::parse), so commands
+    /// sqlparser doesn't know, such as VACUUM, work in scripts too. The text
+    /// is the statement as written, without comments, which is what error
+    /// messages use to point at the failing statement.
+    pub fn parse_script(sql: &str) -> ParseResult<Vec<(String, Statement)>> {
+        split_statements(&strip_comments(sql))
+            .into_iter()
+            .map(|text| {
+                let stmt = Self::parse(&text)?;
+                Ok((text, stmt))
+            })
+            .collect()
+    }
+
+    /// Parse a single expression, such as a stored DEFAULT or CHECK.
+    pub fn parse_expr(sql: &str) -> ParseResult<Expr> {
+        let dialect = GitDbDialect::default();
+        let mut parser = SqlParser::new(&dialect).try_with_sql(sql)?;
+        let expr = parser.parse_expr()?;
+        parser.expect_token(&Token::EOF)?;
+        Self::convert_expr(&expr)
+    }
+
+    /// Run sqlparser over the text, numbering `?` placeholders as `$1`, `$2`, ...
+    fn parse_sql(sql: &str) -> ParseResult<Vec<sp::Statement>> {
+        Ok(SqlParser::parse_sql(&GitDbDialect::default(), &number_placeholders(sql))?)
     }
 
     fn convert_statement(stmt: &sp::Statement) -> ParseResult<Statement> {
@@ -66,40 +112,134 @@ impl Parser {
             sp::Statement::Drop { object_type, names, if_exists, .. } => {
                 Self::convert_drop(object_type, names, *if_exists)
             }
+            sp::Statement::AlterTable { name, if_exists, operations, .. } => {
+                Self::convert_alter_table(name, *if_exists, operations)
+            }
             sp::Statement::Query(query) => Self::convert_query(query),
             sp::Statement::Insert(insert) => Self::convert_insert(insert),
             sp::Statement::Update { table, assignments, selection, .. } => {
                 Self::convert_update(table, assignments, selection)
             }
             sp::Statement::Delete(delete) => Self::convert_delete(delete),
-            sp::Statement::StartTransaction { .. } => Ok(Statement::Begin),
+            sp::Statement::StartTransaction { modes, .. } => Self::convert_begin(modes),
             sp::Statement::Commit { .. } => Ok(Statement::Commit),
             sp::Statement::Rollback { .. } => Ok(Statement::Rollback),
+            sp::Statement::ShowTables { show_options, .. } => {
+                if show_options.show_in.is_some() {
+                    return Err(ParseError::UnsupportedStatement("SHOW TABLES IN/FROM".into()));
+                }
+                Ok(Statement::ShowTables { like: Self::convert_show_filter(show_options)? })
+            }
+            sp::Statement::ShowColumns { show_options, .. } => {
+                let table = show_options.show_in.as_ref()
+                    .and_then(|show_in| show_in.parent_name.as_ref())
+                    .ok_or_else(|| ParseError::MissingClause("FROM table".into()))?;
+                Ok(Statement::ShowColumns {
+                    table: Self::extract_table_name(table)?,
+                    like: Self::convert_show_filter(show_options)?,
+                })
+            }
+            sp::Statement::ExplainTable { table_name, .. } => {
+                Ok(Statement::Describe(Self::extract_table_name(table_name)?))
+            }
+            sp::Statement::Explain { statement, analyze, .. } => {
+                let statement = Self::convert_statement(statement)?;
+                if !matches!(statement, Statement::Select(_)) {
+                    return Err(ParseError::UnsupportedStatement("only SELECT can be explained".into()));
+                }
+                Ok(Statement::Explain {
+                    statement: Box::new(statement),
+                    analyze: *analyze,
+                })
+            }
+            sp::Statement::SetVariable { local: false, hivevar: false, variables, value }
+                if variables.to_string().eq_ignore_ascii_case("AUTHOR") =>
+            {
+                Self::convert_set_author(value)
+            }
             other => Err(ParseError::UnsupportedStatement(format!("{:?}", other))),
         }
     }
 
+    /// `SET AUTHOR = 'Name <email>'`, in the form `git commit --author` takes.
+    fn convert_set_author(value: &[sp::Expr]) -> ParseResult<Statement> {
+        let [value] = value else {
+            return Err(ParseError::Syntax("SET AUTHOR takes a single value".into()));
+        };
+        if let sp::Expr::Identifier(ident) = value {
+            if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("DEFAULT") {
+                return Ok(Statement::SetAuthor(None));
+            }
+        }
+
+        let author = Self::extract_string_from_expr(value)?;
+        let parsed = author
+            .trim()
+            .strip_suffix('>')
+            .and_then(|rest| rest.split_once('<'))
+            .map(|(name, email)| (name.trim(), email.trim()))
+            .filter(|(name, email)| !name.is_empty() && !email.is_empty() && !email.contains(['<', '>']));
+        let (name, email) = parsed.ok_or_else(|| {
+            ParseError::Syntax(format!("author must look like 'Name <email>', got '{}'", author))
+        })?;
+
+        Ok(Statement::SetAuthor(Some(Author {
+            name: name.to_string(),
+            email: email.to_string(),
+        })))
+    }
+
     fn convert_create_table(create: &sp::CreateTable) -> ParseResult<Statement> {
         let name = Self::extract_table_name(&create.name)?;
         let columns = create.columns.iter()
             .map(Self::convert_column_def)
             .collect::<ParseResult<Vec<_>>>()?;
+        let constraints = create.constraints.iter()
+            .filter_map(|c| Self::convert_table_constraint(c).transpose())
+            .collect::<ParseResult<Vec<_>>>()?;
+
+        let mut key_strategy = None;
+        for option in &create.with_options {
+            match option {
+                sp::SqlOption::KeyValue { key, value } if key.value.eq_ignore_ascii_case("key_strategy") => {
+                    key_strategy = Some(Self::convert_key_strategy(value)?);
+                }
+                other => {
+                    return Err(ParseError::UnsupportedStatement(format!("table option {}", other)));
+                }
+            }
+        }
 
         Ok(Statement::CreateTable(CreateTable {
             name,
             columns,
+            constraints,
             if_not_exists: create.if_not_exists,
+            key_strategy,
         }))
     }
 
+    /// Convert the value of a `key_strategy` table option, quoted or not.
+    fn convert_key_strategy(value: &sp::Expr) -> ParseResult<KeyStrategy> {
+        let name = match value {
+            sp::Expr::Identifier(ident) => &ident.value,
+            sp::Expr::Value(v) => match &v.value {
+                sp::Value::SingleQuotedString(s) => s,
+                _ => return Err(ParseError::UnsupportedStatement(format!("key_strategy = {}", value))),
+            },
+            _ => return Err(ParseError::UnsupportedStatement(format!("key_strategy = {}", value))),
+        };
+        name.parse().map_err(ParseError::UnsupportedStatement)
+    }
+
     fn convert_column_def(col: &sp::ColumnDef) -> ParseResult<ColumnDef> {
         let data_type = Self::convert_data_type(&col.data_type)?;
         let constraints = col.options.iter()
-            .filter_map(|opt| Self::convert_column_option(&opt.option).transpose())
+            .filter_map(|opt| Self::convert_column_option(opt).transpose())
             .collect::<ParseResult<Vec<_>>>()?;
 
         Ok(ColumnDef {
-            name: col.name.value.clone(),
+            name: Self::ident_name(&col.name),
             data_type,
             constraints,
         })
@@ -123,17 +263,20 @@ impl Parser {
             sp::DataType::Float(_)
             | sp::DataType::Real
             | sp::DataType::Double(_)
-            | sp::DataType::DoublePrecision
-            | sp::DataType::Decimal(_)
-            | sp::DataType::Numeric(_) => Ok(SqlDataType::Float),
+            | sp::DataType::DoublePrecision => Ok(SqlDataType::Float),
+
+            sp::DataType::Decimal(info)
+            | sp::DataType::Dec(info)
+            | sp::DataType::Numeric(info) => Self::convert_decimal_type(info),
 
             sp::DataType::Boolean | sp::DataType::Bool => Ok(SqlDataType::Boolean),
 
             sp::DataType::JSON | sp::DataType::JSONB => Ok(SqlDataType::Json),
 
             sp::DataType::Timestamp(_, _)
-            | sp::DataType::Datetime(_)
-            | sp::DataType::Date => Ok(SqlDataType::Timestamp),
+            | sp::DataType::Datetime(_) => Ok(SqlDataType::Timestamp),
+
+            sp::DataType::Date => Ok(SqlDataType::Date),
 
             sp::DataType::Uuid => Ok(SqlDataType::Uuid),
 
@@ -141,8 +284,21 @@ impl Parser {
         }
     }
 
-    fn convert_column_option(opt: &sp::ColumnOption) -> ParseResult<Option<ColumnConstraint>> {
-        match opt {
+    /// DECIMAL(p, s); precision defaults to the maximum and scale to zero.
+    fn convert_decimal_type(info: &sp::ExactNumberInfo) -> ParseResult<SqlDataType> {
+        let (precision, scale) = match info {
+            sp::ExactNumberInfo::None => (u64::from(MAX_PRECISION), 0),
+            sp::ExactNumberInfo::Precision(p) => (*p, 0),
+            sp::ExactNumberInfo::PrecisionAndScale(p, s) => (*p, *s),
+        };
+        if precision == 0 || precision > u64::from(MAX_PRECISION) || scale > precision {
+            return Err(ParseError::UnsupportedDataType(format!("DECIMAL({}, {})", precision, scale)));
+        }
+        Ok(SqlDataType::Decimal { precision: precision as u8, scale: scale as u8 })
+    }
+
+    fn convert_column_option(opt: &sp::ColumnOptionDef) -> ParseResult<Option<ColumnConstraint>> {
+        match &opt.option {
             sp::ColumnOption::Null => Ok(None), // Nullable by default
             sp::ColumnOption::NotNull => Ok(Some(ColumnConstraint::NotNull)),
             sp::ColumnOption::Unique { is_primary, .. } => {
@@ -156,10 +312,85 @@ impl Parser {
                 let e = Self::convert_expr(expr)?;
                 Ok(Some(ColumnConstraint::Default(e)))
             }
+            sp::ColumnOption::Check(expr) => Ok(Some(ColumnConstraint::Check {
+                name: opt.name.as_ref().map(Self::ident_name),
+                expr: Self::convert_expr(expr)?,
+            })),
+            sp::ColumnOption::ForeignKey {
+                foreign_table,
+                referred_columns,
+                on_delete,
+                on_update,
+                ..
+            } => Ok(Some(ColumnConstraint::References {
+                name: opt.name.as_ref().map(Self::ident_name),
+                table: Self::extract_table_name(foreign_table)?,
+                columns: Self::ident_names(referred_columns),
+                on_delete: Self::convert_referential_action(*on_delete),
+                on_update: Self::convert_referential_action(*on_update),
+            })),
             _ => Ok(None), // Ignore other constraints for now
         }
     }
 
+    fn convert_table_constraint(
+        constraint: &sp::TableConstraint,
+    ) -> ParseResult<Option<TableConstraint>> {
+        match constraint {
+            sp::TableConstraint::Check { name, expr } => Ok(Some(TableConstraint::Check {
+                name: name.as_ref().map(Self::ident_name),
+                expr: Self::convert_expr(expr)?,
+            })),
+            sp::TableConstraint::PrimaryKey { columns, .. } => {
+                Ok(Some(TableConstraint::PrimaryKey(Self::ident_names(columns))))
+            }
+            sp::TableConstraint::Unique { name, columns, .. } => Ok(Some(TableConstraint::Unique {
+                name: name.as_ref().map(Self::ident_name),
+                columns: Self::ident_names(columns),
+            })),
+            sp::TableConstraint::ForeignKey {
+                name,
+                columns,
+                foreign_table,
+                referred_columns,
+                on_delete,
+                on_update,
+                ..
+            } => Ok(Some(TableConstraint::ForeignKey {
+                name: name.as_ref().map(Self::ident_name),
+                columns: Self::ident_names(columns),
+                references_table: Self::extract_table_name(foreign_table)?,
+                references_columns: Self::ident_names(referred_columns),
+                on_delete: Self::convert_referential_action(*on_delete),
+                on_update: Self::convert_referential_action(*on_update),
+            })),
+            other => Err(ParseError::UnsupportedConstraint(other.to_string())),
+        }
+    }
+
+    fn convert_referential_action(action: Option<sp::ReferentialAction>) -> ReferentialAction {
+        match action {
+            None | Some(sp::ReferentialAction::NoAction) => ReferentialAction::NoAction,
+            Some(sp::ReferentialAction::Restrict) => ReferentialAction::Restrict,
+            Some(sp::ReferentialAction::Cascade) => ReferentialAction::Cascade,
+            Some(sp::ReferentialAction::SetNull) => ReferentialAction::SetNull,
+            Some(sp::ReferentialAction::SetDefault) => ReferentialAction::SetDefault,
+        }
+    }
+
+    /// An identifier's name: folded to lower case, or kept exactly as
+    /// written when quoted.
+    fn ident_name(ident: &sp::Ident) -> String {
+        match ident.quote_style {
+            Some(_) => ident.value.clone(),
+            None => ident.value.to_lowercase(),
+        }
+    }
+
+    fn ident_names(idents: &[sp::Ident]) -> Vec<String> {
+        idents.iter().map(Self::ident_name).collect()
+    }
+
     fn convert_drop(
         object_type: &sp::ObjectType,
         names: &[sp::ObjectName],
@@ -182,7 +413,45 @@ impl Parser {
         }
     }
 
+    fn convert_alter_table(
+        name: &sp::ObjectName,
+        if_exists: bool,
+        operations: &[sp::AlterTableOperation],
+    ) -> ParseResult<Statement> {
+        let [operation] = operations else {
+            return Err(ParseError::UnsupportedStatement(
+                "ALTER TABLE takes exactly one operation".into(),
+            ));
+        };
+        let operation = match operation {
+            sp::AlterTableOperation::RenameTable { table_name } => {
+                AlterTableOperation::RenameTable(Self::extract_table_name(table_name)?)
+            }
+            sp::AlterTableOperation::RenameColumn { old_column_name, new_column_name } => {
+                AlterTableOperation::RenameColumn {
+                    old: Self::ident_name(old_column_name),
+                    new: Self::ident_name(new_column_name),
+                }
+            }
+            other => {
+                return Err(ParseError::UnsupportedStatement(format!(
+                    "ALTER TABLE {} not supported",
+                    other
+                )))
+            }
+        };
+        Ok(Statement::AlterTable(AlterTable {
+            name: Self::extract_table_name(name)?,
+            if_exists,
+            operation,
+        }))
+    }
+
     fn convert_query(query: &sp::Query) -> ParseResult<Statement> {
+        Self::convert_select(query).map(Statement::Select)
+    }
+
+    fn convert_select(query: &sp::Query) -> ParseResult<Select> {
         let body = &query.body;
         let select = match body.as_ref() {
             sp::SetExpr::Select(s) => s,
@@ -212,6 +481,15 @@ impl Parser {
             .map(Self::convert_expr)
             .transpose()?;
 
+        // GROUP BY
+        let group_by = Self::convert_group_by(&select.group_by)?;
+
+        // HAVING clause
+        let having = select.having
+            .as_ref()
+            .map(Self::convert_expr)
+            .transpose()?;
+
         // ORDER BY
         let order_by = query.order_by
             .as_ref()
@@ -224,49 +502,74 @@ impl Parser {
         // LIMIT
         let limit = query.limit
             .as_ref()
-            .and_then(|l| Self::expr_to_usize(l));
+            .map(|l| Self::convert_row_count(l, "LIMIT"))
+            .transpose()?;
 
         // OFFSET
         let offset = query.offset
             .as_ref()
-            .and_then(|o| Self::expr_to_usize(&o.value));
+            .map(|o| Self::convert_row_count(&o.value, "OFFSET"))
+            .transpose()?;
 
-        Ok(Statement::Select(Select {
+        Ok(Select {
             columns,
             from,
             where_clause,
+            group_by,
+            having,
             order_by,
             limit,
             offset,
-        }))
+        })
+    }
+
+    /// GROUP BY a list of columns; expressions and ROLLUP and the like
+    /// aren't supported.
+    fn convert_group_by(group_by: &sp::GroupByExpr) -> ParseResult<Vec<String>> {
+        let sp::GroupByExpr::Expressions(exprs, modifiers) = group_by else {
+            return Err(ParseError::UnsupportedStatement("GROUP BY ALL".into()));
+        };
+        if let Some(modifier) = modifiers.first() {
+            return Err(ParseError::UnsupportedStatement(format!("GROUP BY ... {}", modifier)));
+        }
+        exprs.iter()
+            .map(|expr| match Self::convert_expr(expr)? {
+                Expr::Column(column) => Ok(column),
+                _ => Err(ParseError::UnsupportedExpression(format!("GROUP BY expression: {}", expr))),
+            })
+            .collect()
     }
 
     fn convert_projection(items: &[sp::SelectItem]) -> ParseResult<Vec<SelectColumn>> {
         items.iter().map(|item| {
             match item {
                 sp::SelectItem::Wildcard(_) => Ok(SelectColumn::Wildcard),
-                sp::SelectItem::UnnamedExpr(expr) => {
-                    if let sp::Expr::Identifier(ident) = expr {
-                        Ok(SelectColumn::Column(ident.value.clone()))
-                    } else {
+                sp::SelectItem::UnnamedExpr(expr) => match expr {
+                    sp::Expr::Identifier(ident) => Ok(SelectColumn::Column(Self::ident_name(ident))),
+                    // table.column keeps its qualifier to pick it out of joined rows
+                    sp::Expr::CompoundIdentifier(parts) if parts.len() == 2 => Ok(SelectColumn::Column(
+                        format!("{}.{}", Self::ident_name(&parts[0]), Self::ident_name(&parts[1])),
+                    )),
+                    _ => {
                         let e = Self::convert_expr(expr)?;
                         Ok(SelectColumn::Expr { expr: e, alias: None })
                     }
-                }
+                },
                 sp::SelectItem::ExprWithAlias { expr, alias } => {
                     let e = Self::convert_expr(expr)?;
                     Ok(SelectColumn::Expr {
                         expr: e,
-                        alias: Some(alias.value.clone()),
+                        alias: Some(Self::ident_name(alias)),
                     })
                 }
-                sp::SelectItem::QualifiedWildcard(name, _) => {
-                    // table.* - we treat as wildcard for now
-                    Err(ParseError::UnsupportedExpression(format!(
-                        "Qualified wildcard: {:?}",
-                        name
-                    )))
-                }
+                sp::SelectItem::QualifiedWildcard(kind, _) => match kind {
+                    sp::SelectItemQualifiedWildcardKind::ObjectName(name) => {
+                        Ok(SelectColumn::QualifiedWildcard(Self::extract_table_name(name)?))
+                    }
+                    sp::SelectItemQualifiedWildcardKind::Expr(expr) => Err(
+                        ParseError::UnsupportedExpression(format!("Wildcard on expression: {}", expr)),
+                    ),
+                },
             }
         }).collect()
     }
@@ -284,7 +587,8 @@ impl Parser {
 
     fn convert_order_by_expr(expr: &sp::OrderByExpr) -> ParseResult<OrderBy> {
         let column = match &expr.expr {
-            sp::Expr::Identifier(id) => id.value.clone(),
+            sp::Expr::Identifier(id) => Self::ident_name(id),
+            sp::Expr::CompoundIdentifier(parts) => Self::qualified_name(parts)?,
             other => {
                 return Err(ParseError::UnsupportedExpression(format!(
                     "ORDER BY expression: {:?}",
@@ -302,7 +606,7 @@ impl Parser {
         let columns = if insert.columns.is_empty() {
             None
         } else {
-            Some(insert.columns.iter().map(|c| c.value.clone()).collect())
+            Some(insert.columns.iter().map(Self::ident_name).collect())
         };
 
         let values = match insert.source.as_ref().map(|s| s.body.as_ref()) {
@@ -310,7 +614,7 @@ impl Parser {
                 rows.iter()
                     .map(|row| {
                         row.iter()
-                            .map(Self::convert_expr)
+                            .map(Self::convert_insert_value)
                             .collect::<ParseResult<Vec<_>>>()
                     })
                     .collect::<ParseResult<Vec<_>>>()?
@@ -322,19 +626,42 @@ impl Parser {
             }
         };
 
+        let on_conflict = match insert.or {
+            None => None,
+            Some(sp::SqliteOnConflict::Ignore) => Some(InsertConflict::Ignore),
+            Some(sp::SqliteOnConflict::Replace) => Some(InsertConflict::Replace),
+            Some(other) => {
+                return Err(ParseError::UnsupportedStatement(format!("INSERT {}", other)))
+            }
+        };
+
         Ok(Statement::Insert(Insert {
             table,
             columns,
             values,
+            on_conflict,
         }))
     }
 
+    /// Convert an INSERT value, where a bare `DEFAULT` asks for the column's
+    /// default. sqlparser reads it as a column name.
+    fn convert_insert_value(expr: &sp::Expr) -> ParseResult<Expr> {
+        match expr {
+            sp::Expr::Identifier(ident)
+                if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("DEFAULT") =>
+            {
+                Ok(Expr::Default)
+            }
+            _ => Self::convert_expr(expr),
+        }
+    }
+
     fn convert_update(
         table: &sp::TableWithJoins,
         assignments: &[sp::Assignment],
         selection: &Option<sp::Expr>,
     ) -> ParseResult<Statement> {
-        let table_name = Self::extract_from_table(table)?;
+        let table_name = Self::extract_target_table(table)?;
 
         let assigns = assignments.iter()
             .map(|a| {
@@ -358,7 +685,7 @@ impl Parser {
             sp::AssignmentTarget::ColumnName(parts) => {
                 // ObjectName has .0 field which is Vec<ObjectNamePart>
                 Ok(parts.0.iter()
-                    .map(|p| p.as_ident().map(|id| id.value.clone()).unwrap_or_else(|| p.to_string()))
+                    .map(|p| p.as_ident().map(Self::ident_name).unwrap_or_else(|| p.to_string()))
                     .collect::<Vec<_>>()
                     .join("."))
             }
@@ -366,7 +693,7 @@ impl Parser {
                 // Tuple contains Vec<ObjectName>
                 Ok(parts.iter()
                     .flat_map(|obj| obj.0.iter())
-                    .map(|p| p.as_ident().map(|id| id.value.clone()).unwrap_or_else(|| p.to_string()))
+                    .map(|p| p.as_ident().map(Self::ident_name).unwrap_or_else(|| p.to_string()))
                     .collect::<Vec<_>>()
                     .join("."))
             }
@@ -386,27 +713,56 @@ impl Parser {
             ));
         }
 
-        let table = Self::extract_from_table(&tables[0])?;
+        let table = Self::extract_target_table(&tables[0])?;
         let where_clause = delete.selection.as_ref().map(Self::convert_expr).transpose()?;
 
         Ok(Statement::Delete(Delete { table, where_clause }))
     }
 
+    fn convert_begin(modes: &[sp::TransactionMode]) -> ParseResult<Statement> {
+        let mut isolation = None;
+        for mode in modes {
+            match mode {
+                sp::TransactionMode::IsolationLevel(level) => {
+                    isolation = Some(match level {
+                        sp::TransactionIsolationLevel::ReadUncommitted => TransactionIsolation::ReadUncommitted,
+                        sp::TransactionIsolationLevel::ReadCommitted => TransactionIsolation::ReadCommitted,
+                        sp::TransactionIsolationLevel::RepeatableRead => TransactionIsolation::RepeatableRead,
+                        sp::TransactionIsolationLevel::Serializable => TransactionIsolation::Serializable,
+                        sp::TransactionIsolationLevel::Snapshot => TransactionIsolation::Snapshot,
+                    });
+                }
+                sp::TransactionMode::AccessMode(sp::TransactionAccessMode::ReadWrite) => {}
+                sp::TransactionMode::AccessMode(sp::TransactionAccessMode::ReadOnly) => {
+                    return Err(ParseError::UnsupportedStatement("READ ONLY transactions".into()));
+                }
+            }
+        }
+        Ok(Statement::Begin { isolation })
+    }
+
     fn convert_expr(expr: &sp::Expr) -> ParseResult<Expr> {
         match expr {
-            sp::Expr::Identifier(id) => Ok(Expr::Column(id.value.clone())),
+            sp::Expr::Identifier(id) => Ok(Expr::Column(Self::ident_name(id))),
 
-            sp::Expr::CompoundIdentifier(parts) => {
-                // table.column - just use column for now
-                let col = parts.last()
-                    .map(|p| p.value.clone())
-                    .ok_or_else(|| ParseError::InvalidIdentifier("empty compound identifier".into()))?;
-                Ok(Expr::Column(col))
-            }
+            sp::Expr::CompoundIdentifier(parts) => Ok(Expr::Column(Self::qualified_name(parts)?)),
 
             sp::Expr::Value(v) => Ok(Expr::Literal(Self::convert_value(v)?)),
 
+            sp::Expr::BinaryOp { left, op: op @ (sp::BinaryOperator::Arrow | sp::BinaryOperator::LongArrow), right } => {
+                Ok(Expr::JsonAccess {
+                    expr: Box::new(Self::convert_expr(left)?),
+                    path: Self::convert_json_path(right)?,
+                    as_text: *op == sp::BinaryOperator::LongArrow,
+                })
+            }
+
             sp::Expr::BinaryOp { left, op, right } => {
+                // `0.3 - 0.2` is exactly 0.1, not what two floats subtract to
+                if let Some(exact) = Self::fold_decimal(expr).filter(|d| d.scale() > 0) {
+                    let digits = sp::Value::Number(exact.to_string(), false);
+                    return Ok(Expr::Literal(Self::convert_raw_value(&digits)?));
+                }
                 let l = Self::convert_expr(left)?;
                 let r = Self::convert_expr(right)?;
                 let o = Self::convert_binary_op(op)?;
@@ -418,6 +774,13 @@ impl Parser {
             }
 
             sp::Expr::UnaryOp { op, expr } => {
+                // Fold negative numbers into the literal, so `-5` is a constant
+                if let (sp::UnaryOperator::Minus, sp::Expr::Value(v)) = (op, expr.as_ref()) {
+                    if let sp::Value::Number(digits, long) = &v.value {
+                        let negated = sp::Value::Number(format!("-{}", digits), *long);
+                        return Ok(Expr::Literal(Self::convert_raw_value(&negated)?));
+                    }
+                }
                 let e = Self::convert_expr(expr)?;
                 let o = Self::convert_unary_op(op)?;
                 Ok(Expr::UnaryOp {
@@ -464,14 +827,12 @@ impl Parser {
                 })
             }
 
-            sp::Expr::Like { expr, pattern, negated, .. } => {
-                let e = Self::convert_expr(expr)?;
-                let pat = Self::extract_string_from_expr(pattern)?;
-                Ok(Expr::Like {
-                    expr: Box::new(e),
-                    pattern: pat,
-                    negated: *negated,
-                })
+            sp::Expr::Like { expr, pattern, negated, escape_char, .. } => {
+                Self::convert_like(expr, pattern, *negated, false, escape_char.as_deref())
+            }
+
+            sp::Expr::ILike { expr, pattern, negated, escape_char, .. } => {
+                Self::convert_like(expr, pattern, *negated, true, escape_char.as_deref())
             }
 
             sp::Expr::Function(f) => {
@@ -499,19 +860,115 @@ impl Parser {
                 Ok(Expr::Nested(Box::new(e)))
             }
 
+            // SUBSTRING(s FROM a FOR b) has its own syntax; treat it as SUBSTR(s, a, b)
+            sp::Expr::Substring { expr, substring_from, substring_for, .. } => {
+                let mut args = vec![Self::convert_expr(expr)?];
+                args.push(match substring_from {
+                    Some(from) => Self::convert_expr(from)?,
+                    None => Expr::Literal(LiteralValue::Integer(1)),
+                });
+                if let Some(len) = substring_for {
+                    args.push(Self::convert_expr(len)?);
+                }
+                Ok(Expr::Function { name: "substr".into(), args })
+            }
+
+            // TRIM([LEADING | TRAILING | BOTH] [chars FROM] s) becomes LTRIM/RTRIM/TRIM(s, chars)
+            sp::Expr::Trim { expr, trim_where, trim_what, trim_characters } => {
+                let name = match trim_where {
+                    Some(sp::TrimWhereField::Leading) => "ltrim",
+                    Some(sp::TrimWhereField::Trailing) => "rtrim",
+                    _ => "trim",
+                };
+                let mut args = vec![Self::convert_expr(expr)?];
+                if let Some(what) = trim_what {
+                    args.push(Self::convert_expr(what)?);
+                }
+                for chars in trim_characters.iter().flatten() {
+                    args.push(Self::convert_expr(chars)?);
+                }
+                Ok(Expr::Function { name: name.into(), args })
+            }
+
+            // EXTRACT(YEAR FROM ts) becomes extract('year', ts)
+            sp::Expr::Extract { field, expr, .. } => Ok(Expr::Function {
+                name: "extract".into(),
+                args: vec![
+                    Expr::Literal(LiteralValue::String(field.to_string().to_lowercase())),
+                    Self::convert_expr(expr)?,
+                ],
+            }),
+
+            // INTERVAL 3 DAY becomes interval(3, 'day'), which evaluates to '3 day'
+            sp::Expr::Interval(interval) => {
+                let mut args = vec![Self::convert_expr(&interval.value)?];
+                if let Some(field) = &interval.leading_field {
+                    args.push(Expr::Literal(LiteralValue::String(field.to_string().to_lowercase())));
+                }
+                Ok(Expr::Function { name: "interval".into(), args })
+            }
+
+            sp::Expr::Ceil { expr, field } => Self::convert_ceil_floor("CEIL", expr, field),
+            sp::Expr::Floor { expr, field } => Self::convert_ceil_floor("FLOOR", expr, field),
+
+            sp::Expr::Subquery(query) => {
+                let select = Self::convert_select(query)?;
+                Ok(Expr::Subquery(Box::new(select)))
+            }
+
+            sp::Expr::InSubquery { expr, subquery, negated } => {
+                let e = Self::convert_expr(expr)?;
+                let select = Self::convert_select(subquery)?;
+                Ok(Expr::InSubquery {
+                    expr: Box::new(e),
+                    subquery: Box::new(select),
+                    negated: *negated,
+                })
+            }
+
+            sp::Expr::Exists { subquery, negated } => Ok(Expr::Exists {
+                subquery: Box::new(Self::convert_select(subquery)?),
+                negated: *negated,
+            }),
+
             other => Err(ParseError::UnsupportedExpression(format!("{:?}", other))),
         }
     }
 
+    /// CEIL/FLOOR have their own syntax for `CEIL(x TO DAY)` and `CEIL(x, scale)`.
+    fn convert_ceil_floor(
+        name: &str,
+        expr: &sp::Expr,
+        field: &sp::CeilFloorKind,
+    ) -> ParseResult<Expr> {
+        let mut args = vec![Self::convert_expr(expr)?];
+        match field {
+            sp::CeilFloorKind::DateTimeField(sp::DateTimeField::NoDateTime) => {}
+            sp::CeilFloorKind::Scale(scale) => args.push(Expr::Literal(Self::convert_raw_value(scale)?)),
+            sp::CeilFloorKind::DateTimeField(other) => {
+                return Err(ParseError::UnsupportedExpression(format!("{} TO {}", name, other)))
+            }
+        }
+        Ok(Expr::Function { name: name.into(), args })
+    }
+
     fn convert_value(v: &sp::ValueWithSpan) -> ParseResult<LiteralValue> {
-        match &v.value {
+        Self::convert_raw_value(&v.value)
+    }
+
+    fn convert_raw_value(v: &sp::Value) -> ParseResult<LiteralValue> {
+        match v {
             sp::Value::Null => Ok(LiteralValue::Null),
             sp::Value::Boolean(b) => Ok(LiteralValue::Boolean(*b)),
             sp::Value::Number(s, _) => {
                 if let Ok(i) = s.parse::<i64>() {
                     Ok(LiteralValue::Integer(i))
                 } else if let Ok(f) = s.parse::<f64>() {
-                    Ok(LiteralValue::Float(f))
+                    // Keep digits a float would lose as a decimal string
+                    match (Decimal::parse(s), Decimal::parse(&f.to_string())) {
+                        (Some(exact), Some(float)) if exact != float => Ok(LiteralValue::String(s.clone())),
+                        _ => Ok(LiteralValue::Float(f)),
+                    }
                 } else {
                     Err(ParseError::UnsupportedExpression(format!("Invalid number: {}", s)))
                 }
@@ -522,6 +979,48 @@ impl Parser {
         }
     }
 
+    fn convert_like(
+        expr: &sp::Expr,
+        pattern: &sp::Expr,
+        negated: bool,
+        case_insensitive: bool,
+        escape_char: Option<&str>,
+    ) -> ParseResult<Expr> {
+        let escape_char = escape_char
+            .map(|e| {
+                let mut chars = e.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(c),
+                    _ => Err(ParseError::UnsupportedExpression(format!(
+                        "ESCAPE must be a single character, got '{}'",
+                        e
+                    ))),
+                }
+            })
+            .transpose()?;
+
+        Ok(Expr::Like {
+            expr: Box::new(Self::convert_expr(expr)?),
+            pattern: Self::extract_string_from_expr(pattern)?,
+            negated,
+            case_insensitive,
+            escape_char,
+        })
+    }
+
+    /// The right side of `->`/`->>`: a string key or an integer index.
+    fn convert_json_path(expr: &sp::Expr) -> ParseResult<JsonPath> {
+        match Self::convert_expr(expr)? {
+            Expr::Literal(LiteralValue::String(key)) => Ok(JsonPath::Key(key)),
+            Expr::Literal(LiteralValue::Integer(i)) => Ok(JsonPath::Index(i)),
+            Expr::UnaryOp { op: UnaryOperator::Minus, expr } => match *expr {
+                Expr::Literal(LiteralValue::Integer(i)) => Ok(JsonPath::Index(-i)),
+                other => Err(ParseError::UnsupportedExpression(format!("JSON path -{}", other))),
+            },
+            other => Err(ParseError::UnsupportedExpression(format!("JSON path {}", other))),
+        }
+    }
+
     fn extract_string_from_expr(expr: &sp::Expr) -> ParseResult<String> {
         match expr {
             sp::Expr::Value(v) => match &v.value {
@@ -568,10 +1067,27 @@ impl Parser {
         }
     }
 
+    /// The pattern of a SHOW statement's LIKE filter, the only filter
+    /// supported.
+    fn convert_show_filter(options: &sp::ShowStatementOptions) -> ParseResult<Option<String>> {
+        if options.starts_with.is_some() || options.limit.is_some() || options.limit_from.is_some() {
+            return Err(ParseError::UnsupportedStatement("SHOW ... STARTS WITH/LIMIT".into()));
+        }
+        match &options.filter_position {
+            None => Ok(None),
+            Some(sp::ShowStatementFilterPosition::Infix(filter) | sp::ShowStatementFilterPosition::Suffix(filter)) => {
+                match filter {
+                    sp::ShowStatementFilter::Like(pattern) => Ok(Some(pattern.clone())),
+                    other => Err(ParseError::UnsupportedStatement(format!("SHOW ... {}", other))),
+                }
+            }
+        }
+    }
+
     fn extract_table_name(name: &sp::ObjectName) -> ParseResult<String> {
         // Use just the table name, ignoring schema
         name.0.last()
-            .map(|i| i.as_ident().map(|id| id.value.clone()).unwrap_or_else(|| i.to_string()))
+            .map(|i| i.as_ident().map(Self::ident_name).unwrap_or_else(|| i.to_string()))
             .ok_or_else(|| ParseError::InvalidIdentifier("empty table name".into()))
     }
 
@@ -584,9 +1100,85 @@ impl Parser {
         }
     }
 
-    fn extract_from_table(from: &sp::TableWithJoins) -> ParseResult<String> {
-        match &from.relation {
-            sp::TableFactor::Table { name, .. } => Self::extract_table_name(name),
+    /// `table.column`, the name a joined row gives the column; a schema in
+    /// front of the table is dropped.
+    fn qualified_name(parts: &[sp::Ident]) -> ParseResult<String> {
+        match parts {
+            [] => Err(ParseError::InvalidIdentifier("empty compound identifier".into())),
+            [column] => Ok(Self::ident_name(column)),
+            [.., table, column] => Ok(format!("{}.{}", Self::ident_name(table), Self::ident_name(column))),
+        }
+    }
+
+    fn extract_from_table(from: &sp::TableWithJoins) -> ParseResult<FromSource> {
+        if from.joins.is_empty() {
+            return Self::extract_table_factor(&from.relation);
+        }
+        let mut source = Self::extract_joined_factor(&from.relation)?;
+        for join in &from.joins {
+            let (join_type, constraint) = match &join.join_operator {
+                sp::JoinOperator::Join(c) | sp::JoinOperator::Inner(c) => (JoinType::Inner, c),
+                sp::JoinOperator::Left(c) | sp::JoinOperator::LeftOuter(c) => (JoinType::Left, c),
+                sp::JoinOperator::Right(c) | sp::JoinOperator::RightOuter(c) => (JoinType::Right, c),
+                sp::JoinOperator::FullOuter(c) => (JoinType::Full, c),
+                sp::JoinOperator::CrossJoin => (JoinType::Cross, &sp::JoinConstraint::None),
+                other => {
+                    return Err(ParseError::UnsupportedStatement(format!("Unsupported join: {:?}", other)))
+                }
+            };
+            let on = match constraint {
+                sp::JoinConstraint::On(expr) => Some(Self::convert_expr(expr)?),
+                sp::JoinConstraint::None => None,
+                sp::JoinConstraint::Using(_) => {
+                    return Err(ParseError::UnsupportedStatement("JOIN ... USING not supported, use ON".into()))
+                }
+                sp::JoinConstraint::Natural => {
+                    return Err(ParseError::UnsupportedStatement("NATURAL JOIN not supported, use ON".into()))
+                }
+            };
+            source = FromSource::Join {
+                left: Box::new(source),
+                right: Box::new(Self::extract_joined_factor(&join.relation)?),
+                join_type,
+                on,
+            };
+        }
+        Ok(source)
+    }
+
+    /// One side of a join. Joined rows are qualified by table name, so a
+    /// table alias would leave its columns out of reach.
+    fn extract_joined_factor(relation: &sp::TableFactor) -> ParseResult<FromSource> {
+        if let sp::TableFactor::Table { alias: Some(alias), .. } = relation {
+            return Err(ParseError::UnsupportedStatement(format!(
+                "table alias '{}' in JOIN not supported",
+                alias.name.value
+            )));
+        }
+        Self::extract_table_factor(relation)
+    }
+
+    fn extract_table_factor(relation: &sp::TableFactor) -> ParseResult<FromSource> {
+        match relation {
+            sp::TableFactor::Table { name, .. } => Self::extract_table_name(name).map(FromSource::Table),
+            sp::TableFactor::Derived { lateral: false, subquery, alias } => {
+                let alias = match alias {
+                    Some(alias) if alias.columns.is_empty() => Self::ident_name(&alias.name),
+                    Some(alias) => {
+                        return Err(ParseError::UnsupportedStatement(format!(
+                            "column list on derived table '{}' not supported",
+                            alias.name.value
+                        )))
+                    }
+                    None => {
+                        return Err(ParseError::UnsupportedStatement(
+                            "derived table requires an alias".into(),
+                        ))
+                    }
+                };
+                let select = Self::convert_select(subquery)?;
+                Ok(FromSource::Derived(Box::new(select), alias))
+            }
             other => Err(ParseError::UnsupportedStatement(format!(
                 "Unsupported FROM clause: {:?}",
                 other
@@ -594,17 +1186,224 @@ impl Parser {
         }
     }
 
-    fn expr_to_usize(expr: &sp::Expr) -> Option<usize> {
+    /// The table an UPDATE or DELETE writes to, which can't be derived.
+    fn extract_target_table(from: &sp::TableWithJoins) -> ParseResult<String> {
+        match Self::extract_from_table(from)? {
+            FromSource::Table(name) => Ok(name),
+            FromSource::Derived(_, alias) => Err(ParseError::UnsupportedStatement(format!(
+                "cannot modify derived table '{}'",
+                alias
+            ))),
+            FromSource::Join { .. } => Err(ParseError::UnsupportedStatement("cannot modify a join".into())),
+        }
+    }
+
+    /// Convert a LIMIT or OFFSET expression: a placeholder, or a constant
+    /// integer expression that folds to a non-negative count.
+    fn convert_row_count(expr: &sp::Expr, clause: &str) -> ParseResult<RowCount> {
+        if let sp::Expr::Value(v) = expr {
+            if let sp::Value::Placeholder(p) = &v.value {
+                return p.strip_prefix('$')
+                    .and_then(|n| n.parse().ok())
+                    .filter(|&n| n > 0)
+                    .map(RowCount::Parameter)
+                    .ok_or_else(|| ParseError::InvalidRowCount(format!("{} {}: unsupported placeholder", clause, p)));
+            }
+        }
+
+        let value = Self::fold_integer(expr).ok_or_else(|| {
+            ParseError::InvalidRowCount(format!("{} {}: expected a constant integer", clause, expr))
+        })?;
+        usize::try_from(value)
+            .map(RowCount::Count)
+            .map_err(|_| ParseError::InvalidRowCount(format!("{} {}: must not be negative", clause, expr)))
+    }
+
+    /// Evaluate exact `+`, `-` and `*` over numeric literals as decimals, or
+    /// `None` if the expression isn't constant or overflows.
+    fn fold_decimal(expr: &sp::Expr) -> Option<Decimal> {
+        match expr {
+            sp::Expr::Value(v) => match &v.value {
+                sp::Value::Number(s, _) => Decimal::parse(s),
+                _ => None,
+            },
+            sp::Expr::Nested(inner) => Self::fold_decimal(inner),
+            sp::Expr::UnaryOp { op: sp::UnaryOperator::Plus, expr } => Self::fold_decimal(expr),
+            sp::Expr::UnaryOp { op: sp::UnaryOperator::Minus, expr } => Some(Self::fold_decimal(expr)?.neg()),
+            sp::Expr::BinaryOp { left, op, right } => {
+                let (l, r) = (Self::fold_decimal(left)?, Self::fold_decimal(right)?);
+                match op {
+                    sp::BinaryOperator::Plus => l.checked_add(r),
+                    sp::BinaryOperator::Minus => l.checked_sub(r),
+                    sp::BinaryOperator::Multiply => l.checked_mul(r),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Evaluate integer arithmetic over literals, or `None` if the expression
+    /// isn't constant or overflows.
+    fn fold_integer(expr: &sp::Expr) -> Option<i64> {
         match expr {
             sp::Expr::Value(v) => match &v.value {
                 sp::Value::Number(s, _) => s.parse().ok(),
                 _ => None,
             },
+            sp::Expr::Nested(inner) => Self::fold_integer(inner),
+            sp::Expr::UnaryOp { op: sp::UnaryOperator::Plus, expr } => Self::fold_integer(expr),
+            sp::Expr::UnaryOp { op: sp::UnaryOperator::Minus, expr } => {
+                Self::fold_integer(expr)?.checked_neg()
+            }
+            sp::Expr::BinaryOp { left, op, right } => {
+                let (l, r) = (Self::fold_integer(left)?, Self::fold_integer(right)?);
+                match op {
+                    sp::BinaryOperator::Plus => l.checked_add(r),
+                    sp::BinaryOperator::Minus => l.checked_sub(r),
+                    sp::BinaryOperator::Multiply => l.checked_mul(r),
+                    sp::BinaryOperator::Divide => l.checked_div(r),
+                    sp::BinaryOperator::Modulo => l.checked_rem(r),
+                    _ => None,
+                }
+            }
             _ => None,
         }
     }
 }
 
+/// Number `?` placeholders left to right as `$1`, `$2`, ..., leaving quoted
+/// text and comments untouched.
+fn number_placeholders(sql: &str) -> String {
+    if !sql.contains('?') {
+        return sql.to_string();
+    }
+
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut quote: Option<char> = None;
+    let mut next = 0;
+
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            out.push(c);
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => {
+                quote = Some(c);
+                out.push(c);
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                out.push(c);
+                for c in chars.by_ref() {
+                    out.push(c);
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                out.push(c);
+                out.push(chars.next().unwrap_or_default());
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    out.push(c);
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            '?' => {
+                next += 1;
+                out.push_str(&format!("${}", next));
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Replace SQL comments with whitespace, leaving quoted text untouched.
+fn strip_comments(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            out.push(c);
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => {
+                quote = Some(c);
+                out.push(c);
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                // Line comment runs to end of line
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                out.push('\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                out.push(' ');
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Split a script on the semicolons that end its statements, dropping empty
+/// ones. Comments must already be stripped, see [`strip_comments`].
+fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+
+    for c in sql.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == ';' => {
+                statements.push(std::mem::take(&mut current));
+                continue;
+            }
+            None => {}
+        }
+        current.push(c);
+    }
+    statements.push(current);
+
+    statements
+        .into_iter()
+        .map(|statement| statement.trim().to_string())
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -643,6 +1442,113 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_key_strategy() {
+        let key_strategy = |sql: &str| match Parser::parse(sql) {
+            Ok(Statement::CreateTable(ct)) => Ok(ct.key_strategy),
+            Ok(other) => panic!("Expected CreateTable, got {:?}", other),
+            Err(e) => Err(e),
+        };
+
+        assert_eq!(key_strategy("CREATE TABLE t (a TEXT)").unwrap(), None);
+        assert_eq!(
+            key_strategy("CREATE TABLE t (a TEXT) WITH (key_strategy = 'sequential')").unwrap(),
+            Some(KeyStrategy::Sequential)
+        );
+        assert_eq!(
+            key_strategy("CREATE TABLE t (a TEXT) WITH (KEY_STRATEGY = uuid_v7)").unwrap(),
+            Some(KeyStrategy::UuidV7)
+        );
+        assert!(key_strategy("CREATE TABLE t (a TEXT) WITH (key_strategy = 'serial')").is_err());
+        assert!(key_strategy("CREATE TABLE t (a TEXT) WITH (fillfactor = 70)").is_err());
+    }
+
+    #[test]
+    fn test_parse_check_constraints() {
+        let sql = "CREATE TABLE people (age INTEGER CHECK (age >= 0), \
+                   lo INTEGER, hi INTEGER, CONSTRAINT lo_le_hi CHECK (lo <= hi))";
+        let stmt = Parser::parse(sql).unwrap();
+
+        match stmt {
+            Statement::CreateTable(ct) => {
+                assert!(matches!(
+                    &ct.columns[0].constraints[0],
+                    ColumnConstraint::Check { name: None, .. }
+                ));
+                assert_eq!(ct.constraints.len(), 1);
+                match &ct.constraints[0] {
+                    TableConstraint::Check { name, expr } => {
+                        assert_eq!(name.as_deref(), Some("lo_le_hi"));
+                        assert_eq!(expr.to_string(), "lo <= hi");
+                    }
+                    other => panic!("Expected Check, got {:?}", other),
+                }
+            }
+            _ => panic!("Expected CreateTable"),
+        }
+    }
+
+    #[test]
+    fn test_parse_composite_primary_key() {
+        let sql = "CREATE TABLE members (tenant_id TEXT, id INTEGER, PRIMARY KEY (tenant_id, id))";
+        let stmt = Parser::parse(sql).unwrap();
+
+        match stmt {
+            Statement::CreateTable(ct) => {
+                assert_eq!(
+                    ct.constraints,
+                    vec![TableConstraint::PrimaryKey(vec!["tenant_id".into(), "id".into()])]
+                );
+            }
+            _ => panic!("Expected CreateTable"),
+        }
+    }
+
+    #[test]
+    fn test_parse_unique_and_foreign_keys() {
+        let sql = "CREATE TABLE orders (id INTEGER PRIMARY KEY, \
+                   user_id INTEGER REFERENCES users ON DELETE CASCADE, sku TEXT, \
+                   UNIQUE (user_id, sku), \
+                   CONSTRAINT orders_sku_fkey FOREIGN KEY (sku) REFERENCES products (code))";
+        let stmt = Parser::parse(sql).unwrap();
+
+        match stmt {
+            Statement::CreateTable(ct) => {
+                assert_eq!(
+                    ct.columns[1].constraints,
+                    vec![ColumnConstraint::References {
+                        name: None,
+                        table: "users".into(),
+                        columns: vec![],
+                        on_delete: ReferentialAction::Cascade,
+                        on_update: ReferentialAction::NoAction,
+                    }]
+                );
+                assert_eq!(
+                    ct.constraints,
+                    vec![
+                        TableConstraint::Unique {
+                            name: None,
+                            columns: vec!["user_id".into(), "sku".into()],
+                        },
+                        TableConstraint::ForeignKey {
+                            name: Some("orders_sku_fkey".into()),
+                            columns: vec!["sku".into()],
+                            references_table: "products".into(),
+                            references_columns: vec!["code".into()],
+                            on_delete: ReferentialAction::NoAction,
+                            on_update: ReferentialAction::NoAction,
+                        },
+                    ]
+                );
+            }
+            _ => panic!("Expected CreateTable"),
+        }
+
+        let result = Parser::parse("CREATE TABLE t (a INTEGER, KEY idx_a (a))");
+        assert!(matches!(result, Err(ParseError::UnsupportedConstraint(_))));
+    }
+
     #[test]
     fn test_parse_drop_table() {
         let sql = "DROP TABLE users";
@@ -657,6 +1563,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_alter_table() {
+        let stmt = Parser::parse("ALTER TABLE IF EXISTS users RENAME TO customers").unwrap();
+        assert_eq!(stmt, Statement::AlterTable(AlterTable {
+            name: "users".into(),
+            if_exists: true,
+            operation: AlterTableOperation::RenameTable("customers".into()),
+        }));
+        assert_eq!(stmt.kind(), "alter_table");
+
+        let stmt = Parser::parse("ALTER TABLE users RENAME COLUMN name TO full_name").unwrap();
+        let Statement::AlterTable(at) = stmt else { panic!("Expected AlterTable") };
+        assert_eq!(at.operation, AlterTableOperation::RenameColumn {
+            old: "name".into(),
+            new: "full_name".into(),
+        });
+
+        let result = Parser::parse("ALTER TABLE users ADD COLUMN age INTEGER");
+        assert!(matches!(result, Err(ParseError::UnsupportedStatement(_))));
+    }
+
     #[test]
     fn test_parse_select_all() {
         let sql = "SELECT * FROM users";
@@ -664,7 +1591,7 @@ mod tests {
 
         match stmt {
             Statement::Select(s) => {
-                assert_eq!(s.from, "users");
+                assert_eq!(s.from, FromSource::Table("users".into()));
                 assert_eq!(s.columns.len(), 1);
                 assert!(matches!(s.columns[0], SelectColumn::Wildcard));
                 assert!(s.where_clause.is_none());
@@ -688,6 +1615,141 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_qualified_wildcard() {
+        let sql = "SELECT users.*, orders.total FROM users";
+        let stmt = Parser::parse(sql).unwrap();
+
+        match stmt {
+            Statement::Select(s) => {
+                assert!(matches!(&s.columns[0], SelectColumn::QualifiedWildcard(t) if t == "users"));
+                assert!(matches!(&s.columns[1], SelectColumn::Column(c) if c == "orders.total"));
+                assert_eq!(s.to_string(), sql);
+            }
+            _ => panic!("Expected Select"),
+        }
+    }
+
+    #[test]
+    fn test_nested_unary_minus_round_trips() {
+        let select = |sql: &str| match Parser::parse(sql).unwrap() {
+            Statement::Select(s) => s.to_string(),
+            _ => panic!("Expected Select"),
+        };
+
+        let sql = select("SELECT - -1, -(-x) FROM t");
+        assert!(!sql.contains("--"), "{}", sql);
+        assert_eq!(select(&sql), sql);
+    }
+
+    #[test]
+    fn test_parse_limit_expressions_and_placeholders() {
+        let select = |sql: &str| match Parser::parse(sql).unwrap() {
+            Statement::Select(s) => s,
+            _ => panic!("Expected Select"),
+        };
+
+        let s = select("SELECT * FROM users LIMIT 10 + 5 OFFSET (2 * 3) - 1");
+        assert_eq!(s.limit, Some(RowCount::Count(15)));
+        assert_eq!(s.offset, Some(RowCount::Count(5)));
+
+        let s = select("SELECT * FROM users WHERE name <> '?' LIMIT ? OFFSET ?");
+        assert_eq!(s.limit, Some(RowCount::Parameter(1)));
+        assert_eq!(s.offset, Some(RowCount::Parameter(2)));
+        assert!(s.to_string().ends_with("LIMIT $1 OFFSET $2"));
+        assert_eq!(select("SELECT * FROM users LIMIT $2").limit, Some(RowCount::Parameter(2)));
+
+        for sql in [
+            "SELECT * FROM users LIMIT -1",
+            "SELECT * FROM users LIMIT 5 OFFSET 1 - 2",
+            "SELECT * FROM users LIMIT age",
+            "SELECT * FROM users LIMIT 'ten'",
+            "SELECT * FROM users LIMIT 1 / 0",
+        ] {
+            assert!(matches!(Parser::parse(sql), Err(ParseError::InvalidRowCount(_))), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn test_statement_kind() {
+        let kind = |sql: &str| Parser::parse(sql).unwrap().kind();
+        assert_eq!(kind("SELECT * FROM t"), "select");
+        assert_eq!(kind("CREATE TABLE t (id INTEGER PRIMARY KEY)"), "create_table");
+        assert_eq!(kind("DELETE FROM t"), "delete");
+        assert_eq!(kind("EXPLAIN SELECT * FROM t"), "explain");
+        assert_eq!(kind("BEGIN"), "begin");
+    }
+
+    #[test]
+    fn test_parse_negative_and_boolean_literals() {
+        let select = |sql: &str| match Parser::parse(sql).unwrap() {
+            Statement::Select(s) => s,
+            _ => panic!("Expected Select"),
+        };
+        let literal = |column: &SelectColumn| match column {
+            SelectColumn::Expr { expr: Expr::Literal(lit), .. } => lit.clone(),
+            other => panic!("Expected literal, got {:?}", other),
+        };
+
+        let s = select("SELECT -5, -5.5, TRUE, FALSE, -9223372036854775808 FROM accounts");
+        assert_eq!(literal(&s.columns[0]), LiteralValue::Integer(-5));
+        assert_eq!(literal(&s.columns[1]), LiteralValue::Float(-5.5));
+        assert_eq!(literal(&s.columns[2]), LiteralValue::Boolean(true));
+        assert_eq!(literal(&s.columns[3]), LiteralValue::Boolean(false));
+        assert_eq!(literal(&s.columns[4]), LiteralValue::Integer(i64::MIN));
+
+        // Fractional arithmetic over literals is folded exactly
+        let s = select("SELECT 0.30 - 0.2, (0.1 + 0.2) * 3, 1.5 * 2, 1 + 2 FROM accounts");
+        assert_eq!(literal(&s.columns[0]), LiteralValue::Float(0.1));
+        assert_eq!(literal(&s.columns[1]), LiteralValue::Float(0.9));
+        assert_eq!(literal(&s.columns[2]), LiteralValue::Float(3.0));
+        assert!(matches!(&s.columns[3], SelectColumn::Expr { expr: Expr::BinaryOp { .. }, .. }));
+
+        let s = select("SELECT * FROM accounts WHERE balance > -100 AND active = TRUE");
+        assert_eq!(
+            s.where_clause,
+            Some(Expr::BinaryOp {
+                left: Box::new(Expr::BinaryOp {
+                    left: Box::new(Expr::Column("balance".into())),
+                    op: BinaryOperator::Gt,
+                    right: Box::new(Expr::Literal(LiteralValue::Integer(-100))),
+                }),
+                op: BinaryOperator::And,
+                right: Box::new(Expr::BinaryOp {
+                    left: Box::new(Expr::Column("active".into())),
+                    op: BinaryOperator::Eq,
+                    right: Box::new(Expr::Literal(LiteralValue::Boolean(true))),
+                }),
+            })
+        );
+
+        // Only literals fold; negating a column stays an operator
+        let s = select("SELECT -balance FROM accounts");
+        assert!(matches!(&s.columns[0], SelectColumn::Expr { expr: Expr::UnaryOp { .. }, .. }));
+    }
+
+    #[test]
+    fn test_parse_json_access() {
+        let sql = "SELECT data->'address'->>'city' FROM users WHERE data->'tags'->>-1 = 'vip'";
+        let stmt = Parser::parse(sql).unwrap();
+
+        match stmt {
+            Statement::Select(s) => {
+                match &s.columns[0] {
+                    SelectColumn::Expr { expr: Expr::JsonAccess { expr, path, as_text: true }, .. } => {
+                        assert_eq!(path, &JsonPath::Key("city".into()));
+                        assert!(matches!(**expr, Expr::JsonAccess { as_text: false, .. }));
+                    }
+                    other => panic!("Expected JsonAccess, got {:?}", other),
+                }
+                assert_eq!(s.where_clause.unwrap().to_string(), "data->'tags'->>-1 = 'vip'");
+            }
+            _ => panic!("Expected Select"),
+        }
+
+        assert!(Parser::parse("SELECT data->name FROM users").is_err());
+    }
+
     #[test]
     fn test_parse_select_where() {
         let sql = "SELECT * FROM users WHERE age > 21";
@@ -719,13 +1781,43 @@ mod tests {
                 assert_eq!(s.order_by.len(), 1);
                 assert_eq!(s.order_by[0].column, "name");
                 assert!(!s.order_by[0].ascending);
-                assert_eq!(s.limit, Some(10));
-                assert_eq!(s.offset, Some(5));
+                assert_eq!(s.limit, Some(RowCount::Count(10)));
+                assert_eq!(s.offset, Some(RowCount::Count(5)));
             }
             _ => panic!("Expected Select"),
         }
     }
 
+    #[test]
+    fn test_parse_group_by() {
+        let stmt = Parser::parse("SELECT status, SUM(n) AS c FROM t GROUP BY status, t.kind ORDER BY c DESC").unwrap();
+        let Statement::Select(s) = stmt else {
+            panic!("Expected Select");
+        };
+        assert_eq!(s.group_by, vec!["status", "t.kind"]);
+        assert!(s.is_grouped());
+        assert_eq!(s.to_string(), "SELECT status, SUM(n) AS c FROM t GROUP BY status, t.kind ORDER BY c DESC");
+
+        let stmt = Parser::parse("SELECT a FROM t WHERE b GROUP BY a HAVING SUM(n) > 1").unwrap();
+        let Statement::Select(s) = stmt else {
+            panic!("Expected Select");
+        };
+        assert_eq!(s.to_string(), "SELECT a FROM t WHERE b GROUP BY a HAVING SUM(n) > 1");
+
+        assert!(Parser::parse("SELECT COUNT(*) FROM t GROUP BY a + 1").is_err());
+        assert!(Parser::parse("SELECT COUNT(*) FROM t GROUP BY ROLLUP (a)").is_err());
+    }
+
+    #[test]
+    fn test_parse_exists() {
+        let stmt = Parser::parse("SELECT * FROM users WHERE NOT EXISTS (SELECT 1 FROM bans WHERE id = 3)").unwrap();
+        let Statement::Select(s) = stmt else {
+            panic!("Expected Select");
+        };
+        assert!(matches!(s.where_clause, Some(Expr::Exists { negated: true, .. })));
+        assert_eq!(s.to_string(), "SELECT * FROM users WHERE NOT EXISTS (SELECT 1 FROM bans WHERE id = 3)");
+    }
+
     #[test]
     fn test_parse_insert() {
         let sql = "INSERT INTO users (id, name) VALUES ('1', 'Alice')";
@@ -740,6 +1832,21 @@ mod tests {
             }
             _ => panic!("Expected Insert"),
         }
+
+        let insert = |sql| match Parser::parse(sql).unwrap() {
+            Statement::Insert(i) => i.on_conflict,
+            _ => panic!("Expected Insert"),
+        };
+        assert_eq!(insert("INSERT INTO users (id) VALUES ('1')"), None);
+        assert_eq!(insert("INSERT OR IGNORE INTO users (id) VALUES ('1')"), Some(InsertConflict::Ignore));
+        assert_eq!(insert("INSERT OR REPLACE INTO users (id) VALUES ('1')"), Some(InsertConflict::Replace));
+        assert!(Parser::parse("INSERT OR ROLLBACK INTO users (id) VALUES ('1')").is_err());
+
+        // A bare DEFAULT is the column default, a quoted one a column name
+        let stmt = Parser::parse("INSERT INTO users (id, name) VALUES ('1', DEFAULT), ('2', \"default\")").unwrap();
+        let Statement::Insert(i) = stmt else { panic!("Expected Insert") };
+        assert_eq!(i.values[0][1], Expr::Default);
+        assert_eq!(i.values[1][1], Expr::Column("default".into()));
     }
 
     #[test]
@@ -774,15 +1881,87 @@ mod tests {
 
     #[test]
     fn test_parse_transaction_commands() {
-        assert!(matches!(Parser::parse("BEGIN").unwrap(), Statement::Begin));
-        assert!(matches!(Parser::parse("BEGIN TRANSACTION").unwrap(), Statement::Begin));
+        assert!(matches!(Parser::parse("BEGIN").unwrap(), Statement::Begin { isolation: None }));
+        assert!(matches!(Parser::parse("BEGIN TRANSACTION").unwrap(), Statement::Begin { isolation: None }));
         assert!(matches!(Parser::parse("COMMIT").unwrap(), Statement::Commit));
         assert!(matches!(Parser::parse("ROLLBACK").unwrap(), Statement::Rollback));
+
+        assert_eq!(
+            Parser::parse("BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE").unwrap(),
+            Statement::Begin { isolation: Some(TransactionIsolation::Serializable) },
+        );
+        assert_eq!(
+            Parser::parse("START TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ WRITE").unwrap(),
+            Statement::Begin { isolation: Some(TransactionIsolation::RepeatableRead) },
+        );
+        assert!(Parser::parse("BEGIN TRANSACTION READ ONLY").is_err());
     }
 
     #[test]
     fn test_parse_show_tables() {
-        assert!(matches!(Parser::parse("SHOW TABLES").unwrap(), Statement::ShowTables));
+        assert!(matches!(Parser::parse("SHOW TABLES").unwrap(), Statement::ShowTables { like: None }));
+        assert!(matches!(Parser::parse("show transactions;").unwrap(), Statement::ShowTransactions));
+        assert_eq!(
+            Parser::parse("SHOW TABLES LIKE 'user\\_%'").unwrap(),
+            Statement::ShowTables { like: Some("user\\_%".into()) }
+        );
+        assert_eq!(
+            Parser::parse("SHOW COLUMNS FROM Users LIKE 'na%'").unwrap(),
+            Statement::ShowColumns { table: "users".into(), like: Some("na%".into()) }
+        );
+        assert_eq!(
+            Parser::parse("SHOW COLUMNS IN users").unwrap(),
+            Statement::ShowColumns { table: "users".into(), like: None }
+        );
+        assert!(Parser::parse("SHOW TABLES WHERE name = 'users'").is_err());
+    }
+
+    #[test]
+    fn test_parse_vacuum() {
+        assert_eq!(Parser::parse("VACUUM").unwrap(), Statement::Vacuum { full: false });
+        assert_eq!(Parser::parse("vacuum  full;").unwrap(), Statement::Vacuum { full: true });
+    }
+
+    #[test]
+    fn test_parse_set_author() {
+        let author = Author {
+            name: "Alice Smith".into(),
+            email: "alice@example.com".into(),
+        };
+        assert_eq!(
+            Parser::parse("SET AUTHOR = 'Alice Smith <alice@example.com>'").unwrap(),
+            Statement::SetAuthor(Some(author.clone()))
+        );
+        assert_eq!(
+            Parser::parse("set author to ' Alice Smith<alice@example.com> '").unwrap(),
+            Statement::SetAuthor(Some(author))
+        );
+        assert_eq!(Parser::parse("SET AUTHOR = DEFAULT").unwrap(), Statement::SetAuthor(None));
+
+        assert!(Parser::parse("SET AUTHOR = 'alice@example.com'").is_err());
+        assert!(Parser::parse("SET AUTHOR = '<alice@example.com>'").is_err());
+        assert!(Parser::parse("SET AUTHOR = 'Alice', 'alice@example.com'").is_err());
+        assert!(Parser::parse("SET search_path = 'x'").is_err());
+    }
+
+    #[test]
+    fn test_parse_explain() {
+        match Parser::parse("EXPLAIN SELECT * FROM users WHERE id = '1'").unwrap() {
+            Statement::Explain { statement, analyze } => {
+                assert!(matches!(*statement, Statement::Select(_)));
+                assert!(!analyze);
+            }
+            other => panic!("Expected Explain, got {:?}", other),
+        }
+        assert!(matches!(
+            Parser::parse("EXPLAIN ANALYZE SELECT * FROM users").unwrap(),
+            Statement::Explain { analyze: true, .. }
+        ));
+
+        let err = Parser::parse("EXPLAIN DELETE FROM users").unwrap_err();
+        assert!(err.to_string().contains("only SELECT can be explained"));
+        // DESCRIBE and EXPLAIN of a bare table still describe it
+        assert!(matches!(Parser::parse("EXPLAIN users").unwrap(), Statement::Describe(_)));
     }
 
     #[test]
@@ -844,6 +2023,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_ilike_and_escape() {
+        let sql = "SELECT * FROM users WHERE name NOT ILIKE 'a!%%' ESCAPE '!'";
+        let stmt = Parser::parse(sql).unwrap();
+
+        match stmt {
+            Statement::Select(s) => {
+                let where_clause = s.where_clause.unwrap();
+                assert_eq!(where_clause.to_string(), "name NOT ILIKE 'a!%%' ESCAPE '!'");
+                match where_clause {
+                    Expr::Like { pattern, negated, case_insensitive, escape_char, .. } => {
+                        assert_eq!(pattern, "a!%%");
+                        assert!(negated);
+                        assert!(case_insensitive);
+                        assert_eq!(escape_char, Some('!'));
+                    }
+                    _ => panic!("Expected Like"),
+                }
+            }
+            _ => panic!("Expected Select"),
+        }
+
+        assert!(Parser::parse("SELECT * FROM users WHERE name LIKE 'a' ESCAPE '!!'").is_err());
+    }
+
     #[test]
     fn test_parse_between() {
         let sql = "SELECT * FROM users WHERE age BETWEEN 18 AND 65";
@@ -857,9 +2061,210 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_subqueries() {
+        let sql = "SELECT * FROM users WHERE id NOT IN (SELECT user_id FROM orders) \
+                   AND age > (SELECT age FROM users WHERE name = 'Bob')";
+        let stmt = Parser::parse(sql).unwrap();
+
+        match stmt {
+            Statement::Select(s) => {
+                let Some(Expr::BinaryOp { left, right, .. }) = s.where_clause else {
+                    panic!("Expected AND");
+                };
+                match *left {
+                    Expr::InSubquery { subquery, negated: true, .. } => {
+                        assert_eq!(subquery.from.table(), Some("orders"));
+                        assert_eq!(subquery.to_string(), "SELECT user_id FROM orders");
+                    }
+                    other => panic!("Expected IN subquery, got {:?}", other),
+                }
+                assert!(matches!(*right, Expr::BinaryOp { right, .. } if matches!(*right, Expr::Subquery(_))));
+            }
+            _ => panic!("Expected Select"),
+        }
+    }
+
+    #[test]
+    fn test_parse_derived_table() {
+        let sql = "SELECT t.name FROM (SELECT name, age FROM (SELECT * FROM users) AS u WHERE age > 30) t";
+        let Statement::Select(s) = Parser::parse(sql).unwrap() else {
+            panic!("Expected Select");
+        };
+        let FromSource::Derived(inner, alias) = &s.from else {
+            panic!("Expected derived table, got {:?}", s.from);
+        };
+        assert_eq!(alias, "t");
+        assert!(matches!(&inner.from, FromSource::Derived(users, u) if u == "u" && users.from.table() == Some("users")));
+        assert_eq!(
+            s.to_string(),
+            "SELECT t.name FROM (SELECT name, age FROM (SELECT * FROM users) AS u WHERE age > 30) AS t"
+        );
+
+        assert!(Parser::parse("SELECT * FROM (SELECT * FROM users)").is_err());
+        assert!(Parser::parse("DELETE FROM (SELECT * FROM users) AS u").is_err());
+    }
+
+    #[test]
+    fn test_parse_join() {
+        let sql = "SELECT users.name, orders.total FROM users LEFT JOIN orders ON users.id = orders.user_id";
+        let Statement::Select(s) = Parser::parse(sql).unwrap() else { panic!("Expected Select") };
+        match &s.from {
+            FromSource::Join { left, right, join_type, on: Some(on) } => {
+                assert_eq!(left.table(), Some("users"));
+                assert_eq!(right.table(), Some("orders"));
+                assert_eq!(*join_type, JoinType::Left);
+                assert_eq!(on.referenced_columns(), vec!["users.id", "orders.user_id"]);
+            }
+            other => panic!("Expected a join, got {:?}", other),
+        }
+        assert_eq!(s.to_string(), sql);
+
+        // Joins nest to the left
+        let sql = "SELECT * FROM a JOIN b ON a.id = b.a_id RIGHT JOIN c ON b.id = c.b_id CROSS JOIN d";
+        let Statement::Select(s) = Parser::parse(sql).unwrap() else { panic!("Expected Select") };
+        let names: Vec<&str> = s.from.sources().iter().map(|source| source.name()).collect();
+        assert_eq!(names, vec!["a", "b", "c", "d"]);
+        assert_eq!(s.to_string(), sql);
+
+        for sql in [
+            "SELECT * FROM users u JOIN orders ON u.id = orders.user_id",
+            "SELECT * FROM users JOIN orders USING (id)",
+            "SELECT * FROM users NATURAL JOIN orders",
+            "DELETE FROM users JOIN orders ON users.id = orders.user_id",
+        ] {
+            let result = Parser::parse(sql);
+            assert!(matches!(result, Err(ParseError::UnsupportedStatement(_))), "{}: {:?}", sql, result);
+        }
+    }
+
+    #[test]
+    fn test_parse_substring_and_trim() {
+        let sql = "SELECT SUBSTRING(name FROM 2 FOR 3), TRIM(LEADING 'x' FROM name) FROM users";
+        let stmt = Parser::parse(sql).unwrap();
+
+        match stmt {
+            Statement::Select(s) => {
+                assert_eq!(s.to_string(), "SELECT substr(name, 2, 3), ltrim(name, 'x') FROM users");
+            }
+            _ => panic!("Expected Select"),
+        }
+    }
+
+    #[test]
+    fn test_parse_ceil_floor() {
+        let stmt = Parser::parse("SELECT CEIL(price), FLOOR(price, 1) FROM items").unwrap();
+
+        match stmt {
+            Statement::Select(s) => {
+                assert_eq!(s.to_string(), "SELECT CEIL(price), FLOOR(price, 1) FROM items");
+            }
+            _ => panic!("Expected Select"),
+        }
+    }
+
+    #[test]
+    fn test_parse_date_functions() {
+        let sql = "SELECT DATE(created_at), EXTRACT(YEAR FROM created_at) FROM events \
+                   WHERE created_at > DATE_SUB(NOW(), INTERVAL 7 DAY)";
+        let stmt = Parser::parse(sql).unwrap();
+
+        // Written back as it was parsed, so it reads the same in headers
+        // and parses again
+        match stmt {
+            Statement::Select(s) => {
+                assert_eq!(s.to_string(), sql);
+                assert_eq!(Parser::parse(&s.to_string()).unwrap(), Statement::Select(s));
+            }
+            _ => panic!("Expected Select"),
+        }
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let sql = "-- seed\nCREATE TABLE t (id INTEGER);;\n\
+                   INSERT INTO t (id) VALUES (1);\nSHOW TABLES;\nDESCRIBE t;";
+        let script = Parser::parse_script(sql).unwrap();
+
+        assert_eq!(script.len(), 4);
+        assert!(matches!(script[0].1, Statement::CreateTable(_)));
+        assert_eq!(script[1].0, "INSERT INTO t (id) VALUES (1)");
+        assert_eq!(script[2].1, Statement::ShowTables { like: None });
+        assert_eq!(script[3].1, Statement::Describe("t".into()));
+    }
+
+    #[test]
+    fn test_parse_with_comments() {
+        assert_eq!(Parser::parse("-- start a tx\nBEGIN").unwrap(), Statement::Begin { isolation: None });
+        assert_eq!(Parser::parse("/* done */ COMMIT;").unwrap(), Statement::Commit);
+        assert_eq!(Parser::parse("SHOW /* all */ TABLES -- please").unwrap(), Statement::ShowTables { like: None });
+        assert_eq!(
+            Parser::parse("DESCRIBE /* the */ users; -- table").unwrap(),
+            Statement::Describe("users".into())
+        );
+
+        let stmt = Parser::parse("-- seed users\nINSERT INTO users (name) VALUES ('a -- b /* c */')").unwrap();
+        match stmt {
+            Statement::Insert(ins) => {
+                assert_eq!(
+                    ins.values[0][0],
+                    Expr::Literal(LiteralValue::String("a -- b /* c */".into()))
+                );
+            }
+            _ => panic!("Expected Insert"),
+        }
+
+        assert!(matches!(Parser::parse("-- nothing here"), Err(ParseError::EmptyQuery)));
+    }
+
+    #[test]
+    fn test_parse_script_with_interleaved_comments() {
+        let sql = "BEGIN; -- open\n/* rows */ INSERT INTO t (a) VALUES (1); -- one\nCOMMIT; /* end */";
+        let script = Parser::parse_script(sql).unwrap();
+        assert_eq!(script.len(), 3);
+        assert_eq!(script[0].1, Statement::Begin { isolation: None });
+        assert_eq!(script[2].1, Statement::Commit);
+    }
+
+    #[test]
+    fn test_parse_script_special_commands() {
+        let sql = "DELETE FROM t WHERE note = 'a;b';\nVACUUM;\nvacuum full;\nSHOW TRANSACTIONS;\nSELECT * FROM t";
+        let script = Parser::parse_script(sql).unwrap();
+        assert_eq!(script.len(), 5);
+        assert_eq!(script[0].0, "DELETE FROM t WHERE note = 'a;b'");
+        assert_eq!(script[1].1, Statement::Vacuum { full: false });
+        assert_eq!(script[2].1, Statement::Vacuum { full: true });
+        // Not read as a variable that swallows the rest of the script
+        assert_eq!(script[3].1, Statement::ShowTransactions);
+        assert!(matches!(script[4].1, Statement::Select(_)));
+    }
+
     #[test]
     fn test_empty_query() {
         assert!(matches!(Parser::parse("").unwrap_err(), ParseError::EmptyQuery));
         assert!(matches!(Parser::parse("   ").unwrap_err(), ParseError::EmptyQuery));
     }
+
+    #[test]
+    fn test_parse_identifier_case() {
+        // Unquoted identifiers fold to lower case; quoted ones are kept as written
+        let stmt = Parser::parse("SELECT Name, \"Email\" AS \"E\", Total AS Sum FROM Users WHERE \"Age\" > 1").unwrap();
+        let Statement::Select(select) = stmt else {
+            panic!("Expected Select");
+        };
+        assert_eq!(select.from, FromSource::Table("users".into()));
+        assert_eq!(select.columns[0], SelectColumn::Column("name".into()));
+        assert_eq!(select.columns[1], SelectColumn::Expr { expr: Expr::Column("Email".into()), alias: Some("E".into()) });
+        assert_eq!(select.columns[2], SelectColumn::Expr { expr: Expr::Column("total".into()), alias: Some("sum".into()) });
+        assert!(matches!(select.where_clause, Some(Expr::BinaryOp { ref left, .. }) if **left == Expr::Column("Age".into())));
+
+        let Statement::CreateTable(create) = Parser::parse("CREATE TABLE \"MyTable\" (Id INTEGER PRIMARY KEY)").unwrap() else {
+            panic!("Expected CreateTable");
+        };
+        assert_eq!(create.name, "MyTable");
+        assert_eq!(create.columns[0].name, "id");
+
+        assert_eq!(Parser::parse("DESCRIBE Users").unwrap(), Statement::Describe("users".into()));
+        assert_eq!(Parser::parse("DESCRIBE \"Users\"").unwrap(), Statement::Describe("Users".into()));
+    }
 }
@@ -0,0 +1,100 @@
+//! In-memory read cache for rows and table listings.
+//!
+//! Git objects are content-addressed, so a `BlobId` always names the same row
+//! and a `TreeId` always names the same table listing.  Mutations produce new
+//! IDs rather than changing old ones, which means cached entries can never go
+//! stale - they just stop being looked up and age out of the LRU.
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use lru::LruCache;
+use parking_lot::Mutex;
+
+use crate::storage::blob::Row;
+use crate::storage::types::{BlobId, RowKey, TreeId};
+
+/// Row entries of a table tree, in key order.
+pub(crate) type TableListing = Arc<Vec<(RowKey, BlobId)>>;
+
+/// Hit/miss counters for the read cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+    /// Lookups that had to go to git.
+    pub misses: u64,
+}
+
+/// LRU cache of deserialized rows keyed by blob and listings keyed by tree.
+pub(crate) struct ReadCache {
+    inner: Mutex<ReadCacheInner>,
+}
+
+struct ReadCacheInner {
+    rows: LruCache<BlobId, Row>,
+    listings: LruCache<TreeId, TableListing>,
+    stats: CacheStats,
+}
+
+impl ReadCache {
+    /// Create a cache holding up to `capacity` rows.
+    ///
+    /// Table listings get a smaller share since each one covers a whole table.
+    pub(crate) fn new(capacity: NonZeroUsize) -> Self {
+        let listings = NonZeroUsize::new(capacity.get() / 64).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            inner: Mutex::new(ReadCacheInner {
+                rows: LruCache::new(capacity),
+                listings: LruCache::new(listings),
+                stats: CacheStats::default(),
+            }),
+        }
+    }
+
+    /// Get a cached row.
+    pub(crate) fn get_row(&self, id: BlobId) -> Option<Row> {
+        let mut inner = self.inner.lock();
+        let row = inner.rows.get(&id).cloned();
+        inner.record(row.is_some());
+        row
+    }
+
+    /// Cache a row read from git.
+    pub(crate) fn put_row(&self, id: BlobId, row: &Row) {
+        self.inner.lock().rows.put(id, row.clone());
+    }
+
+    /// Get a cached table listing.
+    pub(crate) fn get_listing(&self, id: TreeId) -> Option<TableListing> {
+        let mut inner = self.inner.lock();
+        let listing = inner.listings.get(&id).cloned();
+        inner.record(listing.is_some());
+        listing
+    }
+
+    /// Cache a table listing read from git.
+    pub(crate) fn put_listing(&self, id: TreeId, listing: TableListing) {
+        self.inner.lock().listings.put(id, listing);
+    }
+
+    /// Current hit/miss counters.
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.inner.lock().stats
+    }
+
+    /// Set the hit/miss counters back to zero, keeping the cached entries.
+    pub(crate) fn reset_stats(&self) {
+        self.inner.lock().stats = CacheStats::default();
+    }
+}
+
+impl ReadCacheInner {
+    fn record(&mut self, hit: bool) {
+        if hit {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+    }
+}
@@ -7,12 +7,15 @@
 //!
 //! this module handles commit creation, history walking, and diff operations
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use chrono::{DateTime, TimeZone, Utc};
-use git2::{Delta, Diff, DiffOptions, Repository, Revwalk, Sort};
+use git2::{Delta, Diff, DiffOptions, ErrorCode, IndexEntry, IndexTime, Repository, Revwalk, Sort};
 
 use crate::storage::error::{StorageError, StorageResult};
+use crate::storage::signing::{CommitSigner, SignatureStatus};
 use crate::storage::tree::TreeHandle;
 use crate::storage::types::{Change, ChangeStatus, CommitId, GitSignature, TreeId};
 
@@ -63,6 +66,11 @@ impl CommitInfo {
     pub fn summary(&self) -> &str {
         self.message.lines().next().unwrap_or(&self.message)
     }
+
+    /// get the trailers and note recorded in the message
+    pub fn trailers(&self) -> CommitTrailers {
+        CommitTrailers::parse(&self.message)
+    }
 }
 
 /// builder for creating commits with a fluent interface
@@ -72,6 +80,8 @@ pub struct CommitBuilder<'a> {
     parents: Vec<CommitId>,
     message: String,
     signature: GitSignature,
+    signer: Option<Arc<dyn CommitSigner>>,
+    note: Option<String>,
     update_ref: Option<String>,
 }
 
@@ -84,6 +94,8 @@ impl<'a> CommitBuilder<'a> {
             parents: Vec::new(),
             message: String::new(),
             signature: GitSignature::gitdb(),
+            signer: None,
+            note: None,
             update_ref: None,
         }
     }
@@ -118,6 +130,18 @@ impl<'a> CommitBuilder<'a> {
         self
     }
 
+    /// sign the commit with `signer`, or leave it unsigned with `None`
+    pub fn signer(mut self, signer: Option<Arc<dyn CommitSigner>>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// add a note to the message, see [`CommitMessage::with_note`]
+    pub fn note(mut self, note: Option<String>) -> Self {
+        self.note = note;
+        self
+    }
+
     /// update a ref (branch) to point to this commit
     pub fn update_ref(mut self, refname: impl Into<String>) -> Self {
         self.update_ref = Some(refname.into());
@@ -142,19 +166,61 @@ impl<'a> CommitBuilder<'a> {
 
         let parent_refs: Vec<&git2::Commit<'_>> = parent_commits. iter().collect();
 
-        let oid = self.repo.commit(
-            self.update_ref.as_deref(),
-            &sig,
-            &sig,
-            &self.message,
-            &tree,
-            &parent_refs,
-        )? ;
+        let message = match &self.note {
+            Some(note) => CommitMessage::with_note(&self.message, note),
+            None => self.message.clone(),
+        };
+
+        let Some(signer) = &self.signer else {
+            let oid = self.repo.commit(
+                self.update_ref.as_deref(),
+                &sig,
+                &sig,
+                &message,
+                &tree,
+                &parent_refs,
+            )? ;
+            return Ok(CommitId::new(oid));
+        };
+
+        // Signed commits are written from their raw content, so the ref is
+        // moved separately
+        let content = self.repo.commit_create_buffer(&sig, &sig, &message, &tree, &parent_refs)?;
+        let content = std::str::from_utf8(&content)?;
+        let signature = signer.sign(content).map_err(StorageError::Signing)?;
+        let oid = self.repo.commit_signed(content, &signature, None)?;
+        if let Some(refname) = &self.update_ref {
+            self.repo.reference(refname, oid, true, &message)?;
+        }
 
         Ok(CommitId::new(oid))
     }
 }
 
+/// check the signature on a commit with `signer`
+pub fn verify_commit(
+    repo: &Repository,
+    id: CommitId,
+    signer: &dyn CommitSigner,
+) -> StorageResult<SignatureStatus> {
+    let (signature, content) = match repo.extract_signature(&id.raw(), None) {
+        Ok(extracted) => extracted,
+        Err(e) if e.code() == ErrorCode::NotFound => {
+            // Missing commits are reported as such, not as unsigned
+            repo.find_commit(id.raw()).map_err(|_| StorageError::CommitNotFound(id.to_string()))?;
+            return Ok(SignatureStatus::Unsigned);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let signature = std::str::from_utf8(&signature)?;
+    let content = std::str::from_utf8(&content)?;
+
+    match signer.verify(content, signature).map_err(StorageError::Signing)? {
+        true => Ok(SignatureStatus::Valid),
+        false => Ok(SignatureStatus::Invalid),
+    }
+}
+
 /// get information about a commit
 pub fn get_commit(repo: &Repository, id: CommitId) -> StorageResult<CommitInfo> {
     let commit = repo
@@ -262,6 +328,12 @@ impl<'repo> HistoryIterator<'repo> {
         self. revwalk.simplify_first_parent(). ok();
         self
     }
+
+    /// stop at `commit`: leave out it and everything it can reach
+    pub fn hiding(mut self, commit: CommitId) -> StorageResult<Self> {
+        self.revwalk.hide(commit.raw())?;
+        Ok(self)
+    }
 }
 
 impl<'repo> Iterator for HistoryIterator<'repo> {
@@ -307,53 +379,218 @@ pub fn detect_conflicts(repo: &Repository, ours: CommitId, theirs: CommitId) ->
     Ok(conflicts)
 }
 
+/// merge the trees of two commits against their merge base
+///
+/// returns `MergeConflict` if git can't combine the two sets of changes
+pub fn merge_trees(repo: &Repository, ours: CommitId, theirs: CommitId) -> StorageResult<TreeId> {
+    merge_trees_resolved(repo, ours, theirs, &BTreeMap::new())
+}
+
+/// merge like `merge_trees`, but take each path in `resolutions` as it is in
+/// the given commit, absent if the commit doesn't have it
+///
+/// resolved paths never conflict, and aren't content-merged even if git could
+pub fn merge_trees_resolved(
+    repo: &Repository,
+    ours: CommitId,
+    theirs: CommitId,
+    resolutions: &BTreeMap<PathBuf, CommitId>,
+) -> StorageResult<TreeId> {
+    let base = find_merge_base(repo, ours, theirs)?.ok_or_else(|| {
+        StorageError::Internal("no common ancestor found for merge".to_string())
+    })?;
+    let tree_of = |id: CommitId| repo.find_commit(id.raw()).and_then(|c| c.tree());
+
+    let mut index = repo.merge_trees(&tree_of(base)?, &tree_of(ours)?, &tree_of(theirs)?, None)?;
+    for (path, source) in resolutions {
+        // Clear the merged entry or conflict at the path, then put back the chosen one
+        let _ = index.conflict_remove(path);
+        let _ = index.remove_path(path);
+        match tree_of(*source)?.get_path(path) {
+            Ok(entry) => index.add(&IndexEntry {
+                ctime: IndexTime::new(0, 0),
+                mtime: IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: entry.filemode() as u32,
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                id: entry.id(),
+                flags: 0,
+                flags_extended: 0,
+                path: path.to_string_lossy().into_owned().into_bytes(),
+            })?,
+            Err(e) if e.code() == ErrorCode::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if index.has_conflicts() {
+        let conflicting_paths = index
+            .conflicts()?
+            .filter_map(|conflict| conflict.ok())
+            .filter_map(|conflict| conflict.our.or(conflict.their).or(conflict.ancestor))
+            .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned()))
+            .collect();
+        return Err(StorageError::MergeConflict { conflicting_paths });
+    }
+
+    Ok(TreeId::new(index.write_tree_to(repo)?))
+}
+
 /// message formatting for database operations
+///
+/// Messages are a `[OP] target` subject line, then an optional note, then
+/// git-style trailers:
+///
+/// ```text
+/// [INSERT] users/123
+///
+/// ticket #123
+///
+/// Tx-Id: 01HZX3K4
+/// Rows: users/123
+/// ```
+///
+/// The trailer keys and layout are stable; [`CommitInfo::trailers`] parses
+/// them back.
 pub struct CommitMessage;
 
 impl CommitMessage {
+    /// trailer naming the transaction that made a commit
+    pub const TX_ID: &'static str = "Tx-Id";
+
+    /// trailer listing the rows a commit wrote, as `table/key`
+    pub const ROWS: &'static str = "Rows";
+
     /// format a message for an INSERT operation
     pub fn insert(table: &str, key: &str, tx_id: Option<&str>) -> String {
-        match tx_id {
-            Some(id) => format!("[INSERT] {}/{} tx:{}", table, key, id),
-            None => format!("[INSERT] {}/{}", table, key),
-        }
+        Self::row_message("INSERT", table, key, tx_id)
     }
 
     /// format a message for an UPDATE operation
     pub fn update(table: &str, key: &str, tx_id: Option<&str>) -> String {
-        match tx_id {
-            Some(id) => format!("[UPDATE] {}/{} tx:{}", table, key, id),
-            None => format! ("[UPDATE] {}/{}", table, key),
-        }
+        Self::row_message("UPDATE", table, key, tx_id)
     }
 
     /// format a message for a DELETE operation
     pub fn delete(table: &str, key: &str, tx_id: Option<&str>) -> String {
-        match tx_id {
-            Some(id) => format!("[DELETE] {}/{} tx:{}", table, key, id),
-            None => format!("[DELETE] {}/{}", table, key),
-        }
+        Self::row_message("DELETE", table, key, tx_id)
     }
 
     /// format a message for a CREATE TABLE operation
     pub fn create_table(table: &str, tx_id: Option<&str>) -> String {
-        match tx_id {
-            Some(id) => format!("[CREATE TABLE] {} tx:{}", table, id),
-            None => format!("[CREATE TABLE] {}", table),
-        }
+        Self::format(&format!("[CREATE TABLE] {}", table), tx_id, None)
     }
 
     /// format a message for a DROP TABLE operation
     pub fn drop_table(table: &str, tx_id: Option<&str>) -> String {
-        match tx_id {
-            Some(id) => format!("[DROP TABLE] {} tx:{}", table, id),
-            None => format! ("[DROP TABLE] {}", table),
-        }
+        Self::format(&format!("[DROP TABLE] {}", table), tx_id, None)
+    }
+
+    /// format a message for an ALTER TABLE ... RENAME TO operation
+    pub fn rename_table(old: &str, new: &str, tx_id: Option<&str>) -> String {
+        Self::format(&format!("[RENAME TABLE] {} -> {}", old, new), tx_id, None)
+    }
+
+    /// format a message for an ALTER TABLE ... RENAME COLUMN operation
+    pub fn rename_column(table: &str, old: &str, new: &str, tx_id: Option<&str>) -> String {
+        Self::format(&format!("[RENAME COLUMN] {}.{} -> {}", table, old, new), tx_id, None)
+    }
+
+    /// format a message for the root commit VACUUM replaces history with
+    pub fn vacuum(head: CommitId) -> String {
+        Self::format(&format!("[VACUUM] History up to {} squashed", head.short()), None, None)
+    }
+
+    /// format a message for a batch of statements flushed as one commit
+    pub fn batch(statements: usize) -> String {
+        Self::format(&format!("[BATCH] {} statements", statements), None, None)
     }
 
     /// format a message for a transaction commit (merge to main)
     pub fn transaction_commit(tx_id: &str) -> String {
-        format!("[COMMIT] Transaction {} merged to main", tx_id)
+        Self::format(&format!("[COMMIT] Transaction {} merged to main", tx_id), Some(tx_id), None)
+    }
+
+    /// add a free-form note to a message, between its subject and trailers
+    pub fn with_note(message: &str, note: &str) -> String {
+        let note = note.trim();
+        if note.is_empty() {
+            return message.to_string();
+        }
+        match message.split_once("\n\n") {
+            Some((subject, rest)) => format!("{}\n\n{}\n\n{}", subject, note, rest),
+            None => format!("{}\n\n{}", message, note),
+        }
+    }
+
+    fn row_message(op: &str, table: &str, key: &str, tx_id: Option<&str>) -> String {
+        let row = format!("{}/{}", table, key);
+        Self::format(&format!("[{}] {}", op, row), tx_id, Some(&row))
+    }
+
+    fn format(subject: &str, tx_id: Option<&str>, row: Option<&str>) -> String {
+        let mut trailers = Vec::new();
+        if let Some(id) = tx_id {
+            trailers.push(format!("{}: {}", Self::TX_ID, id));
+        }
+        if let Some(row) = row {
+            trailers.push(format!("{}: {}", Self::ROWS, row));
+        }
+        if trailers.is_empty() {
+            subject.to_string()
+        } else {
+            format!("{}\n\n{}", subject, trailers.join("\n"))
+        }
+    }
+}
+
+/// trailers parsed from a commit message, see [`CommitMessage`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitTrailers {
+    /// the transaction that made the commit
+    pub tx_id: Option<String>,
+    /// rows the commit wrote, as `table/key`
+    pub rows: Vec<String>,
+    /// the note attached to the commit, if any
+    pub note: Option<String>,
+}
+
+impl CommitTrailers {
+    /// parse the trailers and note out of a commit message
+    ///
+    /// Messages without trailers, such as ones written by hand or by older
+    /// versions, parse to an empty set rather than an error.
+    pub fn parse(message: &str) -> Self {
+        let mut paragraphs: Vec<&str> = message
+            .trim_end()
+            .split("\n\n")
+            .skip(1) // the subject
+            .collect();
+
+        let mut trailers = Self::default();
+        let is_trailer = |line: &str| {
+            line.split_once(": ")
+                .is_some_and(|(key, _)| key == CommitMessage::TX_ID || key == CommitMessage::ROWS)
+        };
+        if paragraphs.last().is_some_and(|p| p.lines().all(is_trailer)) {
+            for line in paragraphs.pop().into_iter().flat_map(str::lines) {
+                let (key, value) = line.split_once(": ").expect("checked above");
+                if key == CommitMessage::TX_ID {
+                    trailers.tx_id = Some(value.to_string());
+                } else {
+                    trailers.rows.extend(value.split(", ").map(str::to_string));
+                }
+            }
+        }
+
+        let note = paragraphs.join("\n\n");
+        if !note.is_empty() {
+            trailers.note = Some(note);
+        }
+        trailers
     }
 }
 
@@ -499,12 +736,40 @@ mod tests {
     fn test_commit_messages() {
         assert_eq!(
             CommitMessage::insert("users", "123", Some("tx001")),
-            "[INSERT] users/123 tx:tx001"
+            "[INSERT] users/123\n\nTx-Id: tx001\nRows: users/123"
         );
-        assert_eq!(CommitMessage::delete("users", "123", None), "[DELETE] users/123");
+        assert_eq!(CommitMessage::delete("users", "123", None), "[DELETE] users/123\n\nRows: users/123");
+        assert_eq!(CommitMessage::create_table("users", None), "[CREATE TABLE] users");
+        assert_eq!(CommitMessage::rename_table("users", "customers", None), "[RENAME TABLE] users -> customers");
+        assert_eq!(CommitMessage::rename_column("users", "name", "full_name", None), "[RENAME COLUMN] users.name -> full_name");
         assert_eq!(
             CommitMessage::transaction_commit("tx001"),
-            "[COMMIT] Transaction tx001 merged to main"
+            "[COMMIT] Transaction tx001 merged to main\n\nTx-Id: tx001"
+        );
+    }
+
+    #[test]
+    fn test_commit_trailers() {
+        let message = CommitMessage::insert("users", "123", Some("tx001"));
+        let noted = CommitMessage::with_note(&message, "ticket #123\n\nsee the migration plan");
+        assert!(noted.starts_with("[INSERT] users/123\n\nticket #123\n\n"));
+        assert_eq!(
+            CommitTrailers::parse(&noted),
+            CommitTrailers {
+                tx_id: Some("tx001".into()),
+                rows: vec!["users/123".into()],
+                note: Some("ticket #123\n\nsee the migration plan".into()),
+            }
         );
+
+        let noted = CommitMessage::with_note(&CommitMessage::create_table("users", None), "ticket #7");
+        assert_eq!(
+            CommitTrailers::parse(&noted),
+            CommitTrailers { note: Some("ticket #7".into()), ..Default::default() }
+        );
+
+        // Old-style and hand-written messages have no trailers
+        assert_eq!(CommitTrailers::parse("[INSERT] users/123 tx:tx001"), CommitTrailers::default());
+        assert_eq!(CommitTrailers::parse("Second commit\n"), CommitTrailers::default());
     }
 }
\ No newline at end of file
@@ -7,7 +7,8 @@ use std::path::PathBuf;
 
 use thiserror::Error;
 
-use crate::storage::types::{InvalidNameError, RowKey, TableName};
+use crate::sql::EvalError;
+use crate::storage::types::{BranchName, InvalidNameError, RowKey, TableName};
 
 /// the main error type for storage operations
 #[derive(Debug, Error)]
@@ -44,6 +45,14 @@ pub enum StorageError {
     #[error("ref not found: {0}")]
     RefNotFound(String),
 
+    /// a scan's predicate couldn't be evaluated
+    #[error("predicate error: {0}")]
+    Predicate(#[from] EvalError),
+
+    /// the row changed since the version the caller read
+    #[error("version mismatch: table={table}, key={key}, expected={expected}, found={found}")]
+    VersionMismatch { table: TableName, key: RowKey, expected: u64, found: u64 },
+
     /// merge conflict detected during commit
     #[error("merge conflict: {conflicting_paths:?}")]
     MergeConflict { conflicting_paths: Vec<PathBuf> },
@@ -64,6 +73,10 @@ pub enum StorageError {
     #[error("repository is empty: no commits found")]
     EmptyRepository,
 
+    /// the commit signer failed
+    #[error("signing error: {0}")]
+    Signing(String),
+
     /// the commit was not found
     #[error("commit not found: {0}")]
     CommitNotFound(String),
@@ -92,6 +105,26 @@ pub enum StorageError {
     #[error("schema violation: {0}")]
     SchemaViolation(String),
 
+    /// the commit isn't in main's history
+    #[error("commit {0} is not in main's history")]
+    NotInHistory(String),
+
+    /// history can't be rewritten while these transaction branches exist
+    #[error("transaction branches are still open: {}", .0.iter().map(|b| b.as_str()).collect::<Vec<_>>().join(", "))]
+    OpenTransactions(Vec<BranchName>),
+
+    /// history can't be rewritten while these write batches are pending
+    #[error("write batches are still pending: {}", .0.join(", "))]
+    PendingBatches(Vec<String>),
+
+    /// history can't be rewritten while these tags point into it
+    #[error("tags point into the history being rewritten: {}", .0.join(", "))]
+    TaggedHistory(Vec<String>),
+
+    /// a write was attempted on a repository opened read-only
+    #[error("repository is read-only")]
+    ReadOnly,
+
     /// internal error that shouldn't happen
     #[error("internal error: {0}")]
     Internal(String),
@@ -117,9 +150,44 @@ impl StorageError {
                 | StorageError::TableAlreadyExists(_)
                 | StorageError::MergeConflict { .. }
                 | StorageError::ConcurrentModification { .. }
+                | StorageError::VersionMismatch { .. }
         )
     }
 
+    /// short name for the kind of error, stable across releases
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StorageError::Git(_) => "git",
+            StorageError::RowNotFound { .. } => "row_not_found",
+            StorageError::TableNotFound(_) => "table_not_found",
+            StorageError::RowAlreadyExists { .. } => "duplicate_key",
+            StorageError::TableAlreadyExists(_) => "table_exists",
+            StorageError::InvalidTableName(_) => "invalid_name",
+            StorageError::Serialization(_) => "serialization",
+            StorageError::RefNotFound(_) => "ref_not_found",
+            StorageError::Predicate(e) => e.kind(),
+            StorageError::VersionMismatch { .. } => "version_mismatch",
+            StorageError::MergeConflict { .. } => "merge_conflict",
+            StorageError::CorruptedData { .. } => "corrupted_data",
+            StorageError::Io(_) => "io",
+            StorageError::NotInitialized(_) => "not_initialized",
+            StorageError::EmptyRepository => "empty_repository",
+            StorageError::Signing(_) => "signing",
+            StorageError::CommitNotFound(_) => "commit_not_found",
+            StorageError::InvalidUtf8(_) => "invalid_utf8",
+            StorageError::UnexpectedEntryType { .. } => "unexpected_entry_type",
+            StorageError::BranchAlreadyExists(_) => "branch_exists",
+            StorageError::ConcurrentModification { .. } => "concurrent_modification",
+            StorageError::SchemaViolation(_) => "schema_violation",
+            StorageError::NotInHistory(_) => "not_in_history",
+            StorageError::OpenTransactions(_) => "open_transactions",
+            StorageError::PendingBatches(_) => "pending_batches",
+            StorageError::TaggedHistory(_) => "tagged_history",
+            StorageError::ReadOnly => "read_only",
+            StorageError::Internal(_) => "internal",
+        }
+    }
+
     /// check if this error is recoverable by retry
     pub fn is_retriable(&self) -> bool {
         matches!(self, StorageError::ConcurrentModification { .. })
@@ -0,0 +1,377 @@
+//! Object store maintenance: packing and loose object cleanup.
+//!
+//! Every write in GitDB is a commit, and every commit leaves new loose objects
+//! behind (the row blob, the table tree, the root tree and the commit itself).
+//! Heavy churn therefore fills `.git/objects` with small files. Repacking moves
+//! everything reachable from a ref into a single delta-compressed pack file, and
+//! gc then deletes the loose copies and older packs the new one supersedes.
+//!
+//! # Concurrency
+//!
+//! gc must not run while a transaction branch is mid-commit. A commit writes
+//! its objects before the ref that makes them reachable, so objects written by
+//! another process in that window are neither packed nor protected, and may sit
+//! in an old pack that gc deletes. Within one process `GitRepository::gc` holds
+//! the repository write lock, which keeps in-process writers out.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use git2::{ObjectType, Oid, Repository};
+
+use crate::storage::error::{StorageError, StorageResult};
+
+/// How long unreachable objects are kept by a pruning gc, by default.
+///
+/// A writer stores a commit's objects before the ref that reaches them, so
+/// a fresh unreachable object may be about to become reachable. Git keeps
+/// them for two weeks; GitDB writers reference theirs within a statement.
+pub const PRUNE_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// Outcome of a gc run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Objects written to the new pack.
+    pub objects_packed: usize,
+    /// Loose objects deleted because the pack now holds them, or, when
+    /// pruning, because nothing reaches them and they are past the grace
+    /// period.
+    pub loose_removed: usize,
+    /// Size of the object store before gc, in bytes.
+    pub size_before: u64,
+    /// Size of the object store after gc, in bytes.
+    pub size_after: u64,
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "packed {} objects, removed {} loose objects ({} -> {} bytes)",
+            self.objects_packed, self.loose_removed, self.size_before, self.size_after
+        )
+    }
+}
+
+/// Every object reachable from any ref: tags, commits, trees and blobs.
+///
+/// Annotated tags are kept along with whatever they point at, and refs that
+/// point straight at a tree or blob keep that object too.
+pub(crate) fn reachable_objects(repo: &Repository) -> StorageResult<HashSet<Oid>> {
+    let mut walk = repo.revwalk()?;
+    let mut objects = HashSet::new();
+
+    for reference in repo.references()? {
+        let Some(mut id) = reference?.resolve()?.target() else {
+            continue;
+        };
+        // Peel tag chains by hand so each tag object is kept as well
+        loop {
+            let object = repo.find_object(id, None)?;
+            match object.kind() {
+                Some(ObjectType::Tag) => {
+                    objects.insert(id);
+                    id = object.peel_to_tag()?.target_id();
+                }
+                Some(ObjectType::Commit) => {
+                    walk.push(id)?;
+                    break;
+                }
+                Some(ObjectType::Tree) => {
+                    collect_tree(repo, id, &mut objects)?;
+                    break;
+                }
+                _ => {
+                    objects.insert(id);
+                    break;
+                }
+            }
+        }
+    }
+
+    for commit_id in walk {
+        let commit = repo.find_commit(commit_id?)?;
+        objects.insert(commit.id());
+        collect_tree(repo, commit.tree_id(), &mut objects)?;
+    }
+    Ok(objects)
+}
+
+/// Add a tree and everything below it, skipping subtrees already seen.
+///
+/// Consecutive commits share almost all of their trees, so the skip keeps a
+/// walk over long histories proportional to what actually changed.
+fn collect_tree(repo: &Repository, tree_id: Oid, objects: &mut HashSet<Oid>) -> StorageResult<()> {
+    if !objects.insert(tree_id) {
+        return Ok(());
+    }
+    let tree = repo.find_tree(tree_id)?;
+    for entry in tree.iter() {
+        if entry.kind() == Some(ObjectType::Tree) {
+            collect_tree(repo, entry.id(), objects)?;
+        } else {
+            objects.insert(entry.id());
+        }
+    }
+    Ok(())
+}
+
+/// Write all reachable objects into a new pack.
+///
+/// Returns the packed objects and the new pack's name (`pack-<hash>`).
+pub(crate) fn repack(repo: &Repository) -> StorageResult<(HashSet<Oid>, Option<String>)> {
+    let objects = reachable_objects(repo)?;
+
+    let mut builder = repo.packbuilder()?;
+    for id in &objects {
+        builder.insert_object(*id, None)?;
+    }
+    builder.write(&repo.path().join("objects").join("pack"), 0o644)?;
+
+    let name = builder.name().map(|hash| format!("pack-{}", hash));
+    Ok((objects, name))
+}
+
+/// Repack, then delete loose objects and packs the new pack supersedes.
+///
+/// With `prune`, objects nothing reaches are deleted as well once they are
+/// older than the given grace period: loose objects by their own mtime, and
+/// old packs by the pack's. Falls back to running `git gc` if packing
+/// through libgit2 fails.
+pub(crate) fn gc(repo: &Repository, prune: Option<Duration>) -> StorageResult<GcReport> {
+    let objects_dir = repo.path().join("objects");
+    let size_before = object_store_stats(&objects_dir)?.disk_size_bytes;
+
+    let (packed, pack_name) = match repack(repo) {
+        Ok(result) => result,
+        Err(e) => return git_gc_fallback(repo, size_before, prune, e),
+    };
+
+    // Anything written after the cutoff may be about to become reachable
+    let cutoff = prune.map(|grace| SystemTime::now().checked_sub(grace).unwrap_or(SystemTime::UNIX_EPOCH));
+    let expired = |path: &Path| -> StorageResult<bool> {
+        Ok(match cutoff {
+            Some(cutoff) => fs::metadata(path)?.modified()? < cutoff,
+            None => false,
+        })
+    };
+
+    let mut loose_removed = 0;
+    for (id, path) in loose_objects(&objects_dir)? {
+        // The pack holds everything reachable, so anything else is unreachable
+        if packed.contains(&id) || expired(&path)? {
+            fs::remove_file(&path)?;
+            loose_removed += 1;
+            // Fan-out directories are left empty once all their objects go
+            if let Some(dir) = path.parent() {
+                let _ = fs::remove_dir(dir);
+            }
+        }
+    }
+
+    // Older packs are redundant once the new one holds all of their objects;
+    // a pack with anything the new one lacks is kept, unless pruning and the
+    // pack is past the grace period
+    if let Some(name) = pack_name {
+        let pack_dir = objects_dir.join("pack");
+        let mut superseded = HashSet::new();
+        for entry in fs::read_dir(&pack_dir)? {
+            let path = entry?.path();
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            if stem.starts_with("pack-")
+                && stem != name
+                && path.extension().is_some_and(|e| e == "idx")
+                && (expired(&objects_dir.join("pack").join(format!("{}.pack", stem)))?
+                    || pack_index_objects(&path)?.iter().all(|id| packed.contains(id)))
+            {
+                superseded.insert(stem.to_string());
+            }
+        }
+        for entry in fs::read_dir(&pack_dir)? {
+            let path = entry?.path();
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            if superseded.contains(stem) {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+
+    Ok(GcReport {
+        objects_packed: packed.len(),
+        loose_removed,
+        size_before,
+        size_after: object_store_stats(&objects_dir)?.disk_size_bytes,
+    })
+}
+
+/// How many of `head` and its first-parent ancestors are still loose, that
+/// is committed since the last repack, counting at most `limit`.
+///
+/// Packing removes loose copies, so this is how far back the last pack is
+/// without keeping any count on disk.
+pub(crate) fn commits_since_pack(repo: &Repository, head: Oid, limit: usize) -> StorageResult<usize> {
+    let objects_dir = repo.path().join("objects");
+    let mut commit = repo.find_commit(head)?;
+    let mut count = 0;
+    while count < limit {
+        let hex = commit.id().to_string();
+        if !objects_dir.join(&hex[..2]).join(&hex[2..]).is_file() {
+            break;
+        }
+        count += 1;
+        match commit.parent(0) {
+            Ok(parent) => commit = parent,
+            Err(_) => break,
+        }
+    }
+    Ok(count)
+}
+
+/// Run `git gc` in the repository when the built-in repack fails.
+fn git_gc_fallback(
+    repo: &Repository,
+    size_before: u64,
+    prune: Option<Duration>,
+    cause: StorageError,
+) -> StorageResult<GcReport> {
+    let mut command = Command::new("git");
+    command.arg("gc").arg("--quiet");
+    if let Some(grace) = prune {
+        command.arg(format!("--prune={}.seconds.ago", grace.as_secs()));
+    }
+    let status = command.current_dir(repo.path()).status();
+    match status {
+        Ok(status) if status.success() => {
+            let objects_dir = repo.path().join("objects");
+            Ok(GcReport {
+                size_before,
+                size_after: object_store_stats(&objects_dir)?.disk_size_bytes,
+                ..GcReport::default()
+            })
+        }
+        // git isn't installed or failed too; the original error is more useful
+        _ => Err(cause),
+    }
+}
+
+/// Loose objects in an objects directory, with their file paths.
+fn loose_objects(objects_dir: &Path) -> StorageResult<Vec<(Oid, PathBuf)>> {
+    let mut found = Vec::new();
+    for dir in fs::read_dir(objects_dir)? {
+        let dir = dir?;
+        let prefix = dir.file_name();
+        let prefix = prefix.to_string_lossy();
+        if prefix.len() != 2 || !dir.file_type()?.is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(dir.path())? {
+            let file = file?;
+            let hex = format!("{}{}", prefix, file.file_name().to_string_lossy());
+            if hex.len() != 40 {
+                continue;
+            }
+            if let Ok(id) = Oid::from_str(&hex) {
+                found.push((id, file.path()));
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Object counts and sizes of an objects directory.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ObjectStoreStats {
+    pub loose_objects: usize,
+    pub packed_objects: usize,
+    pub pack_size_bytes: u64,
+    pub disk_size_bytes: u64,
+}
+
+/// Measure an objects directory without reading any object.
+///
+/// Loose objects are counted by file; packed ones from each pack index's
+/// fan-out table, whose last entry is the number of objects in the pack.
+pub(crate) fn object_store_stats(objects_dir: &Path) -> StorageResult<ObjectStoreStats> {
+    let mut stats = ObjectStoreStats {
+        loose_objects: loose_objects(objects_dir)?.len(),
+        disk_size_bytes: dir_size(objects_dir)?,
+        ..ObjectStoreStats::default()
+    };
+
+    let pack_dir = objects_dir.join("pack");
+    if !pack_dir.is_dir() {
+        return Ok(stats);
+    }
+    for entry in fs::read_dir(pack_dir)? {
+        let path = entry?.path();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("pack") => stats.pack_size_bytes += fs::metadata(&path)?.len(),
+            Some("idx") => stats.packed_objects += pack_index_object_count(&path)?,
+            _ => {}
+        }
+    }
+    Ok(stats)
+}
+
+/// Number of objects recorded in a pack index (`.idx`) file.
+fn pack_index_object_count(path: &Path) -> StorageResult<usize> {
+    let mut header = [0u8; 8 + 256 * 4];
+    let mut file = fs::File::open(path)?;
+    let read = std::io::Read::read(&mut file, &mut header)?;
+    let (_, count) = pack_index_header(path, &header[..read])?;
+    Ok(count)
+}
+
+/// Ids of the objects in a pack index (`.idx`) file.
+///
+/// v2 indexes list the names in one block after the fan-out table; v1
+/// indexes interleave each name with its 4-byte pack offset.
+fn pack_index_objects(path: &Path) -> StorageResult<Vec<Oid>> {
+    let data = fs::read(path)?;
+    let (fanout_start, count) = pack_index_header(path, &data)?;
+    let names_start = fanout_start + 256 * 4;
+    let (stride, skip) = if fanout_start == 0 { (24, 4) } else { (20, 0) };
+
+    let truncated = || StorageError::CorruptedData {
+        path: path.to_path_buf(),
+        reason: "pack index is truncated".into(),
+    };
+    let mut ids = Vec::with_capacity(count);
+    for i in 0..count {
+        let start = names_start + i * stride + skip;
+        let name = data.get(start..start + 20).ok_or_else(truncated)?;
+        ids.push(Oid::from_bytes(name)?);
+    }
+    Ok(ids)
+}
+
+/// Where a pack index's fan-out table starts, and its object count.
+fn pack_index_header(path: &Path, data: &[u8]) -> StorageResult<(usize, usize)> {
+    const V2_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+
+    // v2 indexes start with a magic and version, v1 with the fan-out directly
+    let fanout_start = if data.get(..4) == Some(&V2_MAGIC[..]) { 8 } else { 0 };
+    let last = fanout_start + 255 * 4;
+    if data.len() < last + 4 {
+        return Err(StorageError::CorruptedData {
+            path: path.to_path_buf(),
+            reason: "pack index is truncated".into(),
+        });
+    }
+    let count = u32::from_be_bytes(data[last..last + 4].try_into().expect("4 bytes"));
+    Ok((fanout_start, count as usize))
+}
+
+fn dir_size(dir: &Path) -> StorageResult<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        size += if meta.is_dir() { dir_size(&entry.path())? } else { meta.len() };
+    }
+    Ok(size)
+}
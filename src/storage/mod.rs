@@ -54,18 +54,24 @@
 //! ```
 
 mod blob;
+mod cache;
 mod commit;
 mod error;
+mod gc;
 mod refs;
 mod repository;
+mod signing;
 mod tree;
 mod types;
 
 // Re-export public API
-pub use blob::Row;
-pub use commit::{CommitInfo, CommitMessage};
+pub use blob::{BlobMetadata, Row};
+pub use cache::CacheStats;
+pub use commit::{CommitInfo, CommitMessage, CommitTrailers};
 pub use error::{StorageError, StorageResult};
-pub use repository::{GitRepository, RepositoryStats, TreeSnapshot};
+pub use gc::{GcReport, PRUNE_GRACE};
+pub use repository::{GitRepository, RepositoryStats, TableScan, TreeSnapshot};
+pub use signing::{CommitSigner, SignatureStatus};
 pub use types::{
     BlobId, BranchName, Change, ChangeStatus, CommitId, GitSignature, InvalidNameError,
     RowKey, RowPath, TableName, TreeId,
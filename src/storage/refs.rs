@@ -6,7 +6,8 @@
 //! - Ref resolution and validation
 //!
 //! Transaction branches use a namespaced pattern: `tx/{transaction_id}`
-//! This makes them easy to identify and clean up.
+//! This makes them easy to identify and clean up. Pending write batches are
+//! pinned outside the branch namespace, under `refs/batch/{name}`.
 
 use git2::{BranchType, Repository};
 
@@ -17,6 +18,9 @@ use crate::storage::types::{BranchName, CommitId};
 pub struct RefManager;
 
 impl RefManager {
+    /// Namespace of the refs pinning pending write batches.
+    pub const BATCH_PREFIX: &'static str = "refs/batch/";
+
     /// Resolve a branch name to its current commit ID.
     pub fn resolve_branch(repo: &Repository, branch: &BranchName) -> StorageResult<CommitId> {
         let reference = repo
@@ -95,6 +99,35 @@ impl RefManager {
         Self::update_branch(repo, branch, new_target)
     }
 
+    /// Point a lightweight tag at a commit, replacing any existing tag of that name.
+    pub fn set_tag(repo: &Repository, name: &str, target: CommitId) -> StorageResult<()> {
+        let object = repo.find_object(target.raw(), None)?;
+        repo.tag_lightweight(name, &object, true)?;
+        Ok(())
+    }
+
+    /// Resolve a tag to the commit it points at, if it exists.
+    pub fn resolve_tag(repo: &Repository, name: &str) -> StorageResult<Option<CommitId>> {
+        match repo.find_reference(&format!("refs/tags/{}", name)) {
+            Ok(reference) => Ok(Some(CommitId::new(reference.peel_to_commit()?.id()))),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Delete a tag; deleting a missing tag is not an error.
+    pub fn delete_tag(repo: &Repository, name: &str) -> StorageResult<()> {
+        match repo.tag_delete(name) {
+            Err(e) if e.code() != git2::ErrorCode::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Names of all tags.
+    pub fn list_tags(repo: &Repository) -> StorageResult<Vec<String>> {
+        Ok(repo.tag_names(None)?.iter().flatten().map(str::to_string).collect())
+    }
+
     /// Delete a branch.
     pub fn delete_branch(repo: &Repository, branch: &BranchName) -> StorageResult<()> {
         let mut git_branch = repo
@@ -149,6 +182,37 @@ impl RefManager {
         Self::delete_branch(repo, &branch)
     }
 
+    /// Point `refs/batch/<name>` at the head of a pending write batch.
+    ///
+    /// The ref only keeps the batch's commits reachable, so gc keeps them
+    /// and history rewrites can see the batch; nothing reads through it.
+    pub fn set_batch_ref(repo: &Repository, name: &str, target: CommitId) -> StorageResult<()> {
+        repo.reference(&format!("{}{}", Self::BATCH_PREFIX, name), target.raw(), true, "gitdb: batch")?;
+        Ok(())
+    }
+
+    /// Delete a batch ref; deleting a missing one is not an error.
+    pub fn delete_batch_ref(repo: &Repository, name: &str) -> StorageResult<()> {
+        match repo.find_reference(&format!("{}{}", Self::BATCH_PREFIX, name)) {
+            Ok(mut reference) => Ok(reference.delete()?),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Names and heads of all pending batches.
+    pub fn list_batch_refs(repo: &Repository) -> StorageResult<Vec<(String, CommitId)>> {
+        let mut batches = Vec::new();
+        for reference in repo.references_glob(&format!("{}*", Self::BATCH_PREFIX))? {
+            let reference = reference?;
+            let (Some(name), Some(target)) = (reference.name(), reference.target()) else {
+                continue;
+            };
+            batches.push((name.trim_start_matches(Self::BATCH_PREFIX).to_string(), CommitId::new(target)));
+        }
+        Ok(batches)
+    }
+
     /// Clean up old transaction branches (for recovery after crashes).
     ///
     /// In a real implementation, you'd check timestamps and only delete
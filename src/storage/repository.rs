@@ -6,32 +6,67 @@
 //!
 //! All other storage modules use this for Git access.
 
+use std::collections::{BTreeMap, HashSet};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use git2::Repository;
-use parking_lot::RwLock;
+use parking_lot::Mutex;
 
-use crate::storage::blob::{self, Row};
+use crate::sql::eval::Predicate;
+use crate::sql::Expr;
+use crate::storage::blob::{self, BlobMetadata, Row};
+use crate::storage::cache::{CacheStats, ReadCache, TableListing};
 use crate::storage::commit::{self, CommitBuilder, CommitInfo, CommitMessage};
 use crate::storage::error::{StorageError, StorageResult};
+use crate::storage::gc::{self, GcReport};
 use crate::storage::refs::RefManager;
-use crate::storage::tree::TreeMutator;
-use crate::storage::types::{BranchName, CommitId, GitSignature, RowKey, TableName, TreeId};
+use crate::storage::signing::{CommitSigner, SignatureStatus};
+use crate::storage::tree::{TreeHandle, TreeMutator};
+use crate::storage::types::{BlobId, BranchName, CommitId, GitSignature, RowKey, RowPath, TableName, TreeId};
 
 /// The main Git repository wrapper.
 ///
 /// This provides thread-safe access to all Git operations.
 /// Clone this to share across threads - it uses Arc internally.
+///
+/// libgit2 repository handles are not safe to use from two threads at once,
+/// so every access, read or write, holds the same lock.
 #[derive(Clone)]
 pub struct GitRepository {
     inner: Arc<GitRepositoryInner>,
+    /// Overrides the shared signature for commits made through this handle.
+    author: Option<GitSignature>,
+    /// Added to the message of every commit made through this handle.
+    note: Option<String>,
+    /// Branch this handle reads and advances instead of main.
+    branch: Option<BranchName>,
+    /// Commit this handle reads instead of any branch tip.
+    pinned: Option<CommitId>,
+    /// Head kept in memory instead of on any branch, shared by clones.
+    detached: Option<Arc<Mutex<CommitId>>>,
+    /// Batch ref kept at the detached head, see [`detached_batch`](Self::detached_batch).
+    batch: Option<String>,
 }
 
 struct GitRepositoryInner {
-    repo: RwLock<Repository>,
+    repo: Mutex<Repository>,
     path: PathBuf,
     signature: GitSignature,
+    cache: Option<ReadCache>,
+    signer: Option<Arc<dyn CommitSigner>>,
+    read_only: bool,
+    auto_pack: Option<AutoPack>,
+}
+
+/// Repack policy set by [`GitRepository::with_auto_pack`].
+struct AutoPack {
+    every: NonZeroUsize,
+    /// Commits to main since the last pack, worked out from the object store
+    /// on the first commit after opening.
+    since: Mutex<Option<usize>>,
 }
 
 impl GitRepository {
@@ -42,13 +77,35 @@ impl GitRepository {
 
         Ok(Self {
             inner: Arc::new(GitRepositoryInner {
-                repo: RwLock::new(repo),
+                repo: Mutex::new(repo),
                 path: path.to_path_buf(),
                 signature: GitSignature::gitdb(),
+                cache: None,
+                signer: None,
+                read_only: false,
+                auto_pack: None,
             }),
+            author: None,
+            note: None,
+            branch: None,
+            pinned: None,
+            detached: None,
+            batch: None,
         })
     }
 
+    /// Open an existing repository for reading only.
+    ///
+    /// Every mutating operation, including branch updates and transaction
+    /// branches, fails with [`StorageError::ReadOnly`] before touching git.
+    pub fn open_read_only(path: impl AsRef<Path>) -> StorageResult<Self> {
+        let mut storage = Self::open(path)?;
+        Arc::get_mut(&mut storage.inner)
+            .expect("freshly opened repository is not shared")
+            .read_only = true;
+        Ok(storage)
+    }
+
     /// Initialize a new repository.
     pub fn init(path: impl AsRef<Path>) -> StorageResult<Self> {
         let path = path.as_ref();
@@ -56,10 +113,20 @@ impl GitRepository {
 
         let storage = Self {
             inner: Arc::new(GitRepositoryInner {
-                repo: RwLock::new(repo),
+                repo: Mutex::new(repo),
                 path: path. to_path_buf(),
                 signature: GitSignature::gitdb(),
+                cache: None,
+                signer: None,
+                read_only: false,
+                auto_pack: None,
             }),
+            author: None,
+            note: None,
+            branch: None,
+            pinned: None,
+            detached: None,
+            batch: None,
         };
 
         // Create initial commit
@@ -82,6 +149,11 @@ impl GitRepository {
         }
     }
 
+    /// Whether the repository was opened with [`open_read_only`](Self::open_read_only).
+    pub fn is_read_only(&self) -> bool {
+        self.inner.read_only
+    }
+
     /// Get the repository path.
     pub fn path(&self) -> &Path {
         &self.inner.path
@@ -95,29 +167,249 @@ impl GitRepository {
         self
     }
 
+    /// A handle on the same repository that commits as `author`.
+    ///
+    /// Unlike [`with_signature`](Self::with_signature) this works on a
+    /// shared repository and only affects commits made through the returned
+    /// handle, so each session can attribute its own changes.
+    pub fn as_author(&self, author: GitSignature) -> Self {
+        Self {
+            author: Some(author),
+            ..self.clone()
+        }
+    }
+
+    /// A handle on the same repository that adds `note` to the message of
+    /// every commit it makes, see [`CommitMessage::with_note`].
+    pub fn annotated(&self, note: impl Into<String>) -> Self {
+        Self {
+            note: Some(note.into()),
+            ..self.clone()
+        }
+    }
+
+    /// A handle on the same repository that works on `branch` instead of
+    /// main: [`head`](Self::head) resolves it and
+    /// [`advance_head`](Self::advance_head) moves it.
+    pub fn on_branch(&self, branch: BranchName) -> Self {
+        Self {
+            branch: Some(branch),
+            ..self.clone()
+        }
+    }
+
+    /// A handle on the same repository whose [`head`](Self::head) is always
+    /// `commit`, so every read sees the database as it was then.
+    ///
+    /// Use it on a repository opened with
+    /// [`open_read_only`](Self::open_read_only): writing on top of a pinned
+    /// head would move main back to it.
+    pub fn pinned_at(&self, commit: CommitId) -> Self {
+        Self {
+            pinned: Some(commit),
+            ..self.clone()
+        }
+    }
+
+    /// A handle on the same repository whose head starts at `start` and is
+    /// kept in memory: [`advance_head`](Self::advance_head) moves it without
+    /// touching any ref, and clones of the handle share it.
+    ///
+    /// Commits made through it are reachable from nothing until
+    /// [`publish_squashed`](Self::publish_squashed) lands them on main, so
+    /// they are lost if the process exits first.
+    pub fn detached(&self, start: CommitId) -> Self {
+        Self {
+            branch: None,
+            pinned: None,
+            detached: Some(Arc::new(Mutex::new(start))),
+            batch: None,
+            ..self.clone()
+        }
+    }
+
+    /// Like [`detached`](Self::detached), but the head is also kept in the
+    /// ref `refs/batch/<name>` until [`release_batch`](Self::release_batch).
+    ///
+    /// The ref keeps the commits made through the handle safe from
+    /// [`gc_prune`](Self::gc_prune), and [`vacuum`](Self::vacuum) refuses
+    /// while it exists. A process that exits first leaves the ref behind.
+    pub fn detached_batch(&self, start: CommitId, name: &str) -> StorageResult<Self> {
+        self.with_repo_mut(|repo| RefManager::set_batch_ref(repo, name, start))?;
+        Ok(Self {
+            batch: Some(name.to_string()),
+            ..self.detached(start)
+        })
+    }
+
+    /// Delete the ref a [`detached_batch`](Self::detached_batch) handle
+    /// kept, once its commits are published or discarded.
+    pub fn release_batch(&self, name: &str) -> StorageResult<()> {
+        self.with_repo_mut(|repo| RefManager::delete_batch_ref(repo, name))
+    }
+
+    /// The commit given to [`pinned_at`](Self::pinned_at), if any.
+    pub fn pinned(&self) -> Option<CommitId> {
+        self.pinned
+    }
+
+    /// Find the commit `spec` names: a full or abbreviated id, a branch or
+    /// tag, or any other git revision such as `main~3`.
+    pub fn resolve_commit(&self, spec: &str) -> StorageResult<CommitId> {
+        self.with_repo(|repo| {
+            repo.revparse_single(spec)
+                .and_then(|object| object.peel_to_commit())
+                .map(|commit| CommitId::new(commit.id()))
+                .map_err(|_| StorageError::CommitNotFound(spec.to_string()))
+        })
+    }
+
+    /// The branch this handle works on.
+    pub fn branch(&self) -> BranchName {
+        self.branch.clone().unwrap_or_else(BranchName::main)
+    }
+
+    /// The signature commits made through this handle use.
+    pub fn signature(&self) -> &GitSignature {
+        self.author.as_ref().unwrap_or(&self.inner.signature)
+    }
+
+    /// Sign every commit from now on with `signer`.
+    ///
+    /// Commits are unsigned by default.
+    pub fn with_signing(mut self, signer: impl CommitSigner + 'static) -> Self {
+        let inner = Arc::get_mut(&mut self.inner).expect("cannot modify shared repository");
+        inner.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// Check a commit's signature with the signer given to [`with_signing`](Self::with_signing).
+    pub fn verify_commit(&self, id: CommitId) -> StorageResult<SignatureStatus> {
+        let signer = self.inner.signer.as_deref().ok_or_else(|| {
+            StorageError::Signing("no signer configured to verify with".to_string())
+        })?;
+        self.with_repo(|repo| commit::verify_commit(repo, id, signer))
+    }
+
+    /// Start a commit with this repository's signature and signer.
+    fn commit_builder<'r>(&self, repo: &'r Repository) -> CommitBuilder<'r> {
+        CommitBuilder::new(repo)
+            .signature(self.signature().clone())
+            .signer(self.inner.signer.clone())
+            .note(self.note.clone())
+    }
+
+    /// Cache up to `capacity` deserialized rows in memory.
+    ///
+    /// `read_row` and `scan_table` consult the cache before going to git.
+    /// Entries are keyed by object ID, so writes never make them stale.
+    /// A capacity of zero leaves caching disabled.
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        let inner = Arc::get_mut(&mut self.inner).expect("cannot modify shared repository");
+        inner.cache = NonZeroUsize::new(capacity).map(ReadCache::new);
+        self
+    }
+
+    /// Repack the object store after every `every` commits to main.
+    ///
+    /// Each write leaves loose objects behind until something packs them;
+    /// this runs [`gc`](Self::gc) on the commit that completes each window,
+    /// bounding their number at the cost of that commit's latency. Commits
+    /// since the last pack are counted from the loose commits on main, so
+    /// reopening the repository doesn't restart the window. Zero leaves
+    /// packing to git's own gc, the default.
+    pub fn with_auto_pack(mut self, every: usize) -> Self {
+        let inner = Arc::get_mut(&mut self.inner).expect("cannot modify shared repository");
+        inner.auto_pack = NonZeroUsize::new(every).map(|every| AutoPack { every, since: Mutex::new(None) });
+        self
+    }
+
+    /// Count a commit that moved main to `head`, and repack if it completes
+    /// the window set by [`with_auto_pack`](Self::with_auto_pack).
+    fn committed_to_main(&self, head: CommitId) -> StorageResult<()> {
+        let Some(auto_pack) = &self.inner.auto_pack else {
+            return Ok(());
+        };
+        let every = auto_pack.every.get();
+        let mut since = auto_pack.since.lock();
+        let count = match *since {
+            Some(count) => count + 1,
+            None => self.with_repo(|repo| gc::commits_since_pack(repo, head.raw(), every))?,
+        };
+        // The commit has already landed, so a failed repack only means
+        // trying again on the next one
+        *since = Some(if count >= every && self.gc().is_ok() { 0 } else { count });
+        Ok(())
+    }
+
+    /// Hit/miss counters of the read cache, if one is enabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.inner.cache.as_ref().map(ReadCache::stats)
+    }
+
+    /// Zero the read cache's hit/miss counters, if there is a cache.
+    pub fn reset_cache_stats(&self) {
+        if let Some(cache) = &self.inner.cache {
+            cache.reset_stats();
+        }
+    }
+
     /// Execute a function with read access to the repository.
     pub fn with_repo<F, T>(&self, f: F) -> StorageResult<T>
     where
         F: FnOnce(&Repository) -> StorageResult<T>,
     {
-        let repo = self.inner. repo.read();
+        let repo = self.inner.repo.lock();
         f(&repo)
     }
 
     /// Execute a function with write access to the repository.
+    ///
+    /// All mutations go through here, so this is where read-only mode is
+    /// enforced.
     pub fn with_repo_mut<F, T>(&self, f: F) -> StorageResult<T>
     where
         F: FnOnce(&Repository) -> StorageResult<T>,
     {
-        let repo = self.inner. repo.write();
+        if self.inner.read_only {
+            return Err(StorageError::ReadOnly);
+        }
+        let repo = self.inner.repo.lock();
         f(&repo)
     }
 
     // ==================== High-level Operations ====================
 
-    /// Get the current HEAD commit (tip of main branch).
+    /// Get the current HEAD commit: the tip of main, or of the branch given
+    /// to [`on_branch`](Self::on_branch), or the commit given to
+    /// [`pinned_at`](Self::pinned_at), or the in-memory head of a
+    /// [`detached`](Self::detached) handle.
     pub fn head(&self) -> StorageResult<CommitId> {
-        self.with_repo(|repo| RefManager::head_commit(repo))
+        if let Some(commit) = self.pinned {
+            return Ok(commit);
+        }
+        if let Some(head) = &self.detached {
+            return Ok(*head.lock());
+        }
+        match &self.branch {
+            Some(branch) => self.resolve_branch(branch),
+            None => self.with_repo(RefManager::head_commit),
+        }
+    }
+
+    /// Move this handle's branch, see [`head`](Self::head), to `target`.
+    pub fn advance_head(&self, target: CommitId) -> StorageResult<()> {
+        if let Some(head) = &self.detached {
+            if self.inner.read_only {
+                return Err(StorageError::ReadOnly);
+            }
+            if let Some(name) = &self.batch {
+                self.with_repo_mut(|repo| RefManager::set_batch_ref(repo, name, target))?;
+            }
+            *head.lock() = target;
+            return Ok(());
+        }
+        self.update_branch(&self.branch(), target)
     }
 
     /// Get the commit ID for a branch.
@@ -175,15 +467,51 @@ impl GitRepository {
             let new_tree_id = mutator.write()?;
 
             let message = CommitMessage::create_table(table. as_str(), tx_id);
-            CommitBuilder::new(repo)
+            self.commit_builder(repo)
                 . tree(new_tree_id)
                 .parent(at)
                 . message(message)
-                .signature(self.inner.signature. clone())
                 . commit()
         })
     }
 
+    /// Create a table and store its schema as `schema` in `schemas`, both in
+    /// a single commit.
+    ///
+    /// `schemas` is created if it doesn't exist yet. Fails if the table or
+    /// the schema row already exists.
+    /// Returns the new commit ID.
+    pub fn create_table_with_schema(
+        &self,
+        table: &TableName,
+        schemas: &TableName,
+        schema: Row,
+        at: CommitId,
+        tx_id: Option<&str>,
+    ) -> StorageResult<CommitId> {
+        self.with_repo_mut(|repo| {
+            let tree = commit::get_tree_at_commit(repo, at)?;
+            let blob_id = blob::write_blob(repo, &schema)?;
+
+            let mut mutator = TreeMutator::from_tree(repo, &tree)?;
+            if tree.table_exists(schemas) {
+                mutator.insert_row(repo, &tree, schemas, &schema.key, blob_id)?;
+            } else {
+                mutator.create_table(schemas)?;
+                mutator.upsert_row(schemas, &schema.key, blob_id)?;
+            }
+            mutator.create_table(table)?;
+            let new_tree_id = mutator.write()?;
+
+            let message = CommitMessage::create_table(table.as_str(), tx_id);
+            self.commit_builder(repo)
+                .tree(new_tree_id)
+                .parent(at)
+                .message(message)
+                .commit()
+        })
+    }
+
     /// Drop a table.
     ///
     /// Returns the new commit ID.
@@ -200,18 +528,144 @@ impl GitRepository {
             let new_tree_id = mutator.write()?;
 
             let message = CommitMessage::drop_table(table. as_str(), tx_id);
-            CommitBuilder::new(repo)
+            self.commit_builder(repo)
+                .tree(new_tree_id)
+                .parent(at)
+                .message(message)
+                .commit()
+        })
+    }
+
+    /// Drop a table together with the entries that describe it, in one commit.
+    ///
+    /// The table's own directory is removed if there is one. Each of `rows`
+    /// must exist and is deleted. Each `(table, name)` in `subtrees` is a
+    /// directory inside another table, removed if it is there.
+    ///
+    /// Returns the new commit ID.
+    pub fn drop_table_with(
+        &self,
+        table: &TableName,
+        rows: &[RowPath],
+        subtrees: &[(TableName, String)],
+        at: CommitId,
+        tx_id: Option<&str>,
+    ) -> StorageResult<CommitId> {
+        self.with_repo_mut(|repo| {
+            let tree = commit::get_tree_at_commit(repo, at)?;
+            let mut mutator = TreeMutator::from_tree(repo, &tree)?;
+            if tree.table_exists(table) {
+                mutator.drop_table(table)?;
+            }
+            for row in rows {
+                mutator.delete_row(&row.table, &row.key)?;
+            }
+            for (parent, name) in subtrees {
+                mutator.remove_subtree(parent, name)?;
+            }
+            let new_tree_id = mutator.write()?;
+
+            let message = CommitMessage::drop_table(table.as_str(), tx_id);
+            self.commit_builder(repo)
+                .tree(new_tree_id)
+                .parent(at)
+                .message(message)
+                .commit()
+        })
+    }
+
+    /// Rename a table, keeping its rows.
+    ///
+    /// Fails if `old` doesn't exist or `new` already does.
+    /// Returns the new commit ID.
+    pub fn rename_table(
+        &self,
+        old: &TableName,
+        new: &TableName,
+        at: CommitId,
+        tx_id: Option<&str>,
+    ) -> StorageResult<CommitId> {
+        self.rename_table_with(old, new, &[], &[], at, tx_id)
+    }
+
+    /// Rename a table together with the entries that describe it, in one
+    /// commit.
+    ///
+    /// Each `(path, row)` in `rows` replaces the row at `path`, which must
+    /// exist, with `row` in the same table under `row`'s own key. In each
+    /// table of `subtrees`, a directory named after `old` is renamed after
+    /// `new` if it is there.
+    ///
+    /// Returns the new commit ID.
+    pub fn rename_table_with(
+        &self,
+        old: &TableName,
+        new: &TableName,
+        rows: &[(RowPath, Row)],
+        subtrees: &[TableName],
+        at: CommitId,
+        tx_id: Option<&str>,
+    ) -> StorageResult<CommitId> {
+        self.with_repo_mut(|repo| {
+            let tree = commit::get_tree_at_commit(repo, at)?;
+            let mut mutator = TreeMutator::from_tree(repo, &tree)?;
+            mutator.rename_table(old, new)?;
+            for (path, row) in rows {
+                let blob_id = blob::write_blob(repo, row)?;
+                mutator.delete_row(&path.table, &path.key)?;
+                mutator.upsert_row(&path.table, &row.key, blob_id)?;
+            }
+            for parent in subtrees {
+                mutator.rename_subtree(parent, old.as_str(), new.as_str())?;
+            }
+            let new_tree_id = mutator.write()?;
+
+            let message = CommitMessage::rename_table(old.as_str(), new.as_str(), tx_id);
+            self.commit_builder(repo)
+                .tree(new_tree_id)
+                .parent(at)
+                .message(message)
+                .commit()
+        })
+    }
+
+    /// Rename a column of `table` by writing `rows`, the table's rewritten
+    /// rows and schemas, in one commit.
+    ///
+    /// Each `(table, row)` in `rows` is stored in `table` under `row`'s key,
+    /// replacing any row already there.
+    ///
+    /// Returns the new commit ID.
+    pub fn rename_column_with(
+        &self,
+        table: &TableName,
+        old: &str,
+        new: &str,
+        rows: &[(TableName, Row)],
+        at: CommitId,
+        tx_id: Option<&str>,
+    ) -> StorageResult<CommitId> {
+        self.with_repo_mut(|repo| {
+            let tree = commit::get_tree_at_commit(repo, at)?;
+            let mut mutator = TreeMutator::from_tree(repo, &tree)?;
+            for (table, row) in rows {
+                let blob_id = blob::write_blob(repo, row)?;
+                mutator.upsert_row(table, &row.key, blob_id)?;
+            }
+            let new_tree_id = mutator.write()?;
+
+            let message = CommitMessage::rename_column(table.as_str(), old, new, tx_id);
+            self.commit_builder(repo)
                 .tree(new_tree_id)
                 .parent(at)
                 .message(message)
-                .signature(self. inner.signature.clone())
                 .commit()
         })
     }
 
     // ==================== Row Operations ====================
 
-    /// List all row keys in a table.
+    /// List all row keys in a table, sorted.
     pub fn list_rows(&self, table: &TableName, at: CommitId) -> StorageResult<Vec<RowKey>> {
         self.with_repo(|repo| {
             let tree = commit::get_tree_at_commit(repo, at)?;
@@ -235,10 +689,45 @@ impl GitRepository {
                 None => return Ok(None),
             };
 
-            // Read and deserialize the blob
-            let bytes = blob::read_blob(repo, blob_id)?;
-            let row = blob::deserialize_row(&bytes, key)?;
-            Ok(Some(row))
+            self.load_row(repo, blob_id, key).map(Some)
+        })
+    }
+
+    /// Read a row's blob as stored: its id and the serialized bytes.
+    ///
+    /// The id is the content hash git computed over the bytes, so tools can
+    /// verify a row or index it externally without going through
+    /// [`read_row`](Self::read_row). Skips the read cache.
+    pub fn read_row_raw(
+        &self,
+        table: &TableName,
+        key: &RowKey,
+        at: CommitId,
+    ) -> StorageResult<Option<(BlobId, Vec<u8>)>> {
+        self.with_repo(|repo| {
+            let tree = commit::get_tree_at_commit(repo, at)?;
+            match tree.get_row_blob_id(repo, table, key)? {
+                Some(id) => Ok(Some((id, blob::read_blob(repo, id)?))),
+                None => Ok(None),
+            }
+        })
+    }
+
+    /// A row's blob id and size, from the object header without reading the
+    /// content.
+    pub fn row_blob_metadata(
+        &self,
+        table: &TableName,
+        key: &RowKey,
+        at: CommitId,
+    ) -> StorageResult<Option<BlobMetadata>> {
+        self.with_repo(|repo| {
+            let tree = commit::get_tree_at_commit(repo, at)?;
+            let Some(id) = tree.get_row_blob_id(repo, table, key)? else {
+                return Ok(None);
+            };
+            let (size, _) = repo.odb()?.read_header(id.raw())?;
+            Ok(Some(BlobMetadata { id, size }))
         })
     }
 
@@ -274,11 +763,47 @@ impl GitRepository {
 
             // Create commit
             let message = CommitMessage::insert(table.as_str(), row.key. as_str(), tx_id);
-            CommitBuilder::new(repo)
+            self.commit_builder(repo)
                 . tree(new_tree_id)
                 .parent(at)
                 .message(message)
-                . signature(self.inner.signature.clone())
+                .commit()
+        })
+    }
+
+    /// Insert a new row and write `extra` into `extra_table` in the same
+    /// commit, e.g. to keep bookkeeping for the table in step with its rows.
+    ///
+    /// `extra_table` is created if it doesn't exist yet, and `extra` replaces
+    /// any row with its key. Fails if the new row already exists.
+    /// Returns the new commit ID.
+    pub fn insert_row_with(
+        &self,
+        table: &TableName,
+        row: Row,
+        extra_table: &TableName,
+        extra: Row,
+        at: CommitId,
+        tx_id: Option<&str>,
+    ) -> StorageResult<CommitId> {
+        self.with_repo_mut(|repo| {
+            let tree = commit::get_tree_at_commit(repo, at)?;
+            let row_blob = blob::write_blob(repo, &row)?;
+            let extra_blob = blob::write_blob(repo, &extra)?;
+
+            let mut mutator = TreeMutator::from_tree(repo, &tree)?;
+            mutator.insert_row(repo, &tree, table, &row.key, row_blob)?;
+            if !tree.table_exists(extra_table) {
+                mutator.create_table(extra_table)?;
+            }
+            mutator.upsert_row(extra_table, &extra.key, extra_blob)?;
+            let new_tree_id = mutator.write()?;
+
+            let message = CommitMessage::insert(table.as_str(), row.key.as_str(), tx_id);
+            self.commit_builder(repo)
+                .tree(new_tree_id)
+                .parent(at)
+                .message(message)
                 .commit()
         })
     }
@@ -293,16 +818,54 @@ impl GitRepository {
         row: Row,
         at: CommitId,
         tx_id: Option<&str>,
+    ) -> StorageResult<CommitId> {
+        self.write_update(table, row, None, at, tx_id)
+    }
+
+    /// Update an existing row, provided it's still at `expected` version.
+    ///
+    /// This is optimistic locking: read a row, change it, and write it back
+    /// only if nobody else did in between. Fails with
+    /// [`StorageError::VersionMismatch`] if the stored row has another version.
+    pub fn update_row_if_version(
+        &self,
+        table: &TableName,
+        row: Row,
+        expected: u64,
+        at: CommitId,
+        tx_id: Option<&str>,
+    ) -> StorageResult<CommitId> {
+        self.write_update(table, row, Some(expected), at, tx_id)
+    }
+
+    fn write_update(
+        &self,
+        table: &TableName,
+        row: Row,
+        expected: Option<u64>,
+        at: CommitId,
+        tx_id: Option<&str>,
     ) -> StorageResult<CommitId> {
         self.with_repo_mut(|repo| {
             let tree = commit::get_tree_at_commit(repo, at)?;
 
             // Check if row exists
-            if !tree.row_exists(repo, table, &row. key)? {
+            let Some(current) = tree.get_row_blob_id(repo, table, &row.key)? else {
                 return Err(StorageError::RowNotFound {
                     table: table.clone(),
                     key: row. key.clone(),
                 });
+            };
+            if let Some(expected) = expected {
+                let found = self.load_row(repo, current, &row.key)?.version;
+                if found != expected {
+                    return Err(StorageError::VersionMismatch {
+                        table: table.clone(),
+                        key: row.key.clone(),
+                        expected,
+                        found,
+                    });
+                }
             }
 
             // Write the row as a blob
@@ -315,11 +878,10 @@ impl GitRepository {
 
             // Create commit
             let message = CommitMessage::update(table.as_str(), row.key.as_str(), tx_id);
-            CommitBuilder::new(repo)
+            self.commit_builder(repo)
                 .tree(new_tree_id)
                 .parent(at)
                 . message(message)
-                .signature(self.inner.signature. clone())
                 . commit()
         })
     }
@@ -353,11 +915,10 @@ impl GitRepository {
                 CommitMessage::insert(table.as_str(), row.key.as_str(), tx_id)
             };
 
-            CommitBuilder::new(repo)
+            self.commit_builder(repo)
                 .tree(new_tree_id)
                 .parent(at)
                 . message(message)
-                .signature(self.inner.signature. clone())
                 . commit()
         })
     }
@@ -383,41 +944,115 @@ impl GitRepository {
 
             // Create commit
             let message = CommitMessage::delete(table. as_str(), key.as_str(), tx_id);
-            CommitBuilder::new(repo)
+            self.commit_builder(repo)
                 .tree(new_tree_id)
                 .parent(at)
                 . message(message)
-                .signature(self.inner.signature. clone())
                 . commit()
         })
     }
 
-    /// Scan all rows in a table.
+    /// Scan all rows in a table, in key order.
     ///
-    /// Warning: This reads all rows into memory.  Use with caution on large tables.
+    /// Warning: This reads all rows into memory.  Use [`scan_iter`](Self::scan_iter)
+    /// to read them one at a time.
     pub fn scan_table(&self, table: &TableName, at: CommitId) -> StorageResult<Vec<Row>> {
-        self.with_repo(|repo| {
+        self.scan_iter(table, at)?.collect()
+    }
+
+    /// Scan a table lazily, reading one row blob per step.
+    ///
+    /// Only the table's listing of keys and blob ids is read up front. The
+    /// scan keeps its own handle on the repository and locks it just while
+    /// reading each blob, so it can outlive any borrow of `self`. It reads
+    /// the table as of `at`, whatever is committed in the meantime.
+    pub fn scan_iter(&self, table: &TableName, at: CommitId) -> StorageResult<TableScan> {
+        let entries = self.with_repo(|repo| {
             let tree = commit::get_tree_at_commit(repo, at)?;
-            let keys = tree.list_rows(repo, table)?;
-
-            let mut rows = Vec::with_capacity(keys. len());
-            for key in keys {
-                let blob_id = tree
-                    .get_row_blob_id(repo, table, &key)?
-                    .ok_or_else(|| StorageError::RowNotFound {
-                        table: table. clone(),
-                        key: key.clone(),
-                    })?;
-
-                let bytes = blob::read_blob(repo, blob_id)?;
-                let row = blob::deserialize_row(&bytes, &key)?;
-                rows.push(row);
-            }
+            let table_tree = tree
+                .get_table_tree(repo, table)?
+                .ok_or_else(|| StorageError::TableNotFound(table.clone()))?;
+            Ok(self.table_listing(&table_tree))
+        })?;
 
-            Ok(rows)
+        Ok(TableScan {
+            repo: self.clone(),
+            entries,
+            position: 0,
         })
     }
 
+    /// Scan a table lazily, yielding only the rows matching `predicate`.
+    ///
+    /// Rows are read one blob at a time as with [`scan_iter`](Self::scan_iter)
+    /// and checked as they're read, so the ones that don't match are never
+    /// kept. The predicate sees each row's data, with NULL for any column
+    /// it names that the row doesn't have, as in queries.
+    pub fn scan_where(
+        &self,
+        table: &TableName,
+        at: CommitId,
+        predicate: &Expr,
+    ) -> StorageResult<impl Iterator<Item = StorageResult<Row>>> {
+        let predicate = RowPredicate::new(predicate);
+        Ok(self.scan_iter(table, at)?.filter_map(move |row| {
+            let row = match row {
+                Ok(row) => row,
+                Err(e) => return Some(Err(e)),
+            };
+            match predicate.matches(&row) {
+                Ok(true) => Some(Ok(row)),
+                Ok(false) => None,
+                Err(e) => Some(Err(e)),
+            }
+        }))
+    }
+
+    /// Count the rows of a table matching `predicate`.
+    ///
+    /// Like [`scan_where`](Self::scan_where), but only the count is kept, so
+    /// memory use doesn't grow with the table.
+    pub fn count_where(&self, table: &TableName, at: CommitId, predicate: &Expr) -> StorageResult<usize> {
+        let predicate = RowPredicate::new(predicate);
+        let mut count = 0;
+        for row in self.scan_iter(table, at)? {
+            if predicate.matches(&row?)? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Read and deserialize a row blob, going through the cache if enabled.
+    fn load_row(&self, repo: &Repository, blob_id: BlobId, key: &RowKey) -> StorageResult<Row> {
+        if let Some(row) = self.inner.cache.as_ref().and_then(|c| c.get_row(blob_id)) {
+            // The blob records its own key, so a hit under another name is corrupt
+            if row.key == *key {
+                return Ok(row);
+            }
+        }
+
+        let bytes = blob::read_blob(repo, blob_id)?;
+        let row = blob::deserialize_row(&bytes, key)?;
+        if let Some(cache) = &self.inner.cache {
+            cache.put_row(blob_id, &row);
+        }
+        Ok(row)
+    }
+
+    /// Row entries of a table tree, going through the cache if enabled.
+    fn table_listing(&self, table_tree: &TreeHandle<'_>) -> TableListing {
+        let Some(cache) = &self.inner.cache else {
+            return Arc::new(table_tree.row_entries());
+        };
+        if let Some(listing) = cache.get_listing(table_tree.id()) {
+            return listing;
+        }
+        let listing = Arc::new(table_tree.row_entries());
+        cache.put_listing(table_tree.id(), listing.clone());
+        listing
+    }
+
     // ==================== Branch Operations ====================
 
     /// Create a new branch at the given commit.
@@ -432,7 +1067,26 @@ impl GitRepository {
 
     /// Update a branch to point to a new commit.
     pub fn update_branch(&self, branch: &BranchName, target: CommitId) -> StorageResult<()> {
-        self.with_repo_mut(|repo| RefManager::update_branch(repo, branch, target))
+        self.with_repo_mut(|repo| RefManager::update_branch(repo, branch, target))?;
+        if branch.as_str() == BranchName::MAIN {
+            self.committed_to_main(target)?;
+        }
+        Ok(())
+    }
+
+    /// Tag a commit, moving the tag if it already exists.
+    pub fn set_tag(&self, name: &str, target: CommitId) -> StorageResult<()> {
+        self.with_repo_mut(|repo| RefManager::set_tag(repo, name, target))
+    }
+
+    /// Get the commit a tag points at.
+    pub fn resolve_tag(&self, name: &str) -> StorageResult<Option<CommitId>> {
+        self.with_repo(|repo| RefManager::resolve_tag(repo, name))
+    }
+
+    /// Delete a tag if it exists.
+    pub fn delete_tag(&self, name: &str) -> StorageResult<()> {
+        self.with_repo_mut(|repo| RefManager::delete_tag(repo, name))
     }
 
     /// Check if a branch exists.
@@ -474,6 +1128,82 @@ impl GitRepository {
 
             Ok(tx_commit)
         })
+        .and_then(|head| self.committed_to_main(head).map(|()| head))
+    }
+
+    /// Merge a transaction branch onto main after main has moved on.
+    ///
+    /// Creates a merge commit with main and the transaction head as parents,
+    /// then moves main to it only if main still points to `expected_main`.
+    pub fn merge_into_main(
+        &self,
+        tx_branch: &BranchName,
+        expected_main: CommitId,
+        tx_id: &str,
+    ) -> StorageResult<CommitId> {
+        self.merge_into_main_resolved(tx_branch, expected_main, tx_id, &BTreeMap::new())
+    }
+
+    /// Merge a transaction branch onto main like [`merge_into_main`](Self::merge_into_main),
+    /// taking each path in `resolutions` as it is in the given commit.
+    pub fn merge_into_main_resolved(
+        &self,
+        tx_branch: &BranchName,
+        expected_main: CommitId,
+        tx_id: &str,
+        resolutions: &BTreeMap<PathBuf, CommitId>,
+    ) -> StorageResult<CommitId> {
+        self.with_repo_mut(|repo| {
+            let tx_commit = RefManager::resolve_branch(repo, tx_branch)?;
+            let tree = commit::merge_trees_resolved(repo, expected_main, tx_commit, resolutions)?;
+
+            let merged = self.commit_builder(repo)
+                .tree(tree)
+                .parents(vec![expected_main, tx_commit])
+                .message(CommitMessage::transaction_commit(tx_id))
+                .commit()?;
+
+            RefManager::update_branch_if_unchanged(repo, &BranchName::main(), expected_main, merged)?;
+            Ok(merged)
+        })
+        .and_then(|head| self.committed_to_main(head).map(|()| head))
+    }
+
+    /// Land the commits from `base` to `head` on main as one commit with
+    /// `message`, as [`detached`](Self::detached) handles are flushed.
+    ///
+    /// The commit has `head`'s tree and `base` as its parent; main is
+    /// fast-forwarded to it if it is still at `base`. If main has moved on,
+    /// the commit is merged onto it instead, failing with
+    /// [`StorageError::MergeConflict`] if the two changed the same rows.
+    pub fn publish_squashed(&self, base: CommitId, head: CommitId, message: &str) -> StorageResult<CommitId> {
+        if head == base {
+            return Ok(base);
+        }
+        self.with_repo_mut(|repo| {
+            let tree = commit::get_tree_at_commit(repo, head)?;
+            let squash = self.commit_builder(repo)
+                .tree(tree.id())
+                .parent(base)
+                .message(message)
+                .commit()?;
+
+            let main = BranchName::main();
+            let main_head = RefManager::resolve_branch(repo, &main)?;
+            if main_head == base {
+                RefManager::update_branch_if_unchanged(repo, &main, base, squash)?;
+                return Ok(squash);
+            }
+            let tree = commit::merge_trees(repo, main_head, squash)?;
+            let merged = self.commit_builder(repo)
+                .tree(tree)
+                .parents(vec![main_head, squash])
+                .message(message)
+                .commit()?;
+            RefManager::update_branch_if_unchanged(repo, &main, main_head, merged)?;
+            Ok(merged)
+        })
+        .and_then(|head| self.committed_to_main(head).map(|()| head))
     }
 
     /// Detect conflicts between a transaction branch and main.
@@ -511,6 +1241,60 @@ impl GitRepository {
         })
     }
 
+    /// Commits on the first-parent history of `to` since `from`, oldest first.
+    ///
+    /// Changes committed in a transaction arrive as the one commit that
+    /// brought them to main, so diffing each commit against its first parent
+    /// gives every change exactly once. If `from` isn't in the history of
+    /// `to`, as after a full VACUUM, this is all of `to`'s history.
+    pub fn commits_since(&self, from: CommitId, to: CommitId) -> StorageResult<Vec<CommitInfo>> {
+        self.with_repo(|repo| {
+            let history = commit::history(repo, to)?.first_parent_only();
+            // A vacuum may have pruned `from` altogether
+            let history = match repo.find_commit(from.raw()) {
+                Ok(_) => history.hiding(from)?,
+                Err(_) => history,
+            };
+            let mut commits = history.collect::<StorageResult<Vec<_>>>()?;
+            commits.reverse();
+            Ok(commits)
+        })
+    }
+
+    /// The most recent commit on the first-parent history of `at` that
+    /// changed a row, or `None` if the row doesn't exist at `at`.
+    ///
+    /// A commit changed the row if the row's blob differs from the one in
+    /// its first parent, so a row never modified since it was inserted
+    /// blames the commit that inserted it.
+    pub fn blame(&self, table: &TableName, key: &RowKey, at: CommitId) -> StorageResult<Option<CommitInfo>> {
+        self.with_repo(|repo| {
+            // A missing table just means the row wasn't there yet
+            let blob_at = |commit: CommitId| -> StorageResult<Option<BlobId>> {
+                match commit::get_tree_at_commit(repo, commit)?.get_row_blob_id(repo, table, key) {
+                    Err(StorageError::TableNotFound(_)) => Ok(None),
+                    result => result,
+                }
+            };
+
+            let current = match blob_at(at)? {
+                Some(blob) => blob,
+                None => return Ok(None),
+            };
+            for info in commit::history(repo, at)?.first_parent_only() {
+                let info = info?;
+                let before = match info.parent_ids.first() {
+                    Some(&parent) => blob_at(parent)?,
+                    None => None,
+                };
+                if before != Some(current) {
+                    return Ok(Some(info));
+                }
+            }
+            Ok(None)
+        })
+    }
+
     /// Get diff between two commits.
     pub fn diff(&self, old: CommitId, new: CommitId) -> StorageResult<Vec<crate::storage::types::Change>> {
         self.with_repo(|repo| commit::diff_commits(repo, old, new))
@@ -536,17 +1320,233 @@ impl GitRepository {
 
             let branches = RefManager::list_branches(repo, None)?;
             let tx_branches = RefManager::list_transaction_branches(repo)? ;
+            let objects = gc::object_store_stats(&repo.path().join("objects"))?;
 
             Ok(RepositoryStats {
                 table_count: tables. len(),
                 total_rows,
                 branch_count: branches.len(),
                 active_transactions: tx_branches. len(),
+                loose_objects: objects.loose_objects,
+                packed_objects: objects.packed_objects,
+                pack_size_bytes: objects.pack_size_bytes,
+                disk_size_bytes: objects.disk_size_bytes,
+                total_commits: None,
             })
         })
     }
+
+    /// Stats including `total_commits`, which walks the history from `at`.
+    pub fn stats_with_commits(&self, at: CommitId) -> StorageResult<RepositoryStats> {
+        let mut stats = self.stats(at)?;
+        stats.total_commits = Some(self.count_commits(at)?);
+        Ok(stats)
+    }
+
+    /// Count the commits reachable from `at`.
+    pub fn count_commits(&self, at: CommitId) -> StorageResult<usize> {
+        self.with_repo(|repo| {
+            let mut walk = repo.revwalk()?;
+            walk.push(at.raw())?;
+            let mut count = 0;
+            for id in walk {
+                id?;
+                count += 1;
+            }
+            Ok(count)
+        })
+    }
+
+    // ==================== Maintenance ====================
+
+    /// Replace main's history with a single root commit holding its current
+    /// tree, and return that commit.
+    ///
+    /// Tags on the current head move to the new commit. Refuses while a tag
+    /// points at an older commit of main, since it would point into the
+    /// discarded history, and while any transaction branch or
+    /// [batch](Self::detached_batch) is based on main. Other branches keep
+    /// their own history. The old commits stay in the object store until
+    /// [`gc_prune`](Self::gc_prune) removes them.
+    pub fn vacuum(&self) -> StorageResult<CommitId> {
+        self.squash(None, CommitMessage::vacuum)
+    }
+
+    /// Replace the commits between `base` and main's head with a single
+    /// commit on top of `base` holding the head's tree, and return it.
+    ///
+    /// History up to and including `base` is kept. Tags are handled as in
+    /// [`vacuum`](Self::vacuum), for the squashed commits only. Refuses if
+    /// `base` isn't in main's history, while a tag points at one of the
+    /// squashed commits other than the head, or while a transaction branch
+    /// or batch is based on one of them. Squashing at the head itself
+    /// changes nothing.
+    pub fn squash_since(&self, base: CommitId, message: &str) -> StorageResult<CommitId> {
+        self.squash(Some(base), |_| message.to_string())
+    }
+
+    /// Collapse main's commits after `base`, or all of them for `None`, into
+    /// one commit with the head's tree.
+    fn squash(&self, base: Option<CommitId>, message: impl FnOnce(CommitId) -> String) -> StorageResult<CommitId> {
+        self.with_repo_mut(|repo| {
+            let main = BranchName::main();
+            let head = RefManager::resolve_branch(repo, &main)?;
+            if base == Some(head) {
+                return Ok(head);
+            }
+
+            let mut walk = repo.revwalk()?;
+            walk.push(head.raw())?;
+            if let Some(base) = base {
+                if !repo.graph_descendant_of(head.raw(), base.raw())? {
+                    return Err(StorageError::NotInHistory(base.short()));
+                }
+                walk.hide(base.raw())?;
+            }
+            let squashed = walk.map(|id| id.map(CommitId::new)).collect::<Result<HashSet<_>, _>>()?;
+
+            // A transaction based on a squashed commit could no longer merge
+            let mut open = Vec::new();
+            for branch in RefManager::list_transaction_branches(repo)? {
+                let tip = RefManager::resolve_branch(repo, &branch)?;
+                if commit::find_merge_base(repo, tip, head)?.is_some_and(|at| squashed.contains(&at)) {
+                    open.push(branch);
+                }
+            }
+            if !open.is_empty() {
+                return Err(StorageError::OpenTransactions(open));
+            }
+            // Likewise a pending batch, which lands on main as a merge from its base
+            let mut pending = Vec::new();
+            for (name, tip) in RefManager::list_batch_refs(repo)? {
+                if commit::find_merge_base(repo, tip, head)?.is_some_and(|at| squashed.contains(&at)) {
+                    pending.push(name);
+                }
+            }
+            if !pending.is_empty() {
+                return Err(StorageError::PendingBatches(pending));
+            }
+
+            // Tags on the head follow it; any other tag in the range would be lost
+            let mut moved = Vec::new();
+            let mut stranded = Vec::new();
+            for tag in RefManager::list_tags(repo)? {
+                match RefManager::resolve_tag(repo, &tag)? {
+                    Some(target) if target == head => moved.push(tag),
+                    Some(target) if squashed.contains(&target) => stranded.push(tag),
+                    _ => {}
+                }
+            }
+            if !stranded.is_empty() {
+                return Err(StorageError::TaggedHistory(stranded));
+            }
+
+            let tree = commit::get_tree_at_commit(repo, head)?;
+            let squash = self.commit_builder(repo)
+                .tree(tree.id())
+                .parents(base.into_iter().collect())
+                .message(message(head))
+                .commit()?;
+
+            // Only touch tags once main is known to have moved to the squash
+            RefManager::update_branch_if_unchanged(repo, &main, head, squash)?;
+            for tag in moved {
+                RefManager::set_tag(repo, &tag, squash)?;
+            }
+            Ok(squash)
+        })
+    }
+
+    /// Pack every object reachable from a ref into a new pack file.
+    ///
+    /// Loose objects are left in place; use [`gc`](Self::gc) to remove them.
+    /// Returns the number of objects packed.
+    pub fn repack(&self) -> StorageResult<usize> {
+        self.with_repo_mut(|repo| Ok(gc::repack(repo)?.0.len()))
+    }
+
+    /// Repack, then delete the loose objects and old packs made redundant.
+    ///
+    /// Must not run while a transaction branch is mid-commit: its objects
+    /// are written before the ref that makes them reachable, so gc would
+    /// neither pack nor keep them. The write lock held here only keeps out
+    /// writers in this process. Falls back to `git gc` if packing through
+    /// libgit2 fails.
+    pub fn gc(&self) -> StorageResult<GcReport> {
+        self.run_gc(None)
+    }
+
+    /// Like [`gc`](Self::gc), but also delete objects no ref reaches, such
+    /// as the history [`vacuum`](Self::vacuum) discarded, once they are
+    /// older than [`PRUNE_GRACE`](gc::PRUNE_GRACE).
+    pub fn gc_prune(&self) -> StorageResult<GcReport> {
+        self.gc_prune_older_than(gc::PRUNE_GRACE)
+    }
+
+    /// Like [`gc_prune`](Self::gc_prune), with a grace period of `grace`.
+    ///
+    /// A zero grace also deletes objects that another process has written
+    /// but not yet referenced, so only use it when nothing else is writing.
+    pub fn gc_prune_older_than(&self, grace: Duration) -> StorageResult<GcReport> {
+        self.run_gc(Some(grace))
+    }
+
+    fn run_gc(&self, prune: Option<Duration>) -> StorageResult<GcReport> {
+        if self.inner.read_only {
+            return Err(StorageError::ReadOnly);
+        }
+        let mut repo = self.inner.repo.lock();
+        let report = gc::gc(&repo, prune)?;
+        // Drop libgit2's view of the deleted packs and loose objects
+        *repo = Repository::open(&self.inner.path)?;
+        Ok(report)
+    }
+}
+
+/// Lazy scan over a table's rows, in key order; see [`GitRepository::scan_iter`].
+pub struct TableScan {
+    repo: GitRepository,
+    entries: TableListing,
+    position: usize,
+}
+
+impl TableScan {
+    /// Start the scan over from the first row.
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+
+    /// Move past up to `n` rows without reading them, returning how many
+    /// were skipped.
+    pub fn skip_rows(&mut self, n: usize) -> usize {
+        let skipped = n.min(self.entries.len() - self.position);
+        self.position += skipped;
+        skipped
+    }
+}
+
+impl Iterator for TableScan {
+    type Item = StorageResult<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, blob_id) = self.entries.get(self.position)?;
+        self.position += 1;
+        Some(self.repo.with_repo(|repo| self.repo.load_row(repo, *blob_id, key)))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.skip_rows(n);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.entries.len() - self.position;
+        (remaining, Some(remaining))
+    }
 }
 
+impl ExactSizeIterator for TableScan {}
+
 /// A snapshot of the tree structure at a commit.
 #[derive(Debug, Clone)]
 pub struct TreeSnapshot {
@@ -561,6 +1561,16 @@ pub struct RepositoryStats {
     pub total_rows: usize,
     pub branch_count: usize,
     pub active_transactions: usize,
+    /// Objects stored as individual files; many means gc is worth running.
+    pub loose_objects: usize,
+    /// Objects stored in pack files.
+    pub packed_objects: usize,
+    /// Size of all pack files in bytes.
+    pub pack_size_bytes: u64,
+    /// Size of `.git/objects` in bytes.
+    pub disk_size_bytes: u64,
+    /// Commits in the history, only filled in by `stats_with_commits`.
+    pub total_commits: Option<usize>,
 }
 
 impl std::fmt::Display for RepositoryStats {
@@ -569,7 +1579,38 @@ impl std::fmt::Display for RepositoryStats {
         writeln!(f, "  Tables: {}", self. table_count)?;
         writeln!(f, "  Total Rows: {}", self.total_rows)? ;
         writeln!(f, "  Branches: {}", self.branch_count)?;
-        writeln!(f, "  Active Transactions: {}", self.active_transactions)
+        writeln!(f, "  Active Transactions: {}", self.active_transactions)?;
+        if let Some(commits) = self.total_commits {
+            writeln!(f, "  Commits: {}", commits)?;
+        }
+        writeln!(f, "  Loose Objects: {}", self.loose_objects)?;
+        writeln!(f, "  Packed Objects: {} ({} bytes)", self.packed_objects, self.pack_size_bytes)?;
+        writeln!(f, "  Disk Size: {} bytes", self.disk_size_bytes)
+    }
+}
+
+/// A predicate checked against stored rows, for [`GitRepository::scan_where`]
+/// and [`GitRepository::count_where`].
+struct RowPredicate {
+    predicate: Predicate,
+    /// Columns the predicate names, read as NULL where a row lacks them.
+    columns: Vec<String>,
+}
+
+impl RowPredicate {
+    fn new(expr: &Expr) -> Self {
+        let columns = expr.referenced_columns().into_iter().map(String::from).collect();
+        Self { predicate: Predicate::new(expr.clone()), columns }
+    }
+
+    fn matches(&self, row: &Row) -> StorageResult<bool> {
+        let mut values: serde_json::Map<String, serde_json::Value> = row.data.iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        for column in &self.columns {
+            values.entry(column.clone()).or_insert(serde_json::Value::Null);
+        }
+        Ok(self.predicate.matches(&mut values)?)
     }
 }
 
@@ -684,6 +1725,30 @@ mod tests {
         assert!(read_row.is_none());
     }
 
+    #[test]
+    fn test_read_row_raw() {
+        let (_dir, repo) = setup();
+        let table = TableName::new("users").unwrap();
+        let head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        let key = RowKey::new("user1").unwrap();
+        let mut data = BTreeMap::new();
+        data.insert("name".to_string(), serde_json::json!("Alice"));
+        let head = repo.insert_row(&table, Row::new(key.clone(), data), head, None).unwrap();
+
+        let (id, bytes) = repo.read_row_raw(&table, &key, head).unwrap().unwrap();
+        let hashed = git2::Oid::hash_object(git2::ObjectType::Blob, &bytes).unwrap();
+        assert_eq!(id.to_hex(), hashed.to_string());
+        assert_eq!(blob::deserialize_row(&bytes, &key).unwrap(), repo.read_row(&table, &key, head).unwrap().unwrap());
+
+        let metadata = repo.row_blob_metadata(&table, &key, head).unwrap().unwrap();
+        assert_eq!(metadata.id, id);
+        assert_eq!(metadata.size, bytes.len());
+
+        let missing = RowKey::new("user2").unwrap();
+        assert!(repo.read_row_raw(&table, &missing, head).unwrap().is_none());
+        assert!(repo.row_blob_metadata(&table, &missing, head).unwrap().is_none());
+    }
+
     #[test]
     fn test_scan_table() {
         let (_dir, repo) = setup();
@@ -704,6 +1769,518 @@ mod tests {
         // Scan
         let rows = repo.scan_table(&table, head).unwrap();
         assert_eq!(rows.len(), 5);
+
+        // A lazy scan reads the same rows and keeps reading the snapshot it started from
+        let mut scan = repo.scan_iter(&table, head).unwrap();
+        assert_eq!(scan.len(), 5);
+        let first = scan.next().unwrap().unwrap();
+        assert_eq!(first.key, rows[0].key);
+        repo.delete_row(&table, &rows[1].key, head, None).unwrap();
+        let rest: Vec<Row> = scan.by_ref().collect::<StorageResult<_>>().unwrap();
+        assert_eq!(rest, rows[1..]);
+        scan.rewind();
+        assert_eq!(scan.count(), 5);
+
+        let missing = TableName::new("missing").unwrap();
+        assert!(matches!(repo.scan_iter(&missing, head), Err(StorageError::TableNotFound(_))));
+    }
+
+    #[test]
+    fn test_scan_where() {
+        let (_dir, repo) = setup();
+        let repo = repo.with_cache(100);
+        let table = TableName::new("items").unwrap();
+        let mut head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        for i in 0..6 {
+            let mut data = BTreeMap::new();
+            data.insert("value".to_string(), serde_json::json!(i));
+            let row = Row::new(RowKey::new(format!("item{}", i)).unwrap(), data);
+            head = repo.insert_row(&table, row, head, None).unwrap();
+        }
+
+        let predicate = match crate::sql::Parser::parse("DELETE FROM items WHERE value % 2 = 1").unwrap() {
+            crate::sql::Statement::Delete(d) => d.where_clause.unwrap(),
+            _ => unreachable!(),
+        };
+        let mut matches = repo.scan_where(&table, head, &predicate).unwrap();
+        assert_eq!(matches.next().unwrap().unwrap().key.as_str(), "item1");
+        // Reading stops with the iterator: the listing and two blobs so far
+        let stats = repo.cache_stats().unwrap();
+        assert_eq!(stats.hits + stats.misses, 3);
+
+        let rest: Vec<_> = matches.map(|r| r.unwrap().key.into_string()).collect();
+        assert_eq!(rest, ["item3", "item5"]);
+
+        let missing = crate::sql::Expr::Column("missing".into());
+        assert_eq!(repo.scan_where(&table, head, &missing).unwrap().count(), 0);
+
+        let bad = match crate::sql::Parser::parse("DELETE FROM items WHERE value / 0 = 1").unwrap() {
+            crate::sql::Statement::Delete(d) => d.where_clause.unwrap(),
+            _ => unreachable!(),
+        };
+        let mut errors = repo.scan_where(&table, head, &bad).unwrap();
+        assert!(matches!(errors.next(), Some(Err(StorageError::Predicate(_)))));
+    }
+
+    #[test]
+    fn test_count_where() {
+        let (_dir, repo) = setup();
+        let table = TableName::new("items").unwrap();
+        let mut head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        for i in 0..5 {
+            let mut data = BTreeMap::new();
+            data.insert("active".to_string(), serde_json::json!(i % 2 == 0));
+            if i > 0 {
+                data.insert("value".to_string(), serde_json::json!(i));
+            }
+            let row = Row::new(RowKey::new(format!("item{}", i)).unwrap(), data);
+            head = repo.insert_row(&table, row, head, None).unwrap();
+        }
+
+        let count = |sql: &str| {
+            let predicate = match crate::sql::Parser::parse(sql).unwrap() {
+                crate::sql::Statement::Delete(d) => d.where_clause.unwrap(),
+                _ => unreachable!(),
+            };
+            repo.count_where(&table, head, &predicate).unwrap()
+        };
+        assert_eq!(count("DELETE FROM items WHERE active"), 3);
+        assert_eq!(count("DELETE FROM items WHERE value > 2"), 2);
+        assert_eq!(count("DELETE FROM items WHERE value IS NULL"), 1);
+        assert_eq!(count("DELETE FROM items WHERE FALSE"), 0);
+    }
+
+    #[test]
+    fn test_scan_is_in_key_order() {
+        let (dir, repo) = setup();
+        let table = TableName::new("items").unwrap();
+        let mut head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+
+        // File names sort `a-b.json` before `a.json`; keys sort the other way
+        for key in ["b", "a-b", "a", "c0", "c"] {
+            let row = Row::new(RowKey::new(key).unwrap(), BTreeMap::new());
+            head = repo.insert_row(&table, row, head, None).unwrap();
+        }
+        let expected = ["a", "a-b", "b", "c", "c0"];
+        let keys = |repo: &GitRepository| -> Vec<String> {
+            repo.scan_table(&table, head).unwrap().into_iter().map(|r| r.key.into_string()).collect()
+        };
+        assert_eq!(keys(&repo), expected);
+        let listed: Vec<String> = repo.list_rows(&table, head).unwrap().into_iter().map(RowKey::into_string).collect();
+        assert_eq!(listed, expected);
+
+        // Same order after repacking and reopening
+        repo.gc().unwrap();
+        let reopened = GitRepository::open(dir.path()).unwrap();
+        assert_eq!(keys(&reopened), expected);
+    }
+
+    #[test]
+    fn test_gc_packs_loose_objects() {
+        let (_dir, repo) = setup();
+        let head = repo.head().unwrap();
+
+        let table = TableName::new("items").unwrap();
+        let mut head = repo.create_table(&table, head, None).unwrap();
+        for i in 0..5 {
+            let key = RowKey::new(format!("item{}", i)).unwrap();
+            let mut data = BTreeMap::new();
+            data.insert("value".to_string(), serde_json::json!(i));
+            head = repo.insert_row(&table, Row::new(key, data), head, None).unwrap();
+        }
+        repo.update_branch(&BranchName::main(), head).unwrap();
+        assert!(repo.stats(head).unwrap().loose_objects > 0);
+
+        let report = repo.gc().unwrap();
+        assert!(report.objects_packed > 0);
+        let stats = repo.stats_with_commits(head).unwrap();
+        assert_eq!(stats.loose_objects, 0);
+        assert_eq!(stats.packed_objects, report.objects_packed);
+        assert!(stats.pack_size_bytes > 0);
+        // Initial commit, create table, five inserts
+        assert_eq!(stats.total_commits, Some(7));
+        assert_eq!(repo.stats(head).unwrap().total_commits, None);
+
+        // Everything is still readable from the pack, and a second gc is harmless
+        assert_eq!(repo.scan_table(&table, head).unwrap().len(), 5);
+        repo.gc().unwrap();
+        assert_eq!(repo.scan_table(&table, repo.head().unwrap()).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_gc_keeps_annotated_tags() {
+        let (dir, repo) = setup();
+        let table = TableName::new("items").unwrap();
+        let head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        repo.update_branch(&BranchName::main(), head).unwrap();
+
+        // Pack the tag object on its own, the way an outside `git gc` would,
+        // so the only copy lives in a pack the next gc replaces
+        let tag_id = repo
+            .with_repo(|git| {
+                let target = git.find_object(head.raw(), None)?;
+                let signature = git2::Signature::now("test", "test@example.com")?;
+                let tag_id = git.tag("v1", &target, &signature, "release", false)?;
+                let mut builder = git.packbuilder()?;
+                builder.insert_object(tag_id, None)?;
+                builder.write(&git.path().join("objects").join("pack"), 0o644)?;
+                Ok(tag_id)
+            })
+            .unwrap();
+        let hex = tag_id.to_string();
+        std::fs::remove_file(dir.path().join(".git/objects").join(&hex[..2]).join(&hex[2..])).unwrap();
+
+        repo.gc().unwrap();
+        assert_eq!(repo.resolve_tag("v1").unwrap(), Some(head));
+        let reopened = GitRepository::open(dir.path()).unwrap();
+        assert!(reopened.with_repo(|git| Ok(git.find_tag(tag_id)?.message() == Some("release"))).unwrap());
+    }
+
+    #[test]
+    fn test_auto_pack_every() {
+        let (dir, _) = setup();
+        let table = TableName::new("items").unwrap();
+        let insert = |repo: &GitRepository, i: usize| {
+            let row = Row::new(RowKey::new(format!("item{}", i)).unwrap(), BTreeMap::new());
+            let head = repo.insert_row(&table, row, repo.head().unwrap(), None).unwrap();
+            repo.update_branch(&BranchName::main(), head).unwrap();
+            repo.stats(head).unwrap().loose_objects
+        };
+
+        // The initial commit and the table make two; the first insert is the third
+        let repo = GitRepository::open(dir.path()).unwrap().with_auto_pack(3);
+        let head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        repo.update_branch(&BranchName::main(), head).unwrap();
+        assert_eq!(insert(&repo, 0), 0);
+        assert!(insert(&repo, 1) > 0);
+        drop(repo);
+
+        // Reopening picks the window up where it was
+        let repo = GitRepository::open(dir.path()).unwrap().with_auto_pack(3);
+        assert!(insert(&repo, 2) > 0);
+        assert_eq!(insert(&repo, 3), 0);
+        assert_eq!(repo.scan_table(&table, repo.head().unwrap()).unwrap().len(), 4);
+
+        // Branches other than main don't count
+        let repo = GitRepository::open(dir.path()).unwrap().with_auto_pack(1);
+        let branch = repo.create_transaction_branch("t1", repo.head().unwrap()).unwrap();
+        let row = Row::new(RowKey::new("item9").unwrap(), BTreeMap::new());
+        let tx_head = repo.insert_row(&table, row, repo.head().unwrap(), None).unwrap();
+        repo.update_branch(&branch, tx_head).unwrap();
+        assert!(repo.stats(tx_head).unwrap().loose_objects > 0);
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let (_dir, repo) = setup();
+        let table = TableName::new("items").unwrap();
+        let mut head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        let old = head;
+        for i in 0..3 {
+            let row = Row::new(RowKey::new(format!("item{}", i)).unwrap(), BTreeMap::new());
+            head = repo.insert_row(&table, row, head, None).unwrap();
+        }
+        repo.update_branch(&BranchName::main(), head).unwrap();
+        repo.set_tag("old", old).unwrap();
+        repo.set_tag("current", head).unwrap();
+
+        // An open transaction keeps history in place
+        repo.create_transaction_branch("tx1", head).unwrap();
+        assert!(matches!(repo.vacuum(), Err(StorageError::OpenTransactions(open)) if open.len() == 1));
+        assert_eq!(repo.head().unwrap(), head);
+        repo.delete_transaction_branch("tx1").unwrap();
+
+        // So does a tag on an older commit, which is left as it was
+        assert!(matches!(repo.vacuum(), Err(StorageError::TaggedHistory(tags)) if tags == vec!["old"]));
+        assert_eq!(repo.head().unwrap(), head);
+        assert_eq!(repo.resolve_tag("current").unwrap(), Some(head));
+        repo.delete_tag("old").unwrap();
+
+        let root = repo.vacuum().unwrap();
+        assert_eq!(repo.head().unwrap(), root);
+        assert!(repo.get_commit(root).unwrap().parent_ids.is_empty());
+        assert_eq!(repo.tree_at(root).unwrap().tree_id, repo.tree_at(head).unwrap().tree_id);
+        assert_eq!(repo.count_commits(root).unwrap(), 1);
+        assert_eq!(repo.resolve_tag("current").unwrap(), Some(root));
+
+        // Pruning removes the old commits for good
+        repo.gc_prune().unwrap();
+        assert!(repo.get_commit(head).is_ok());
+        repo.gc_prune_older_than(Duration::ZERO).unwrap();
+        assert!(repo.get_commit(head).is_err());
+        assert_eq!(repo.scan_table(&table, root).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_squash_since() {
+        let (_dir, repo) = setup();
+        let table = TableName::new("items").unwrap();
+        let base = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        let mut head = base;
+        let mut middle = base;
+        for i in 0..6 {
+            let row = Row::new(RowKey::new(format!("item{}", i)).unwrap(), BTreeMap::new());
+            head = repo.insert_row(&table, row, head, None).unwrap();
+            if i == 2 {
+                middle = head;
+            }
+        }
+        repo.update_branch(&BranchName::main(), head).unwrap();
+        repo.set_tag("milestone", base).unwrap();
+        repo.set_tag("load-3", middle).unwrap();
+        let commits = repo.count_commits(head).unwrap();
+        let tree = repo.tree_at(head).unwrap().tree_id;
+
+        // Not in main's history
+        let elsewhere = repo.delete_row(&table, &RowKey::new("item0").unwrap(), middle, None).unwrap();
+        assert!(matches!(repo.squash_since(elsewhere, "bulk load"), Err(StorageError::NotInHistory(_))));
+
+        // A transaction started inside the range blocks the squash; one
+        // started at the base doesn't
+        repo.create_transaction_branch("inside", middle).unwrap();
+        repo.create_transaction_branch("before", base).unwrap();
+        assert!(matches!(
+            repo.squash_since(base, "bulk load"),
+            Err(StorageError::OpenTransactions(open)) if open == vec![BranchName::for_transaction("inside")]
+        ));
+        assert_eq!(repo.head().unwrap(), head);
+        repo.delete_transaction_branch("inside").unwrap();
+
+        // A tag inside the range would be lost, so it blocks the squash too
+        assert!(matches!(
+            repo.squash_since(base, "bulk load"),
+            Err(StorageError::TaggedHistory(tags)) if tags == vec!["load-3"]
+        ));
+        assert_eq!(repo.resolve_tag("load-3").unwrap(), Some(middle));
+        repo.delete_tag("load-3").unwrap();
+        repo.set_tag("latest", head).unwrap();
+
+        let squashed = repo.squash_since(base, "bulk load").unwrap();
+        assert_eq!(repo.head().unwrap(), squashed);
+        let info = repo.get_commit(squashed).unwrap();
+        assert_eq!(info.parent_ids, vec![base]);
+        assert_eq!(info.message.trim(), "bulk load");
+        assert_eq!(repo.count_commits(squashed).unwrap(), commits - 5);
+        assert_eq!(repo.resolve_tag("milestone").unwrap(), Some(base));
+        assert_eq!(repo.resolve_tag("latest").unwrap(), Some(squashed));
+
+        // Trees are content-addressed, so the same id means byte-identical
+        assert_eq!(repo.tree_at(squashed).unwrap().tree_id, tree);
+
+        // Nothing after the head to squash
+        assert_eq!(repo.squash_since(squashed, "again").unwrap(), squashed);
+    }
+
+    #[test]
+    fn test_open_read_only() {
+        let (dir, repo) = setup();
+        let table = TableName::new("items").unwrap();
+        let head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        repo.update_branch(&BranchName::main(), head).unwrap();
+
+        let repo = GitRepository::open_read_only(dir.path()).unwrap();
+        assert!(repo.is_read_only());
+        assert!(repo.scan_table(&table, head).unwrap().is_empty());
+        let branches = repo.list_branches().unwrap();
+
+        let row = Row::new(RowKey::new("a").unwrap(), BTreeMap::new());
+        assert!(matches!(repo.insert_row(&table, row, head, None), Err(StorageError::ReadOnly)));
+        assert!(matches!(
+            repo.create_transaction_branch("tx", head),
+            Err(StorageError::ReadOnly)
+        ));
+        assert!(matches!(
+            repo.update_branch(&BranchName::main(), head),
+            Err(StorageError::ReadOnly)
+        ));
+        assert_eq!(repo.list_branches().unwrap(), branches);
+    }
+
+    #[test]
+    fn test_drop_table_with() {
+        let (_dir, repo) = setup();
+        let users = TableName::new("users").unwrap();
+        let schemas = TableName::new("_schemas").unwrap();
+        let mut head = repo.head().unwrap();
+        head = repo.create_table(&users, head, None).unwrap();
+        head = repo.create_table(&schemas, head, None).unwrap();
+        head = repo.insert_row(&users, Row::new(RowKey::new("a").unwrap(), BTreeMap::new()), head, None).unwrap();
+        head = repo.upsert_row(&schemas, Row::new(RowKey::new("users").unwrap(), BTreeMap::new()), head, None).unwrap();
+
+        // An index directory for the table, next to another table's
+        head = repo.with_repo_mut(|r| {
+            let tree = commit::get_tree_at_commit(r, head)?;
+            let mut entries = r.treebuilder(None)?;
+            entries.insert("a.json", r.blob(b"{}")?, 0o100644)?;
+            let entries = entries.write()?;
+            let mut indexes = r.treebuilder(None)?;
+            indexes.insert("users", entries, 0o040000)?;
+            indexes.insert("orders", entries, 0o040000)?;
+            let mut root = r.treebuilder(Some(tree.inner()))?;
+            root.insert("_indexes", indexes.write()?, 0o040000)?;
+            repo.commit_builder(r)
+                .tree(TreeId::new(root.write()?))
+                .parent(head)
+                .message("add indexes")
+                .commit()
+        }).unwrap();
+
+        let schema_row = RowPath::new(schemas.clone(), RowKey::new("users").unwrap());
+        let index = (TableName::new("_indexes").unwrap(), "users".to_string());
+
+        // A missing row fails the whole drop
+        let missing = RowPath::new(schemas.clone(), RowKey::new("nope").unwrap());
+        assert!(matches!(
+            repo.drop_table_with(&users, &[schema_row.clone(), missing], &[], head, None),
+            Err(StorageError::RowNotFound { .. })
+        ));
+
+        let dropped = repo.drop_table_with(&users, std::slice::from_ref(&schema_row), &[index], head, None).unwrap();
+        assert_eq!(repo.get_commit(dropped).unwrap().parent_ids, vec![head]);
+        assert!(!repo.table_exists(&users, dropped).unwrap());
+        assert!(repo.read_row(&schemas, &schema_row.key, dropped).unwrap().is_none());
+        let index_entries = repo.with_repo(|r| {
+            let tree = commit::get_tree_at_commit(r, dropped)?;
+            Ok(["users", "orders"].map(|t| tree.get_entry_at_path(Path::new(&format!("_indexes/{}", t))).is_some()))
+        }).unwrap();
+        assert_eq!(index_entries, [false, true]);
+    }
+
+    #[test]
+    fn test_rename_table_with() {
+        let (_dir, repo) = setup();
+        let users = TableName::new("users").unwrap();
+        let customers = TableName::new("customers").unwrap();
+        let schemas = TableName::new("_schemas").unwrap();
+        let row = |key: &str| Row::new(RowKey::new(key).unwrap(), BTreeMap::new());
+        let mut head = repo.head().unwrap();
+        head = repo.create_table(&users, head, None).unwrap();
+        head = repo.create_table(&schemas, head, None).unwrap();
+        head = repo.insert_row(&users, row("a"), head, None).unwrap();
+        head = repo.upsert_row(&schemas, row("users"), head, None).unwrap();
+
+        let schema_row = RowPath::new(schemas.clone(), RowKey::new("users").unwrap());
+        let renamed = repo.rename_table_with(
+            &users,
+            &customers,
+            &[(schema_row.clone(), row("customers"))],
+            &[TableName::new("_indexes").unwrap()],
+            head,
+            None,
+        ).unwrap();
+        assert_eq!(repo.get_commit(renamed).unwrap().parent_ids, vec![head]);
+        assert!(!repo.table_exists(&users, renamed).unwrap());
+        assert_eq!(repo.list_rows(&customers, renamed).unwrap(), vec![RowKey::new("a").unwrap()]);
+        assert_eq!(repo.list_rows(&schemas, renamed).unwrap(), vec![RowKey::new("customers").unwrap()]);
+
+        // The new name can't be taken, and every replaced row must exist
+        assert!(matches!(
+            repo.rename_table(&users, &schemas, head, None),
+            Err(StorageError::TableAlreadyExists(_))
+        ));
+        assert!(matches!(
+            repo.rename_table_with(&customers, &users, &[(schema_row, row("users"))], &[], renamed, None),
+            Err(StorageError::RowNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_create_table_with_schema() {
+        let (_dir, repo) = setup();
+        let users = TableName::new("users").unwrap();
+        let orders = TableName::new("orders").unwrap();
+        let schemas = TableName::new("_schemas").unwrap();
+        let schema = |name: &str| Row::new(RowKey::new(name).unwrap(), BTreeMap::new());
+        let head = repo.head().unwrap();
+
+        // The first table brings the schema directory with it
+        let created = repo.create_table_with_schema(&users, &schemas, schema("users"), head, None).unwrap();
+        assert_eq!(repo.get_commit(created).unwrap().parent_ids, vec![head]);
+        assert!(repo.table_exists(&users, created).unwrap());
+        assert!(repo.read_row(&schemas, &RowKey::new("users").unwrap(), created).unwrap().is_some());
+
+        // Neither an existing schema nor an existing table is overwritten
+        assert!(matches!(
+            repo.create_table_with_schema(&orders, &schemas, schema("users"), created, None),
+            Err(StorageError::RowAlreadyExists { .. })
+        ));
+        assert!(matches!(
+            repo.create_table_with_schema(&users, &schemas, schema("orders"), created, None),
+            Err(StorageError::TableAlreadyExists(_))
+        ));
+
+        let second = repo.create_table_with_schema(&orders, &schemas, schema("orders"), created, None).unwrap();
+        assert_eq!(repo.list_rows(&schemas, second).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_read_cache() {
+        let dir = TempDir::new().unwrap();
+        let repo = GitRepository::init(dir.path()).unwrap().with_cache(100);
+        let head = repo.head().unwrap();
+
+        let table = TableName::new("items").unwrap();
+        let mut head = repo.create_table(&table, head, None).unwrap();
+        for i in 0..3 {
+            let key = RowKey::new(format!("item{}", i)).unwrap();
+            let mut data = BTreeMap::new();
+            data.insert("value".to_string(), serde_json::json!(i));
+            head = repo.insert_row(&table, Row::new(key, data), head, None).unwrap();
+        }
+
+        // First scan misses on the listing and every row, second one hits
+        repo.scan_table(&table, head).unwrap();
+        assert_eq!(repo.cache_stats().unwrap(), CacheStats { hits: 0, misses: 4 });
+        repo.scan_table(&table, head).unwrap();
+        assert_eq!(repo.cache_stats().unwrap(), CacheStats { hits: 4, misses: 4 });
+
+        // An update writes a new blob, so readers see the new value
+        let key = RowKey::new("item1").unwrap();
+        let mut data = BTreeMap::new();
+        data.insert("value".to_string(), serde_json::json!(42));
+        let row = repo.read_row(&table, &key, head).unwrap().unwrap().with_update(data);
+        let head = repo.update_row(&table, row, head, None).unwrap();
+        let row = repo.read_row(&table, &key, head).unwrap().unwrap();
+        assert_eq!(row.data["value"], serde_json::json!(42));
+
+        assert!(setup().1.cache_stats().is_none());
+    }
+
+    /// Signs with a keyed hash of the content, standing in for GPG
+    struct KeyedSigner(u64);
+
+    impl CommitSigner for KeyedSigner {
+        fn sign(&self, content: &str) -> Result<String, String> {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            (self.0, content).hash(&mut hasher);
+            Ok(format!("keyed:{:016x}", hasher.finish()))
+        }
+
+        fn verify(&self, content: &str, signature: &str) -> Result<bool, String> {
+            Ok(self.sign(content)? == signature)
+        }
+    }
+
+    #[test]
+    fn test_signed_commits() {
+        let dir = TempDir::new().unwrap();
+        let repo = GitRepository::init(dir.path()).unwrap().with_signing(KeyedSigner(7));
+        let initial = repo.head().unwrap();
+
+        let table = TableName::new("users").unwrap();
+        let head = repo.create_table(&table, initial, None).unwrap();
+        assert_eq!(repo.verify_commit(head).unwrap(), SignatureStatus::Valid);
+        assert_eq!(repo.verify_commit(initial).unwrap(), SignatureStatus::Unsigned);
+
+        // Another key rejects the signature
+        let other = GitRepository::open(dir.path()).unwrap().with_signing(KeyedSigner(8));
+        assert_eq!(other.verify_commit(head).unwrap(), SignatureStatus::Invalid);
+
+        // Unsigned by default, and nothing to verify with
+        let (_dir, plain) = setup();
+        let head = plain.create_table(&table, plain.head().unwrap(), None).unwrap();
+        assert!(matches!(plain.verify_commit(head), Err(StorageError::Signing(_))));
     }
 
     #[test]
@@ -831,6 +2408,30 @@ mod tests {
         assert!(matches!(result, Err(StorageError::RowNotFound { .. })));
     }
 
+    #[test]
+    fn test_update_row_if_version() {
+        let (_dir, repo) = setup();
+        let table = TableName::new("users").unwrap();
+        let head = repo.create_table(&table, repo.head().unwrap(), None).unwrap();
+        let key = RowKey::new("user1").unwrap();
+        let head = repo.insert_row(&table, Row::new(key.clone(), BTreeMap::new()), head, None).unwrap();
+
+        // Two writers read version 1; the first one to write wins
+        let read = repo.read_row(&table, &key, head).unwrap().unwrap();
+        let first = read.clone().with_update(BTreeMap::from([("n".to_string(), serde_json::json!(1))]));
+        let second = read.with_update(BTreeMap::from([("n".to_string(), serde_json::json!(2))]));
+        let head = repo.update_row_if_version(&table, first, 1, head, None).unwrap();
+        let result = repo.update_row_if_version(&table, second, 1, head, None);
+        assert!(matches!(
+            result,
+            Err(StorageError::VersionMismatch { expected: 1, found: 2, .. })
+        ));
+        assert!(result.unwrap_err().is_conflict());
+
+        let row = repo.read_row(&table, &key, head).unwrap().unwrap();
+        assert_eq!((row.version, row.get("n")), (2, Some(&serde_json::json!(1))));
+    }
+
     #[test]
     fn test_delete_nonexistent_fails() {
         let (_dir, repo) = setup();
@@ -0,0 +1,27 @@
+//! Commit signing
+//!
+//! GitDB doesn't do any cryptography itself. A [`CommitSigner`] supplied by
+//! the application signs each commit's raw content, the same bytes `git
+//! commit -S` signs, and the signature is stored in the commit's `gpgsig`
+//! header where `git log --show-signature` finds it. GPG and SSH signatures
+//! both work that way.
+
+/// Signs commits and checks their signatures.
+pub trait CommitSigner: Send + Sync {
+    /// Sign a commit's raw content, returning an armored signature.
+    fn sign(&self, content: &str) -> Result<String, String>;
+
+    /// Check an armored signature over a commit's raw content.
+    fn verify(&self, content: &str, signature: &str) -> Result<bool, String>;
+}
+
+/// Outcome of verifying a commit's signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// The commit has no signature.
+    Unsigned,
+    /// The signer accepted the signature.
+    Valid,
+    /// The signer rejected the signature.
+    Invalid,
+}
@@ -91,14 +91,14 @@ impl<'repo> TreeHandle<'repo> {
         }
     }
 
-    /// list all row keys in a table
+    /// list all row keys in a table, in key order
     pub fn list_rows(&self, repo: &Repository, table: &TableName) -> StorageResult<Vec<RowKey>> {
         let table_tree = match self.get_table_tree(repo, table)? {
             Some(t) => t,
             None => return Err(StorageError::TableNotFound(table.clone())),
         };
 
-        let keys = table_tree
+        let mut keys: Vec<RowKey> = table_tree
             .tree
             .iter()
             .filter_map(|entry| {
@@ -117,9 +117,30 @@ impl<'repo> TreeHandle<'repo> {
             })
             .collect();
 
+        // tree order sorts by file name, which puts `a-b.json` before `a.json`
+        keys.sort();
         Ok(keys)
     }
 
+    /// list `(key, blob ID)` pairs for the row files of a table tree, in key order
+    ///
+    /// call this on the handle returned by `get_table_tree`.
+    pub fn row_entries(&self) -> Vec<(RowKey, BlobId)> {
+        let mut entries: Vec<(RowKey, BlobId)> = self.tree
+            .iter()
+            .filter_map(|entry| {
+                if entry.kind() != Some(ObjectType::Blob) {
+                    return None;
+                }
+                let key_str = entry.name()?.strip_suffix(".json")?;
+                let key = RowKey::new(key_str).ok()?;
+                Some((key, BlobId::new(entry.id())))
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
     /// get the blob ID for a specific row
     pub fn get_row_blob_id(&self, repo: &Repository, table: &TableName, key: &RowKey) -> StorageResult<Option<BlobId>> {
         let table_tree = match self.get_table_tree(repo, table)? {
@@ -278,6 +299,67 @@ impl<'repo> TreeMutator<'repo> {
         Ok(())
     }
 
+    /// rename a table's directory, keeping its rows
+    pub fn rename_table(&mut self, old: &TableName, new: &TableName) -> StorageResult<()> {
+        let (old_str, new_str) = (old.as_str(), new.as_str());
+
+        if !self.modified_tables.contains_key(old_str) && !self.original_tables.contains_key(old_str) {
+            return Err(StorageError::TableNotFound(old.clone()));
+        }
+        if self.modified_tables.contains_key(new_str) || self.original_tables.contains_key(new_str) {
+            return Err(StorageError::TableAlreadyExists(new.clone()));
+        }
+
+        // move whichever version of the table we have under the new name
+        if let Some(builder) = self.modified_tables.remove(old_str) {
+            self.modified_tables.insert(new_str.to_string(), builder);
+        }
+        if let Some(tree_id) = self.original_tables.remove(old_str) {
+            self.root_builder.insert(new_str, tree_id, FileMode::Tree.into())?;
+            self.original_tables.insert(new_str.to_string(), tree_id);
+        }
+        self.root_builder.remove(old_str)?;
+
+        Ok(())
+    }
+
+    /// rename the directory `from` inside a table to `to`, if both the table
+    /// and the directory exist
+    ///
+    /// returns whether there was anything to rename
+    pub fn rename_subtree(&mut self, table: &TableName, from: &str, to: &str) -> StorageResult<bool> {
+        let table_str = table.as_str();
+        if !self.modified_tables.contains_key(table_str) && !self.original_tables.contains_key(table_str) {
+            return Ok(false);
+        }
+
+        let table_builder = self.get_table_builder(table_str)?;
+        let subtree = match table_builder.get(from)? {
+            Some(entry) if entry.kind() == Some(ObjectType::Tree) => entry.id(),
+            _ => return Ok(false),
+        };
+        table_builder.remove(from)?;
+        table_builder.insert(to, subtree, FileMode::Tree.into())?;
+        Ok(true)
+    }
+
+    /// remove the directory `name` inside a table, if both exist
+    ///
+    /// returns whether there was anything to remove
+    pub fn remove_subtree(&mut self, table: &TableName, name: &str) -> StorageResult<bool> {
+        let table_str = table.as_str();
+        if !self.modified_tables.contains_key(table_str) && !self.original_tables.contains_key(table_str) {
+            return Ok(false);
+        }
+
+        let table_builder = self.get_table_builder(table_str)?;
+        let exists = matches!(table_builder.get(name)?, Some(entry) if entry.kind() == Some(ObjectType::Tree));
+        if exists {
+            table_builder.remove(name)?;
+        }
+        Ok(exists)
+    }
+
     /// insert or update a row in a table
     pub fn upsert_row(&mut self, table: &TableName, key: &RowKey, blob_id: BlobId) -> StorageResult<()> {
         let table_str = table. as_str();
@@ -459,6 +541,41 @@ mod tests {
         assert!(handle.list_tables().is_empty());
     }
 
+    #[test]
+    fn test_rename_table() {
+        let (_dir, repo) = setup_repo();
+        let tree_id = create_initial_tree(&repo).unwrap();
+        let tree = repo.find_tree(tree_id.raw()).unwrap();
+        let handle = TreeHandle::new(tree);
+        let users = TableName::new("users").unwrap();
+        let customers = TableName::new("customers").unwrap();
+
+        let mut mutator = TreeMutator::from_tree(&repo, &handle).unwrap();
+        mutator.create_table(&users).unwrap();
+        let tree_id = mutator.write().unwrap();
+        let tree = repo.find_tree(tree_id.raw()).unwrap();
+        let handle = TreeHandle::new(tree);
+
+        // rows written before the rename in the same mutator move along
+        let blob = repo.blob(b"{}").unwrap();
+        let mut mutator = TreeMutator::from_tree(&repo, &handle).unwrap();
+        mutator.upsert_row(&users, &RowKey::new("1").unwrap(), BlobId::new(blob)).unwrap();
+        let staging = TableName::new("staging").unwrap();
+        mutator.rename_table(&users, &staging).unwrap();
+        mutator.rename_table(&staging, &customers).unwrap();
+        assert!(matches!(mutator.rename_table(&users, &customers), Err(StorageError::TableNotFound(_))));
+        let tree_id = mutator.write().unwrap();
+
+        let tree = repo.find_tree(tree_id.raw()).unwrap();
+        let handle = TreeHandle::new(tree);
+        assert!(!handle.table_exists(&users));
+        assert_eq!(handle.list_rows(&repo, &customers).unwrap(), vec![RowKey::new("1").unwrap()]);
+
+        let mut mutator = TreeMutator::from_tree(&repo, &handle).unwrap();
+        mutator.create_table(&users).unwrap();
+        assert!(matches!(mutator.rename_table(&users, &customers), Err(StorageError::TableAlreadyExists(_))));
+    }
+
     #[test]
     fn test_upsert_and_list_rows() {
         let (_dir, repo) = setup_repo();
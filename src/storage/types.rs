@@ -48,6 +48,11 @@ impl BlobId {
     pub(crate) fn raw(&self) -> Oid {
         self.0
     }
+
+    /// The blob's full hex id, the same `git cat-file` takes.
+    pub fn to_hex(&self) -> String {
+        self.0.to_string()
+    }
 }
 
 impl fmt::Display for BlobId {
@@ -157,7 +162,7 @@ impl AsRef<str> for TableName {
 ///
 /// row keys are used as filenames, so they have similar restrictions
 /// to table names but are typically auto generated (ULIDs, UUIDs)
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct RowKey(String);
 
 impl RowKey {
@@ -178,8 +183,9 @@ impl RowKey {
         }
 
         for (i, c) in key.chars().enumerate() {
-            // alphanumeric, underscore, hyphen allowed
-            if !c.is_ascii_alphanumeric() && c != '_' && c != '-' {
+            // alphanumeric, underscore, hyphen allowed, plus the escape and
+            // separator of composite keys
+            if !c.is_ascii_alphanumeric() && !matches!(c, '_' | '-' | '%' | ':') {
                 return Err(InvalidNameError::InvalidCharacter { char: c, position: i });
             }
         }
@@ -187,6 +193,29 @@ impl RowKey {
         Ok(())
     }
 
+    /// Build a key from several parts, e.g. the columns of a composite primary key.
+    ///
+    /// Each part is percent-encoded, so any string is allowed, and the parts
+    /// are joined with `:`. Equal parts always give the same key.
+    pub fn from_parts<S: AsRef<str>>(parts: &[S]) -> Result<Self, InvalidNameError> {
+        let encoded: Vec<String> = parts
+            .iter()
+            .map(|part| {
+                part.as_ref()
+                    .bytes()
+                    .map(|b| {
+                        if b.is_ascii_alphanumeric() || b == b'_' || b == b'-' {
+                            (b as char).to_string()
+                        } else {
+                            format!("%{:02X}", b)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::new(encoded.join(":"))
+    }
+
     /// get the string representation
     pub fn as_str(&self) -> &str {
         &self.0
@@ -201,6 +230,22 @@ impl RowKey {
     pub fn generate() -> Self {
         Self(ulid::Ulid::new(). to_string(). to_lowercase())
     }
+
+    /// Generate a new row key from a UUIDv7, which sorts by creation time.
+    pub fn generate_uuid_v7() -> Self {
+        Self(uuid::Uuid::now_v7().to_string())
+    }
+
+    /// Generate a new row key from a random UUIDv4.
+    pub fn generate_uuid_v4() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    /// Row key for a number in a sequence, in decimal zero-padded to the
+    /// width of `u64::MAX`, so keys sort in numeric order.
+    pub fn sequential(n: u64) -> Self {
+        Self(format!("{:020}", n))
+    }
 }
 
 impl fmt::Display for RowKey {
@@ -383,6 +428,16 @@ pub struct Change {
     pub status: ChangeStatus,
 }
 
+impl Change {
+    /// the row the change is to, or `None` for a path outside a table's rows
+    pub fn row(&self) -> Option<RowPath> {
+        let path = self.path.to_str()?;
+        let (table, file) = path.split_once('/')?;
+        let key = file.strip_suffix(".json")?;
+        Some(RowPath::new(TableName::new(table).ok()?, RowKey::new(key).ok()?))
+    }
+}
+
 /// the type of change in a diff
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ChangeStatus {
@@ -399,6 +454,17 @@ pub enum ChangeStatus {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_change_row() {
+        let change = |path: &str| Change { path: PathBuf::from(path), status: ChangeStatus::Modified };
+
+        let row = change("users/42.json").row().unwrap();
+        assert_eq!(row.table.as_str(), "users");
+        assert_eq!(row.key.as_str(), "42");
+        assert!(change("users/.keep").row().is_none());
+        assert!(change("users").row().is_none());
+    }
+
     #[test]
     fn test_table_name_valid() {
         assert!(TableName::new("users").is_ok());
@@ -425,12 +491,34 @@ mod tests {
         assert!(RowKey::new("simple_key").is_ok());
     }
 
+    #[test]
+    fn test_row_key_from_parts() {
+        let key = RowKey::from_parts(&["acme", "42"]).unwrap();
+        assert_eq!(key.as_str(), "acme:42");
+
+        // Separators and other characters inside a part are escaped
+        let key = RowKey::from_parts(&["a:b", "c d%"]).unwrap();
+        assert_eq!(key.as_str(), "a%3Ab:c%20d%25");
+        assert_ne!(key, RowKey::from_parts(&["a", "b:c d%"]).unwrap());
+
+        assert!(RowKey::from_parts(&["", ""]).is_ok());
+        assert!(RowKey::from_parts::<&str>(&[]).is_err());
+    }
+
     #[test]
     fn test_row_key_generate() {
         let key1 = RowKey::generate();
         let key2 = RowKey::generate();
         assert_ne!(key1, key2);
         assert_eq!(key1.as_str(). len(), 26); // ULID length
+
+        // Every strategy's keys pass validation
+        for key in [RowKey::generate_uuid_v7(), RowKey::generate_uuid_v4(), RowKey::sequential(42)] {
+            assert_eq!(RowKey::new(key.as_str()).unwrap(), key);
+        }
+        assert_eq!(RowKey::generate_uuid_v7().as_str().as_bytes()[14], b'7');
+        assert_eq!(RowKey::sequential(42).as_str(), "00000000000000000042");
+        assert!(RowKey::sequential(9) < RowKey::sequential(10));
     }
 
     #[test]
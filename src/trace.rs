@@ -0,0 +1,36 @@
+//! Optional `tracing` instrumentation, enabled by the `tracing` feature.
+//!
+//! Parsing, planning, executing and committing run inside `tracing` spans
+//! (`parse`, `plan`, `execute`, `commit`) that a subscriber installed by the
+//! embedding application picks up. Functions declare their span with
+//! `#[cfg_attr(feature = "tracing", tracing::instrument(...))]`; fields only
+//! known at the end, such as rows affected, are filled in with [`record`].
+//! Without the feature both compile to nothing.
+
+use std::fmt;
+
+/// Record `field` on the current span, computing its value only if the span
+/// is being collected.
+#[cfg(feature = "tracing")]
+pub(crate) fn record<V: fmt::Display>(field: &'static str, value: impl FnOnce() -> V) {
+    let span = tracing::Span::current();
+    if !span.is_disabled() {
+        span.record(field, tracing::field::display(value()));
+    }
+}
+
+/// Record `field` on the current span; a no-op without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn record<V: fmt::Display>(_field: &'static str, _value: impl FnOnce() -> V) {}
+
+/// Log a warning about `error`, such as one a destructor can't return.
+#[cfg(feature = "tracing")]
+pub(crate) fn warn(message: &'static str, error: &dyn fmt::Display) {
+    tracing::warn!(error = %error, "{}", message);
+}
+
+/// Log a warning about `error`; a no-op without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn warn(_message: &'static str, _error: &dyn fmt::Display) {}
@@ -0,0 +1,227 @@
+//! Describing and resolving what a commit conflicted on.
+//!
+//! Storage reports conflicts as blob paths like `users/abc.json`. These are
+//! turned into the row they hold, with the version on each side, so callers
+//! can show or resolve them without knowing the repository layout.
+//!
+//! [`Transaction::commit_or_resolve`] hands conflicts back in a
+//! [`ConflictResolver`]: pick a side for each one, then
+//! [`resolve_and_commit`](ConflictResolver::resolve_and_commit) writes a merge
+//! commit taking those versions, much like finishing a `git rebase`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::SCHEMA_DIR;
+use crate::storage::{CommitId, GitRepository, Row, RowKey, StorageResult, TableName};
+use crate::transaction::context::{Transaction, TxActive, TxCommitted};
+use crate::transaction::error::{TransactionError, TransactionResult};
+
+/// Something changed both by a transaction and on main since it began.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    /// A row written on both sides.
+    Row(Box<RowConflict>),
+    /// A change that isn't a row, such as a table's schema in `_schemas/`.
+    Schema(PathBuf),
+}
+
+/// A row written on both sides, with both versions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowConflict {
+    pub table: TableName,
+    pub key: RowKey,
+    /// The transaction's version, `None` if it deleted the row.
+    pub ours: Option<Row>,
+    /// Main's version, `None` if it was deleted there.
+    pub theirs: Option<Row>,
+}
+
+impl Conflict {
+    /// Path of the conflicting blob in the repository.
+    pub fn path(&self) -> PathBuf {
+        match self {
+            Conflict::Row(row) => row_blob_path(&row.table, &row.key),
+            Conflict::Schema(path) => path.clone(),
+        }
+    }
+
+    /// Describe conflicting paths, reading rows from the transaction's commit
+    /// (`ours`) and main (`theirs`).
+    pub(crate) fn from_paths(
+        repo: &GitRepository,
+        paths: Vec<PathBuf>,
+        ours: CommitId,
+        theirs: CommitId,
+    ) -> StorageResult<Vec<Conflict>> {
+        paths
+            .into_iter()
+            .map(|path| {
+                let Some((table, key)) = row_path(&path) else {
+                    return Ok(Conflict::Schema(path));
+                };
+                Ok(Conflict::Row(Box::new(RowConflict {
+                    ours: read_if_table(repo, &table, &key, ours)?,
+                    theirs: read_if_table(repo, &table, &key, theirs)?,
+                    table,
+                    key,
+                })))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::Row(row) => write!(f, "row {}/{}", row.table, row.key),
+            Conflict::Schema(path) => write!(f, "schema {}", path.display()),
+        }
+    }
+}
+
+/// Which side's version to keep for a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The transaction's version.
+    Ours,
+    /// Main's version.
+    Theirs,
+}
+
+/// Result of [`Transaction::commit_or_resolve`].
+pub enum CommitOutcome {
+    /// Main now includes the transaction.
+    Committed(Transaction<TxCommitted>),
+    /// Nothing was published; resolve the conflicts and try again.
+    Conflicted(ConflictResolver),
+}
+
+/// A transaction whose commit conflicted, kept active until it is resolved.
+pub struct ConflictResolver {
+    tx: Transaction<TxActive>,
+    conflicts: Vec<Conflict>,
+    resolutions: BTreeMap<PathBuf, Resolution>,
+}
+
+impl ConflictResolver {
+    pub(crate) fn new(tx: Transaction<TxActive>, conflicts: Vec<Conflict>) -> Self {
+        Self {
+            tx,
+            conflicts,
+            resolutions: BTreeMap::new(),
+        }
+    }
+
+    /// ID of the conflicted transaction.
+    pub fn transaction_id(&self) -> &str {
+        self.tx.id()
+    }
+
+    /// Everything that conflicted, resolved or not.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Conflicts that have no side picked yet.
+    pub fn unresolved(&self) -> Vec<&Conflict> {
+        self.conflicts
+            .iter()
+            .filter(|c| !self.resolutions.contains_key(&c.path()))
+            .collect()
+    }
+
+    /// Pick which version of a conflicting row to keep.
+    pub fn pick(&mut self, table: &TableName, key: &RowKey, resolution: Resolution) -> TransactionResult<()> {
+        let path = row_blob_path(table, key);
+        if !self.conflicts.iter().any(|c| c.path() == path) {
+            return Err(TransactionError::InvalidOperation(format!(
+                "row {}/{} is not in conflict",
+                table, key
+            )));
+        }
+        self.resolutions.insert(path, resolution);
+        Ok(())
+    }
+
+    /// Pick the same side for every conflict that has none yet, schemas included.
+    pub fn pick_all(&mut self, resolution: Resolution) {
+        for conflict in &self.conflicts {
+            self.resolutions.entry(conflict.path()).or_insert(resolution);
+        }
+    }
+
+    /// Publish the transaction, keeping the picked version of each conflict.
+    ///
+    /// Returns the resolver again, with its picks, if some conflicts are
+    /// unresolved, including new ones from main moving on in the meantime.
+    pub fn resolve_and_commit(mut self) -> TransactionResult<CommitOutcome> {
+        if !self.unresolved().is_empty() {
+            return Ok(CommitOutcome::Conflicted(self));
+        }
+        match self.tx.publish(&self.resolutions) {
+            Err(TransactionError::Conflict { conflicts }) => {
+                self.conflicts = conflicts;
+                Ok(CommitOutcome::Conflicted(self))
+            }
+            result => self.tx.finish(result).map(CommitOutcome::Committed),
+        }
+    }
+
+    /// Give up on resolving and get the still active transaction back, to
+    /// keep working on it or roll it back.
+    pub fn into_transaction(self) -> Transaction<TxActive> {
+        self.tx
+    }
+}
+
+/// Where a row is stored, the inverse of `row_path`.
+fn row_blob_path(table: &TableName, key: &RowKey) -> PathBuf {
+    Path::new(table.as_str()).join(format!("{}.json", key.as_str()))
+}
+
+/// The table and key a `table/key.json` path holds.
+fn row_path(path: &Path) -> Option<(TableName, RowKey)> {
+    let mut components = path.iter();
+    let table = components.next()?.to_str()?;
+    let file = components.next()?.to_str()?;
+    if components.next().is_some() || table == SCHEMA_DIR {
+        return None;
+    }
+    let key = file.strip_suffix(".json")?;
+    Some((TableName::new(table).ok()?, RowKey::new(key).ok()?))
+}
+
+/// Read a row, treating a table missing on one side as a missing row.
+fn read_if_table(
+    repo: &GitRepository,
+    table: &TableName,
+    key: &RowKey,
+    at: CommitId,
+) -> StorageResult<Option<Row>> {
+    if !repo.table_exists(table, at)? {
+        return Ok(None);
+    }
+    repo.read_row(table, key, at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_path() {
+        let (table, key) = row_path(Path::new("users/abc.json")).unwrap();
+        assert_eq!(table.as_str(), "users");
+        assert_eq!(key.as_str(), "abc");
+
+        assert!(row_path(Path::new("_schemas/users.json")).is_none());
+        assert!(row_path(Path::new("users/abc.txt")).is_none());
+        assert!(row_path(Path::new("users")).is_none());
+        assert!(row_path(Path::new("users/nested/abc.json")).is_none());
+
+        let (table, key) = row_path(&row_blob_path(&table, &key)).unwrap();
+        assert_eq!((table.as_str(), key.as_str()), ("users", "abc"));
+    }
+}
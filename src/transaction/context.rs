@@ -8,14 +8,21 @@
 
 use std::collections::BTreeMap;
 use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use serde_json::Value;
 
 use crate::storage::{
     BranchName, CommitId, GitRepository, Row, RowKey, StorageError, TableName,
 };
+use crate::transaction::conflict::{CommitOutcome, Conflict, ConflictResolver, Resolution};
 use crate::transaction::error::{TransactionError, TransactionResult};
 use crate::transaction::isolation::IsolationLevel;
+use crate::transaction::lock::{LockManager, LockMode, LockTarget};
+
+/// Most times a commit re-merges after losing a race for main.
+const MAX_COMMIT_ATTEMPTS: usize = 16;
 
 /// Marker type for active transactions.
 #[derive(Debug)]
@@ -57,10 +64,34 @@ pub struct Transaction<State> {
     pub(crate) metadata: TransactionMetadata,
     /// Reference to the repository.
     pub(crate) repo: GitRepository,
+    /// Locks taken by this transaction.
+    locks: TxLocks,
     /// Phantom data for typestate.
     _state: PhantomData<State>,
 }
 
+/// The locks of one transaction, released when it ends or is dropped.
+struct TxLocks {
+    manager: Arc<LockManager>,
+    tx_id: String,
+}
+
+impl TxLocks {
+    fn acquire(&self, target: LockTarget, mode: LockMode) -> TransactionResult<()> {
+        self.manager.acquire(&self.tx_id, target, mode)
+    }
+
+    fn release(&self) {
+        self.manager.release_all(&self.tx_id);
+    }
+}
+
+impl Drop for TxLocks {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
 impl<State> Transaction<State> {
     /// Get the transaction ID.
     pub fn id(&self) -> &str {
@@ -81,6 +112,11 @@ impl<State> Transaction<State> {
     pub fn branch(&self) -> &BranchName {
         &self.metadata.branch
     }
+
+    /// Get when the transaction started.
+    pub fn started_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.metadata.started_at
+    }
 }
 
 impl Transaction<TxActive> {
@@ -92,6 +128,10 @@ impl Transaction<TxActive> {
         base_commit: CommitId,
         isolation: IsolationLevel,
     ) -> Self {
+        let locks = TxLocks {
+            manager: Arc::new(LockManager::new()),
+            tx_id: tx_id.clone(),
+        };
         Self {
             metadata: TransactionMetadata {
                 tx_id,
@@ -102,10 +142,20 @@ impl Transaction<TxActive> {
                 started_at: chrono::Utc::now(),
             },
             repo,
+            locks,
             _state: PhantomData,
         }
     }
 
+    /// Take locks in a shared lock manager instead of a private one.
+    pub(crate) fn with_lock_manager(mut self, manager: Arc<LockManager>) -> Self {
+        self.locks = TxLocks {
+            manager,
+            tx_id: self.metadata.tx_id.clone(),
+        };
+        self
+    }
+
     /// Get the commit to read from based on isolation level.
     ///
     /// For both isolation levels, we read from the transaction's current commit
@@ -126,10 +176,18 @@ impl Transaction<TxActive> {
         self.metadata.current_commit
     }
 
+    /// Pick up commits made directly on the transaction branch, e.g. through
+    /// a repository handle from [`GitRepository::on_branch`].
+    pub(crate) fn sync_with_branch(&mut self) -> TransactionResult<()> {
+        self.metadata.current_commit = self.repo.resolve_branch(&self.metadata.branch)?;
+        Ok(())
+    }
+
     // ==================== Table Operations ====================
 
     /// Create a new table.
     pub fn create_table(&mut self, table: &TableName) -> TransactionResult<()> {
+        self.locks.acquire(LockTarget::table(table.as_str()), LockMode::Exclusive)?;
         let new_commit = self.repo.create_table(
             table,
             self.metadata.current_commit,
@@ -142,6 +200,7 @@ impl Transaction<TxActive> {
 
     /// Drop a table.
     pub fn drop_table(&mut self, table: &TableName) -> TransactionResult<()> {
+        self.locks.acquire(LockTarget::table(table.as_str()), LockMode::Exclusive)?;
         let new_commit = self.repo.drop_table(
             table,
             self.metadata.current_commit,
@@ -168,6 +227,7 @@ impl Transaction<TxActive> {
 
     /// Insert a new row.
     pub fn insert(&mut self, table: &TableName, row: Row) -> TransactionResult<()> {
+        self.lock_row(table, &row.key, LockMode::Exclusive)?;
         let new_commit = self.repo.insert_row(
             table,
             row,
@@ -192,6 +252,7 @@ impl Transaction<TxActive> {
 
     /// Update an existing row.
     pub fn update(&mut self, table: &TableName, row: Row) -> TransactionResult<()> {
+        self.lock_row(table, &row.key, LockMode::Exclusive)?;
         let new_commit = self.repo.update_row(
             table,
             row,
@@ -205,6 +266,7 @@ impl Transaction<TxActive> {
 
     /// Insert or update a row (upsert).
     pub fn upsert(&mut self, table: &TableName, row: Row) -> TransactionResult<()> {
+        self.lock_row(table, &row.key, LockMode::Exclusive)?;
         let new_commit = self.repo.upsert_row(
             table,
             row,
@@ -218,6 +280,7 @@ impl Transaction<TxActive> {
 
     /// Delete a row.
     pub fn delete(&mut self, table: &TableName, key: &RowKey) -> TransactionResult<()> {
+        self.lock_row(table, key, LockMode::Exclusive)?;
         let new_commit = self.repo.delete_row(
             table,
             key,
@@ -231,22 +294,29 @@ impl Transaction<TxActive> {
 
     /// Read a single row.
     pub fn read(&self, table: &TableName, key: &RowKey) -> TransactionResult<Option<Row>> {
+        self.lock_row(table, key, LockMode::Shared)?;
         let commit = self.read_commit()?;
         self.repo.read_row(table, key, commit).map_err(TransactionError::from)
     }
 
     /// Scan all rows in a table.
     pub fn scan(&self, table: &TableName) -> TransactionResult<Vec<Row>> {
+        self.locks.acquire(LockTarget::table(table.as_str()), LockMode::Shared)?;
         let commit = self.read_commit()?;
         self.repo.scan_table(table, commit).map_err(TransactionError::from)
     }
 
     /// List all row keys in a table.
     pub fn list_keys(&self, table: &TableName) -> TransactionResult<Vec<RowKey>> {
+        self.locks.acquire(LockTarget::table(table.as_str()), LockMode::Shared)?;
         let commit = self.read_commit()?;
         self.repo.list_rows(table, commit).map_err(TransactionError::from)
     }
 
+    fn lock_row(&self, table: &TableName, key: &RowKey, mode: LockMode) -> TransactionResult<()> {
+        self.locks.acquire(LockTarget::row(table.as_str(), key.as_str()), mode)
+    }
+
     // ==================== Transaction Control ====================
 
     /// Update the transaction branch to point to current commit.
@@ -258,58 +328,109 @@ impl Transaction<TxActive> {
 
     /// Commit the transaction.
     ///
-    /// This attempts to fast-forward main to include our changes.
-    /// Returns error if there are conflicts with concurrent transactions.
+    /// Fast-forwards main if it hasn't moved since the transaction began.
+    /// Otherwise the transaction's changes are merged onto the new main, which
+    /// succeeds as long as no row was changed on both sides. Locks are
+    /// released whether or not the commit succeeds, and on a conflict the
+    /// transaction's changes are discarded; see
+    /// [`commit_or_resolve`](Self::commit_or_resolve) to keep them.
     pub fn commit(self) -> TransactionResult<Transaction<TxCommitted>> {
-        // Check for conflicts by seeing if main has moved
-        let main_head = self.repo.head()?;
-
-        if main_head != self.metadata.base_commit {
-            // Main has moved - check for conflicts
-            let conflicts = self.repo.detect_conflicts(&self.metadata.branch, main_head)?;
-            if !conflicts.is_empty() {
-                // Clean up the branch before returning error
-                let _ = self.repo.delete_transaction_branch(&self.metadata.tx_id);
-                return Err(TransactionError::Conflict { paths: conflicts });
-            }
-        }
+        let result = self.publish(&BTreeMap::new());
+        self.finish(result)
+    }
 
-        // Fast-forward main to our commit
-        match self.repo.fast_forward_main(&self.metadata.branch, self.metadata.base_commit) {
-            Ok(_) => {}
-            Err(StorageError::ConcurrentModification { .. }) => {
-                // Another transaction just committed - retry detection
-                let main_head = self.repo.head()?;
-                let conflicts = self.repo.detect_conflicts(&self.metadata.branch, main_head)?;
-                let _ = self.repo.delete_transaction_branch(&self.metadata.tx_id);
-                return Err(TransactionError::Conflict { paths: conflicts });
-            }
-            Err(e) => {
-                let _ = self.repo.delete_transaction_branch(&self.metadata.tx_id);
-                return Err(TransactionError::Storage(e));
+    /// Commit the transaction, or hand back its conflicts to resolve.
+    ///
+    /// Like [`commit`](Self::commit), except that on a conflict the
+    /// transaction stays active, with its branch and locks intact, inside the
+    /// returned [`ConflictResolver`].
+    pub fn commit_or_resolve(self) -> TransactionResult<CommitOutcome> {
+        match self.publish(&BTreeMap::new()) {
+            Err(TransactionError::Conflict { conflicts }) => {
+                Ok(CommitOutcome::Conflicted(ConflictResolver::new(self, conflicts)))
             }
+            result => self.finish(result).map(CommitOutcome::Committed),
         }
+    }
 
-        // Clean up the transaction branch
+    /// End the transaction with the result of publishing it.
+    pub(crate) fn finish(mut self, result: TransactionResult<CommitId>) -> TransactionResult<Transaction<TxCommitted>> {
         let _ = self.repo.delete_transaction_branch(&self.metadata.tx_id);
+        self.locks.release();
 
+        self.metadata.current_commit = result?;
         Ok(Transaction {
             metadata: self.metadata,
             repo: self.repo,
+            locks: self.locks,
             _state: PhantomData,
         })
     }
 
+    /// Move main to include this transaction's changes; returns main's new head.
+    ///
+    /// Paths changed on both sides conflict unless `resolutions` says which
+    /// side to keep.
+    pub(crate) fn publish(&self, resolutions: &BTreeMap<PathBuf, Resolution>) -> TransactionResult<CommitId> {
+        for _ in 0..MAX_COMMIT_ATTEMPTS {
+            let main_head = self.repo.head()?;
+
+            let result = if main_head == self.metadata.base_commit {
+                self.repo.fast_forward_main(&self.metadata.branch, main_head)
+            } else {
+                // Main has moved - only merge if every path touched on both
+                // sides has been resolved
+                let conflicts = self.repo.detect_conflicts(&self.metadata.branch, main_head)?;
+                if conflicts.iter().any(|path| !resolutions.contains_key(path)) {
+                    return Err(self.conflict(conflicts, main_head));
+                }
+                let sources = conflicts
+                    .into_iter()
+                    .map(|path| {
+                        let source = match resolutions[&path] {
+                            Resolution::Ours => self.metadata.current_commit,
+                            Resolution::Theirs => main_head,
+                        };
+                        (path, source)
+                    })
+                    .collect();
+                self.repo.merge_into_main_resolved(&self.metadata.branch, main_head, &self.metadata.tx_id, &sources)
+            };
+
+            match result {
+                Ok(commit) => return Ok(commit),
+                // Another transaction moved main first; merge onto its commit
+                Err(StorageError::ConcurrentModification { .. }) => continue,
+                Err(StorageError::MergeConflict { conflicting_paths }) => {
+                    return Err(self.conflict(conflicting_paths, main_head));
+                }
+                Err(e) => return Err(TransactionError::Storage(e)),
+            }
+        }
+
+        Err(TransactionError::SerializationFailure)
+    }
+
+    /// Describe conflicting paths as the rows they hold on each side.
+    fn conflict(&self, paths: Vec<PathBuf>, main_head: CommitId) -> TransactionError {
+        match Conflict::from_paths(&self.repo, paths, self.metadata.current_commit, main_head) {
+            Ok(conflicts) => TransactionError::Conflict { conflicts },
+            Err(e) => TransactionError::Storage(e),
+        }
+    }
+
     /// Rollback the transaction.
     ///
     /// This simply deletes the transaction branch, discarding all changes.
     pub fn rollback(self) -> TransactionResult<Transaction<TxAborted>> {
         // Clean up the transaction branch
         let _ = self.repo.delete_transaction_branch(&self.metadata.tx_id);
+        self.locks.release();
 
         Ok(Transaction {
             metadata: self.metadata,
             repo: self.repo,
+            locks: self.locks,
             _state: PhantomData,
         })
     }
@@ -1,10 +1,9 @@
 //! Transaction error types.
 
-use std::path::PathBuf;
-
 use thiserror::Error;
 
 use crate::storage::StorageError;
+use crate::transaction::conflict::Conflict;
 
 /// Result type for transaction operations.
 pub type TransactionResult<T> = Result<T, TransactionError>;
@@ -17,10 +16,10 @@ pub enum TransactionError {
     Storage(#[from] StorageError),
 
     /// Transaction conflict - another transaction modified the same data.
-    #[error("transaction conflict on paths: {}", paths_display(.paths))]
+    #[error("transaction conflict on {}", conflicts_display(.conflicts))]
     Conflict {
-        /// Paths that were modified by both this transaction and another.
-        paths: Vec<PathBuf>,
+        /// What was modified by both this transaction and another.
+        conflicts: Vec<Conflict>,
     },
 
     /// Transaction was already committed or aborted.
@@ -60,10 +59,10 @@ pub enum TransactionError {
     Internal(String),
 }
 
-fn paths_display(paths: &[PathBuf]) -> String {
-    paths
+fn conflicts_display(conflicts: &[Conflict]) -> String {
+    conflicts
         .iter()
-        .map(|p| p.display().to_string())
+        .map(|c| c.to_string())
         .collect::<Vec<_>>()
         .join(", ")
 }
@@ -76,25 +75,43 @@ impl TransactionError {
             TransactionError::Conflict { .. }
                 | TransactionError::SerializationFailure
                 | TransactionError::Timeout { .. }
+                | TransactionError::Deadlock { .. }
         )
     }
 
-    /// Create a conflict error from a list of paths.
-    pub fn conflict(paths: Vec<PathBuf>) -> Self {
-        Self::Conflict { paths }
+    /// A short name for the kind of error, stable across releases.
+    ///
+    /// Wrapped errors report their own kind.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TransactionError::Storage(e) => e.kind(),
+            TransactionError::Conflict { .. } => "transaction_conflict",
+            TransactionError::NotActive { .. } => "transaction_not_active",
+            TransactionError::NotFound(_) => "transaction_not_found",
+            TransactionError::Timeout { .. } => "transaction_timeout",
+            TransactionError::Deadlock { .. } => "deadlock",
+            TransactionError::InvalidOperation(_) => "invalid_operation",
+            TransactionError::SerializationFailure => "serialization_failure",
+            TransactionError::Internal(_) => "internal",
+        }
+    }
+
+    /// Create a conflict error from what was modified on both sides.
+    pub fn conflict(conflicts: Vec<Conflict>) -> Self {
+        Self::Conflict { conflicts }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::PathBuf;
 
     #[test]
     fn test_error_retryable() {
-        let conflict = TransactionError::Conflict {
-            paths: vec![PathBuf::from("users/123.json")],
-        };
+        let conflict = TransactionError::conflict(vec![Conflict::Schema(PathBuf::from("_schemas/users.json"))]);
         assert!(conflict.is_retryable());
+        assert_eq!(conflict.to_string(), "transaction conflict on schema _schemas/users.json");
 
         let not_active = TransactionError::NotActive {
             tx_id: "tx001".to_string(),
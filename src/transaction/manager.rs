@@ -4,19 +4,29 @@
 //! It handles:
 //! - Transaction creation and lifecycle
 //! - Tracking active transactions
-//! - Serializing commits to main
+//! - Owning the lock table shared by its transactions
 //! - Cleanup of abandoned transactions
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use parking_lot::{Mutex, RwLock};
+use parking_lot::RwLock;
 use ulid::Ulid;
 
 use crate::storage::{CommitId, GitRepository};
+use crate::transaction::conflict::{CommitOutcome, ConflictResolver};
 use crate::transaction::context::{Transaction, TransactionMetadata, TxActive};
 use crate::transaction::error::{TransactionError, TransactionResult};
 use crate::transaction::isolation::IsolationLevel;
+use crate::transaction::lock::LockManager;
+
+/// Delay before the first retry in `commit_with_retry`; doubles each attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(5);
+
+/// Longest delay between two attempts in `commit_with_retry`.
+const RETRY_MAX_DELAY: Duration = Duration::from_millis(500);
 
 /// Transaction manager - coordinates all transaction operations.
 ///
@@ -31,22 +41,35 @@ struct TransactionManagerInner {
     repo: GitRepository,
     /// Active transactions tracked by ID.
     active: RwLock<HashMap<String, TransactionMetadata>>,
-    /// Mutex for serializing commits to main branch.
-    commit_lock: Mutex<()>,
+    /// Row and table locks of all transactions.
+    locks: Arc<LockManager>,
+    /// Commits that failed or stopped on a conflict with another transaction.
+    conflicts: AtomicU64,
 }
 
 impl TransactionManager {
     /// Create a new transaction manager for the given repository.
     pub fn new(repo: GitRepository) -> Self {
+        Self::with_lock_manager(repo, LockManager::new())
+    }
+
+    /// Create a transaction manager using the given lock manager.
+    pub fn with_lock_manager(repo: GitRepository, locks: LockManager) -> Self {
         Self {
             inner: Arc::new(TransactionManagerInner {
                 repo,
                 active: RwLock::new(HashMap::new()),
-                commit_lock: Mutex::new(()),
+                locks: Arc::new(locks),
+                conflicts: AtomicU64::new(0),
             }),
         }
     }
 
+    /// Get the lock manager shared by this manager's transactions.
+    pub fn lock_manager(&self) -> &LockManager {
+        &self.inner.locks
+    }
+
     /// Get a reference to the underlying repository.
     pub fn repo(&self) -> &GitRepository {
         &self.inner.repo
@@ -81,7 +104,8 @@ impl TransactionManager {
             branch.clone(),
             base_commit,
             isolation,
-        );
+        )
+        .with_lock_manager(self.inner.locks.clone());
 
         // Track in active transactions
         {
@@ -119,26 +143,65 @@ impl TransactionManager {
         self.inner.active.write().remove(tx_id);
     }
 
-    /// Commit a transaction with serialization.
+    /// Commit a transaction.
     ///
-    /// This acquires a lock to ensure only one transaction commits at a time,
-    /// preventing race conditions during the fast-forward operation.
+    /// Transactions that touched different rows commit independently: main
+    /// is moved with a compare-and-swap, and a transaction that loses the race
+    /// is merged onto the winner. Returns the new head of main.
     pub fn commit_transaction(
         &self,
         tx: Transaction<TxActive>,
     ) -> TransactionResult<CommitId> {
-        // Acquire commit lock to serialize commits
-        let _guard = self.inner.commit_lock.lock();
+        let tx_id = tx.id().to_string();
 
+        // Perform the commit; it is no longer active either way
+        let committed = tx.commit();
+        self.mark_completed(&tx_id);
+        if committed.as_ref().is_err_and(is_contention) {
+            self.inner.conflicts.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Ok(committed?.final_commit())
+    }
+
+    /// Commit a transaction, or hand back its conflicts to resolve.
+    ///
+    /// A conflicted transaction stays active; finish it with
+    /// [`resolve_and_commit`](Self::resolve_and_commit) or roll back
+    /// [`ConflictResolver::into_transaction`].
+    pub fn commit_or_resolve(&self, tx: Transaction<TxActive>) -> TransactionResult<CommitOutcome> {
         let tx_id = tx.id().to_string();
+        let outcome = tx.commit_or_resolve();
+        if matches!(outcome, Ok(CommitOutcome::Conflicted(_))) || outcome.as_ref().is_err_and(is_contention) {
+            self.inner.conflicts.fetch_add(1, Ordering::Relaxed);
+        }
+        self.track_outcome(&tx_id, outcome)
+    }
 
-        // Perform the commit
-        let committed = tx.commit()?;
+    /// Commit a conflicted transaction with the sides picked on its resolver.
+    pub fn resolve_and_commit(&self, resolver: ConflictResolver) -> TransactionResult<CommitOutcome> {
+        let tx_id = resolver.transaction_id().to_string();
+        self.track_outcome(&tx_id, resolver.resolve_and_commit())
+    }
 
-        // Remove from active tracking
-        self.mark_completed(&tx_id);
+    /// Stop tracking a transaction unless it is still waiting on conflicts.
+    fn track_outcome(&self, tx_id: &str, outcome: TransactionResult<CommitOutcome>) -> TransactionResult<CommitOutcome> {
+        if !matches!(outcome, Ok(CommitOutcome::Conflicted(_))) {
+            self.mark_completed(tx_id);
+        }
+        outcome
+    }
 
-        Ok(committed.final_commit())
+    /// Number of commits that failed, or stopped for resolution, because of
+    /// a conflict with another transaction. Resolving a conflict doesn't
+    /// count again.
+    pub fn conflicts(&self) -> u64 {
+        self.inner.conflicts.load(Ordering::Relaxed)
+    }
+
+    /// Set the conflict count back to zero.
+    pub fn reset_conflicts(&self) {
+        self.inner.conflicts.store(0, Ordering::Relaxed);
     }
 
     /// Rollback a transaction.
@@ -226,12 +289,45 @@ impl TransactionManager {
         }
     }
 
+    /// Execute a function within a transaction, retrying it on conflicts.
+    ///
+    /// When the commit loses a race with another transaction (a conflict,
+    /// serialization failure or deadlock), the transaction is rolled back and
+    /// `f` runs again against a fresh transaction on the current main, after
+    /// an exponentially growing delay. Gives up after `max_attempts` runs and
+    /// returns the last error. Any other error is returned immediately.
+    pub fn commit_with_retry<F, T>(&self, mut f: F, max_attempts: usize) -> TransactionResult<T>
+    where
+        F: FnMut(&mut Transaction<TxActive>) -> TransactionResult<T>,
+    {
+        let mut delay = RETRY_BASE_DELAY;
+        let mut attempt = 1;
+        loop {
+            match self.with_transaction(&mut f) {
+                Err(e) if is_contention(&e) && attempt < max_attempts => {
+                    std::thread::sleep(delay);
+                    delay = (delay * 2).min(RETRY_MAX_DELAY);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
     /// Get current head of main branch.
     pub fn head(&self) -> TransactionResult<CommitId> {
         self.inner.repo.head().map_err(TransactionError::from)
     }
 }
 
+/// Whether an error means the transaction lost a race and may succeed if re-run.
+///
+/// A timeout is retryable for the caller but isn't a lost race, so it isn't
+/// retried here.
+fn is_contention(err: &TransactionError) -> bool {
+    err.is_retryable() && !matches!(err, TransactionError::Timeout { .. })
+}
+
 // Ensure TransactionManager can be safely shared across threads
 impl std::fmt::Debug for TransactionManager {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -248,7 +344,8 @@ mod tests {
     use serde_json::Value;
     use tempfile::TempDir;
 
-    use crate::storage::{RowKey, TableName};
+    use crate::storage::{Row, RowKey, TableName};
+    use crate::transaction::{Conflict, Resolution};
 
     fn setup() -> (TempDir, TransactionManager) {
         let dir = TempDir::new().unwrap();
@@ -406,10 +503,164 @@ mod tests {
         let commit1 = manager.commit_transaction(tx1);
         assert!(commit1.is_ok());
 
-        // Second commit might fail with conflict (depends on whether paths overlap)
-        // In this case, different keys so should succeed
-        let commit2 = manager.commit_transaction(tx2);
-        // This will fail because the base commit has moved
-        assert!(commit2.is_err());
+        // Main has moved, but the keys differ, so the second commit is merged
+        let commit2 = manager.commit_transaction(tx2).unwrap();
+        let repo = manager.repo();
+        assert_eq!(repo.head().unwrap(), commit2);
+        assert_eq!(repo.list_rows(&table, commit2).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_commit_or_resolve() {
+        let (_dir, manager) = setup();
+        let table = TableName::new("users").unwrap();
+        manager.with_transaction(|tx| tx.create_table(&table)).unwrap();
+        let key = |k: &str| RowKey::new(k).unwrap();
+        let name = |n: &str| {
+            let mut data = BTreeMap::new();
+            data.insert("name".to_string(), Value::from(n));
+            data
+        };
+
+        // Both write alice; tx2 also writes a row nobody else touched
+        let mut tx1 = manager.begin().unwrap();
+        let mut tx2 = manager.begin().unwrap();
+        tx1.insert_data(&table, key("alice"), name("from tx1")).unwrap();
+        manager.commit_transaction(tx1).unwrap();
+        tx2.insert_data(&table, key("alice"), name("from tx2")).unwrap();
+        tx2.insert_data(&table, key("bob"), name("Bob")).unwrap();
+        let main_before = manager.head().unwrap();
+
+        let CommitOutcome::Conflicted(mut resolver) = manager.commit_or_resolve(tx2).unwrap() else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(manager.conflicts(), 1);
+        // Nothing was published and the transaction is still active
+        assert_eq!(manager.head().unwrap(), main_before);
+        assert_eq!(manager.active_count(), 1);
+        assert_eq!(resolver.unresolved().len(), 1);
+        assert!(resolver.pick(&table, &key("bob"), Resolution::Ours).is_err());
+
+        // Committing with conflicts unresolved hands the resolver back
+        let CommitOutcome::Conflicted(mut resolver) = manager.resolve_and_commit(resolver).unwrap() else {
+            panic!("expected the resolver back");
+        };
+        resolver.pick(&table, &key("alice"), Resolution::Theirs).unwrap();
+        let CommitOutcome::Committed(committed) = manager.resolve_and_commit(resolver).unwrap() else {
+            panic!("expected a commit");
+        };
+        assert_eq!(committed.final_commit(), manager.head().unwrap());
+        assert_eq!(manager.active_count(), 0);
+        assert_eq!(manager.conflicts(), 1);
+
+        let tx = manager.begin().unwrap();
+        assert_eq!(tx.read(&table, &key("alice")).unwrap().unwrap().get("name"), Some(&Value::from("from tx1")));
+        assert_eq!(tx.read(&table, &key("bob")).unwrap().unwrap().get("name"), Some(&Value::from("Bob")));
+        manager.rollback_transaction(tx).unwrap();
+
+        // Keeping ours overwrites main's version
+        let mut tx3 = manager.begin().unwrap();
+        let mut tx4 = manager.begin().unwrap();
+        tx3.update(&table, Row::new(key("bob"), name("from tx3"))).unwrap();
+        manager.commit_transaction(tx3).unwrap();
+        tx4.delete(&table, &key("bob")).unwrap();
+        let CommitOutcome::Conflicted(mut resolver) = manager.commit_or_resolve(tx4).unwrap() else {
+            panic!("expected a conflict");
+        };
+        match resolver.conflicts() {
+            [Conflict::Row(row)] => assert!(row.ours.is_none() && row.theirs.is_some()),
+            other => panic!("unexpected conflicts: {:?}", other),
+        }
+        resolver.pick_all(Resolution::Ours);
+        assert!(matches!(manager.resolve_and_commit(resolver).unwrap(), CommitOutcome::Committed(_)));
+        let tx = manager.begin().unwrap();
+        assert_eq!(tx.read(&table, &key("bob")).unwrap(), None);
+        manager.rollback_transaction(tx).unwrap();
+    }
+
+    #[test]
+    fn test_row_locks_block_conflicting_writers() {
+        let dir = TempDir::new().unwrap();
+        let repo = GitRepository::init(dir.path()).unwrap();
+        let manager =
+            TransactionManager::with_lock_manager(repo, LockManager::with_timeout(Duration::from_millis(50)));
+
+        let table = TableName::new("users").unwrap();
+        manager.with_transaction(|tx| tx.create_table(&table)).unwrap();
+
+        let mut tx1 = manager.begin().unwrap();
+        let mut tx2 = manager.begin().unwrap();
+        tx1.insert_data(&table, RowKey::new("alice").unwrap(), BTreeMap::new()).unwrap();
+
+        // The same row is locked by tx1 until it finishes
+        let err = tx2.insert_data(&table, RowKey::new("alice").unwrap(), BTreeMap::new());
+        assert!(matches!(err, Err(TransactionError::Timeout { .. })));
+
+        // Once tx1 commits the lock is free, but the write still conflicts
+        let tx1_id = tx1.id().to_string();
+        manager.commit_transaction(tx1).unwrap();
+        assert!(manager.lock_manager().locks_held(&tx1_id).is_empty());
+        let mut data = BTreeMap::new();
+        data.insert("name".to_string(), serde_json::json!("Alice"));
+        tx2.insert_data(&table, RowKey::new("alice").unwrap(), data).unwrap();
+        let Err(TransactionError::Conflict { conflicts }) = manager.commit_transaction(tx2) else {
+            panic!("expected a conflict");
+        };
+
+        // Reported as the row, with the version on each side
+        match conflicts.as_slice() {
+            [Conflict::Row(row)] => {
+                assert_eq!((row.table.as_str(), row.key.as_str()), ("users", "alice"));
+                assert_eq!(row.ours.as_ref().unwrap().get("name"), Some(&serde_json::json!("Alice")));
+                assert_eq!(row.theirs.as_ref().unwrap().get("name"), None);
+            }
+            other => panic!("unexpected conflicts: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_commit_with_retry() {
+        let (_dir, manager) = setup();
+        let table = TableName::new("counter").unwrap();
+        let key = RowKey::new("total").unwrap();
+        manager.with_transaction(|tx| tx.create_table(&table)).unwrap();
+
+        let set = |tx: &mut Transaction<TxActive>, value: i64| {
+            let mut data = BTreeMap::new();
+            data.insert("value".to_string(), Value::from(value));
+            tx.upsert(&table, Row::new(key.clone(), data))
+        };
+
+        // The first attempt loses a race for the same row, the second wins
+        let mut attempts = 0;
+        manager
+            .commit_with_retry(
+                |tx| {
+                    attempts += 1;
+                    if attempts == 1 {
+                        manager.with_transaction(|other| set(other, 1))?;
+                    }
+                    set(tx, 2)
+                },
+                3,
+            )
+            .unwrap();
+        assert_eq!(attempts, 2);
+
+        let repo = manager.repo();
+        let row = repo.read_row(&table, &key, repo.head().unwrap()).unwrap().unwrap();
+        assert_eq!(row.get("value"), Some(&Value::from(2)));
+
+        // Other errors are not retried
+        let mut attempts = 0;
+        let result: TransactionResult<()> = manager.commit_with_retry(
+            |_| {
+                attempts += 1;
+                Err(TransactionError::Internal("boom".to_string()))
+            },
+            3,
+        );
+        assert!(matches!(result, Err(TransactionError::Internal(_))));
+        assert_eq!(attempts, 1);
     }
 }
@@ -9,7 +9,7 @@
 //! ```text
 //! ┌─────────────────────────────────────────────────────────────┐
 //! │                   TransactionManager                        │
-//! │  (Coordinates transactions, tracks active tx, serializes)   │
+//! │  (Coordinates transactions, tracks active tx, shares locks) │
 //! └─────────────────────────────────────────────────────────────┘
 //!                              │
 //!        ┌─────────────────────┼─────────────────────┐
@@ -39,12 +39,16 @@
 //! tx.commit()?;  // or tx.rollback();
 //! ```
 
+mod conflict;
 mod context;
 mod error;
 mod isolation;
+mod lock;
 mod manager;
 
+pub use conflict::{CommitOutcome, Conflict, ConflictResolver, Resolution, RowConflict};
 pub use context::{Transaction, TxActive, TxCommitted, TxAborted};
 pub use error::{TransactionError, TransactionResult};
 pub use isolation::IsolationLevel;
+pub use lock::{LockManager, LockMode, LockTarget};
 pub use manager::TransactionManager;